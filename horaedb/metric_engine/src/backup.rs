@@ -0,0 +1,434 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+//! Backup and restore of a storage root.
+//!
+//! Backups are organized in the following way:
+//! ```plaintext
+//! {prefix}/data/{file_id}
+//! {prefix}/index/{file_id}
+//! {prefix}/backups/{backup_id}
+//! ```
+//! Data files, along with their inverted indexes, are shared by all backups,
+//! and every backup records the manifest at the time it's taken, including the
+//! range tombstones. Backup ids are increasing, and an incremental backup only
+//! copies the files absent from the manifest of the latest backup.
+//!
+//! File ids can't serve as the watermark of incremental backups, since ids are
+//! allocated before files are committed, a file committed after a backup may
+//! have a smaller id than the files in that backup.
+
+use std::collections::HashSet;
+
+use anyhow::Context;
+use bytes::Bytes;
+use futures::TryStreamExt;
+use object_store::{path::Path, PutPayload};
+use prost::Message;
+
+use crate::{
+    manifest,
+    sst::{self, FileId, SstFile, Tier},
+    storage::LeveledCompactionOptions,
+    tombstone::Tombstone,
+    types::ObjectStoreRef,
+    Result,
+};
+
+const DATA_PREFIX: &str = "data";
+const INDEX_PREFIX: &str = "index";
+const BACKUPS_PREFIX: &str = "backups";
+
+pub struct BackupRequest {
+    pub store: ObjectStoreRef,
+    pub prefix: String,
+    /// Only copy files not in the latest backup when true.
+    pub incremental: bool,
+}
+
+#[derive(Debug, Default)]
+pub struct BackupResult {
+    pub backup_id: u64,
+    pub num_files: usize,
+    pub num_copied: usize,
+    pub bytes_copied: u64,
+}
+
+#[derive(Debug, Default)]
+pub struct RestoreResult {
+    pub backup_id: u64,
+    pub num_files: usize,
+    pub bytes_copied: u64,
+}
+
+/// Copy `ssts` under `root_path` to the backup location, and record them as
+/// a new backup along with `tombstones` and `leveled_compaction`.
+///
+/// Data files of ssts in the local tier are copied from `local_store`, they
+/// are restored into the cloud tier.
+pub(crate) async fn backup(
+    store: &ObjectStoreRef,
+    local_store: Option<&ObjectStoreRef>,
+    root_path: &str,
+    mut ssts: Vec<SstFile>,
+    tombstones: Vec<Tombstone>,
+    leveled_compaction: Option<LeveledCompactionOptions>,
+    req: &BackupRequest,
+) -> Result<BackupResult> {
+    let latest = latest_backup_id(&req.store, &req.prefix).await?;
+    // Files of the latest backup are already in the backup location.
+    let backed_up = match latest {
+        Some(backup_id) if req.incremental => {
+            let bytes = read_backup_manifest(&req.store, &req.prefix, backup_id).await?;
+            pb_types::Manifest::decode(bytes)
+                .context("decode backup manifest")?
+                .files
+                .iter()
+                .map(|f| f.id)
+                .collect()
+        }
+        _ => HashSet::new(),
+    };
+
+    let mut result = BackupResult {
+        backup_id: latest.map_or(1, |id| id + 1),
+        num_files: ssts.len(),
+        ..Default::default()
+    };
+    for sst in &ssts {
+        if backed_up.contains(&sst.id) {
+            continue;
+        }
+
+        let data_store = match (sst.meta.tier, local_store) {
+            (Tier::Local, Some(local_store)) => local_store,
+            _ => store,
+        };
+        let src = Path::from(data_path(root_path, sst::PREFIX_PATH, sst.id));
+        let dst = Path::from(data_path(&req.prefix, DATA_PREFIX, sst.id));
+        result.bytes_copied += copy_object(data_store, &src, &req.store, &dst).await?;
+        if sst.meta.inverted_index_size > 0 {
+            let src = Path::from(data_path(root_path, sst::INDEX_PREFIX_PATH, sst.id));
+            let dst = Path::from(data_path(&req.prefix, INDEX_PREFIX, sst.id));
+            result.bytes_copied += copy_object(store, &src, &req.store, &dst).await?;
+        }
+        result.num_copied += 1;
+    }
+
+    for sst in &mut ssts {
+        sst.meta.tier = Tier::Cloud;
+    }
+    // The backup is visible only after all files are copied.
+    // Reserved file ids are recovered from the files on restore.
+    let pb_manifest = pb_types::Manifest {
+        files: ssts.into_iter().map(Into::into).collect(),
+        next_file_id: 0,
+        tombstones: tombstones.into_iter().map(Into::into).collect(),
+        leveled_compaction: leveled_compaction.map(Into::into),
+        // Deltas are deleted on restore.
+        merged_delta_seq: 0,
+        // Retired ssts are not backed up, so older versions are unavailable
+        // after restore.
+        retired: Vec::new(),
+    };
+    let backup_path = Path::from(format!(
+        "{}/{BACKUPS_PREFIX}/{}",
+        req.prefix, result.backup_id
+    ));
+    req.store
+        .put(&backup_path, PutPayload::from(pb_manifest.encode_to_vec()))
+        .await
+        .with_context(|| format!("write backup manifest, path:{backup_path}"))?;
+
+    Ok(result)
+}
+
+/// Rebuild a storage root from the backup with `backup_id`, or the latest
+/// backup when it's `None`.
+///
+/// The manifest is written after all data files are restored, so the root is
+/// consistent once this function returns successfully.
+pub async fn restore(
+    backup_store: &ObjectStoreRef,
+    backup_prefix: &str,
+    backup_id: Option<u64>,
+    target_store: &ObjectStoreRef,
+    root_path: &str,
+) -> Result<RestoreResult> {
+    let backup_id = match backup_id {
+        Some(v) => v,
+        None => latest_backup_id(backup_store, backup_prefix)
+            .await?
+            .context("no backup found")?,
+    };
+    let bytes = read_backup_manifest(backup_store, backup_prefix, backup_id).await?;
+    let pb_manifest =
+        pb_types::Manifest::decode(bytes.clone()).context("decode backup manifest")?;
+    let ssts = pb_manifest
+        .files
+        .into_iter()
+        .map(SstFile::try_from)
+        .collect::<Result<Vec<_>>>()?;
+
+    let mut result = RestoreResult {
+        backup_id,
+        num_files: ssts.len(),
+        ..Default::default()
+    };
+    for sst in &ssts {
+        let src = Path::from(data_path(backup_prefix, DATA_PREFIX, sst.id));
+        let dst = Path::from(data_path(root_path, sst::PREFIX_PATH, sst.id));
+        result.bytes_copied += copy_object(backup_store, &src, target_store, &dst).await?;
+        if sst.meta.inverted_index_size > 0 {
+            let src = Path::from(data_path(backup_prefix, INDEX_PREFIX, sst.id));
+            let dst = Path::from(data_path(root_path, sst::INDEX_PREFIX_PATH, sst.id));
+            result.bytes_copied += copy_object(backup_store, &src, target_store, &dst).await?;
+        }
+    }
+
+    let snapshot_path = Path::from(format!(
+        "{root_path}/{}/{}",
+        manifest::PREFIX_PATH,
+        manifest::SNAPSHOT_FILENAME
+    ));
+    target_store
+        .put(&snapshot_path, PutPayload::from(bytes))
+        .await
+        .with_context(|| format!("write manifest snapshot, path:{snapshot_path}"))?;
+    // Stale deltas would be replayed upon the restored snapshot.
+    let delta_dir = Path::from(format!(
+        "{root_path}/{}/{}",
+        manifest::PREFIX_PATH,
+        manifest::DELTA_PREFIX
+    ));
+    let stale_deltas = target_store
+        .list(Some(&delta_dir))
+        .try_collect::<Vec<_>>()
+        .await
+        .with_context(|| format!("list manifest deltas, path:{delta_dir}"))?;
+    for delta in stale_deltas {
+        target_store
+            .delete(&delta.location)
+            .await
+            .with_context(|| format!("delete manifest delta, path:{}", delta.location))?;
+    }
+
+    Ok(result)
+}
+
+async fn read_backup_manifest(
+    store: &ObjectStoreRef,
+    prefix: &str,
+    backup_id: u64,
+) -> Result<Bytes> {
+    let backup_path = Path::from(format!("{prefix}/{BACKUPS_PREFIX}/{backup_id}"));
+    let bytes = store
+        .get(&backup_path)
+        .await
+        .with_context(|| format!("get backup manifest, path:{backup_path}"))?
+        .bytes()
+        .await
+        .context("read backup manifest")?;
+
+    Ok(bytes)
+}
+
+/// Returns the id of the latest backup, `None` if there is no backup.
+pub async fn latest_backup_id(store: &ObjectStoreRef, prefix: &str) -> Result<Option<u64>> {
+    let backups_path = Path::from(format!("{prefix}/{BACKUPS_PREFIX}"));
+    let objects = store
+        .list(Some(&backups_path))
+        .try_collect::<Vec<_>>()
+        .await
+        .with_context(|| format!("list backups, path:{backups_path}"))?;
+
+    Ok(objects
+        .iter()
+        .filter_map(|meta| meta.location.filename()?.parse::<u64>().ok())
+        .max())
+}
+
+fn data_path(root: &str, prefix: &str, id: FileId) -> String {
+    format!("{root}/{prefix}/{id}")
+}
+
+pub(crate) async fn copy_object(
+    src_store: &ObjectStoreRef,
+    src: &Path,
+    dst_store: &ObjectStoreRef,
+    dst: &Path,
+) -> Result<u64> {
+    let bytes: Bytes = src_store
+        .get(src)
+        .await
+        .with_context(|| format!("get object, path:{src}"))?
+        .bytes()
+        .await
+        .with_context(|| format!("read object, path:{src}"))?;
+    let size = bytes.len() as u64;
+    dst_store
+        .put(dst, PutPayload::from(bytes))
+        .await
+        .with_context(|| format!("put object, path:{dst}"))?;
+
+    Ok(size)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use object_store::memory::InMemory;
+
+    use super::*;
+    use crate::{sst::FileMeta, types::TimeRange};
+
+    fn new_sst(id: FileId) -> SstFile {
+        SstFile {
+            id,
+            meta: FileMeta {
+                max_sequence: id,
+                num_rows: 1,
+                size: 1,
+                time_range: TimeRange::new(0.into(), 1.into()),
+                level: 0,
+                inverted_index_size: 0,
+                aggregates: Vec::new(),
+                tier: Tier::Cloud,
+                min_key: Vec::new(),
+                max_key: Vec::new(),
+                checksum: None,
+                null_columns: Vec::new(),
+            },
+        }
+    }
+
+    #[tokio::test]
+    async fn test_incremental_backup_and_restore() {
+        let store: ObjectStoreRef = Arc::new(InMemory::new());
+        let backup_store: ObjectStoreRef = Arc::new(InMemory::new());
+        for id in [1, 2, 3] {
+            let path = Path::from(data_path("root", sst::PREFIX_PATH, id));
+            store
+                .put(&path, PutPayload::from(vec![id as u8]))
+                .await
+                .unwrap();
+        }
+        let req = BackupRequest {
+            store: backup_store.clone(),
+            prefix: "backup".to_string(),
+            incremental: true,
+        };
+
+        let result = backup(
+            &store,
+            None,
+            "root",
+            vec![new_sst(1), new_sst(2)],
+            vec![],
+            None,
+            &req,
+        )
+        .await
+        .unwrap();
+        assert_eq!((1, 2), (result.backup_id, result.num_copied));
+        let result = backup(
+            &store,
+            None,
+            "root",
+            vec![new_sst(1), new_sst(2), new_sst(3)],
+            vec![],
+            None,
+            &req,
+        )
+        .await
+        .unwrap();
+        assert_eq!((2, 1), (result.backup_id, result.num_copied));
+
+        let target_store: ObjectStoreRef = Arc::new(InMemory::new());
+        let result = restore(&backup_store, "backup", None, &target_store, "restored")
+            .await
+            .unwrap();
+        assert_eq!((2, 3), (result.backup_id, result.num_files));
+        let restored = target_store
+            .get(&Path::from(data_path("restored", sst::PREFIX_PATH, 3)))
+            .await
+            .unwrap()
+            .bytes()
+            .await
+            .unwrap();
+        assert_eq!(vec![3], restored.to_vec());
+    }
+
+    #[tokio::test]
+    async fn test_incremental_backup_with_smaller_id() {
+        let store: ObjectStoreRef = Arc::new(InMemory::new());
+        let backup_store: ObjectStoreRef = Arc::new(InMemory::new());
+        for id in [1, 2, 3] {
+            let path = Path::from(data_path("root", sst::PREFIX_PATH, id));
+            store
+                .put(&path, PutPayload::from(vec![id as u8]))
+                .await
+                .unwrap();
+        }
+        let req = BackupRequest {
+            store: backup_store.clone(),
+            prefix: "backup".to_string(),
+            incremental: true,
+        };
+
+        // The file with id 2 is being written when the first backup is taken.
+        let result = backup(
+            &store,
+            None,
+            "root",
+            vec![new_sst(1), new_sst(3)],
+            vec![],
+            None,
+            &req,
+        )
+        .await
+        .unwrap();
+        assert_eq!((1, 2), (result.backup_id, result.num_copied));
+        let result = backup(
+            &store,
+            None,
+            "root",
+            vec![new_sst(1), new_sst(2), new_sst(3)],
+            vec![],
+            None,
+            &req,
+        )
+        .await
+        .unwrap();
+        assert_eq!((2, 1), (result.backup_id, result.num_copied));
+
+        let target_store: ObjectStoreRef = Arc::new(InMemory::new());
+        let result = restore(&backup_store, "backup", None, &target_store, "restored")
+            .await
+            .unwrap();
+        assert_eq!((2, 3), (result.backup_id, result.num_files));
+        let restored = target_store
+            .get(&Path::from(data_path("restored", sst::PREFIX_PATH, 2)))
+            .await
+            .unwrap()
+            .bytes()
+            .await
+            .unwrap();
+        assert_eq!(vec![2], restored.to_vec());
+    }
+}
@@ -0,0 +1,209 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+//! Pruning by the bloom filters of primary key columns.
+//!
+//! Equality predicates on primary key columns, like `host = 'a'` or
+//! `host IN ('a', 'b')`, are checked against the bloom filters of ssts before
+//! the scan is planned, so ssts and row groups without the keys are skipped
+//! before any data page is read.
+
+use anyhow::Context;
+use arrow::datatypes::Schema;
+use datafusion::{
+    common::ScalarValue,
+    logical_expr::{expr::InList, utils::split_conjunction, BinaryExpr, Expr, Operator},
+};
+use parquet::{
+    arrow::{async_reader::AsyncFileReader, ParquetRecordBatchStreamBuilder},
+    bloom_filter::Sbbf,
+};
+
+use crate::Result;
+
+/// Rows matching the predicate must have one of `values` in `column`.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct KeyEquality {
+    pub(crate) column: String,
+    pub(crate) values: Vec<ScalarValue>,
+}
+
+/// Extract equalities on the columns of `key_indices` from the conjunctions of
+/// `predicate`, literals are cast to the types of the columns.
+pub(crate) fn key_equalities(
+    predicate: &[Expr],
+    schema: &Schema,
+    key_indices: &[usize],
+) -> Vec<KeyEquality> {
+    if key_indices.is_empty() {
+        return Vec::new();
+    }
+
+    predicate
+        .iter()
+        .flat_map(split_conjunction)
+        .filter_map(|expr| {
+            let (column, values) = match expr {
+                Expr::BinaryExpr(BinaryExpr {
+                    left,
+                    op: Operator::Eq,
+                    right,
+                }) => match (left.as_ref(), right.as_ref()) {
+                    (Expr::Column(c), Expr::Literal(v)) | (Expr::Literal(v), Expr::Column(c)) => {
+                        (c, vec![v])
+                    }
+                    _ => return None,
+                },
+                Expr::InList(InList {
+                    expr,
+                    list,
+                    negated: false,
+                }) => {
+                    let Expr::Column(c) = expr.as_ref() else {
+                        return None;
+                    };
+                    let values = list
+                        .iter()
+                        .map(|v| match v {
+                            Expr::Literal(v) => Some(v),
+                            _ => None,
+                        })
+                        .collect::<Option<Vec<_>>>()?;
+                    (c, values)
+                }
+                _ => return None,
+            };
+
+            let (idx, field) = schema.column_with_name(&column.name)?;
+            if !key_indices.contains(&idx) {
+                return None;
+            }
+            let values = values
+                .into_iter()
+                .map(|v| v.cast_to(field.data_type()).ok())
+                .collect::<Option<Vec<_>>>()?;
+
+            Some(KeyEquality {
+                column: column.name.clone(),
+                values,
+            })
+        })
+        .collect()
+}
+
+/// Returns whether every row group of the sst may contain rows matching all
+/// `equalities`.
+///
+/// Row groups without bloom filters on the columns are always kept.
+pub(crate) async fn prune_row_groups<R>(reader: R, equalities: &[KeyEquality]) -> Result<Vec<bool>>
+where
+    R: AsyncFileReader + Send + 'static,
+{
+    let mut builder = ParquetRecordBatchStreamBuilder::new(reader)
+        .await
+        .context("read parquet metadata")?;
+    let metadata = builder.metadata().clone();
+    let columns = metadata.file_metadata().schema_descr().columns();
+    let column_indices = equalities
+        .iter()
+        .map(|eq| columns.iter().position(|c| c.name() == eq.column))
+        .collect::<Vec<_>>();
+
+    let mut keep = vec![true; metadata.num_row_groups()];
+    for (row_group, keep) in keep.iter_mut().enumerate() {
+        for (eq, column) in equalities.iter().zip(&column_indices) {
+            let Some(column) = *column else {
+                continue;
+            };
+            let sbbf = builder
+                .get_row_group_column_bloom_filter(row_group, column)
+                .await
+                .with_context(|| format!("read bloom filter, column:{}", eq.column))?;
+            let Some(sbbf) = sbbf else {
+                continue;
+            };
+            if !eq.values.iter().any(|v| may_contain(&sbbf, v)) {
+                *keep = false;
+                break;
+            }
+        }
+    }
+
+    Ok(keep)
+}
+
+/// Values are checked in their parquet physical types, and values of types
+/// not supported are assumed to be contained.
+fn may_contain(sbbf: &Sbbf, value: &ScalarValue) -> bool {
+    match value {
+        ScalarValue::Utf8(Some(v))
+        | ScalarValue::LargeUtf8(Some(v))
+        | ScalarValue::Utf8View(Some(v)) => sbbf.check(v.as_str()),
+        ScalarValue::Dictionary(_, v) => may_contain(sbbf, v),
+        ScalarValue::Binary(Some(v))
+        | ScalarValue::LargeBinary(Some(v))
+        | ScalarValue::BinaryView(Some(v)) => sbbf.check(v.as_slice()),
+        ScalarValue::Int8(Some(v)) => sbbf.check(&(*v as i32)),
+        ScalarValue::Int16(Some(v)) => sbbf.check(&(*v as i32)),
+        ScalarValue::Int32(Some(v)) => sbbf.check(v),
+        ScalarValue::UInt8(Some(v)) => sbbf.check(&(*v as i32)),
+        ScalarValue::UInt16(Some(v)) => sbbf.check(&(*v as i32)),
+        ScalarValue::UInt32(Some(v)) => sbbf.check(&(*v as i32)),
+        ScalarValue::Int64(Some(v)) => sbbf.check(v),
+        ScalarValue::UInt64(Some(v)) => sbbf.check(&(*v as i64)),
+        _ => true,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use arrow::datatypes::{DataType, Field};
+    use datafusion::prelude::{col, lit};
+
+    use super::*;
+
+    #[test]
+    fn test_key_equalities() {
+        let schema = Schema::new(vec![
+            Field::new("host", DataType::Utf8, false),
+            Field::new("ts", DataType::Int64, false),
+            Field::new("value", DataType::UInt64, false),
+        ]);
+        let predicate = vec![
+            col("host").eq(lit("a")).and(col("value").eq(lit(1u64))),
+            col("host").in_list(vec![lit("b"), lit("c")], false),
+            col("host").in_list(vec![lit("d")], true),
+            col("host").not_eq(lit("e")),
+        ];
+
+        let equalities = key_equalities(&predicate, &schema, &[0]);
+        assert_eq!(
+            equalities,
+            vec![
+                KeyEquality {
+                    column: "host".to_string(),
+                    values: vec![ScalarValue::from("a")],
+                },
+                KeyEquality {
+                    column: "host".to_string(),
+                    values: vec![ScalarValue::from("b"), ScalarValue::from("c")],
+                },
+            ]
+        );
+        assert!(key_equalities(&predicate, &schema, &[]).is_empty());
+    }
+}
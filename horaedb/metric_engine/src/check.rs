@@ -0,0 +1,442 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+//! Consistency checks of a storage root, like fsck.
+//!
+//! The manifest is checked against the data files it references, and ssts
+//! whose data files are missing or broken can be quarantined: they are removed
+//! from the manifest and their data files are moved under
+//! `{root}/quarantine/data`, so operators can inspect them later.
+//!
+//! Repairing must not run while the storage is opened by others, since the
+//! manifest is opened for writing.
+
+use std::collections::HashSet;
+
+use futures::TryStreamExt;
+use object_store::path::Path;
+
+use crate::{
+    checksum,
+    error::StorageIoContext,
+    manifest::{self, Manifest},
+    sst::{self, FileId, Level, SstFile, Tier, LEVEL_0},
+    types::{ManifestOptions, ObjectStoreRef},
+    Result,
+};
+
+const QUARANTINE_PREFIX: &str = "quarantine";
+
+/// An inconsistency found by the [StorageChecker].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Inconsistency {
+    /// The data file of the sst is missing.
+    MissingFile { id: FileId, path: String },
+    /// The size of the data file differs from the size in the manifest.
+    SizeMismatch {
+        id: FileId,
+        path: String,
+        expect: u64,
+        actual: u64,
+    },
+    /// The checksum of the data file differs from the one in the manifest.
+    ChecksumMismatch { id: FileId, path: String },
+    /// The sst claims an inverted index, which is missing.
+    MissingIndex { id: FileId, path: String },
+    /// The max sequence of the sst is larger than its id, which should never
+    /// happen since ids are allocated in increasing order.
+    SequenceAhead { id: FileId, max_sequence: u64 },
+    /// The id of the sst is not reserved by the manifest, so it may be
+    /// allocated again.
+    IdNotReserved { id: FileId, next_file_id: FileId },
+    /// Ssts of the same level, except [LEVEL_0], overlap with each other.
+    OverlappingLevel { level: Level, ids: (FileId, FileId) },
+    /// The data file is not referenced by the manifest, e.g. left by a
+    /// crashed writer.
+    OrphanFile { path: String },
+}
+
+impl Inconsistency {
+    /// The sst which should be quarantined, `None` if the inconsistency is not
+    /// repairable by quarantining.
+    fn broken_sst(&self) -> Option<FileId> {
+        match self {
+            Self::MissingFile { id, .. }
+            | Self::SizeMismatch { id, .. }
+            | Self::ChecksumMismatch { id, .. } => Some(*id),
+            Self::MissingIndex { .. }
+            | Self::SequenceAhead { .. }
+            | Self::IdNotReserved { .. }
+            | Self::OverlappingLevel { .. }
+            | Self::OrphanFile { .. } => None,
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct CheckReport {
+    pub num_ssts: usize,
+    pub inconsistencies: Vec<Inconsistency>,
+    /// Ssts removed from the manifest, only when repairing.
+    pub quarantined: Vec<FileId>,
+}
+
+impl CheckReport {
+    pub fn is_consistent(&self) -> bool {
+        self.inconsistencies.is_empty()
+    }
+}
+
+pub struct StorageChecker {
+    store: ObjectStoreRef,
+    /// Store of ssts in the local tier, see [crate::tier].
+    local_store: Option<ObjectStoreRef>,
+    /// Read whole data files to verify their checksums.
+    verify_checksums: bool,
+    /// Quarantine broken ssts.
+    repair: bool,
+}
+
+impl StorageChecker {
+    pub fn new(store: ObjectStoreRef) -> Self {
+        Self {
+            store,
+            local_store: None,
+            verify_checksums: false,
+            repair: false,
+        }
+    }
+
+    pub fn with_local_store(mut self, local_store: ObjectStoreRef) -> Self {
+        self.local_store = Some(local_store);
+        self
+    }
+
+    pub fn with_verify_checksums(mut self, verify_checksums: bool) -> Self {
+        self.verify_checksums = verify_checksums;
+        self
+    }
+
+    pub fn with_repair(mut self, repair: bool) -> Self {
+        self.repair = repair;
+        self
+    }
+
+    /// Check the storage at `root`, and quarantine broken ssts if repairing.
+    pub async fn run(&self, root: &str) -> Result<CheckReport> {
+        let manifest_path = format!("{root}/{}", manifest::PREFIX_PATH);
+        let payload = Manifest::load_payload(&manifest_path, self.store.clone()).await?;
+        let ssts = payload.ssts().to_vec();
+        let next_file_id = payload.next_file_id();
+
+        let mut report = CheckReport {
+            num_ssts: ssts.len(),
+            ..Default::default()
+        };
+        check_sequences(&ssts, next_file_id, &mut report.inconsistencies);
+        for sst in &ssts {
+            self.check_files(root, sst, &mut report.inconsistencies)
+                .await?;
+        }
+        // Files of retired ssts are kept for scans at older versions.
+        let referenced = ssts
+            .iter()
+            .chain(payload.retired().iter().map(|r| &r.sst))
+            .map(|sst| sst.id)
+            .collect::<HashSet<_>>();
+        self.check_orphans(root, &referenced, &mut report.inconsistencies)
+            .await?;
+
+        if self.repair {
+            let broken = report
+                .inconsistencies
+                .iter()
+                .filter_map(Inconsistency::broken_sst)
+                .collect::<HashSet<_>>();
+            if !broken.is_empty() {
+                // Data files are moved first, so they are never lost if the
+                // manifest fails to be updated.
+                for sst in ssts.iter().filter(|sst| broken.contains(&sst.id)) {
+                    self.quarantine(root, sst).await?;
+                }
+                let mut quarantined = broken.into_iter().collect::<Vec<_>>();
+                quarantined.sort_unstable();
+                let manifest = Manifest::try_new(
+                    manifest_path,
+                    self.store.clone(),
+                    ManifestOptions::default(),
+                )
+                .await?;
+                manifest.update(Vec::new(), &quarantined).await?;
+                report.quarantined = quarantined;
+            }
+        }
+
+        Ok(report)
+    }
+
+    fn store_of(&self, sst: &SstFile) -> &ObjectStoreRef {
+        match (&self.local_store, sst.meta.tier) {
+            (Some(local_store), Tier::Local) => local_store,
+            _ => &self.store,
+        }
+    }
+
+    async fn check_files(
+        &self,
+        root: &str,
+        sst: &SstFile,
+        inconsistencies: &mut Vec<Inconsistency>,
+    ) -> Result<()> {
+        let store = self.store_of(sst);
+        let path = Path::from(data_path(root, sst::PREFIX_PATH, sst.id));
+        match store.head(&path).await {
+            Ok(object_meta) => {
+                let actual = object_meta.size as u64;
+                if actual != u64::from(sst.meta.size) {
+                    inconsistencies.push(Inconsistency::SizeMismatch {
+                        id: sst.id,
+                        path: path.to_string(),
+                        expect: sst.meta.size.into(),
+                        actual,
+                    });
+                } else if let Some(expect) = sst.meta.checksum.filter(|_| self.verify_checksums) {
+                    let bytes = store
+                        .get(&path)
+                        .await
+                        .storage_io(|| format!("get sst, path:{path}"))?
+                        .bytes()
+                        .await
+                        .storage_io(|| format!("read sst, path:{path}"))?;
+                    if checksum::verify(path.as_ref(), &bytes, expect).is_err() {
+                        inconsistencies.push(Inconsistency::ChecksumMismatch {
+                            id: sst.id,
+                            path: path.to_string(),
+                        });
+                    }
+                }
+            }
+            Err(object_store::Error::NotFound { .. }) => {
+                inconsistencies.push(Inconsistency::MissingFile {
+                    id: sst.id,
+                    path: path.to_string(),
+                });
+            }
+            Err(e) => {
+                return Err(crate::Error::StorageIo {
+                    msg: format!("get object meta, path:{path}"),
+                    source: e,
+                })
+            }
+        }
+
+        // Inverted indexes are always in the cloud store.
+        if sst.meta.inverted_index_size > 0 {
+            let path = Path::from(data_path(root, sst::INDEX_PREFIX_PATH, sst.id));
+            match self.store.head(&path).await {
+                Ok(_) => {}
+                Err(object_store::Error::NotFound { .. }) => {
+                    inconsistencies.push(Inconsistency::MissingIndex {
+                        id: sst.id,
+                        path: path.to_string(),
+                    });
+                }
+                Err(e) => {
+                    return Err(crate::Error::StorageIo {
+                        msg: format!("get object meta, path:{path}"),
+                        source: e,
+                    })
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn check_orphans(
+        &self,
+        root: &str,
+        referenced: &HashSet<FileId>,
+        inconsistencies: &mut Vec<Inconsistency>,
+    ) -> Result<()> {
+        let prefix = Path::from(format!("{root}/{}", sst::PREFIX_PATH));
+        for store in std::iter::once(&self.store).chain(self.local_store.as_ref()) {
+            let objects = store
+                .list(Some(&prefix))
+                .try_collect::<Vec<_>>()
+                .await
+                .storage_io(|| format!("list data files, path:{prefix}"))?;
+            for object in objects {
+                let id = object
+                    .location
+                    .filename()
+                    .and_then(|name| name.parse::<FileId>().ok());
+                if !id.is_some_and(|id| referenced.contains(&id)) {
+                    inconsistencies.push(Inconsistency::OrphanFile {
+                        path: object.location.to_string(),
+                    });
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Move the data file of `sst` under the quarantine prefix if it exists.
+    async fn quarantine(&self, root: &str, sst: &SstFile) -> Result<()> {
+        let store = self.store_of(sst);
+        let src = Path::from(data_path(root, sst::PREFIX_PATH, sst.id));
+        let dst = Path::from(format!(
+            "{root}/{QUARANTINE_PREFIX}/{}/{}",
+            sst::PREFIX_PATH,
+            sst.id
+        ));
+        match store.rename(&src, &dst).await {
+            Ok(()) | Err(object_store::Error::NotFound { .. }) => Ok(()),
+            Err(e) => Err(crate::Error::StorageIo {
+                msg: format!("quarantine sst, from:{src}, to:{dst}"),
+                source: e,
+            }),
+        }
+    }
+}
+
+/// Check sequences and levels of `ssts`, which are recorded in the manifest
+/// only.
+fn check_sequences(
+    ssts: &[SstFile],
+    next_file_id: FileId,
+    inconsistencies: &mut Vec<Inconsistency>,
+) {
+    for sst in ssts {
+        if sst.meta.max_sequence > sst.id {
+            inconsistencies.push(Inconsistency::SequenceAhead {
+                id: sst.id,
+                max_sequence: sst.meta.max_sequence,
+            });
+        }
+        if sst.id >= next_file_id {
+            inconsistencies.push(Inconsistency::IdNotReserved {
+                id: sst.id,
+                next_file_id,
+            });
+        }
+    }
+
+    let mut leveled = ssts
+        .iter()
+        .filter(|sst| sst.meta.level > LEVEL_0)
+        .collect::<Vec<_>>();
+    leveled.sort_by_key(|sst| (sst.meta.level, *sst.meta.time_range.start));
+    for pair in leveled.windows(2) {
+        let (prev, next) = (pair[0], pair[1]);
+        if prev.meta.level == next.meta.level
+            && prev.meta.time_range.overlaps(&next.meta.time_range)
+        {
+            inconsistencies.push(Inconsistency::OverlappingLevel {
+                level: prev.meta.level,
+                ids: (prev.id, next.id),
+            });
+        }
+    }
+}
+
+fn data_path(root: &str, prefix: &str, id: FileId) -> String {
+    format!("{root}/{prefix}/{id}")
+}
+
+#[cfg(test)]
+mod tests {
+    use arrow::datatypes::DataType;
+    use object_store::PutPayload;
+
+    use super::*;
+    use crate::testing::TableBuilder;
+
+    #[tokio::test]
+    async fn test_check_and_repair() {
+        let table = TableBuilder::new()
+            .tag("host")
+            .field("value", DataType::Float64)
+            .build()
+            .await
+            .unwrap();
+        for _ in 0..3 {
+            table.write_series(2, 2).await.unwrap();
+        }
+        let store = table.store.clone();
+        // Close the storage, so the manifest can be repaired.
+        drop(table);
+
+        let report = StorageChecker::new(store.clone())
+            .with_verify_checksums(true)
+            .run("/test")
+            .await
+            .unwrap();
+        assert_eq!(3, report.num_ssts);
+        assert!(report.is_consistent(), "{report:?}");
+
+        // Remove the first sst, truncate the second one, and leave an orphan.
+        let mut ssts = Manifest::load_payload("/test/manifest", store.clone())
+            .await
+            .unwrap()
+            .ssts()
+            .to_vec();
+        ssts.sort_by_key(|sst| sst.id);
+        let path_of = |id| Path::from(data_path("/test", sst::PREFIX_PATH, id));
+        store.delete(&path_of(ssts[0].id)).await.unwrap();
+        store
+            .put(&path_of(ssts[1].id), PutPayload::from_static(b"PAR1"))
+            .await
+            .unwrap();
+        store
+            .put(&path_of(u64::MAX), PutPayload::from_static(b"PAR1"))
+            .await
+            .unwrap();
+
+        let orphan = Inconsistency::OrphanFile {
+            path: path_of(u64::MAX).to_string(),
+        };
+        let report = StorageChecker::new(store.clone())
+            .run("/test")
+            .await
+            .unwrap();
+        assert_eq!(3, report.inconsistencies.len(), "{report:?}");
+        assert!(report
+            .inconsistencies
+            .contains(&Inconsistency::MissingFile {
+                id: ssts[0].id,
+                path: path_of(ssts[0].id).to_string(),
+            }));
+        assert!(report.inconsistencies.contains(&orphan));
+        assert!(report.quarantined.is_empty());
+
+        let report = StorageChecker::new(store.clone())
+            .with_repair(true)
+            .run("/test")
+            .await
+            .unwrap();
+        assert_eq!(vec![ssts[0].id, ssts[1].id], report.quarantined);
+        let quarantined = Path::from(format!("/test/{QUARANTINE_PREFIX}/data/{}", ssts[1].id));
+        assert!(store.head(&quarantined).await.is_ok());
+
+        // Only the orphan is left, which is never repaired.
+        let report = StorageChecker::new(store).run("/test").await.unwrap();
+        assert_eq!(1, report.num_ssts);
+        assert_eq!(vec![orphan], report.inconsistencies);
+    }
+}
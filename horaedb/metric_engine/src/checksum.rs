@@ -0,0 +1,190 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+//! Checksums of ssts and manifest deltas.
+//!
+//! The crc32 of every sst is computed while it's written and recorded in the
+//! manifest, and it's verified against the whole file before the sst is read
+//! according to [ChecksumVerification], since it costs an extra read.
+//!
+//! Manifest deltas carry their crc32 in a trailer, which is always verified
+//! when they are loaded since they are small. Deltas written before the
+//! trailer is introduced are loaded without verification.
+
+use std::sync::{
+    atomic::{AtomicU64, Ordering},
+    Arc, Mutex,
+};
+
+use bytes::Bytes;
+use futures::future::BoxFuture;
+use parquet::{arrow::async_writer::AsyncFileWriter, errors::Result as ParquetResult};
+
+use crate::{Error, Result};
+
+/// Magic ending the trailer of checksummed payloads.
+const TRAILER_MAGIC: &[u8; 4] = b"HCRC";
+/// Trailer is the crc32 of the payload in little endian and the magic.
+const TRAILER_LEN: usize = 8;
+
+/// When to verify the checksums of ssts before reading them.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ChecksumVerification {
+    /// Verify every read.
+    Always,
+    /// Verify one of every `one_in` reads.
+    Sampled { one_in: u32 },
+    #[default]
+    Never,
+}
+
+/// Decides whether a read should be verified by [ChecksumVerification].
+#[derive(Debug, Default)]
+pub(crate) struct ChecksumVerifier {
+    mode: ChecksumVerification,
+    reads: AtomicU64,
+}
+
+impl ChecksumVerifier {
+    pub(crate) fn new(mode: ChecksumVerification) -> Self {
+        Self {
+            mode,
+            reads: AtomicU64::new(0),
+        }
+    }
+
+    pub(crate) fn should_verify(&self) -> bool {
+        match self.mode {
+            ChecksumVerification::Always => true,
+            ChecksumVerification::Sampled { one_in } => {
+                let reads = self.reads.fetch_add(1, Ordering::Relaxed);
+                reads % u64::from(one_in.max(1)) == 0
+            }
+            ChecksumVerification::Never => false,
+        }
+    }
+}
+
+pub(crate) fn checksum(bytes: &[u8]) -> u32 {
+    crc32fast::hash(bytes)
+}
+
+/// Ensure the checksum of `bytes` is `expect`, `path` is where the bytes come
+/// from.
+pub(crate) fn verify(path: &str, bytes: &[u8], expect: u32) -> Result<()> {
+    let actual = checksum(bytes);
+    if actual != expect {
+        return Err(Error::Corruption {
+            path: path.to_string(),
+            msg: format!("checksum mismatch, expect:{expect}, actual:{actual}"),
+        });
+    }
+
+    Ok(())
+}
+
+/// Append the checksum trailer to `payload`.
+pub(crate) fn seal(mut payload: Vec<u8>) -> Vec<u8> {
+    let crc = checksum(&payload);
+    payload.extend_from_slice(&crc.to_le_bytes());
+    payload.extend_from_slice(TRAILER_MAGIC);
+    payload
+}
+
+/// Verify and strip the trailer appended by [seal], `bytes` without the
+/// trailer are returned as they are.
+pub(crate) fn unseal(path: &str, bytes: Bytes) -> Result<Bytes> {
+    if bytes.len() < TRAILER_LEN || !bytes.ends_with(TRAILER_MAGIC) {
+        return Ok(bytes);
+    }
+
+    let payload_len = bytes.len() - TRAILER_LEN;
+    let crc = &bytes[payload_len..payload_len + 4];
+    let expect = u32::from_le_bytes(crc.try_into().expect("crc is 4 bytes"));
+    verify(path, &bytes[..payload_len], expect)?;
+
+    Ok(bytes.slice(..payload_len))
+}
+
+/// Compute the checksum of bytes written through it.
+pub(crate) struct ChecksumWriter<W> {
+    inner: W,
+    hasher: Arc<Mutex<crc32fast::Hasher>>,
+}
+
+impl<W> ChecksumWriter<W> {
+    pub(crate) fn new(inner: W) -> Self {
+        Self {
+            inner,
+            hasher: Arc::new(Mutex::new(crc32fast::Hasher::new())),
+        }
+    }
+
+    /// Handle to get the checksum after the writer is consumed, e.g. by
+    /// closing the arrow writer.
+    pub(crate) fn handle(&self) -> ChecksumHandle {
+        ChecksumHandle(self.hasher.clone())
+    }
+}
+
+impl<W: AsyncFileWriter> AsyncFileWriter for ChecksumWriter<W> {
+    fn write(&mut self, bs: Bytes) -> BoxFuture<'_, ParquetResult<()>> {
+        self.hasher.lock().unwrap().update(&bs);
+        self.inner.write(bs)
+    }
+
+    fn complete(&mut self) -> BoxFuture<'_, ParquetResult<()>> {
+        self.inner.complete()
+    }
+}
+
+pub(crate) struct ChecksumHandle(Arc<Mutex<crc32fast::Hasher>>);
+
+impl ChecksumHandle {
+    pub(crate) fn finalize(&self) -> u32 {
+        self.0.lock().unwrap().clone().finalize()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_seal_and_unseal() {
+        let payload = b"manifest delta".to_vec();
+        let sealed = Bytes::from(seal(payload.clone()));
+        assert_eq!(payload, unseal("delta", sealed.clone()).unwrap().to_vec());
+
+        // Payloads without the trailer are kept.
+        let legacy = Bytes::from(payload.clone());
+        assert_eq!(legacy, unseal("delta", legacy.clone()).unwrap());
+
+        let mut corrupted = sealed.to_vec();
+        corrupted[0] ^= 1;
+        let err = unseal("delta", Bytes::from(corrupted)).unwrap_err();
+        assert!(matches!(err, Error::Corruption { path, .. } if path == "delta"));
+    }
+
+    #[test]
+    fn test_sampled_verification() {
+        let verifier = ChecksumVerifier::new(ChecksumVerification::Sampled { one_in: 3 });
+        let verified = (0..9).filter(|_| verifier.should_verify()).count();
+        assert_eq!(3, verified);
+        assert!(!ChecksumVerifier::default().should_verify());
+    }
+}
@@ -0,0 +1,541 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+//! Codecs of value columns specialized for time series.
+//!
+//! Values of an encoded column are encoded as one blob per row group, which is
+//! stored in the first row of the row group in a binary column, followed by
+//! nulls:
+//! ```plaintext
+//! | num_values: u32 (LE) | has_nulls: 1 bit | validity: num_values bits | values |
+//! ```
+//! The validity bits are only present when there are nulls, and the values of
+//! null rows are encoded as they are in the arrow buffer.
+//!
+//! So batches read from ssts must start at the first row of a row group and
+//! cover the whole row group, and encoded columns have no statistics.
+
+use std::{collections::HashMap, sync::Arc};
+
+use anyhow::Context;
+use arrow::{
+    array::{
+        new_empty_array, Array, ArrayRef, AsArray, BinaryBuilder, Float64Array, Int64Array,
+        RecordBatch, RecordBatchOptions,
+    },
+    buffer::NullBuffer,
+    datatypes::{DataType, Field, Float64Type, Int64Type, Schema, SchemaRef},
+};
+use macros::ensure;
+
+use crate::Result;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ValueCodec {
+    /// XOR of consecutive float values, see the Gorilla paper of Facebook.
+    /// Only for `Float64` columns.
+    Gorilla,
+    /// Delta of the deltas of consecutive integers, which is mostly zero for
+    /// values increasing at a constant rate. Only for `Int64` columns.
+    DeltaOfDelta,
+}
+
+impl ValueCodec {
+    pub fn supports(&self, data_type: &DataType) -> bool {
+        match self {
+            ValueCodec::Gorilla => data_type == &DataType::Float64,
+            ValueCodec::DeltaOfDelta => data_type == &DataType::Int64,
+        }
+    }
+
+    fn encode(&self, array: &dyn Array) -> Result<Vec<u8>> {
+        ensure!(
+            self.supports(array.data_type()),
+            "{self:?} doesn't support type {}",
+            array.data_type()
+        );
+        let mut writer = BitWriter::default();
+        writer
+            .buf
+            .extend_from_slice(&(array.len() as u32).to_le_bytes());
+        writer.num_bits = 32;
+        match array.nulls() {
+            Some(nulls) if nulls.null_count() > 0 => {
+                writer.write_bit(true);
+                for valid in nulls.iter() {
+                    writer.write_bit(valid);
+                }
+            }
+            _ => writer.write_bit(false),
+        }
+        match self {
+            ValueCodec::Gorilla => {
+                encode_gorilla(array.as_primitive::<Float64Type>().values(), &mut writer)
+            }
+            ValueCodec::DeltaOfDelta => {
+                encode_delta_of_delta(array.as_primitive::<Int64Type>().values(), &mut writer)
+            }
+        }
+
+        Ok(writer.buf)
+    }
+
+    fn decode(&self, blob: &[u8]) -> Result<ArrayRef> {
+        ensure!(blob.len() >= 4, "encoded values are too short");
+        let num_values = u32::from_le_bytes(blob[..4].try_into().unwrap()) as usize;
+        let mut reader = BitReader {
+            buf: &blob[4..],
+            pos: 0,
+        };
+        let nulls = if reader.read_bit()? {
+            let validity = (0..num_values)
+                .map(|_| reader.read_bit())
+                .collect::<Result<Vec<_>>>()?;
+            Some(NullBuffer::from(validity))
+        } else {
+            None
+        };
+        let array: ArrayRef = match self {
+            ValueCodec::Gorilla => Arc::new(Float64Array::new(
+                decode_gorilla(num_values, &mut reader)?.into(),
+                nulls,
+            )),
+            ValueCodec::DeltaOfDelta => Arc::new(Int64Array::new(
+                decode_delta_of_delta(num_values, &mut reader)?.into(),
+                nulls,
+            )),
+        };
+
+        Ok(array)
+    }
+}
+
+/// Codecs of the value columns of a storage, by column name.
+#[derive(Debug, Default)]
+pub(crate) struct ColumnCodecs {
+    codecs: HashMap<String, ValueCodec>,
+    /// Row groups never have more rows than this.
+    max_row_group_size: usize,
+}
+
+impl ColumnCodecs {
+    pub(crate) fn new(codecs: HashMap<String, ValueCodec>, max_row_group_size: usize) -> Self {
+        Self {
+            codecs,
+            max_row_group_size,
+        }
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.codecs.is_empty()
+    }
+
+    /// Min size of batches read from ssts to cover whole row groups, `None` if
+    /// no column is encoded.
+    pub(crate) fn min_read_batch_size(&self) -> Option<usize> {
+        (!self.is_empty()).then_some(self.max_row_group_size)
+    }
+
+    /// Schema of ssts, in which encoded columns are nullable binary columns.
+    pub(crate) fn encoded_schema(&self, schema: &SchemaRef) -> SchemaRef {
+        if self.is_empty() {
+            return schema.clone();
+        }
+
+        let fields = schema
+            .fields()
+            .iter()
+            .map(|field| match self.codecs.get(field.name()) {
+                Some(_) => Arc::new(Field::new(field.name(), DataType::Binary, true)),
+                None => field.clone(),
+            })
+            .collect::<Vec<_>>();
+        Arc::new(Schema::new_with_metadata(fields, schema.metadata().clone()))
+    }
+
+    /// Encode the rows of one row group into `encoded_schema`.
+    pub(crate) fn encode_batch(
+        &self,
+        batch: &RecordBatch,
+        encoded_schema: &SchemaRef,
+    ) -> Result<RecordBatch> {
+        let columns = batch
+            .schema()
+            .fields()
+            .iter()
+            .zip(batch.columns())
+            .map(|(field, array)| match self.codecs.get(field.name()) {
+                Some(codec) => encode_column(*codec, array.as_ref()),
+                None => Ok(array.clone()),
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        RecordBatch::try_new(encoded_schema.clone(), columns).context("build encoded batch")
+    }
+
+    /// Decode the columns of `batch` read from an sst into the types of the
+    /// same columns in `schema`.
+    ///
+    /// Columns of ssts written before the codec is set are not encoded, and
+    /// are kept as they are.
+    pub(crate) fn decode_batch(
+        &self,
+        batch: RecordBatch,
+        schema: &SchemaRef,
+    ) -> Result<RecordBatch> {
+        if self.is_empty() {
+            return Ok(batch);
+        }
+
+        let columns = batch
+            .schema()
+            .fields()
+            .iter()
+            .zip(batch.columns())
+            .map(|(field, array)| {
+                let data_type = schema
+                    .field_with_name(field.name())
+                    .with_context(|| format!("column {} not found", field.name()))?
+                    .data_type();
+                self.decode_column(field.name(), array, data_type)
+            })
+            .collect::<Result<Vec<_>>>()?;
+        let fields = batch
+            .schema()
+            .fields()
+            .iter()
+            .map(|field| schema.field_with_name(field.name()).unwrap().clone())
+            .collect::<Vec<_>>();
+        let options = RecordBatchOptions::new().with_row_count(Some(batch.num_rows()));
+        RecordBatch::try_new_with_options(Arc::new(Schema::new(fields)), columns, &options)
+            .context("build decoded batch")
+    }
+
+    /// Decode column `name` read from an sst into `data_type`.
+    pub(crate) fn decode_column(
+        &self,
+        name: &str,
+        array: &ArrayRef,
+        data_type: &DataType,
+    ) -> Result<ArrayRef> {
+        match self.codecs.get(name) {
+            Some(codec) if array.data_type() != data_type => {
+                decode_column(*codec, array, data_type)
+            }
+            _ => Ok(array.clone()),
+        }
+    }
+}
+
+fn encode_column(codec: ValueCodec, array: &dyn Array) -> Result<ArrayRef> {
+    let mut builder = BinaryBuilder::with_capacity(array.len(), 0);
+    if !array.is_empty() {
+        builder.append_value(codec.encode(array)?);
+        builder.append_nulls(array.len() - 1);
+    }
+
+    Ok(Arc::new(builder.finish()))
+}
+
+fn decode_column(codec: ValueCodec, array: &ArrayRef, data_type: &DataType) -> Result<ArrayRef> {
+    let blobs = array.as_binary_opt::<i32>().with_context(|| {
+        format!(
+            "encoded column should be binary, type:{}",
+            array.data_type()
+        )
+    })?;
+    if blobs.is_empty() {
+        return Ok(new_empty_array(data_type));
+    }
+    ensure!(
+        blobs.is_valid(0),
+        "encoded values should be in the first row of the batch"
+    );
+    let values = codec.decode(blobs.value(0))?;
+    ensure!(
+        values.len() == blobs.len(),
+        "batch should cover the whole row group, rows:{}, encoded:{}",
+        blobs.len(),
+        values.len()
+    );
+
+    Ok(values)
+}
+
+fn encode_gorilla(values: &[f64], writer: &mut BitWriter) {
+    let Some(first) = values.first() else {
+        return;
+    };
+    let mut prev = first.to_bits();
+    writer.write_bits(prev, 64);
+    // Window of the meaningful bits of the previous xor, `None` at first.
+    let mut window: Option<(u32, u32)> = None;
+    for v in &values[1..] {
+        let xor = v.to_bits() ^ prev;
+        prev = v.to_bits();
+        if xor == 0 {
+            writer.write_bit(false);
+            continue;
+        }
+
+        writer.write_bit(true);
+        let leading = xor.leading_zeros().min(31);
+        let trailing = xor.trailing_zeros();
+        match window {
+            Some((prev_leading, prev_trailing))
+                if leading >= prev_leading && trailing >= prev_trailing =>
+            {
+                writer.write_bit(false);
+                writer.write_bits(xor >> prev_trailing, 64 - prev_leading - prev_trailing);
+            }
+            _ => {
+                let len = 64 - leading - trailing;
+                writer.write_bit(true);
+                writer.write_bits(leading as u64, 5);
+                writer.write_bits((len - 1) as u64, 6);
+                writer.write_bits(xor >> trailing, len);
+                window = Some((leading, trailing));
+            }
+        }
+    }
+}
+
+fn decode_gorilla(num_values: usize, reader: &mut BitReader) -> Result<Vec<f64>> {
+    let mut values = Vec::with_capacity(num_values);
+    if num_values == 0 {
+        return Ok(values);
+    }
+    let mut prev = reader.read_bits(64)?;
+    values.push(f64::from_bits(prev));
+    let (mut leading, mut trailing) = (0, 0);
+    for _ in 1..num_values {
+        if reader.read_bit()? {
+            if reader.read_bit()? {
+                leading = reader.read_bits(5)? as u32;
+                let len = reader.read_bits(6)? as u32 + 1;
+                trailing = 64u32
+                    .checked_sub(leading + len)
+                    .context("invalid window of gorilla encoded value")?;
+            }
+            prev ^= reader.read_bits(64 - leading - trailing)? << trailing;
+        }
+        values.push(f64::from_bits(prev));
+    }
+
+    Ok(values)
+}
+
+/// Widths of the zigzag encoded delta of deltas, prefixed by the same number
+/// of one bits as its index, followed by a zero bit except the last one.
+const DELTA_OF_DELTA_WIDTHS: [u32; 5] = [0, 7, 9, 12, 64];
+
+fn encode_delta_of_delta(values: &[i64], writer: &mut BitWriter) {
+    let Some(first) = values.first() else {
+        return;
+    };
+    writer.write_bits(*first as u64, 64);
+    if values.len() < 2 {
+        return;
+    }
+    let mut delta = values[1].wrapping_sub(values[0]);
+    writer.write_bits(delta as u64, 64);
+    for pair in values[1..].windows(2) {
+        let new_delta = pair[1].wrapping_sub(pair[0]);
+        let dod = new_delta.wrapping_sub(delta);
+        delta = new_delta;
+        let zigzag = ((dod << 1) ^ (dod >> 63)) as u64;
+        let bucket = DELTA_OF_DELTA_WIDTHS
+            .iter()
+            .position(|width| *width == 64 || zigzag < 1 << width)
+            .unwrap();
+        for _ in 0..bucket {
+            writer.write_bit(true);
+        }
+        if bucket < DELTA_OF_DELTA_WIDTHS.len() - 1 {
+            writer.write_bit(false);
+        }
+        writer.write_bits(zigzag, DELTA_OF_DELTA_WIDTHS[bucket]);
+    }
+}
+
+fn decode_delta_of_delta(num_values: usize, reader: &mut BitReader) -> Result<Vec<i64>> {
+    let mut values = Vec::with_capacity(num_values);
+    if num_values == 0 {
+        return Ok(values);
+    }
+    let mut prev = reader.read_bits(64)? as i64;
+    values.push(prev);
+    if num_values < 2 {
+        return Ok(values);
+    }
+    let mut delta = reader.read_bits(64)? as i64;
+    prev = prev.wrapping_add(delta);
+    values.push(prev);
+    for _ in 2..num_values {
+        let mut bucket = 0;
+        while bucket < DELTA_OF_DELTA_WIDTHS.len() - 1 && reader.read_bit()? {
+            bucket += 1;
+        }
+        let zigzag = reader.read_bits(DELTA_OF_DELTA_WIDTHS[bucket])?;
+        let dod = (zigzag >> 1) as i64 ^ -((zigzag & 1) as i64);
+        delta = delta.wrapping_add(dod);
+        prev = prev.wrapping_add(delta);
+        values.push(prev);
+    }
+
+    Ok(values)
+}
+
+#[derive(Default)]
+struct BitWriter {
+    buf: Vec<u8>,
+    num_bits: usize,
+}
+
+impl BitWriter {
+    fn write_bit(&mut self, bit: bool) {
+        self.write_bits(bit as u64, 1);
+    }
+
+    /// Write the lowest `n` bits of `v`, the most significant bit first.
+    fn write_bits(&mut self, v: u64, mut n: u32) {
+        while n > 0 {
+            let used = (self.num_bits % 8) as u32;
+            if used == 0 {
+                self.buf.push(0);
+            }
+            let take = n.min(8 - used);
+            let bits = ((v >> (n - take)) & ((1 << take) - 1)) as u8;
+            *self.buf.last_mut().unwrap() |= bits << (8 - used - take);
+            self.num_bits += take as usize;
+            n -= take;
+        }
+    }
+}
+
+struct BitReader<'a> {
+    buf: &'a [u8],
+    pos: usize,
+}
+
+impl BitReader<'_> {
+    fn read_bit(&mut self) -> Result<bool> {
+        Ok(self.read_bits(1)? == 1)
+    }
+
+    fn read_bits(&mut self, mut n: u32) -> Result<u64> {
+        ensure!(
+            self.pos + n as usize <= self.buf.len() * 8,
+            "unexpected end of encoded values"
+        );
+        let mut v = 0u64;
+        while n > 0 {
+            let used = (self.pos % 8) as u32;
+            let take = n.min(8 - used);
+            let byte = self.buf[self.pos / 8] as u64;
+            v = (v << take) | ((byte >> (8 - used - take)) & ((1 << take) - 1));
+            self.pos += take as usize;
+            n -= take;
+        }
+
+        Ok(v)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn roundtrip(codec: ValueCodec, array: ArrayRef) {
+        let encoded = encode_column(codec, array.as_ref()).unwrap();
+        assert_eq!(array.len(), encoded.len());
+        assert_eq!(array.len().saturating_sub(1), encoded.null_count());
+        let decoded = decode_column(codec, &encoded, array.data_type()).unwrap();
+        assert_eq!(&array, &decoded);
+    }
+
+    #[test]
+    fn test_gorilla() {
+        let values = vec![
+            Some(1.0),
+            Some(1.0),
+            Some(1.5),
+            None,
+            Some(-3.25),
+            Some(f64::MAX),
+            Some(f64::MIN_POSITIVE),
+            Some(0.1),
+            Some(0.2),
+            Some(f64::INFINITY),
+        ];
+        roundtrip(ValueCodec::Gorilla, Arc::new(Float64Array::from(values)));
+        let values = (0..1000)
+            .map(|i| (i as f64 * 0.1).sin())
+            .collect::<Vec<_>>();
+        roundtrip(ValueCodec::Gorilla, Arc::new(Float64Array::from(values)));
+        roundtrip(
+            ValueCodec::Gorilla,
+            Arc::new(Float64Array::from(vec![42.0])),
+        );
+        roundtrip(ValueCodec::Gorilla, new_empty_array(&DataType::Float64));
+    }
+
+    #[test]
+    fn test_delta_of_delta() {
+        let values = vec![
+            Some(1000),
+            Some(2000),
+            Some(3000),
+            Some(4001),
+            None,
+            Some(4900),
+            Some(i64::MAX),
+            Some(i64::MIN),
+            Some(0),
+        ];
+        roundtrip(ValueCodec::DeltaOfDelta, Arc::new(Int64Array::from(values)));
+        // Values increasing at a constant rate take about one bit each.
+        let values = (0..1000)
+            .map(|i| 1_700_000_000_000 + i * 10)
+            .collect::<Vec<i64>>();
+        let array = Int64Array::new(values.into(), None);
+        assert!(ValueCodec::DeltaOfDelta.encode(&array).unwrap().len() < 200);
+        roundtrip(ValueCodec::DeltaOfDelta, Arc::new(array));
+        roundtrip(
+            ValueCodec::DeltaOfDelta,
+            Arc::new(Int64Array::from(vec![-7])),
+        );
+    }
+
+    #[test]
+    fn test_decode_partial_row_group() {
+        let array: ArrayRef = Arc::new(Int64Array::from(vec![1, 2, 3]));
+        let encoded = encode_column(ValueCodec::DeltaOfDelta, array.as_ref()).unwrap();
+        assert!(decode_column(
+            ValueCodec::DeltaOfDelta,
+            &encoded.slice(1, 2),
+            &DataType::Int64
+        )
+        .is_err());
+        assert!(decode_column(
+            ValueCodec::DeltaOfDelta,
+            &encoded.slice(0, 2),
+            &DataType::Int64
+        )
+        .is_err());
+    }
+}
@@ -0,0 +1,234 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+//! Deduplication of rows on ingestion.
+//!
+//! Exporters retry writes on timeout, so the same rows may be ingested more
+//! than once. Fingerprints of the primary keys and timestamp of rows ingested
+//! recently are remembered in two generations, and rows matching either of
+//! them are dropped before written, so retries don't inflate ssts before
+//! compaction dedups them.
+//!
+//! Fingerprints are 64-bit hashes, distinct rows are dropped only if their
+//! hashes collide, which is negligible.
+//!
+//! Storages in [MergeMode::MergeOnWrite] also dedup rows of the same keys
+//! exactly by [dedup_by_key] when ssts are written, where rows with larger
+//! sequences win.
+//!
+//! [MergeMode::MergeOnWrite]: crate::types::MergeMode::MergeOnWrite
+
+use std::{
+    collections::{
+        hash_map::{DefaultHasher, Entry},
+        HashMap, HashSet,
+    },
+    hash::{Hash, Hasher},
+    mem,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use anyhow::Context;
+use arrow::{
+    array::{AsArray, BooleanArray, RecordBatch, UInt32Array},
+    compute::{filter_record_batch, take_record_batch},
+    datatypes::UInt64Type,
+    row::{RowConverter, SortField},
+};
+
+use crate::Result;
+
+/// Options of ingestion deduplication.
+///
+/// Rows whose primary keys and timestamp equal those of a row ingested within
+/// `window` are dropped, and rows ingested within twice the window may be
+/// dropped too.
+#[derive(Debug, Clone)]
+pub struct IngestDedupOptions {
+    pub window: Duration,
+    /// Max rows remembered in one window, older rows are forgotten early once
+    /// exceeded.
+    pub max_rows: usize,
+}
+
+impl Default for IngestDedupOptions {
+    fn default() -> Self {
+        Self {
+            window: Duration::from_secs(300),
+            max_rows: 1 << 20,
+        }
+    }
+}
+
+struct Generations {
+    current: HashSet<u64>,
+    previous: HashSet<u64>,
+    rotated_at: Instant,
+}
+
+impl Generations {
+    fn rotate(&mut self, now: Instant) {
+        self.previous = mem::take(&mut self.current);
+        self.rotated_at = now;
+    }
+}
+
+/// Keep the last one of rows with the same values of `columns`, the kept rows
+/// are in their original order.
+///
+/// If `sequence_index` is set, the row with the largest sequence in the
+/// column is kept instead, and the last one of them on ties.
+pub(crate) fn dedup_by_key(
+    batch: RecordBatch,
+    columns: &[usize],
+    sequence_index: Option<usize>,
+) -> Result<RecordBatch> {
+    let sort_fields = columns
+        .iter()
+        .map(|i| SortField::new(batch.schema().field(*i).data_type().clone()))
+        .collect();
+    let converter = RowConverter::new(sort_fields).context("create row converter")?;
+    let key_columns = columns
+        .iter()
+        .map(|i| batch.column(*i).clone())
+        .collect::<Vec<_>>();
+    let rows = converter
+        .convert_columns(&key_columns)
+        .context("convert key columns")?;
+
+    let sequences = sequence_index.map(|i| batch.column(i).as_primitive::<UInt64Type>().values());
+    let mut last_rows = HashMap::with_capacity(rows.num_rows());
+    for (idx, row) in rows.iter().enumerate() {
+        match last_rows.entry(row) {
+            Entry::Occupied(mut kept) => {
+                if sequences.is_none_or(|v| v[idx] >= v[*kept.get() as usize]) {
+                    kept.insert(idx as u32);
+                }
+            }
+            Entry::Vacant(entry) => {
+                entry.insert(idx as u32);
+            }
+        }
+    }
+    if last_rows.len() == batch.num_rows() {
+        return Ok(batch);
+    }
+    let mut indices = last_rows.into_values().collect::<Vec<_>>();
+    indices.sort_unstable();
+    let batch =
+        take_record_batch(&batch, &UInt32Array::from(indices)).context("take deduplicated rows")?;
+
+    Ok(batch)
+}
+
+pub(crate) struct IngestDeduper {
+    /// Indices of the primary key columns and the timestamp column.
+    columns: Vec<usize>,
+    options: IngestDedupOptions,
+    generations: Mutex<Generations>,
+}
+
+impl IngestDeduper {
+    pub(crate) fn new(columns: Vec<usize>, options: IngestDedupOptions) -> Self {
+        Self {
+            columns,
+            options,
+            generations: Mutex::new(Generations {
+                current: HashSet::new(),
+                previous: HashSet::new(),
+                rotated_at: Instant::now(),
+            }),
+        }
+    }
+
+    /// Drop rows of `batch` ingested recently, returns the rows left and their
+    /// fingerprints, which should be recorded once the rows are written.
+    pub(crate) fn dedup(&self, batch: RecordBatch) -> Result<(RecordBatch, Vec<u64>)> {
+        let fingerprints = self.fingerprints(&batch)?;
+        let keep = {
+            let mut generations = self.generations.lock().unwrap();
+            self.expire(&mut generations);
+            fingerprints
+                .iter()
+                .map(|v| !generations.current.contains(v) && !generations.previous.contains(v))
+                .collect::<Vec<_>>()
+        };
+        if keep.iter().all(|v| *v) {
+            return Ok((batch, fingerprints));
+        }
+
+        let kept_fingerprints = fingerprints
+            .into_iter()
+            .zip(&keep)
+            .filter_map(|(v, keep)| keep.then_some(v))
+            .collect();
+        let batch = filter_record_batch(&batch, &BooleanArray::from(keep))
+            .context("filter ingested rows")?;
+        Ok((batch, kept_fingerprints))
+    }
+
+    /// Remember the fingerprints of rows written.
+    pub(crate) fn record(&self, fingerprints: Vec<u64>) {
+        let mut generations = self.generations.lock().unwrap();
+        self.expire(&mut generations);
+        for v in fingerprints {
+            if generations.current.len() >= self.options.max_rows {
+                generations.rotate(Instant::now());
+            }
+            generations.current.insert(v);
+        }
+    }
+
+    fn expire(&self, generations: &mut Generations) {
+        let now = Instant::now();
+        let elapsed = now.duration_since(generations.rotated_at);
+        if elapsed >= self.options.window * 2 {
+            generations.current.clear();
+            generations.previous.clear();
+            generations.rotated_at = now;
+        } else if elapsed >= self.options.window {
+            generations.rotate(now);
+        }
+    }
+
+    fn fingerprints(&self, batch: &RecordBatch) -> Result<Vec<u64>> {
+        let columns = self
+            .columns
+            .iter()
+            .map(|i| batch.column(*i).clone())
+            .collect::<Vec<_>>();
+        let converter = RowConverter::new(
+            columns
+                .iter()
+                .map(|c| SortField::new(c.data_type().clone()))
+                .collect(),
+        )
+        .context("create row converter")?;
+        let rows = converter
+            .convert_columns(&columns)
+            .context("convert key columns")?;
+
+        Ok(rows
+            .iter()
+            .map(|row| {
+                let mut hasher = DefaultHasher::new();
+                row.as_ref().hash(&mut hasher);
+                hasher.finish()
+            })
+            .collect())
+    }
+}
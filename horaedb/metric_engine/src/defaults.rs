@@ -0,0 +1,153 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+//! Default values of columns added by schema evolution.
+//!
+//! Fields may be appended to the schema of a storage after ssts are written,
+//! and those ssts have no values of them. Scans and compactions fill them with
+//! nulls, or with the default declared in the metadata of the field under
+//! [DEFAULT_VALUE_KEY], e.g. `{"horaedb.default": "0"}`, which is a constant
+//! parsed into the type of the field.
+//!
+//! Rows written after the field is added are never changed by the default,
+//! even if their values are null.
+
+use std::collections::HashMap;
+
+use anyhow::Context;
+use arrow::{
+    array::{new_null_array, ArrayRef, RecordBatch, RecordBatchOptions},
+    datatypes::{Field, Schema, SchemaRef},
+};
+use datafusion::common::ScalarValue;
+
+use crate::Result;
+
+/// Key of the default value in the metadata of a field.
+pub const DEFAULT_VALUE_KEY: &str = "horaedb.default";
+
+#[derive(Debug, Default)]
+pub(crate) struct ColumnDefaults {
+    values: HashMap<String, ScalarValue>,
+}
+
+impl ColumnDefaults {
+    pub(crate) fn try_new(schema: &Schema) -> Result<Self> {
+        let mut values = HashMap::new();
+        for field in schema.fields() {
+            let Some(value) = field.metadata().get(DEFAULT_VALUE_KEY) else {
+                continue;
+            };
+            let value = ScalarValue::try_from_string(value.clone(), field.data_type())
+                .with_context(|| {
+                    format!("invalid default of column {}, value:{value}", field.name())
+                })?;
+            values.insert(field.name().clone(), value);
+        }
+
+        Ok(Self { values })
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+
+    pub(crate) fn contains(&self, column: &str) -> bool {
+        self.values.contains_key(column)
+    }
+
+    /// Values of `field` for `num_rows` rows of an sst without it.
+    pub(crate) fn missing_column(&self, field: &Field, num_rows: usize) -> Result<ArrayRef> {
+        match self.values.get(field.name()) {
+            Some(value) => {
+                let array = value
+                    .to_array_of_size(num_rows)
+                    .with_context(|| format!("build default of column {}", field.name()))?;
+                Ok(array)
+            }
+            None => Ok(new_null_array(field.data_type(), num_rows)),
+        }
+    }
+
+    /// Rebuild `batch` read from an sst in `schema`, columns missing from the
+    /// sst are filled by [ColumnDefaults::missing_column].
+    pub(crate) fn fill_missing(
+        &self,
+        batch: RecordBatch,
+        schema: &SchemaRef,
+    ) -> Result<RecordBatch> {
+        if batch.schema().fields().len() == schema.fields().len() {
+            return Ok(batch);
+        }
+
+        let columns = schema
+            .fields()
+            .iter()
+            .map(|field| match batch.column_by_name(field.name()) {
+                Some(array) => Ok(array.clone()),
+                None => self.missing_column(field, batch.num_rows()),
+            })
+            .collect::<Result<Vec<_>>>()?;
+        let options = RecordBatchOptions::new().with_row_count(Some(batch.num_rows()));
+        let batch = RecordBatch::try_new_with_options(schema.clone(), columns, &options)
+            .context("fill missing columns")?;
+
+        Ok(batch)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use arrow::{
+        array::{Float64Array, Int64Array},
+        datatypes::DataType,
+    };
+
+    use super::*;
+
+    #[test]
+    fn test_fill_missing() {
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("ts", DataType::Int64, false),
+            Field::new("value", DataType::Float64, true).with_metadata(HashMap::from([(
+                DEFAULT_VALUE_KEY.to_string(),
+                "1.5".to_string(),
+            )])),
+            Field::new("other", DataType::Float64, true),
+        ]));
+        let defaults = ColumnDefaults::try_new(&schema).unwrap();
+        let old_schema = Arc::new(Schema::new(vec![Field::new("ts", DataType::Int64, false)]));
+        let batch =
+            RecordBatch::try_new(old_schema, vec![Arc::new(Int64Array::from(vec![1, 2]))]).unwrap();
+
+        let batch = defaults.fill_missing(batch, &schema).unwrap();
+        assert_eq!(
+            &(Arc::new(Float64Array::from(vec![1.5, 1.5])) as ArrayRef),
+            batch.column(1)
+        );
+        assert_eq!(2, batch.column(2).null_count());
+
+        let invalid = Schema::new(vec![Field::new("value", DataType::Float64, true)
+            .with_metadata(HashMap::from([(
+                DEFAULT_VALUE_KEY.to_string(),
+                "abc".to_string(),
+            )]))]);
+        assert!(ColumnDefaults::try_new(&invalid).is_err());
+    }
+}
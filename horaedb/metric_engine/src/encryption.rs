@@ -0,0 +1,406 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+//! Client-side encryption of objects written by the engine.
+//!
+//! [EncryptedObjectStore] wraps another object store, and encrypts every
+//! object with AES-256-GCM before it is written, so ssts and manifest files in
+//! a shared bucket are unreadable without the tenant key.
+//!
+//! Layout of an encrypted object:
+//! ```plaintext
+//! | version(u8) | key_id(u32) | nonce(12B) | ciphertext | tag(16B) |
+//! ```
+//! The version and key id are authenticated along with the ciphertext, and
+//! the key id allows keys to be rotated without rewriting existing objects.
+
+use std::{collections::HashMap, fmt, ops::Range, sync::Arc};
+
+use aes_gcm::{
+    aead::{Aead, AeadCore, KeyInit, OsRng, Payload},
+    Aes256Gcm, Key, Nonce,
+};
+use anyhow::anyhow;
+use async_trait::async_trait;
+use bytes::{BufMut, Bytes, BytesMut};
+use futures::{stream, stream::BoxStream, StreamExt, TryStreamExt};
+use object_store::{
+    path::Path, GetOptions, GetRange, GetResult, GetResultPayload, ListResult, MultipartUpload,
+    ObjectMeta, ObjectStore, PutMultipartOpts, PutOptions, PutPayload, PutResult, UploadPart,
+};
+
+use crate::{types::ObjectStoreRef, Error, Result};
+
+const STORE_NAME: &str = "Encrypted";
+const FORMAT_VERSION: u8 = 1;
+const NONCE_LEN: usize = 12;
+const TAG_LEN: usize = 16;
+/// Length of the authenticated header: version + key id.
+const AAD_LEN: usize = 1 + 4;
+const HEADER_LEN: usize = AAD_LEN + NONCE_LEN;
+/// Bytes added to every object by the encryption.
+const OVERHEAD: usize = HEADER_LEN + TAG_LEN;
+
+pub type EncryptionKey = [u8; 32];
+
+/// Provides keys used to encrypt and decrypt objects.
+pub trait KeyProvider: fmt::Debug + Send + Sync {
+    /// Returns the id and the key used to encrypt new objects.
+    fn current_key(&self) -> Result<(u32, EncryptionKey)>;
+
+    /// Returns the key with given id, used to decrypt existing objects.
+    fn key(&self, key_id: u32) -> Result<EncryptionKey>;
+}
+
+pub type KeyProviderRef = Arc<dyn KeyProvider>;
+
+/// Key provider holding a fixed set of keys in memory.
+pub struct StaticKeyProvider {
+    current_key_id: u32,
+    keys: HashMap<u32, EncryptionKey>,
+}
+
+impl StaticKeyProvider {
+    pub fn new(current_key_id: u32, current_key: EncryptionKey) -> Self {
+        Self {
+            current_key_id,
+            keys: HashMap::from([(current_key_id, current_key)]),
+        }
+    }
+
+    /// Add a key which is only used to decrypt objects written by it before.
+    pub fn with_retired_key(mut self, key_id: u32, key: EncryptionKey) -> Self {
+        self.keys.entry(key_id).or_insert(key);
+        self
+    }
+}
+
+impl fmt::Debug for StaticKeyProvider {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // Never print the keys.
+        f.debug_struct("StaticKeyProvider")
+            .field("current_key_id", &self.current_key_id)
+            .field("key_ids", &self.keys.keys().collect::<Vec<_>>())
+            .finish()
+    }
+}
+
+impl KeyProvider for StaticKeyProvider {
+    fn current_key(&self) -> Result<(u32, EncryptionKey)> {
+        self.key(self.current_key_id)
+            .map(|key| (self.current_key_id, key))
+    }
+
+    fn key(&self, key_id: u32) -> Result<EncryptionKey> {
+        self.keys
+            .get(&key_id)
+            .copied()
+            .ok_or_else(|| anyhow!("encryption key not found, id:{key_id}").into())
+    }
+}
+
+/// Object store which encrypts objects on write, and decrypts them on read
+/// transparently.
+///
+/// Sizes reported by `head` and `list` are sizes of the plaintext, so readers
+/// relying on object size, such as the parquet reader, work as usual.
+///
+/// Note: The whole object is fetched and decrypted for range requests.
+#[derive(Debug)]
+pub struct EncryptedObjectStore {
+    inner: ObjectStoreRef,
+    key_provider: KeyProviderRef,
+}
+
+impl EncryptedObjectStore {
+    pub fn new(inner: ObjectStoreRef, key_provider: KeyProviderRef) -> Self {
+        Self {
+            inner,
+            key_provider,
+        }
+    }
+
+    fn encrypt(&self, plaintext: &[u8]) -> Result<Bytes> {
+        let (key_id, key) = self.key_provider.current_key()?;
+        let cipher = Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&key));
+        let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+
+        let mut header = BytesMut::with_capacity(HEADER_LEN);
+        header.put_u8(FORMAT_VERSION);
+        header.put_u32(key_id);
+        let ciphertext = cipher
+            .encrypt(
+                &nonce,
+                Payload {
+                    msg: plaintext,
+                    aad: &header[..],
+                },
+            )
+            .map_err(|e| anyhow!("encrypt object, err:{e}"))?;
+
+        let mut buf = BytesMut::with_capacity(HEADER_LEN + ciphertext.len());
+        buf.put_slice(&header);
+        buf.put_slice(nonce.as_slice());
+        buf.put_slice(&ciphertext);
+        Ok(buf.freeze())
+    }
+
+    fn decrypt(&self, location: &Path, data: &[u8]) -> Result<Bytes> {
+        if data.len() < OVERHEAD {
+            return Err(anyhow!("encrypted object is too short, location:{location}").into());
+        }
+        let (aad, rest) = data.split_at(AAD_LEN);
+        let version = aad[0];
+        if version != FORMAT_VERSION {
+            return Err(anyhow!(
+                "unknown encryption version, location:{location}, version:{version}"
+            )
+            .into());
+        }
+        let key_id = u32::from_be_bytes(aad[1..].try_into().unwrap());
+        let key = self.key_provider.key(key_id)?;
+        let cipher = Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&key));
+        let (nonce, ciphertext) = rest.split_at(NONCE_LEN);
+        let plaintext = cipher
+            .decrypt(
+                Nonce::from_slice(nonce),
+                Payload {
+                    msg: ciphertext,
+                    aad,
+                },
+            )
+            .map_err(|e| anyhow!("decrypt object, location:{location}, err:{e}"))?;
+
+        Ok(Bytes::from(plaintext))
+    }
+}
+
+fn to_store_error(err: Error) -> object_store::Error {
+    object_store::Error::Generic {
+        store: STORE_NAME,
+        source: Box::new(err),
+    }
+}
+
+fn plaintext_meta(mut meta: ObjectMeta) -> ObjectMeta {
+    meta.size = meta.size.saturating_sub(OVERHEAD);
+    meta
+}
+
+fn resolve_range(range: Option<GetRange>, len: usize) -> Result<Range<usize>> {
+    let range = match range {
+        None => return Ok(0..len),
+        Some(GetRange::Bounded(r)) => r.start..r.end.min(len),
+        Some(GetRange::Offset(offset)) => offset..len,
+        Some(GetRange::Suffix(n)) => len.saturating_sub(n)..len,
+    };
+    if range.start >= range.end {
+        return Err(anyhow!("invalid range, range:{range:?}, len:{len}").into());
+    }
+
+    Ok(range)
+}
+
+impl fmt::Display for EncryptedObjectStore {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Encrypted({})", self.inner)
+    }
+}
+
+#[async_trait]
+impl ObjectStore for EncryptedObjectStore {
+    async fn put_opts(
+        &self,
+        location: &Path,
+        payload: PutPayload,
+        opts: PutOptions,
+    ) -> object_store::Result<PutResult> {
+        let encrypted = self
+            .encrypt(&Bytes::from(payload))
+            .map_err(to_store_error)?;
+        self.inner
+            .put_opts(location, PutPayload::from(encrypted), opts)
+            .await
+    }
+
+    async fn put_multipart_opts(
+        &self,
+        location: &Path,
+        opts: PutMultipartOpts,
+    ) -> object_store::Result<Box<dyn MultipartUpload>> {
+        // The whole object must be sealed by one tag, so parts are buffered and
+        // written at once on completion.
+        Ok(Box::new(EncryptedUpload {
+            store: EncryptedObjectStore::new(self.inner.clone(), self.key_provider.clone()),
+            location: location.clone(),
+            opts: Some(opts),
+            parts: Vec::new(),
+        }))
+    }
+
+    async fn get_opts(
+        &self,
+        location: &Path,
+        mut options: GetOptions,
+    ) -> object_store::Result<GetResult> {
+        let range = options.range.take();
+        let head = options.head;
+        let result = self.inner.get_opts(location, options).await?;
+        let meta = plaintext_meta(result.meta.clone());
+        let attributes = result.attributes.clone();
+        if head {
+            return Ok(GetResult {
+                payload: GetResultPayload::Stream(stream::empty().boxed()),
+                range: 0..meta.size,
+                meta,
+                attributes,
+            });
+        }
+
+        let data = result.bytes().await?;
+        let plaintext = self.decrypt(location, &data).map_err(to_store_error)?;
+        let range = resolve_range(range, plaintext.len()).map_err(to_store_error)?;
+        let bytes = plaintext.slice(range.clone());
+
+        Ok(GetResult {
+            payload: GetResultPayload::Stream(stream::once(async move { Ok(bytes) }).boxed()),
+            meta,
+            range,
+            attributes,
+        })
+    }
+
+    async fn delete(&self, location: &Path) -> object_store::Result<()> {
+        self.inner.delete(location).await
+    }
+
+    fn list(&self, prefix: Option<&Path>) -> BoxStream<'_, object_store::Result<ObjectMeta>> {
+        self.inner.list(prefix).map_ok(plaintext_meta).boxed()
+    }
+
+    async fn list_with_delimiter(&self, prefix: Option<&Path>) -> object_store::Result<ListResult> {
+        let mut result = self.inner.list_with_delimiter(prefix).await?;
+        result.objects = result.objects.into_iter().map(plaintext_meta).collect();
+        Ok(result)
+    }
+
+    // Encrypted objects are self-contained, so they can be copied as is.
+    async fn copy(&self, from: &Path, to: &Path) -> object_store::Result<()> {
+        self.inner.copy(from, to).await
+    }
+
+    async fn copy_if_not_exists(&self, from: &Path, to: &Path) -> object_store::Result<()> {
+        self.inner.copy_if_not_exists(from, to).await
+    }
+}
+
+#[derive(Debug)]
+struct EncryptedUpload {
+    store: EncryptedObjectStore,
+    location: Path,
+    opts: Option<PutMultipartOpts>,
+    parts: Vec<PutPayload>,
+}
+
+#[async_trait]
+impl MultipartUpload for EncryptedUpload {
+    fn put_part(&mut self, data: PutPayload) -> UploadPart {
+        self.parts.push(data);
+        Box::pin(futures::future::ready(Ok(())))
+    }
+
+    async fn complete(&mut self) -> object_store::Result<PutResult> {
+        let parts = std::mem::take(&mut self.parts);
+        let mut buf = BytesMut::with_capacity(parts.iter().map(|p| p.content_length()).sum());
+        for chunk in parts.iter().flat_map(|p| p.iter()) {
+            buf.put_slice(chunk);
+        }
+        let opts = self.opts.take().unwrap_or_default();
+        self.store
+            .put_opts(
+                &self.location,
+                PutPayload::from(buf.freeze()),
+                PutOptions {
+                    tags: opts.tags,
+                    attributes: opts.attributes,
+                    ..Default::default()
+                },
+            )
+            .await
+    }
+
+    async fn abort(&mut self) -> object_store::Result<()> {
+        self.parts.clear();
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use object_store::memory::InMemory;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_encrypted_object_store() {
+        let inner: ObjectStoreRef = Arc::new(InMemory::new());
+        let key_provider = Arc::new(StaticKeyProvider::new(1, [7; 32]));
+        let store = EncryptedObjectStore::new(inner.clone(), key_provider);
+
+        let path = Path::from("data/1");
+        let plaintext = b"hello horaedb";
+        store
+            .put(&path, PutPayload::from_static(plaintext))
+            .await
+            .unwrap();
+
+        // Object in the inner store is unreadable.
+        let raw = inner.get(&path).await.unwrap().bytes().await.unwrap();
+        assert_eq!(plaintext.len() + OVERHEAD, raw.len());
+        assert!(!raw.windows(plaintext.len()).any(|w| w == plaintext));
+
+        let meta = store.head(&path).await.unwrap();
+        assert_eq!(plaintext.len(), meta.size);
+        let data = store.get(&path).await.unwrap().bytes().await.unwrap();
+        assert_eq!(&plaintext[..], &data[..]);
+        let data = store.get_range(&path, 6..13).await.unwrap();
+        assert_eq!(b"horaedb", &data[..]);
+
+        let mut upload = store.put_multipart(&Path::from("data/2")).await.unwrap();
+        upload
+            .put_part(PutPayload::from_static(b"hello "))
+            .await
+            .unwrap();
+        upload
+            .put_part(PutPayload::from_static(b"world"))
+            .await
+            .unwrap();
+        upload.complete().await.unwrap();
+        let data = store
+            .get(&Path::from("data/2"))
+            .await
+            .unwrap()
+            .bytes()
+            .await
+            .unwrap();
+        assert_eq!(b"hello world", &data[..]);
+
+        // Objects are unreadable with a wrong key.
+        let other = EncryptedObjectStore::new(inner, Arc::new(StaticKeyProvider::new(1, [8; 32])));
+        assert!(other.get(&path).await.is_err());
+    }
+}
@@ -15,14 +15,138 @@
 // specific language governing permissions and limitations
 // under the License.
 
+//! Errors of the metric engine.
+//!
+//! Errors callers may react to have their own variants, and they are grouped
+//! by [ErrorKind], e.g. to map them to gRPC status codes. The others are
+//! wrapped as [Error::Internal].
+
+use std::sync::Arc;
+
 pub use anyhow::Error as AnyhowError;
 use thiserror::Error;
 
 #[derive(Error, Debug)]
 #[non_exhaustive]
 pub enum Error {
+    #[error("quota exceeded, tenant:{tenant}, {msg}")]
+    QuotaExceeded { tenant: String, msg: String },
+
+    #[error("server is busy, {msg}")]
+    Backpressure { msg: String },
+
+    #[error("primary key contains nulls, column:{column}, num_nulls:{num_nulls}")]
+    NullPrimaryKey { column: String, num_nulls: usize },
+
+    #[error("schema mismatch, {msg}")]
+    SchemaMismatch { msg: String },
+
+    #[error("invalid timestamp, {msg}")]
+    TimestampInvalid { msg: String },
+
+    #[error("manifest is corrupted, {msg}")]
+    ManifestCorrupt {
+        msg: String,
+        #[source]
+        source: Option<AnyhowError>,
+    },
+
+    #[error("manifest version {version} is unavailable, {msg}")]
+    VersionUnavailable { version: u64, msg: String },
+
+    #[error("data is corrupted, path:{path}, {msg}")]
+    Corruption { path: String, msg: String },
+
+    #[error("storage io failed, {msg}, err:{source}")]
+    StorageIo {
+        msg: String,
+        #[source]
+        source: object_store::Error,
+    },
+
+    /// Error shared by several requests, e.g. updates committed to the
+    /// manifest together.
+    #[error(transparent)]
+    Shared(Arc<Error>),
+
     #[error(transparent)]
     Internal(#[from] anyhow::Error),
 }
 
+/// Kinds of errors, which tell callers how to handle them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorKind {
+    /// The request is invalid, and retrying it won't help.
+    InvalidArgument,
+    /// The request is rejected by quotas or backpressure, it may succeed
+    /// after a while.
+    ResourceExhausted,
+    /// The object store is unavailable, it may succeed after a while.
+    Unavailable,
+    /// The persisted data is unrecoverable.
+    DataLoss,
+    Internal,
+}
+
+impl Error {
+    pub fn kind(&self) -> ErrorKind {
+        match self {
+            Self::NullPrimaryKey { .. }
+            | Self::SchemaMismatch { .. }
+            | Self::TimestampInvalid { .. }
+            | Self::VersionUnavailable { .. } => ErrorKind::InvalidArgument,
+            Self::QuotaExceeded { .. } | Self::Backpressure { .. } => ErrorKind::ResourceExhausted,
+            Self::StorageIo { .. } => ErrorKind::Unavailable,
+            Self::ManifestCorrupt { .. } | Self::Corruption { .. } => ErrorKind::DataLoss,
+            Self::Shared(e) => e.kind(),
+            Self::Internal(_) => ErrorKind::Internal,
+        }
+    }
+
+    pub(crate) fn manifest_corrupt(msg: impl Into<String>) -> Self {
+        Self::ManifestCorrupt {
+            msg: msg.into(),
+            source: None,
+        }
+    }
+}
+
+/// Convert errors of the object store into [Error::StorageIo].
+pub(crate) trait StorageIoContext<T> {
+    fn storage_io<F>(self, msg: F) -> Result<T>
+    where
+        F: FnOnce() -> String;
+}
+
+impl<T> StorageIoContext<T> for std::result::Result<T, object_store::Error> {
+    fn storage_io<F>(self, msg: F) -> Result<T>
+    where
+        F: FnOnce() -> String,
+    {
+        self.map_err(|source| Error::StorageIo { msg: msg(), source })
+    }
+}
+
 pub type Result<T> = std::result::Result<T, Error>;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_error_kind() {
+        let err: Result<()> = Err(object_store::Error::NotImplemented).storage_io(|| "get".into());
+        let err = err.unwrap_err();
+        assert_eq!(ErrorKind::Unavailable, err.kind());
+        assert!(err.to_string().starts_with("storage io failed, get"));
+
+        assert_eq!(
+            ErrorKind::DataLoss,
+            Error::manifest_corrupt("file meta is missing").kind()
+        );
+        assert_eq!(
+            ErrorKind::Internal,
+            Error::from(anyhow::anyhow!("unknown")).kind()
+        );
+    }
+}
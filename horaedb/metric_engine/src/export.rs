@@ -0,0 +1,256 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+//! Export SSTs to a plain, date-partitioned parquet dataset.
+//!
+//! The exported layout is compatible with Hive-style partition discovery used
+//! by Spark/Trino:
+//! ```plaintext
+//! {prefix}/dt=2024-10-01/{file_id}.parquet
+//! {prefix}/dt=2024-10-02/{file_id}.parquet
+//! ```
+
+use std::collections::BTreeMap;
+
+use anyhow::Context;
+use arrow::{
+    array::{new_null_array, BooleanArray, Int64Array, RecordBatch},
+    compute::filter_record_batch,
+    datatypes::SchemaRef,
+};
+use futures::StreamExt;
+use object_store::path::Path;
+use parquet::arrow::{
+    async_reader::ParquetObjectReader, async_writer::ParquetObjectWriter, AsyncArrowWriter,
+    ParquetRecordBatchStreamBuilder, ProjectionMask,
+};
+
+use crate::{
+    codec::ColumnCodecs,
+    sst::{FileId, SstFile},
+    types::{ObjectStoreRef, TimeRange, TimeUnit},
+    Result,
+};
+
+pub struct ExportRequest {
+    /// Only rows within this range are exported.
+    pub range: TimeRange,
+    /// Destination store of the exported dataset.
+    pub store: ObjectStoreRef,
+    /// Root path of the exported dataset in the destination store.
+    pub prefix: String,
+}
+
+#[derive(Debug, Clone)]
+pub struct ExportedFile {
+    pub path: String,
+    pub num_rows: usize,
+}
+
+#[derive(Debug, Default)]
+pub struct ExportResult {
+    pub files: Vec<ExportedFile>,
+}
+
+/// Rewrites one sst into the destination dataset.
+///
+/// Only columns of `schema` are kept, so engine-internal columns are stripped
+/// from the output, and columns encoded by `codecs` are decoded. Columns not
+/// written since they are all null, see [FileMeta::null_columns], are filled
+/// with nulls.
+///
+/// [FileMeta::null_columns]: crate::sst::FileMeta::null_columns
+#[allow(clippy::too_many_arguments)]
+pub(crate) async fn export_sst(
+    src_store: ObjectStoreRef,
+    src_path: Path,
+    sst: &SstFile,
+    schema: &SchemaRef,
+    codecs: &ColumnCodecs,
+    timestamp_index: usize,
+    time_unit: TimeUnit,
+    req: &ExportRequest,
+) -> Result<Vec<ExportedFile>> {
+    let object_meta = src_store
+        .head(&src_path)
+        .await
+        .with_context(|| format!("get object meta, path:{src_path}"))?;
+    let reader = ParquetObjectReader::new(src_store, object_meta);
+    let mut builder = ParquetRecordBatchStreamBuilder::new(reader)
+        .await
+        .context("create parquet stream builder")?;
+    if let Some(batch_size) = codecs.min_read_batch_size() {
+        builder = builder.with_batch_size(batch_size);
+    }
+    let file_schema = builder.schema().clone();
+    let indices = schema
+        .fields()
+        .iter()
+        .filter(|field| !sst.meta.null_columns.contains(field.name()))
+        .map(|field| {
+            file_schema
+                .index_of(field.name())
+                .with_context(|| format!("column {} is missing in sst {}", field.name(), sst.id))
+        })
+        .collect::<anyhow::Result<Vec<_>>>()?;
+    let mask = ProjectionMask::roots(builder.parquet_schema(), indices);
+    let mut stream = builder
+        .with_projection(mask)
+        .build()
+        .context("build parquet stream")?;
+
+    let mut writers: BTreeMap<i64, (String, AsyncArrowWriter<ParquetObjectWriter>, usize)> =
+        BTreeMap::new();
+    while let Some(batch) = stream.next().await {
+        let batch = batch.context("read sst batch")?;
+        // Projection keeps the file's column order, so rebuild with the user schema.
+        let columns = schema
+            .fields()
+            .iter()
+            .map(|field| match batch.column_by_name(field.name()) {
+                Some(array) => codecs.decode_column(field.name(), array, field.data_type()),
+                None => Ok(new_null_array(field.data_type(), batch.num_rows())),
+            })
+            .collect::<Result<Vec<_>>>()?;
+        let batch = RecordBatch::try_new(schema.clone(), columns)
+            .context("rebuild batch with user schema")?;
+        for (day, part) in split_by_day(&batch, timestamp_index, time_unit, &req.range)? {
+            if !writers.contains_key(&day) {
+                let path = build_export_path(&req.prefix, day, sst.id);
+                let object_writer =
+                    ParquetObjectWriter::new(req.store.clone(), Path::from(path.as_str()));
+                let writer = AsyncArrowWriter::try_new(object_writer, schema.clone(), None)
+                    .context("create arrow writer")?;
+                writers.insert(day, (path, writer, 0));
+            }
+            let (_, writer, num_rows) = writers.get_mut(&day).unwrap();
+            writer.write(&part).await.context("write arrow batch")?;
+            *num_rows += part.num_rows();
+        }
+    }
+
+    let mut files = Vec::with_capacity(writers.len());
+    for (_, (path, writer, num_rows)) in writers {
+        writer.close().await.context("close arrow writer")?;
+        files.push(ExportedFile { path, num_rows });
+    }
+
+    Ok(files)
+}
+
+/// Split batch into parts, one for each day, rows out of `range` are dropped.
+fn split_by_day(
+    batch: &RecordBatch,
+    timestamp_index: usize,
+    time_unit: TimeUnit,
+    range: &TimeRange,
+) -> Result<Vec<(i64, RecordBatch)>> {
+    let units_per_day = time_unit.units_per_day();
+    let time_column = batch
+        .column(timestamp_index)
+        .as_any()
+        .downcast_ref::<Int64Array>()
+        .context("timestamp column should be int64")?;
+
+    let mut days = time_column
+        .values()
+        .iter()
+        .filter(|v| range.start.0 <= **v && **v < range.end.0)
+        .map(|v| v.div_euclid(units_per_day))
+        .collect::<Vec<_>>();
+    days.sort_unstable();
+    days.dedup();
+
+    let mut parts = Vec::with_capacity(days.len());
+    for day in days {
+        let predicate = time_column
+            .values()
+            .iter()
+            .map(|v| {
+                Some(range.start.0 <= *v && *v < range.end.0 && v.div_euclid(units_per_day) == day)
+            })
+            .collect::<BooleanArray>();
+        let part = filter_record_batch(batch, &predicate).context("filter batch by day")?;
+        parts.push((day, part));
+    }
+
+    Ok(parts)
+}
+
+fn build_export_path(prefix: &str, day: i64, id: FileId) -> String {
+    let (year, month, day) = civil_from_days(day);
+    format!("{prefix}/dt={year:04}-{month:02}-{day:02}/{id}.parquet")
+}
+
+/// Convert days since unix epoch to (year, month, day).
+///
+/// See http://howardhinnant.github.io/date_algorithms.html#civil_from_days
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let d = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let m = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let y = yoe + era * 400;
+
+    (if m <= 2 { y + 1 } else { y }, m, d)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use arrow::datatypes::{DataType, Field, Schema};
+
+    use super::*;
+
+    const MILLIS_PER_DAY: i64 = TimeUnit::Millisecond.units_per_day();
+
+    #[test]
+    fn test_civil_from_days() {
+        assert_eq!((1970, 1, 1), civil_from_days(0));
+        assert_eq!((1969, 12, 31), civil_from_days(-1));
+        assert_eq!((2000, 2, 29), civil_from_days(11016));
+        assert_eq!((2024, 10, 16), civil_from_days(20012));
+    }
+
+    #[test]
+    fn test_split_by_day() {
+        let schema = Arc::new(Schema::new(vec![Field::new("ts", DataType::Int64, false)]));
+        let batch = RecordBatch::try_new(
+            schema,
+            vec![Arc::new(Int64Array::from(vec![
+                1,
+                MILLIS_PER_DAY - 1,
+                MILLIS_PER_DAY,
+                2 * MILLIS_PER_DAY + 1,
+            ]))],
+        )
+        .unwrap();
+
+        let range = TimeRange::new(0.into(), (2 * MILLIS_PER_DAY).into());
+        let parts = split_by_day(&batch, 0, TimeUnit::Millisecond, &range).unwrap();
+        let parts = parts
+            .into_iter()
+            .map(|(day, part)| (day, part.num_rows()))
+            .collect::<Vec<_>>();
+        assert_eq!(vec![(0, 2), (1, 1)], parts);
+    }
+}
@@ -0,0 +1,113 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+//! Health checks of a storage, see
+//! [CloudObjectStorage::health](crate::storage::CloudObjectStorage::health).
+//!
+//! Every check results in a [HealthStatus], and the status of the storage is
+//! the worst of them. Storages unhealthy can't serve requests, e.g. the object
+//! store is unreachable, while degraded ones still serve them but need
+//! attention of operators, e.g. compactions fall behind.
+
+use std::time::Duration;
+
+use crate::types::Timestamp;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum HealthStatus {
+    Healthy,
+    Degraded,
+    Unhealthy,
+}
+
+#[derive(Debug, Clone)]
+pub struct HealthOptions {
+    /// Degraded once the max committed timestamp is ahead of the local clock
+    /// by more than it, which means either the clock or the writers are wrong.
+    pub max_clock_skew: Duration,
+    /// Degraded once more L0 ssts than it are waiting to be compacted.
+    pub max_l0_files: usize,
+}
+
+impl Default for HealthOptions {
+    fn default() -> Self {
+        Self {
+            max_clock_skew: Duration::from_secs(300),
+            max_l0_files: 64,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CheckResult {
+    pub status: HealthStatus,
+    /// Why the check is not healthy, empty if it is.
+    pub message: String,
+    pub elapsed: Duration,
+}
+
+impl CheckResult {
+    pub(crate) fn healthy(elapsed: Duration) -> Self {
+        Self {
+            status: HealthStatus::Healthy,
+            message: String::new(),
+            elapsed,
+        }
+    }
+
+    pub(crate) fn new(status: HealthStatus, message: String, elapsed: Duration) -> Self {
+        Self {
+            status,
+            message,
+            elapsed,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct HealthReport {
+    /// Listing the root of the storage, and the local tier if it's enabled.
+    pub object_store: CheckResult,
+    /// Loading the manifest from the object store.
+    pub manifest: CheckResult,
+    /// Comparing the local clock with `max_timestamp`.
+    pub clock: CheckResult,
+    /// Comparing L0 ssts with [HealthOptions::max_l0_files].
+    pub compaction: CheckResult,
+    /// Max timestamp of committed rows in the time unit of the storage,
+    /// `None` if there are no ssts.
+    pub max_timestamp: Option<Timestamp>,
+    pub l0_files: usize,
+    pub l0_bytes: u64,
+    /// Buckets of small ssts scheduled to be merged by the next compaction.
+    pub pending_compactions: usize,
+}
+
+impl HealthReport {
+    pub fn status(&self) -> HealthStatus {
+        [
+            &self.object_store,
+            &self.manifest,
+            &self.clock,
+            &self.compaction,
+        ]
+        .iter()
+        .map(|check| check.status)
+        .max()
+        .unwrap_or(HealthStatus::Healthy)
+    }
+}
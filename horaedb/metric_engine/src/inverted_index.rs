@@ -0,0 +1,245 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+//! Inverted index of tag columns.
+//!
+//! Every sst may be written with an inverted index mapping each value of the
+//! indexed tag columns to the bitmap of row groups containing it:
+//! ```plaintext
+//! {root_path}/index/{file_id}
+//! ```
+//! Equality predicates on the tag columns look up the index before the scan is
+//! planned, so row groups without the values are skipped even if the bloom
+//! filters of high-cardinality tags have false positives.
+
+use std::collections::BTreeMap;
+
+use anyhow::Context;
+use arrow::array::{Array, ArrayRef, AsArray, RecordBatch};
+use datafusion::common::ScalarValue;
+
+use crate::{bloom::KeyEquality, Result};
+
+/// Columns with more distinct values in an sst are not indexed.
+const MAX_VALUES_PER_COLUMN: usize = 1 << 20;
+
+struct IndexedColumn {
+    index: usize,
+    name: String,
+    /// Value -> bitmap of row groups, `None` once there are too many values.
+    values: Option<BTreeMap<Vec<u8>, Vec<u8>>>,
+}
+
+/// Builder of the inverted index of an sst, batches are added in the order
+/// they are written.
+pub(crate) struct InvertedIndexBuilder {
+    columns: Vec<IndexedColumn>,
+    num_row_groups: usize,
+}
+
+impl InvertedIndexBuilder {
+    /// Index the columns of the given indices and names.
+    pub(crate) fn new(columns: impl IntoIterator<Item = (usize, String)>) -> Self {
+        Self {
+            columns: columns
+                .into_iter()
+                .map(|(index, name)| IndexedColumn {
+                    index,
+                    name,
+                    values: Some(BTreeMap::new()),
+                })
+                .collect(),
+            num_row_groups: 0,
+        }
+    }
+
+    /// Add rows of `batch` written into the row group `row_group`.
+    pub(crate) fn add_batch(&mut self, batch: &RecordBatch, row_group: usize) -> Result<()> {
+        self.num_row_groups = self.num_row_groups.max(row_group + 1);
+        let (byte, bit) = (row_group / 8, 1 << (row_group % 8));
+        for column in &mut self.columns {
+            let Some(values) = &mut column.values else {
+                continue;
+            };
+            let array = batch.column(column.index);
+            for row in 0..batch.num_rows() {
+                let Some(value) = value_at(array, row)? else {
+                    continue;
+                };
+                let bitmap = values.entry(value).or_default();
+                if bitmap.len() <= byte {
+                    bitmap.resize(byte + 1, 0);
+                }
+                bitmap[byte] |= bit;
+            }
+            if values.len() > MAX_VALUES_PER_COLUMN {
+                column.values = None;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Returns `None` if no column is indexed or no row is added.
+    pub(crate) fn finish(self) -> Option<pb_types::InvertedIndex> {
+        if self.num_row_groups == 0 {
+            return None;
+        }
+        let columns = self
+            .columns
+            .into_iter()
+            .filter_map(|column| {
+                let (values, row_groups) = column.values?.into_iter().unzip();
+                Some(pb_types::InvertedIndexColumn {
+                    name: column.name,
+                    values,
+                    row_groups,
+                })
+            })
+            .collect::<Vec<_>>();
+        if columns.is_empty() {
+            return None;
+        }
+
+        Some(pb_types::InvertedIndex {
+            num_row_groups: self.num_row_groups as u32,
+            columns,
+        })
+    }
+}
+
+/// Returns whether every row group of the sst may contain rows matching all
+/// `equalities` by the inverted `index`.
+///
+/// Equalities on columns not indexed are ignored.
+pub(crate) fn prune_row_groups(
+    index: &pb_types::InvertedIndex,
+    equalities: &[KeyEquality],
+) -> Vec<bool> {
+    let mut keep = vec![true; index.num_row_groups as usize];
+    for eq in equalities {
+        let Some(column) = index.columns.iter().find(|c| c.name == eq.column) else {
+            continue;
+        };
+        let Some(values) = eq
+            .values
+            .iter()
+            .map(encode_value)
+            .collect::<Option<Vec<_>>>()
+        else {
+            continue;
+        };
+
+        let mut matched = vec![false; keep.len()];
+        for value in values {
+            let Ok(pos) = column.values.binary_search(&value) else {
+                continue;
+            };
+            let bitmap = &column.row_groups[pos];
+            for (row_group, matched) in matched.iter_mut().enumerate() {
+                let byte = bitmap.get(row_group / 8).copied().unwrap_or_default();
+                *matched |= byte & (1 << (row_group % 8)) != 0;
+            }
+        }
+        for (keep, matched) in keep.iter_mut().zip(matched) {
+            *keep &= matched;
+        }
+    }
+
+    keep
+}
+
+fn value_at(array: &ArrayRef, row: usize) -> Result<Option<Vec<u8>>> {
+    if array.is_null(row) {
+        return Ok(None);
+    }
+    if let Some(array) = array.as_string_opt::<i32>() {
+        return Ok(Some(array.value(row).as_bytes().to_vec()));
+    }
+
+    let value = ScalarValue::try_from_array(array, row).context("get value of tag column")?;
+    Ok(encode_value(&value))
+}
+
+/// Bytes of `value` in the index, `None` for nulls.
+fn encode_value(value: &ScalarValue) -> Option<Vec<u8>> {
+    if value.is_null() {
+        return None;
+    }
+
+    let bytes = match value {
+        ScalarValue::Dictionary(_, v) => return encode_value(v),
+        ScalarValue::Utf8(Some(v))
+        | ScalarValue::LargeUtf8(Some(v))
+        | ScalarValue::Utf8View(Some(v)) => v.as_bytes().to_vec(),
+        ScalarValue::Binary(Some(v))
+        | ScalarValue::LargeBinary(Some(v))
+        | ScalarValue::BinaryView(Some(v))
+        | ScalarValue::FixedSizeBinary(_, Some(v)) => v.clone(),
+        v => v.to_string().into_bytes(),
+    };
+    Some(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use arrow::{
+        array::StringArray,
+        datatypes::{DataType, Field, Schema},
+    };
+    use datafusion::prelude::{col, lit};
+
+    use super::*;
+    use crate::bloom::key_equalities;
+
+    #[test]
+    fn test_prune_row_groups() {
+        let schema = Arc::new(Schema::new(vec![Field::new("host", DataType::Utf8, true)]));
+        let batch = |hosts: Vec<Option<&str>>| {
+            RecordBatch::try_new(schema.clone(), vec![Arc::new(StringArray::from(hosts))]).unwrap()
+        };
+        let mut builder = InvertedIndexBuilder::new([(0, "host".to_string())]);
+        builder
+            .add_batch(&batch(vec![Some("a"), Some("b")]), 0)
+            .unwrap();
+        builder.add_batch(&batch(vec![Some("b"), None]), 1).unwrap();
+        builder.add_batch(&batch(vec![Some("c")]), 9).unwrap();
+        let index = builder.finish().unwrap();
+        assert_eq!(10, index.num_row_groups);
+
+        let prune = |expr| {
+            let equalities = key_equalities(&[expr], &schema, &[0]);
+            prune_row_groups(&index, &equalities)
+        };
+        let expected =
+            |row_groups: &[usize]| (0..10).map(|i| row_groups.contains(&i)).collect::<Vec<_>>();
+        assert_eq!(expected(&[0]), prune(col("host").eq(lit("a"))));
+        assert_eq!(expected(&[0, 1]), prune(col("host").eq(lit("b"))));
+        assert_eq!(
+            expected(&[0, 9]),
+            prune(col("host").in_list(vec![lit("a"), lit("c")], false))
+        );
+        assert_eq!(expected(&[]), prune(col("host").eq(lit("d"))));
+
+        // Nothing is indexed.
+        assert!(InvertedIndexBuilder::new([(0, "host".to_string())])
+            .finish()
+            .is_none());
+    }
+}
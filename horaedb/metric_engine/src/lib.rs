@@ -17,11 +17,37 @@
 
 //! Storage Engine for metrics.
 
+pub mod backup;
+mod bloom;
+pub mod check;
+pub mod checksum;
+pub mod codec;
+pub mod dedup;
+pub mod defaults;
+pub mod encryption;
 pub mod error;
-mod manifest;
+pub mod export;
+pub mod health;
+mod inverted_index;
+pub mod limiter;
+pub mod manifest;
+pub mod memory;
+pub mod multipart;
+mod operator;
+mod primary_key;
+pub mod quota;
 mod read;
+pub mod replication;
+pub mod result_cache;
+pub mod series;
 mod sst;
 pub mod storage;
+pub mod store_factory;
+pub mod store_provider;
+#[cfg(any(test, feature = "testing"))]
+pub mod testing;
+pub mod tier;
+pub mod tombstone;
 pub mod types;
 
-pub use error::{AnyhowError, Error, Result};
+pub use error::{AnyhowError, Error, ErrorKind, Result};
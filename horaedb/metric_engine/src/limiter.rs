@@ -0,0 +1,233 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+//! Node level limits on reading ssts.
+//!
+//! Scans and compactions of all storages on a node share one [IoLimiter], so
+//! bursty load can't exhaust the connection pool of the object store, or get
+//! throttled by the provider. A single task, e.g. a manual compaction, may be
+//! paced further by a [RateLimiter].
+
+use std::{ops::Range, sync::Arc};
+
+use bytes::Bytes;
+use futures::{future::BoxFuture, FutureExt};
+use parquet::{
+    arrow::async_reader::AsyncFileReader,
+    errors::{ParquetError, Result as ParquetResult},
+    file::metadata::ParquetMetaData,
+};
+use tokio::{
+    sync::{OwnedSemaphorePermit, Semaphore},
+    time::{Duration, Instant},
+};
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct IoLimits {
+    /// Max number of concurrent GET requests, `None` means unlimited.
+    pub max_concurrent_gets: Option<usize>,
+    /// Max number of parquet readers open at the same time, `None` means
+    /// unlimited.
+    pub max_open_readers: Option<usize>,
+}
+
+pub type IoLimiterRef = Arc<IoLimiter>;
+
+#[derive(Debug)]
+pub struct IoLimiter {
+    gets: Arc<Semaphore>,
+    readers: Arc<Semaphore>,
+}
+
+impl IoLimiter {
+    pub fn new(limits: IoLimits) -> Self {
+        let semaphore = |limit: Option<usize>| {
+            Arc::new(Semaphore::new(
+                limit
+                    .unwrap_or(Semaphore::MAX_PERMITS)
+                    .min(Semaphore::MAX_PERMITS),
+            ))
+        };
+
+        Self {
+            gets: semaphore(limits.max_concurrent_gets),
+            readers: semaphore(limits.max_open_readers),
+        }
+    }
+
+    pub fn available_gets(&self) -> usize {
+        self.gets.available_permits()
+    }
+
+    pub fn available_readers(&self) -> usize {
+        self.readers.available_permits()
+    }
+}
+
+impl Default for IoLimiter {
+    fn default() -> Self {
+        Self::new(IoLimits::default())
+    }
+}
+
+/// Limiter of the bytes per second of a task.
+///
+/// Acquired bytes delay the next acquisition until they are within the rate,
+/// so a large acquisition proceeds at once, and the following ones wait.
+pub(crate) struct RateLimiter {
+    bytes_per_sec: u64,
+    next_free: Instant,
+}
+
+impl RateLimiter {
+    pub(crate) fn new(bytes_per_sec: u64) -> Self {
+        Self {
+            bytes_per_sec: bytes_per_sec.max(1),
+            next_free: Instant::now(),
+        }
+    }
+
+    /// Wait for the bytes acquired before, and then acquire `bytes`.
+    pub(crate) async fn acquire(&mut self, bytes: u64) {
+        tokio::time::sleep_until(self.next_free).await;
+        self.consume(bytes);
+    }
+
+    /// Acquire `bytes` without waiting, e.g. for bytes already written.
+    pub(crate) fn consume(&mut self, bytes: u64) {
+        let cost = Duration::from_secs_f64(bytes as f64 / self.bytes_per_sec as f64);
+        self.next_free = self.next_free.max(Instant::now()) + cost;
+    }
+}
+
+async fn acquire(semaphore: &Arc<Semaphore>) -> ParquetResult<OwnedSemaphorePermit> {
+    semaphore
+        .clone()
+        .acquire_owned()
+        .await
+        .map_err(|e| ParquetError::External(Box::new(e)))
+}
+
+/// [AsyncFileReader] limited by an [IoLimiter].
+///
+/// A reader is counted as open from its first request until it's dropped.
+pub struct LimitedReader<R> {
+    inner: R,
+    limiter: IoLimiterRef,
+    reader_permit: Option<OwnedSemaphorePermit>,
+}
+
+impl<R> LimitedReader<R> {
+    pub fn new(inner: R, limiter: IoLimiterRef) -> Self {
+        Self {
+            inner,
+            limiter,
+            reader_permit: None,
+        }
+    }
+
+    /// Returns the permit of a GET request, which should be held until the
+    /// request finishes.
+    async fn acquire_get(&mut self) -> ParquetResult<OwnedSemaphorePermit> {
+        if self.reader_permit.is_none() {
+            self.reader_permit = Some(acquire(&self.limiter.readers).await?);
+        }
+
+        acquire(&self.limiter.gets).await
+    }
+}
+
+impl<R: AsyncFileReader> AsyncFileReader for LimitedReader<R> {
+    fn get_bytes(&mut self, range: Range<usize>) -> BoxFuture<'_, ParquetResult<Bytes>> {
+        async move {
+            let _permit = self.acquire_get().await?;
+            self.inner.get_bytes(range).await
+        }
+        .boxed()
+    }
+
+    fn get_byte_ranges(
+        &mut self,
+        ranges: Vec<Range<usize>>,
+    ) -> BoxFuture<'_, ParquetResult<Vec<Bytes>>> {
+        async move {
+            let _permit = self.acquire_get().await?;
+            self.inner.get_byte_ranges(ranges).await
+        }
+        .boxed()
+    }
+
+    fn get_metadata(&mut self) -> BoxFuture<'_, ParquetResult<Arc<ParquetMetaData>>> {
+        async move {
+            let _permit = self.acquire_get().await?;
+            self.inner.get_metadata().await
+        }
+        .boxed()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct MockReader;
+
+    impl AsyncFileReader for MockReader {
+        fn get_bytes(&mut self, range: Range<usize>) -> BoxFuture<'_, ParquetResult<Bytes>> {
+            futures::future::ready(Ok(Bytes::from(vec![0; range.len()]))).boxed()
+        }
+
+        fn get_metadata(&mut self) -> BoxFuture<'_, ParquetResult<Arc<ParquetMetaData>>> {
+            unimplemented!()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_limited_reader() {
+        let limiter = Arc::new(IoLimiter::new(IoLimits {
+            max_concurrent_gets: Some(4),
+            max_open_readers: Some(1),
+        }));
+
+        let mut reader = LimitedReader::new(MockReader, limiter.clone());
+        assert_eq!(1, limiter.available_readers());
+        assert_eq!(8, reader.get_bytes(0..8).await.unwrap().len());
+        // The reader is open until it's dropped, and the GET has finished.
+        assert_eq!(0, limiter.available_readers());
+        assert_eq!(4, limiter.available_gets());
+
+        // Another reader waits for the open one.
+        let mut other = LimitedReader::new(MockReader, limiter.clone());
+        let blocked = tokio::time::timeout(Duration::from_millis(10), other.get_bytes(0..8)).await;
+        assert!(blocked.is_err());
+
+        drop(reader);
+        assert_eq!(8, other.get_bytes(0..8).await.unwrap().len());
+    }
+
+    #[tokio::test]
+    async fn test_rate_limiter() {
+        let mut limiter = RateLimiter::new(1000);
+        let begin = Instant::now();
+        limiter.acquire(100).await;
+
+        // Waits for the 100 bytes acquired, and the 50 bytes consumed.
+        limiter.consume(50);
+        limiter.acquire(100).await;
+        assert!(begin.elapsed() >= Duration::from_millis(150));
+    }
+}
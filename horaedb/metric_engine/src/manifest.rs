@@ -15,31 +15,249 @@
 // specific language governing permissions and limitations
 // under the License.
 
+//! Manifest of ssts.
+//!
+//! Updates are queued and committed in batches, every batch is persisted as
+//! one delta object before it's applied in memory and acknowledged, so an
+//! acknowledged update is never lost. Deltas are merged into the snapshot
+//! periodically:
+//! ```plaintext
+//! {path}/snapshot
+//! {path}/delta/{seq}
+//! ```
+//! On startup, deltas not merged into the snapshot are replayed upon it in
+//! sequence order. Merged deltas are deleted in the background, fenced by the
+//! sequence recorded in the snapshot and a retention window, so readers which
+//! loaded an older snapshot can still read them.
+//!
+//! Range tombstones are recorded along with ssts, see [crate::tombstone], so
+//! are the tuned options of leveled compaction.
+//!
+//! Ssts removed by compactions may be retired instead, which keeps their files
+//! for scans at older versions, along with the time they are retired, until
+//! [ManifestOptions::history_retention] expires, see [RetiredSst].
+//!
+//! File ids are allocated from ranges reserved in the manifest, so they are
+//! never reused after restarts. Deltas are created only if absent, so of two
+//! writers racing for the same delta, the latter fails to commit instead of
+//! overwriting the reservation of the former.
+//!
+//! Read replicas open the manifest read only, which never commits, and reload
+//! the snapshot and deltas periodically instead.
+//!
+//! The version of the manifest is the sequence of the next delta, and the
+//! payload at a version is the one with all deltas below it applied. Every
+//! merged snapshot is also kept as a history snapshot at its merged sequence:
+//! ```plaintext
+//! {path}/snapshots/{merged_delta_seq}
+//! ```
+//! so older versions can be loaded from the history snapshot below them and
+//! the deltas after it, until they expire, see [Manifest::load_at].
+//!
+//! Committed updates are published to subscribers as [ManifestEvent]s in the
+//! order they are committed, see [Manifest::subscribe].
+
+use std::{
+    collections::HashSet,
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc,
+    },
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
 use anyhow::Context;
 use bytes::Bytes;
-use object_store::{path::Path, PutPayload};
+use futures::{StreamExt, TryStreamExt};
+use macros::ensure;
+use object_store::{path::Path, PutMode, PutOptions, PutPayload};
 use prost::Message;
-use tokio::sync::RwLock;
+use tokio::{
+    sync::{broadcast, mpsc, oneshot, Mutex, RwLock},
+    task::JoinHandle,
+};
 
 use crate::{
+    checksum,
+    error::StorageIoContext,
     sst::{FileId, FileMeta, SstFile},
-    types::{ObjectStoreRef, TimeRange},
-    AnyhowError, Error, Result,
+    storage::LeveledCompactionOptions,
+    tombstone::Tombstone,
+    types::{ManifestOptions, ObjectStoreRef, TimeRange},
+    Error, Result,
 };
 
 pub const PREFIX_PATH: &str = "manifest";
 pub const SNAPSHOT_FILENAME: &str = "snapshot";
+pub const DELTA_PREFIX: &str = "delta";
+pub const HISTORY_PREFIX: &str = "snapshots";
+/// Events a subscriber may fall behind before it misses some of them.
+const EVENT_CAPACITY: usize = 1024;
 
 pub struct Manifest {
-    path: String,
-    snapshot_path: Path,
-    store: ObjectStoreRef,
+    payload: Arc<RwLock<Payload>>,
+    sender: mpsc::UnboundedSender<CommitTask>,
+    /// File ids reserved but not allocated yet, `[start, end)`.
+    reserved_ids: Mutex<(FileId, FileId)>,
+    file_id_batch: u64,
+    history_retention: Duration,
+    /// Set if the manifest is opened read only.
+    refresher: Option<Refresher>,
+    events: broadcast::Sender<ManifestEvent>,
+    loader: Loader,
+    /// Version of the payload, i.e. the sequence of the next delta.
+    version: Arc<AtomicU64>,
+}
 
-    payload: RwLock<Payload>,
+/// Change of the manifest committed.
+#[derive(Clone, Debug)]
+pub enum ManifestEvent {
+    /// Fresh ssts are added, e.g. by writes and imports.
+    Added(Vec<SstFile>),
+    /// Ssts are removed, e.g. by compactions dropping all rows of the inputs.
+    Removed(Vec<FileId>),
+    /// Ssts of `inputs` are replaced by `outputs` atomically. Migrations of
+    /// tiers replace ssts with the same ids, see [crate::tier].
+    Compacted {
+        inputs: Vec<FileId>,
+        outputs: Vec<SstFile>,
+    },
+    /// Rows are deleted by the tombstones.
+    TombstonesAdded(Vec<Tombstone>),
+    /// Tombstones are removed once all ssts they apply to are compacted.
+    TombstonesRemoved(Vec<FileId>),
+}
+
+impl ManifestEvent {
+    fn from_update(update: &MetaUpdate) -> Vec<Self> {
+        let mut events = Vec::new();
+        match (update.to_adds.is_empty(), update.to_removes.is_empty()) {
+            (false, true) => events.push(ManifestEvent::Added(update.to_adds.clone())),
+            (true, false) => events.push(ManifestEvent::Removed(update.to_removes.clone())),
+            (false, false) => events.push(ManifestEvent::Compacted {
+                inputs: update.to_removes.clone(),
+                outputs: update.to_adds.clone(),
+            }),
+            (true, true) => {}
+        }
+        if !update.tombstones_to_add.is_empty() {
+            events.push(ManifestEvent::TombstonesAdded(
+                update.tombstones_to_add.clone(),
+            ));
+        }
+        if !update.tombstones_to_remove.is_empty() {
+            events.push(ManifestEvent::TombstonesRemoved(
+                update.tombstones_to_remove.clone(),
+            ));
+        }
+
+        events
+    }
 }
 
 pub struct Payload {
     files: Vec<SstFile>,
+    next_file_id: FileId,
+    tombstones: Vec<Tombstone>,
+    leveled_compaction: Option<LeveledCompactionOptions>,
+    retired: Vec<RetiredSst>,
+}
+
+/// Sst removed from the manifest whose files are kept, so scans at versions
+/// before it's removed still read it.
+#[derive(Clone, Debug)]
+pub struct RetiredSst {
+    pub sst: SstFile,
+    /// Unix timestamp in milliseconds when it's removed.
+    pub retired_at_ms: i64,
+}
+
+impl TryFrom<pb_types::RetiredSst> for RetiredSst {
+    type Error = Error;
+
+    fn try_from(value: pb_types::RetiredSst) -> Result<Self> {
+        let file = value
+            .file
+            .ok_or_else(|| Error::manifest_corrupt("file of retired sst is missing"))?;
+
+        Ok(Self {
+            sst: file.try_into()?,
+            retired_at_ms: value.retired_at_ms,
+        })
+    }
+}
+
+impl From<RetiredSst> for pb_types::RetiredSst {
+    fn from(value: RetiredSst) -> Self {
+        pb_types::RetiredSst {
+            file: Some(value.sst.into()),
+            retired_at_ms: value.retired_at_ms,
+        }
+    }
+}
+
+impl Payload {
+    fn empty() -> Self {
+        Self {
+            files: vec![],
+            next_file_id: 0,
+            tombstones: vec![],
+            leveled_compaction: None,
+            retired: vec![],
+        }
+    }
+
+    pub fn ssts(&self) -> &[SstFile] {
+        &self.files
+    }
+
+    pub fn tombstones(&self) -> &[Tombstone] {
+        &self.tombstones
+    }
+
+    pub fn retired(&self) -> &[RetiredSst] {
+        &self.retired
+    }
+
+    /// File ids below it are reserved.
+    pub fn next_file_id(&self) -> FileId {
+        self.next_file_id
+    }
+
+    /// Applying the same update more than once is a no-op.
+    fn apply(&mut self, update: MetaUpdate) {
+        self.next_file_id = self.next_file_id.max(update.next_file_id);
+        let to_removes = update.to_removes.into_iter().collect::<HashSet<_>>();
+        self.files.retain(|f| !to_removes.contains(&f.id));
+        for file in update.to_adds {
+            if !self.files.iter().any(|f| f.id == file.id) {
+                self.files.push(file);
+            }
+        }
+
+        let to_removes = update
+            .tombstones_to_remove
+            .into_iter()
+            .collect::<HashSet<_>>();
+        self.tombstones.retain(|t| !to_removes.contains(&t.id));
+        for tombstone in update.tombstones_to_add {
+            if !self.tombstones.iter().any(|t| t.id == tombstone.id) {
+                self.tombstones.push(tombstone);
+            }
+        }
+
+        if update.leveled_compaction.is_some() {
+            self.leveled_compaction = update.leveled_compaction;
+        }
+
+        let to_removes = update.retired_to_remove.into_iter().collect::<HashSet<_>>();
+        self.retired.retain(|r| !to_removes.contains(&r.sst.id));
+        for retired in update.to_retire {
+            if !self.retired.iter().any(|r| r.sst.id == retired.sst.id) {
+                self.retired.push(retired);
+            }
+        }
+    }
 }
 
 impl TryFrom<pb_types::Manifest> for Payload {
@@ -51,8 +269,32 @@ impl TryFrom<pb_types::Manifest> for Payload {
             .into_iter()
             .map(SstFile::try_from)
             .collect::<Result<Vec<_>>>()?;
+        let tombstones = value
+            .tombstones
+            .into_iter()
+            .map(Tombstone::try_from)
+            .collect::<Result<Vec<_>>>()?;
+        let retired = value
+            .retired
+            .into_iter()
+            .map(RetiredSst::try_from)
+            .collect::<Result<Vec<_>>>()?;
+        // Manifests written before ids are reserved don't record it.
+        let next_file_id = files
+            .iter()
+            .map(|f| f.id + 1)
+            .chain(tombstones.iter().map(|t| t.id + 1))
+            .max()
+            .unwrap_or_default()
+            .max(value.next_file_id);
 
-        Ok(Self { files })
+        Ok(Self {
+            files,
+            next_file_id,
+            tombstones,
+            leveled_compaction: value.leveled_compaction.map(Into::into),
+            retired,
+        })
     }
 }
 
@@ -64,72 +306,590 @@ impl From<Payload> for pb_types::Manifest {
                 .into_iter()
                 .map(pb_types::SstFile::from)
                 .collect(),
+            next_file_id: value.next_file_id,
+            tombstones: value
+                .tombstones
+                .into_iter()
+                .map(pb_types::Tombstone::from)
+                .collect(),
+            leveled_compaction: value.leveled_compaction.map(Into::into),
+            merged_delta_seq: 0,
+            retired: value
+                .retired
+                .into_iter()
+                .map(pb_types::RetiredSst::from)
+                .collect(),
         }
     }
 }
 
-impl Manifest {
-    pub async fn try_new(path: String, store: ObjectStoreRef) -> Result<Self> {
-        let snapshot_path = Path::from(format!("{path}/{SNAPSHOT_FILENAME}"));
-        let payload = match store.get(&snapshot_path).await {
-            Ok(v) => {
-                let bytes = v
-                    .bytes()
-                    .await
-                    .context("failed to read manifest snapshot")?;
-                let pb_payload = pb_types::Manifest::decode(bytes)
-                    .context("failed to decode manifest snapshot")?;
-                Payload::try_from(pb_payload)?
-            }
+#[derive(Debug, Default, Clone)]
+struct MetaUpdate {
+    to_adds: Vec<SstFile>,
+    to_removes: Vec<FileId>,
+    next_file_id: FileId,
+    tombstones_to_add: Vec<Tombstone>,
+    tombstones_to_remove: Vec<FileId>,
+    leveled_compaction: Option<LeveledCompactionOptions>,
+    to_retire: Vec<RetiredSst>,
+    retired_to_remove: Vec<FileId>,
+}
+
+impl MetaUpdate {
+    fn merge(&mut self, other: MetaUpdate) {
+        self.to_adds.extend(other.to_adds);
+        self.to_removes.extend(other.to_removes);
+        self.next_file_id = self.next_file_id.max(other.next_file_id);
+        self.tombstones_to_add.extend(other.tombstones_to_add);
+        self.tombstones_to_remove.extend(other.tombstones_to_remove);
+        if other.leveled_compaction.is_some() {
+            self.leveled_compaction = other.leveled_compaction;
+        }
+        self.to_retire.extend(other.to_retire);
+        self.retired_to_remove.extend(other.retired_to_remove);
+    }
+}
+
+impl TryFrom<pb_types::MetaUpdate> for MetaUpdate {
+    type Error = Error;
+
+    fn try_from(value: pb_types::MetaUpdate) -> Result<Self> {
+        let to_adds = value
+            .to_adds
+            .into_iter()
+            .map(SstFile::try_from)
+            .collect::<Result<Vec<_>>>()?;
+        let tombstones_to_add = value
+            .tombstones_to_add
+            .into_iter()
+            .map(Tombstone::try_from)
+            .collect::<Result<Vec<_>>>()?;
+        let to_retire = value
+            .to_retire
+            .into_iter()
+            .map(RetiredSst::try_from)
+            .collect::<Result<Vec<_>>>()?;
+
+        Ok(Self {
+            to_adds,
+            to_removes: value.to_removes,
+            next_file_id: value.next_file_id,
+            tombstones_to_add,
+            tombstones_to_remove: value.tombstones_to_remove,
+            leveled_compaction: value.leveled_compaction.map(Into::into),
+            to_retire,
+            retired_to_remove: value.retired_to_remove,
+        })
+    }
+}
+
+impl From<MetaUpdate> for pb_types::MetaUpdate {
+    fn from(value: MetaUpdate) -> Self {
+        pb_types::MetaUpdate {
+            to_adds: value
+                .to_adds
+                .into_iter()
+                .map(pb_types::SstFile::from)
+                .collect(),
+            to_removes: value.to_removes,
+            next_file_id: value.next_file_id,
+            tombstones_to_add: value
+                .tombstones_to_add
+                .into_iter()
+                .map(pb_types::Tombstone::from)
+                .collect(),
+            tombstones_to_remove: value.tombstones_to_remove,
+            leveled_compaction: value.leveled_compaction.map(Into::into),
+            to_retire: value
+                .to_retire
+                .into_iter()
+                .map(pb_types::RetiredSst::from)
+                .collect(),
+            retired_to_remove: value.retired_to_remove,
+        }
+    }
+}
+
+struct CommitTask {
+    update: MetaUpdate,
+    /// The error is shared by all the tasks committed together.
+    done: oneshot::Sender<std::result::Result<(), Arc<Error>>>,
+}
+
+/// Loader of the payload from the snapshot and deltas.
+#[derive(Clone)]
+struct Loader {
+    store: ObjectStoreRef,
+    snapshot_path: Path,
+    delta_dir: Path,
+    history_dir: Path,
+}
+
+impl Loader {
+    fn new(path: &str, store: ObjectStoreRef) -> Self {
+        Self {
+            store,
+            snapshot_path: Path::from(format!("{path}/{SNAPSHOT_FILENAME}")),
+            delta_dir: Path::from(format!("{path}/{DELTA_PREFIX}")),
+            history_dir: Path::from(format!("{path}/{HISTORY_PREFIX}")),
+        }
+    }
+
+    /// Returns the payload of the snapshot at `path` and its merged delta
+    /// sequence, `None` if it doesn't exist.
+    async fn load_snapshot(&self, path: &Path) -> Result<Option<(Payload, u64)>> {
+        let bytes = match self.store.get(path).await {
+            Ok(v) => v
+                .bytes()
+                .await
+                .storage_io(|| format!("failed to read manifest snapshot, path:{path}"))?,
+            Err(object_store::Error::NotFound { .. }) => return Ok(None),
             Err(err) => {
-                if err.to_string().contains("not found") {
-                    Payload { files: vec![] }
-                } else {
-                    let context = format!("Failed to get manifest snapshot, path:{snapshot_path}");
-                    return Err(AnyhowError::new(err).context(context).into());
-                }
+                return Err(Error::StorageIo {
+                    msg: format!("failed to get manifest snapshot, path:{path}"),
+                    source: err,
+                })
             }
         };
+        let pb_payload = pb_types::Manifest::decode(bytes).map_err(|e| Error::ManifestCorrupt {
+            msg: format!("failed to decode manifest snapshot, path:{path}"),
+            source: Some(e.into()),
+        })?;
+        let merged_delta_seq = pb_payload.merged_delta_seq;
 
-        Ok(Self {
-            path,
+        Ok(Some((Payload::try_from(pb_payload)?, merged_delta_seq)))
+    }
+
+    async fn load_delta(&self, path: &Path) -> Result<MetaUpdate> {
+        let bytes = self
+            .store
+            .get(path)
+            .await
+            .storage_io(|| format!("failed to get manifest delta, path:{path}"))?
+            .bytes()
+            .await
+            .storage_io(|| format!("failed to read manifest delta, path:{path}"))?;
+        let bytes = checksum::unseal(path.as_ref(), bytes)?;
+        let pb_update =
+            pb_types::MetaUpdate::decode(bytes).map_err(|e| Error::ManifestCorrupt {
+                msg: format!("failed to decode manifest delta, path:{path}"),
+                source: Some(e.into()),
+            })?;
+
+        MetaUpdate::try_from(pb_update)
+    }
+
+    /// Returns the payload, the merged delta sequence of the snapshot and the
+    /// deltas replayed upon it.
+    async fn load(&self) -> Result<(Payload, u64, Vec<Path>)> {
+        let (mut payload, merged_delta_seq) = self
+            .load_snapshot(&self.snapshot_path)
+            .await?
+            .unwrap_or_else(|| (Payload::empty(), 0));
+
+        // Merged deltas may be left before they expire.
+        let deltas = list_deltas(&self.store, &self.delta_dir)
+            .await?
+            .into_iter()
+            .filter(|path| delta_seq(path).is_some_and(|seq| seq >= merged_delta_seq))
+            .collect::<Vec<_>>();
+        for delta_path in &deltas {
+            payload.apply(self.load_delta(delta_path).await?);
+        }
+
+        Ok((payload, merged_delta_seq, deltas))
+    }
+
+    /// Returns the payload at `version`, which is loaded from the latest
+    /// snapshot not after it, and the deltas between them.
+    async fn load_at(&self, version: u64) -> Result<Payload> {
+        let mut base = self
+            .load_snapshot(&self.snapshot_path)
+            .await?
+            .filter(|(_, seq)| *seq <= version);
+        if base.is_none() {
+            let history = list_deltas(&self.store, &self.history_dir).await?;
+            if let Some(path) = history
+                .iter()
+                .rev()
+                .find(|path| delta_seq(path).is_some_and(|seq| seq <= version))
+            {
+                base = self.load_snapshot(path).await?;
+            }
+        }
+        let (mut payload, base_seq) = base.unwrap_or_else(|| (Payload::empty(), 0));
+
+        let deltas = list_deltas(&self.store, &self.delta_dir)
+            .await?
+            .into_iter()
+            .filter(|path| delta_seq(path).is_some_and(|seq| seq >= base_seq && seq < version))
+            .collect::<Vec<_>>();
+        ensure!(
+            deltas.len() as u64 == version - base_seq,
+            Error::VersionUnavailable {
+                version,
+                msg: format!("deltas after snapshot {base_seq} are expired"),
+            }
+        );
+        for delta_path in &deltas {
+            payload.apply(self.load_delta(delta_path).await?);
+        }
+
+        Ok(payload)
+    }
+}
+
+/// Sequence of the next delta after `deltas` replayed upon the snapshot.
+fn next_delta_seq(merged_delta_seq: u64, deltas: &[Path]) -> u64 {
+    deltas
+        .last()
+        .and_then(delta_seq)
+        .map_or(merged_delta_seq, |seq| seq + 1)
+}
+
+/// Background task reloading the payload of a read only manifest, it's
+/// stopped when dropped.
+struct Refresher {
+    handle: JoinHandle<()>,
+}
+
+impl Drop for Refresher {
+    fn drop(&mut self) {
+        self.handle.abort();
+    }
+}
+
+impl Manifest {
+    pub async fn try_new(
+        path: String,
+        store: ObjectStoreRef,
+        options: ManifestOptions,
+    ) -> Result<Self> {
+        let loader = Loader::new(&path, store);
+        let (payload, merged_delta_seq, deltas) = loader.load().await?;
+        let Loader {
+            store,
             snapshot_path,
+            delta_dir,
+            history_dir,
+        } = loader.clone();
+
+        let next_delta_seq = next_delta_seq(merged_delta_seq, &deltas);
+        let version = Arc::new(AtomicU64::new(next_delta_seq));
+        let next_file_id = payload.next_file_id;
+        let file_id_batch = options.file_id_batch.max(1);
+        let history_retention = options.history_retention;
+        let payload = Arc::new(RwLock::new(payload));
+        let (sender, receiver) = mpsc::unbounded_channel();
+        let (events, _) = broadcast::channel(EVENT_CAPACITY);
+        let cleaner = DeltaCleaner {
+            store: store.clone(),
+            delta_dir: delta_dir.clone(),
+            history_dir: history_dir.clone(),
+            retention: options.delta_retention.max(options.history_retention),
+            batch_size: options.delete_batch_size.max(1),
+            batch_interval: options.delete_interval,
+            running: Arc::new(AtomicBool::new(false)),
+        };
+        // Clean the merged deltas left by the last run.
+        if merged_delta_seq > 0 {
+            cleaner.spawn(merged_delta_seq);
+        }
+        let committer = Committer {
+            snapshot_path,
+            delta_dir,
+            history_dir,
             store,
-            payload: RwLock::new(payload),
+            payload: payload.clone(),
+            version: version.clone(),
+            options,
+            deltas,
+            next_delta_seq,
+            cleaner,
+            events: events.clone(),
+        };
+        tokio::spawn(committer.run(receiver));
+
+        Ok(Self {
+            payload,
+            sender,
+            reserved_ids: Mutex::new((next_file_id, next_file_id)),
+            file_id_batch,
+            history_retention,
+            refresher: None,
+            events,
+            loader,
+            version,
         })
     }
 
-    // TODO: Now this functions is poorly implemented, we concat new_sst to
-    // snapshot, and upload it back in a whole.
-    // In more efficient way, we can create a new diff file, and do compaction in
-    // background to merge them to `snapshot`.
-    pub async fn add_file(&self, id: FileId, meta: FileMeta) -> Result<()> {
-        let mut payload = self.payload.write().await;
-        let mut tmp_ssts = payload.files.clone();
-        let new_sst = SstFile { id, meta };
-        tmp_ssts.push(new_sst.clone());
-        let pb_manifest = pb_types::Manifest {
-            files: tmp_ssts.into_iter().map(|f| f.into()).collect::<Vec<_>>(),
+    /// Open the manifest read only, every update fails, and it's reloaded
+    /// every `refresh_interval` to catch up with the writer.
+    pub async fn open_read_only(
+        path: String,
+        store: ObjectStoreRef,
+        refresh_interval: Duration,
+    ) -> Result<Self> {
+        let loader = Loader::new(&path, store);
+        let (payload, merged_delta_seq, deltas) = loader.load().await?;
+        let payload = Arc::new(RwLock::new(payload));
+        let version = Arc::new(AtomicU64::new(next_delta_seq(merged_delta_seq, &deltas)));
+        let handle = {
+            let loader = loader.clone();
+            let payload = payload.clone();
+            let version = version.clone();
+            tokio::spawn(async move {
+                let mut ticker = tokio::time::interval(refresh_interval);
+                // The first tick completes immediately.
+                ticker.tick().await;
+                loop {
+                    ticker.tick().await;
+                    // Failed refreshes are retried at the next tick.
+                    if let Ok((new_payload, merged_delta_seq, deltas)) = loader.load().await {
+                        *payload.write().await = new_payload;
+                        version.store(next_delta_seq(merged_delta_seq, &deltas), Ordering::Release);
+                    }
+                }
+            })
         };
+        // Updates are never received.
+        let (sender, _) = mpsc::unbounded_channel();
 
-        let mut buf = Vec::with_capacity(pb_manifest.encoded_len());
-        pb_manifest
-            .encode(&mut buf)
-            .context("failed to encode manifest")?;
-        let put_payload = PutPayload::from_bytes(Bytes::from(buf));
+        Ok(Self {
+            payload,
+            sender,
+            reserved_ids: Mutex::new((0, 0)),
+            file_id_batch: 1,
+            history_retention: Duration::ZERO,
+            refresher: Some(Refresher { handle }),
+            events: broadcast::channel(EVENT_CAPACITY).0,
+            loader,
+            version,
+        })
+    }
 
-        // 1. Persist the snapshot
-        self.store
-            .put(&self.snapshot_path, put_payload)
-            .await
-            .context("Failed to update manifest")?;
+    /// Subscribe to updates committed from now on.
+    ///
+    /// A subscriber falling behind more than [EVENT_CAPACITY] events misses
+    /// the oldest ones, and gets [broadcast::error::RecvError::Lagged], which
+    /// should resync from [Manifest::all_ssts]. Manifests opened read only
+    /// never publish events.
+    pub fn subscribe(&self) -> broadcast::Receiver<ManifestEvent> {
+        self.events.subscribe()
+    }
 
-        // 2. Update cached payload
-        payload.files.push(new_sst);
+    pub fn is_read_only(&self) -> bool {
+        self.refresher.is_some()
+    }
+
+    /// Reload the manifest opened read only now.
+    pub async fn refresh(&self) -> Result<()> {
+        if !self.is_read_only() {
+            return Ok(());
+        }
+        let (payload, merged_delta_seq, deltas) = self.loader.load().await?;
+        *self.payload.write().await = payload;
+        self.version
+            .store(next_delta_seq(merged_delta_seq, &deltas), Ordering::Release);
 
         Ok(())
     }
 
+    /// Version of the payload, it increases with every commit.
+    pub fn version(&self) -> u64 {
+        self.version.load(Ordering::Acquire)
+    }
+
+    /// Load the payload at `version` from the object store.
+    ///
+    /// It fails with [Error::VersionUnavailable] if the version is ahead of
+    /// the current one, or the deltas to load it are expired, see
+    /// [ManifestOptions::history_retention]. Files of ssts in the payload may
+    /// be deleted by compactions since then.
+    pub async fn load_at(&self, version: u64) -> Result<Payload> {
+        let current = self.version();
+        ensure!(
+            version <= current,
+            Error::VersionUnavailable {
+                version,
+                msg: format!("current version is {current}"),
+            }
+        );
+
+        self.loader.load_at(version).await
+    }
+
+    /// Load the manifest at `path` without opening it, and returns the number
+    /// of its ssts, e.g. to verify the manifest is readable.
+    pub async fn verify(path: &str, store: ObjectStoreRef) -> Result<usize> {
+        let (payload, _, _) = Loader::new(path, store).load().await?;
+        Ok(payload.files.len())
+    }
+
+    /// Load the payload of the manifest at `path` without opening it.
+    pub(crate) async fn load_payload(path: &str, store: ObjectStoreRef) -> Result<Payload> {
+        let (payload, _, _) = Loader::new(path, store).load().await?;
+        Ok(payload)
+    }
+
+    /// Allocate an id for a new file, which is never allocated again by this
+    /// manifest, even after restarts.
+    pub async fn allocate_id(&self) -> Result<FileId> {
+        let mut reserved = self.reserved_ids.lock().await;
+        let (start, end) = *reserved;
+        if start < end {
+            *reserved = (start + 1, end);
+            return Ok(start);
+        }
+
+        // Reserve a new range, it's persisted before any id in it is used.
+        let new_end = end + self.file_id_batch;
+        self.commit(MetaUpdate {
+            next_file_id: new_end,
+            ..Default::default()
+        })
+        .await?;
+        *reserved = (end + 1, new_end);
+
+        Ok(end)
+    }
+
+    pub async fn add_file(&self, id: FileId, meta: FileMeta) -> Result<()> {
+        self.add_files(vec![SstFile { id, meta }]).await
+    }
+
+    pub async fn add_files(&self, new_ssts: Vec<SstFile>) -> Result<()> {
+        self.update(new_ssts, &[]).await
+    }
+
+    /// Add `new_ssts` and remove ssts in `to_delete` atomically.
+    ///
+    /// It returns after the update is persisted, concurrent updates are
+    /// committed together.
+    pub async fn update(&self, new_ssts: Vec<SstFile>, to_delete: &[FileId]) -> Result<()> {
+        self.update_with_tombstones(new_ssts, to_delete, &[]).await
+    }
+
+    /// Same as [Manifest::update], and also removes tombstones in
+    /// `tombstones_to_delete` atomically.
+    pub async fn update_with_tombstones(
+        &self,
+        new_ssts: Vec<SstFile>,
+        to_delete: &[FileId],
+        tombstones_to_delete: &[FileId],
+    ) -> Result<()> {
+        self.commit(MetaUpdate {
+            to_adds: new_ssts,
+            to_removes: to_delete.to_vec(),
+            tombstones_to_remove: tombstones_to_delete.to_vec(),
+            ..Default::default()
+        })
+        .await
+    }
+
+    /// Same as [Manifest::update_with_tombstones], but ssts in `to_retire`
+    /// are kept as retired ones, whose files should be deleted only after
+    /// [Manifest::history_retention].
+    pub async fn retire(
+        &self,
+        new_ssts: Vec<SstFile>,
+        to_retire: &[SstFile],
+        tombstones_to_delete: &[FileId],
+    ) -> Result<()> {
+        let retired_at_ms = now_ms();
+        self.commit(MetaUpdate {
+            to_adds: new_ssts,
+            to_removes: to_retire.iter().map(|f| f.id).collect(),
+            tombstones_to_remove: tombstones_to_delete.to_vec(),
+            to_retire: to_retire
+                .iter()
+                .map(|sst| RetiredSst {
+                    sst: sst.clone(),
+                    retired_at_ms,
+                })
+                .collect(),
+            ..Default::default()
+        })
+        .await
+    }
+
+    /// Forget retired ssts in `ids`, their files should be deleted after it.
+    pub async fn remove_retired(&self, ids: &[FileId]) -> Result<()> {
+        self.commit(MetaUpdate {
+            retired_to_remove: ids.to_vec(),
+            ..Default::default()
+        })
+        .await
+    }
+
+    pub async fn retired_ssts(&self) -> Vec<RetiredSst> {
+        self.payload.read().await.retired.clone()
+    }
+
+    /// How long ssts removed by compactions are retired before deleted, zero
+    /// means they are deleted at once.
+    pub fn history_retention(&self) -> Duration {
+        self.history_retention
+    }
+
+    pub async fn add_tombstone(&self, tombstone: Tombstone) -> Result<()> {
+        self.commit(MetaUpdate {
+            tombstones_to_add: vec![tombstone],
+            ..Default::default()
+        })
+        .await
+    }
+
+    pub async fn leveled_compaction(&self) -> Option<LeveledCompactionOptions> {
+        self.payload.read().await.leveled_compaction.clone()
+    }
+
+    pub async fn set_leveled_compaction(&self, options: LeveledCompactionOptions) -> Result<()> {
+        self.commit(MetaUpdate {
+            leveled_compaction: Some(options),
+            ..Default::default()
+        })
+        .await
+    }
+
+    async fn commit(&self, update: MetaUpdate) -> Result<()> {
+        ensure!(!self.is_read_only(), "manifest is read only");
+        let (done, done_rx) = oneshot::channel();
+        let task = CommitTask { update, done };
+        self.sender
+            .send(task)
+            .map_err(|_| anyhow::anyhow!("manifest committer is stopped"))?;
+
+        done_rx
+            .await
+            .context("manifest committer is stopped")?
+            .map_err(Error::Shared)
+    }
+
+    pub async fn all_ssts(&self) -> Vec<SstFile> {
+        let payload = self.payload.read().await;
+        payload.files.clone()
+    }
+
+    pub async fn num_ssts(&self) -> usize {
+        self.payload.read().await.files.len()
+    }
+
+    pub async fn all_tombstones(&self) -> Vec<Tombstone> {
+        self.payload.read().await.tombstones.clone()
+    }
+
+    pub async fn find_tombstones(&self, time_range: &TimeRange) -> Vec<Tombstone> {
+        let payload = self.payload.read().await;
+
+        payload
+            .tombstones
+            .iter()
+            .filter(|t| t.time_range.overlaps(time_range))
+            .cloned()
+            .collect()
+    }
+
     pub async fn find_ssts(&self, time_range: &TimeRange) -> Vec<SstFile> {
         let payload = self.payload.read().await;
 
@@ -141,3 +901,583 @@ impl Manifest {
             .collect()
     }
 }
+
+/// Paths of deltas under `delta_dir`, sorted by sequence.
+async fn list_deltas(store: &ObjectStoreRef, delta_dir: &Path) -> Result<Vec<Path>> {
+    let objects = store
+        .list(Some(delta_dir))
+        .try_collect::<Vec<_>>()
+        .await
+        .storage_io(|| format!("failed to list manifest deltas, path:{delta_dir}"))?;
+    let mut deltas = objects
+        .into_iter()
+        .filter_map(|meta| {
+            let seq = delta_seq(&meta.location)?;
+            Some((seq, meta.location))
+        })
+        .collect::<Vec<_>>();
+    deltas.sort_unstable_by_key(|(seq, _)| *seq);
+
+    Ok(deltas.into_iter().map(|(_, path)| path).collect())
+}
+
+fn delta_seq(path: &Path) -> Option<u64> {
+    path.filename()?.parse().ok()
+}
+
+pub(crate) fn now_ms() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis() as i64
+}
+
+/// Deleter of the deltas merged into the snapshot, and history snapshots
+/// older than the latest one.
+#[derive(Clone)]
+struct DeltaCleaner {
+    store: ObjectStoreRef,
+    delta_dir: Path,
+    history_dir: Path,
+    retention: Duration,
+    batch_size: usize,
+    batch_interval: Duration,
+    /// At most one cleaning runs at a time.
+    running: Arc<AtomicBool>,
+}
+
+impl DeltaCleaner {
+    /// Delete the expired objects below `fence` in the background, it's skipped
+    /// if the last cleaning is still running.
+    fn spawn(&self, fence: u64) {
+        if self.running.swap(true, Ordering::AcqRel) {
+            return;
+        }
+
+        let cleaner = self.clone();
+        tokio::spawn(async move {
+            // Deltas failed to be deleted are deleted by the next cleaning.
+            let _ = cleaner.clean(fence).await;
+            cleaner.running.store(false, Ordering::Release);
+        });
+    }
+
+    /// Delete deltas and history snapshots whose sequences are below `fence`
+    /// and older than the retention in batches, returns the number of deleted
+    /// objects.
+    async fn clean(&self, fence: u64) -> Result<usize> {
+        let expire_ms = now_ms().saturating_sub(self.retention.as_millis() as i64);
+        let mut expired = Vec::new();
+        for dir in [&self.delta_dir, &self.history_dir] {
+            let objects = self
+                .store
+                .list(Some(dir))
+                .try_collect::<Vec<_>>()
+                .await
+                .storage_io(|| format!("failed to list manifest objects, path:{dir}"))?;
+            expired.extend(
+                objects
+                    .into_iter()
+                    .filter(|meta| {
+                        delta_seq(&meta.location).is_some_and(|seq| seq < fence)
+                            && meta.last_modified.timestamp_millis() <= expire_ms
+                    })
+                    .map(|meta| meta.location),
+            );
+        }
+
+        for (i, batch) in expired.chunks(self.batch_size).enumerate() {
+            if i > 0 {
+                tokio::time::sleep(self.batch_interval).await;
+            }
+            let locations = futures::stream::iter(batch.iter().cloned().map(Ok)).boxed();
+            let mut deleted = self.store.delete_stream(locations);
+            while let Some(res) = deleted.next().await {
+                match res {
+                    Ok(_) | Err(object_store::Error::NotFound { .. }) => {}
+                    Err(e) => {
+                        return Err(Error::StorageIo {
+                            msg: "failed to delete manifest object".to_string(),
+                            source: e,
+                        })
+                    }
+                }
+            }
+        }
+
+        Ok(expired.len())
+    }
+}
+
+/// The only writer of the manifest objects.
+struct Committer {
+    snapshot_path: Path,
+    delta_dir: Path,
+    history_dir: Path,
+    store: ObjectStoreRef,
+    payload: Arc<RwLock<Payload>>,
+    version: Arc<AtomicU64>,
+    options: ManifestOptions,
+    /// Deltas not merged into the snapshot yet.
+    deltas: Vec<Path>,
+    next_delta_seq: u64,
+    cleaner: DeltaCleaner,
+    events: broadcast::Sender<ManifestEvent>,
+}
+
+impl Committer {
+    async fn run(mut self, mut receiver: mpsc::UnboundedReceiver<CommitTask>) {
+        while let Some(task) = receiver.recv().await {
+            let mut tasks = vec![task];
+            // Wait a moment so more updates can be committed together.
+            if !self.options.commit_interval.is_zero() {
+                tokio::time::sleep(self.options.commit_interval).await;
+            }
+            while let Ok(task) = receiver.try_recv() {
+                tasks.push(task);
+            }
+
+            let mut update = MetaUpdate::default();
+            let mut waiters = Vec::with_capacity(tasks.len());
+            let mut events = Vec::new();
+            for task in tasks {
+                events.extend(ManifestEvent::from_update(&task.update));
+                update.merge(task.update);
+                waiters.push(task.done);
+            }
+            let res = self.commit(update).await.map_err(Arc::new);
+            // Events are published before the updates are acknowledged.
+            if res.is_ok() {
+                for event in events {
+                    // There may be no subscribers.
+                    let _ = self.events.send(event);
+                }
+            }
+            for waiter in waiters {
+                // The caller may be cancelled, ignore it.
+                let _ = waiter.send(res.clone());
+            }
+        }
+    }
+
+    async fn commit(&mut self, update: MetaUpdate) -> Result<()> {
+        let delta_path = Path::from(format!("{}/{}", self.delta_dir, self.next_delta_seq));
+        let pb_update = pb_types::MetaUpdate::from(update.clone());
+
+        // 1. Persist the delta, it fails if another writer has committed the
+        // same sequence.
+        self.store
+            .put_opts(
+                &delta_path,
+                PutPayload::from(checksum::seal(pb_update.encode_to_vec())),
+                PutOptions::from(PutMode::Create),
+            )
+            .await
+            .storage_io(|| format!("failed to write manifest delta, path:{delta_path}"))?;
+        self.next_delta_seq += 1;
+        self.deltas.push(delta_path);
+
+        // 2. Update cached payload
+        self.payload.write().await.apply(update);
+        self.version.store(self.next_delta_seq, Ordering::Release);
+
+        if self.deltas.len() >= self.options.max_deltas {
+            // Deltas are replayed on startup if merging fails, so the failure is not
+            // propagated to the committed update.
+            let _ = self.merge_deltas().await;
+        }
+
+        Ok(())
+    }
+
+    /// Merge deltas into the snapshot.
+    async fn merge_deltas(&mut self) -> Result<()> {
+        let pb_manifest = {
+            let payload = self.payload.read().await;
+            pb_types::Manifest {
+                files: payload.files.iter().cloned().map(|f| f.into()).collect(),
+                next_file_id: payload.next_file_id,
+                tombstones: payload
+                    .tombstones
+                    .iter()
+                    .cloned()
+                    .map(|t| t.into())
+                    .collect(),
+                leveled_compaction: payload.leveled_compaction.clone().map(Into::into),
+                merged_delta_seq: self.next_delta_seq,
+                retired: payload.retired.iter().cloned().map(|r| r.into()).collect(),
+            }
+        };
+        let bytes = Bytes::from(pb_manifest.encode_to_vec());
+        self.store
+            .put(&self.snapshot_path, PutPayload::from_bytes(bytes.clone()))
+            .await
+            .storage_io(|| {
+                format!(
+                    "failed to update manifest snapshot, path:{}",
+                    self.snapshot_path
+                )
+            })?;
+        // Failing to keep the history snapshot only makes versions before it
+        // unavailable.
+        let history_path = Path::from(format!("{}/{}", self.history_dir, self.next_delta_seq));
+        let _ = self
+            .store
+            .put(&history_path, PutPayload::from_bytes(bytes))
+            .await;
+
+        // Merged deltas are never replayed, and they are deleted after the retention.
+        self.deltas.clear();
+        self.cleaner.spawn(self.next_delta_seq);
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use object_store::memory::InMemory;
+
+    use super::*;
+    use crate::{error::ErrorKind, sst::Tier, tombstone::KeyRange, types::Timestamp};
+
+    fn new_sst(id: FileId) -> SstFile {
+        SstFile {
+            id,
+            meta: FileMeta {
+                max_sequence: id,
+                num_rows: 1,
+                size: 1,
+                time_range: TimeRange::new(Timestamp(0), Timestamp(1)),
+                level: 0,
+                inverted_index_size: 0,
+                aggregates: Vec::new(),
+                tier: Tier::Cloud,
+                min_key: Vec::new(),
+                max_key: Vec::new(),
+                checksum: None,
+                null_columns: Vec::new(),
+            },
+        }
+    }
+
+    #[tokio::test]
+    async fn test_batched_commits() {
+        let store: ObjectStoreRef = Arc::new(InMemory::new());
+        let options = ManifestOptions {
+            commit_interval: Duration::from_millis(10),
+            max_deltas: 100,
+            file_id_batch: 1024,
+            ..Default::default()
+        };
+        let manifest = Arc::new(
+            Manifest::try_new("/manifest".to_string(), store.clone(), options.clone())
+                .await
+                .unwrap(),
+        );
+
+        let handles = (0..10)
+            .map(|id| {
+                let manifest = manifest.clone();
+                tokio::spawn(async move { manifest.add_file(id, new_sst(id).meta).await })
+            })
+            .collect::<Vec<_>>();
+        for handle in handles {
+            handle.await.unwrap().unwrap();
+        }
+        manifest.update(vec![new_sst(10)], &[0, 1]).await.unwrap();
+        assert_eq!(9, manifest.num_ssts().await);
+
+        // Concurrent updates are batched into fewer deltas.
+        let deltas = list_deltas(&store, &Path::from("/manifest/delta"))
+            .await
+            .unwrap();
+        assert!(deltas.len() < 11);
+
+        // Reopen from the snapshot and deltas.
+        let reopened = Manifest::try_new("/manifest".to_string(), store, options)
+            .await
+            .unwrap();
+        let mut ids = reopened
+            .all_ssts()
+            .await
+            .into_iter()
+            .map(|f| f.id)
+            .collect::<Vec<_>>();
+        ids.sort_unstable();
+        assert_eq!((2..=10).collect::<Vec<_>>(), ids);
+    }
+
+    #[tokio::test]
+    async fn test_batched_commit_failure() {
+        let store: ObjectStoreRef = Arc::new(InMemory::new());
+        let options = ManifestOptions {
+            commit_interval: Duration::from_millis(10),
+            ..Default::default()
+        };
+        let manifest = Arc::new(
+            Manifest::try_new("/manifest".to_string(), store.clone(), options)
+                .await
+                .unwrap(),
+        );
+        // Another writer has committed the next delta.
+        let delta_path = Path::from(format!("/manifest/delta/{}", manifest.version()));
+        store
+            .put(&delta_path, PutPayload::from_static(b"conflict"))
+            .await
+            .unwrap();
+
+        let handles = (0..3)
+            .map(|id| {
+                let manifest = manifest.clone();
+                tokio::spawn(async move { manifest.add_file(id, new_sst(id).meta).await })
+            })
+            .collect::<Vec<_>>();
+        for handle in handles {
+            let err = handle.await.unwrap().unwrap_err();
+            assert_eq!(ErrorKind::Unavailable, err.kind());
+            let Error::Shared(err) = err else {
+                panic!("unexpected error:{err}");
+            };
+            assert!(matches!(
+                err.as_ref(),
+                Error::StorageIo {
+                    source: object_store::Error::AlreadyExists { .. },
+                    ..
+                }
+            ));
+        }
+        assert_eq!(0, manifest.num_ssts().await);
+    }
+
+    #[tokio::test]
+    async fn test_merge_deltas() {
+        let store: ObjectStoreRef = Arc::new(InMemory::new());
+        let options = ManifestOptions {
+            commit_interval: Duration::ZERO,
+            max_deltas: 2,
+            file_id_batch: 1024,
+            delta_retention: Duration::ZERO,
+            delete_batch_size: 1,
+            delete_interval: Duration::from_millis(1),
+            ..Default::default()
+        };
+        let manifest = Manifest::try_new("/manifest".to_string(), store.clone(), options.clone())
+            .await
+            .unwrap();
+        for id in 0..3 {
+            manifest.add_file(id, new_sst(id).meta).await.unwrap();
+        }
+
+        // The first two deltas are merged into the snapshot, and deleted in the
+        // background.
+        let delta_dir = Path::from("/manifest/delta");
+        let mut deltas = list_deltas(&store, &delta_dir).await.unwrap();
+        for _ in 0..100 {
+            if deltas.len() == 1 {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+            deltas = list_deltas(&store, &delta_dir).await.unwrap();
+        }
+        assert_eq!(1, deltas.len());
+        let reopened = Manifest::try_new("/manifest".to_string(), store, options)
+            .await
+            .unwrap();
+        assert_eq!(3, reopened.num_ssts().await);
+    }
+
+    #[tokio::test]
+    async fn test_retain_merged_deltas() {
+        let store: ObjectStoreRef = Arc::new(InMemory::new());
+        let options = ManifestOptions {
+            commit_interval: Duration::ZERO,
+            max_deltas: 2,
+            file_id_batch: 1024,
+            delta_retention: Duration::from_secs(3600),
+            ..Default::default()
+        };
+        let manifest = Manifest::try_new("/manifest".to_string(), store.clone(), options.clone())
+            .await
+            .unwrap();
+        for id in 0..3 {
+            manifest.add_file(id, new_sst(id).meta).await.unwrap();
+        }
+        manifest.update(vec![], &[0]).await.unwrap();
+
+        // Merged deltas are kept within the retention, but not replayed again.
+        let delta_dir = Path::from("/manifest/delta");
+        assert_eq!(4, list_deltas(&store, &delta_dir).await.unwrap().len());
+        let reopened = Manifest::try_new("/manifest".to_string(), store.clone(), options)
+            .await
+            .unwrap();
+        assert_eq!(2, reopened.num_ssts().await);
+        reopened.add_file(3, new_sst(3).meta).await.unwrap();
+        assert_eq!(3, reopened.num_ssts().await);
+
+        // Only deltas below the fence are deleted once expired.
+        let cleaner = DeltaCleaner {
+            store: store.clone(),
+            delta_dir: delta_dir.clone(),
+            history_dir: Path::from("/manifest/snapshots"),
+            retention: Duration::ZERO,
+            batch_size: 1,
+            batch_interval: Duration::ZERO,
+            running: Arc::new(AtomicBool::new(false)),
+        };
+        assert_eq!(2, cleaner.clean(2).await.unwrap());
+        assert_eq!(0, cleaner.clean(2).await.unwrap());
+        let deltas = list_deltas(&store, &delta_dir).await.unwrap();
+        assert_eq!(
+            vec![Some(2), Some(3), Some(4)],
+            deltas.iter().map(delta_seq).collect::<Vec<_>>()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_tombstones() {
+        let store: ObjectStoreRef = Arc::new(InMemory::new());
+        let options = ManifestOptions {
+            commit_interval: Duration::ZERO,
+            max_deltas: 2,
+            file_id_batch: 1024,
+            ..Default::default()
+        };
+        let manifest = Manifest::try_new("/manifest".to_string(), store.clone(), options.clone())
+            .await
+            .unwrap();
+        for id in 0..2 {
+            manifest
+                .add_tombstone(Tombstone {
+                    id,
+                    sequence: id,
+                    key_range: KeyRange::all(),
+                    time_range: TimeRange::new(Timestamp(id as i64), Timestamp(10)),
+                })
+                .await
+                .unwrap();
+        }
+        manifest
+            .update_with_tombstones(vec![new_sst(2)], &[], &[0])
+            .await
+            .unwrap();
+
+        let reopened = Manifest::try_new("/manifest".to_string(), store, options)
+            .await
+            .unwrap();
+        let tombstones = reopened
+            .find_tombstones(&TimeRange::new(Timestamp(0), Timestamp(1)))
+            .await;
+        assert!(tombstones.is_empty());
+        let tombstones = reopened.all_tombstones().await;
+        assert_eq!(vec![1], tombstones.iter().map(|t| t.id).collect::<Vec<_>>());
+        assert_eq!(1, reopened.num_ssts().await);
+    }
+
+    #[tokio::test]
+    async fn test_subscribe() {
+        let store: ObjectStoreRef = Arc::new(InMemory::new());
+        let options = ManifestOptions {
+            commit_interval: Duration::ZERO,
+            ..Default::default()
+        };
+        let manifest = Manifest::try_new("/manifest".to_string(), store, options)
+            .await
+            .unwrap();
+        manifest.add_file(0, new_sst(0).meta).await.unwrap();
+        let mut events = manifest.subscribe();
+
+        let ids = |ssts: &[SstFile]| ssts.iter().map(|f| f.id).collect::<Vec<_>>();
+        manifest
+            .add_files(vec![new_sst(1), new_sst(2)])
+            .await
+            .unwrap();
+        match events.recv().await.unwrap() {
+            ManifestEvent::Added(ssts) => assert_eq!(vec![1, 2], ids(&ssts)),
+            e => panic!("unexpected event {e:?}"),
+        }
+        manifest.update(vec![new_sst(3)], &[0, 1]).await.unwrap();
+        match events.recv().await.unwrap() {
+            ManifestEvent::Compacted { inputs, outputs } => {
+                assert_eq!(vec![0, 1], inputs);
+                assert_eq!(vec![3], ids(&outputs));
+            }
+            e => panic!("unexpected event {e:?}"),
+        }
+        manifest.update(vec![], &[2]).await.unwrap();
+        match events.recv().await.unwrap() {
+            ManifestEvent::Removed(removed) => assert_eq!(vec![2], removed),
+            e => panic!("unexpected event {e:?}"),
+        }
+        let tombstone = Tombstone {
+            id: 4,
+            sequence: 4,
+            key_range: KeyRange::all(),
+            time_range: TimeRange::new(Timestamp(0), Timestamp(10)),
+        };
+        manifest.add_tombstone(tombstone.clone()).await.unwrap();
+        match events.recv().await.unwrap() {
+            ManifestEvent::TombstonesAdded(tombstones) => assert_eq!(vec![tombstone], tombstones),
+            e => panic!("unexpected event {e:?}"),
+        }
+
+        // Reservations of file ids change no sst.
+        manifest.allocate_id().await.unwrap();
+        assert!(events.try_recv().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_open_read_only() {
+        let store: ObjectStoreRef = Arc::new(InMemory::new());
+        let options = ManifestOptions {
+            commit_interval: Duration::ZERO,
+            max_deltas: 2,
+            ..Default::default()
+        };
+        let manifest = Manifest::try_new("/manifest".to_string(), store.clone(), options)
+            .await
+            .unwrap();
+        manifest.add_file(0, new_sst(0).meta).await.unwrap();
+        let replica =
+            Manifest::open_read_only("/manifest".to_string(), store, Duration::from_secs(3600))
+                .await
+                .unwrap();
+        assert_eq!(1, replica.num_ssts().await);
+
+        // Deltas merged into the snapshot are loaded too.
+        for id in 1..4 {
+            manifest.add_file(id, new_sst(id).meta).await.unwrap();
+        }
+        assert_eq!(1, replica.num_ssts().await);
+        replica.refresh().await.unwrap();
+        assert_eq!(4, replica.num_ssts().await);
+
+        assert!(replica.add_file(4, new_sst(4).meta).await.is_err());
+        assert!(replica.allocate_id().await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_allocate_id() {
+        let store: ObjectStoreRef = Arc::new(InMemory::new());
+        let options = ManifestOptions {
+            commit_interval: Duration::ZERO,
+            max_deltas: 100,
+            file_id_batch: 2,
+            ..Default::default()
+        };
+        let manifest = Manifest::try_new("/manifest".to_string(), store.clone(), options.clone())
+            .await
+            .unwrap();
+        let mut ids = Vec::new();
+        for _ in 0..3 {
+            ids.push(manifest.allocate_id().await.unwrap());
+        }
+        assert_eq!(vec![0, 1, 2], ids);
+
+        // Ids reserved before restart are skipped.
+        let reopened = Manifest::try_new("/manifest".to_string(), store, options)
+            .await
+            .unwrap();
+        assert_eq!(4, reopened.allocate_id().await.unwrap());
+    }
+}
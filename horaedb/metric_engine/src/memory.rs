@@ -0,0 +1,155 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+//! Node level memory budget of the write path.
+//!
+//! Writes of all storages on a node reserve the memory of their batches and
+//! parquet encoding buffers from one [WriteMemoryController] until the ssts
+//! are flushed, so ingest spikes are pushed back instead of running out of
+//! memory.
+
+use std::{sync::Arc, time::Duration};
+
+use anyhow::Context;
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+use crate::{Error, Result};
+
+#[derive(Debug, Clone, Copy)]
+pub struct WriteMemoryOptions {
+    /// Max bytes used by writes in flight.
+    pub budget_bytes: usize,
+    /// Max time a write waits for memory before it fails with
+    /// [Error::Backpressure], `None` means waiting until memory is freed.
+    pub max_wait: Option<Duration>,
+}
+
+impl Default for WriteMemoryOptions {
+    fn default() -> Self {
+        Self {
+            budget_bytes: 1024 * 1024 * 1024,
+            max_wait: None,
+        }
+    }
+}
+
+pub type WriteMemoryControllerRef = Arc<WriteMemoryController>;
+
+#[derive(Debug)]
+pub struct WriteMemoryController {
+    budget_bytes: usize,
+    max_wait: Option<Duration>,
+    /// One permit per byte.
+    permits: Arc<Semaphore>,
+}
+
+/// Memory reserved by a write, it's freed on drop.
+#[derive(Debug)]
+pub struct MemoryReservation {
+    _permit: OwnedSemaphorePermit,
+}
+
+impl WriteMemoryController {
+    pub fn new(options: WriteMemoryOptions) -> Self {
+        let budget_bytes = options.budget_bytes.clamp(1, Semaphore::MAX_PERMITS);
+        Self {
+            budget_bytes,
+            max_wait: options.max_wait,
+            permits: Arc::new(Semaphore::new(budget_bytes)),
+        }
+    }
+
+    /// Reserve `bytes` of memory, waiting for other writes to free memory if
+    /// the budget is exceeded.
+    ///
+    /// A write larger than the whole budget reserves the whole budget, so it
+    /// runs alone rather than never.
+    pub async fn reserve(&self, bytes: usize) -> Result<MemoryReservation> {
+        let reserved = bytes.clamp(1, self.budget_bytes).min(u32::MAX as usize) as u32;
+        let acquire = self.permits.clone().acquire_many_owned(reserved);
+        let permit =
+            match self.max_wait {
+                None => acquire.await,
+                Some(max_wait) => tokio::time::timeout(max_wait, acquire).await.map_err(|_| {
+                    Error::Backpressure {
+                        msg: format!(
+                            "write memory exhausted, budget:{}, used:{}, incoming:{bytes}",
+                            self.budget_bytes,
+                            self.used_bytes()
+                        ),
+                    }
+                })?,
+            }
+            .context("acquire write memory")?;
+
+        Ok(MemoryReservation { _permit: permit })
+    }
+
+    pub fn budget_bytes(&self) -> usize {
+        self.budget_bytes
+    }
+
+    pub fn used_bytes(&self) -> usize {
+        self.budget_bytes - self.permits.available_permits()
+    }
+}
+
+impl Default for WriteMemoryController {
+    fn default() -> Self {
+        Self::new(WriteMemoryOptions::default())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_reserve_memory() {
+        let controller = Arc::new(WriteMemoryController::new(WriteMemoryOptions {
+            budget_bytes: 100,
+            max_wait: Some(Duration::from_millis(10)),
+        }));
+        let reservation = controller.reserve(60).await.unwrap();
+        assert_eq!(60, controller.used_bytes());
+        assert!(matches!(
+            controller.reserve(60).await,
+            Err(Error::Backpressure { .. })
+        ));
+
+        // Memory is freed once the reservation is dropped.
+        drop(reservation);
+        assert_eq!(0, controller.used_bytes());
+        let reservation = controller.reserve(1000).await.unwrap();
+        assert_eq!(100, controller.used_bytes());
+        drop(reservation);
+
+        // Writes wait for memory without max wait.
+        let controller = Arc::new(WriteMemoryController::new(WriteMemoryOptions {
+            budget_bytes: 100,
+            max_wait: None,
+        }));
+        let first = controller.reserve(100).await.unwrap();
+        let waiting = tokio::spawn({
+            let controller = controller.clone();
+            async move { controller.reserve(10).await.map(|_| ()) }
+        });
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        assert!(!waiting.is_finished());
+        drop(first);
+        waiting.await.unwrap().unwrap();
+    }
+}
@@ -0,0 +1,207 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+//! Cleanup of incomplete multipart uploads.
+//!
+//! Writers crashed in the middle of a multipart upload leave the uploaded
+//! parts behind, which are invisible to `list` but still billed by most
+//! backends. `object_store` has no API to list them, so backends supporting
+//! it plug in via [MultipartUploadLister].
+
+use std::{
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::{Duration, SystemTime},
+};
+
+use async_trait::async_trait;
+use object_store::path::Path;
+use tokio::task::JoinHandle;
+
+use crate::Result;
+
+/// An upload initiated but neither completed nor aborted.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PendingUpload {
+    pub path: Path,
+    pub upload_id: String,
+    pub initiated: SystemTime,
+}
+
+#[async_trait]
+pub trait MultipartUploadLister: Send + Sync {
+    /// List incomplete uploads whose path starts with `prefix`.
+    async fn list_uploads(&self, prefix: &Path) -> Result<Vec<PendingUpload>>;
+
+    async fn abort_upload(&self, upload: &PendingUpload) -> Result<()>;
+}
+
+pub type MultipartUploadListerRef = Arc<dyn MultipartUploadLister>;
+
+#[derive(Debug, Clone)]
+pub struct MultipartCleanOptions {
+    /// Uploads younger than this may still be in progress, and are kept.
+    pub min_age: Duration,
+    pub interval: Duration,
+}
+
+impl Default for MultipartCleanOptions {
+    fn default() -> Self {
+        Self {
+            min_age: Duration::from_secs(3600),
+            interval: Duration::from_secs(3600),
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct MultipartCleanStats {
+    pub uploads_aborted: AtomicU64,
+    pub failed_rounds: AtomicU64,
+}
+
+/// Abort uploads under `prefix` older than `min_age`, return the number of
+/// aborted uploads.
+pub async fn clean_once(
+    lister: &dyn MultipartUploadLister,
+    prefix: &Path,
+    min_age: Duration,
+) -> Result<usize> {
+    let now = SystemTime::now();
+    let mut aborted = 0;
+    for upload in lister.list_uploads(prefix).await? {
+        // Clock skew may make `initiated` later than now, treat it as fresh.
+        let age = now.duration_since(upload.initiated).unwrap_or_default();
+        if age < min_age {
+            continue;
+        }
+        lister.abort_upload(&upload).await?;
+        aborted += 1;
+    }
+
+    Ok(aborted)
+}
+
+/// Background task cleaning incomplete uploads under a prefix, once at start
+/// and then every `interval`. The task is stopped when dropped.
+pub struct MultipartCleaner {
+    stats: Arc<MultipartCleanStats>,
+    handle: JoinHandle<()>,
+}
+
+impl MultipartCleaner {
+    pub fn start(
+        lister: MultipartUploadListerRef,
+        prefix: Path,
+        options: MultipartCleanOptions,
+    ) -> Self {
+        let stats = Arc::new(MultipartCleanStats::default());
+        let task_stats = stats.clone();
+        let handle = tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(options.interval);
+            loop {
+                // The first tick completes immediately.
+                ticker.tick().await;
+                match clean_once(lister.as_ref(), &prefix, options.min_age).await {
+                    Ok(n) => {
+                        task_stats
+                            .uploads_aborted
+                            .fetch_add(n as u64, Ordering::Relaxed);
+                    }
+                    Err(_) => {
+                        task_stats.failed_rounds.fetch_add(1, Ordering::Relaxed);
+                    }
+                }
+            }
+        });
+
+        Self { stats, handle }
+    }
+
+    pub fn stats(&self) -> &MultipartCleanStats {
+        &self.stats
+    }
+}
+
+impl Drop for MultipartCleaner {
+    fn drop(&mut self) {
+        self.handle.abort();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use super::*;
+
+    #[derive(Default)]
+    struct MockLister {
+        uploads: Mutex<Vec<PendingUpload>>,
+    }
+
+    #[async_trait]
+    impl MultipartUploadLister for MockLister {
+        async fn list_uploads(&self, prefix: &Path) -> Result<Vec<PendingUpload>> {
+            let uploads = self.uploads.lock().unwrap();
+            Ok(uploads
+                .iter()
+                .filter(|u| u.path.prefix_matches(prefix))
+                .cloned()
+                .collect())
+        }
+
+        async fn abort_upload(&self, upload: &PendingUpload) -> Result<()> {
+            self.uploads.lock().unwrap().retain(|u| u != upload);
+            Ok(())
+        }
+    }
+
+    fn upload(path: &str, age: Duration) -> PendingUpload {
+        PendingUpload {
+            path: Path::from(path),
+            upload_id: path.to_string(),
+            initiated: SystemTime::now() - age,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_clean_once() {
+        let hour = Duration::from_secs(3600);
+        let lister = MockLister::default();
+        *lister.uploads.lock().unwrap() = vec![
+            upload("root/data/1", 2 * hour),
+            upload("root/data/2", Duration::ZERO),
+            upload("other/data/3", 2 * hour),
+        ];
+
+        let aborted = clean_once(&lister, &Path::from("root/data"), hour)
+            .await
+            .unwrap();
+        assert_eq!(aborted, 1);
+        let left = lister
+            .uploads
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|u| u.upload_id.clone())
+            .collect::<Vec<_>>();
+        assert_eq!(left, vec!["root/data/2", "other/data/3"]);
+    }
+}
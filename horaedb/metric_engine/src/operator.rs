@@ -0,0 +1,564 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+//! Operators applied on the sorted scan output stream.
+
+use std::{
+    collections::VecDeque,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use arrow::{
+    array::RecordBatch,
+    compute::concat_batches,
+    datatypes::SchemaRef,
+    row::{OwnedRow, RowConverter, SortField},
+};
+use datafusion::{
+    error::{DataFusionError, Result as DfResult},
+    execution::{RecordBatchStream, SendableRecordBatchStream},
+};
+use futures::{Stream, StreamExt};
+use tracing::Span;
+
+/// Emit the latest `limit` rows of every series.
+///
+/// The input stream must be sorted by series key columns, then by time. In
+/// ascending order only the trailing rows of every series need to be
+/// buffered, and in descending order (`newest_first`) rows after the leading
+/// ones are skipped.
+pub struct LatestPerSeriesStream {
+    input: SendableRecordBatchStream,
+    key_indices: Vec<usize>,
+    limit: usize,
+    newest_first: bool,
+    converter: RowConverter,
+
+    /// Key of the series being buffered.
+    current_key: Option<OwnedRow>,
+    /// Latest rows of the series being buffered, at most `limit` rows.
+    pending: Vec<RecordBatch>,
+    done: bool,
+}
+
+impl LatestPerSeriesStream {
+    pub fn try_new(
+        input: SendableRecordBatchStream,
+        key_indices: Vec<usize>,
+        limit: usize,
+        newest_first: bool,
+    ) -> DfResult<Self> {
+        let schema = input.schema();
+        let sort_fields = key_indices
+            .iter()
+            .map(|i| SortField::new(schema.field(*i).data_type().clone()))
+            .collect();
+        let converter = RowConverter::new(sort_fields)?;
+
+        Ok(Self {
+            input,
+            key_indices,
+            limit,
+            newest_first,
+            converter,
+            current_key: None,
+            pending: Vec::new(),
+            done: false,
+        })
+    }
+
+    /// Consume one input batch, returns rows of series finished in this batch.
+    fn process_batch(&mut self, batch: RecordBatch) -> DfResult<Vec<RecordBatch>> {
+        let key_columns = self
+            .key_indices
+            .iter()
+            .map(|i| batch.column(*i).clone())
+            .collect::<Vec<_>>();
+        let rows = self.converter.convert_columns(&key_columns)?;
+
+        let mut output = Vec::new();
+        let mut segment_start = 0;
+        for i in 0..=rows.num_rows() {
+            let boundary = if i == rows.num_rows() {
+                true
+            } else {
+                i > segment_start && rows.row(i) != rows.row(i - 1)
+            };
+            if !boundary {
+                continue;
+            }
+            if i > segment_start {
+                let first_key = rows.row(segment_start);
+                let continues_current = self
+                    .current_key
+                    .as_ref()
+                    .is_some_and(|key| key.row() == first_key);
+                if !continues_current {
+                    output.append(&mut self.pending);
+                    self.current_key = Some(first_key.owned());
+                }
+                self.push_pending(batch.slice(segment_start, i - segment_start));
+            }
+            segment_start = i;
+        }
+
+        Ok(output)
+    }
+
+    /// Append rows of current series, and drop the oldest rows beyond limit.
+    fn push_pending(&mut self, rows: RecordBatch) {
+        let mut num_rows = self.pending.iter().map(|b| b.num_rows()).sum::<usize>();
+        if self.newest_first {
+            let to_keep = rows.num_rows().min(self.limit.saturating_sub(num_rows));
+            if to_keep > 0 {
+                self.pending.push(rows.slice(0, to_keep));
+            }
+            return;
+        }
+
+        self.pending.push(rows);
+        num_rows += self.pending.last().map_or(0, |b| b.num_rows());
+        while num_rows > self.limit {
+            let first = &self.pending[0];
+            let to_drop = num_rows - self.limit;
+            if first.num_rows() <= to_drop {
+                num_rows -= first.num_rows();
+                self.pending.remove(0);
+            } else {
+                let remaining = first.slice(to_drop, first.num_rows() - to_drop);
+                self.pending[0] = remaining;
+                num_rows -= to_drop;
+            }
+        }
+    }
+
+    fn concat(&self, batches: &[RecordBatch]) -> DfResult<RecordBatch> {
+        concat_batches(&self.input.schema(), batches).map_err(DataFusionError::from)
+    }
+}
+
+impl Stream for LatestPerSeriesStream {
+    type Item = DfResult<RecordBatch>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        if self.done {
+            return Poll::Ready(None);
+        }
+
+        loop {
+            match self.input.poll_next_unpin(cx) {
+                Poll::Ready(Some(Ok(batch))) => {
+                    let output = match self.process_batch(batch) {
+                        Ok(v) => v,
+                        Err(e) => return Poll::Ready(Some(Err(e))),
+                    };
+                    if !output.is_empty() {
+                        return Poll::Ready(Some(self.concat(&output)));
+                    }
+                }
+                Poll::Ready(Some(Err(e))) => return Poll::Ready(Some(Err(e))),
+                Poll::Ready(None) => {
+                    self.done = true;
+                    if self.pending.is_empty() {
+                        return Poll::Ready(None);
+                    }
+                    let pending = std::mem::take(&mut self.pending);
+                    return Poll::Ready(Some(self.concat(&pending)));
+                }
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+    }
+}
+
+impl RecordBatchStream for LatestPerSeriesStream {
+    fn schema(&self) -> SchemaRef {
+        self.input.schema()
+    }
+}
+
+/// Merge small batches of the input into batches of about `target_rows` rows
+/// or `target_bytes` bytes, whichever is reached first.
+///
+/// Batches larger than half of the target are passed through without copying
+/// when nothing is buffered, so only the tiny batches, e.g. those left by
+/// selective filters, pay for the concatenation.
+pub struct CoalesceStream {
+    input: SendableRecordBatchStream,
+    target_rows: usize,
+    target_bytes: usize,
+
+    pending: Vec<RecordBatch>,
+    pending_rows: usize,
+    pending_bytes: usize,
+    done: bool,
+}
+
+impl CoalesceStream {
+    pub fn new(input: SendableRecordBatchStream, target_rows: usize, target_bytes: usize) -> Self {
+        Self {
+            input,
+            target_rows,
+            target_bytes,
+            pending: Vec::new(),
+            pending_rows: 0,
+            pending_bytes: 0,
+            done: false,
+        }
+    }
+
+    fn take_pending(&mut self) -> DfResult<RecordBatch> {
+        let pending = std::mem::take(&mut self.pending);
+        self.pending_rows = 0;
+        self.pending_bytes = 0;
+        concat_batches(&self.input.schema(), &pending).map_err(DataFusionError::from)
+    }
+}
+
+impl Stream for CoalesceStream {
+    type Item = DfResult<RecordBatch>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        if self.done {
+            return Poll::Ready(None);
+        }
+
+        loop {
+            match self.input.poll_next_unpin(cx) {
+                Poll::Ready(Some(Ok(batch))) => {
+                    if batch.num_rows() == 0 {
+                        continue;
+                    }
+                    let num_bytes = batch.get_array_memory_size();
+                    let large = batch.num_rows() >= self.target_rows / 2
+                        || num_bytes >= self.target_bytes / 2;
+                    if self.pending.is_empty() && large {
+                        return Poll::Ready(Some(Ok(batch)));
+                    }
+
+                    self.pending_rows += batch.num_rows();
+                    self.pending_bytes += num_bytes;
+                    self.pending.push(batch);
+                    if self.pending_rows >= self.target_rows
+                        || self.pending_bytes >= self.target_bytes
+                    {
+                        return Poll::Ready(Some(self.take_pending()));
+                    }
+                }
+                Poll::Ready(Some(Err(e))) => return Poll::Ready(Some(Err(e))),
+                Poll::Ready(None) => {
+                    self.done = true;
+                    if self.pending.is_empty() {
+                        return Poll::Ready(None);
+                    }
+                    return Poll::Ready(Some(self.take_pending()));
+                }
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+    }
+}
+
+impl RecordBatchStream for CoalesceStream {
+    fn schema(&self) -> SchemaRef {
+        self.input.schema()
+    }
+}
+
+/// Re-chunk batches of the input into batches of exactly `batch_size` rows,
+/// except the last one.
+///
+/// Tiny batches are concatenated, and huge ones are sliced without copying, so
+/// a batch of the input with exactly `batch_size` rows is passed through.
+pub struct RechunkStream {
+    input: SendableRecordBatchStream,
+    batch_size: usize,
+
+    pending: VecDeque<RecordBatch>,
+    pending_rows: usize,
+    done: bool,
+}
+
+impl RechunkStream {
+    pub fn new(input: SendableRecordBatchStream, batch_size: usize) -> Self {
+        Self {
+            input,
+            batch_size: batch_size.max(1),
+            pending: VecDeque::new(),
+            pending_rows: 0,
+            done: false,
+        }
+    }
+
+    /// Take the first `num_rows` pending rows as one batch.
+    fn take_rows(&mut self, num_rows: usize) -> DfResult<RecordBatch> {
+        let mut taken = Vec::new();
+        let mut remaining = num_rows;
+        while remaining > 0 {
+            let Some(batch) = self.pending.pop_front() else {
+                break;
+            };
+            if batch.num_rows() <= remaining {
+                remaining -= batch.num_rows();
+                taken.push(batch);
+            } else {
+                taken.push(batch.slice(0, remaining));
+                self.pending
+                    .push_front(batch.slice(remaining, batch.num_rows() - remaining));
+                remaining = 0;
+            }
+        }
+        self.pending_rows -= num_rows - remaining;
+
+        if taken.len() == 1 {
+            return Ok(taken.pop().unwrap());
+        }
+        concat_batches(&self.input.schema(), &taken).map_err(DataFusionError::from)
+    }
+}
+
+impl Stream for RechunkStream {
+    type Item = DfResult<RecordBatch>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        loop {
+            if self.pending_rows >= self.batch_size {
+                let batch_size = self.batch_size;
+                return Poll::Ready(Some(self.take_rows(batch_size)));
+            }
+            if self.done {
+                if self.pending_rows == 0 {
+                    return Poll::Ready(None);
+                }
+                let pending_rows = self.pending_rows;
+                return Poll::Ready(Some(self.take_rows(pending_rows)));
+            }
+
+            match self.input.poll_next_unpin(cx) {
+                Poll::Ready(Some(Ok(batch))) => {
+                    if batch.num_rows() == 0 {
+                        continue;
+                    }
+                    self.pending_rows += batch.num_rows();
+                    self.pending.push_back(batch);
+                }
+                Poll::Ready(Some(Err(e))) => return Poll::Ready(Some(Err(e))),
+                Poll::Ready(None) => self.done = true,
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+    }
+}
+
+impl RecordBatchStream for RechunkStream {
+    fn schema(&self) -> SchemaRef {
+        self.input.schema()
+    }
+}
+
+/// Poll the input within `span`, so work done by the input is traced as its
+/// children, rows and bytes returned are recorded into it once the input is
+/// drained.
+pub struct TracedStream {
+    input: SendableRecordBatchStream,
+    span: Span,
+    rows: usize,
+    bytes: usize,
+}
+
+impl TracedStream {
+    pub fn new(input: SendableRecordBatchStream, span: Span) -> Self {
+        Self {
+            input,
+            span,
+            rows: 0,
+            bytes: 0,
+        }
+    }
+}
+
+impl Stream for TracedStream {
+    type Item = DfResult<RecordBatch>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+        let _entered = this.span.enter();
+        let poll = this.input.poll_next_unpin(cx);
+        match &poll {
+            Poll::Ready(Some(Ok(batch))) => {
+                this.rows += batch.num_rows();
+                this.bytes += batch.get_array_memory_size();
+            }
+            Poll::Ready(None) => {
+                this.span.record("rows", this.rows);
+                this.span.record("bytes", this.bytes);
+            }
+            _ => {}
+        }
+
+        poll
+    }
+}
+
+impl RecordBatchStream for TracedStream {
+    fn schema(&self) -> SchemaRef {
+        self.input.schema()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use arrow::{
+        array::{Int64Array, UInt8Array},
+        datatypes::{DataType, Field, Schema},
+    };
+    use datafusion::physical_plan::stream::RecordBatchStreamAdapter;
+    use futures::TryStreamExt;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_latest_per_series() {
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("series", DataType::UInt8, false),
+            Field::new("ts", DataType::Int64, false),
+        ]));
+        let build_batch = |series: Vec<u8>, ts: Vec<i64>| {
+            RecordBatch::try_new(
+                schema.clone(),
+                vec![
+                    Arc::new(UInt8Array::from(series)),
+                    Arc::new(Int64Array::from(ts)),
+                ],
+            )
+            .unwrap()
+        };
+        let batches = vec![
+            build_batch(vec![1, 1, 1, 2], vec![1, 2, 3, 1]),
+            build_batch(vec![2, 2, 3], vec![2, 3, 1]),
+            build_batch(vec![3, 4, 4], vec![2, 1, 2]),
+        ];
+        let input = RecordBatchStreamAdapter::new(
+            schema.clone(),
+            futures::stream::iter(batches.into_iter().map(Ok)),
+        );
+
+        let stream = LatestPerSeriesStream::try_new(Box::pin(input), vec![0], 2, false).unwrap();
+        let output = stream.try_collect::<Vec<_>>().await.unwrap();
+        let output = concat_batches(&schema, &output).unwrap();
+
+        let expected = build_batch(vec![1, 1, 2, 2, 3, 3, 4, 4], vec![2, 3, 2, 3, 1, 2, 1, 2]);
+        assert_eq!(expected, output);
+    }
+
+    #[tokio::test]
+    async fn test_latest_per_series_newest_first() {
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("series", DataType::UInt8, false),
+            Field::new("ts", DataType::Int64, false),
+        ]));
+        let build_batch = |series: Vec<u8>, ts: Vec<i64>| {
+            RecordBatch::try_new(
+                schema.clone(),
+                vec![
+                    Arc::new(UInt8Array::from(series)),
+                    Arc::new(Int64Array::from(ts)),
+                ],
+            )
+            .unwrap()
+        };
+        let batches = vec![
+            build_batch(vec![1, 1, 1, 2], vec![3, 2, 1, 3]),
+            build_batch(vec![2, 2, 3], vec![2, 1, 2]),
+            build_batch(vec![3, 4, 4], vec![1, 2, 1]),
+        ];
+        let input = RecordBatchStreamAdapter::new(
+            schema.clone(),
+            futures::stream::iter(batches.into_iter().map(Ok)),
+        );
+
+        let stream = LatestPerSeriesStream::try_new(Box::pin(input), vec![0], 2, true).unwrap();
+        let output = stream.try_collect::<Vec<_>>().await.unwrap();
+        let output = concat_batches(&schema, &output).unwrap();
+
+        let expected = build_batch(vec![1, 1, 2, 2, 3, 3, 4, 4], vec![3, 2, 3, 2, 2, 1, 2, 1]);
+        assert_eq!(expected, output);
+    }
+
+    #[tokio::test]
+    async fn test_coalesce() {
+        let schema = Arc::new(Schema::new(vec![Field::new("ts", DataType::Int64, false)]));
+        let build_batch = |ts: Vec<i64>| {
+            RecordBatch::try_new(schema.clone(), vec![Arc::new(Int64Array::from(ts))]).unwrap()
+        };
+        let batches = vec![
+            build_batch(vec![1]),
+            build_batch(vec![]),
+            build_batch(vec![2, 3]),
+            build_batch(vec![4]),
+            // Passed through since nothing is buffered.
+            build_batch(vec![5, 6, 7, 8, 9]),
+            build_batch(vec![10]),
+        ];
+        let input = RecordBatchStreamAdapter::new(
+            schema.clone(),
+            futures::stream::iter(batches.into_iter().map(Ok)),
+        );
+
+        let stream = CoalesceStream::new(Box::pin(input), 4, usize::MAX);
+        let output = stream.try_collect::<Vec<_>>().await.unwrap();
+        let expected = vec![
+            build_batch(vec![1, 2, 3, 4]),
+            build_batch(vec![5, 6, 7, 8, 9]),
+            build_batch(vec![10]),
+        ];
+        assert_eq!(expected, output);
+    }
+
+    #[tokio::test]
+    async fn test_rechunk() {
+        let schema = Arc::new(Schema::new(vec![Field::new("ts", DataType::Int64, false)]));
+        let build_batch = |ts: Vec<i64>| {
+            RecordBatch::try_new(schema.clone(), vec![Arc::new(Int64Array::from(ts))]).unwrap()
+        };
+        let batches = vec![
+            build_batch(vec![1]),
+            build_batch(vec![]),
+            build_batch(vec![2, 3]),
+            build_batch(vec![4, 5, 6, 7, 8, 9, 10, 11]),
+            build_batch(vec![12, 13, 14]),
+            build_batch(vec![15]),
+        ];
+        let input = RecordBatchStreamAdapter::new(
+            schema.clone(),
+            futures::stream::iter(batches.into_iter().map(Ok)),
+        );
+
+        let stream = RechunkStream::new(Box::pin(input), 4);
+        let output = stream.try_collect::<Vec<_>>().await.unwrap();
+        let expected = vec![
+            build_batch(vec![1, 2, 3, 4]),
+            build_batch(vec![5, 6, 7, 8]),
+            build_batch(vec![9, 10, 11, 12]),
+            build_batch(vec![13, 14, 15]),
+        ];
+        assert_eq!(expected, output);
+    }
+}
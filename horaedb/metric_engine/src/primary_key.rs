@@ -0,0 +1,388 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+//! Composite keys of series key columns, and pruning of ssts by their ranges.
+//!
+//! Values of the series key columns of a row are encoded into one key, whose
+//! bytes compare in the same order as the values, column by column. The min
+//! and max keys of every sst are recorded in its [FileMeta], so scans with
+//! predicates on leading series keys, e.g. `host = 'a' AND dc >= 'b'`, skip
+//! ssts out of the range without reading their parquet metadata.
+//!
+//! Every value is prefixed by a marker, 0 for nulls and 1 for others.
+//! Integers are encoded into 8 big endian bytes, with the sign bit of signed
+//! ones flipped. Strings and binaries are encoded with every 0 byte escaped
+//! into `0 0xFF`, and terminated by `0 0`, so no encoded value is a prefix of
+//! another one. Dictionary columns are encoded by their values.
+//!
+//! [FileMeta]: crate::sst::FileMeta
+
+use arrow::{
+    array::{downcast_dictionary_array, Array, ArrayRef, AsArray, RecordBatch},
+    datatypes::{
+        ArrowNativeType, DataType, Int16Type, Int32Type, Int64Type, Int8Type, Schema, UInt16Type,
+        UInt32Type, UInt64Type, UInt8Type,
+    },
+};
+use datafusion::{
+    common::ScalarValue,
+    logical_expr::{utils::split_conjunction, BinaryExpr, Expr, Operator},
+};
+
+const NULL: u8 = 0;
+const NOT_NULL: u8 = 1;
+
+pub(crate) fn supports(data_type: &DataType) -> bool {
+    if let DataType::Dictionary(_, value_type) = data_type {
+        return supports(value_type);
+    }
+    data_type.is_integer()
+        || matches!(
+            data_type,
+            DataType::Utf8 | DataType::LargeUtf8 | DataType::Binary | DataType::LargeBinary
+        )
+}
+
+fn encode_bytes(buf: &mut Vec<u8>, v: &[u8]) {
+    buf.push(NOT_NULL);
+    for b in v {
+        buf.push(*b);
+        if *b == 0 {
+            buf.push(0xFF);
+        }
+    }
+    buf.extend_from_slice(&[0, 0]);
+}
+
+fn encode_i64(buf: &mut Vec<u8>, v: i64) {
+    buf.push(NOT_NULL);
+    buf.extend_from_slice(&((v as u64) ^ (1 << 63)).to_be_bytes());
+}
+
+fn encode_u64(buf: &mut Vec<u8>, v: u64) {
+    buf.push(NOT_NULL);
+    buf.extend_from_slice(&v.to_be_bytes());
+}
+
+/// Encode the value of `array` at `row`, the type of it must be supported.
+fn encode_array_value(buf: &mut Vec<u8>, array: &ArrayRef, row: usize) {
+    if array.is_null(row) {
+        buf.push(NULL);
+        return;
+    }
+    match array.data_type() {
+        DataType::Utf8 => encode_bytes(buf, array.as_string::<i32>().value(row).as_bytes()),
+        DataType::LargeUtf8 => encode_bytes(buf, array.as_string::<i64>().value(row).as_bytes()),
+        DataType::Binary => encode_bytes(buf, array.as_binary::<i32>().value(row)),
+        DataType::LargeBinary => encode_bytes(buf, array.as_binary::<i64>().value(row)),
+        DataType::Int8 => encode_i64(buf, array.as_primitive::<Int8Type>().value(row) as i64),
+        DataType::Int16 => encode_i64(buf, array.as_primitive::<Int16Type>().value(row) as i64),
+        DataType::Int32 => encode_i64(buf, array.as_primitive::<Int32Type>().value(row) as i64),
+        DataType::Int64 => encode_i64(buf, array.as_primitive::<Int64Type>().value(row)),
+        DataType::UInt8 => encode_u64(buf, array.as_primitive::<UInt8Type>().value(row) as u64),
+        DataType::UInt16 => encode_u64(buf, array.as_primitive::<UInt16Type>().value(row) as u64),
+        DataType::UInt32 => encode_u64(buf, array.as_primitive::<UInt32Type>().value(row) as u64),
+        DataType::UInt64 => encode_u64(buf, array.as_primitive::<UInt64Type>().value(row)),
+        DataType::Dictionary(_, _) => downcast_dictionary_array!(
+            array => {
+                let key = array.keys().value(row).as_usize();
+                encode_array_value(buf, array.values(), key)
+            },
+            other => unreachable!("unsupported key type {other}")
+        ),
+        other => unreachable!("unsupported key type {other}"),
+    }
+}
+
+/// Returns false if the type of `v` is not supported or it's null.
+fn encode_scalar(buf: &mut Vec<u8>, v: &ScalarValue) -> bool {
+    match v {
+        ScalarValue::Utf8(Some(v)) | ScalarValue::LargeUtf8(Some(v)) => {
+            encode_bytes(buf, v.as_bytes())
+        }
+        ScalarValue::Binary(Some(v)) | ScalarValue::LargeBinary(Some(v)) => encode_bytes(buf, v),
+        ScalarValue::Int8(Some(v)) => encode_i64(buf, *v as i64),
+        ScalarValue::Int16(Some(v)) => encode_i64(buf, *v as i64),
+        ScalarValue::Int32(Some(v)) => encode_i64(buf, *v as i64),
+        ScalarValue::Int64(Some(v)) => encode_i64(buf, *v),
+        ScalarValue::UInt8(Some(v)) => encode_u64(buf, *v as u64),
+        ScalarValue::UInt16(Some(v)) => encode_u64(buf, *v as u64),
+        ScalarValue::UInt32(Some(v)) => encode_u64(buf, *v as u64),
+        ScalarValue::UInt64(Some(v)) => encode_u64(buf, *v),
+        ScalarValue::Dictionary(_, v) => return encode_scalar(buf, v),
+        _ => return false,
+    }
+    true
+}
+
+/// Min and max keys of the rows of an sst.
+pub(crate) struct KeyRangeBuilder {
+    /// `None` if any key column is not supported.
+    key_indices: Option<Vec<usize>>,
+    min: Vec<u8>,
+    max: Vec<u8>,
+    buf: Vec<u8>,
+}
+
+impl KeyRangeBuilder {
+    pub(crate) fn new(schema: &Schema, key_indices: Vec<usize>) -> Self {
+        let supported = !key_indices.is_empty()
+            && key_indices
+                .iter()
+                .all(|i| supports(schema.field(*i).data_type()));
+        Self {
+            key_indices: supported.then_some(key_indices),
+            min: Vec::new(),
+            max: Vec::new(),
+            buf: Vec::new(),
+        }
+    }
+
+    pub(crate) fn update(&mut self, batch: &RecordBatch) {
+        let Some(key_indices) = &self.key_indices else {
+            return;
+        };
+        let columns = key_indices
+            .iter()
+            .map(|i| batch.column(*i))
+            .collect::<Vec<_>>();
+        for row in 0..batch.num_rows() {
+            self.buf.clear();
+            for column in &columns {
+                encode_array_value(&mut self.buf, column, row);
+            }
+            if self.min.is_empty() || self.buf < self.min {
+                self.min.clone_from(&self.buf);
+            }
+            if self.max.is_empty() || self.buf > self.max {
+                self.max.clone_from(&self.buf);
+            }
+        }
+    }
+
+    /// Returns the min and max keys, both are empty if they are unknown.
+    pub(crate) fn finish(self) -> (Vec<u8>, Vec<u8>) {
+        (self.min, self.max)
+    }
+}
+
+/// Bounds of the keys of rows matching a predicate, `true` means the bound is
+/// inclusive. Keys starting with the bound are considered equal to it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct KeyBounds {
+    lower: Option<(Vec<u8>, bool)>,
+    upper: Option<(Vec<u8>, bool)>,
+}
+
+impl KeyBounds {
+    /// Extract bounds from the conjunctions of `predicate`, by equalities on
+    /// the leading columns of `key_indices` and comparisons on the column
+    /// following them.
+    ///
+    /// Returns `None` if the leading column is not restricted.
+    pub(crate) fn try_new(
+        predicate: &[Expr],
+        schema: &Schema,
+        key_indices: &[usize],
+    ) -> Option<Self> {
+        let comparisons = predicate
+            .iter()
+            .flat_map(split_conjunction)
+            .filter_map(|expr| comparison(expr, schema))
+            .collect::<Vec<_>>();
+        let mut prefix = Vec::new();
+        for idx in key_indices {
+            let name = schema.field(*idx).name();
+            let of_column = comparisons
+                .iter()
+                .filter(|(column, ..)| column == name)
+                .collect::<Vec<_>>();
+            if let Some((_, _, v)) = of_column.iter().find(|(_, op, _)| *op == Operator::Eq) {
+                if !encode_scalar(&mut prefix, v) {
+                    break;
+                }
+                continue;
+            }
+
+            let bound = |ops: [Operator; 2]| {
+                of_column.iter().find_map(|(_, op, v)| {
+                    let mut key = prefix.clone();
+                    (ops.contains(op) && encode_scalar(&mut key, v))
+                        .then(|| (key, matches!(op, Operator::GtEq | Operator::LtEq)))
+                })
+            };
+            let lower = bound([Operator::Gt, Operator::GtEq]);
+            let upper = bound([Operator::Lt, Operator::LtEq]);
+            if lower.is_some() || upper.is_some() {
+                let or_prefix = || (!prefix.is_empty()).then(|| (prefix.clone(), true));
+                return Some(Self {
+                    lower: lower.or_else(or_prefix),
+                    upper: upper.or_else(or_prefix),
+                });
+            }
+            break;
+        }
+
+        (!prefix.is_empty()).then(|| Self {
+            lower: Some((prefix.clone(), true)),
+            upper: Some((prefix, true)),
+        })
+    }
+
+    /// Whether an sst with keys in `[min_key, max_key]` may contain matching
+    /// rows, ssts with unknown keys always may.
+    pub(crate) fn may_match(&self, min_key: &[u8], max_key: &[u8]) -> bool {
+        if min_key.is_empty() || max_key.is_empty() {
+            return true;
+        }
+        if let Some((lower, inclusive)) = &self.lower {
+            let below = max_key < lower.as_slice();
+            if below || (!inclusive && max_key.starts_with(lower)) {
+                return false;
+            }
+        }
+        if let Some((upper, inclusive)) = &self.upper {
+            let above = min_key > upper.as_slice() && !min_key.starts_with(upper);
+            if above || (!inclusive && min_key >= upper.as_slice()) {
+                return false;
+            }
+        }
+
+        true
+    }
+}
+
+/// Extract `column op literal` from `expr`, the literal is cast to the type
+/// of the column, only lossless casts between integers are allowed.
+fn comparison(expr: &Expr, schema: &Schema) -> Option<(String, Operator, ScalarValue)> {
+    let Expr::BinaryExpr(BinaryExpr { left, op, right }) = expr else {
+        return None;
+    };
+    let (column, op, v) = match (left.as_ref(), right.as_ref()) {
+        (Expr::Column(c), Expr::Literal(v)) => (c, *op, v),
+        (Expr::Literal(v), Expr::Column(c)) => (c, op.swap()?, v),
+        _ => return None,
+    };
+    if !matches!(
+        op,
+        Operator::Eq | Operator::Lt | Operator::LtEq | Operator::Gt | Operator::GtEq
+    ) {
+        return None;
+    }
+    let (_, field) = schema.column_with_name(&column.name)?;
+    // Keys of dictionary columns are encoded by their values.
+    let data_type = match field.data_type() {
+        DataType::Dictionary(_, value_type) => value_type.as_ref(),
+        data_type => data_type,
+    };
+    if v.data_type() != *data_type && !(v.data_type().is_integer() && data_type.is_integer()) {
+        return None;
+    }
+    let v = v.cast_to(data_type).ok().filter(|v| !v.is_null())?;
+
+    Some((column.name.clone(), op, v))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use arrow::{
+        array::{DictionaryArray, Int64Array, StringArray},
+        datatypes::Field,
+    };
+    use datafusion::prelude::{col, lit};
+
+    use super::*;
+
+    #[test]
+    fn test_key_range_pruning() {
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("host", DataType::Utf8, true),
+            Field::new("id", DataType::Int64, true),
+        ]));
+        let batch = RecordBatch::try_new(
+            schema.clone(),
+            vec![
+                Arc::new(StringArray::from(vec![Some("b"), Some("b"), Some("d")])),
+                Arc::new(Int64Array::from(vec![Some(-1), Some(3), None])),
+            ],
+        )
+        .unwrap();
+        let mut builder = KeyRangeBuilder::new(&schema, vec![0, 1]);
+        builder.update(&batch);
+        let (min, max) = builder.finish();
+
+        let may_match = |predicate: Vec<Expr>| {
+            KeyBounds::try_new(&predicate, &schema, &[0, 1])
+                .is_none_or(|bounds| bounds.may_match(&min, &max))
+        };
+        for (predicate, expected) in [
+            (vec![col("host").eq(lit("b"))], true),
+            (vec![col("host").eq(lit("c"))], true),
+            (vec![col("host").eq(lit("a"))], false),
+            (vec![col("host").eq(lit("e"))], false),
+            (vec![col("host").gt(lit("d"))], false),
+            (vec![col("host").gt_eq(lit("d"))], true),
+            (vec![col("host").lt(lit("b"))], false),
+            (vec![col("host").lt_eq(lit("b"))], true),
+            (vec![lit("b").gt(col("host"))], false),
+            (vec![col("host").eq(lit("b")), col("id").gt(lit(3))], true),
+            (vec![col("host").eq(lit("b")), col("id").lt(lit(-1))], false),
+            (
+                vec![col("host").eq(lit("b")).and(col("id").eq(lit(0)))],
+                true,
+            ),
+            (
+                vec![col("host").eq(lit("a")).and(col("id").eq(lit(0)))],
+                false,
+            ),
+            // Not restricted on the leading column.
+            (vec![col("id").eq(lit(100))], true),
+        ] {
+            assert_eq!(expected, may_match(predicate.clone()), "{predicate:?}");
+        }
+    }
+
+    #[test]
+    fn test_dictionary_keys() {
+        let hosts = vec![Some("b"), None, Some("a"), Some("b")];
+        let range_of = |array: ArrayRef| {
+            let schema = Schema::new(vec![Field::new("host", array.data_type().clone(), true)]);
+            let batch = RecordBatch::try_new(Arc::new(schema.clone()), vec![array]).unwrap();
+            let mut builder = KeyRangeBuilder::new(&schema, vec![0]);
+            builder.update(&batch);
+            builder.finish()
+        };
+
+        let dictionary = range_of(Arc::new(
+            hosts
+                .iter()
+                .copied()
+                .collect::<DictionaryArray<Int32Type>>(),
+        ));
+        assert_eq!(range_of(Arc::new(StringArray::from(hosts))), dictionary);
+
+        let schema = Schema::new(vec![Field::new(
+            "host",
+            DataType::Dictionary(Box::new(DataType::Int32), Box::new(DataType::Utf8)),
+            true,
+        )]);
+        let bounds = KeyBounds::try_new(&[col("host").eq(lit("c"))], &schema, &[0]).unwrap();
+        assert!(!bounds.may_match(&dictionary.0, &dictionary.1));
+    }
+}
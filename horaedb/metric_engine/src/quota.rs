@@ -0,0 +1,243 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+//! Per-tenant quota of the write path.
+//!
+//! Usage of every tenant is tracked in memory, and is also exposed for billing.
+
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, RwLock,
+    },
+};
+
+use crate::{Error, Result};
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct QuotaLimits {
+    /// Max number of rows allowed to ingest, `None` means unlimited.
+    pub max_ingest_rows: Option<u64>,
+    /// Max bytes allowed to ingest, `None` means unlimited.
+    pub max_ingest_bytes: Option<u64>,
+    /// Max bytes of ssts allowed to store, `None` means unlimited.
+    pub max_storage_bytes: Option<u64>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TenantUsage {
+    pub ingest_rows: u64,
+    pub ingest_bytes: u64,
+    pub storage_bytes: u64,
+}
+
+#[derive(Debug, Default)]
+struct TenantQuota {
+    limits: QuotaLimits,
+    ingest_rows: AtomicU64,
+    ingest_bytes: AtomicU64,
+    storage_bytes: AtomicU64,
+}
+
+impl TenantQuota {
+    fn usage(&self) -> TenantUsage {
+        TenantUsage {
+            ingest_rows: self.ingest_rows.load(Ordering::Relaxed),
+            ingest_bytes: self.ingest_bytes.load(Ordering::Relaxed),
+            storage_bytes: self.storage_bytes.load(Ordering::Relaxed),
+        }
+    }
+}
+
+pub type QuotaManagerRef = Arc<QuotaManager>;
+
+/// Quota manager shared by all storages of one node.
+#[derive(Debug, Default)]
+pub struct QuotaManager {
+    /// Limits for tenants without dedicated limits.
+    default_limits: QuotaLimits,
+    tenants: RwLock<HashMap<String, Arc<TenantQuota>>>,
+}
+
+impl QuotaManager {
+    pub fn new(default_limits: QuotaLimits) -> Self {
+        Self {
+            default_limits,
+            tenants: RwLock::new(HashMap::new()),
+        }
+    }
+
+    /// Set limits of the tenant, usage tracked so far is kept.
+    pub fn set_limits(&self, tenant: &str, limits: QuotaLimits) {
+        let mut tenants = self.tenants.write().unwrap();
+        let usage = tenants
+            .get(tenant)
+            .map(|quota| quota.usage())
+            .unwrap_or_default();
+        let quota = TenantQuota {
+            limits,
+            ingest_rows: AtomicU64::new(usage.ingest_rows),
+            ingest_bytes: AtomicU64::new(usage.ingest_bytes),
+            storage_bytes: AtomicU64::new(usage.storage_bytes),
+        };
+        tenants.insert(tenant.to_string(), Arc::new(quota));
+    }
+
+    /// Check whether a write of `num_rows` rows and `num_bytes` bytes is
+    /// allowed for the tenant.
+    pub fn check_write(&self, tenant: &str, num_rows: u64, num_bytes: u64) -> Result<()> {
+        let quota = self.tenant_quota(tenant);
+        let usage = quota.usage();
+        let limits = &quota.limits;
+        let check_limit = |limit: Option<u64>, used: u64, incoming: u64, what: &str| match limit {
+            Some(limit) if used + incoming > limit => Err(Error::QuotaExceeded {
+                tenant: tenant.to_string(),
+                msg: format!("{what} limit:{limit}, used:{used}, incoming:{incoming}"),
+            }),
+            _ => Ok(()),
+        };
+
+        check_limit(
+            limits.max_ingest_rows,
+            usage.ingest_rows,
+            num_rows,
+            "ingest rows",
+        )?;
+        check_limit(
+            limits.max_ingest_bytes,
+            usage.ingest_bytes,
+            num_bytes,
+            "ingest bytes",
+        )?;
+        // Size of the sst is unknown before writing, so only reject writes when
+        // the storage is already full.
+        if let Some(limit) = limits.max_storage_bytes {
+            if usage.storage_bytes >= limit {
+                return Err(Error::QuotaExceeded {
+                    tenant: tenant.to_string(),
+                    msg: format!("storage bytes limit:{limit}, used:{}", usage.storage_bytes),
+                });
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Record a successful write.
+    pub fn record_write(&self, tenant: &str, num_rows: u64, num_bytes: u64, sst_bytes: u64) {
+        let quota = self.tenant_quota(tenant);
+        quota.ingest_rows.fetch_add(num_rows, Ordering::Relaxed);
+        quota.ingest_bytes.fetch_add(num_bytes, Ordering::Relaxed);
+        quota.storage_bytes.fetch_add(sst_bytes, Ordering::Relaxed);
+    }
+
+    /// Record ssts are added into or removed from the storage without writes,
+    /// such as opening an existing storage or deleting files.
+    pub fn adjust_storage(&self, tenant: &str, added_bytes: u64, removed_bytes: u64) {
+        let quota = self.tenant_quota(tenant);
+        quota
+            .storage_bytes
+            .fetch_add(added_bytes, Ordering::Relaxed);
+        // Never goes below zero.
+        let _ = quota
+            .storage_bytes
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |v| {
+                Some(v.saturating_sub(removed_bytes))
+            });
+    }
+
+    pub fn usage(&self, tenant: &str) -> TenantUsage {
+        self.tenants
+            .read()
+            .unwrap()
+            .get(tenant)
+            .map(|quota| quota.usage())
+            .unwrap_or_default()
+    }
+
+    /// Usage of all tenants, used for billing.
+    pub fn usages(&self) -> HashMap<String, TenantUsage> {
+        self.tenants
+            .read()
+            .unwrap()
+            .iter()
+            .map(|(tenant, quota)| (tenant.clone(), quota.usage()))
+            .collect()
+    }
+
+    fn tenant_quota(&self, tenant: &str) -> Arc<TenantQuota> {
+        if let Some(quota) = self.tenants.read().unwrap().get(tenant) {
+            return quota.clone();
+        }
+
+        let mut tenants = self.tenants.write().unwrap();
+        tenants
+            .entry(tenant.to_string())
+            .or_insert_with(|| {
+                Arc::new(TenantQuota {
+                    limits: self.default_limits,
+                    ..Default::default()
+                })
+            })
+            .clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_quota_check() {
+        let manager = QuotaManager::new(QuotaLimits {
+            max_ingest_rows: Some(100),
+            ..Default::default()
+        });
+        manager.set_limits(
+            "t2",
+            QuotaLimits {
+                max_storage_bytes: Some(10),
+                ..Default::default()
+            },
+        );
+
+        assert!(manager.check_write("t1", 100, 1000).is_ok());
+        manager.record_write("t1", 60, 1000, 10);
+        assert!(matches!(
+            manager.check_write("t1", 50, 0),
+            Err(Error::QuotaExceeded { .. })
+        ));
+
+        assert!(manager.check_write("t2", 1000, 1000).is_ok());
+        manager.record_write("t2", 1000, 1000, 10);
+        assert!(manager.check_write("t2", 1, 1).is_err());
+        manager.adjust_storage("t2", 0, 5);
+        assert!(manager.check_write("t2", 1, 1).is_ok());
+
+        let usages = manager.usages();
+        assert_eq!(
+            TenantUsage {
+                ingest_rows: 60,
+                ingest_bytes: 1000,
+                storage_bytes: 10,
+            },
+            usages["t1"]
+        );
+        assert_eq!(5, usages["t2"].storage_bytes);
+    }
+}
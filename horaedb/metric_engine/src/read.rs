@@ -15,25 +15,83 @@
 // specific language governing permissions and limitations
 // under the License.
 
+use std::{collections::HashSet, ops::Range, sync::Arc};
+
+use arrow::{
+    array::{RecordBatch, RecordBatchOptions},
+    datatypes::{Schema, SchemaRef},
+};
+use bytes::Bytes;
 use datafusion::{
-    datasource::physical_plan::{FileMeta, ParquetFileReaderFactory},
-    error::Result as DfResult,
+    datasource::{
+        physical_plan::{FileMeta, ParquetFileReaderFactory},
+        schema_adapter::{SchemaAdapter, SchemaAdapterFactory, SchemaMapper},
+    },
+    error::{DataFusionError, Result as DfResult},
     parquet::arrow::async_reader::AsyncFileReader,
     physical_plan::metrics::ExecutionPlanMetricsSet,
 };
-use parquet::arrow::async_reader::ParquetObjectReader;
+use futures::{future::BoxFuture, FutureExt};
+use object_store::path::Path;
+use parquet::{
+    arrow::async_reader::ParquetObjectReader, errors::Result as ParquetResult,
+    file::metadata::ParquetMetaData,
+};
+use tracing::{Instrument, Span};
 
-use crate::types::ObjectStoreRef;
+use crate::{
+    codec::ColumnCodecs,
+    defaults::ColumnDefaults,
+    limiter::{IoLimiter, IoLimiterRef, LimitedReader},
+    types::ObjectStoreRef,
+};
 
 #[derive(Debug, Clone)]
 pub struct DefaultParquetFileReaderFactory {
     object_store: ObjectStoreRef,
+    preload_page_index: bool,
+    io_limiter: IoLimiterRef,
+    /// Files read from the local store instead, see [crate::tier].
+    local_files: Option<(ObjectStoreRef, HashSet<Path>)>,
+    /// Parent of spans tracing the reads.
+    span: Span,
 }
 
 /// Returns a AsyncFileReader factory
 impl DefaultParquetFileReaderFactory {
     pub fn new(object_store: ObjectStoreRef) -> Self {
-        Self { object_store }
+        Self {
+            object_store,
+            preload_page_index: false,
+            io_limiter: Arc::new(IoLimiter::default()),
+            local_files: None,
+            span: Span::none(),
+        }
+    }
+
+    pub fn with_io_limiter(mut self, io_limiter: IoLimiterRef) -> Self {
+        self.io_limiter = io_limiter;
+        self
+    }
+
+    /// Fetch page index along with the footer, instead of loading it in
+    /// another request.
+    pub fn with_preload_page_index(mut self, preload_page_index: bool) -> Self {
+        self.preload_page_index = preload_page_index;
+        self
+    }
+
+    /// Read `files` from `local_store`, which have not been migrated to the
+    /// object store yet.
+    pub fn with_local_files(mut self, local_store: ObjectStoreRef, files: HashSet<Path>) -> Self {
+        self.local_files = Some((local_store, files));
+        self
+    }
+
+    /// Trace reads of every file by a child span of `span`.
+    pub fn with_span(mut self, span: Span) -> Self {
+        self.span = span;
+        self
     }
 }
 
@@ -45,11 +103,180 @@ impl ParquetFileReaderFactory for DefaultParquetFileReaderFactory {
         metadata_size_hint: Option<usize>,
         _metrics: &ExecutionPlanMetricsSet,
     ) -> DfResult<Box<dyn AsyncFileReader + Send>> {
-        let object_store = self.object_store.clone();
-        let mut reader = ParquetObjectReader::new(object_store, file_meta.object_meta);
+        let object_store = match &self.local_files {
+            Some((local_store, files)) if files.contains(&file_meta.object_meta.location) => {
+                local_store.clone()
+            }
+            _ => self.object_store.clone(),
+        };
+        let span = tracing::info_span!(
+            parent: &self.span,
+            "sst.read",
+            path = %file_meta.object_meta.location,
+            size = file_meta.object_meta.size,
+        );
+        let mut reader = ParquetObjectReader::new(object_store, file_meta.object_meta)
+            .with_preload_column_index(self.preload_page_index)
+            .with_preload_offset_index(self.preload_page_index);
         if let Some(size) = metadata_size_hint {
             reader = reader.with_footer_size_hint(size);
         }
-        Ok(Box::new(reader))
+        Ok(Box::new(TracedReader {
+            inner: LimitedReader::new(reader, self.io_limiter.clone()),
+            span,
+        }))
+    }
+}
+
+/// [AsyncFileReader] tracing every request to the object store by a span,
+/// `span` of the file lasts until the reader is dropped.
+struct TracedReader<R> {
+    inner: R,
+    span: Span,
+}
+
+impl<R: AsyncFileReader> AsyncFileReader for TracedReader<R> {
+    fn get_bytes(&mut self, range: Range<usize>) -> BoxFuture<'_, ParquetResult<Bytes>> {
+        let span = tracing::info_span!(parent: &self.span, "object_store.get", bytes = range.len());
+        self.inner.get_bytes(range).instrument(span).boxed()
+    }
+
+    fn get_byte_ranges(
+        &mut self,
+        ranges: Vec<Range<usize>>,
+    ) -> BoxFuture<'_, ParquetResult<Vec<Bytes>>> {
+        let span = tracing::info_span!(
+            parent: &self.span,
+            "object_store.get_ranges",
+            ranges = ranges.len(),
+            bytes = ranges.iter().map(|r| r.len()).sum::<usize>(),
+        );
+        self.inner.get_byte_ranges(ranges).instrument(span).boxed()
+    }
+
+    fn get_metadata(&mut self) -> BoxFuture<'_, ParquetResult<Arc<ParquetMetaData>>> {
+        let span = tracing::info_span!(parent: &self.span, "object_store.get_metadata");
+        self.inner.get_metadata().instrument(span).boxed()
+    }
+}
+
+/// Decodes columns encoded by [codecs](crate::codec) when reading ssts, and
+/// fills columns missing from them by their [defaults](crate::defaults).
+#[derive(Debug)]
+pub struct SstSchemaAdapterFactory {
+    codecs: Arc<ColumnCodecs>,
+    defaults: Arc<ColumnDefaults>,
+}
+
+impl SstSchemaAdapterFactory {
+    pub(crate) fn new(codecs: Arc<ColumnCodecs>, defaults: Arc<ColumnDefaults>) -> Self {
+        Self { codecs, defaults }
+    }
+}
+
+impl SchemaAdapterFactory for SstSchemaAdapterFactory {
+    fn create(
+        &self,
+        projected_table_schema: SchemaRef,
+        _table_schema: SchemaRef,
+    ) -> Box<dyn SchemaAdapter> {
+        Box::new(SstSchemaAdapter {
+            codecs: self.codecs.clone(),
+            defaults: self.defaults.clone(),
+            projected_table_schema,
+        })
+    }
+}
+
+struct SstSchemaAdapter {
+    codecs: Arc<ColumnCodecs>,
+    defaults: Arc<ColumnDefaults>,
+    projected_table_schema: SchemaRef,
+}
+
+impl SchemaAdapter for SstSchemaAdapter {
+    fn map_column_index(&self, index: usize, file_schema: &Schema) -> Option<usize> {
+        let field = self.projected_table_schema.field(index);
+        file_schema.index_of(field.name()).ok()
+    }
+
+    fn map_schema(&self, file_schema: &Schema) -> DfResult<(Arc<dyn SchemaMapper>, Vec<usize>)> {
+        let mut projection = Vec::with_capacity(self.projected_table_schema.fields().len());
+        let field_mappings = self
+            .projected_table_schema
+            .fields()
+            .iter()
+            .map(|field| {
+                let index = file_schema.index_of(field.name()).ok()?;
+                projection.push(index);
+                Some(projection.len() - 1)
+            })
+            .collect();
+
+        Ok((
+            Arc::new(SstSchemaMapper {
+                codecs: self.codecs.clone(),
+                defaults: self.defaults.clone(),
+                projected_table_schema: self.projected_table_schema.clone(),
+                field_mappings,
+            }),
+            projection,
+        ))
+    }
+}
+
+#[derive(Debug)]
+struct SstSchemaMapper {
+    codecs: Arc<ColumnCodecs>,
+    defaults: Arc<ColumnDefaults>,
+    projected_table_schema: SchemaRef,
+    /// Index of every column of the table schema in the projected file batch,
+    /// `None` if the sst doesn't have the column.
+    field_mappings: Vec<Option<usize>>,
+}
+
+impl SchemaMapper for SstSchemaMapper {
+    fn map_batch(&self, batch: RecordBatch) -> DfResult<RecordBatch> {
+        let columns = self
+            .projected_table_schema
+            .fields()
+            .iter()
+            .zip(&self.field_mappings)
+            .map(|(field, mapping)| match mapping {
+                Some(index) => self
+                    .codecs
+                    .decode_column(field.name(), batch.column(*index), field.data_type())
+                    .map_err(|e| DataFusionError::External(Box::new(e))),
+                None => self
+                    .defaults
+                    .missing_column(field, batch.num_rows())
+                    .map_err(|e| DataFusionError::External(Box::new(e))),
+            })
+            .collect::<DfResult<Vec<_>>>()?;
+        let options = RecordBatchOptions::new().with_row_count(Some(batch.num_rows()));
+        let batch = RecordBatch::try_new_with_options(
+            self.projected_table_schema.clone(),
+            columns,
+            &options,
+        )?;
+
+        Ok(batch)
+    }
+
+    fn map_partial_batch(&self, batch: RecordBatch) -> DfResult<RecordBatch> {
+        let batch_schema = batch.schema();
+        let fields = batch_schema
+            .fields()
+            .iter()
+            .map(|field| {
+                self.projected_table_schema
+                    .field_with_name(field.name())
+                    .cloned()
+                    .unwrap_or_else(|_| field.as_ref().clone())
+            })
+            .collect::<Vec<_>>();
+        self.codecs
+            .decode_batch(batch, &Arc::new(Schema::new(fields)))
+            .map_err(|e| DataFusionError::External(Box::new(e)))
     }
 }
@@ -0,0 +1,567 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+//! Asynchronous replication of a storage to a secondary region.
+//!
+//! The [Replicator] tails the manifest events of the primary storage, see
+//! [Manifest::subscribe], copies data files and inverted indexes of new ssts
+//! to the secondary store under the same root, and then commits the same
+//! changes to the manifest of the secondary root as deltas. Ssts are copied
+//! before they are committed, so the secondary root is always a consistent
+//! copy of the primary, lagging behind by the events not replicated yet.
+//! Replicated ssts are always in the cloud tier.
+//!
+//! Events missed, e.g. when the replicator falls behind the primary or fails
+//! to replicate an event, are recovered by resyncing the whole manifest.
+//!
+//! Once the primary region is lost, the secondary root is promoted by
+//! [promote], which fences the replicators still running, and then it's
+//! opened by [CloudObjectStorage::try_new] as the new primary.
+
+use std::{
+    collections::HashSet,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::Context;
+use macros::ensure;
+use object_store::{path::Path, PutPayload};
+use tokio::{
+    sync::{
+        broadcast::{self, error::RecvError},
+        oneshot,
+    },
+    task::JoinHandle,
+};
+
+use crate::{
+    manifest::{self, Manifest, ManifestEvent},
+    sst::{self, FileId, SstFile, Tier},
+    storage::CloudObjectStorage,
+    types::{ManifestOptions, ObjectStoreRef},
+    Error, Result,
+};
+
+/// Marker of the promoted secondary root, replicators never write to it.
+const PROMOTED_PATH: &str = "replication/promoted";
+
+#[derive(Debug, Clone)]
+pub struct ReplicationOptions {
+    /// Store of the secondary region.
+    pub target_store: ObjectStoreRef,
+    /// Options of the manifest of the secondary root.
+    pub manifest: ManifestOptions,
+    /// Failed resyncs are retried after it.
+    pub retry_interval: Duration,
+}
+
+impl ReplicationOptions {
+    pub fn new(target_store: ObjectStoreRef) -> Self {
+        Self {
+            target_store,
+            manifest: ManifestOptions::default(),
+            retry_interval: Duration::from_secs(10),
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct ReplicationStats {
+    pub events_replicated: AtomicU64,
+    pub files_replicated: AtomicU64,
+    pub bytes_replicated: AtomicU64,
+    pub resyncs: AtomicU64,
+    pub failed_rounds: AtomicU64,
+    /// Events received but not replicated yet, a pending resync counts as
+    /// one.
+    pub pending_events: AtomicU64,
+    /// The last time the secondary was observed to catch up with the primary,
+    /// 0 if never.
+    pub caught_up_at_ms: AtomicU64,
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct PromoteResult {
+    pub num_files: usize,
+}
+
+/// Background task replicating a storage to the secondary store. The task is
+/// stopped when dropped.
+pub struct Replicator {
+    stats: Arc<ReplicationStats>,
+    stop: Option<oneshot::Sender<()>>,
+    handle: JoinHandle<()>,
+}
+
+impl Replicator {
+    /// Start replicating `storage`, the secondary root is resynced first.
+    ///
+    /// Only one replicator should run for a secondary root.
+    pub async fn start(
+        storage: Arc<CloudObjectStorage>,
+        options: ReplicationOptions,
+    ) -> Result<Self> {
+        let root_path = storage.root_path().to_string();
+        let target = Manifest::try_new(
+            format!("{root_path}/{}", manifest::PREFIX_PATH),
+            options.target_store.clone(),
+            options.manifest,
+        )
+        .await?;
+        // Subscribe before the first resync, so no event is missed.
+        let events = storage.subscribe_manifest();
+        let stats = Arc::new(ReplicationStats::default());
+        let worker = Worker {
+            storage,
+            target,
+            target_store: options.target_store,
+            root_path,
+            stats: stats.clone(),
+            needs_resync: true,
+            promoted: false,
+        };
+        let (stop, stopped) = oneshot::channel();
+        let handle = tokio::spawn(worker.run(events, stopped, options.retry_interval));
+
+        Ok(Self {
+            stats,
+            stop: Some(stop),
+            handle,
+        })
+    }
+
+    pub fn stats(&self) -> &ReplicationStats {
+        &self.stats
+    }
+
+    /// Upper bound of how far the secondary lags behind the primary, `None` if
+    /// it never caught up.
+    pub fn lag(&self) -> Option<Duration> {
+        let caught_up_at_ms = self.stats.caught_up_at_ms.load(Ordering::Relaxed);
+        if caught_up_at_ms == 0 {
+            return None;
+        }
+        if self.stats.pending_events.load(Ordering::Relaxed) == 0 {
+            return Some(Duration::ZERO);
+        }
+
+        Some(Duration::from_millis(
+            now_ms().saturating_sub(caught_up_at_ms),
+        ))
+    }
+
+    /// Returns false once the replicator is stopped, e.g. after the secondary
+    /// root is promoted or the primary storage is dropped.
+    pub fn is_running(&self) -> bool {
+        !self.handle.is_finished()
+    }
+
+    /// Stop replicating after the event in progress is replicated.
+    pub async fn stop(mut self) {
+        if let Some(stop) = self.stop.take() {
+            let _ = stop.send(());
+        }
+        let _ = (&mut self.handle).await;
+    }
+}
+
+impl Drop for Replicator {
+    fn drop(&mut self) {
+        self.handle.abort();
+    }
+}
+
+/// Promote the secondary root at `root_path` of `store` to primary.
+///
+/// Replicators are fenced before replicating the next event, and every sst in
+/// the manifest of the secondary root is checked to be replicated. The event a
+/// replicator is replicating may still be committed, so the replicator should
+/// be stopped first if the primary is still reachable.
+pub async fn promote(store: &ObjectStoreRef, root_path: &str) -> Result<PromoteResult> {
+    let marker_path = Path::from(format!("{root_path}/{PROMOTED_PATH}"));
+    store
+        .put(&marker_path, PutPayload::default())
+        .await
+        .with_context(|| format!("write promoted marker, path:{marker_path}"))?;
+
+    let manifest = Manifest::open_read_only(
+        format!("{root_path}/{}", manifest::PREFIX_PATH),
+        store.clone(),
+        ManifestOptions::default().refresh_interval,
+    )
+    .await?;
+    let ssts = manifest.all_ssts().await;
+    for sst in &ssts {
+        for path in sst_paths(root_path, sst) {
+            store
+                .head(&path)
+                .await
+                .with_context(|| format!("sst {} is not replicated, path:{path}", sst.id))?;
+        }
+    }
+
+    Ok(PromoteResult {
+        num_files: ssts.len(),
+    })
+}
+
+/// Paths of the data file and inverted index of `sst`.
+fn sst_paths(root_path: &str, sst: &SstFile) -> Vec<Path> {
+    let mut paths = vec![Path::from(format!(
+        "{root_path}/{}/{}",
+        sst::PREFIX_PATH,
+        sst.id
+    ))];
+    if sst.meta.inverted_index_size > 0 {
+        paths.push(Path::from(format!(
+            "{root_path}/{}/{}",
+            sst::INDEX_PREFIX_PATH,
+            sst.id
+        )));
+    }
+
+    paths
+}
+
+fn now_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis() as u64
+}
+
+struct Worker {
+    storage: Arc<CloudObjectStorage>,
+    /// Manifest of the secondary root, only written by this worker.
+    target: Manifest,
+    target_store: ObjectStoreRef,
+    root_path: String,
+    stats: Arc<ReplicationStats>,
+    needs_resync: bool,
+    /// Set once the secondary root is found promoted.
+    promoted: bool,
+}
+
+impl Worker {
+    async fn run(
+        mut self,
+        mut events: broadcast::Receiver<ManifestEvent>,
+        mut stopped: oneshot::Receiver<()>,
+        retry_interval: Duration,
+    ) {
+        loop {
+            if self.needs_resync {
+                if self.resync().await.is_err() {
+                    if self.promoted {
+                        return;
+                    }
+                    self.stats.failed_rounds.fetch_add(1, Ordering::Relaxed);
+                    tokio::select! {
+                        _ = tokio::time::sleep(retry_interval) => continue,
+                        _ = &mut stopped => return,
+                    }
+                }
+                self.needs_resync = false;
+                self.stats.resyncs.fetch_add(1, Ordering::Relaxed);
+                self.update_progress(&events);
+            }
+
+            let event = tokio::select! {
+                event = events.recv() => event,
+                _ = &mut stopped => return,
+            };
+            match event {
+                Ok(event) => {
+                    if self.replicate(event).await.is_ok() {
+                        self.stats.events_replicated.fetch_add(1, Ordering::Relaxed);
+                    } else if self.promoted {
+                        return;
+                    } else {
+                        // The secondary may be partially updated by the event.
+                        self.stats.failed_rounds.fetch_add(1, Ordering::Relaxed);
+                        self.needs_resync = true;
+                    }
+                }
+                Err(RecvError::Lagged(_)) => self.needs_resync = true,
+                // The primary storage is dropped.
+                Err(RecvError::Closed) => return,
+            }
+            self.update_progress(&events);
+        }
+    }
+
+    fn update_progress(&self, events: &broadcast::Receiver<ManifestEvent>) {
+        let pending = events.len() as u64 + u64::from(self.needs_resync);
+        self.stats.pending_events.store(pending, Ordering::Relaxed);
+        if pending == 0 {
+            self.stats
+                .caught_up_at_ms
+                .store(now_ms(), Ordering::Relaxed);
+        }
+    }
+
+    async fn replicate(&mut self, event: ManifestEvent) -> Result<()> {
+        self.check_fence().await?;
+        match event {
+            ManifestEvent::Added(ssts) => {
+                let ssts = self.copy_ssts(ssts).await?;
+                self.target.add_files(ssts).await?;
+            }
+            ManifestEvent::Removed(ids) => self.update_target(Vec::new(), &ids, &[]).await?,
+            ManifestEvent::Compacted { inputs, outputs } => {
+                let outputs = self.copy_ssts(outputs).await?;
+                self.update_target(outputs, &inputs, &[]).await?;
+            }
+            ManifestEvent::TombstonesAdded(tombstones) => {
+                for tombstone in tombstones {
+                    self.target.add_tombstone(tombstone).await?;
+                }
+            }
+            ManifestEvent::TombstonesRemoved(ids) => {
+                self.update_target(Vec::new(), &[], &ids).await?
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Make the secondary root the same as the primary.
+    async fn resync(&mut self) -> Result<()> {
+        self.check_fence().await?;
+        let primary = self.storage.manifest();
+        let ssts = primary.all_ssts().await;
+        let tombstones = primary.all_tombstones().await;
+        let leveled_compaction = primary.leveled_compaction().await;
+
+        let ids = ssts.iter().map(|f| f.id).collect::<HashSet<_>>();
+        let to_delete = self
+            .target
+            .all_ssts()
+            .await
+            .into_iter()
+            .map(|f| f.id)
+            .filter(|id| !ids.contains(id))
+            .collect::<Vec<_>>();
+        let target_tombstones = self.target.all_tombstones().await;
+        let tombstone_ids = tombstones.iter().map(|t| t.id).collect::<HashSet<_>>();
+        let tombstones_to_delete = target_tombstones
+            .iter()
+            .map(|t| t.id)
+            .filter(|id| !tombstone_ids.contains(id))
+            .collect::<Vec<_>>();
+
+        let ssts = self.copy_ssts(ssts).await?;
+        self.update_target(ssts, &to_delete, &tombstones_to_delete)
+            .await?;
+        for tombstone in tombstones {
+            if !target_tombstones.iter().any(|t| t.id == tombstone.id) {
+                self.target.add_tombstone(tombstone).await?;
+            }
+        }
+        if let Some(options) = leveled_compaction {
+            if self.target.leveled_compaction().await.as_ref() != Some(&options) {
+                self.target.set_leveled_compaction(options).await?;
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn check_fence(&mut self) -> Result<()> {
+        let marker_path = Path::from(format!("{}/{PROMOTED_PATH}", self.root_path));
+        self.promoted = match self.target_store.head(&marker_path).await {
+            Ok(_) => true,
+            Err(object_store::Error::NotFound { .. }) => false,
+            Err(e) => {
+                return Err(Error::StorageIo {
+                    msg: format!("head promoted marker, path:{marker_path}"),
+                    source: e,
+                })
+            }
+        };
+        ensure!(
+            !self.promoted,
+            "secondary root is promoted, root:{}",
+            self.root_path
+        );
+
+        Ok(())
+    }
+
+    /// Copy ssts not in the secondary yet, returns `ssts` switched to the
+    /// cloud tier.
+    async fn copy_ssts(&self, mut ssts: Vec<SstFile>) -> Result<Vec<SstFile>> {
+        let replicated = self
+            .target
+            .all_ssts()
+            .await
+            .into_iter()
+            .map(|f| f.id)
+            .collect::<HashSet<_>>();
+        for sst in &mut ssts {
+            if !replicated.contains(&sst.id) {
+                let bytes = self.storage.copy_sst(sst, &self.target_store).await?;
+                self.stats.files_replicated.fetch_add(1, Ordering::Relaxed);
+                self.stats
+                    .bytes_replicated
+                    .fetch_add(bytes, Ordering::Relaxed);
+            }
+            sst.meta.tier = Tier::Cloud;
+        }
+
+        Ok(ssts)
+    }
+
+    /// Commit the update to the secondary, and then delete files of the ssts
+    /// removed.
+    async fn update_target(
+        &self,
+        new_ssts: Vec<SstFile>,
+        to_delete: &[FileId],
+        tombstones_to_delete: &[FileId],
+    ) -> Result<()> {
+        let removed = self
+            .target
+            .all_ssts()
+            .await
+            .into_iter()
+            .filter(|f| to_delete.contains(&f.id) && !new_ssts.iter().any(|n| n.id == f.id))
+            .collect::<Vec<_>>();
+        self.target
+            .update_with_tombstones(new_ssts, to_delete, tombstones_to_delete)
+            .await?;
+        // Failing to delete them only leaks the objects.
+        for sst in removed {
+            for path in sst_paths(&self.root_path, &sst) {
+                let _ = self.target_store.delete(&path).await;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use arrow::datatypes::DataType;
+    use futures::TryStreamExt;
+    use object_store::memory::InMemory;
+
+    use super::*;
+    use crate::{
+        storage::{OutputOrder, ScanRequest, TimeMergeStorage, WriteRequest},
+        testing::TableBuilder,
+        types::{TimeRange, Timestamp, WriteOptions},
+    };
+
+    async fn wait_until(cond: impl Fn() -> bool) {
+        for _ in 0..500 {
+            if cond() {
+                return;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        panic!("condition is not met in time");
+    }
+
+    async fn num_rows(storage: &CloudObjectStorage) -> usize {
+        let stream = storage
+            .scan(ScanRequest {
+                range: TimeRange::new(Timestamp::MIN, Timestamp::MAX),
+                predicate: Vec::new(),
+                projections: None,
+                limit_per_series: None,
+                output_order: OutputOrder::ByKey,
+                output_exprs: None,
+                memory_limit: None,
+                snapshot_version: None,
+                batch_size: None,
+            })
+            .await
+            .unwrap();
+        let batches = stream.try_collect::<Vec<_>>().await.unwrap();
+        batches.iter().map(|b| b.num_rows()).sum()
+    }
+
+    #[tokio::test]
+    async fn test_replicate_and_promote() {
+        let table = TableBuilder::new()
+            .tag("host")
+            .field("value", DataType::Float64)
+            .build()
+            .await
+            .unwrap();
+        table.write_series(2, 2).await.unwrap();
+        let generator = table.generator().num_series(3).points_per_series(2);
+        let schema = table.storage.schema().clone();
+        let storage = Arc::new(table.storage);
+        let target_store: ObjectStoreRef = Arc::new(InMemory::new());
+        let replicator = Replicator::start(
+            storage.clone(),
+            ReplicationOptions::new(target_store.clone()),
+        )
+        .await
+        .unwrap();
+        wait_until(|| replicator.stats().resyncs.load(Ordering::Relaxed) == 1).await;
+
+        let batch = generator.generate().unwrap();
+        storage.write(WriteRequest { batch }).await.unwrap();
+        // The secondary catches up once the write is replicated.
+        wait_until(|| {
+            replicator.stats().events_replicated.load(Ordering::Relaxed) == 1
+                && replicator.lag() == Some(Duration::ZERO)
+        })
+        .await;
+        assert_eq!(
+            2,
+            replicator.stats().files_replicated.load(Ordering::Relaxed)
+        );
+        let secondary = CloudObjectStorage::open_replica(
+            "/test".to_string(),
+            target_store.clone(),
+            schema.clone(),
+            2,
+            1,
+            WriteOptions::default(),
+        )
+        .await
+        .unwrap();
+        assert_eq!(10, num_rows(&secondary).await);
+
+        // Replicators are fenced once the secondary is promoted.
+        let result = promote(&target_store, "/test").await.unwrap();
+        assert_eq!(2, result.num_files);
+        let batch = generator.generate().unwrap();
+        storage.write(WriteRequest { batch }).await.unwrap();
+        wait_until(|| !replicator.is_running()).await;
+        let promoted = CloudObjectStorage::try_new(
+            "/test".to_string(),
+            target_store,
+            schema,
+            2,
+            1,
+            WriteOptions::default(),
+        )
+        .await
+        .unwrap();
+        assert_eq!(10, num_rows(&promoted).await);
+    }
+}
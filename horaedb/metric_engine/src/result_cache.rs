@@ -0,0 +1,366 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+//! Cache of scan results.
+//!
+//! Small results of recent scans are cached as arrow IPC streams, keyed by the
+//! scan request, i.e. the predicate, projections and time range. Every entry
+//! is tagged with the version of the range it covers, which consists of the
+//! ids of ssts and tombstones overlapping with the range. So the entry is
+//! invalidated once ssts are written into, compacted or deleted from the
+//! range, while writes to other ranges keep it.
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    io::Cursor,
+    pin::Pin,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    task::{Context, Poll},
+};
+
+use anyhow::Context as _;
+use arrow::{
+    array::RecordBatch,
+    datatypes::SchemaRef,
+    ipc::{reader::StreamReader, writer::StreamWriter},
+};
+use bytes::Bytes;
+use datafusion::{
+    error::Result as DfResult,
+    execution::{RecordBatchStream, SendableRecordBatchStream},
+};
+use futures::{Stream, StreamExt};
+
+use crate::{sst::FileId, Result};
+
+#[derive(Debug, Clone)]
+pub struct ResultCacheOptions {
+    /// Max bytes of all cached results.
+    pub capacity_bytes: usize,
+    /// Results larger than it in memory are never cached.
+    pub max_entry_bytes: usize,
+}
+
+impl Default for ResultCacheOptions {
+    fn default() -> Self {
+        Self {
+            capacity_bytes: 64 * 1024 * 1024,
+            max_entry_bytes: 1024 * 1024,
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct ResultCacheStats {
+    pub hits: AtomicU64,
+    pub misses: AtomicU64,
+    /// Entries dropped since their ranges are changed.
+    pub invalidations: AtomicU64,
+    /// Entries dropped to make room for new ones.
+    pub evictions: AtomicU64,
+}
+
+/// Version of the time range covered by a scan.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct RangeVersion {
+    ssts: Vec<FileId>,
+    tombstones: Vec<FileId>,
+}
+
+impl RangeVersion {
+    pub(crate) fn new(mut ssts: Vec<FileId>, mut tombstones: Vec<FileId>) -> Self {
+        ssts.sort_unstable();
+        tombstones.sort_unstable();
+        Self { ssts, tombstones }
+    }
+}
+
+struct Entry {
+    version: RangeVersion,
+    data: Bytes,
+    /// Entries with smaller ticks are used less recently.
+    tick: u64,
+}
+
+#[derive(Default)]
+struct Inner {
+    entries: HashMap<String, Entry>,
+    lru: BTreeMap<u64, String>,
+    next_tick: u64,
+    size: usize,
+}
+
+impl Inner {
+    fn remove(&mut self, key: &str) {
+        if let Some(entry) = self.entries.remove(key) {
+            self.lru.remove(&entry.tick);
+            self.size -= entry.data.len();
+        }
+    }
+
+    fn touch(&mut self, key: &str) {
+        let tick = self.next_tick;
+        if let Some(entry) = self.entries.get_mut(key) {
+            self.lru.remove(&entry.tick);
+            self.lru.insert(tick, key.to_string());
+            entry.tick = tick;
+            self.next_tick += 1;
+        }
+    }
+}
+
+/// Least recently used cache of scan results, it may be shared by storages
+/// since keys include the roots of them.
+pub struct ResultCache {
+    options: ResultCacheOptions,
+    inner: Mutex<Inner>,
+    stats: ResultCacheStats,
+}
+
+pub type ResultCacheRef = Arc<ResultCache>;
+
+impl ResultCache {
+    pub fn new(options: ResultCacheOptions) -> Self {
+        Self {
+            options,
+            inner: Mutex::new(Inner::default()),
+            stats: ResultCacheStats::default(),
+        }
+    }
+
+    pub fn stats(&self) -> &ResultCacheStats {
+        &self.stats
+    }
+
+    /// Bytes of the cached results.
+    pub fn size(&self) -> usize {
+        self.inner.lock().unwrap().size
+    }
+
+    pub fn clear(&self) {
+        *self.inner.lock().unwrap() = Inner::default();
+    }
+
+    /// Returns the schema and batches of `key` if it's cached at `version`.
+    pub(crate) fn get(
+        &self,
+        key: &str,
+        version: &RangeVersion,
+    ) -> Result<Option<(SchemaRef, Vec<RecordBatch>)>> {
+        let data = {
+            let mut inner = self.inner.lock().unwrap();
+            let cached = inner
+                .entries
+                .get(key)
+                .map(|entry| (entry.version == *version, entry.data.clone()));
+            let data = match cached {
+                Some((true, data)) => data,
+                Some((false, _)) => {
+                    inner.remove(key);
+                    self.stats.invalidations.fetch_add(1, Ordering::Relaxed);
+                    self.stats.misses.fetch_add(1, Ordering::Relaxed);
+                    return Ok(None);
+                }
+                None => {
+                    self.stats.misses.fetch_add(1, Ordering::Relaxed);
+                    return Ok(None);
+                }
+            };
+            inner.touch(key);
+            data
+        };
+        self.stats.hits.fetch_add(1, Ordering::Relaxed);
+
+        let reader =
+            StreamReader::try_new(Cursor::new(data), None).context("decode cached result")?;
+        let schema = reader.schema();
+        let batches = reader
+            .collect::<std::result::Result<Vec<_>, _>>()
+            .context("decode cached batches")?;
+        Ok(Some((schema, batches)))
+    }
+
+    /// Cache `batches` of `key` at `version`, least recently used entries are
+    /// evicted if the cache is full.
+    pub(crate) fn insert(
+        &self,
+        key: String,
+        version: RangeVersion,
+        schema: &SchemaRef,
+        batches: &[RecordBatch],
+    ) -> Result<()> {
+        let mut writer =
+            StreamWriter::try_new(Vec::new(), schema).context("create result encoder")?;
+        for batch in batches {
+            writer.write(batch).context("encode result batch")?;
+        }
+        let data = Bytes::from(writer.into_inner().context("finish result encoder")?);
+        if data.len() > self.options.capacity_bytes {
+            return Ok(());
+        }
+
+        let mut inner = self.inner.lock().unwrap();
+        inner.remove(&key);
+        while inner.size + data.len() > self.options.capacity_bytes {
+            let Some((_, oldest)) = inner.lru.pop_first() else {
+                break;
+            };
+            if let Some(entry) = inner.entries.remove(&oldest) {
+                inner.size -= entry.data.len();
+            }
+            self.stats.evictions.fetch_add(1, Ordering::Relaxed);
+        }
+        let tick = inner.next_tick;
+        inner.next_tick += 1;
+        inner.size += data.len();
+        inner.lru.insert(tick, key.clone());
+        inner.entries.insert(
+            key,
+            Entry {
+                version,
+                data,
+                tick,
+            },
+        );
+
+        Ok(())
+    }
+}
+
+/// Pass batches of the input through, and cache them once the input is
+/// drained if they are small enough.
+pub(crate) struct CachingStream {
+    input: SendableRecordBatchStream,
+    cache: ResultCacheRef,
+    key: String,
+    version: Option<RangeVersion>,
+    /// `None` once the result is too large or the input fails.
+    batches: Option<Vec<RecordBatch>>,
+    num_bytes: usize,
+}
+
+impl CachingStream {
+    pub(crate) fn new(
+        input: SendableRecordBatchStream,
+        cache: ResultCacheRef,
+        key: String,
+        version: RangeVersion,
+    ) -> Self {
+        Self {
+            input,
+            cache,
+            key,
+            version: Some(version),
+            batches: Some(Vec::new()),
+            num_bytes: 0,
+        }
+    }
+}
+
+impl Stream for CachingStream {
+    type Item = DfResult<RecordBatch>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+        let poll = this.input.poll_next_unpin(cx);
+        match &poll {
+            Poll::Ready(Some(Ok(batch))) => {
+                this.num_bytes += batch.get_array_memory_size();
+                if this.num_bytes > this.cache.options.max_entry_bytes {
+                    this.batches = None;
+                } else if let Some(batches) = &mut this.batches {
+                    batches.push(batch.clone());
+                }
+            }
+            Poll::Ready(Some(Err(_))) => this.batches = None,
+            Poll::Ready(None) => {
+                if let (Some(batches), Some(version)) = (this.batches.take(), this.version.take()) {
+                    let key = std::mem::take(&mut this.key);
+                    // Failing to cache the result never fails the scan.
+                    let _ = this
+                        .cache
+                        .insert(key, version, &this.input.schema(), &batches);
+                }
+            }
+            Poll::Pending => {}
+        }
+
+        poll
+    }
+}
+
+impl RecordBatchStream for CachingStream {
+    fn schema(&self) -> SchemaRef {
+        self.input.schema()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use arrow::{
+        array::Int64Array,
+        datatypes::{DataType, Field, Schema},
+    };
+
+    use super::*;
+
+    #[test]
+    fn test_invalidate_and_evict() {
+        let schema = Arc::new(Schema::new(vec![Field::new("v", DataType::Int64, false)]));
+        let batch = RecordBatch::try_new(
+            schema.clone(),
+            vec![Arc::new(Int64Array::from_iter_values(0..100))],
+        )
+        .unwrap();
+        let cache = ResultCache::new(ResultCacheOptions::default());
+        let version = RangeVersion::new(vec![2, 1], vec![]);
+        cache
+            .insert("a".to_string(), version.clone(), &schema, &[batch.clone()])
+            .unwrap();
+        let entry_size = cache.size();
+        let (_, batches) = cache
+            .get("a", &RangeVersion::new(vec![1, 2], vec![]))
+            .unwrap()
+            .unwrap();
+        assert_eq!(vec![batch.clone()], batches);
+
+        // Ssts of the range are changed.
+        assert!(cache
+            .get("a", &RangeVersion::new(vec![1, 2, 3], vec![]))
+            .unwrap()
+            .is_none());
+        assert_eq!(1, cache.stats().invalidations.load(Ordering::Relaxed));
+        assert_eq!(0, cache.size());
+
+        // Only room for one entry.
+        let cache = ResultCache::new(ResultCacheOptions {
+            capacity_bytes: entry_size * 3 / 2,
+            ..Default::default()
+        });
+        for key in ["a", "b"] {
+            cache
+                .insert(key.to_string(), version.clone(), &schema, &[batch.clone()])
+                .unwrap();
+        }
+        assert!(cache.get("a", &version).unwrap().is_none());
+        assert!(cache.get("b", &version).unwrap().is_some());
+        assert_eq!(1, cache.stats().evictions.load(Ordering::Relaxed));
+    }
+}
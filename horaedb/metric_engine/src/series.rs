@@ -0,0 +1,489 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+//! Label dictionary of series.
+//!
+//! Rows are stored with a compact series id as a primary key column instead
+//! of the full label set, and the dictionary maps label sets to series ids and
+//! back. New entries are persisted in batches before their ids are handed out:
+//! ```plaintext
+//! {root_path}/series/{seq}
+//! ```
+//! Batches are replayed in sequence order on open, and merged into one once
+//! there are too many of them.
+//!
+//! Series ids are hashes of the label sets, the next free id is taken on
+//! collision. So ids of known label sets are always looked up in the
+//! dictionary instead of being hashed again.
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    sync::Arc,
+};
+
+use anyhow::Context;
+use arrow::{
+    array::{Array, ArrayRef, AsArray, RecordBatch, StringBuilder, UInt64Array},
+    compute::cast,
+    datatypes::{DataType, Field, Schema, UInt64Type},
+};
+use futures::TryStreamExt;
+use macros::ensure;
+use object_store::{path::Path, PutMode, PutOptions, PutPayload};
+use prost::Message;
+use tokio::sync::{Mutex, RwLock};
+
+use crate::{types::ObjectStoreRef, Result};
+
+pub const PREFIX_PATH: &str = "series";
+/// Batches are merged on open once there are more of them.
+const MAX_BATCHES: usize = 64;
+
+pub type SeriesId = u64;
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Label {
+    pub name: String,
+    pub value: String,
+}
+
+/// Label set identifying a series, labels are sorted by names.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct Labels(Vec<Label>);
+
+impl Labels {
+    /// The last one wins for duplicate names.
+    pub fn new(labels: impl IntoIterator<Item = Label>) -> Self {
+        let labels = labels
+            .into_iter()
+            .map(|label| (label.name, label.value))
+            .collect::<BTreeMap<_, _>>();
+
+        Self(
+            labels
+                .into_iter()
+                .map(|(name, value)| Label { name, value })
+                .collect(),
+        )
+    }
+
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.0
+            .binary_search_by(|label| label.name.as_str().cmp(name))
+            .ok()
+            .map(|i| self.0[i].value.as_str())
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Label> {
+        self.0.iter()
+    }
+
+    /// FNV-1a hash of the labels, which is stable across versions.
+    fn hash_id(&self) -> SeriesId {
+        const OFFSET: u64 = 0xcbf29ce484222325;
+        const PRIME: u64 = 0x100000001b3;
+
+        let mut hash = OFFSET;
+        for label in &self.0 {
+            let bytes = label.name.bytes().chain([0]);
+            for byte in bytes.chain(label.value.bytes()).chain([0]) {
+                hash ^= byte as u64;
+                hash = hash.wrapping_mul(PRIME);
+            }
+        }
+
+        hash
+    }
+}
+
+#[derive(Default)]
+struct Entries {
+    ids: HashMap<Labels, SeriesId>,
+    labels: HashMap<SeriesId, Labels>,
+}
+
+impl Entries {
+    fn insert(&mut self, id: SeriesId, labels: Labels) {
+        self.ids.insert(labels.clone(), id);
+        self.labels.insert(id, labels);
+    }
+
+    fn to_batch(&self) -> pb_types::SeriesBatch {
+        pb_types::SeriesBatch {
+            entries: self
+                .labels
+                .iter()
+                .map(|(id, labels)| entry_to_pb(*id, labels))
+                .collect(),
+        }
+    }
+}
+
+pub struct SeriesDictionary {
+    dir: Path,
+    store: ObjectStoreRef,
+    entries: RwLock<Entries>,
+    /// Sequence of the next batch, the lock serializes the writers.
+    next_seq: Mutex<u64>,
+}
+
+pub type SeriesDictionaryRef = Arc<SeriesDictionary>;
+
+impl SeriesDictionary {
+    /// Open the dictionary under `root_path`, persisted batches are replayed.
+    pub async fn try_new(root_path: &str, store: ObjectStoreRef) -> Result<Self> {
+        let dir = Path::from(format!("{root_path}/{PREFIX_PATH}"));
+        let batches = list_batches(&store, &dir).await?;
+
+        let mut entries = Entries::default();
+        for (_, path) in &batches {
+            let bytes = store
+                .get(path)
+                .await
+                .with_context(|| format!("failed to get series batch, path:{path}"))?
+                .bytes()
+                .await
+                .with_context(|| format!("failed to read series batch, path:{path}"))?;
+            let batch = pb_types::SeriesBatch::decode(bytes)
+                .with_context(|| format!("failed to decode series batch, path:{path}"))?;
+            for entry in batch.entries {
+                let (id, labels) = entry_from_pb(entry);
+                entries.insert(id, labels);
+            }
+        }
+
+        let mut next_seq = batches.last().map(|(seq, _)| seq + 1).unwrap_or_default();
+        if batches.len() > MAX_BATCHES {
+            // The merged batch contains all the entries, so replaying it along with the
+            // batches failed to be deleted is harmless.
+            let merged = Path::from(format!("{dir}/{next_seq}"));
+            put_batch(&store, &merged, entries.to_batch()).await?;
+            next_seq += 1;
+            for (_, path) in &batches {
+                let _ = store.delete(path).await;
+            }
+        }
+
+        Ok(Self {
+            dir,
+            store,
+            entries: RwLock::new(entries),
+            next_seq: Mutex::new(next_seq),
+        })
+    }
+
+    /// Number of the series in the dictionary.
+    pub async fn len(&self) -> usize {
+        self.entries.read().await.ids.len()
+    }
+
+    pub async fn is_empty(&self) -> bool {
+        self.len().await == 0
+    }
+
+    /// Ids of `label_sets`, the unknown label sets are added to the dictionary
+    /// and persisted before returning.
+    pub async fn get_or_create(&self, label_sets: &[Labels]) -> Result<Vec<SeriesId>> {
+        {
+            let entries = self.entries.read().await;
+            if let Some(ids) = label_sets
+                .iter()
+                .map(|labels| entries.ids.get(labels).copied())
+                .collect::<Option<Vec<_>>>()
+            {
+                return Ok(ids);
+            }
+        }
+
+        let mut next_seq = self.next_seq.lock().await;
+        let mut added = Entries::default();
+        {
+            let entries = self.entries.read().await;
+            for labels in label_sets {
+                if entries.ids.contains_key(labels) || added.ids.contains_key(labels) {
+                    continue;
+                }
+                let mut id = labels.hash_id();
+                while entries.labels.contains_key(&id) || added.labels.contains_key(&id) {
+                    id = id.wrapping_add(1);
+                }
+                added.insert(id, labels.clone());
+            }
+        }
+
+        if !added.ids.is_empty() {
+            // It fails if another writer has persisted the same sequence.
+            let path = Path::from(format!("{}/{}", self.dir, *next_seq));
+            put_batch(&self.store, &path, added.to_batch()).await?;
+            *next_seq += 1;
+        }
+
+        let mut entries = self.entries.write().await;
+        for (id, labels) in added.labels {
+            entries.insert(id, labels);
+        }
+        Ok(label_sets
+            .iter()
+            .map(|labels| entries.ids[labels])
+            .collect())
+    }
+
+    /// Label sets of `ids`, `None` for unknown ids.
+    pub async fn labels(&self, ids: &[SeriesId]) -> Vec<Option<Labels>> {
+        let entries = self.entries.read().await;
+        ids.iter()
+            .map(|id| entries.labels.get(id).cloned())
+            .collect()
+    }
+
+    /// Replace the string columns of `label_columns` in `batch` with the
+    /// `series_column` of series ids, which is placed at first. Columns may be
+    /// dictionaries of strings as well.
+    ///
+    /// Null values are not part of the label sets.
+    pub async fn encode_batch(
+        &self,
+        batch: &RecordBatch,
+        label_columns: &[usize],
+        series_column: &str,
+    ) -> Result<RecordBatch> {
+        let schema = batch.schema();
+        let mut label_arrays = Vec::with_capacity(label_columns.len());
+        for idx in label_columns {
+            let array = batch.column(*idx);
+            let array = match array.data_type() {
+                DataType::Dictionary(_, value_type) if **value_type == DataType::Utf8 => {
+                    cast(array, &DataType::Utf8).context("unpack dictionary label column")?
+                }
+                _ => array.clone(),
+            };
+            ensure!(
+                array.as_string_opt::<i32>().is_some(),
+                "label column must be utf8, column:{}",
+                schema.field(*idx).name()
+            );
+            label_arrays.push(array);
+        }
+        let columns = label_columns
+            .iter()
+            .zip(&label_arrays)
+            .map(|(idx, array)| (schema.field(*idx).name(), array.as_string::<i32>()))
+            .collect::<Vec<_>>();
+
+        let label_sets = (0..batch.num_rows())
+            .map(|row| {
+                Labels::new(columns.iter().filter(|(_, array)| array.is_valid(row)).map(
+                    |(name, array)| Label {
+                        name: name.to_string(),
+                        value: array.value(row).to_string(),
+                    },
+                ))
+            })
+            .collect::<Vec<_>>();
+        let ids = self.get_or_create(&label_sets).await?;
+
+        let mut fields = vec![Arc::new(Field::new(series_column, DataType::UInt64, false))];
+        let mut arrays: Vec<ArrayRef> = vec![Arc::new(UInt64Array::from(ids))];
+        for (idx, field) in schema.fields().iter().enumerate() {
+            if !label_columns.contains(&idx) {
+                fields.push(field.clone());
+                arrays.push(batch.column(idx).clone());
+            }
+        }
+        let batch = RecordBatch::try_new(Arc::new(Schema::new(fields)), arrays)
+            .context("build batch with series ids")?;
+
+        Ok(batch)
+    }
+
+    /// Replace the column of series ids at `series_column` in `batch` with the
+    /// utf8 columns of `label_names`, the missing labels are null.
+    pub async fn decode_batch(
+        &self,
+        batch: &RecordBatch,
+        series_column: usize,
+        label_names: &[String],
+    ) -> Result<RecordBatch> {
+        let ids = batch.column(series_column).as_primitive_opt::<UInt64Type>();
+        ensure!(ids.is_some(), "series column must be uint64");
+        let ids = ids.unwrap();
+
+        let label_sets = self.labels(ids.values()).await;
+        let mut builders = label_names
+            .iter()
+            .map(|_| StringBuilder::new())
+            .collect::<Vec<_>>();
+        for (id, labels) in ids.values().iter().zip(&label_sets) {
+            ensure!(labels.is_some(), "unknown series id:{id}");
+            let labels = labels.as_ref().unwrap();
+            for (name, builder) in label_names.iter().zip(&mut builders) {
+                builder.append_option(labels.get(name));
+            }
+        }
+
+        let schema = batch.schema();
+        let mut fields = Vec::with_capacity(schema.fields().len() + label_names.len());
+        let mut arrays: Vec<ArrayRef> = Vec::with_capacity(fields.capacity());
+        for name in label_names {
+            fields.push(Arc::new(Field::new(name, DataType::Utf8, true)));
+        }
+        for mut builder in builders {
+            arrays.push(Arc::new(builder.finish()));
+        }
+        for (idx, field) in schema.fields().iter().enumerate() {
+            if idx != series_column {
+                fields.push(field.clone());
+                arrays.push(batch.column(idx).clone());
+            }
+        }
+        let batch = RecordBatch::try_new(Arc::new(Schema::new(fields)), arrays)
+            .context("build batch with labels")?;
+
+        Ok(batch)
+    }
+}
+
+/// Sequences and paths of batches under `dir`, sorted by sequence.
+async fn list_batches(store: &ObjectStoreRef, dir: &Path) -> Result<Vec<(u64, Path)>> {
+    let objects = store
+        .list(Some(dir))
+        .try_collect::<Vec<_>>()
+        .await
+        .with_context(|| format!("failed to list series batches, path:{dir}"))?;
+    let mut batches = objects
+        .into_iter()
+        .filter_map(|meta| {
+            let seq = meta.location.filename()?.parse::<u64>().ok()?;
+            Some((seq, meta.location))
+        })
+        .collect::<Vec<_>>();
+    batches.sort_unstable_by_key(|(seq, _)| *seq);
+
+    Ok(batches)
+}
+
+async fn put_batch(
+    store: &ObjectStoreRef,
+    path: &Path,
+    batch: pb_types::SeriesBatch,
+) -> Result<()> {
+    store
+        .put_opts(
+            path,
+            PutPayload::from(batch.encode_to_vec()),
+            PutOptions::from(PutMode::Create),
+        )
+        .await
+        .with_context(|| format!("failed to write series batch, path:{path}"))?;
+
+    Ok(())
+}
+
+fn entry_to_pb(id: SeriesId, labels: &Labels) -> pb_types::SeriesEntry {
+    pb_types::SeriesEntry {
+        id,
+        labels: labels
+            .iter()
+            .map(|label| pb_types::Label {
+                name: label.name.clone(),
+                value: label.value.clone(),
+            })
+            .collect(),
+    }
+}
+
+fn entry_from_pb(entry: pb_types::SeriesEntry) -> (SeriesId, Labels) {
+    let labels = Labels::new(entry.labels.into_iter().map(|label| Label {
+        name: label.name,
+        value: label.value,
+    }));
+
+    (entry.id, labels)
+}
+
+#[cfg(test)]
+mod tests {
+    use arrow::array::{Int64Array, StringArray};
+    use object_store::memory::InMemory;
+
+    use super::*;
+
+    fn labels(pairs: &[(&str, &str)]) -> Labels {
+        Labels::new(pairs.iter().map(|(name, value)| Label {
+            name: name.to_string(),
+            value: value.to_string(),
+        }))
+    }
+
+    #[tokio::test]
+    async fn test_series_dictionary() {
+        let store: ObjectStoreRef = Arc::new(InMemory::new());
+        let dict = SeriesDictionary::try_new("root", store.clone())
+            .await
+            .unwrap();
+
+        let label_sets = vec![
+            labels(&[("host", "a"), ("region", "x")]),
+            labels(&[("region", "x"), ("host", "a")]),
+            labels(&[("host", "b")]),
+        ];
+        let ids = dict.get_or_create(&label_sets).await.unwrap();
+        assert_eq!(ids[0], ids[1]);
+        assert_ne!(ids[0], ids[2]);
+        assert_eq!(2, dict.len().await);
+        assert_eq!(ids, dict.get_or_create(&label_sets).await.unwrap());
+
+        // Entries are replayed after reopening.
+        let dict = SeriesDictionary::try_new("root", store).await.unwrap();
+        assert_eq!(
+            vec![Some(label_sets[2].clone()), None],
+            dict.labels(&[ids[2], ids[2].wrapping_add(1)]).await
+        );
+
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("host", DataType::Utf8, true),
+            Field::new("value", DataType::Int64, false),
+            Field::new("region", DataType::Utf8, true),
+        ]));
+        let batch = RecordBatch::try_new(
+            schema,
+            vec![
+                Arc::new(StringArray::from(vec![Some("a"), Some("b"), Some("c")])),
+                Arc::new(Int64Array::from(vec![1, 2, 3])),
+                Arc::new(StringArray::from(vec![Some("x"), None, Some("y")])),
+            ],
+        )
+        .unwrap();
+        let encoded = dict
+            .encode_batch(&batch, &[0, 2], "series_id")
+            .await
+            .unwrap();
+        assert_eq!(2, encoded.num_columns());
+        let series_ids = encoded.column(0).as_primitive::<UInt64Type>();
+        assert_eq!(ids[0], series_ids.value(0));
+        assert_eq!(ids[2], series_ids.value(1));
+        assert_eq!(3, dict.len().await);
+
+        let decoded = dict
+            .decode_batch(&encoded, 0, &["host".to_string(), "region".to_string()])
+            .await
+            .unwrap();
+        assert_eq!(batch.column(0), decoded.column(0));
+        assert_eq!(batch.column(2), decoded.column(1));
+        assert_eq!(batch.column(1), decoded.column(2));
+    }
+}
@@ -15,22 +15,28 @@
 // specific language governing permissions and limitations
 // under the License.
 
-use std::{
-    sync::{
-        atomic::{AtomicU64, Ordering},
-        LazyLock,
-    },
-    time::SystemTime,
+use anyhow::Context;
+use arrow::{
+    array::{Array, ArrayRef, AsArray},
+    compute::{self, cast},
+    datatypes::{DataType, Float64Type},
 };
-
 use macros::ensure;
 
 use crate::{types::TimeRange, Error};
 
 pub const PREFIX_PATH: &str = "data";
+/// Prefix of the inverted indexes of ssts, see [crate::inverted_index].
+pub const INDEX_PREFIX_PATH: &str = "index";
 
 pub type FileId = u64;
 
+/// Level of an sst, see [FileMeta::level].
+pub type Level = u32;
+
+pub const LEVEL_0: Level = 0;
+pub const LEVEL_1: Level = 1;
+
 #[derive(Clone, Debug)]
 pub struct SstFile {
     pub id: FileId,
@@ -41,7 +47,10 @@ impl TryFrom<pb_types::SstFile> for SstFile {
     type Error = Error;
 
     fn try_from(value: pb_types::SstFile) -> Result<Self, Self::Error> {
-        ensure!(value.meta.is_some(), "file meta is missing");
+        ensure!(
+            value.meta.is_some(),
+            Error::manifest_corrupt("file meta is missing")
+        );
         let meta = value.meta.unwrap();
         let meta = meta.try_into()?;
 
@@ -64,20 +73,80 @@ pub struct FileMeta {
     pub num_rows: u32,
     pub size: u32,
     pub time_range: TimeRange,
+    /// Fresh ssts land in [LEVEL_0] and may overlap with each other, ssts in
+    /// [LEVEL_1] and deeper levels are compacted into non-overlapping time
+    /// slices, so ssts of the same level never overlap.
+    pub level: Level,
+    /// Size of the inverted index written along with the sst, 0 if there is
+    /// none.
+    pub inverted_index_size: u32,
+    /// Aggregates of the numeric value columns, which may answer aggregate
+    /// queries without reading the sst.
+    pub aggregates: Vec<ColumnAggregate>,
+    pub tier: Tier,
+    /// Min and max keys of the series key columns, see [crate::primary_key].
+    /// Both are empty if they are unknown.
+    pub min_key: Vec<u8>,
+    pub max_key: Vec<u8>,
+    /// Crc32 of the data file, see [crate::checksum]. `None` if it's unknown.
+    pub checksum: Option<u32>,
+    /// Value columns left out of the data file since all of their values are
+    /// null, empty unless the sst is written with
+    /// [WriteOptions::sparse_columns](crate::types::WriteOptions::sparse_columns).
+    pub null_columns: Vec<String>,
+}
+
+/// Where the data file of an sst is stored, see [crate::tier].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Tier {
+    #[default]
+    Cloud,
+    Local,
+}
+
+impl From<pb_types::Tier> for Tier {
+    fn from(value: pb_types::Tier) -> Self {
+        match value {
+            pb_types::Tier::Cloud => Tier::Cloud,
+            pb_types::Tier::Local => Tier::Local,
+        }
+    }
+}
+
+impl From<Tier> for pb_types::Tier {
+    fn from(value: Tier) -> Self {
+        match value {
+            Tier::Cloud => pb_types::Tier::Cloud,
+            Tier::Local => pb_types::Tier::Local,
+        }
+    }
 }
 
 impl TryFrom<pb_types::SstMeta> for FileMeta {
     type Error = Error;
 
     fn try_from(value: pb_types::SstMeta) -> Result<Self, Self::Error> {
-        ensure!(value.time_range.is_some(), "time range is missing");
+        ensure!(
+            value.time_range.is_some(),
+            Error::manifest_corrupt("time range of sst is missing")
+        );
         let time_range = value.time_range.unwrap();
 
         Ok(Self {
             max_sequence: value.max_sequence,
             num_rows: value.num_rows,
             size: value.size,
-            time_range: TimeRange::new(time_range.start.into(), time_range.end.into()),
+            time_range: TimeRange::try_new(time_range.start.into(), time_range.end.into())?,
+            level: value.level,
+            inverted_index_size: value.inverted_index_size,
+            tier: pb_types::Tier::try_from(value.tier)
+                .context("unknown tier")?
+                .into(),
+            aggregates: value.aggregates.into_iter().map(Into::into).collect(),
+            min_key: value.min_key,
+            max_key: value.max_key,
+            checksum: value.checksum,
+            null_columns: value.null_columns,
         })
     }
 }
@@ -92,23 +161,101 @@ impl From<FileMeta> for pb_types::SstMeta {
                 start: *value.time_range.start,
                 end: *value.time_range.end,
             }),
+            level: value.level,
+            inverted_index_size: value.inverted_index_size,
+            aggregates: value.aggregates.into_iter().map(Into::into).collect(),
+            tier: pb_types::Tier::from(value.tier).into(),
+            min_key: value.min_key,
+            max_key: value.max_key,
+            checksum: value.checksum,
+            null_columns: value.null_columns,
         }
     }
 }
 
-// Used for sst file id allocation.
-// This number mustn't go backwards on restarts, otherwise file id
-// collisions are possible. So don't change time on the server
-// between server restarts.
-static NEXT_ID: LazyLock<AtomicU64> = LazyLock::new(|| {
-    AtomicU64::new(
-        SystemTime::now()
-            .duration_since(SystemTime::UNIX_EPOCH)
-            .unwrap()
-            .as_nanos() as u64,
-    )
-});
-
-pub fn allocate_id() -> u64 {
-    NEXT_ID.fetch_add(1, Ordering::SeqCst)
+/// Aggregates of a column over all rows of an sst, values are cast to f64, and
+/// booleans are cast to 0 or 1.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ColumnAggregate {
+    pub column: String,
+    /// Number of non-null values.
+    pub count: u64,
+    pub sum: f64,
+    /// `None` if all values are null.
+    pub min: Option<f64>,
+    pub max: Option<f64>,
+}
+
+impl ColumnAggregate {
+    pub fn new(column: String) -> Self {
+        Self {
+            column,
+            count: 0,
+            sum: 0.0,
+            min: None,
+            max: None,
+        }
+    }
+
+    /// Columns of these types are aggregated. Decimals are not since their
+    /// aggregates in f64 are not exact.
+    pub fn supports(data_type: &DataType) -> bool {
+        match data_type {
+            DataType::Boolean => true,
+            DataType::Decimal128(_, _) | DataType::Decimal256(_, _) => false,
+            data_type => data_type.is_numeric(),
+        }
+    }
+
+    /// Add values of `array` to the aggregates.
+    pub fn update(&mut self, array: &ArrayRef) -> crate::Result<()> {
+        let array = cast(array, &DataType::Float64).context("cast values to f64")?;
+        let array = array.as_primitive::<Float64Type>();
+        self.count += (array.len() - array.null_count()) as u64;
+        self.sum += compute::sum(array).unwrap_or_default();
+        self.min = min_of(self.min, compute::min(array));
+        self.max = max_of(self.max, compute::max(array));
+
+        Ok(())
+    }
+}
+
+/// Min of two optional values, `None` is ignored.
+pub fn min_of(a: Option<f64>, b: Option<f64>) -> Option<f64> {
+    match (a, b) {
+        (Some(a), Some(b)) => Some(a.min(b)),
+        (a, b) => a.or(b),
+    }
+}
+
+/// Max of two optional values, `None` is ignored.
+pub fn max_of(a: Option<f64>, b: Option<f64>) -> Option<f64> {
+    match (a, b) {
+        (Some(a), Some(b)) => Some(a.max(b)),
+        (a, b) => a.or(b),
+    }
+}
+
+impl From<pb_types::ColumnAggregate> for ColumnAggregate {
+    fn from(value: pb_types::ColumnAggregate) -> Self {
+        Self {
+            column: value.column,
+            count: value.count,
+            sum: value.sum,
+            min: value.min,
+            max: value.max,
+        }
+    }
+}
+
+impl From<ColumnAggregate> for pb_types::ColumnAggregate {
+    fn from(value: ColumnAggregate) -> Self {
+        pb_types::ColumnAggregate {
+            column: value.column,
+            count: value.count,
+            sum: value.sum,
+            min: value.min,
+            max: value.max,
+        }
+    }
 }
@@ -15,72 +15,614 @@
 // specific language governing permissions and limitations
 // under the License.
 
-use std::{sync::Arc, vec};
+use std::{
+    collections::{hash_map::DefaultHasher, BTreeMap, BTreeSet, HashMap, HashSet},
+    hash::{Hash, Hasher},
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex, RwLock,
+    },
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+    vec,
+};
 
 use anyhow::Context;
 use arrow::{
-    array::{Int64Array, RecordBatch},
-    datatypes::SchemaRef,
+    array::{AsArray, Int64Array, RecordBatch, UInt32Array, UInt64Array},
+    compute::{cast, concat_batches, filter_record_batch, take_record_batch, SortOptions},
+    datatypes::{DataType, Field, Int64Type, Schema, SchemaRef},
+    row::{RowConverter, SortField},
 };
 use async_trait::async_trait;
 use datafusion::{
-    common::DFSchema,
+    common::{DFSchema, ScalarValue},
     datasource::{
         listing::PartitionedFile,
-        physical_plan::{FileScanConfig, ParquetExec},
+        physical_plan::{parquet::ParquetAccessPlan, FileScanConfig, ParquetExec},
+    },
+    execution::{
+        context::ExecutionProps,
+        disk_manager::{DiskManager, DiskManagerConfig},
+        memory_pool::FairSpillPool,
+        object_store::ObjectStoreUrl,
+        runtime_env::RuntimeEnvBuilder,
+        SendableRecordBatchStream, TaskContext,
     },
-    execution::{context::ExecutionProps, object_store::ObjectStoreUrl, SendableRecordBatchStream},
     logical_expr::{utils::conjunction, Expr},
-    physical_expr::{create_physical_expr, LexOrdering},
-    physical_plan::{execute_stream, memory::MemoryExec, sorts::sort::SortExec},
+    physical_expr::{create_physical_expr, expressions::Column, LexOrdering, PhysicalExpr},
+    physical_plan::{
+        display::DisplayableExecutionPlan,
+        execute_stream,
+        filter::FilterExec,
+        memory::{MemoryExec, MemoryStream},
+        projection::ProjectionExec,
+        sorts::{sort::SortExec, sort_preserving_merge::SortPreservingMergeExec},
+        ExecutionPlan,
+    },
     physical_planner::create_physical_sort_exprs,
-    prelude::{ident, SessionContext},
+    prelude::{ident, lit, SessionConfig, SessionContext},
 };
-use futures::StreamExt;
+use futures::{StreamExt, TryStreamExt};
 use macros::ensure;
-use object_store::path::Path;
+use object_store::{path::Path, PutPayload};
 use parquet::{
-    arrow::{async_writer::ParquetObjectWriter, AsyncArrowWriter},
-    file::properties::WriterProperties,
+    arrow::{
+        async_reader::ParquetObjectReader, async_writer::ParquetObjectWriter, AsyncArrowWriter,
+        ParquetRecordBatchStreamBuilder,
+    },
+    basic::Encoding,
+    file::{
+        metadata::{ParquetMetaData, RowGroupMetaData},
+        properties::{EnabledStatistics, WriterProperties},
+        statistics::Statistics,
+    },
     format::SortingColumn,
     schema::types::ColumnPath,
 };
+use prost::Message;
+use tracing::Instrument;
 
 use crate::{
-    manifest::Manifest,
-    read::DefaultParquetFileReaderFactory,
-    sst::{allocate_id, FileId, FileMeta},
-    types::{ObjectStoreRef, TimeRange, Timestamp, WriteOptions, WriteResult},
-    Result,
+    backup::{self, BackupRequest, BackupResult, RestoreResult},
+    bloom::{self, KeyEquality},
+    checksum::{self, ChecksumVerification, ChecksumVerifier, ChecksumWriter},
+    codec::ColumnCodecs,
+    dedup::{self, IngestDedupOptions, IngestDeduper},
+    defaults::ColumnDefaults,
+    error::StorageIoContext,
+    export::{self, ExportRequest, ExportResult},
+    health::{CheckResult, HealthOptions, HealthReport, HealthStatus},
+    inverted_index::{self, InvertedIndexBuilder},
+    limiter::{IoLimiter, IoLimiterRef, LimitedReader, RateLimiter},
+    manifest::{Manifest, ManifestEvent},
+    memory::WriteMemoryControllerRef,
+    multipart::{MultipartCleanOptions, MultipartCleaner, MultipartUploadListerRef},
+    operator::{CoalesceStream, LatestPerSeriesStream, RechunkStream, TracedStream},
+    primary_key::{KeyBounds, KeyRangeBuilder},
+    quota::QuotaManagerRef,
+    read::{DefaultParquetFileReaderFactory, SstSchemaAdapterFactory},
+    result_cache::{CachingStream, RangeVersion, ResultCacheRef},
+    sst::{self, ColumnAggregate, FileId, FileMeta, Level, SstFile, Tier, LEVEL_0, LEVEL_1},
+    tier::{MigrateResult, TieringOptions},
+    tombstone::{self, KeyRange, Tombstone},
+    types::{
+        ColumnOptions, MergeMode, NullKeyPolicy, ObjectStoreRef, TimeOrder, TimeRange, TimeUnit,
+        Timestamp, WriteOptions, WriteResult,
+    },
+    Error, Result,
 };
 
 pub struct WriteRequest {
-    batch: RecordBatch,
+    pub batch: RecordBatch,
 }
 
 pub struct ScanRequest {
-    range: TimeRange,
-    predicate: Vec<Expr>,
+    /// Range in the time unit of the storage.
+    pub range: TimeRange,
+    pub predicate: Vec<Expr>,
     /// `None` means all columns.
-    projections: Option<Vec<usize>>,
+    pub projections: Option<Vec<usize>>,
+    /// Only return the latest N rows of every series when set, series key
+    /// columns must be included in the projections.
+    ///
+    /// Rows are ordered by series keys and then timestamp in the time order of
+    /// the storage in this case, `output_order` is ignored.
+    pub limit_per_series: Option<usize>,
+    pub output_order: OutputOrder,
+    /// Output columns computed from the projected columns, such as
+    /// `value * 8` or `concat(dc, host)`, `None` means the projected columns.
+    ///
+    /// They are evaluated by the engine, so only the results are shipped.
+    /// Not supported with `limit_per_series` yet.
+    pub output_exprs: Option<Vec<Expr>>,
+    /// Memory budget of the scan in bytes, sorting spills to the disk once
+    /// it's exceeded, `None` means unbounded.
+    pub memory_limit: Option<usize>,
+    /// Scan the manifest at this version instead of the latest one, see
+    /// [Manifest::version], so the same scan returns the same rows later.
+    ///
+    /// It fails once the version is expired, or ssts at the version are
+    /// deleted by compactions since then, both are kept within
+    /// [ManifestOptions::history_retention](crate::types::ManifestOptions::history_retention).
+    pub snapshot_version: Option<u64>,
+    /// Rows of every output batch except the last one, tiny batches are
+    /// concatenated and huge ones are split to it, e.g. to frame responses.
+    /// `None` only coalesces tiny batches, see [OutputCoalesceOptions].
+    pub batch_size: Option<usize>,
+}
+
+/// Ordering of rows returned by scan.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum OutputOrder {
+    /// No ordering guarantee, the final sort is skipped, which is suitable for
+    /// aggregation-only consumers.
+    None,
+    /// Ordered by primary keys.
+    #[default]
+    ByKey,
+    /// Ordered by timestamp, and then by the other primary keys.
+    ByTime,
+}
+
+/// Request of a compaction, ssts are picked by the compaction policies of the
+/// storage, i.e. compact-on-read and leveled compaction.
+#[derive(Clone, Debug, Default)]
+pub struct CompactRequest {
+    /// Only ssts overlapping with the range are compacted, `None` means all.
+    pub range: Option<TimeRange>,
+    /// Steps whose inputs would exceed it in total are skipped.
+    pub max_input_bytes: Option<u64>,
+    /// Max bytes read and written per second.
+    pub io_rate_limit: Option<u64>,
+    pub cancel: Option<CancellationToken>,
+}
+
+/// Token to cancel a compaction, which stops before its next step, so the
+/// steps already done are kept.
+#[derive(Clone, Debug, Default)]
+pub struct CancellationToken(Arc<AtomicBool>);
+
+impl CancellationToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Release);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Acquire)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AggregateFunction {
+    Count,
+    Sum,
+    Min,
+    Max,
+}
+
+/// Aggregate of a numeric value column pushed down to the storage, see
+/// [TimeMergeStorage::aggregate].
+pub struct AggregateRequest {
+    pub column: String,
+    pub function: AggregateFunction,
+    /// Range in the time unit of the storage.
+    pub range: TimeRange,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum AggregateResult {
+    /// Answered by the metadata of ssts, `None` means null.
+    Exact(Option<f64>),
+    /// Ssts must be scanned for the value, which is within the bounds if they
+    /// are known.
+    Bounded {
+        lower: Option<f64>,
+        upper: Option<f64>,
+    },
+}
+
+/// Delete rows in the time range whose leading series key is in the key
+/// range, see [tombstone](crate::tombstone).
+pub struct DeleteRequest {
+    /// Range in the time unit of the storage.
+    pub range: TimeRange,
+    pub key_range: KeyRange,
+}
+
+/// Stats of a scan.
+///
+/// Stats collected during execution are complete only after the stream is
+/// drained.
+pub struct ScanStats {
+    /// Ssts read by the scan.
+    pub files_touched: usize,
+    /// Ssts pruned by the scan range.
+    pub files_pruned: usize,
+    /// Number of sorted runs to merge, every L0 sst is a run, and all ssts of
+    /// another level are one run.
+    pub sorted_runs: usize,
+    /// Ssts skipped by the min and max series keys recorded in the manifest.
+    pub files_pruned_by_key_range: usize,
+    /// Ssts skipped by the bloom filters of primary keys before planning.
+    pub files_pruned_by_bloom_filter: usize,
+    /// Ssts skipped by their inverted indexes of tags before planning.
+    pub files_pruned_by_inverted_index: usize,
+    row_groups_pruned_by_bloom_filter: usize,
+    row_groups_pruned_by_inverted_index: usize,
+    plan: Arc<dyn ExecutionPlan>,
+    parquet_exec: Arc<ParquetExec>,
+}
+
+impl ScanStats {
+    fn metric(&self, name: &str) -> usize {
+        self.parquet_exec
+            .metrics()
+            .and_then(|metrics| metrics.sum_by_name(name))
+            .map(|v| v.as_usize())
+            .unwrap_or(0)
+    }
+
+    /// Row groups skipped by statistics, bloom filters or inverted indexes.
+    pub fn row_groups_skipped(&self) -> usize {
+        self.row_groups_pruned_by_bloom_filter
+            + self.row_groups_pruned_by_inverted_index
+            + self.metric("row_groups_pruned_statistics")
+            + self.metric("row_groups_pruned_bloom_filter")
+    }
+
+    /// Rows skipped by the page index.
+    pub fn rows_pruned_by_page_index(&self) -> usize {
+        self.metric("page_index_rows_filtered")
+    }
+
+    pub fn bytes_read(&self) -> usize {
+        self.metric("bytes_scanned")
+    }
+
+    /// Text of the physical plan with metrics, like `EXPLAIN ANALYZE`.
+    pub fn plan(&self) -> String {
+        DisplayableExecutionPlan::with_metrics(self.plan.as_ref())
+            .indent(true)
+            .to_string()
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct CompactionReport {
+    pub input_files: Vec<FileId>,
+    pub output_files: Vec<FileId>,
+    pub bytes_read: u64,
+    pub bytes_written: u64,
+    pub duration: Duration,
+    /// Rows dropped by dedup or TTL.
+    pub rows_dropped: u64,
+    /// The compaction is cancelled before all its steps are done.
+    pub cancelled: bool,
+}
+
+impl CompactionReport {
+    fn merge(&mut self, other: CompactionReport) {
+        self.input_files.extend(other.input_files);
+        self.output_files.extend(other.output_files);
+        self.bytes_read += other.bytes_read;
+        self.bytes_written += other.bytes_written;
+        self.duration += other.duration;
+        self.rows_dropped += other.rows_dropped;
+        self.cancelled |= other.cancelled;
+    }
+
+    /// Number of input ssts merged.
+    pub fn files_merged(&self) -> usize {
+        self.input_files.len()
+    }
+
+    /// Bytes written per byte read, 0 when nothing is read.
+    pub fn write_amplification(&self) -> f64 {
+        if self.bytes_read == 0 {
+            return 0.0;
+        }
+        self.bytes_written as f64 / self.bytes_read as f64
+    }
+}
+
+/// Limits of a compaction from its [CompactRequest], checked before every
+/// step of it.
+struct CompactControl {
+    range: Option<TimeRange>,
+    max_input_bytes: Option<u64>,
+    input_bytes: u64,
+    rate_limiter: Option<RateLimiter>,
+    cancel: Option<CancellationToken>,
+}
+
+impl CompactControl {
+    fn new(req: CompactRequest) -> Self {
+        Self {
+            range: req.range,
+            max_input_bytes: req.max_input_bytes,
+            input_bytes: 0,
+            rate_limiter: req.io_rate_limit.map(RateLimiter::new),
+            cancel: req.cancel,
+        }
+    }
+
+    fn overlaps(&self, range: &TimeRange) -> bool {
+        self.range.as_ref().is_none_or(|r| r.overlaps(range))
+    }
+
+    fn is_cancelled(&self) -> bool {
+        self.cancel.as_ref().is_some_and(|c| c.is_cancelled())
+    }
+
+    /// Returns whether the step reading `files` may run, it waits for the rate
+    /// limit if so.
+    async fn admit(&mut self, files: &[SstFile]) -> bool {
+        if self.is_cancelled() {
+            return false;
+        }
+        let bytes: u64 = files.iter().map(|f| f.meta.size as u64).sum();
+        if self
+            .max_input_bytes
+            .is_some_and(|max| self.input_bytes + bytes > max)
+        {
+            return false;
+        }
+
+        self.input_bytes += bytes;
+        if let Some(limiter) = &mut self.rate_limiter {
+            limiter.acquire(bytes).await;
+        }
+        true
+    }
+
+    /// Account bytes written by the step just done.
+    fn done(&mut self, report: &CompactionReport) {
+        if let Some(limiter) = &mut self.rate_limiter {
+            limiter.consume(report.bytes_written);
+        }
+    }
+}
+
+/// Options of compact-on-read.
+///
+/// When a scan touches more than `max_small_files` small ssts in one time
+/// bucket, they are merged inline if they are small enough in total, or
+/// scheduled to the next [TimeMergeStorage::compact] otherwise, so read
+/// amplification is bounded.
+#[derive(Debug, Clone)]
+pub struct CompactOnReadOptions {
+    /// Ssts smaller than this are considered small.
+    pub small_file_size: usize,
+    pub max_small_files: usize,
+    /// Ssts are grouped into buckets by their start time.
+    pub bucket_duration: Duration,
+    /// Buckets whose small ssts exceed this size in total are not merged
+    /// inline.
+    pub inline_max_bytes: usize,
+}
+
+impl Default for CompactOnReadOptions {
+    fn default() -> Self {
+        Self {
+            small_file_size: 4 * 1024 * 1024,
+            max_small_files: 8,
+            bucket_duration: Duration::from_secs(3600),
+            inline_max_bytes: 16 * 1024 * 1024,
+        }
+    }
+}
+
+/// Options of coalescing tiny batches of the scan output, see
+/// [CloudObjectStorage::with_output_coalesce].
+#[derive(Debug, Clone)]
+pub struct OutputCoalesceOptions {
+    pub target_rows: usize,
+    pub target_bytes: usize,
+}
+
+impl Default for OutputCoalesceOptions {
+    fn default() -> Self {
+        Self {
+            target_rows: 8192,
+            target_bytes: 8 * 1024 * 1024,
+        }
+    }
+}
+
+/// Max level supported by leveled compaction.
+const MAX_LEVEL: Level = 7;
+
+/// Options of leveled compaction.
+///
+/// Fresh ssts, including those of late-arriving data, land in L0 and overlap
+/// with each other. Compaction rewrites them into L1 ssts of non-overlapping
+/// time slices, so a scan merges L0 ssts and one sorted run per level.
+///
+/// Once the ssts of a level below `max_level` exceed the target size of the
+/// level, its oldest slices are pushed down into the next level. The target
+/// size of L1 is `base_level_bytes`, and that of every next level is
+/// multiplied by `level_size_multipliers[level - 1]`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LeveledCompactionOptions {
+    pub slice_duration: Duration,
+    pub max_level: Level,
+    pub base_level_bytes: u64,
+    /// The last multiplier applies to all deeper levels.
+    pub level_size_multipliers: Vec<f64>,
+    /// Max size of an sst of L1, L2 and so on, the last one applies to all
+    /// deeper levels.
+    pub max_file_bytes: Vec<u64>,
+}
+
+impl Default for LeveledCompactionOptions {
+    fn default() -> Self {
+        Self {
+            slice_duration: Duration::from_secs(3600),
+            max_level: LEVEL_1,
+            base_level_bytes: 256 * 1024 * 1024,
+            level_size_multipliers: vec![10.0],
+            max_file_bytes: vec![64 * 1024 * 1024],
+        }
+    }
+}
+
+impl LeveledCompactionOptions {
+    pub fn validate(&self) -> Result<()> {
+        ensure!(
+            !self.slice_duration.is_zero(),
+            "slice duration must be positive"
+        );
+        ensure!(
+            (LEVEL_1..=MAX_LEVEL).contains(&self.max_level),
+            "max level must be in [{LEVEL_1}, {MAX_LEVEL}], value:{}",
+            self.max_level
+        );
+        ensure!(
+            self.base_level_bytes > 0,
+            "base level bytes must be positive"
+        );
+        ensure!(
+            self.max_level == LEVEL_1 || !self.level_size_multipliers.is_empty(),
+            "level size multipliers are required for more than one level"
+        );
+        ensure!(
+            self.level_size_multipliers
+                .iter()
+                .all(|v| v.is_finite() && *v > 1.0),
+            "level size multipliers must be greater than 1, value:{:?}",
+            self.level_size_multipliers
+        );
+        ensure!(
+            !self.max_file_bytes.is_empty(),
+            "max file bytes are required"
+        );
+        // Sizes of ssts are recorded in u32.
+        ensure!(
+            self.max_file_bytes
+                .iter()
+                .all(|v| *v > 0 && *v <= u32::MAX as u64),
+            "max file bytes must be in [1, {}], value:{:?}",
+            u32::MAX,
+            self.max_file_bytes
+        );
+
+        Ok(())
+    }
+
+    /// Target size of all ssts of `level`, which is at least L1.
+    fn target_bytes(&self, level: Level) -> u64 {
+        (LEVEL_1..level).fold(self.base_level_bytes as f64, |bytes, l| {
+            bytes * Self::nth_or_last(&self.level_size_multipliers, l).unwrap_or(1.0)
+        }) as u64
+    }
+
+    /// Max size of an sst of `level`, which is at least L1.
+    fn max_file_bytes(&self, level: Level) -> u64 {
+        Self::nth_or_last(&self.max_file_bytes, level).unwrap_or(u64::MAX)
+    }
+
+    /// Value for `level` in a list starting from L1.
+    fn nth_or_last<T: Copy>(values: &[T], level: Level) -> Option<T> {
+        let idx = (level as usize).saturating_sub(1);
+        values.get(idx).or(values.last()).copied()
+    }
+}
+
+impl From<pb_types::LeveledCompactionOptions> for LeveledCompactionOptions {
+    fn from(value: pb_types::LeveledCompactionOptions) -> Self {
+        Self {
+            slice_duration: Duration::from_millis(value.slice_duration_ms),
+            max_level: value.max_level,
+            base_level_bytes: value.base_level_bytes,
+            level_size_multipliers: value.level_size_multipliers,
+            max_file_bytes: value.max_file_bytes,
+        }
+    }
+}
+
+impl From<LeveledCompactionOptions> for pb_types::LeveledCompactionOptions {
+    fn from(value: LeveledCompactionOptions) -> Self {
+        pb_types::LeveledCompactionOptions {
+            slice_duration_ms: value.slice_duration.as_millis() as u64,
+            max_level: value.max_level,
+            base_level_bytes: value.base_level_bytes,
+            level_size_multipliers: value.level_size_multipliers,
+            max_file_bytes: value.max_file_bytes,
+        }
+    }
+}
+
+pub struct ImportRequest {
+    /// Paths of the parquet files to import, they must be in the same object
+    /// store as the storage.
+    pub paths: Vec<Path>,
 }
 
-pub struct CompactRequest {}
+#[derive(Debug, Default)]
+pub struct ImportResult {
+    /// Imported files and their allocated ids.
+    pub files: Vec<(Path, FileId)>,
+    /// Number of files which are re-sorted since they are not sorted by
+    /// primary keys.
+    pub num_resorted: usize,
+}
 
 /// Time-aware merge storage interface.
 #[async_trait]
 pub trait TimeMergeStorage {
     fn schema(&self) -> &SchemaRef;
 
+    /// Rows are durable and visible to scans once it returns.
+    ///
+    /// Written batches are not buffered in memtables but encoded into ssts
+    /// directly, so there is no flush policy by size or time yet, one should
+    /// be added along with memtables to bound the delay of buffered rows.
+    ///
+    /// Rows are upserted in merge-on-write mode, and appended otherwise.
     async fn write(&self, req: WriteRequest) -> Result<()>;
 
-    /// Implementation shoule ensure that the returned stream is sorted by time,
-    /// from old to latest.
+    /// Write rows with last-write-wins semantics, a row replaces the rows of
+    /// the same primary keys and timestamp written before, and the last one
+    /// wins among those of the same batch. Only storages in merge-on-write
+    /// mode support it.
+    ///
+    /// Rows are tagged with the sequence of the write internally, so newer
+    /// rows still win after any ssts are compacted.
+    async fn upsert(&self, req: WriteRequest) -> Result<()>;
+
+    /// Write immutable rows, e.g. measurements, which are never deduplicated
+    /// when written, so callers should never append the same keys twice.
+    async fn append(&self, req: WriteRequest) -> Result<()>;
+
+    /// Implementation should ensure that the returned stream is sorted as
+    /// `req.output_order` requires.
     async fn scan(&self, req: ScanRequest) -> Result<SendableRecordBatchStream>;
 
-    async fn compact(&self, req: CompactRequest) -> Result<()>;
+    async fn compact(&self, req: CompactRequest) -> Result<CompactionReport>;
+
+    /// Delete rows written before, which are invisible once it returns.
+    async fn delete(&self, req: DeleteRequest) -> Result<()>;
+
+    /// Answer the aggregate by the metadata of ssts without scanning them if
+    /// possible, otherwise the bounds of it are returned.
+    async fn aggregate(&self, req: AggregateRequest) -> Result<AggregateResult>;
 }
 
+/// Name of the partition column holding the max sequence of the sst rows come
+/// from, it's only read when tombstones are applied.
+const SEQUENCE_COLUMN: &str = "__sequence";
+
+/// Name of the column holding the sequence of the write every row comes from,
+/// which is appended to the rows of ssts in merge-on-write mode, and is never
+/// returned by scans. Ssts written before it lack the column, and their rows
+/// take the max sequence of the ssts.
+const ROW_SEQUENCE_COLUMN: &str = "__row_sequence";
+
 /// `TimeMergeStorage` implementation using cloud object storage.
 pub struct CloudObjectStorage {
     path: String,
@@ -88,10 +630,70 @@ pub struct CloudObjectStorage {
     arrow_schema: SchemaRef,
     num_primary_key: usize,
     timestamp_index: usize,
+    /// Series key columns written with bloom filters.
+    bloom_filter_keys: Vec<usize>,
+    /// Whether any column is written with bloom filters, the predicate is
+    /// checked against them during scan if so.
+    bloom_filter_on_read: bool,
+    /// Series key columns indexed by the inverted indexes of ssts.
+    inverted_index_keys: Vec<usize>,
+    /// Numeric value columns aggregated in the metadata of ssts.
+    aggregate_columns: Vec<usize>,
     manifest: Manifest,
 
     df_schema: DFSchema,
     write_props: WriterProperties,
+    time_unit: TimeUnit,
+    target_row_group_bytes: Option<usize>,
+    enable_page_index: bool,
+    time_order: TimeOrder,
+    null_keys: NullKeyPolicy,
+    /// Tenant of the storage and the quota manager it's charged to.
+    quota: Option<(String, QuotaManagerRef)>,
+    compact_on_read: Option<CompactOnReadOptions>,
+    /// Buckets scheduled to be compacted by compact-on-read.
+    pending_compactions: Mutex<BTreeSet<i64>>,
+    /// Options set by [CloudObjectStorage::set_leveled_compaction] are
+    /// persisted in the manifest.
+    leveled_compaction: RwLock<Option<LeveledCompactionOptions>>,
+    /// Limits on reading ssts, shared by storages of the node.
+    io_limiter: IoLimiterRef,
+    /// Aborts uploads of ssts left behind by crashed writers.
+    multipart_cleaner: Option<MultipartCleaner>,
+    /// Drops rows ingested recently, e.g. by retries of exporters.
+    ingest_deduper: Option<IngestDeduper>,
+    /// Memory budget of writes, shared by storages of the node.
+    write_memory: Option<WriteMemoryControllerRef>,
+    /// Where scans exceeding their memory limits spill to.
+    disk_manager: Arc<DiskManager>,
+    output_coalesce: OutputCoalesceOptions,
+    merge_mode: MergeMode,
+    codecs: Arc<ColumnCodecs>,
+    /// Values of columns missing from ssts written before they are added.
+    column_defaults: Arc<ColumnDefaults>,
+    /// Schema of rows in ssts, which is the table schema with
+    /// [ROW_SEQUENCE_COLUMN] appended in merge-on-write mode.
+    sst_schema: SchemaRef,
+    /// Encoded `sst_schema`, see [ColumnCodecs::encoded_schema].
+    file_schema: SchemaRef,
+    tiering: Option<TieringOptions>,
+    /// Duration of flush segments in the time unit, see
+    /// [WriteOptions::flush_segment].
+    flush_segment: Option<i64>,
+    /// Number of sub-shards flushed concurrently, see
+    /// [WriteOptions::write_shards].
+    write_shards: usize,
+    sparse_columns: bool,
+    result_cache: Option<ResultCacheRef>,
+    health_options: HealthOptions,
+    /// Held when ssts are removed from or switched to another tier in the
+    /// manifest, so migrated ssts are never added back after removed.
+    tier_lock: tokio::sync::Mutex<()>,
+    /// Held by every compaction until its outputs are committed, so ssts are
+    /// never compacted twice.
+    compaction_lock: tokio::sync::Mutex<()>,
+    /// Verifies checksums of ssts before they are read.
+    checksum_verifier: ChecksumVerifier,
 }
 
 /// It will organize the data in the following way:
@@ -114,218 +716,4825 @@ impl CloudObjectStorage {
         write_options: WriteOptions,
     ) -> Result<Self> {
         let manifest_prefix = crate::manifest::PREFIX_PATH;
-        let manifest =
-            Manifest::try_new(format!("{root_path}/{manifest_prefix}"), store.clone()).await?;
+        let manifest = Manifest::try_new(
+            format!("{root_path}/{manifest_prefix}"),
+            store.clone(),
+            write_options.manifest.clone(),
+        )
+        .await?;
+        Self::try_new_with_manifest(
+            manifest,
+            root_path,
+            store,
+            arrow_schema,
+            num_primary_key,
+            timestamp_index,
+            write_options,
+        )
+        .await
+    }
+
+    /// Open a read replica of the storage at `root_path`, which never writes
+    /// to `store`, and serves scans from the manifest reloaded periodically,
+    /// see [ManifestOptions](crate::types::ManifestOptions).
+    ///
+    /// Ssts kept in the local tier of the writer are not readable by replicas,
+    /// see [crate::tier].
+    pub async fn open_replica(
+        root_path: String,
+        store: ObjectStoreRef,
+        arrow_schema: SchemaRef,
+        num_primary_key: usize,
+        timestamp_index: usize,
+        write_options: WriteOptions,
+    ) -> Result<Self> {
+        // Scans in merge-on-write mode merge overlapping ssts first.
+        ensure!(
+            write_options.merge_mode == MergeMode::Append,
+            "read replicas only support the append mode"
+        );
+        let manifest_prefix = crate::manifest::PREFIX_PATH;
+        let manifest = Manifest::open_read_only(
+            format!("{root_path}/{manifest_prefix}"),
+            store.clone(),
+            write_options.manifest.refresh_interval,
+        )
+        .await?;
+        Self::try_new_with_manifest(
+            manifest,
+            root_path,
+            store,
+            arrow_schema,
+            num_primary_key,
+            timestamp_index,
+            write_options,
+        )
+        .await
+    }
+
+    async fn try_new_with_manifest(
+        manifest: Manifest,
+        root_path: String,
+        store: ObjectStoreRef,
+        arrow_schema: SchemaRef,
+        num_primary_key: usize,
+        timestamp_index: usize,
+        write_options: WriteOptions,
+    ) -> Result<Self> {
         let df_schema = DFSchema::try_from(arrow_schema.clone()).context("build DFSchema")?;
-        let write_props = Self::build_write_props(write_options, num_primary_key);
+        let time_unit = write_options.time_unit;
+        let target_row_group_bytes = write_options.target_row_group_bytes;
+        let enable_page_index = write_options.enable_page_index;
+        let time_order = write_options.time_order;
+        let null_keys = write_options.null_keys;
+        let merge_mode = write_options.merge_mode;
+        let write_shards = write_options.write_shards.max(1);
+        let sparse_columns = write_options.sparse_columns;
+        let flush_segment = write_options.flush_segment.map(|d| {
+            TimeUnit::Nanosecond
+                .convert(d.as_nanos() as i64, time_unit)
+                .max(1)
+        });
+        let mut codecs = HashMap::new();
+        for (name, opts) in write_options.column_options.iter().flatten() {
+            let Some(codec) = opts.codec else {
+                continue;
+            };
+            let (index, field) = arrow_schema
+                .column_with_name(name)
+                .with_context(|| format!("column {name} of codec {codec:?} not found"))?;
+            // Ssts are sorted and pruned by the primary keys, so they are kept
+            // as they are.
+            ensure!(
+                index >= num_primary_key,
+                "primary key {name} can't be encoded by {codec:?}"
+            );
+            ensure!(
+                codec.supports(field.data_type()),
+                "codec {codec:?} doesn't support column {name} of type {}",
+                field.data_type()
+            );
+            codecs.insert(name.clone(), codec);
+        }
+        let codecs = Arc::new(ColumnCodecs::new(codecs, write_options.max_row_group_size));
+        let column_defaults = Arc::new(ColumnDefaults::try_new(&arrow_schema)?);
+        let sst_schema = if write_options.merge_mode == MergeMode::MergeOnWrite {
+            let mut fields = arrow_schema.fields().to_vec();
+            fields.push(Arc::new(Field::new(
+                ROW_SEQUENCE_COLUMN,
+                DataType::UInt64,
+                false,
+            )));
+            Arc::new(Schema::new_with_metadata(
+                fields,
+                arrow_schema.metadata().clone(),
+            ))
+        } else {
+            arrow_schema.clone()
+        };
+        let file_schema = codecs.encoded_schema(&sst_schema);
+        let keys_enabled_by = |enabled: fn(&ColumnOptions) -> Option<bool>, default: bool| {
+            (0..num_primary_key)
+                .filter(|i| *i != timestamp_index)
+                .filter(|i| {
+                    let name = arrow_schema.field(*i).name();
+                    write_options
+                        .column_options
+                        .as_ref()
+                        .and_then(|opts| opts.get(name))
+                        .and_then(enabled)
+                        .unwrap_or(default)
+                })
+                .collect::<Vec<_>>()
+        };
+        let bloom_filter_keys = keys_enabled_by(
+            |opts| opts.enable_bloom_filter,
+            write_options.enable_bloom_filter,
+        );
+        let inverted_index_keys = keys_enabled_by(
+            |opts| opts.enable_inverted_index,
+            write_options.enable_inverted_index,
+        );
+        let bloom_filter_on_read = write_options.enable_bloom_filter
+            || write_options
+                .column_options
+                .iter()
+                .flat_map(|opts| opts.values())
+                .any(|opts| opts.enable_bloom_filter == Some(true));
+        let aggregate_columns = (num_primary_key..arrow_schema.fields().len())
+            .filter(|i| ColumnAggregate::supports(arrow_schema.field(*i).data_type()))
+            .collect();
+        let write_props = Self::build_write_props(
+            write_options,
+            &arrow_schema,
+            num_primary_key,
+            timestamp_index,
+        );
+        let leveled_compaction = RwLock::new(manifest.leveled_compaction().await);
+        let disk_manager =
+            DiskManager::try_new(DiskManagerConfig::NewOs).context("create disk manager")?;
         Ok(Self {
             path: root_path,
             num_primary_key,
             timestamp_index,
+            bloom_filter_keys,
+            bloom_filter_on_read,
+            inverted_index_keys,
+            aggregate_columns,
             store,
             arrow_schema,
             manifest,
             df_schema,
             write_props,
+            time_unit,
+            target_row_group_bytes,
+            enable_page_index,
+            time_order,
+            null_keys,
+            quota: None,
+            compact_on_read: None,
+            pending_compactions: Mutex::new(BTreeSet::new()),
+            leveled_compaction,
+            io_limiter: Arc::new(IoLimiter::default()),
+            multipart_cleaner: None,
+            ingest_deduper: None,
+            write_memory: None,
+            disk_manager,
+            output_coalesce: OutputCoalesceOptions::default(),
+            merge_mode,
+            codecs,
+            column_defaults,
+            sst_schema,
+            file_schema,
+            tiering: None,
+            flush_segment,
+            write_shards,
+            sparse_columns,
+            result_cache: None,
+            health_options: HealthOptions::default(),
+            tier_lock: tokio::sync::Mutex::new(()),
+            compaction_lock: tokio::sync::Mutex::new(()),
+            checksum_verifier: ChecksumVerifier::default(),
         })
     }
 
-    fn build_file_path(&self, id: FileId) -> String {
-        let root = &self.path;
-        let prefix = crate::sst::PREFIX_PATH;
-        format!("{root}/{prefix}/{id}")
+    pub fn time_unit(&self) -> TimeUnit {
+        self.time_unit
     }
 
-    async fn write_batch(&self, req: WriteRequest) -> Result<WriteResult> {
-        let file_id = allocate_id();
-        let file_path = self.build_file_path(file_id);
-        let file_path = Path::from(file_path);
-        let object_store_writer = ParquetObjectWriter::new(self.store.clone(), file_path.clone());
-        let mut writer = AsyncArrowWriter::try_new(
-            object_store_writer,
-            self.schema().clone(),
-            Some(self.write_props.clone()),
-        )
-        .context("create arrow writer")?;
+    pub fn is_replica(&self) -> bool {
+        self.manifest.is_read_only()
+    }
 
-        // sort record batch
-        let mut batches = self.sort_batch(req.batch).await?;
-        while let Some(batch) = batches.next().await {
-            let batch = batch.context("get sorted batch")?;
-            writer.write(&batch).await.context("write arrow batch")?;
-        }
-        writer.close().await.context("close arrow writer")?;
-        let object_meta = self
-            .store
-            .head(&file_path)
-            .await
-            .context("get object meta")?;
+    /// Reload the manifest of the replica now, instead of waiting for the next
+    /// refresh.
+    pub async fn refresh_manifest(&self) -> Result<()> {
+        self.manifest.refresh().await
+    }
 
-        Ok(WriteResult {
-            id: file_id,
-            size: object_meta.size,
-        })
+    /// Subscribe to the changes of ssts committed from now on, see
+    /// [Manifest::subscribe].
+    pub fn subscribe_manifest(&self) -> tokio::sync::broadcast::Receiver<ManifestEvent> {
+        self.manifest.subscribe()
     }
 
-    fn build_sort_exprs(&self) -> Result<LexOrdering> {
-        let sort_exprs = (0..self.num_primary_key)
-            .map(|i| {
-                ident(self.schema().field(i).name())
-                    .sort(true /* asc */, true /* nulls_first */)
-            })
-            .collect::<Vec<_>>();
-        let sort_exprs =
-            create_physical_sort_exprs(&sort_exprs, &self.df_schema, &ExecutionProps::default())
-                .context("create physical sort exprs")?;
+    pub(crate) fn root_path(&self) -> &str {
+        &self.path
+    }
 
-        Ok(sort_exprs)
+    pub(crate) fn manifest(&self) -> &Manifest {
+        &self.manifest
     }
 
-    async fn sort_batch(&self, batch: RecordBatch) -> Result<SendableRecordBatchStream> {
-        let ctx = SessionContext::default();
-        let schema = batch.schema();
-        let sort_exprs = self.build_sort_exprs()?;
-        let batch_plan =
-            MemoryExec::try_new(&[vec![batch]], schema, None).context("build batch plan")?;
-        let physical_plan = Arc::new(SortExec::new(sort_exprs, Arc::new(batch_plan)));
+    fn ensure_writable(&self) -> Result<()> {
+        ensure!(!self.is_replica(), "read replicas are never written");
+        Ok(())
+    }
 
-        let res =
-            execute_stream(physical_plan, ctx.task_ctx()).context("execute sort physical plan")?;
-        Ok(res)
+    pub fn with_compact_on_read(mut self, options: CompactOnReadOptions) -> Self {
+        self.compact_on_read = Some(options);
+        self
     }
 
-    fn build_write_props(write_options: WriteOptions, num_primary_key: usize) -> WriterProperties {
-        let sorting_columns = write_options.enable_sorting_columns.then(|| {
-            (0..num_primary_key)
-                .map(|i| {
-                    SortingColumn::new(i as i32, false /* desc */, true /* nulls_first */)
-                })
-                .collect::<Vec<_>>()
-        });
+    /// Compact ssts level by level on [TimeMergeStorage::compact].
+    ///
+    /// Options persisted by [Self::set_leveled_compaction] take precedence
+    /// over `options`.
+    pub fn with_leveled_compaction(mut self, options: LeveledCompactionOptions) -> Result<Self> {
+        options.validate()?;
+        self.leveled_compaction
+            .get_mut()
+            .unwrap()
+            .get_or_insert(options);
+        Ok(self)
+    }
 
-        let mut builder = WriterProperties::builder()
-            .set_max_row_group_size(write_options.max_row_group_size)
-            .set_write_batch_size(write_options.write_bacth_size)
-            .set_sorting_columns(sorting_columns)
-            .set_dictionary_enabled(write_options.enable_dict)
-            .set_bloom_filter_enabled(write_options.enable_bloom_filter)
-            .set_encoding(write_options.encoding)
-            .set_compression(write_options.compression);
+    /// Tune leveled compaction of this storage, the options are persisted and
+    /// used by the following compactions, even after restarts.
+    pub async fn set_leveled_compaction(&self, options: LeveledCompactionOptions) -> Result<()> {
+        options.validate()?;
+        self.manifest
+            .set_leveled_compaction(options.clone())
+            .await?;
+        *self.leveled_compaction.write().unwrap() = Some(options);
 
-        if write_options.column_options.is_none() {
-            return builder.build();
-        }
+        Ok(())
+    }
 
-        for (col_name, col_opt) in write_options.column_options.unwrap() {
-            let col_path = ColumnPath::new(vec![col_name.to_string()]);
-            if let Some(enable_dict) = col_opt.enable_dict {
-                builder = builder.set_column_dictionary_enabled(col_path.clone(), enable_dict);
-            }
-            if let Some(enable_bloom_filter) = col_opt.enable_bloom_filter {
-                builder =
-                    builder.set_column_bloom_filter_enabled(col_path.clone(), enable_bloom_filter);
-            }
-            if let Some(encoding) = col_opt.encoding {
-                builder = builder.set_column_encoding(col_path.clone(), encoding);
-            }
-            if let Some(compression) = col_opt.compression {
-                builder = builder.set_column_compression(col_path, compression);
-            }
-        }
+    pub fn leveled_compaction(&self) -> Option<LeveledCompactionOptions> {
+        self.leveled_compaction.read().unwrap().clone()
+    }
 
-        builder.build()
+    /// Limit reading of ssts by `io_limiter`, which is usually shared by all
+    /// storages of the node.
+    pub fn with_io_limiter(mut self, io_limiter: IoLimiterRef) -> Self {
+        self.io_limiter = io_limiter;
+        self
     }
+
+    /// Reserve memory of writes from `controller`, which is usually shared by
+    /// all storages of the node.
+    pub fn with_write_memory(mut self, controller: WriteMemoryControllerRef) -> Self {
+        self.write_memory = Some(controller);
+        self
+    }
+
+    /// Spill sorting of scans exceeding their memory limits to `dirs`
+    /// instead of the temporary directory of the OS.
+    pub fn with_spill_dirs(mut self, dirs: Vec<PathBuf>) -> Result<Self> {
+        self.disk_manager = DiskManager::try_new(DiskManagerConfig::NewSpecified(dirs))
+            .context("create disk manager")?;
+        Ok(self)
+    }
+
+    /// Write fresh ssts to the local store of `options`, which are migrated to
+    /// the object store later, see [crate::tier].
+    pub fn with_tiering(mut self, options: TieringOptions) -> Self {
+        self.tiering = Some(options);
+        self
+    }
+
+    /// Merge tiny batches of the scan output into batches of the target size
+    /// before they are returned, which is enabled with the default options.
+    pub fn with_output_coalesce(mut self, options: OutputCoalesceOptions) -> Self {
+        self.output_coalesce = options;
+        self
+    }
+
+    /// Cache small results of scans, which may be shared by storages.
+    pub fn with_result_cache(mut self, cache: ResultCacheRef) -> Self {
+        self.result_cache = Some(cache);
+        self
+    }
+
+    /// Thresholds of [CloudObjectStorage::health].
+    pub fn with_health_options(mut self, options: HealthOptions) -> Self {
+        self.health_options = options;
+        self
+    }
+
+    /// Verify checksums of ssts before reading them by scans and compactions,
+    /// ssts failing the verification are reported as [Error::Corruption].
+    pub fn with_checksum_verification(mut self, mode: ChecksumVerification) -> Self {
+        self.checksum_verifier = ChecksumVerifier::new(mode);
+        self
+    }
+
+    /// Clean incomplete multipart uploads under the data prefix at startup
+    /// and then periodically, the backend must be able to list them.
+    pub fn with_multipart_cleaner(
+        mut self,
+        lister: MultipartUploadListerRef,
+        options: MultipartCleanOptions,
+    ) -> Self {
+        let prefix = Path::from(format!("{}/{}", self.path, crate::sst::PREFIX_PATH));
+        self.multipart_cleaner = Some(MultipartCleaner::start(lister, prefix, options));
+        self
+    }
+
+    pub fn multipart_cleaner(&self) -> Option<&MultipartCleaner> {
+        self.multipart_cleaner.as_ref()
+    }
+
+    /// Drop written rows whose primary keys and timestamp equal those of rows
+    /// ingested recently.
+    pub fn with_ingest_dedup(mut self, options: IngestDedupOptions) -> Self {
+        self.ingest_deduper = Some(IngestDeduper::new(self.dedup_key_indices(), options));
+        self
+    }
+
+    /// The primary key columns, and the timestamp column if it's not in the
+    /// primary key.
+    fn dedup_key_indices(&self) -> Vec<usize> {
+        let mut columns = (0..self.num_primary_key).collect::<Vec<_>>();
+        if self.timestamp_index >= self.num_primary_key {
+            columns.push(self.timestamp_index);
+        }
+        columns
+    }
+
+    /// Charge writes of this storage to `tenant`, existing ssts are counted
+    /// as its storage usage.
+    pub async fn with_quota(mut self, tenant: String, manager: QuotaManagerRef) -> Self {
+        let storage_bytes = self
+            .manifest
+            .all_ssts()
+            .await
+            .iter()
+            .map(|f| f.meta.size as u64)
+            .sum();
+        manager.adjust_storage(&tenant, storage_bytes, 0);
+        self.quota = Some((tenant, manager));
+        self
+    }
+
+    fn build_file_path(&self, id: FileId) -> String {
+        let root = &self.path;
+        let prefix = crate::sst::PREFIX_PATH;
+        format!("{root}/{prefix}/{id}")
+    }
+
+    fn build_index_path(&self, id: FileId) -> String {
+        let root = &self.path;
+        let prefix = crate::sst::INDEX_PREFIX_PATH;
+        format!("{root}/{prefix}/{id}")
+    }
+
+    /// Split `batch` at the boundaries of flush segments, rows keep their
+    /// order in every split.
+    fn split_by_segment(&self, batch: RecordBatch) -> Result<Vec<RecordBatch>> {
+        let Some(segment) = self.flush_segment else {
+            return Ok(vec![batch]);
+        };
+        let timestamps = batch
+            .column(self.timestamp_index)
+            .as_primitive::<Int64Type>()
+            .values();
+        let mut rows_of_segments: BTreeMap<i64, Vec<u32>> = BTreeMap::new();
+        for (row, ts) in timestamps.iter().enumerate() {
+            rows_of_segments
+                .entry(ts.div_euclid(segment))
+                .or_default()
+                .push(row as u32);
+        }
+        if rows_of_segments.len() <= 1 {
+            return Ok(vec![batch]);
+        }
+
+        let mut batches = Vec::with_capacity(rows_of_segments.len());
+        for rows in rows_of_segments.into_values() {
+            let split = take_record_batch(&batch, &UInt32Array::from(rows))
+                .context("take rows of flush segment")?;
+            batches.push(split);
+        }
+
+        Ok(batches)
+    }
+
+    /// Split `batch` into sub-shards by the hash of series keys, so rows of a
+    /// series always go to the same sub-shard, rows keep their order in every
+    /// split.
+    fn split_by_shard(&self, batch: RecordBatch) -> Result<Vec<RecordBatch>> {
+        let key_indices = self.series_key_indices();
+        if self.write_shards <= 1 || key_indices.is_empty() {
+            return Ok(vec![batch]);
+        }
+        let columns = key_indices
+            .iter()
+            .map(|i| batch.column(*i).clone())
+            .collect::<Vec<_>>();
+        let converter = RowConverter::new(
+            columns
+                .iter()
+                .map(|c| SortField::new(c.data_type().clone()))
+                .collect(),
+        )
+        .context("create row converter")?;
+        let rows = converter
+            .convert_columns(&columns)
+            .context("convert series key columns")?;
+        let mut rows_of_shards = vec![Vec::new(); self.write_shards];
+        for (idx, row) in rows.iter().enumerate() {
+            let mut hasher = DefaultHasher::new();
+            row.as_ref().hash(&mut hasher);
+            let shard = (hasher.finish() % self.write_shards as u64) as usize;
+            rows_of_shards[shard].push(idx as u32);
+        }
+        if rows_of_shards
+            .iter()
+            .filter(|rows| !rows.is_empty())
+            .count()
+            <= 1
+        {
+            return Ok(vec![batch]);
+        }
+
+        let mut batches = Vec::with_capacity(self.write_shards);
+        for rows in rows_of_shards.into_iter().filter(|rows| !rows.is_empty()) {
+            let split = take_record_batch(&batch, &UInt32Array::from(rows))
+                .context("take rows of sub-shard")?;
+            batches.push(split);
+        }
+
+        Ok(batches)
+    }
+
+    /// Append [ROW_SEQUENCE_COLUMN] of `sequence` to `batch` of the table
+    /// schema.
+    fn with_row_sequence(&self, batch: RecordBatch, sequence: u64) -> Result<RecordBatch> {
+        let num_rows = batch.num_rows();
+        let mut columns = batch.columns().to_vec();
+        columns.push(Arc::new(UInt64Array::from_value(sequence, num_rows)));
+        let batch = RecordBatch::try_new(self.sst_schema.clone(), columns)
+            .context("append row sequence column")?;
+
+        Ok(batch)
+    }
+
+    /// Store of the data file of `sst`.
+    fn store_of(&self, sst: &SstFile) -> &ObjectStoreRef {
+        match (&self.tiering, sst.meta.tier) {
+            (Some(tiering), Tier::Local) => &tiering.local_store,
+            _ => &self.store,
+        }
+    }
+
+    /// Copy the data file and inverted index of `sst` to the same paths in
+    /// `target`, returns the bytes copied.
+    pub(crate) async fn copy_sst(&self, sst: &SstFile, target: &ObjectStoreRef) -> Result<u64> {
+        let path = Path::from(self.build_file_path(sst.id));
+        let mut bytes = backup::copy_object(self.store_of(sst), &path, target, &path).await?;
+        if sst.meta.inverted_index_size > 0 {
+            let path = Path::from(self.build_index_path(sst.id));
+            bytes += backup::copy_object(&self.store, &path, target, &path).await?;
+        }
+
+        Ok(bytes)
+    }
+
+    /// Delete retired ssts older than
+    /// [ManifestOptions::history_retention](crate::types::ManifestOptions::history_retention),
+    /// returns the number of them. It's called after every compaction.
+    pub async fn purge_retired(&self) -> Result<usize> {
+        self.ensure_writable()?;
+        let retention_ms = self.manifest.history_retention().as_millis() as i64;
+        let expire_ms = crate::manifest::now_ms().saturating_sub(retention_ms);
+        let expired = self
+            .manifest
+            .retired_ssts()
+            .await
+            .into_iter()
+            .filter(|r| r.retired_at_ms <= expire_ms)
+            .map(|r| r.sst)
+            .collect::<Vec<_>>();
+        if expired.is_empty() {
+            return Ok(0);
+        }
+
+        // Files are deleted after the manifest is updated, so no retired sst
+        // refers to deleted files.
+        let ids = expired.iter().map(|f| f.id).collect::<Vec<_>>();
+        self.manifest.remove_retired(&ids).await?;
+        for sst in &expired {
+            self.delete_sst(sst).await;
+        }
+
+        Ok(expired.len())
+    }
+
+    /// Delete the data file and inverted index of `sst`.
+    ///
+    /// The sst may be migrated after it's loaded, so its data file is deleted
+    /// from both tiers. Failing to delete them only leaks the objects.
+    async fn delete_sst(&self, sst: &SstFile) {
+        let path = Path::from(self.build_file_path(sst.id));
+        let _ = self.store.delete(&path).await;
+        if let Some(tiering) = &self.tiering {
+            let _ = tiering.local_store.delete(&path).await;
+        }
+        if sst.meta.inverted_index_size > 0 {
+            let _ = self
+                .store
+                .delete(&Path::from(self.build_index_path(sst.id)))
+                .await;
+        }
+    }
+
+    #[tracing::instrument(
+        name = "storage.flush",
+        skip_all,
+        fields(
+            num_rows = req.batch.num_rows(),
+            file_id = tracing::field::Empty,
+            bytes = tracing::field::Empty,
+            tier = tracing::field::Empty,
+        )
+    )]
+    async fn write_batch(&self, req: WriteRequest) -> Result<WriteResult> {
+        self.ensure_writable()?;
+        let file_id = self.manifest.allocate_id().await?;
+        tracing::Span::current().record("file_id", file_id);
+        let file_path = self.build_file_path(file_id);
+        let file_path = Path::from(file_path);
+        let (store, tier) = match &self.tiering {
+            Some(tiering) => (&tiering.local_store, Tier::Local),
+            None => (&self.store, Tier::Cloud),
+        };
+        let object_store_writer =
+            ChecksumWriter::new(ParquetObjectWriter::new(store.clone(), file_path.clone()));
+        let checksum = object_store_writer.handle();
+        // Columns all null are left out of the file in the sparse mode.
+        let null_columns = self.null_columns(&req.batch);
+        let projection = (!null_columns.is_empty()).then(|| {
+            (0..self.file_schema.fields().len())
+                .filter(|i| !null_columns.contains(self.file_schema.field(*i).name()))
+                .collect::<Vec<_>>()
+        });
+        let file_schema = match &projection {
+            Some(projection) => Arc::new(
+                self.file_schema
+                    .project(projection)
+                    .context("project file schema")?,
+            ),
+            None => self.file_schema.clone(),
+        };
+        let mut writer = AsyncArrowWriter::try_new(
+            object_store_writer,
+            file_schema,
+            Some(self.write_props.clone()),
+        )
+        .context("create arrow writer")?;
+        // Rows of the row group in progress are encoded at once when any column
+        // is encoded, see [codec](crate::codec).
+        let mut encoding = (!self.codecs.is_empty()).then(Vec::new);
+
+        let mut row_group_size = self.row_group_size(&req.batch);
+        let mut index_builder = (!self.inverted_index_keys.is_empty()).then(|| {
+            InvertedIndexBuilder::new(
+                self.inverted_index_keys
+                    .iter()
+                    .map(|i| (*i, self.schema().field(*i).name().clone())),
+            )
+        });
+        let mut aggregates = self
+            .aggregate_columns
+            .iter()
+            .map(|i| ColumnAggregate::new(self.schema().field(*i).name().clone()))
+            .collect::<Vec<_>>();
+        let mut key_range = KeyRangeBuilder::new(self.schema(), self.series_key_indices());
+        // sort record batch
+        let mut batches = self.sort_batch(req.batch).await?;
+        while let Some(batch) = batches.next().await {
+            let batch = batch.context("get sorted batch")?;
+            for (idx, aggregate) in self.aggregate_columns.iter().zip(&mut aggregates) {
+                aggregate.update(batch.column(*idx))?;
+            }
+            key_range.update(&batch);
+            let mut offset = 0;
+            while offset < batch.num_rows() {
+                let in_progress_rows = match &encoding {
+                    Some(slices) => slices.iter().map(RecordBatch::num_rows).sum(),
+                    None => writer.in_progress_rows(),
+                };
+                let len = (row_group_size - in_progress_rows).min(batch.num_rows() - offset);
+                let slice = batch.slice(offset, len);
+                // The slice never exceeds the row group in progress.
+                if let Some(builder) = &mut index_builder {
+                    builder.add_batch(&slice, writer.flushed_row_groups().len())?;
+                }
+                match &mut encoding {
+                    Some(slices) => slices.push(slice),
+                    None => {
+                        let slice = project_batch(slice, projection.as_deref())?;
+                        writer.write(&slice).await.context("write arrow batch")?
+                    }
+                }
+                offset += len;
+                if in_progress_rows + len >= row_group_size {
+                    if let Some(slices) = &mut encoding {
+                        self.write_encoded(&mut writer, slices, projection.as_deref())
+                            .await?;
+                    }
+                    writer.flush().await.context("flush row group")?;
+                    if let Some(v) = self.encoded_row_group_size(writer.flushed_row_groups()) {
+                        row_group_size = v;
+                    }
+                }
+            }
+        }
+        if let Some(slices) = &mut encoding {
+            self.write_encoded(&mut writer, slices, projection.as_deref())
+                .await?;
+        }
+        writer.close().await.context("close arrow writer")?;
+        let checksum = Some(checksum.finalize());
+        let object_meta = store
+            .head(&file_path)
+            .await
+            .storage_io(|| format!("get object meta, path:{file_path}"))?;
+        let span = tracing::Span::current();
+        span.record("bytes", object_meta.size);
+        span.record("tier", tracing::field::debug(tier));
+        let mut inverted_index_size = 0;
+        if let Some(index) = index_builder.and_then(InvertedIndexBuilder::finish) {
+            let index_path = Path::from(self.build_index_path(file_id));
+            let buf = index.encode_to_vec();
+            inverted_index_size = buf.len();
+            self.store
+                .put(&index_path, PutPayload::from(buf))
+                .await
+                .storage_io(|| format!("write inverted index, path:{index_path}"))?;
+        }
+        let (min_key, max_key) = key_range.finish();
+
+        Ok(WriteResult {
+            id: file_id,
+            size: object_meta.size,
+            inverted_index_size,
+            aggregates,
+            tier,
+            min_key,
+            max_key,
+            checksum,
+            null_columns,
+        })
+    }
+
+    /// Value columns all null in `batch`, which are not written in the sparse
+    /// mode. Columns with defaults are always written, otherwise their nulls
+    /// would be read as the defaults.
+    fn null_columns(&self, batch: &RecordBatch) -> Vec<String> {
+        if !self.sparse_columns || batch.num_rows() == 0 {
+            return Vec::new();
+        }
+
+        let schema = batch.schema();
+        (self.num_primary_key..schema.fields().len())
+            .filter(|i| batch.column(*i).null_count() == batch.num_rows())
+            .map(|i| schema.field(i).name())
+            .filter(|name| !self.column_defaults.contains(name))
+            .cloned()
+            .collect()
+    }
+
+    /// Flush `batch` into a new L0 sst, which is not added to the manifest yet.
+    async fn flush_split(&self, batch: RecordBatch) -> Result<SstFile> {
+        let batch_rows = batch.num_rows();
+        let time_column = batch
+            .column(self.timestamp_index)
+            .as_any()
+            .downcast_ref::<Int64Array>()
+            .context("timestamp column should be int64")?;
+
+        let mut start = Timestamp::MAX;
+        let mut end = Timestamp::MIN;
+        for v in time_column.values() {
+            start = start.min(Timestamp(*v));
+            end = end.max(Timestamp(*v));
+        }
+        let time_range = TimeRange::try_from_inclusive(start, end)?;
+        let WriteResult {
+            id: file_id,
+            size: file_size,
+            inverted_index_size,
+            aggregates,
+            tier,
+            min_key,
+            max_key,
+            checksum,
+            null_columns,
+        } = self.write_batch(WriteRequest { batch }).await?;
+
+        Ok(SstFile {
+            id: file_id,
+            meta: FileMeta {
+                // Since file_id in increasing order, we can use it as sequence.
+                max_sequence: file_id,
+                num_rows: batch_rows as u32,
+                size: file_size as u32,
+                time_range,
+                level: LEVEL_0,
+                inverted_index_size: inverted_index_size as u32,
+                aggregates,
+                tier,
+                min_key,
+                max_key,
+                checksum,
+                null_columns,
+            },
+        })
+    }
+
+    /// Write rows of `req`, which are deduplicated by keys first if
+    /// `dedup_keys` is set.
+    #[tracing::instrument(
+        name = "storage.write",
+        skip_all,
+        fields(
+            path = %self.path,
+            num_rows = req.batch.num_rows(),
+            files = tracing::field::Empty,
+            bytes = tracing::field::Empty,
+        )
+    )]
+    async fn write_rows(&self, req: WriteRequest, dedup_keys: bool) -> Result<()> {
+        let req = WriteRequest {
+            batch: self.normalize_timestamp(req.batch)?,
+        };
+        ensure!(
+            req.batch.schema_ref().eq(self.schema()),
+            Error::SchemaMismatch {
+                msg: format!(
+                    "schema of write batch not match, expect:{}, given:{}",
+                    self.schema(),
+                    req.batch.schema_ref()
+                ),
+            }
+        );
+        ensure!(req.batch.num_rows() > 0, "write batch is empty");
+        self.check_null_keys(&req.batch)?;
+        let (req, fingerprints) = match &self.ingest_deduper {
+            Some(deduper) => {
+                let (batch, fingerprints) = deduper.dedup(req.batch)?;
+                if batch.num_rows() == 0 {
+                    return Ok(());
+                }
+                (WriteRequest { batch }, fingerprints)
+            }
+            None => (req, Vec::new()),
+        };
+        let req = if dedup_keys {
+            WriteRequest {
+                batch: dedup::dedup_by_key(req.batch, &self.dedup_key_indices(), None)?,
+            }
+        } else {
+            req
+        };
+
+        let num_rows = req.batch.num_rows();
+        let num_bytes = req.batch.get_array_memory_size();
+        if let Some((tenant, manager)) = &self.quota {
+            manager.check_write(tenant, num_rows as u64, num_bytes as u64)?;
+        }
+        // The batch is copied once sorted, and encoded into buffers of about the
+        // same size, they are held until the sst is flushed.
+        let _reservation = match &self.write_memory {
+            Some(controller) => Some(controller.reserve(num_bytes * 2).await?),
+            None => None,
+        };
+        let batch = if self.merge_mode == MergeMode::MergeOnWrite {
+            // Rows of the batch share a sequence larger than those written
+            // before, so they win over the older rows once merged.
+            let sequence = self.manifest.allocate_id().await?;
+            self.with_row_sequence(req.batch, sequence)?
+        } else {
+            req.batch
+        };
+        let mut splits = Vec::new();
+        for batch in self.split_by_segment(batch)? {
+            splits.extend(self.split_by_shard(batch)?);
+        }
+        // Splits of different sub-shards are flushed concurrently.
+        let new_ssts = futures::stream::iter(splits)
+            .map(|batch| self.flush_split(batch))
+            .buffered(self.write_shards)
+            .try_collect::<Vec<_>>()
+            .await?;
+        let files_size = new_ssts.iter().map(|f| f.meta.size as usize).sum::<usize>();
+        let span = tracing::Span::current();
+        span.record(
+            "files",
+            tracing::field::debug(new_ssts.iter().map(|f| f.id).collect::<Vec<_>>()),
+        );
+        span.record("bytes", files_size);
+        // Ssts of the batch are visible at the same time.
+        self.manifest.add_files(new_ssts).await?;
+        if let Some((tenant, manager)) = &self.quota {
+            manager.record_write(tenant, num_rows as u64, num_bytes as u64, files_size as u64);
+        }
+        // Rows failed to be written are not remembered, so retries are kept.
+        if let Some(deduper) = &self.ingest_deduper {
+            deduper.record(fingerprints);
+        }
+
+        Ok(())
+    }
+
+    /// Encode `slices` of one row group, and write them into `writer`.
+    async fn write_encoded(
+        &self,
+        writer: &mut AsyncArrowWriter<ChecksumWriter<ParquetObjectWriter>>,
+        slices: &mut Vec<RecordBatch>,
+        projection: Option<&[usize]>,
+    ) -> Result<()> {
+        if slices.is_empty() {
+            return Ok(());
+        }
+        let batch = concat_batches(&self.sst_schema, slices.iter()).context("concat batches")?;
+        slices.clear();
+        let batch = self.codecs.encode_batch(&batch, &self.file_schema)?;
+        let batch = project_batch(batch, projection)?;
+        writer.write(&batch).await.context("write arrow batch")?;
+
+        Ok(())
+    }
+
+    fn bucket_of(&self, sst: &SstFile, options: &CompactOnReadOptions) -> i64 {
+        let bucket_duration = TimeUnit::Nanosecond
+            .convert(options.bucket_duration.as_nanos() as i64, self.time_unit)
+            .max(1);
+        sst.meta.time_range.start.div_euclid(bucket_duration)
+    }
+
+    /// Group small ssts touched by a scan into buckets, then merge or schedule
+    /// buckets with too many of them.
+    ///
+    /// Returns true if any bucket is merged inline.
+    async fn maybe_compact_on_read(&self, ssts: &[SstFile]) -> Result<bool> {
+        let Some(options) = &self.compact_on_read else {
+            return Ok(false);
+        };
+        if self.is_replica() {
+            return Ok(false);
+        }
+
+        // Buckets are only merged inline when no other compaction is running,
+        // and ssts compacted by others meanwhile are skipped.
+        let guard = self.compaction_lock.try_lock().ok();
+        let live = if guard.is_some() {
+            let ssts = self.manifest.all_ssts().await;
+            Some(ssts.into_iter().map(|f| f.id).collect::<HashSet<_>>())
+        } else {
+            None
+        };
+        let mut buckets: BTreeMap<i64, Vec<SstFile>> = BTreeMap::new();
+        // L1 ssts are merged by leveled compaction.
+        for sst in ssts.iter().filter(|f| {
+            f.meta.level == LEVEL_0
+                && (f.meta.size as usize) < options.small_file_size
+                && match &live {
+                    Some(live) => live.contains(&f.id),
+                    None => true,
+                }
+        }) {
+            buckets
+                .entry(self.bucket_of(sst, options))
+                .or_default()
+                .push(sst.clone());
+        }
+
+        let mut compacted = false;
+        for (bucket, files) in buckets {
+            if files.len() <= options.max_small_files {
+                continue;
+            }
+            let total_bytes: usize = files.iter().map(|f| f.meta.size as usize).sum();
+            if guard.is_some() && total_bytes <= options.inline_max_bytes {
+                self.compact_files(files).await?;
+                compacted = true;
+            } else {
+                self.pending_compactions.lock().unwrap().insert(bucket);
+            }
+        }
+
+        Ok(compacted)
+    }
+
+    /// Merge `files` into one sst, and replace them in the manifest.
+    ///
+    /// Callers must hold `compaction_lock`.
+    async fn compact_files(&self, files: Vec<SstFile>) -> Result<CompactionReport> {
+        ensure!(!files.is_empty(), "no files to compact");
+
+        let begin = Instant::now();
+        let batch = self.read_files(&files).await?;
+        let num_rows = batch.num_rows();
+        // All rows are deleted by tombstones.
+        if num_rows == 0 {
+            return self.replace_files(&files, Vec::new(), begin).await;
+        }
+        let WriteResult {
+            id,
+            size,
+            inverted_index_size,
+            aggregates,
+            tier,
+            min_key,
+            max_key,
+            checksum,
+            null_columns,
+        } = self.write_batch(WriteRequest { batch }).await?;
+
+        let mut time_range = files[0].meta.time_range.clone();
+        for file in &files[1..] {
+            time_range = time_range.union(&file.meta.time_range);
+        }
+        let new_file = SstFile {
+            id,
+            meta: FileMeta {
+                // Keep sequence of inputs, so newer writes still win.
+                max_sequence: files.iter().map(|f| f.meta.max_sequence).max().unwrap(),
+                num_rows: num_rows as u32,
+                size: size as u32,
+                time_range,
+                level: LEVEL_0,
+                inverted_index_size: inverted_index_size as u32,
+                aggregates,
+                tier,
+                min_key,
+                max_key,
+                checksum,
+                null_columns,
+            },
+        };
+        self.replace_files(&files, vec![new_file], begin).await
+    }
+
+    /// Rewrite L0 ssts, and L1 ssts in the same time slices with them, into
+    /// L1 ssts, so L1 ssts never overlap. Then push the oldest slices of every
+    /// level exceeding its target size down into the next level.
+    ///
+    /// Only slices overlapping with the range of `control` are compacted.
+    ///
+    /// Callers must hold `compaction_lock`.
+    async fn compact_levels(
+        &self,
+        options: &LeveledCompactionOptions,
+        control: &mut CompactControl,
+    ) -> Result<CompactionReport> {
+        let slice_duration = TimeUnit::Nanosecond
+            .convert(options.slice_duration.as_nanos() as i64, self.time_unit)
+            .max(1);
+        let slice_of = |f: &SstFile| f.meta.time_range.start.div_euclid(slice_duration);
+        let slices_of = |f: &SstFile| {
+            let range = &f.meta.time_range;
+            let first = range.start.div_euclid(slice_duration);
+            let last = (*range.end - 1).div_euclid(slice_duration);
+            first..=last
+        };
+        let slice_range = |slice: i64| {
+            let start = slice.saturating_mul(slice_duration);
+            TimeRange::new(
+                Timestamp(start),
+                Timestamp(start.saturating_add(slice_duration)),
+            )
+        };
+        let mut result = CompactionReport::default();
+
+        let begin = Instant::now();
+        let ssts = self.manifest.all_ssts().await;
+        let level0 = ssts
+            .iter()
+            .filter(|f| f.meta.level == LEVEL_0)
+            .collect::<Vec<_>>();
+        let mut slices = level0
+            .iter()
+            .filter(|f| control.overlaps(&f.meta.time_range))
+            .flat_map(|f| slices_of(f))
+            .collect::<BTreeSet<_>>();
+        // L0 ssts sharing slices with the picked ones are rewritten together,
+        // so rows of older L1 ssts never win over them.
+        let mut num_picked = 0;
+        loop {
+            let picked = level0
+                .iter()
+                .filter(|f| slices_of(f).any(|s| slices.contains(&s)))
+                .collect::<Vec<_>>();
+            if picked.len() == num_picked {
+                break;
+            }
+            num_picked = picked.len();
+            slices.extend(picked.into_iter().flat_map(|f| slices_of(f)));
+        }
+        if !slices.is_empty() {
+            let files = ssts
+                .into_iter()
+                .filter(|f| {
+                    (f.meta.level == LEVEL_0 || f.meta.level == LEVEL_1)
+                        && slices_of(f).any(|s| slices.contains(&s))
+                })
+                .collect::<Vec<_>>();
+            if control.admit(&files).await {
+                let new_files = self
+                    .rewrite_into_level(&files, LEVEL_1, options, slice_duration)
+                    .await?;
+                let report = self.replace_files(&files, new_files, begin).await?;
+                control.done(&report);
+                result.merge(report);
+            }
+        }
+
+        for level in LEVEL_1..options.max_level {
+            let begin = Instant::now();
+            let ssts = self.manifest.all_ssts().await;
+            let mut level_bytes: u64 = ssts
+                .iter()
+                .filter(|f| f.meta.level == level)
+                .map(|f| f.meta.size as u64)
+                .sum();
+            let target_bytes = options.target_bytes(level);
+            if level_bytes <= target_bytes {
+                continue;
+            }
+
+            let mut bytes_of_slices: BTreeMap<i64, u64> = BTreeMap::new();
+            for f in ssts.iter().filter(|f| f.meta.level == level) {
+                *bytes_of_slices.entry(slice_of(f)).or_default() += f.meta.size as u64;
+            }
+            let mut slices = BTreeSet::new();
+            for (slice, bytes) in bytes_of_slices {
+                if level_bytes <= target_bytes {
+                    break;
+                }
+                level_bytes -= bytes;
+                if control.overlaps(&slice_range(slice)) {
+                    slices.insert(slice);
+                }
+            }
+            let files = ssts
+                .into_iter()
+                .filter(|f| {
+                    (f.meta.level == level || f.meta.level == level + 1)
+                        && slices.contains(&slice_of(f))
+                })
+                .collect::<Vec<_>>();
+            if files.is_empty() || !control.admit(&files).await {
+                continue;
+            }
+            let new_files = self
+                .rewrite_into_level(&files, level + 1, options, slice_duration)
+                .await?;
+            let report = self.replace_files(&files, new_files, begin).await?;
+            control.done(&report);
+            result.merge(report);
+        }
+        result.cancelled = control.is_cancelled();
+
+        Ok(result)
+    }
+
+    /// Rewrite `files` into ssts of `level`, every sst is in one time slice
+    /// and no larger than the max file size of the level, so they never
+    /// overlap with each other.
+    async fn rewrite_into_level(
+        &self,
+        files: &[SstFile],
+        level: Level,
+        options: &LeveledCompactionOptions,
+        slice_duration: i64,
+    ) -> Result<Vec<SstFile>> {
+        let batch = self.read_files(files).await?;
+        if batch.num_rows() == 0 {
+            return Ok(Vec::new());
+        }
+        let timestamps = batch
+            .column(self.timestamp_index)
+            .as_primitive::<Int64Type>()
+            .values()
+            .clone();
+        let mut rows_of_slices: BTreeMap<i64, Vec<u32>> = BTreeMap::new();
+        for (row, ts) in timestamps.iter().enumerate() {
+            rows_of_slices
+                .entry(ts.div_euclid(slice_duration))
+                .or_default()
+                .push(row as u32);
+        }
+        // Sizes of outputs are estimated from inputs, they are usually smaller
+        // since deleted and duplicated rows are dropped.
+        let input_bytes: u64 = files.iter().map(|f| f.meta.size as u64).sum();
+        let row_bytes = (input_bytes / batch.num_rows() as u64).max(1);
+        let max_rows = (options.max_file_bytes(level) / row_bytes).max(1) as usize;
+        let max_sequence = files.iter().map(|f| f.meta.max_sequence).max().unwrap();
+
+        let mut new_files = Vec::with_capacity(rows_of_slices.len());
+        for mut rows in rows_of_slices.into_values() {
+            rows.sort_by_key(|i| timestamps[*i as usize]);
+            // Rows of the same timestamp are kept in one sst, so ssts of the
+            // slice don't overlap.
+            let mut chunks = Vec::new();
+            let mut chunk_start = 0;
+            for i in 1..rows.len() {
+                if i - chunk_start >= max_rows
+                    && timestamps[rows[i] as usize] != timestamps[rows[i - 1] as usize]
+                {
+                    chunks.push(&rows[chunk_start..i]);
+                    chunk_start = i;
+                }
+            }
+            chunks.push(&rows[chunk_start..]);
+
+            for rows in chunks {
+                let start = timestamps[rows[0] as usize];
+                let end = timestamps[rows[rows.len() - 1] as usize];
+                let chunk = take_record_batch(&batch, &UInt32Array::from(rows.to_vec()))
+                    .context("take rows of time slice")?;
+                let num_rows = chunk.num_rows();
+                let WriteResult {
+                    id,
+                    size,
+                    inverted_index_size,
+                    aggregates,
+                    tier,
+                    min_key,
+                    max_key,
+                    checksum,
+                    null_columns,
+                } = self.write_batch(WriteRequest { batch: chunk }).await?;
+                new_files.push(SstFile {
+                    id,
+                    meta: FileMeta {
+                        max_sequence,
+                        num_rows: num_rows as u32,
+                        size: size as u32,
+                        time_range: TimeRange::try_from_inclusive(
+                            Timestamp(start),
+                            Timestamp(end),
+                        )?,
+                        level,
+                        inverted_index_size: inverted_index_size as u32,
+                        aggregates,
+                        tier,
+                        min_key,
+                        max_key,
+                        checksum,
+                        null_columns,
+                    },
+                });
+            }
+        }
+
+        Ok(new_files)
+    }
+
+    /// Read all rows of `files`, rows deleted by tombstones are dropped.
+    async fn read_files(&self, files: &[SstFile]) -> Result<RecordBatch> {
+        self.verify_ssts(files).await?;
+        let tombstones = self.manifest.all_tombstones().await;
+        // Rows of newer ssts come later, so they are kept by dedup.
+        let mut files = files.iter().collect::<Vec<_>>();
+        files.sort_by_key(|f| f.meta.max_sequence);
+        let mut batches = Vec::new();
+        for file in files {
+            let tombstones = tombstones
+                .iter()
+                .filter(|t| t.applies_to(file))
+                .cloned()
+                .collect::<Vec<_>>();
+            let filter = self.build_tombstone_filter(&tombstones, &self.df_schema, false)?;
+            let path = Path::from(self.build_file_path(file.id));
+            let store = self.store_of(file);
+            let object_meta = store
+                .head(&path)
+                .await
+                .storage_io(|| format!("get object meta, path:{path}"))?;
+            let reader = LimitedReader::new(
+                ParquetObjectReader::new(store.clone(), object_meta),
+                self.io_limiter.clone(),
+            );
+            let mut builder = ParquetRecordBatchStreamBuilder::new(reader)
+                .await
+                .with_context(|| format!("read parquet metadata, path:{path}"))?;
+            if let Some(batch_size) = self.codecs.min_read_batch_size() {
+                builder = builder.with_batch_size(batch_size);
+            }
+            let stream = builder.build().context("build parquet stream")?;
+            let file_batches = stream
+                .try_collect::<Vec<_>>()
+                .await
+                .with_context(|| format!("read parquet file, path:{path}"))?;
+            for batch in file_batches {
+                let batch = self.codecs.decode_batch(batch, &self.sst_schema)?;
+                let batch = if self.merge_mode == MergeMode::MergeOnWrite
+                    && batch.schema().index_of(ROW_SEQUENCE_COLUMN).is_err()
+                {
+                    let batch = self
+                        .column_defaults
+                        .fill_missing(batch, &self.arrow_schema)?;
+                    self.with_row_sequence(batch, file.meta.max_sequence)?
+                } else {
+                    self.column_defaults.fill_missing(batch, &self.sst_schema)?
+                };
+                let batch = match &filter {
+                    Some(filter) => {
+                        let keep = filter
+                            .evaluate(&batch)
+                            .and_then(|v| v.into_array(batch.num_rows()))
+                            .context("evaluate tombstone filter")?;
+                        filter_record_batch(&batch, keep.as_boolean())
+                            .context("drop rows deleted by tombstones")?
+                    }
+                    None => batch,
+                };
+                batches.push(batch);
+            }
+        }
+        let batch = concat_batches(&self.sst_schema, &batches).context("concat batches")?;
+        if self.merge_mode == MergeMode::MergeOnWrite {
+            let sequence_index = self.sst_schema.fields().len() - 1;
+            return dedup::dedup_by_key(batch, &self.dedup_key_indices(), Some(sequence_index));
+        }
+
+        Ok(batch)
+    }
+
+    /// Merge ssts overlapping with `range` and in time with each other, so
+    /// every row is unique among them once they are merged.
+    ///
+    /// Rows are deduplicated within every sst in merge-on-write mode, and rows
+    /// of ssts not overlapping in time never share the same timestamp.
+    ///
+    /// Returns true if any ssts are merged.
+    async fn merge_overlapping(&self, range: &TimeRange) -> Result<bool> {
+        if self.merge_mode != MergeMode::MergeOnWrite {
+            return Ok(false);
+        }
+        if overlapping_groups(&self.manifest.find_ssts(range).await).is_empty() {
+            return Ok(false);
+        }
+
+        // Scans must not return duplicate rows, so they wait for running
+        // compactions, which may have merged the ssts already.
+        let _guard = self.compaction_lock.lock().await;
+        let groups = overlapping_groups(&self.manifest.find_ssts(range).await);
+        let merged = !groups.is_empty();
+        for files in groups {
+            self.compact_files(files).await?;
+        }
+
+        Ok(merged)
+    }
+
+    /// Replace `inputs` with `outputs` of a compaction in the manifest.
+    ///
+    /// It fails and deletes `outputs` if any input is already removed from the
+    /// manifest, otherwise both outputs of compacting it twice are kept.
+    #[tracing::instrument(
+        name = "storage.replace_files",
+        skip_all,
+        fields(
+            inputs = ?inputs.iter().map(|f| f.id).collect::<Vec<_>>(),
+            outputs = ?outputs.iter().map(|f| f.id).collect::<Vec<_>>(),
+        )
+    )]
+    async fn replace_files(
+        &self,
+        inputs: &[SstFile],
+        outputs: Vec<SstFile>,
+        begin: Instant,
+    ) -> Result<CompactionReport> {
+        let to_delete = inputs.iter().map(|f| f.id).collect::<Vec<_>>();
+        let output_files = outputs.iter().map(|f| f.id).collect::<Vec<_>>();
+        let output_rows: u64 = outputs.iter().map(|f| f.meta.num_rows as u64).sum();
+        let bytes_written: u64 = outputs.iter().map(|f| f.meta.size as u64).sum();
+        // Tombstones are useless once all ssts they apply to are rewritten by
+        // this compaction, newer ssts are never affected by them.
+        let inputs_set = to_delete.iter().collect::<HashSet<_>>();
+        let ssts = self.manifest.all_ssts().await;
+        let tombstones_to_delete = self
+            .manifest
+            .all_tombstones()
+            .await
+            .into_iter()
+            .filter(|t| {
+                ssts.iter()
+                    .filter(|f| t.applies_to(f))
+                    .all(|f| inputs_set.contains(&f.id))
+            })
+            .map(|t| t.id)
+            .collect::<Vec<_>>();
+        let retire = !self.manifest.history_retention().is_zero();
+        {
+            let _guard = self.tier_lock.lock().await;
+            let live = self
+                .manifest
+                .all_ssts()
+                .await
+                .into_iter()
+                .map(|f| f.id)
+                .collect::<HashSet<_>>();
+            if let Some(sst) = inputs.iter().find(|f| !live.contains(&f.id)) {
+                for output in &outputs {
+                    self.delete_sst(output).await;
+                }
+                return Err(anyhow::anyhow!(
+                    "input of compaction is already removed, id:{}",
+                    sst.id
+                )
+                .into());
+            }
+            if retire {
+                self.manifest
+                    .retire(outputs, inputs, &tombstones_to_delete)
+                    .await?;
+            } else {
+                self.manifest
+                    .update_with_tombstones(outputs, &to_delete, &tombstones_to_delete)
+                    .await?;
+            }
+        }
+        let input_rows: u64 = inputs.iter().map(|f| f.meta.num_rows as u64).sum();
+        let bytes_read = inputs.iter().map(|f| f.meta.size as u64).sum();
+        if let Some((tenant, manager)) = &self.quota {
+            manager.adjust_storage(tenant, bytes_written, bytes_read);
+        }
+
+        // Inputs are unreachable once the manifest is updated, unless they are
+        // retired for scans at older versions.
+        if !retire {
+            for sst in inputs {
+                self.delete_sst(sst).await;
+            }
+        }
+        // Failing to purge only keeps the retired ssts longer.
+        let _ = self.purge_retired().await;
+
+        Ok(CompactionReport {
+            input_files: to_delete,
+            output_files,
+            bytes_read,
+            bytes_written,
+            duration: begin.elapsed(),
+            rows_dropped: input_rows.saturating_sub(output_rows),
+            cancelled: false,
+        })
+    }
+
+    /// Key of the scan in the result cache, root path is included since the
+    /// cache may be shared.
+    fn result_cache_key(&self, req: &ScanRequest) -> String {
+        format!(
+            "{}|{:?}|{:?}|{:?}|{:?}|{:?}|{:?}|{:?}",
+            self.path,
+            req.range,
+            req.predicate,
+            req.projections,
+            req.limit_per_series,
+            req.output_order,
+            req.output_exprs,
+            req.batch_size
+        )
+    }
+
+    /// Same as [TimeMergeStorage::scan], and also returns stats of the scan.
+    ///
+    /// The scan is traced by a span lasting until the returned stream is
+    /// dropped, object store requests of the scan are traced as its children.
+    pub async fn scan_with_stats(
+        &self,
+        req: ScanRequest,
+    ) -> Result<(SendableRecordBatchStream, ScanStats)> {
+        req.range.check()?;
+        let span = tracing::info_span!(
+            "storage.scan",
+            path = %self.path,
+            range = ?req.range,
+            files_touched = tracing::field::Empty,
+            files_pruned = tracing::field::Empty,
+            rows = tracing::field::Empty,
+            bytes = tracing::field::Empty,
+        );
+        let (stream, stats) = self.plan_scan(req, &span).instrument(span.clone()).await?;
+        span.record("files_touched", stats.files_touched);
+        span.record("files_pruned", stats.files_pruned);
+
+        Ok((Box::pin(TracedStream::new(stream, span)), stats))
+    }
+
+    async fn plan_scan(
+        &self,
+        req: ScanRequest,
+        span: &tracing::Span,
+    ) -> Result<(SendableRecordBatchStream, ScanStats)> {
+        ensure!(
+            req.output_exprs.is_none() || req.limit_per_series.is_none(),
+            "output exprs are not supported with limit per series"
+        );
+        let snapshot = match req.snapshot_version {
+            Some(version) => Some(self.manifest.load_at(version).await?),
+            None => None,
+        };
+        let (mut ssts, num_ssts) = match &snapshot {
+            Some(payload) => {
+                let ssts = payload
+                    .ssts()
+                    .iter()
+                    .filter(|f| f.meta.time_range.overlaps(&req.range))
+                    .cloned()
+                    .collect::<Vec<_>>();
+                (ssts, payload.ssts().len())
+            }
+            None => {
+                let mut ssts = self.manifest.find_ssts(&req.range).await;
+                if self.maybe_compact_on_read(&ssts).await? {
+                    ssts = self.manifest.find_ssts(&req.range).await;
+                }
+                if self.merge_overlapping(&req.range).await? {
+                    ssts = self.manifest.find_ssts(&req.range).await;
+                }
+                (ssts, self.manifest.num_ssts().await)
+            }
+        };
+        let num_overlapped = ssts.len();
+        // Key ranges are recorded in the manifest, so they are checked before
+        // any index is loaded.
+        let series_keys = self.series_key_indices();
+        if let Some(bounds) = KeyBounds::try_new(&req.predicate, self.schema(), &series_keys) {
+            ssts.retain(|f| bounds.may_match(&f.meta.min_key, &f.meta.max_key));
+        }
+        let files_pruned_by_key_range = num_overlapped - ssts.len();
+        if let Some(version) = req.snapshot_version {
+            self.ensure_ssts_exist(version, &ssts).await?;
+        }
+        self.verify_ssts(&ssts).await?;
+        let key_equalities =
+            bloom::key_equalities(&req.predicate, self.schema(), &self.bloom_filter_keys);
+        let index_equalities =
+            bloom::key_equalities(&req.predicate, self.schema(), &self.inverted_index_keys);
+        let mut access_plans = HashMap::new();
+        let mut row_groups_pruned_by_bloom_filter = 0;
+        let mut files_pruned_by_inverted_index = 0;
+        let mut row_groups_pruned_by_inverted_index = 0;
+        if !key_equalities.is_empty() || !index_equalities.is_empty() {
+            let mut kept = Vec::with_capacity(ssts.len());
+            for sst in ssts {
+                // The inverted index is checked first, since it's much smaller
+                // than the bloom filters.
+                let mut row_groups = None;
+                if !index_equalities.is_empty() && sst.meta.inverted_index_size > 0 {
+                    let index = self.load_inverted_index(&sst).await?;
+                    let keep = inverted_index::prune_row_groups(&index, &index_equalities);
+                    let num_pruned = keep.iter().filter(|keep| !**keep).count();
+                    row_groups_pruned_by_inverted_index += num_pruned;
+                    if num_pruned == keep.len() {
+                        files_pruned_by_inverted_index += 1;
+                        continue;
+                    }
+                    row_groups = Some(keep);
+                }
+                if !key_equalities.is_empty() {
+                    let by_bloom = self.prune_by_bloom_filter(&sst, &key_equalities).await?;
+                    let mut keep = row_groups.unwrap_or_else(|| vec![true; by_bloom.len()]);
+                    for (keep, by_bloom) in keep.iter_mut().zip(by_bloom) {
+                        if *keep && !by_bloom {
+                            *keep = false;
+                            row_groups_pruned_by_bloom_filter += 1;
+                        }
+                    }
+                    row_groups = Some(keep);
+                }
+
+                let row_groups = row_groups.unwrap_or_default();
+                if !row_groups.is_empty() && row_groups.iter().all(|keep| !keep) {
+                    continue;
+                }
+                if row_groups.contains(&false) {
+                    let mut plan = ParquetAccessPlan::new_all(row_groups.len());
+                    for (idx, keep) in row_groups.iter().enumerate() {
+                        if !keep {
+                            plan.skip(idx);
+                        }
+                    }
+                    access_plans.insert(sst.id, plan);
+                }
+                kept.push(sst);
+            }
+            ssts = kept;
+        }
+        // Ssts of a level other than L0 don't overlap, so they are read in time
+        // order as one sorted run, followed by L0 ssts.
+        ssts.sort_by_key(|f| {
+            (
+                f.meta.level == LEVEL_0,
+                f.meta.level,
+                f.meta.time_range.start.clone(),
+            )
+        });
+        let num_l0_ssts = ssts.iter().filter(|f| f.meta.level == LEVEL_0).count();
+        let num_levels = ssts
+            .iter()
+            .filter(|f| f.meta.level != LEVEL_0)
+            .map(|f| f.meta.level)
+            .collect::<BTreeSet<_>>()
+            .len();
+        let sorted_runs = num_l0_ssts + num_levels;
+        let tombstones = match &snapshot {
+            Some(payload) => payload
+                .tombstones()
+                .iter()
+                .filter(|t| t.time_range.overlaps(&req.range))
+                .cloned()
+                .collect(),
+            None => self.manifest.find_tombstones(&req.range).await,
+        };
+        let tombstones = tombstones
+            .into_iter()
+            .filter(|t| ssts.iter().any(|f| t.applies_to(f)))
+            .collect::<Vec<_>>();
+        let apply_tombstones = !tombstones.is_empty();
+        // we won't use url for selecting object_store.
+        let dummy_url = ObjectStoreUrl::parse("empty://").unwrap();
+        // TODO: we could group ssts based on time range.
+        // TODO: fetch using multiple threads since read from parquet will incur CPU
+        // when convert between arrow and parquet.
+        let files = ssts
+            .iter()
+            .map(|f| {
+                let mut file = PartitionedFile::new(self.build_file_path(f.id), f.meta.size as u64);
+                if apply_tombstones {
+                    file.partition_values = vec![ScalarValue::UInt64(Some(f.meta.max_sequence))];
+                }
+                match access_plans.remove(&f.id) {
+                    Some(plan) => file.with_extensions(Arc::new(plan)),
+                    None => file,
+                }
+            })
+            .collect::<Vec<_>>();
+        // Ssts are read by as many partitions as the sub-shards, whose results
+        // are merged after sorted separately.
+        let num_partitions = self.write_shards.min(files.len()).max(1);
+        let mut partitions = vec![Vec::new(); num_partitions];
+        for (idx, file) in files.into_iter().enumerate() {
+            partitions[idx % num_partitions].push(file);
+        }
+        let mut scan_config =
+            FileScanConfig::new(dummy_url, self.schema().clone()).with_file_groups(partitions);
+        let sort_indices = match (req.limit_per_series, req.output_order) {
+            // Latest rows of one series must be adjacent and ordered by time.
+            (Some(_), _) => {
+                let mut indices = self.series_key_indices();
+                indices.push(self.timestamp_index);
+                Some(indices)
+            }
+            (None, OutputOrder::None) => None,
+            (None, OutputOrder::ByKey) => Some((0..self.num_primary_key).collect()),
+            (None, OutputOrder::ByTime) => Some(
+                std::iter::once(self.timestamp_index)
+                    .chain(self.series_key_indices())
+                    .collect(),
+            ),
+        };
+        // Columns required by tombstones and sorting are appended to the projected
+        // columns, and are removed after rows are sorted.
+        let mut projections = req
+            .projections
+            .unwrap_or_else(|| (0..self.schema().fields().len()).collect());
+        let num_projected = projections.len();
+        let required = apply_tombstones
+            .then(|| std::iter::once(self.timestamp_index).chain(self.leading_key_index()))
+            .into_iter()
+            .flatten()
+            .chain(sort_indices.iter().flatten().copied());
+        for idx in required {
+            if !projections.contains(&idx) {
+                projections.push(idx);
+            }
+        }
+        let num_scanned = projections.len();
+        scan_config = if apply_tombstones {
+            projections.push(self.schema().fields().len());
+            scan_config
+                .with_table_partition_cols(vec![Field::new(
+                    SEQUENCE_COLUMN,
+                    DataType::UInt64,
+                    false,
+                )])
+                .with_projection(Some(projections))
+        } else {
+            scan_config.with_projection(Some(projections))
+        };
+
+        let mut reader_factory = DefaultParquetFileReaderFactory::new(self.store.clone())
+            .with_preload_page_index(self.enable_page_index)
+            .with_io_limiter(self.io_limiter.clone())
+            .with_span(span.clone());
+        if let Some(tiering) = &self.tiering {
+            let local_files = ssts
+                .iter()
+                .filter(|f| f.meta.tier == Tier::Local)
+                .map(|f| Path::from(self.build_file_path(f.id)))
+                .collect();
+            reader_factory =
+                reader_factory.with_local_files(tiering.local_store.clone(), local_files);
+        }
+        let mut builder = ParquetExec::builder(scan_config)
+            .with_parquet_file_reader_factory(Arc::new(reader_factory));
+        if !self.codecs.is_empty() || !self.column_defaults.is_empty() {
+            builder = builder.with_schema_adapter_factory(Arc::new(SstSchemaAdapterFactory::new(
+                self.codecs.clone(),
+                self.column_defaults.clone(),
+            )));
+        }
+        // The time range is checked along with the predicate, so row groups and
+        // pages out of the range are skipped by their statistics.
+        let predicate = req
+            .predicate
+            .into_iter()
+            .chain(self.time_range_predicate(&req.range));
+        if let Some(expr) = conjunction(predicate) {
+            let filters = create_physical_expr(&expr, &self.df_schema, &ExecutionProps::new())
+                .context("create pyhsical expr")?;
+            builder = builder.with_predicate(filters);
+        }
+
+        // Pages are pruned by the predicate with the column index, and row groups
+        // kept above are pruned by the bloom filters of all columns, including the
+        // fields which are not checked before planning.
+        //
+        // Encoded values are decoded by whole row groups, so pages are never
+        // skipped when any column is encoded.
+        let parquet_exec = Arc::new(
+            builder
+                .build()
+                .with_enable_page_index(self.enable_page_index && self.codecs.is_empty())
+                .with_bloom_filter_on_read(self.bloom_filter_on_read),
+        );
+        let parquet_exec_ref = parquet_exec.clone();
+        let mut scan_plan: Arc<dyn ExecutionPlan> = parquet_exec;
+        if apply_tombstones {
+            scan_plan = self.apply_tombstones(&tombstones, scan_plan, num_scanned)?;
+        }
+        // Sort exprs refer to columns by their indices in the scanned plan, which
+        // differ from the ones in the table schema once columns are projected.
+        let sort_exprs = match sort_indices {
+            Some(indices) => {
+                let scan_schema = DFSchema::try_from(scan_plan.schema().as_ref().clone())
+                    .context("build DFSchema")?;
+                Some(self.build_sort_exprs_by(indices, &scan_schema)?)
+            }
+            None => None,
+        };
+        let mut physical_plan: Arc<dyn ExecutionPlan> = match sort_exprs {
+            Some(sort_exprs) if num_partitions > 1 => {
+                let sort =
+                    SortExec::new(sort_exprs.clone(), scan_plan).with_preserve_partitioning(true);
+                Arc::new(SortPreservingMergeExec::new(sort_exprs, Arc::new(sort)))
+            }
+            Some(sort_exprs) => Arc::new(SortExec::new(sort_exprs, scan_plan)),
+            // Partitions are coalesced when executed.
+            None => scan_plan,
+        };
+        if num_scanned > num_projected {
+            physical_plan = Self::project_leading_columns(physical_plan, num_projected)?;
+        }
+        if let Some(exprs) = req.output_exprs {
+            physical_plan = Self::build_projection(exprs, physical_plan)?;
+        }
+        let stats = ScanStats {
+            files_touched: ssts.len(),
+            files_pruned: num_ssts.saturating_sub(num_overlapped),
+            sorted_runs,
+            files_pruned_by_key_range,
+            files_pruned_by_bloom_filter: num_overlapped
+                - ssts.len()
+                - files_pruned_by_key_range
+                - files_pruned_by_inverted_index,
+            files_pruned_by_inverted_index,
+            row_groups_pruned_by_inverted_index,
+            row_groups_pruned_by_bloom_filter,
+            plan: physical_plan.clone(),
+            parquet_exec: parquet_exec_ref,
+        };
+
+        let task_ctx = self.build_scan_task_ctx(req.memory_limit)?;
+        // TODO: dedup record batch based on primary keys and sequence number.
+        let res = execute_stream(physical_plan, task_ctx).context("execute scan physical plan")?;
+
+        if let Some(limit) = req.limit_per_series {
+            let output_schema = res.schema();
+            let key_indices = self
+                .series_key_indices()
+                .into_iter()
+                .map(|i| output_schema.index_of(self.schema().field(i).name()))
+                .collect::<std::result::Result<Vec<_>, _>>()
+                .context("series key columns must be projected")?;
+            let newest_first = self.time_order == TimeOrder::Desc;
+            let stream = LatestPerSeriesStream::try_new(res, key_indices, limit, newest_first)
+                .context("create latest per series stream")?;
+            return Ok((
+                self.coalesce_output(Box::pin(stream), req.batch_size),
+                stats,
+            ));
+        }
+
+        Ok((self.coalesce_output(res, req.batch_size), stats))
+    }
+
+    /// Predicate on the timestamp column selecting rows in `range`, `None` if
+    /// the range is unbounded.
+    fn time_range_predicate(&self, range: &TimeRange) -> Option<Expr> {
+        let column = ident(self.schema().field(self.timestamp_index).name());
+        let mut exprs = Vec::with_capacity(2);
+        if range.start > Timestamp::MIN {
+            exprs.push(column.clone().gt_eq(lit(*range.start)));
+        }
+        if range.end < Timestamp::MAX {
+            exprs.push(column.lt(lit(*range.end)));
+        }
+        conjunction(exprs)
+    }
+
+    fn coalesce_output(
+        &self,
+        stream: SendableRecordBatchStream,
+        batch_size: Option<usize>,
+    ) -> SendableRecordBatchStream {
+        if let Some(batch_size) = batch_size {
+            return Box::pin(RechunkStream::new(stream, batch_size));
+        }
+        let OutputCoalesceOptions {
+            target_rows,
+            target_bytes,
+        } = self.output_coalesce;
+        Box::pin(CoalesceStream::new(stream, target_rows, target_bytes))
+    }
+
+    /// Scans with a memory limit get their own memory pool, sharing the disk
+    /// manager of the storage to spill to.
+    fn build_scan_task_ctx(&self, memory_limit: Option<usize>) -> Result<Arc<TaskContext>> {
+        let mut config = SessionConfig::new();
+        if let Some(batch_size) = self.codecs.min_read_batch_size() {
+            let batch_size = config.batch_size().max(batch_size);
+            config = config.with_batch_size(batch_size);
+        }
+        let Some(memory_limit) = memory_limit else {
+            return Ok(SessionContext::new_with_config(config).task_ctx());
+        };
+
+        let runtime = RuntimeEnvBuilder::new()
+            .with_memory_pool(Arc::new(FairSpillPool::new(memory_limit)))
+            .with_disk_manager(DiskManagerConfig::Existing(self.disk_manager.clone()))
+            .build_arc()
+            .context("build runtime of scan")?;
+        // Memory reserved for merging spilled runs is 10MiB by default, which
+        // should not take up most of small limits.
+        let merge_reservation = config
+            .options()
+            .execution
+            .sort_spill_reservation_bytes
+            .min(memory_limit / 4);
+        let config = config.with_sort_spill_reservation_bytes(merge_reservation);
+        let ctx = SessionContext::new_with_config_rt(config, runtime);
+        Ok(ctx.task_ctx())
+    }
+
+    /// Filter out rows of `input` deleted by `tombstones`, and only the first
+    /// `num_columns` columns are kept.
+    ///
+    /// The max sequence of the sst of every row is read from the partition
+    /// column [SEQUENCE_COLUMN].
+    fn apply_tombstones(
+        &self,
+        tombstones: &[Tombstone],
+        input: Arc<dyn ExecutionPlan>,
+        num_columns: usize,
+    ) -> Result<Arc<dyn ExecutionPlan>> {
+        let input_schema =
+            DFSchema::try_from(input.schema().as_ref().clone()).context("build DFSchema")?;
+        let filter = self
+            .build_tombstone_filter(tombstones, &input_schema, true)?
+            .context("tombstones are empty")?;
+        let filter = FilterExec::try_new(filter, input).context("build tombstone filter plan")?;
+
+        Self::project_leading_columns(Arc::new(filter), num_columns)
+    }
+
+    /// Keep the first `num_columns` columns of `input`.
+    fn project_leading_columns(
+        input: Arc<dyn ExecutionPlan>,
+        num_columns: usize,
+    ) -> Result<Arc<dyn ExecutionPlan>> {
+        let exprs = input
+            .schema()
+            .fields()
+            .iter()
+            .take(num_columns)
+            .enumerate()
+            .map(|(idx, field)| {
+                let column: Arc<dyn PhysicalExpr> = Arc::new(Column::new(field.name(), idx));
+                (column, field.name().clone())
+            })
+            .collect::<Vec<_>>();
+        let projection = ProjectionExec::try_new(exprs, input).context("build projection plan")?;
+
+        Ok(Arc::new(projection))
+    }
+
+    /// Build the filter keeping rows not deleted by `tombstones` upon `schema`,
+    /// `None` is returned if `tombstones` is empty.
+    ///
+    /// Rows are assumed to come from ssts the tombstones apply to unless
+    /// `with_sequence` is true, see [Tombstone::to_expr].
+    fn build_tombstone_filter(
+        &self,
+        tombstones: &[Tombstone],
+        schema: &DFSchema,
+        with_sequence: bool,
+    ) -> Result<Option<Arc<dyn PhysicalExpr>>> {
+        let key_column = self
+            .leading_key_index()
+            .map(|idx| self.schema().field(idx).name().as_str());
+        let timestamp_column = self.schema().field(self.timestamp_index).name();
+        let sequence_column = with_sequence.then_some(SEQUENCE_COLUMN);
+        let Some(expr) =
+            tombstone::build_filter_expr(tombstones, key_column, timestamp_column, sequence_column)
+        else {
+            return Ok(None);
+        };
+        let filter = create_physical_expr(&expr, schema, &ExecutionProps::new())
+            .with_context(|| format!("create tombstone filter, expr:{expr}"))?;
+
+        Ok(Some(filter))
+    }
+
+    /// Evaluate `exprs` upon rows of `input`, output columns are named after
+    /// the exprs.
+    fn build_projection(
+        exprs: Vec<Expr>,
+        input: Arc<dyn ExecutionPlan>,
+    ) -> Result<Arc<dyn ExecutionPlan>> {
+        let input_schema =
+            DFSchema::try_from(input.schema().as_ref().clone()).context("build DFSchema")?;
+        let exprs = exprs
+            .into_iter()
+            .map(|expr| {
+                let physical_expr =
+                    create_physical_expr(&expr, &input_schema, &ExecutionProps::new())
+                        .with_context(|| format!("create physical expr, expr:{expr}"))?;
+                Ok((physical_expr, expr.schema_name().to_string()))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        let projection = ProjectionExec::try_new(exprs, input).context("build projection plan")?;
+
+        Ok(Arc::new(projection))
+    }
+
+    /// Timestamps in arrow timestamp type are converted to int64 in the time
+    /// unit of the storage.
+    fn normalize_timestamp(&self, batch: RecordBatch) -> Result<RecordBatch> {
+        let column = batch.column(self.timestamp_index);
+        let DataType::Timestamp(unit, _) = column.data_type() else {
+            return Ok(batch);
+        };
+
+        let from = TimeUnit::from(*unit);
+        let values = cast(column, &DataType::Int64).context("cast timestamp column")?;
+        let values = values
+            .as_primitive::<Int64Type>()
+            .unary::<_, Int64Type>(|v| from.convert(v, self.time_unit));
+        let mut columns = batch.columns().to_vec();
+        columns[self.timestamp_index] = Arc::new(values);
+        let batch = RecordBatch::try_new(self.schema().clone(), columns)
+            .context("rebuild batch with converted timestamp")?;
+
+        Ok(batch)
+    }
+
+    /// Rows of a row group, estimated from the average row width of the batch
+    /// when target row group bytes is set.
+    fn row_group_size(&self, batch: &RecordBatch) -> usize {
+        let max_row_group_size = self.write_props.max_row_group_size();
+        let Some(target_bytes) = self.target_row_group_bytes else {
+            return max_row_group_size;
+        };
+        if batch.num_rows() == 0 {
+            return max_row_group_size;
+        }
+
+        let row_width = (batch.get_array_memory_size() / batch.num_rows()).max(1);
+        (target_bytes / row_width).clamp(1, max_row_group_size)
+    }
+
+    /// Rows of a row group, computed from the average encoded row width of the
+    /// flushed row groups when target row group bytes is set.
+    ///
+    /// The encoded width reflects the encoding and compression of the data, so
+    /// it's more accurate than the in-memory width.
+    fn encoded_row_group_size(&self, flushed: &[RowGroupMetaData]) -> Option<usize> {
+        let target_bytes = self.target_row_group_bytes?;
+        let (bytes, rows) = flushed.iter().fold((0, 0), |(bytes, rows), rg| {
+            (bytes + rg.compressed_size(), rows + rg.num_rows())
+        });
+        if rows == 0 {
+            return None;
+        }
+
+        let row_width = (bytes / rows).max(1) as usize;
+        Some((target_bytes / row_width).clamp(1, self.write_props.max_row_group_size()))
+    }
+
+    fn build_sort_exprs(&self) -> Result<LexOrdering> {
+        self.build_sort_exprs_by(0..self.num_primary_key, &self.df_schema)
+    }
+
+    /// Build sort exprs of columns at `indices` of the table schema upon
+    /// `schema`, which must contain these columns.
+    fn build_sort_exprs_by(
+        &self,
+        indices: impl IntoIterator<Item = usize>,
+        schema: &DFSchema,
+    ) -> Result<LexOrdering> {
+        let sort_exprs = indices
+            .into_iter()
+            .map(|i| {
+                let asc = i != self.timestamp_index || self.time_order == TimeOrder::Asc;
+                ident(self.schema().field(i).name()).sort(asc, self.null_keys.nulls_first())
+            })
+            .collect::<Vec<_>>();
+        let sort_exprs =
+            create_physical_sort_exprs(&sort_exprs, schema, &ExecutionProps::default())
+                .context("create physical sort exprs")?;
+
+        Ok(sort_exprs)
+    }
+
+    /// Primary key columns except the timestamp column.
+    fn series_key_indices(&self) -> Vec<usize> {
+        (0..self.num_primary_key)
+            .filter(|i| *i != self.timestamp_index)
+            .collect()
+    }
+
+    /// Returns whether every row group of the sst may contain rows matching
+    /// `equalities` by the bloom filters.
+    async fn prune_by_bloom_filter(
+        &self,
+        sst: &SstFile,
+        equalities: &[KeyEquality],
+    ) -> Result<Vec<bool>> {
+        let path = Path::from(self.build_file_path(sst.id));
+        let store = self.store_of(sst);
+        let object_meta = store
+            .head(&path)
+            .await
+            .storage_io(|| format!("get object meta, path:{path}"))?;
+        let reader = LimitedReader::new(
+            ParquetObjectReader::new(store.clone(), object_meta),
+            self.io_limiter.clone(),
+        );
+
+        bloom::prune_row_groups(reader, equalities).await
+    }
+
+    /// Ssts of an older manifest version may be deleted by compactions, which
+    /// fails the scan with [Error::VersionUnavailable] before any is read.
+    async fn ensure_ssts_exist(&self, version: u64, ssts: &[SstFile]) -> Result<()> {
+        let heads = ssts.iter().map(|sst| async move {
+            let path = Path::from(self.build_file_path(sst.id));
+            match self.store_of(sst).head(&path).await {
+                Ok(_) => Ok(()),
+                Err(object_store::Error::NotFound { .. }) => Err(Error::VersionUnavailable {
+                    version,
+                    msg: format!("sst {} is deleted", sst.id),
+                }),
+                Err(e) => Err(Error::StorageIo {
+                    msg: format!("head sst, path:{path}"),
+                    source: e,
+                }),
+            }
+        });
+        futures::future::try_join_all(heads).await?;
+
+        Ok(())
+    }
+
+    /// Verify checksums of `ssts` sampled by the checksum verifier, ssts whose
+    /// checksums are unknown are skipped.
+    async fn verify_ssts(&self, ssts: &[SstFile]) -> Result<()> {
+        let verifications = ssts
+            .iter()
+            .filter_map(|sst| Some((sst, sst.meta.checksum?)))
+            .filter(|_| self.checksum_verifier.should_verify())
+            .map(|(sst, expect)| async move {
+                let path = Path::from(self.build_file_path(sst.id));
+                let bytes = self
+                    .store_of(sst)
+                    .get(&path)
+                    .await
+                    .storage_io(|| format!("get sst, path:{path}"))?
+                    .bytes()
+                    .await
+                    .storage_io(|| format!("read sst, path:{path}"))?;
+                checksum::verify(path.as_ref(), &bytes, expect)
+            });
+        futures::future::try_join_all(verifications).await?;
+
+        Ok(())
+    }
+
+    async fn load_inverted_index(&self, sst: &SstFile) -> Result<pb_types::InvertedIndex> {
+        let path = Path::from(self.build_index_path(sst.id));
+        let bytes = self
+            .store
+            .get(&path)
+            .await
+            .storage_io(|| format!("get inverted index, path:{path}"))?
+            .bytes()
+            .await
+            .storage_io(|| format!("read inverted index, path:{path}"))?;
+        let index = pb_types::InvertedIndex::decode(bytes)
+            .with_context(|| format!("decode inverted index, path:{path}"))?;
+
+        Ok(index)
+    }
+
+    /// The first series key column, which key ranges of tombstones apply to.
+    fn leading_key_index(&self) -> Option<usize> {
+        self.series_key_indices().first().copied()
+    }
+
+    async fn sort_batch(&self, batch: RecordBatch) -> Result<SendableRecordBatchStream> {
+        let ctx = SessionContext::default();
+        let schema = batch.schema();
+        let sort_exprs = self.build_sort_exprs()?;
+        let batch_plan =
+            MemoryExec::try_new(&[vec![batch]], schema, None).context("build batch plan")?;
+        let physical_plan = Arc::new(SortExec::new(sort_exprs, Arc::new(batch_plan)));
+
+        let res =
+            execute_stream(physical_plan, ctx.task_ctx()).context("execute sort physical plan")?;
+        Ok(res)
+    }
+
+    /// Migrate ssts in the local tier older than
+    /// [TieringOptions::migrate_after] to the object store.
+    pub async fn migrate_tiers(&self) -> Result<MigrateResult> {
+        self.ensure_writable()?;
+        let mut result = MigrateResult::default();
+        let Some(tiering) = &self.tiering else {
+            return Ok(result);
+        };
+
+        let ssts = self.manifest.all_ssts().await;
+        for sst in ssts.into_iter().filter(|f| f.meta.tier == Tier::Local) {
+            let path = Path::from(self.build_file_path(sst.id));
+            let object_meta = tiering
+                .local_store
+                .head(&path)
+                .await
+                .storage_io(|| format!("get object meta, path:{path}"))?;
+            let age = SystemTime::now()
+                .duration_since(object_meta.last_modified.into())
+                .unwrap_or_default();
+            if age < tiering.migrate_after {
+                continue;
+            }
+
+            let bytes = tiering
+                .local_store
+                .get(&path)
+                .await
+                .storage_io(|| format!("get local sst, path:{path}"))?
+                .bytes()
+                .await
+                .storage_io(|| format!("read local sst, path:{path}"))?;
+            // The whole sst is read already, so it's always verified to never
+            // upload a corrupted one.
+            if let Some(expect) = sst.meta.checksum {
+                checksum::verify(path.as_ref(), &bytes, expect)?;
+            }
+            let size = bytes.len() as u64;
+            self.store
+                .put(&path, PutPayload::from(bytes))
+                .await
+                .storage_io(|| format!("upload sst, path:{path}"))?;
+            {
+                let _guard = self.tier_lock.lock().await;
+                // The sst may be compacted while it's uploaded.
+                let exists = self
+                    .manifest
+                    .all_ssts()
+                    .await
+                    .iter()
+                    .any(|f| f.id == sst.id);
+                if !exists {
+                    let _ = self.store.delete(&path).await;
+                    continue;
+                }
+                let mut migrated = sst.clone();
+                migrated.meta.tier = Tier::Cloud;
+                self.manifest.update(vec![migrated], &[sst.id]).await?;
+            }
+            // Scans planned before the update may still read the local copy,
+            // which fail and should be retried.
+            let _ = tiering.local_store.delete(&path).await;
+            result.files_migrated += 1;
+            result.bytes_migrated += size;
+        }
+
+        Ok(result)
+    }
+
+    /// Check the object store, the manifest, the local clock and the debt of
+    /// compactions, e.g. for load balancers and operators.
+    ///
+    /// The manifest is fully loaded from the object store, so it should not be
+    /// called too frequently on storages with large manifests.
+    pub async fn health(&self) -> HealthReport {
+        let begin = Instant::now();
+        let root = Path::from(self.path.as_str());
+        let mut stores = vec![("object store", &self.store)];
+        if let Some(tiering) = &self.tiering {
+            stores.push(("local store", &tiering.local_store));
+        }
+        let mut errors = Vec::new();
+        for (name, store) in stores {
+            if let Err(e) = store.list_with_delimiter(Some(&root)).await {
+                errors.push(format!("list {name}, path:{root}, err:{e}"));
+            }
+        }
+        let object_store = if errors.is_empty() {
+            CheckResult::healthy(begin.elapsed())
+        } else {
+            CheckResult::new(HealthStatus::Unhealthy, errors.join("; "), begin.elapsed())
+        };
+
+        let begin = Instant::now();
+        let manifest_path = format!("{}/{}", self.path, crate::manifest::PREFIX_PATH);
+        let manifest = match Manifest::verify(&manifest_path, self.store.clone()).await {
+            Ok(_) => CheckResult::healthy(begin.elapsed()),
+            Err(e) => CheckResult::new(
+                HealthStatus::Unhealthy,
+                format!("load manifest, path:{manifest_path}, err:{e}"),
+                begin.elapsed(),
+            ),
+        };
+
+        let begin = Instant::now();
+        let ssts = self.manifest.all_ssts().await;
+        let max_timestamp = ssts
+            .iter()
+            .map(|f| Timestamp(*f.meta.time_range.end - 1))
+            .max();
+        let clock = match (SystemTime::now().duration_since(UNIX_EPOCH), max_timestamp) {
+            (Err(e), _) => CheckResult::new(
+                HealthStatus::Unhealthy,
+                format!("local clock is before unix epoch, err:{e}"),
+                begin.elapsed(),
+            ),
+            (Ok(now), Some(max_timestamp)) => {
+                let now = TimeUnit::Nanosecond.convert(now.as_nanos() as i64, self.time_unit);
+                let max_skew = TimeUnit::Nanosecond.convert(
+                    self.health_options.max_clock_skew.as_nanos() as i64,
+                    self.time_unit,
+                );
+                if max_timestamp.0.saturating_sub(now) > max_skew {
+                    CheckResult::new(
+                        HealthStatus::Degraded,
+                        format!(
+                            "max committed timestamp is ahead of the local clock, \
+                             max_timestamp:{}, now:{now}",
+                            max_timestamp.0
+                        ),
+                        begin.elapsed(),
+                    )
+                } else {
+                    CheckResult::healthy(begin.elapsed())
+                }
+            }
+            (Ok(_), None) => CheckResult::healthy(begin.elapsed()),
+        };
+
+        let begin = Instant::now();
+        let l0_ssts = ssts
+            .iter()
+            .filter(|f| f.meta.level == LEVEL_0)
+            .collect::<Vec<_>>();
+        let l0_files = l0_ssts.len();
+        let l0_bytes = l0_ssts.iter().map(|f| f.meta.size as u64).sum();
+        let pending_compactions = self.pending_compactions.lock().unwrap().len();
+        let compaction = if l0_files > self.health_options.max_l0_files {
+            CheckResult::new(
+                HealthStatus::Degraded,
+                format!(
+                    "too many L0 ssts, files:{l0_files}, limit:{}",
+                    self.health_options.max_l0_files
+                ),
+                begin.elapsed(),
+            )
+        } else {
+            CheckResult::healthy(begin.elapsed())
+        };
+
+        HealthReport {
+            object_store,
+            manifest,
+            clock,
+            compaction,
+            max_timestamp,
+            l0_files,
+            l0_bytes,
+            pending_compactions,
+        }
+    }
+
+    /// Export ssts overlapping with `req.range` to `req.store` as plain parquet
+    /// files partitioned by date, only columns of the user schema are kept.
+    pub async fn export(&self, req: ExportRequest) -> Result<ExportResult> {
+        let ssts = self.manifest.find_ssts(&req.range).await;
+        let mut result = ExportResult::default();
+        for sst in &ssts {
+            let src_path = Path::from(self.build_file_path(sst.id));
+            let files = export::export_sst(
+                self.store_of(sst).clone(),
+                src_path,
+                sst,
+                self.schema(),
+                &self.codecs,
+                self.timestamp_index,
+                self.time_unit,
+                &req,
+            )
+            .await?;
+            result.files.extend(files);
+        }
+
+        Ok(result)
+    }
+
+    /// Backup the current manifest and data files it references, see
+    /// [backup](crate::backup) for the layout of backups.
+    pub async fn backup(&self, req: BackupRequest) -> Result<BackupResult> {
+        let ssts = self.manifest.all_ssts().await;
+        let tombstones = self.manifest.all_tombstones().await;
+        let leveled_compaction = self.manifest.leveled_compaction().await;
+        backup::backup(
+            &self.store,
+            self.tiering.as_ref().map(|tiering| &tiering.local_store),
+            &self.path,
+            ssts,
+            tombstones,
+            leveled_compaction,
+            &req,
+        )
+        .await
+    }
+
+    /// Restore the backup with `backup_id`, or the latest backup when it's
+    /// `None`, into `target_root` of the store of this storage.
+    ///
+    /// The root of this storage is never overwritten, the restored root should
+    /// be opened by a new storage.
+    pub async fn restore_to(
+        &self,
+        backup_store: &ObjectStoreRef,
+        backup_prefix: &str,
+        backup_id: Option<u64>,
+        target_root: &str,
+    ) -> Result<RestoreResult> {
+        ensure!(
+            target_root.trim_end_matches('/') != self.path.trim_end_matches('/'),
+            "restore into the root of a running storage, root:{target_root}"
+        );
+
+        backup::restore(
+            backup_store,
+            backup_prefix,
+            backup_id,
+            &self.store,
+            target_root,
+        )
+        .await
+    }
+
+    /// Register existing parquet files into the manifest.
+    ///
+    /// Files are validated against the schema first, files whose rows are
+    /// sorted by primary keys are copied to the data directory directly, others
+    /// are re-sorted before being registered.
+    pub async fn import(&self, req: ImportRequest) -> Result<ImportResult> {
+        let mut result = ImportResult::default();
+        let mut ssts = Vec::with_capacity(req.paths.len());
+        for path in req.paths {
+            let object_meta = self
+                .store
+                .head(&path)
+                .await
+                .storage_io(|| format!("get object meta, path:{path}"))?;
+            let reader = ParquetObjectReader::new(self.store.clone(), object_meta.clone());
+            let builder = ParquetRecordBatchStreamBuilder::new(reader)
+                .await
+                .with_context(|| format!("read parquet metadata, path:{path}"))?;
+            ensure!(
+                builder.schema().fields() == self.schema().fields(),
+                Error::SchemaMismatch {
+                    msg: format!("schema of sst not match, path:{path}"),
+                }
+            );
+
+            let metadata = builder.metadata().clone();
+            let time_range = self.time_range_from_metadata(&metadata)?;
+            let num_rows = metadata.file_metadata().num_rows() as u32;
+            let declared_sorted = self.declares_sorted_by_primary_key(&metadata);
+            let batches = builder
+                .build()
+                .context("build parquet stream")?
+                .try_collect::<Vec<_>>()
+                .await
+                .with_context(|| format!("read parquet file, path:{path}"))?;
+            let batch = concat_batches(self.schema(), &batches).context("concat batches")?;
+            self.check_null_keys(&batch)?;
+            // The declared sorting columns are only a hint, the order of rows is
+            // always checked since the file may be written by others. Copied files
+            // are not indexed.
+            let copy = declared_sorted && self.is_sorted_by_primary_key(&batch)?;
+            let WriteResult {
+                id: file_id,
+                size: file_size,
+                inverted_index_size,
+                aggregates,
+                tier,
+                min_key,
+                max_key,
+                checksum,
+                null_columns,
+            } = if copy {
+                let file_id = self.manifest.allocate_id().await?;
+                let file_path = Path::from(self.build_file_path(file_id));
+                self.store
+                    .copy(&path, &file_path)
+                    .await
+                    .with_context(|| format!("copy file, from:{path}, to:{file_path}"))?;
+                // Aggregates, keys and checksum are not computed for copied files.
+                WriteResult {
+                    id: file_id,
+                    size: object_meta.size,
+                    inverted_index_size: 0,
+                    aggregates: Vec::new(),
+                    tier: Tier::Cloud,
+                    min_key: Vec::new(),
+                    max_key: Vec::new(),
+                    checksum: None,
+                    null_columns: Vec::new(),
+                }
+            } else {
+                let batch = if self.merge_mode == MergeMode::MergeOnWrite {
+                    let sequence = self.manifest.allocate_id().await?;
+                    self.with_row_sequence(batch, sequence)?
+                } else {
+                    batch
+                };
+                let write_result = self.write_batch(WriteRequest { batch }).await?;
+                result.num_resorted += 1;
+                write_result
+            };
+
+            ssts.push(SstFile {
+                id: file_id,
+                meta: FileMeta {
+                    max_sequence: file_id,
+                    num_rows,
+                    size: file_size as u32,
+                    time_range,
+                    level: LEVEL_0,
+                    inverted_index_size: inverted_index_size as u32,
+                    aggregates,
+                    tier,
+                    min_key,
+                    max_key,
+                    checksum,
+                    null_columns,
+                },
+            });
+            result.files.push((path, file_id));
+        }
+        self.manifest.add_files(ssts).await?;
+
+        Ok(result)
+    }
+
+    fn time_range_from_metadata(&self, metadata: &ParquetMetaData) -> Result<TimeRange> {
+        ensure!(metadata.num_row_groups() > 0, "parquet file is empty");
+
+        let mut start = Timestamp::MAX;
+        let mut end = Timestamp::MIN;
+        for row_group in metadata.row_groups() {
+            let Some(Statistics::Int64(stats)) =
+                row_group.column(self.timestamp_index).statistics()
+            else {
+                return Err(anyhow::anyhow!("timestamp statistics is missing").into());
+            };
+            let (Some(min), Some(max)) = (stats.min_opt(), stats.max_opt()) else {
+                return Err(anyhow::anyhow!("timestamp min/max is missing").into());
+            };
+            start = start.min(Timestamp(*min));
+            end = end.max(Timestamp(*max));
+        }
+
+        TimeRange::try_from_inclusive(start, end)
+    }
+
+    /// Whether sorting columns of every row group declare the rows are sorted
+    /// by primary keys, as the files written by us do.
+    fn declares_sorted_by_primary_key(&self, metadata: &ParquetMetaData) -> bool {
+        let expected = Self::primary_key_sorting_columns(
+            self.num_primary_key,
+            self.timestamp_index,
+            self.time_order,
+            self.null_keys,
+        );
+        metadata.row_groups().iter().all(|row_group| {
+            row_group
+                .sorting_columns()
+                .is_some_and(|columns| columns.starts_with(&expected))
+        })
+    }
+
+    /// Whether rows of `batch` are sorted by primary keys in the order of the
+    /// ssts written by this storage.
+    fn is_sorted_by_primary_key(&self, batch: &RecordBatch) -> Result<bool> {
+        let sort_fields = (0..self.num_primary_key)
+            .map(|i| {
+                let options = SortOptions {
+                    descending: i == self.timestamp_index && self.time_order == TimeOrder::Desc,
+                    nulls_first: self.null_keys.nulls_first(),
+                };
+                SortField::new_with_options(batch.schema().field(i).data_type().clone(), options)
+            })
+            .collect();
+        let converter = RowConverter::new(sort_fields).context("create row converter")?;
+        let rows = converter
+            .convert_columns(&batch.columns()[..self.num_primary_key])
+            .context("convert primary key columns")?;
+
+        Ok(rows.iter().zip(rows.iter().skip(1)).all(|(a, b)| a <= b))
+    }
+
+    fn primary_key_sorting_columns(
+        num_primary_key: usize,
+        timestamp_index: usize,
+        time_order: TimeOrder,
+        null_keys: NullKeyPolicy,
+    ) -> Vec<SortingColumn> {
+        (0..num_primary_key)
+            .map(|i| {
+                let desc = i == timestamp_index && time_order == TimeOrder::Desc;
+                SortingColumn::new(i as i32, desc, null_keys.nulls_first())
+            })
+            .collect()
+    }
+
+    /// Fails if nulls of primary keys are rejected and `batch` contains any.
+    fn check_null_keys(&self, batch: &RecordBatch) -> Result<()> {
+        if self.null_keys != NullKeyPolicy::Reject {
+            return Ok(());
+        }
+        for i in 0..self.num_primary_key {
+            let num_nulls = batch.column(i).null_count();
+            if num_nulls > 0 {
+                return Err(Error::NullPrimaryKey {
+                    column: self.schema().field(i).name().clone(),
+                    num_nulls,
+                });
+            }
+        }
+
+        Ok(())
+    }
+
+    fn build_write_props(
+        write_options: WriteOptions,
+        schema: &Schema,
+        num_primary_key: usize,
+        timestamp_index: usize,
+    ) -> WriterProperties {
+        let sorting_columns = write_options.enable_sorting_columns.then(|| {
+            Self::primary_key_sorting_columns(
+                num_primary_key,
+                timestamp_index,
+                write_options.time_order,
+                write_options.null_keys,
+            )
+        });
+
+        let mut builder = WriterProperties::builder()
+            .set_max_row_group_size(write_options.max_row_group_size)
+            .set_write_batch_size(write_options.write_bacth_size)
+            .set_sorting_columns(sorting_columns)
+            .set_dictionary_enabled(write_options.enable_dict)
+            .set_bloom_filter_enabled(write_options.enable_bloom_filter)
+            .set_statistics_enabled(if write_options.enable_page_index {
+                EnabledStatistics::Page
+            } else {
+                EnabledStatistics::Chunk
+            })
+            .set_encoding(write_options.encoding)
+            .set_compression(write_options.compression);
+
+        // Defaults of value types the global encoding is not meant for, they
+        // are overridden by the column options.
+        for field in schema.fields().iter().skip(num_primary_key) {
+            let col_path = ColumnPath::new(vec![field.name().to_string()]);
+            match field.data_type() {
+                DataType::Boolean => {
+                    builder = builder
+                        .set_column_dictionary_enabled(col_path.clone(), false)
+                        .set_column_encoding(col_path, Encoding::RLE);
+                }
+                // Stored as fixed length byte arrays, which only support few
+                // encodings.
+                DataType::Decimal128(precision, _) if *precision > 18 => {
+                    builder = builder.set_column_encoding(col_path, Encoding::PLAIN);
+                }
+                _ => {}
+            }
+        }
+
+        if write_options.column_options.is_none() {
+            return builder.build();
+        }
+
+        for (col_name, col_opt) in write_options.column_options.unwrap() {
+            let col_path = ColumnPath::new(vec![col_name.to_string()]);
+            if let Some(enable_dict) = col_opt.enable_dict {
+                builder = builder.set_column_dictionary_enabled(col_path.clone(), enable_dict);
+            }
+            if let Some(enable_bloom_filter) = col_opt.enable_bloom_filter {
+                builder =
+                    builder.set_column_bloom_filter_enabled(col_path.clone(), enable_bloom_filter);
+            }
+            if let Some(encoding) = col_opt.encoding {
+                builder = builder.set_column_encoding(col_path.clone(), encoding);
+            }
+            // Values are encoded by the codec into one blob per row group,
+            // which gains nothing from the parquet encodings and statistics.
+            if col_opt.codec.is_some() {
+                builder = builder
+                    .set_column_dictionary_enabled(col_path.clone(), false)
+                    .set_column_bloom_filter_enabled(col_path.clone(), false)
+                    .set_column_statistics_enabled(col_path.clone(), EnabledStatistics::None)
+                    .set_column_encoding(col_path.clone(), Encoding::PLAIN);
+            }
+            if let Some(compression) = col_opt.compression {
+                builder = builder.set_column_compression(col_path, compression);
+            }
+        }
+
+        builder.build()
+    }
+}
+
+#[async_trait]
+impl TimeMergeStorage for CloudObjectStorage {
+    fn schema(&self) -> &SchemaRef {
+        &self.arrow_schema
+    }
+
+    async fn write(&self, req: WriteRequest) -> Result<()> {
+        match self.merge_mode {
+            MergeMode::Append => self.append(req).await,
+            MergeMode::MergeOnWrite => self.upsert(req).await,
+        }
+    }
+
+    async fn upsert(&self, req: WriteRequest) -> Result<()> {
+        ensure!(
+            self.merge_mode == MergeMode::MergeOnWrite,
+            "upsert requires merge-on-write mode"
+        );
+        self.write_rows(req, true).await
+    }
+
+    async fn append(&self, req: WriteRequest) -> Result<()> {
+        self.write_rows(req, false).await
+    }
+
+    async fn scan(&self, req: ScanRequest) -> Result<SendableRecordBatchStream> {
+        req.range.check()?;
+        // Results at older versions are never cached.
+        let cache = self
+            .result_cache
+            .as_ref()
+            .filter(|_| req.snapshot_version.is_none());
+        let Some(cache) = cache else {
+            let (stream, _) = self.scan_with_stats(req).await?;
+            return Ok(stream);
+        };
+
+        // Taken before the scan, so ssts added during the scan only make the
+        // cached result stale at once, rather than missing from it.
+        let version = RangeVersion::new(
+            self.manifest
+                .find_ssts(&req.range)
+                .await
+                .into_iter()
+                .map(|f| f.id)
+                .collect(),
+            self.manifest
+                .find_tombstones(&req.range)
+                .await
+                .into_iter()
+                .map(|t| t.id)
+                .collect(),
+        );
+        let key = self.result_cache_key(&req);
+        if let Some((schema, batches)) = cache.get(&key, &version)? {
+            let stream =
+                MemoryStream::try_new(batches, schema, None).context("build cached stream")?;
+            return Ok(Box::pin(stream));
+        }
+
+        let (stream, _) = self.scan_with_stats(req).await?;
+        Ok(Box::pin(CachingStream::new(
+            stream,
+            cache.clone(),
+            key,
+            version,
+        )))
+    }
+
+    #[tracing::instrument(
+        name = "storage.compact",
+        skip_all,
+        fields(
+            path = %self.path,
+            range = ?req.range,
+            files_merged = tracing::field::Empty,
+            bytes_read = tracing::field::Empty,
+            bytes_written = tracing::field::Empty,
+            cancelled = tracing::field::Empty,
+        )
+    )]
+    async fn compact(&self, req: CompactRequest) -> Result<CompactionReport> {
+        self.ensure_writable()?;
+        let begin = Instant::now();
+        let _guard = self.compaction_lock.lock().await;
+        let mut control = CompactControl::new(req);
+        let buckets = std::mem::take(&mut *self.pending_compactions.lock().unwrap());
+        let mut result = CompactionReport::default();
+        if let (Some(options), false) = (&self.compact_on_read, buckets.is_empty()) {
+            let ssts = self.manifest.all_ssts().await;
+            for bucket in buckets {
+                let files = ssts
+                    .iter()
+                    .filter(|f| {
+                        f.meta.level == LEVEL_0
+                            && (f.meta.size as usize) < options.small_file_size
+                            && self.bucket_of(f, options) == bucket
+                    })
+                    .cloned()
+                    .collect::<Vec<_>>();
+                if files.len() <= 1 {
+                    continue;
+                }
+                // Buckets skipped are left to the next compaction.
+                if !files.iter().any(|f| control.overlaps(&f.meta.time_range))
+                    || !control.admit(&files).await
+                {
+                    self.pending_compactions.lock().unwrap().insert(bucket);
+                    continue;
+                }
+                let report = self.compact_files(files).await?;
+                control.done(&report);
+                result.merge(report);
+            }
+        }
+        let leveled_compaction = self.leveled_compaction();
+        if let Some(options) = &leveled_compaction {
+            result.merge(self.compact_levels(options, &mut control).await?);
+        }
+        result.cancelled = control.is_cancelled();
+        result.duration = begin.elapsed();
+        let span = tracing::Span::current();
+        span.record("files_merged", result.files_merged());
+        span.record("bytes_read", result.bytes_read);
+        span.record("bytes_written", result.bytes_written);
+        span.record("cancelled", result.cancelled);
+
+        Ok(result)
+    }
+
+    async fn delete(&self, req: DeleteRequest) -> Result<()> {
+        req.range.check()?;
+        ensure!(
+            req.key_range.is_all() || self.leading_key_index().is_some(),
+            "key range is not supported without series keys"
+        );
+
+        // Sequences of ssts are their file ids, so ssts written before the
+        // tombstone always have smaller sequences.
+        let id = self.manifest.allocate_id().await?;
+        self.manifest
+            .add_tombstone(Tombstone {
+                id,
+                sequence: id,
+                key_range: req.key_range,
+                time_range: req.range,
+            })
+            .await
+    }
+
+    async fn aggregate(&self, req: AggregateRequest) -> Result<AggregateResult> {
+        req.range.check()?;
+        ensure!(
+            self.schema().index_of(&req.column).is_ok(),
+            "column not found, column:{}",
+            req.column
+        );
+        let ssts = self.manifest.find_ssts(&req.range).await;
+        let tombstones = self.manifest.all_tombstones().await;
+
+        Ok(aggregate_ssts(&ssts, &tombstones, &req, self.merge_mode))
+    }
+}
+
+/// Keep columns of `batch` at `projection`, all of them if it's `None`.
+fn project_batch(batch: RecordBatch, projection: Option<&[usize]>) -> Result<RecordBatch> {
+    match projection {
+        Some(projection) => Ok(batch.project(projection).context("project batch")?),
+        None => Ok(batch),
+    }
+}
+
+/// Aggregate `req` by the metadata of `ssts` overlapping with its range.
+///
+/// It's exact only if every sst lies in the range, has the aggregates of the
+/// column, and has no rows deleted by `tombstones`. Otherwise rows of such ssts
+/// are certainly included, and all rows are included by the rest ssts at most.
+///
+/// In merge-on-write mode, ssts overlapping in time may hold rows of the same
+/// keys, which are merged during scan, so only bounds are returned for them.
+fn aggregate_ssts(
+    ssts: &[SstFile],
+    tombstones: &[Tombstone],
+    req: &AggregateRequest,
+    merge_mode: MergeMode,
+) -> AggregateResult {
+    let mut certain = Vec::with_capacity(ssts.len());
+    // Aggregates of all ssts, `None` if any of them is unknown.
+    let mut all = Some(Vec::with_capacity(ssts.len()));
+    // Upper bound of the count.
+    let mut max_count = 0;
+    for sst in ssts {
+        let aggregate = sst.meta.aggregates.iter().find(|a| a.column == req.column);
+        let contained = req.range.start <= sst.meta.time_range.start
+            && sst.meta.time_range.end <= req.range.end;
+        let deleted = tombstones.iter().any(|t| t.applies_to(sst));
+        if let (Some(aggregate), true, false) = (aggregate, contained, deleted) {
+            certain.push(aggregate);
+        }
+        max_count += aggregate.map_or(sst.meta.num_rows as u64, |a| a.count);
+        all = all.zip(aggregate).map(|(mut all, aggregate)| {
+            all.push(aggregate);
+            all
+        });
+    }
+
+    let count = |aggregates: &[&ColumnAggregate]| aggregates.iter().map(|a| a.count).sum::<u64>();
+    let min = |aggregates: &[&ColumnAggregate]| {
+        aggregates.iter().fold(None, |v, a| sst::min_of(v, a.min))
+    };
+    let max = |aggregates: &[&ColumnAggregate]| {
+        aggregates.iter().fold(None, |v, a| sst::max_of(v, a.max))
+    };
+    let duplicated = merge_mode == MergeMode::MergeOnWrite && any_overlapping(ssts);
+    if duplicated {
+        // Rows of a sst are distinct, and values replaced by later writes are
+        // still in the aggregates.
+        let (lower, upper) = match req.function {
+            AggregateFunction::Count => (
+                certain.iter().map(|a| a.count).max().map(|v| v as f64),
+                Some(max_count as f64),
+            ),
+            AggregateFunction::Sum => (None, None),
+            AggregateFunction::Min => (all.as_deref().and_then(min), None),
+            AggregateFunction::Max => (None, all.as_deref().and_then(max)),
+        };
+        return AggregateResult::Bounded { lower, upper };
+    }
+
+    if certain.len() == ssts.len() {
+        return AggregateResult::Exact(match req.function {
+            AggregateFunction::Count => Some(count(&certain) as f64),
+            AggregateFunction::Sum => {
+                (count(&certain) > 0).then(|| certain.iter().map(|a| a.sum).sum())
+            }
+            AggregateFunction::Min => min(&certain),
+            AggregateFunction::Max => max(&certain),
+        });
+    }
+
+    let (lower, upper) = match req.function {
+        AggregateFunction::Count => (Some(count(&certain) as f64), Some(max_count as f64)),
+        // Values may be negative, so there is no bound of the sum.
+        AggregateFunction::Sum => (None, None),
+        AggregateFunction::Min => (all.as_deref().and_then(min), min(&certain)),
+        AggregateFunction::Max => (max(&certain), all.as_deref().and_then(max)),
+    };
+    AggregateResult::Bounded { lower, upper }
+}
+
+/// Whether any two of `ssts` overlap in time.
+fn any_overlapping(ssts: &[SstFile]) -> bool {
+    let mut ranges: Vec<_> = ssts.iter().map(|f| &f.meta.time_range).collect();
+    ranges.sort_by_key(|r| r.start.clone());
+    // A range overlapping with any later one overlaps with the next one.
+    ranges.windows(2).any(|w| w[0].overlaps(w[1]))
 }
 
-#[async_trait]
-impl TimeMergeStorage for CloudObjectStorage {
-    fn schema(&self) -> &SchemaRef {
-        &self.arrow_schema
+/// Group `ssts` overlapping in time with each other, ssts overlapping with no
+/// others are left out.
+fn overlapping_groups(ssts: &[SstFile]) -> Vec<Vec<SstFile>> {
+    let mut sorted = ssts.to_vec();
+    sorted.sort_by_key(|f| f.meta.time_range.start.clone());
+    let mut groups: Vec<(Timestamp, Vec<SstFile>)> = Vec::new();
+    for sst in sorted {
+        let end = sst.meta.time_range.end.clone();
+        match groups.last_mut() {
+            Some((group_end, group)) if sst.meta.time_range.start < *group_end => {
+                *group_end = group_end.clone().max(end);
+                group.push(sst);
+            }
+            _ => groups.push((end, vec![sst])),
+        }
+    }
+
+    groups
+        .into_iter()
+        .map(|(_, files)| files)
+        .filter(|files| files.len() > 1)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use arrow::{
+        array::{
+            new_null_array, Float64Array, StringArray, TimestampSecondArray, UInt64Array,
+            UInt8Array,
+        },
+        datatypes::{DataType, Decimal128Type, Field, Float64Type, Schema},
+    };
+    use datafusion::prelude::{col, concat, lit};
+    use object_store::{local::LocalFileSystem, memory::InMemory};
+    use parquet::{
+        arrow::{arrow_reader::ArrowReaderOptions, ArrowWriter},
+        file::page_index::index::Index,
+    };
+
+    use super::*;
+    use crate::codec::ValueCodec;
+
+    #[tokio::test]
+    async fn test_scan_by_time() {
+        let table = crate::testing::TableBuilder::new()
+            .tag("host")
+            .field("value", DataType::Float64)
+            .build()
+            .await
+            .unwrap();
+        table.write_series(3, 4).await.unwrap();
+
+        let stream = table
+            .storage
+            .scan(ScanRequest {
+                range: TimeRange::new(Timestamp::MIN, Timestamp::MAX),
+                predicate: Vec::new(),
+                projections: None,
+                limit_per_series: None,
+                output_order: OutputOrder::ByTime,
+                output_exprs: None,
+                memory_limit: None,
+                snapshot_version: None,
+                batch_size: None,
+            })
+            .await
+            .unwrap();
+        let batches = stream.try_collect::<Vec<_>>().await.unwrap();
+        let timestamps = batches
+            .iter()
+            .flat_map(|b| b.column(1).as_primitive::<Int64Type>().values().to_vec())
+            .collect::<Vec<_>>();
+        assert_eq!(12, timestamps.len());
+        assert!(timestamps.is_sorted());
+    }
+
+    #[tokio::test]
+    async fn test_scan_batch_size() {
+        let table = crate::testing::TableBuilder::new()
+            .tag("host")
+            .field("value", DataType::Float64)
+            .build()
+            .await
+            .unwrap();
+        for _ in 0..3 {
+            table.write_series(3, 3).await.unwrap();
+        }
+
+        let stream = table
+            .storage
+            .scan(ScanRequest {
+                range: TimeRange::new(Timestamp::MIN, Timestamp::MAX),
+                predicate: Vec::new(),
+                projections: None,
+                limit_per_series: None,
+                output_order: OutputOrder::ByKey,
+                output_exprs: None,
+                memory_limit: None,
+                snapshot_version: None,
+                batch_size: Some(4),
+            })
+            .await
+            .unwrap();
+        let batches = stream.try_collect::<Vec<_>>().await.unwrap();
+        assert_eq!(
+            vec![4, 4, 4, 4, 4, 4, 3],
+            batches.iter().map(|b| b.num_rows()).collect::<Vec<_>>()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_dictionary_tags() {
+        let table = crate::testing::TableBuilder::new()
+            .dictionary_tag("host")
+            .field("value", DataType::Float64)
+            .build()
+            .await
+            .unwrap();
+        for _ in 0..2 {
+            table.write_series(3, 2).await.unwrap();
+        }
+        let ssts = table.storage.manifest.all_ssts().await;
+        assert!(ssts.iter().all(|f| !f.meta.min_key.is_empty()));
+
+        let stream = table
+            .storage
+            .scan(ScanRequest {
+                range: TimeRange::new(Timestamp::MIN, Timestamp::MAX),
+                predicate: vec![col("host").eq(lit("host-1"))],
+                projections: None,
+                limit_per_series: None,
+                output_order: OutputOrder::ByKey,
+                output_exprs: None,
+                memory_limit: None,
+                snapshot_version: None,
+                batch_size: None,
+            })
+            .await
+            .unwrap();
+        let batches = stream.try_collect::<Vec<_>>().await.unwrap();
+        let hosts = batches
+            .iter()
+            .map(|b| cast(b.column(0), &DataType::Utf8).unwrap())
+            .flat_map(|a| {
+                a.as_string::<i32>()
+                    .iter()
+                    .map(|v| v.unwrap().to_string())
+                    .collect::<Vec<_>>()
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(vec!["host-1"; 4], hosts);
+        assert!(matches!(
+            batches[0].schema().field(0).data_type(),
+            DataType::Dictionary(_, _)
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_decimal_and_boolean_values() {
+        let table = crate::testing::TableBuilder::new()
+            .tag("host")
+            .field("price", DataType::Decimal128(20, 2))
+            .field("up", DataType::Boolean)
+            .build()
+            .await
+            .unwrap();
+        table.write_series(2, 2).await.unwrap();
+
+        let batches = table.scan_all().await.unwrap();
+        assert_eq!(
+            &DataType::Decimal128(20, 2),
+            batches[0].column(2).data_type()
+        );
+        let prices = batches
+            .iter()
+            .flat_map(|b| {
+                b.column(2)
+                    .as_primitive::<Decimal128Type>()
+                    .values()
+                    .to_vec()
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(vec![0, 1, 2, 3], prices);
+        let ups = batches
+            .iter()
+            .flat_map(|b| {
+                b.column(3)
+                    .as_boolean()
+                    .iter()
+                    .flatten()
+                    .collect::<Vec<_>>()
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(vec![true, false, true, false], ups);
+
+        let aggregate = |column: &str, function| {
+            table.storage.aggregate(AggregateRequest {
+                column: column.to_string(),
+                function,
+                range: TimeRange::new(Timestamp::MIN, Timestamp::MAX),
+            })
+        };
+        assert_eq!(
+            AggregateResult::Exact(Some(2.0)),
+            aggregate("up", AggregateFunction::Sum).await.unwrap()
+        );
+        // Decimals are always scanned for exact aggregates.
+        assert!(matches!(
+            aggregate("price", AggregateFunction::Sum).await.unwrap(),
+            AggregateResult::Bounded { .. }
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_sparse_columns() {
+        let table = crate::testing::TableBuilder::new()
+            .tag("host")
+            .field("a", DataType::Float64)
+            .field("b", DataType::Float64)
+            .write_options(WriteOptions {
+                sparse_columns: true,
+                ..Default::default()
+            })
+            .build()
+            .await
+            .unwrap();
+        let batch = table.generator().num_series(2).generate().unwrap();
+        let mut columns = batch.columns().to_vec();
+        columns[3] = new_null_array(&DataType::Float64, batch.num_rows());
+        let sparse = RecordBatch::try_new(batch.schema(), columns).unwrap();
+        table
+            .storage
+            .write(WriteRequest { batch: sparse })
+            .await
+            .unwrap();
+
+        let ssts = table.storage.manifest.all_ssts().await;
+        assert_eq!(vec!["b".to_string()], ssts[0].meta.null_columns);
+        let path = Path::from(table.storage.build_file_path(ssts[0].id));
+        let object_meta = table.store.head(&path).await.unwrap();
+        let reader = ParquetObjectReader::new(table.store.clone(), object_meta);
+        let builder = ParquetRecordBatchStreamBuilder::new(reader).await.unwrap();
+        assert!(builder.schema().field_with_name("b").is_err());
+
+        // Rows of the sparse sst are read with nulls.
+        table.storage.write(WriteRequest { batch }).await.unwrap();
+        let batches = table.scan_all().await.unwrap();
+        let num_nulls = batches
+            .iter()
+            .map(|b| b.column(3).null_count())
+            .sum::<usize>();
+        let num_rows = batches.iter().map(|b| b.num_rows()).sum::<usize>();
+        assert_eq!((2, 4), (num_nulls, num_rows));
+    }
+
+    #[tokio::test]
+    async fn test_column_defaults() {
+        let table = crate::testing::TableBuilder::new()
+            .tag("host")
+            .field("value", DataType::Float64)
+            .build()
+            .await
+            .unwrap();
+        table.write_series(2, 2).await.unwrap();
+
+        // `extra` is added after the sst is written.
+        let mut fields = table.storage.schema().fields().to_vec();
+        fields.push(Arc::new(
+            Field::new("extra", DataType::Float64, true).with_metadata(HashMap::from([(
+                crate::defaults::DEFAULT_VALUE_KEY.to_string(),
+                "0.5".to_string(),
+            )])),
+        ));
+        let storage = CloudObjectStorage::try_new(
+            "/test".to_string(),
+            table.store.clone(),
+            Arc::new(Schema::new(fields)),
+            2,
+            1,
+            WriteOptions::default(),
+        )
+        .await
+        .unwrap();
+        let stream = storage
+            .scan(ScanRequest {
+                range: TimeRange::new(Timestamp::MIN, Timestamp::MAX),
+                predicate: Vec::new(),
+                projections: None,
+                limit_per_series: None,
+                output_order: OutputOrder::ByKey,
+                output_exprs: None,
+                memory_limit: None,
+                snapshot_version: None,
+                batch_size: None,
+            })
+            .await
+            .unwrap();
+        let batches = stream.try_collect::<Vec<_>>().await.unwrap();
+        let extra = batches
+            .iter()
+            .flat_map(|b| b.column(3).as_primitive::<Float64Type>().values().to_vec())
+            .collect::<Vec<_>>();
+        assert_eq!(vec![0.5; 4], extra);
+    }
+
+    #[tokio::test]
+    async fn test_time_order_desc() {
+        let table = crate::testing::TableBuilder::new()
+            .tag("host")
+            .field("value", DataType::Float64)
+            .write_options(WriteOptions {
+                time_order: TimeOrder::Desc,
+                ..Default::default()
+            })
+            .build()
+            .await
+            .unwrap();
+        table.write_series(2, 3).await.unwrap();
+
+        let timestamps = |batches: Vec<RecordBatch>| {
+            batches
+                .iter()
+                .flat_map(|b| b.column(1).as_primitive::<Int64Type>().values().to_vec())
+                .collect::<Vec<_>>()
+        };
+        let batches = table.scan_all().await.unwrap();
+        assert_eq!(vec![2000, 1000, 0, 2000, 1000, 0], timestamps(batches));
+
+        let stream = table
+            .storage
+            .scan(ScanRequest {
+                range: TimeRange::new(Timestamp::MIN, Timestamp::MAX),
+                predicate: Vec::new(),
+                projections: None,
+                limit_per_series: Some(1),
+                output_order: OutputOrder::ByKey,
+                output_exprs: None,
+                memory_limit: None,
+                snapshot_version: None,
+                batch_size: None,
+            })
+            .await
+            .unwrap();
+        let batches = stream.try_collect::<Vec<_>>().await.unwrap();
+        assert_eq!(vec![2000, 2000], timestamps(batches));
+    }
+
+    #[tokio::test]
+    async fn test_limit_per_series_with_projection() {
+        let table = crate::testing::TableBuilder::new()
+            .tag("host")
+            .field("value", DataType::Float64)
+            .build()
+            .await
+            .unwrap();
+        table.write_series(3, 4).await.unwrap();
+
+        // The timestamp is not projected and the columns are reordered.
+        let stream = table
+            .storage
+            .scan(ScanRequest {
+                range: TimeRange::new(Timestamp::MIN, Timestamp::MAX),
+                predicate: Vec::new(),
+                projections: Some(vec![2, 0]),
+                limit_per_series: Some(1),
+                output_order: OutputOrder::ByKey,
+                output_exprs: None,
+                memory_limit: None,
+                snapshot_version: None,
+                batch_size: None,
+            })
+            .await
+            .unwrap();
+        let batches = stream.try_collect::<Vec<_>>().await.unwrap();
+        let batch = concat_batches(&batches[0].schema(), &batches).unwrap();
+        assert_eq!(2, batch.num_columns());
+        assert_eq!(
+            &[3.0, 7.0, 11.0],
+            batch.column(0).as_primitive::<Float64Type>().values()
+        );
+        let hosts = batch.column(1).as_string::<i32>();
+        assert_eq!(
+            vec!["host-0", "host-1", "host-2"],
+            hosts.iter().flatten().collect::<Vec<_>>()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_compact_on_read() {
+        let table = crate::testing::TableBuilder::new()
+            .tag("host")
+            .field("value", DataType::Float64)
+            .build()
+            .await
+            .unwrap();
+        for _ in 0..4 {
+            table.write_series(2, 2).await.unwrap();
+        }
+        let storage = table.storage.with_compact_on_read(CompactOnReadOptions {
+            max_small_files: 2,
+            ..Default::default()
+        });
+        assert_eq!(4, storage.manifest.all_ssts().await.len());
+
+        let stream = storage
+            .scan(ScanRequest {
+                range: TimeRange::new(Timestamp::MIN, Timestamp::MAX),
+                predicate: Vec::new(),
+                projections: None,
+                limit_per_series: None,
+                output_order: OutputOrder::ByKey,
+                output_exprs: None,
+                memory_limit: None,
+                snapshot_version: None,
+                batch_size: None,
+            })
+            .await
+            .unwrap();
+        let batches = stream.try_collect::<Vec<_>>().await.unwrap();
+        assert_eq!(16, batches.iter().map(|b| b.num_rows()).sum::<usize>());
+        let ssts = storage.manifest.all_ssts().await;
+        assert_eq!(1, ssts.len());
+        assert_eq!(16, ssts[0].meta.num_rows);
+
+        // Nothing is scheduled since the bucket is merged inline.
+        let result = storage.compact(CompactRequest::default()).await.unwrap();
+        assert!(result.input_files.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_compact_on_read_during_compaction() {
+        let table = crate::testing::TableBuilder::new()
+            .tag("host")
+            .field("value", DataType::Float64)
+            .build()
+            .await
+            .unwrap();
+        for _ in 0..4 {
+            table.write_series(2, 2).await.unwrap();
+        }
+        let storage = table.storage.with_compact_on_read(CompactOnReadOptions {
+            max_small_files: 2,
+            ..Default::default()
+        });
+
+        // The bucket is only scheduled while another compaction is running.
+        let guard = storage.compaction_lock.lock().await;
+        let stream = storage
+            .scan(ScanRequest {
+                range: TimeRange::new(Timestamp::MIN, Timestamp::MAX),
+                predicate: Vec::new(),
+                projections: None,
+                limit_per_series: None,
+                output_order: OutputOrder::ByKey,
+                output_exprs: None,
+                memory_limit: None,
+                snapshot_version: None,
+                batch_size: None,
+            })
+            .await
+            .unwrap();
+        let batches = stream.try_collect::<Vec<_>>().await.unwrap();
+        assert_eq!(16, batches.iter().map(|b| b.num_rows()).sum::<usize>());
+        assert_eq!(4, storage.manifest.all_ssts().await.len());
+        assert_eq!(1, storage.pending_compactions.lock().unwrap().len());
+        drop(guard);
+
+        let result = storage.compact(CompactRequest::default()).await.unwrap();
+        assert_eq!(4, result.input_files.len());
+        assert_eq!(1, storage.manifest.all_ssts().await.len());
+    }
+
+    #[tokio::test]
+    async fn test_leveled_compaction() {
+        let mut table = crate::testing::TableBuilder::new()
+            .tag("host")
+            .field("value", DataType::Float64)
+            .build()
+            .await
+            .unwrap();
+        table.storage = table
+            .storage
+            .with_leveled_compaction(LeveledCompactionOptions {
+                slice_duration: Duration::from_secs(2),
+                ..Default::default()
+            })
+            .unwrap();
+        let write_at = |start: i64| {
+            let batch = table
+                .generator()
+                .num_series(2)
+                .points_per_series(4)
+                .start(start)
+                .generate()
+                .unwrap();
+            table.storage.write(WriteRequest { batch })
+        };
+        // The second write arrives late and overlaps with the first one.
+        write_at(0).await.unwrap();
+        write_at(1000).await.unwrap();
+
+        let result = table
+            .storage
+            .compact(CompactRequest::default())
+            .await
+            .unwrap();
+        assert_eq!(2, result.input_files.len());
+        assert_eq!(3, result.output_files.len());
+        let mut ssts = table.storage.manifest.all_ssts().await;
+        ssts.sort_by_key(|f| f.meta.time_range.start.clone());
+        assert!(ssts.iter().all(|f| f.meta.level == LEVEL_1));
+        assert!(ssts
+            .windows(2)
+            .all(|w| !w[0].meta.time_range.overlaps(&w[1].meta.time_range)));
+
+        // Only slices overlapping with the new L0 sst are rewritten.
+        write_at(0).await.unwrap();
+        let result = table
+            .storage
+            .compact(CompactRequest::default())
+            .await
+            .unwrap();
+        assert_eq!(3, result.input_files.len());
+        assert_eq!(2, result.output_files.len());
+
+        let (stream, stats) = table
+            .storage
+            .scan_with_stats(ScanRequest {
+                range: TimeRange::new(Timestamp::MIN, Timestamp::MAX),
+                predicate: Vec::new(),
+                projections: None,
+                limit_per_series: None,
+                output_order: OutputOrder::ByKey,
+                output_exprs: None,
+                memory_limit: None,
+                snapshot_version: None,
+                batch_size: None,
+            })
+            .await
+            .unwrap();
+        let batches = stream.try_collect::<Vec<_>>().await.unwrap();
+        assert_eq!(24, batches.iter().map(|b| b.num_rows()).sum::<usize>());
+        assert_eq!(1, stats.sorted_runs);
+    }
+
+    #[tokio::test]
+    async fn test_multi_level_compaction() {
+        let table = crate::testing::TableBuilder::new()
+            .tag("host")
+            .field("value", DataType::Float64)
+            .build()
+            .await
+            .unwrap();
+        let invalid = LeveledCompactionOptions {
+            max_level: 2,
+            level_size_multipliers: vec![0.5],
+            ..Default::default()
+        };
+        assert!(table.storage.set_leveled_compaction(invalid).await.is_err());
+        // L1 always exceeds its target, and every L2 sst holds one timestamp.
+        let options = LeveledCompactionOptions {
+            slice_duration: Duration::from_secs(2),
+            max_level: 2,
+            base_level_bytes: 1,
+            level_size_multipliers: vec![10.0],
+            max_file_bytes: vec![u32::MAX as u64, 1],
+        };
+        table
+            .storage
+            .set_leveled_compaction(options.clone())
+            .await
+            .unwrap();
+
+        table.write_series(2, 4).await.unwrap();
+        table
+            .storage
+            .compact(CompactRequest::default())
+            .await
+            .unwrap();
+        let mut ssts = table.storage.manifest.all_ssts().await;
+        ssts.sort_by_key(|f| f.meta.time_range.start.clone());
+        assert_eq!(4, ssts.len());
+        assert!(ssts
+            .iter()
+            .all(|f| f.meta.level == 2 && f.meta.num_rows == 2));
+        assert!(ssts
+            .windows(2)
+            .all(|w| !w[0].meta.time_range.overlaps(&w[1].meta.time_range)));
+        let num_rows: usize = table
+            .scan_all()
+            .await
+            .unwrap()
+            .iter()
+            .map(|b| b.num_rows())
+            .sum();
+        assert_eq!(8, num_rows);
+
+        // The options are persisted, and take precedence over the default.
+        let reopened = crate::testing::TableBuilder::new()
+            .store(table.store.clone())
+            .tag("host")
+            .field("value", DataType::Float64)
+            .build()
+            .await
+            .unwrap();
+        let storage = reopened
+            .storage
+            .with_leveled_compaction(LeveledCompactionOptions::default())
+            .unwrap();
+        assert_eq!(Some(options), storage.leveled_compaction());
+    }
+
+    #[tokio::test]
+    async fn test_delete_range() {
+        let mut table = crate::testing::TableBuilder::new()
+            .tag("host")
+            .field("value", DataType::Float64)
+            .build()
+            .await
+            .unwrap();
+        table.storage = table
+            .storage
+            .with_leveled_compaction(LeveledCompactionOptions {
+                slice_duration: Duration::from_secs(2),
+                ..Default::default()
+            })
+            .unwrap();
+        let num_rows =
+            |batches: Vec<RecordBatch>| -> usize { batches.iter().map(|b| b.num_rows()).sum() };
+        table.write_series(2, 4).await.unwrap();
+
+        // Delete the first two points of `host-0`.
+        table
+            .storage
+            .delete(DeleteRequest {
+                range: TimeRange::new(Timestamp(0), Timestamp(2000)),
+                key_range: KeyRange {
+                    start: Some("host-0".to_string()),
+                    end: Some("host-1".to_string()),
+                },
+            })
+            .await
+            .unwrap();
+        assert_eq!(6, num_rows(table.scan_all().await.unwrap()));
+        let stream = table
+            .storage
+            .scan(ScanRequest {
+                range: TimeRange::new(Timestamp::MIN, Timestamp::MAX),
+                predicate: Vec::new(),
+                projections: Some(vec![2]),
+                limit_per_series: None,
+                output_order: OutputOrder::None,
+                output_exprs: None,
+                memory_limit: None,
+                snapshot_version: None,
+                batch_size: None,
+            })
+            .await
+            .unwrap();
+        let batches = stream.try_collect::<Vec<_>>().await.unwrap();
+        assert_eq!(1, batches[0].num_columns());
+        assert_eq!(6, num_rows(batches));
+
+        // Rows written after the delete are not affected.
+        table.write_series(2, 4).await.unwrap();
+        assert_eq!(14, num_rows(table.scan_all().await.unwrap()));
+
+        // Deleted rows are dropped by compaction, and so is the tombstone.
+        let result = table
+            .storage
+            .compact(CompactRequest::default())
+            .await
+            .unwrap();
+        assert_eq!(2, result.rows_dropped);
+        assert!(table.storage.manifest.all_tombstones().await.is_empty());
+        assert_eq!(14, num_rows(table.scan_all().await.unwrap()));
+    }
+
+    #[tokio::test]
+    async fn test_compaction_report() {
+        let table = crate::testing::TableBuilder::new()
+            .tag("host")
+            .field("value", DataType::Float64)
+            .build()
+            .await
+            .unwrap();
+        for _ in 0..3 {
+            table.write_series(2, 2).await.unwrap();
+        }
+        let storage = table.storage.with_compact_on_read(CompactOnReadOptions {
+            max_small_files: 2,
+            inline_max_bytes: 0,
+            ..Default::default()
+        });
+        let ssts = storage.manifest.all_ssts().await;
+        assert!(!storage.maybe_compact_on_read(&ssts).await.unwrap());
+
+        let result = storage.compact(CompactRequest::default()).await.unwrap();
+        let mut input_files = result.input_files.clone();
+        input_files.sort_unstable();
+        let mut expected = ssts.iter().map(|f| f.id).collect::<Vec<_>>();
+        expected.sort_unstable();
+        assert_eq!(expected, input_files);
+        assert_eq!(1, result.output_files.len());
+        assert_eq!(
+            ssts.iter().map(|f| f.meta.size as u64).sum::<u64>(),
+            result.bytes_read
+        );
+        assert!(result.bytes_written > 0);
+        assert_eq!(0, result.rows_dropped);
+    }
+
+    #[tokio::test]
+    async fn test_compaction_controls() {
+        let table = crate::testing::TableBuilder::new()
+            .tag("host")
+            .field("value", DataType::Float64)
+            .build()
+            .await
+            .unwrap();
+        for _ in 0..3 {
+            table.write_series(2, 2).await.unwrap();
+        }
+        let storage = table.storage.with_compact_on_read(CompactOnReadOptions {
+            max_small_files: 2,
+            inline_max_bytes: 0,
+            ..Default::default()
+        });
+        let ssts = storage.manifest.all_ssts().await;
+        assert!(!storage.maybe_compact_on_read(&ssts).await.unwrap());
+
+        // Skipped buckets are kept for the next compaction.
+        let cancel = CancellationToken::new();
+        cancel.cancel();
+        let result = storage
+            .compact(CompactRequest {
+                cancel: Some(cancel),
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+        assert!(result.cancelled);
+        assert_eq!(0, result.files_merged());
+        let result = storage
+            .compact(CompactRequest {
+                range: Some(TimeRange::new(Timestamp(i64::MAX - 1), Timestamp::MAX)),
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+        assert_eq!(0, result.files_merged());
+        let result = storage
+            .compact(CompactRequest {
+                max_input_bytes: Some(1),
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+        assert_eq!(0, result.files_merged());
+        assert_eq!(3, storage.manifest.num_ssts().await);
+
+        let result = storage
+            .compact(CompactRequest {
+                range: Some(TimeRange::new(Timestamp::MIN, Timestamp::MAX)),
+                io_rate_limit: Some(1 << 30),
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+        assert!(!result.cancelled);
+        assert_eq!(3, result.files_merged());
+        assert_eq!(1, storage.manifest.num_ssts().await);
+    }
+
+    #[tokio::test]
+    async fn test_page_index() {
+        let table = crate::testing::TableBuilder::new()
+            .tag("host")
+            .field("value", DataType::Float64)
+            .build()
+            .await
+            .unwrap();
+        table.write_series(2, 100).await.unwrap();
+
+        let sst = &table.storage.manifest.all_ssts().await[0];
+        let path = Path::from(table.storage.build_file_path(sst.id));
+        let object_meta = table.store.head(&path).await.unwrap();
+        let reader = ParquetObjectReader::new(table.store.clone(), object_meta);
+        let builder = ParquetRecordBatchStreamBuilder::new_with_options(
+            reader,
+            ArrowReaderOptions::new().with_page_index(true),
+        )
+        .await
+        .unwrap();
+        let metadata = builder.metadata();
+        assert!(metadata.offset_index().is_some());
+        let column_index = metadata.column_index().unwrap();
+        assert!(!matches!(column_index[0][0], Index::NONE));
+    }
+
+    #[tokio::test]
+    async fn test_scan_with_stats() {
+        let table = crate::testing::TableBuilder::new()
+            .tag("host")
+            .field("value", DataType::Float64)
+            .build()
+            .await
+            .unwrap();
+        table.write_series(2, 2).await.unwrap();
+        table
+            .storage
+            .write(WriteRequest {
+                batch: table.generator().start(1_000_000).generate().unwrap(),
+            })
+            .await
+            .unwrap();
+
+        let (stream, stats) = table
+            .storage
+            .scan_with_stats(ScanRequest {
+                range: TimeRange::new(Timestamp(0), Timestamp(1000)),
+                predicate: Vec::new(),
+                projections: None,
+                limit_per_series: None,
+                output_order: OutputOrder::ByKey,
+                output_exprs: None,
+                memory_limit: None,
+                snapshot_version: None,
+                batch_size: None,
+            })
+            .await
+            .unwrap();
+        let batches = stream.try_collect::<Vec<_>>().await.unwrap();
+        assert_eq!(4, batches.iter().map(|b| b.num_rows()).sum::<usize>());
+        assert_eq!(1, stats.files_touched);
+        assert_eq!(1, stats.files_pruned);
+        assert!(stats.bytes_read() > 0);
+        assert!(stats.plan().contains("ParquetExec"));
+    }
+
+    #[tokio::test]
+    async fn test_verify_sst_checksum() {
+        let table = crate::testing::TableBuilder::new()
+            .tag("host")
+            .field("value", DataType::Float64)
+            .build()
+            .await
+            .unwrap();
+        table.write_series(2, 2).await.unwrap();
+        let storage = table
+            .storage
+            .with_checksum_verification(ChecksumVerification::Always);
+        let ssts = storage.manifest.all_ssts().await;
+        assert_eq!(1, ssts.len());
+        assert!(ssts[0].meta.checksum.is_some());
+        let scan = || {
+            storage.scan_with_stats(ScanRequest {
+                range: TimeRange::new(Timestamp::MIN, Timestamp::MAX),
+                predicate: Vec::new(),
+                projections: None,
+                limit_per_series: None,
+                output_order: OutputOrder::ByKey,
+                output_exprs: None,
+                memory_limit: None,
+                snapshot_version: None,
+                batch_size: None,
+            })
+        };
+        assert!(scan().await.is_ok());
+
+        // Flip a byte of the sst.
+        let path = Path::from(storage.build_file_path(ssts[0].id));
+        let mut bytes = table
+            .store
+            .get(&path)
+            .await
+            .unwrap()
+            .bytes()
+            .await
+            .unwrap()
+            .to_vec();
+        bytes[8] ^= 1;
+        table
+            .store
+            .put(&path, PutPayload::from(bytes))
+            .await
+            .unwrap();
+        let Err(err) = scan().await else {
+            panic!("corrupted sst should fail the scan");
+        };
+        assert!(matches!(err, Error::Corruption { path: p, .. } if p == path.as_ref()));
+    }
+
+    #[tokio::test]
+    async fn test_scan_pruned_by_time_range() {
+        let table = crate::testing::TableBuilder::new()
+            .tag("host")
+            .field("value", DataType::Float64)
+            .write_options(WriteOptions {
+                max_row_group_size: 2,
+                ..Default::default()
+            })
+            .build()
+            .await
+            .unwrap();
+        table.write_series(1, 4).await.unwrap();
+
+        let (stream, stats) = table
+            .storage
+            .scan_with_stats(ScanRequest {
+                range: TimeRange::new(Timestamp(2000), Timestamp::MAX),
+                predicate: Vec::new(),
+                projections: None,
+                limit_per_series: None,
+                output_order: OutputOrder::ByKey,
+                output_exprs: None,
+                memory_limit: None,
+                snapshot_version: None,
+                batch_size: None,
+            })
+            .await
+            .unwrap();
+        let batches = stream.try_collect::<Vec<_>>().await.unwrap();
+        let timestamps = batches
+            .iter()
+            .flat_map(|b| b.column(1).as_primitive::<Int64Type>().values().to_vec())
+            .collect::<Vec<_>>();
+        assert_eq!(vec![2000, 3000], timestamps);
+        assert_eq!(1, stats.row_groups_skipped());
+    }
+
+    #[tokio::test]
+    async fn test_scan_pruned_by_bloom_filter() {
+        let table = crate::testing::TableBuilder::new()
+            .tag("host")
+            .field("value", DataType::Float64)
+            .write_options(WriteOptions {
+                enable_bloom_filter: true,
+                ..Default::default()
+            })
+            .build()
+            .await
+            .unwrap();
+        // Ssts with series of host-0..host-1, and host-0..host-3.
+        table.write_series(2, 2).await.unwrap();
+        table.write_series(4, 2).await.unwrap();
+
+        let scan = |predicate| {
+            table.storage.scan_with_stats(ScanRequest {
+                range: TimeRange::new(Timestamp::MIN, Timestamp::MAX),
+                predicate,
+                projections: None,
+                limit_per_series: None,
+                output_order: OutputOrder::ByKey,
+                output_exprs: None,
+                memory_limit: None,
+                snapshot_version: None,
+                batch_size: None,
+            })
+        };
+
+        // The first sst is out of the key range.
+        let (stream, stats) = scan(vec![col("host").eq(lit("host-3"))]).await.unwrap();
+        let batches = stream.try_collect::<Vec<_>>().await.unwrap();
+        assert_eq!(2, batches.iter().map(|b| b.num_rows()).sum::<usize>());
+        assert_eq!(1, stats.files_touched);
+        assert_eq!(1, stats.files_pruned_by_key_range);
+        assert_eq!(0, stats.files_pruned_by_bloom_filter);
+
+        // Within key ranges of both ssts.
+        let (stream, stats) = scan(vec![col("host").eq(lit("host-05"))]).await.unwrap();
+        let batches = stream.try_collect::<Vec<_>>().await.unwrap();
+        assert_eq!(0, batches.iter().map(|b| b.num_rows()).sum::<usize>());
+        assert_eq!(0, stats.files_touched);
+        assert_eq!(2, stats.files_pruned_by_bloom_filter);
+
+        let predicate = col("host").in_list(vec![lit("host-1"), lit("host-5")], false);
+        let (stream, stats) = scan(vec![predicate]).await.unwrap();
+        let batches = stream.try_collect::<Vec<_>>().await.unwrap();
+        assert_eq!(4, batches.iter().map(|b| b.num_rows()).sum::<usize>());
+        assert_eq!(2, stats.files_touched);
+        assert_eq!(0, stats.files_pruned_by_bloom_filter);
+
+        // Predicates on fields are not checked.
+        let (_, stats) = scan(vec![col("value").eq(lit(1.0))]).await.unwrap();
+        assert_eq!(2, stats.files_touched);
+    }
+
+    #[tokio::test]
+    async fn test_scan_pruned_by_key_range() {
+        let table = crate::testing::TableBuilder::new()
+            .tag("host")
+            .field("value", DataType::Float64)
+            .build()
+            .await
+            .unwrap();
+        // Ssts with series of host-0..host-1, and host-0..host-3.
+        table.write_series(2, 2).await.unwrap();
+        table.write_series(4, 2).await.unwrap();
+        let ssts = table.storage.manifest.all_ssts().await;
+        assert!(ssts.iter().all(|f| !f.meta.min_key.is_empty()));
+
+        let scan = |predicate| {
+            table.storage.scan_with_stats(ScanRequest {
+                range: TimeRange::new(Timestamp::MIN, Timestamp::MAX),
+                predicate,
+                projections: None,
+                limit_per_series: None,
+                output_order: OutputOrder::ByKey,
+                output_exprs: None,
+                memory_limit: None,
+                snapshot_version: None,
+                batch_size: None,
+            })
+        };
+        for (predicate, num_rows, files_pruned) in [
+            (col("host").gt(lit("host-1")), 4, 1),
+            (col("host").gt_eq(lit("host-1")), 8, 0),
+            (col("host").lt(lit("host-0")), 0, 2),
+            (col("host").eq(lit("host-2")), 2, 1),
+            (col("host").lt_eq(lit("host-1")), 8, 0),
+        ] {
+            let (stream, stats) = scan(vec![predicate.clone()]).await.unwrap();
+            let batches = stream.try_collect::<Vec<_>>().await.unwrap();
+            let rows = batches.iter().map(|b| b.num_rows()).sum::<usize>();
+            assert_eq!(num_rows, rows, "{predicate}");
+            assert_eq!(files_pruned, stats.files_pruned_by_key_range, "{predicate}");
+        }
+
+        // Key ranges of compacted ssts cover their inputs.
+        table.storage.compact_files(ssts.clone()).await.unwrap();
+        let compacted = table.storage.manifest.all_ssts().await;
+        assert_eq!(1, compacted.len());
+        let min_key = ssts.iter().map(|f| &f.meta.min_key).min().unwrap();
+        let max_key = ssts.iter().map(|f| &f.meta.max_key).max().unwrap();
+        assert_eq!(min_key, &compacted[0].meta.min_key);
+        assert_eq!(max_key, &compacted[0].meta.max_key);
+    }
+
+    #[tokio::test]
+    async fn test_flush_segment() {
+        let table = crate::testing::TableBuilder::new()
+            .tag("host")
+            .field("value", DataType::Float64)
+            .write_options(WriteOptions {
+                flush_segment: Some(Duration::from_secs(2)),
+                ..Default::default()
+            })
+            .build()
+            .await
+            .unwrap();
+        // Timestamps are 0, 1000, ..., 4000 in milliseconds.
+        table.write_series(2, 5).await.unwrap();
+
+        let mut ssts = table.storage.manifest.all_ssts().await;
+        ssts.sort_by_key(|f| f.meta.time_range.start.clone());
+        let ranges = ssts
+            .iter()
+            .map(|f| (*f.meta.time_range.start, *f.meta.time_range.end))
+            .collect::<Vec<_>>();
+        assert_eq!(vec![(0, 1001), (2000, 3001), (4000, 4001)], ranges);
+        assert_eq!(
+            vec![4, 4, 2],
+            ssts.iter().map(|f| f.meta.num_rows).collect::<Vec<_>>()
+        );
+        let batches = table.scan_all().await.unwrap();
+        assert_eq!(10, batches.iter().map(|b| b.num_rows()).sum::<usize>());
+    }
+
+    #[tokio::test]
+    async fn test_write_shards() {
+        let table = crate::testing::TableBuilder::new()
+            .tag("host")
+            .field("value", DataType::Float64)
+            .write_options(WriteOptions {
+                write_shards: 4,
+                ..Default::default()
+            })
+            .build()
+            .await
+            .unwrap();
+        table.write_series(8, 2).await.unwrap();
+        table.write_series(8, 2).await.unwrap();
+
+        // Series are flushed into ssts of sub-shards, and scans merge them.
+        let ssts = table.storage.manifest.all_ssts().await;
+        assert!(ssts.len() > 2 && ssts.len() <= 8, "{}", ssts.len());
+        assert_eq!(32, ssts.iter().map(|f| f.meta.num_rows).sum::<u32>());
+        let batches = table.scan_all().await.unwrap();
+        let hosts = batches
+            .iter()
+            .flat_map(|b| {
+                b.column(0)
+                    .as_string::<i32>()
+                    .iter()
+                    .map(|v| v.unwrap().to_string())
+                    .collect::<Vec<_>>()
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(32, hosts.len());
+        assert!(hosts.is_sorted());
+    }
+
+    #[tokio::test]
+    async fn test_result_cache() {
+        use crate::result_cache::{ResultCache, ResultCacheOptions};
+
+        let table = crate::testing::TableBuilder::new()
+            .tag("host")
+            .field("value", DataType::Float64)
+            .build()
+            .await
+            .unwrap();
+        let batches = [0, 100_000, 5000].map(|start| {
+            table
+                .generator()
+                .num_series(2)
+                .points_per_series(5)
+                .start(start)
+                .generate()
+                .unwrap()
+        });
+        let cache = Arc::new(ResultCache::new(ResultCacheOptions::default()));
+        let storage = table.storage.with_result_cache(cache.clone());
+        storage
+            .write(WriteRequest {
+                batch: batches[0].clone(),
+            })
+            .await
+            .unwrap();
+
+        let scan = || async {
+            let stream = storage
+                .scan(ScanRequest {
+                    range: TimeRange::new(Timestamp(0), Timestamp(10000)),
+                    predicate: Vec::new(),
+                    projections: None,
+                    limit_per_series: None,
+                    output_order: OutputOrder::ByKey,
+                    output_exprs: None,
+                    memory_limit: None,
+                    snapshot_version: None,
+                    batch_size: None,
+                })
+                .await
+                .unwrap();
+            let batches = stream.try_collect::<Vec<_>>().await.unwrap();
+            batches.iter().map(|b| b.num_rows()).sum::<usize>()
+        };
+        let hits = || cache.stats().hits.load(Ordering::Relaxed);
+        assert_eq!(10, scan().await);
+        assert_eq!(0, hits());
+        assert_eq!(10, scan().await);
+        assert_eq!(1, hits());
+
+        // Writes out of the range keep the cached result.
+        storage
+            .write(WriteRequest {
+                batch: batches[1].clone(),
+            })
+            .await
+            .unwrap();
+        assert_eq!(10, scan().await);
+        assert_eq!(2, hits());
+
+        // Writes into the range invalidate it.
+        storage
+            .write(WriteRequest {
+                batch: batches[2].clone(),
+            })
+            .await
+            .unwrap();
+        assert_eq!(20, scan().await);
+        assert_eq!(2, hits());
+        assert_eq!(1, cache.stats().invalidations.load(Ordering::Relaxed));
+    }
+
+    #[tokio::test]
+    async fn test_health() {
+        let table = crate::testing::TableBuilder::new()
+            .tag("host")
+            .field("value", DataType::Float64)
+            .build()
+            .await
+            .unwrap();
+        let now_ms = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_millis() as i64;
+        let batches = [0, now_ms + 3600 * 1000].map(|start| {
+            table
+                .generator()
+                .num_series(2)
+                .points_per_series(2)
+                .start(start)
+                .generate()
+                .unwrap()
+        });
+        let storage = table.storage.with_health_options(HealthOptions {
+            max_l0_files: 1,
+            ..Default::default()
+        });
+        storage
+            .write(WriteRequest {
+                batch: batches[0].clone(),
+            })
+            .await
+            .unwrap();
+        let report = storage.health().await;
+        assert_eq!(HealthStatus::Healthy, report.status());
+        assert_eq!(Some(Timestamp(1000)), report.max_timestamp);
+        assert_eq!(1, report.l0_files);
+
+        // Rows an hour later than now, and one more L0 sst.
+        storage
+            .write(WriteRequest {
+                batch: batches[1].clone(),
+            })
+            .await
+            .unwrap();
+        let report = storage.health().await;
+        assert_eq!(HealthStatus::Degraded, report.status());
+        assert_eq!(HealthStatus::Healthy, report.object_store.status);
+        assert_eq!(HealthStatus::Healthy, report.manifest.status);
+        assert_eq!(HealthStatus::Degraded, report.clock.status);
+        assert_eq!(HealthStatus::Degraded, report.compaction.status);
+        assert_eq!(2, report.l0_files);
+    }
+
+    #[tokio::test]
+    async fn test_scan_pruned_by_field_bloom_filter() {
+        let table = crate::testing::TableBuilder::new()
+            .tag("host")
+            .field("value", DataType::Utf8)
+            .write_options(WriteOptions {
+                max_row_group_size: 2,
+                column_options: Some(HashMap::from([(
+                    "value".to_string(),
+                    ColumnOptions {
+                        enable_dict: None,
+                        enable_bloom_filter: Some(true),
+                        enable_inverted_index: None,
+                        encoding: None,
+                        compression: None,
+                        codec: None,
+                    },
+                )])),
+                ..Default::default()
+            })
+            .build()
+            .await
+            .unwrap();
+        // Row groups of values `value-0..value-1` and `value-2..value-3`.
+        table.write_series(2, 2).await.unwrap();
+
+        // The value is within the statistics of the first row group, which is
+        // pruned by the bloom filter while scanning.
+        let (stream, stats) = table
+            .storage
+            .scan_with_stats(ScanRequest {
+                range: TimeRange::new(Timestamp::MIN, Timestamp::MAX),
+                predicate: vec![col("value").eq(lit("value-00"))],
+                projections: None,
+                limit_per_series: None,
+                output_order: OutputOrder::ByKey,
+                output_exprs: None,
+                memory_limit: None,
+                snapshot_version: None,
+                batch_size: None,
+            })
+            .await
+            .unwrap();
+        let batches = stream.try_collect::<Vec<_>>().await.unwrap();
+        assert_eq!(0, batches.iter().map(|b| b.num_rows()).sum::<usize>());
+        assert_eq!(0, stats.files_pruned_by_bloom_filter);
+        assert_eq!(1, stats.metric("row_groups_pruned_bloom_filter"));
+    }
+
+    #[tokio::test]
+    async fn test_scan_pruned_by_inverted_index() {
+        let table = crate::testing::TableBuilder::new()
+            .tag("host")
+            .field("value", DataType::Float64)
+            .write_options(WriteOptions {
+                max_row_group_size: 2,
+                enable_inverted_index: true,
+                ..Default::default()
+            })
+            .build()
+            .await
+            .unwrap();
+        // Ssts with series of host-0..host-1, and host-0..host-3, every row group
+        // holds one series.
+        table.write_series(2, 2).await.unwrap();
+        table.write_series(4, 2).await.unwrap();
+        let ssts = table.storage.manifest.all_ssts().await;
+        assert!(ssts.iter().all(|f| f.meta.inverted_index_size > 0));
+
+        let scan = |predicate| {
+            table.storage.scan_with_stats(ScanRequest {
+                range: TimeRange::new(Timestamp::MIN, Timestamp::MAX),
+                predicate,
+                projections: None,
+                limit_per_series: None,
+                output_order: OutputOrder::ByKey,
+                output_exprs: None,
+                memory_limit: None,
+                snapshot_version: None,
+                batch_size: None,
+            })
+        };
+
+        let (stream, stats) = scan(vec![col("host").eq(lit("host-3"))]).await.unwrap();
+        let batches = stream.try_collect::<Vec<_>>().await.unwrap();
+        assert_eq!(2, batches.iter().map(|b| b.num_rows()).sum::<usize>());
+        assert_eq!(1, stats.files_touched);
+        assert_eq!(1, stats.files_pruned_by_key_range);
+        assert_eq!(0, stats.files_pruned_by_bloom_filter);
+        assert!(stats.row_groups_skipped() >= 3);
+
+        // Within key ranges of both ssts.
+        let (stream, stats) = scan(vec![col("host").eq(lit("host-05"))]).await.unwrap();
+        let batches = stream.try_collect::<Vec<_>>().await.unwrap();
+        assert_eq!(0, batches.iter().map(|b| b.num_rows()).sum::<usize>());
+        assert_eq!(0, stats.files_touched);
+        assert_eq!(2, stats.files_pruned_by_inverted_index);
+        assert!(stats.row_groups_skipped() >= 3);
+
+        let predicate = col("host").in_list(vec![lit("host-1"), lit("host-5")], false);
+        let (stream, stats) = scan(vec![predicate]).await.unwrap();
+        let batches = stream.try_collect::<Vec<_>>().await.unwrap();
+        assert_eq!(4, batches.iter().map(|b| b.num_rows()).sum::<usize>());
+        assert_eq!(2, stats.files_touched);
+        assert!(stats.row_groups_skipped() >= 4);
+
+        // Indexes of compacted inputs are deleted along with them.
+        let result = table.storage.compact_files(ssts.clone()).await.unwrap();
+        for id in result.input_files {
+            let path = Path::from(table.storage.build_index_path(id));
+            assert!(table.store.head(&path).await.is_err());
+        }
+    }
+
+    #[tokio::test]
+    async fn test_scan_output_exprs() {
+        let table = crate::testing::TableBuilder::new()
+            .tag("dc")
+            .tag("host")
+            .field("value", DataType::Float64)
+            .build()
+            .await
+            .unwrap();
+        table.write_series(2, 2).await.unwrap();
+
+        let stream = table
+            .storage
+            .scan(ScanRequest {
+                range: TimeRange::new(Timestamp::MIN, Timestamp::MAX),
+                predicate: Vec::new(),
+                projections: None,
+                limit_per_series: None,
+                output_order: OutputOrder::ByKey,
+                output_exprs: Some(vec![
+                    concat(vec![col("dc"), lit("/"), col("host")]).alias("series"),
+                    (col("value") * lit(8.0)).alias("value"),
+                ]),
+                memory_limit: None,
+                snapshot_version: None,
+                batch_size: None,
+            })
+            .await
+            .unwrap();
+        let batches = stream.try_collect::<Vec<_>>().await.unwrap();
+        let batch = concat_batches(&batches[0].schema(), &batches).unwrap();
+        assert_eq!(2, batch.num_columns());
+        assert_eq!("series", batch.schema().field(0).name());
+        let series = batch.column(0).as_string::<i32>();
+        assert_eq!("dc-0/host-0", series.value(0));
+        assert_eq!("dc-1/host-1", series.value(3));
+        let values = batch.column(1).as_primitive::<Float64Type>().values();
+        assert_eq!(&[0.0, 8.0, 16.0, 24.0], values.as_ref());
     }
 
-    async fn write(&self, req: WriteRequest) -> Result<()> {
-        ensure!(req.batch.schema_ref().eq(self.schema()), "schema not match");
+    #[tokio::test]
+    async fn test_scan_with_memory_limit() {
+        let table = crate::testing::TableBuilder::new()
+            .tag("host")
+            .field("value", DataType::Float64)
+            .build()
+            .await
+            .unwrap();
+        for _ in 0..8 {
+            table.write_series(1000, 4).await.unwrap();
+        }
+        let expected = table.scan_all().await.unwrap();
 
-        let num_rows = req.batch.num_rows();
-        let time_column = req
-            .batch
-            .column(self.timestamp_index)
-            .as_any()
-            .downcast_ref::<Int64Array>()
-            .context("timestamp column should be int64")?;
+        let storage = table
+            .storage
+            .with_spill_dirs(vec![std::env::temp_dir()])
+            .unwrap();
+        let stream = storage
+            .scan(ScanRequest {
+                range: TimeRange::new(Timestamp::MIN, Timestamp::MAX),
+                predicate: Vec::new(),
+                projections: None,
+                limit_per_series: None,
+                output_order: OutputOrder::ByKey,
+                output_exprs: None,
+                memory_limit: Some(1 << 20),
+                snapshot_version: None,
+                batch_size: None,
+            })
+            .await
+            .unwrap();
+        let batches = stream.try_collect::<Vec<_>>().await.unwrap();
+        let schema = expected[0].schema();
+        assert_eq!(
+            concat_batches(&schema, &expected).unwrap(),
+            concat_batches(&schema, &batches).unwrap()
+        );
+    }
 
-        let mut start = Timestamp::MAX;
-        let mut end = Timestamp::MIN;
-        for v in time_column.values() {
-            start = start.min(Timestamp(*v));
-            end = end.max(Timestamp(*v));
+    #[tokio::test]
+    async fn test_merge_on_write() {
+        let table = crate::testing::TableBuilder::new()
+            .tag("host")
+            .field("value", DataType::Float64)
+            .write_options(WriteOptions {
+                merge_mode: MergeMode::MergeOnWrite,
+                ..Default::default()
+            })
+            .build()
+            .await
+            .unwrap();
+        // Rows of the same keys in one batch are deduplicated when written.
+        let batch = table
+            .generator()
+            .num_series(2)
+            .points_per_series(2)
+            .generate()
+            .unwrap();
+        let batch = concat_batches(&batch.schema(), &[batch.clone(), batch]).unwrap();
+        table.storage.write(WriteRequest { batch }).await.unwrap();
+        let ssts = table.storage.manifest.all_ssts().await;
+        assert_eq!(4, ssts[0].meta.num_rows);
+
+        // Overlapping ssts are merged before scanned, only once by concurrent
+        // scans.
+        table.write_series(2, 2).await.unwrap();
+        table.write_series(2, 2).await.unwrap();
+        let inputs = table.storage.manifest.all_ssts().await;
+        assert_eq!(3, inputs.len());
+        let (first, second) = tokio::join!(table.scan_all(), table.scan_all());
+        for batches in [first.unwrap(), second.unwrap()] {
+            assert_eq!(4, batches.iter().map(|b| b.num_rows()).sum::<usize>());
         }
-        let time_range = TimeRange::new(start, end + 1);
-        let WriteResult {
-            id: file_id,
-            size: file_size,
-        } = self.write_batch(req).await?;
-        let file_meta = FileMeta {
-            max_sequence: file_id, // Since file_id in increasing order, we can use it as sequence.
-            num_rows: num_rows as u32,
-            size: file_size as u32,
-            time_range,
-        };
-        self.manifest.add_file(file_id, file_meta).await?;
+        let ssts = table.storage.manifest.all_ssts().await;
+        assert_eq!(1, ssts.len());
+        assert_eq!(4, ssts[0].meta.num_rows);
 
-        Ok(())
+        // Ssts already compacted are never compacted again.
+        assert!(table.storage.compact_files(inputs).await.is_err());
+        let batches = table.scan_all().await.unwrap();
+        assert_eq!(4, batches.iter().map(|b| b.num_rows()).sum::<usize>());
+        assert_eq!(
+            ssts.iter().map(|f| f.id).collect::<Vec<_>>(),
+            table
+                .storage
+                .manifest
+                .all_ssts()
+                .await
+                .iter()
+                .map(|f| f.id)
+                .collect::<Vec<_>>()
+        );
+
+        // Ssts not overlapping are scanned as is.
+        table
+            .storage
+            .write(WriteRequest {
+                batch: table
+                    .generator()
+                    .num_series(2)
+                    .start(1 << 20)
+                    .generate()
+                    .unwrap(),
+            })
+            .await
+            .unwrap();
+        let batches = table.scan_all().await.unwrap();
+        assert_eq!(6, batches.iter().map(|b| b.num_rows()).sum::<usize>());
+        assert_eq!(2, table.storage.manifest.all_ssts().await.len());
     }
 
-    async fn scan(&self, req: ScanRequest) -> Result<SendableRecordBatchStream> {
-        let ssts = self.manifest.find_ssts(&req.range).await;
-        // we won't use url for selecting object_store.
-        let dummy_url = ObjectStoreUrl::parse("empty://").unwrap();
-        // TODO: we could group ssts based on time range.
-        // TODO: fetch using multiple threads since read from parquet will incur CPU
-        // when convert between arrow and parquet.
-        let file_groups = ssts
+    #[tokio::test]
+    async fn test_upsert_and_append() {
+        let table = crate::testing::TableBuilder::new()
+            .tag("host")
+            .field("value", DataType::Float64)
+            .write_options(WriteOptions {
+                merge_mode: MergeMode::MergeOnWrite,
+                ..Default::default()
+            })
+            .build()
+            .await
+            .unwrap();
+        let schema = table.storage.schema().clone();
+        let make_batch = |hosts: Vec<&str>, values: Vec<f64>| {
+            let timestamps = vec![0; hosts.len()];
+            RecordBatch::try_new(
+                schema.clone(),
+                vec![
+                    Arc::new(StringArray::from(hosts)),
+                    Arc::new(Int64Array::from(timestamps)),
+                    Arc::new(Float64Array::from(values)),
+                ],
+            )
+            .unwrap()
+        };
+        for (host, value) in [("a", 1.0), ("a", 2.0), ("b", 3.0)] {
+            table
+                .storage
+                .upsert(WriteRequest {
+                    batch: make_batch(vec![host], vec![value]),
+                })
+                .await
+                .unwrap();
+        }
+        // The output keeps the max sequence of the first and the last ssts,
+        // while the older row of it still loses to the second sst.
+        let mut ssts = table.storage.manifest.all_ssts().await;
+        ssts.sort_by_key(|f| f.id);
+        table
+            .storage
+            .compact_files(vec![ssts[0].clone(), ssts[2].clone()])
+            .await
+            .unwrap();
+        let batches = table.scan_all().await.unwrap();
+        let batch = concat_batches(&schema, &batches).unwrap();
+        assert_eq!(
+            vec![2.0, 3.0],
+            batch
+                .column(2)
+                .as_primitive::<Float64Type>()
+                .values()
+                .to_vec()
+        );
+
+        // Appended rows are never deduplicated when written.
+        table
+            .storage
+            .append(WriteRequest {
+                batch: make_batch(vec!["c", "c"], vec![4.0, 5.0]),
+            })
+            .await
+            .unwrap();
+        let num_rows = table
+            .storage
+            .manifest
+            .all_ssts()
+            .await
             .iter()
-            .map(|f| PartitionedFile::new(self.build_file_path(f.id), f.meta.size as u64))
-            .collect::<Vec<_>>();
-        let scan_config = FileScanConfig::new(dummy_url, self.schema().clone())
-            .with_file_group(file_groups)
-            .with_projection(req.projections);
+            .map(|f| f.meta.num_rows)
+            .sum::<u32>();
+        assert_eq!(4, num_rows);
+
+        let table = crate::testing::TableBuilder::new()
+            .tag("host")
+            .field("value", DataType::Float64)
+            .build()
+            .await
+            .unwrap();
+        assert!(table
+            .storage
+            .upsert(WriteRequest {
+                batch: make_batch(vec!["a"], vec![1.0]),
+            })
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn test_encoded_columns() {
+        let write_options = |column, codec| WriteOptions {
+            max_row_group_size: 3,
+            column_options: Some(HashMap::from([(
+                String::from(column),
+                ColumnOptions {
+                    enable_dict: None,
+                    enable_bloom_filter: None,
+                    enable_inverted_index: None,
+                    encoding: None,
+                    compression: None,
+                    codec: Some(codec),
+                },
+            )])),
+            ..Default::default()
+        };
+        let builder = || {
+            crate::testing::TableBuilder::new()
+                .tag("host")
+                .field("value", DataType::Float64)
+                .field("count", DataType::Int64)
+        };
+        // Primary keys and unsupported types can't be encoded.
+        for (column, codec) in [
+            ("ts", ValueCodec::DeltaOfDelta),
+            ("count", ValueCodec::Gorilla),
+        ] {
+            let res = builder()
+                .write_options(write_options(column, codec))
+                .build();
+            assert!(res.await.is_err());
+        }
 
-        let mut builder = ParquetExec::builder(scan_config).with_parquet_file_reader_factory(
-            Arc::new(DefaultParquetFileReaderFactory::new(self.store.clone())),
+        let mut write_options = write_options("value", ValueCodec::Gorilla);
+        write_options.column_options.as_mut().unwrap().insert(
+            "count".to_string(),
+            ColumnOptions {
+                enable_dict: None,
+                enable_bloom_filter: None,
+                enable_inverted_index: None,
+                encoding: None,
+                compression: None,
+                codec: Some(ValueCodec::DeltaOfDelta),
+            },
         );
-        if let Some(expr) = conjunction(req.predicate) {
-            let filters = create_physical_expr(&expr, &self.df_schema, &ExecutionProps::new())
-                .context("create pyhsical expr")?;
-            builder = builder.with_predicate(filters);
+        let table = builder()
+            .write_options(write_options)
+            .build()
+            .await
+            .unwrap();
+        let batch = table.write_series(2, 5).await.unwrap();
+        let ssts = table.storage.manifest.all_ssts().await;
+        let path = Path::from(table.storage.build_file_path(ssts[0].id));
+        let object_meta = table.store.head(&path).await.unwrap();
+        let reader = ParquetObjectReader::new(table.store.clone(), object_meta);
+        let builder = ParquetRecordBatchStreamBuilder::new(reader).await.unwrap();
+        assert_eq!(4, builder.metadata().num_row_groups());
+        for column in ["value", "count"] {
+            let field = builder.schema().field_with_name(column).unwrap();
+            assert_eq!(&DataType::Binary, field.data_type());
         }
 
-        let parquet_exec = builder.build();
-        let sort_exprs = self.build_sort_exprs()?;
-        let physical_plan = Arc::new(SortExec::new(sort_exprs, Arc::new(parquet_exec)));
+        let batches = table.scan_all().await.unwrap();
+        assert_eq!(batch, concat_batches(&batch.schema(), &batches).unwrap());
+        assert_eq!(batch, table.storage.read_files(&ssts).await.unwrap());
+    }
 
-        let ctx = SessionContext::default();
-        // TODO: dedup record batch based on primary keys and sequence number.
-        let res =
-            execute_stream(physical_plan, ctx.task_ctx()).context("execute sort physical plan")?;
+    #[tokio::test]
+    async fn test_tiered_storage() {
+        let local_store: ObjectStoreRef = Arc::new(InMemory::new());
+        let mut table = crate::testing::TableBuilder::new()
+            .tag("host")
+            .field("value", DataType::Float64)
+            .build()
+            .await
+            .unwrap();
+        table.storage = table.storage.with_tiering(TieringOptions {
+            local_store: local_store.clone(),
+            migrate_after: Duration::ZERO,
+        });
+        table.write_series(2, 2).await.unwrap();
+        let ssts = table.storage.manifest.all_ssts().await;
+        assert_eq!(Tier::Local, ssts[0].meta.tier);
+        let path = Path::from(table.storage.build_file_path(ssts[0].id));
+        assert!(local_store.head(&path).await.is_ok());
+        assert!(table.store.head(&path).await.is_err());
+        let batches = table.scan_all().await.unwrap();
+        assert_eq!(4, batches.iter().map(|b| b.num_rows()).sum::<usize>());
 
-        Ok(res)
+        let result = table.storage.migrate_tiers().await.unwrap();
+        assert_eq!(1, result.files_migrated);
+        let ssts = table.storage.manifest.all_ssts().await;
+        assert_eq!(Tier::Cloud, ssts[0].meta.tier);
+        assert!(local_store.head(&path).await.is_err());
+        assert!(table.store.head(&path).await.is_ok());
+        let batches = table.scan_all().await.unwrap();
+        assert_eq!(4, batches.iter().map(|b| b.num_rows()).sum::<usize>());
+        let result = table.storage.migrate_tiers().await.unwrap();
+        assert_eq!(0, result.files_migrated);
     }
 
-    async fn compact(&self, req: CompactRequest) -> Result<()> {
-        todo!()
+    #[tokio::test]
+    async fn test_read_replica() {
+        let table = crate::testing::TableBuilder::new()
+            .tag("host")
+            .field("value", DataType::Float64)
+            .build()
+            .await
+            .unwrap();
+        table.write_series(2, 2).await.unwrap();
+        let replica = CloudObjectStorage::open_replica(
+            "/test".to_string(),
+            table.store.clone(),
+            table.storage.schema().clone(),
+            2,
+            1,
+            WriteOptions::default(),
+        )
+        .await
+        .unwrap();
+        assert!(replica.is_replica());
+        let scan = || async {
+            let stream = replica
+                .scan(ScanRequest {
+                    range: TimeRange::new(Timestamp::MIN, Timestamp::MAX),
+                    predicate: Vec::new(),
+                    projections: None,
+                    limit_per_series: None,
+                    output_order: OutputOrder::ByKey,
+                    output_exprs: None,
+                    memory_limit: None,
+                    snapshot_version: None,
+                    batch_size: None,
+                })
+                .await
+                .unwrap();
+            let batches = stream.try_collect::<Vec<_>>().await.unwrap();
+            batches.iter().map(|b| b.num_rows()).sum::<usize>()
+        };
+        assert_eq!(4, scan().await);
+
+        // Writes are visible to the replica after the manifest is refreshed.
+        table.write_series(3, 2).await.unwrap();
+        assert_eq!(4, scan().await);
+        replica.refresh_manifest().await.unwrap();
+        assert_eq!(10, scan().await);
+
+        let batch = table.generator().generate().unwrap();
+        assert!(replica.write(WriteRequest { batch }).await.is_err());
+        assert!(replica.compact(CompactRequest::default()).await.is_err());
+        assert_eq!(2, table.storage.manifest.num_ssts().await);
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use arrow::{
-        array::UInt8Array,
-        datatypes::{DataType, Field, Schema},
-    };
-    use object_store::local::LocalFileSystem;
+    async fn num_rows_at(storage: &CloudObjectStorage, version: u64) -> Result<usize> {
+        let stream = storage
+            .scan(ScanRequest {
+                range: TimeRange::new(Timestamp::MIN, Timestamp::MAX),
+                predicate: Vec::new(),
+                projections: None,
+                limit_per_series: None,
+                output_order: OutputOrder::ByKey,
+                output_exprs: None,
+                memory_limit: None,
+                snapshot_version: Some(version),
+                batch_size: None,
+            })
+            .await?;
+        let batches = stream.try_collect::<Vec<_>>().await.unwrap();
+        Ok(batches.iter().map(|b| b.num_rows()).sum())
+    }
 
-    use super::*;
+    #[tokio::test]
+    async fn test_scan_at_snapshot_version() {
+        let table = crate::testing::TableBuilder::new()
+            .tag("host")
+            .field("value", DataType::Float64)
+            .build()
+            .await
+            .unwrap();
+        let storage = &table.storage;
+        table.write_series(2, 2).await.unwrap();
+        let version = storage.manifest.version();
+        table.write_series(3, 2).await.unwrap();
+        // Rows are appended, so the series written twice are duplicated.
+        assert_eq!(
+            10,
+            table
+                .scan_all()
+                .await
+                .unwrap()
+                .iter()
+                .map(|b| b.num_rows())
+                .sum::<usize>()
+        );
+        assert_eq!(4, num_rows_at(storage, version).await.unwrap());
+        assert_eq!(
+            10,
+            num_rows_at(storage, storage.manifest.version())
+                .await
+                .unwrap()
+        );
+        assert!(matches!(
+            num_rows_at(storage, storage.manifest.version() + 1).await,
+            Err(Error::VersionUnavailable { .. })
+        ));
+
+        // Ssts at the version are deleted by the compaction.
+        storage.compact(CompactRequest::default()).await.unwrap();
+        assert!(matches!(
+            num_rows_at(storage, version).await,
+            Err(Error::VersionUnavailable { .. })
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_retire_compacted_ssts() {
+        let table = crate::testing::TableBuilder::new()
+            .tag("host")
+            .field("value", DataType::Float64)
+            .write_options(WriteOptions {
+                manifest: crate::types::ManifestOptions {
+                    history_retention: Duration::from_secs(3600),
+                    ..Default::default()
+                },
+                ..Default::default()
+            })
+            .build()
+            .await
+            .unwrap();
+        let storage = &table.storage;
+        table.write_series(2, 2).await.unwrap();
+        let version = storage.manifest.version();
+        table.write_series(3, 2).await.unwrap();
+        let inputs = storage.manifest.all_ssts().await;
+        storage.compact(CompactRequest::default()).await.unwrap();
+
+        // Inputs of the compaction are retired, so older versions are still
+        // readable within the retention.
+        let mut retired = storage
+            .manifest
+            .retired_ssts()
+            .await
+            .into_iter()
+            .map(|r| r.sst.id)
+            .collect::<Vec<_>>();
+        retired.sort_unstable();
+        let mut input_ids = inputs.iter().map(|f| f.id).collect::<Vec<_>>();
+        input_ids.sort_unstable();
+        assert_eq!(input_ids, retired);
+        assert_eq!(4, num_rows_at(storage, version).await.unwrap());
+        assert_eq!(0, storage.purge_retired().await.unwrap());
+        for sst in &inputs {
+            let path = Path::from(storage.build_file_path(sst.id));
+            assert!(table.store.head(&path).await.is_ok());
+        }
+
+        // Retired ssts are still readable, but never compacted again.
+        let ssts = storage.manifest.all_ssts().await;
+        assert!(storage.compact_files(inputs).await.is_err());
+        let mut ids = storage
+            .manifest
+            .all_ssts()
+            .await
+            .iter()
+            .map(|f| f.id)
+            .collect::<Vec<_>>();
+        ids.sort_unstable();
+        let mut expected = ssts.iter().map(|f| f.id).collect::<Vec<_>>();
+        expected.sort_unstable();
+        assert_eq!(expected, ids);
+    }
+
+    #[tokio::test]
+    async fn test_normalize_timestamp() {
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("pk", DataType::UInt64, false),
+            Field::new("ts", DataType::Int64, false),
+        ]));
+        let storage = CloudObjectStorage::try_new(
+            "/tmp/storage".to_string(),
+            Arc::new(InMemory::new()),
+            schema,
+            2,
+            1,
+            WriteOptions {
+                time_unit: TimeUnit::Microsecond,
+                ..Default::default()
+            },
+        )
+        .await
+        .unwrap();
+
+        let input_schema = Arc::new(Schema::new(vec![
+            Field::new("pk", DataType::UInt64, false),
+            Field::new(
+                "ts",
+                DataType::Timestamp(arrow::datatypes::TimeUnit::Second, None),
+                false,
+            ),
+        ]));
+        let batch = RecordBatch::try_new(
+            input_schema,
+            vec![
+                Arc::new(UInt64Array::from(vec![1, 2])),
+                Arc::new(TimestampSecondArray::from(vec![1, 2])),
+            ],
+        )
+        .unwrap();
+        let batch = storage.normalize_timestamp(batch).unwrap();
+        assert_eq!(storage.schema(), batch.schema_ref());
+        assert_eq!(
+            &[1_000_000, 2_000_000],
+            batch
+                .column(1)
+                .as_primitive::<Int64Type>()
+                .values()
+                .as_ref()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_adaptive_row_group_size() {
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("pk", DataType::UInt64, false),
+            Field::new("ts", DataType::Int64, false),
+            Field::new("value", DataType::UInt64, false),
+        ]));
+        let storage = CloudObjectStorage::try_new(
+            "/tmp/storage".to_string(),
+            Arc::new(InMemory::new()),
+            schema.clone(),
+            2,
+            1,
+            WriteOptions {
+                target_row_group_bytes: Some(4096),
+                ..Default::default()
+            },
+        )
+        .await
+        .unwrap();
+
+        let num_rows = 1000;
+        let batch = RecordBatch::try_new(
+            schema,
+            vec![
+                Arc::new(UInt64Array::from_iter_values(0..num_rows)),
+                Arc::new(Int64Array::from_iter_values(0..num_rows as i64)),
+                Arc::new(UInt64Array::from_iter_values(0..num_rows)),
+            ],
+        )
+        .unwrap();
+        let row_group_size = storage.row_group_size(&batch);
+        assert!(row_group_size < num_rows as usize);
+        assert_eq!(None, storage.encoded_row_group_size(&[]));
+
+        let WriteResult { id, .. } = storage
+            .write_batch(WriteRequest {
+                batch: batch.clone(),
+            })
+            .await
+            .unwrap();
+        let path = Path::from(storage.build_file_path(id));
+        let object_meta = storage.store.head(&path).await.unwrap();
+        let reader = ParquetObjectReader::new(storage.store.clone(), object_meta);
+        let builder = ParquetRecordBatchStreamBuilder::new(reader).await.unwrap();
+        let row_groups = builder.metadata().row_groups();
+        assert!(row_groups.len() > 1);
+        // The first row group is sized by the in-memory row width.
+        assert_eq!(row_group_size, row_groups[0].num_rows() as usize);
+        // The following ones are sized by the encoded row width of the flushed
+        // row groups.
+        let mut written = 0;
+        for i in 1..row_groups.len() {
+            written += row_groups[i - 1].num_rows() as usize;
+            let expected = storage
+                .encoded_row_group_size(&row_groups[..i])
+                .unwrap()
+                .min(num_rows as usize - written);
+            assert_eq!(expected, row_groups[i].num_rows() as usize);
+        }
+    }
 
     #[tokio::test]
     async fn test_sort_batch() {
@@ -380,4 +5589,375 @@ mod tests {
             offset += length;
         }
     }
+
+    #[tokio::test]
+    async fn test_null_keys() {
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("host", DataType::Utf8, true),
+            Field::new("ts", DataType::Int64, false),
+            Field::new("value", DataType::Float64, true),
+        ]));
+        let batch = RecordBatch::try_new(
+            schema.clone(),
+            vec![
+                Arc::new(StringArray::from(vec![None, Some("a")])),
+                Arc::new(Int64Array::from(vec![0, 0])),
+                Arc::new(Float64Array::from(vec![1.0, 2.0])),
+            ],
+        )
+        .unwrap();
+        let open = |null_keys| {
+            CloudObjectStorage::try_new(
+                "/test".to_string(),
+                Arc::new(InMemory::new()),
+                schema.clone(),
+                2,
+                1,
+                WriteOptions {
+                    null_keys,
+                    ..Default::default()
+                },
+            )
+        };
+
+        let storage = open(NullKeyPolicy::NullsLast).await.unwrap();
+        let sorted = storage
+            .sort_batch(batch.clone())
+            .await
+            .unwrap()
+            .try_collect::<Vec<_>>()
+            .await
+            .unwrap();
+        let hosts = sorted[0].column(0).as_string::<i32>();
+        assert_eq!(vec![Some("a"), None], hosts.iter().collect::<Vec<_>>());
+
+        let storage = open(NullKeyPolicy::Reject).await.unwrap();
+        let err = storage.write(WriteRequest { batch }).await.unwrap_err();
+        assert!(matches!(
+            err,
+            Error::NullPrimaryKey { column, num_nulls: 1 } if column == "host"
+        ));
+        assert_eq!(0, storage.manifest.num_ssts().await);
+    }
+
+    /// Put `batch` as a parquet file at `path` of the table's store, the file
+    /// declares it's sorted by primary keys if `declare_sorted` is true.
+    async fn put_parquet_file(
+        table: &crate::testing::TestTable,
+        path: &Path,
+        batch: &RecordBatch,
+        declare_sorted: bool,
+    ) {
+        let storage = &table.storage;
+        let sorting_columns = declare_sorted.then(|| {
+            CloudObjectStorage::primary_key_sorting_columns(
+                storage.num_primary_key,
+                storage.timestamp_index,
+                storage.time_order,
+                storage.null_keys,
+            )
+        });
+        let props = WriterProperties::builder()
+            .set_sorting_columns(sorting_columns)
+            .build();
+        let mut buf = Vec::new();
+        let mut writer = ArrowWriter::try_new(&mut buf, batch.schema(), Some(props)).unwrap();
+        writer.write(batch).unwrap();
+        writer.close().unwrap();
+        table.store.put(path, buf.into()).await.unwrap();
+    }
+
+    async fn check_import(declare_sorted: bool, reverse_rows: bool, expected_resorted: usize) {
+        let table = crate::testing::TableBuilder::new()
+            .tag("host")
+            .field("value", DataType::Float64)
+            .build()
+            .await
+            .unwrap();
+        let batch = table
+            .generator()
+            .num_series(3)
+            .points_per_series(4)
+            .generate()
+            .unwrap();
+        let input = if reverse_rows {
+            let indices = UInt32Array::from_iter_values((0..batch.num_rows() as u32).rev());
+            take_record_batch(&batch, &indices).unwrap()
+        } else {
+            batch.clone()
+        };
+        let path = Path::from("/external/data.parquet");
+        put_parquet_file(&table, &path, &input, declare_sorted).await;
+
+        let result = table
+            .storage
+            .import(ImportRequest {
+                paths: vec![path.clone()],
+            })
+            .await
+            .unwrap();
+        assert_eq!(1, result.files.len());
+        assert_eq!(path, result.files[0].0);
+        assert_eq!(expected_resorted, result.num_resorted);
+
+        let batches = table.scan_all().await.unwrap();
+        let scanned = concat_batches(&batch.schema(), &batches).unwrap();
+        assert_eq!(batch, scanned);
+    }
+
+    #[tokio::test]
+    async fn test_import_sorted_file() {
+        check_import(true, false, 0).await;
+    }
+
+    #[tokio::test]
+    async fn test_import_unsorted_file() {
+        check_import(false, true, 1).await;
+    }
+
+    #[tokio::test]
+    async fn test_import_wrongly_declared_sorting() {
+        // The file declares sorting columns but its rows are in reverse order.
+        check_import(true, true, 1).await;
+    }
+
+    #[tokio::test]
+    async fn test_ingest_dedup() {
+        let table = crate::testing::TableBuilder::new()
+            .tag("host")
+            .field("value", DataType::Float64)
+            .build()
+            .await
+            .unwrap();
+        let storage = table
+            .storage
+            .with_ingest_dedup(IngestDedupOptions::default());
+        let generator = crate::testing::SeriesGenerator::new(storage.schema().clone(), 1)
+            .num_series(2)
+            .points_per_series(2);
+        let batch = generator.generate().unwrap();
+        storage.write(WriteRequest { batch }).await.unwrap();
+
+        // Retried rows are dropped, and nothing is written if all are dropped.
+        let batch = generator.generate().unwrap();
+        storage.write(WriteRequest { batch }).await.unwrap();
+        assert_eq!(1, storage.manifest.all_ssts().await.len());
+
+        // Only rows of new timestamps are written.
+        let batch = generator.points_per_series(3).generate().unwrap();
+        storage.write(WriteRequest { batch }).await.unwrap();
+        let ssts = storage.manifest.all_ssts().await;
+        assert_eq!(2, ssts.len());
+        assert_eq!(6, ssts.iter().map(|f| f.meta.num_rows).sum::<u32>());
+    }
+
+    #[tokio::test]
+    async fn test_aggregate_by_file_meta() {
+        let table = crate::testing::TableBuilder::new()
+            .tag("host")
+            .field("value", DataType::Float64)
+            .build()
+            .await
+            .unwrap();
+        // Values 0..4 in [0, 1000], and values 0..2 in [5000, 6000].
+        table.write_series(2, 2).await.unwrap();
+        let batch = table
+            .generator()
+            .start(5000)
+            .points_per_series(2)
+            .generate()
+            .unwrap();
+        table.storage.write(WriteRequest { batch }).await.unwrap();
+
+        let aggregate = |function, start, end| {
+            table.storage.aggregate(AggregateRequest {
+                column: "value".to_string(),
+                function,
+                range: TimeRange::new(Timestamp(start), Timestamp(end)),
+            })
+        };
+        let exact = |v| AggregateResult::Exact(Some(v));
+        let bounded = |lower, upper| AggregateResult::Bounded { lower, upper };
+        assert_eq!(
+            exact(6.0),
+            aggregate(AggregateFunction::Count, 0, 10000).await.unwrap()
+        );
+        assert_eq!(
+            exact(7.0),
+            aggregate(AggregateFunction::Sum, 0, 10000).await.unwrap()
+        );
+        assert_eq!(
+            exact(3.0),
+            aggregate(AggregateFunction::Max, 0, 10000).await.unwrap()
+        );
+        assert_eq!(
+            AggregateResult::Exact(None),
+            aggregate(AggregateFunction::Max, 2000, 3000).await.unwrap()
+        );
+
+        // The latter sst is partially in the range.
+        assert_eq!(
+            bounded(Some(4.0), Some(6.0)),
+            aggregate(AggregateFunction::Count, 0, 5500).await.unwrap()
+        );
+        assert_eq!(
+            bounded(Some(3.0), Some(3.0)),
+            aggregate(AggregateFunction::Max, 0, 5500).await.unwrap()
+        );
+
+        // Rows of the former sst are deleted.
+        table
+            .storage
+            .delete(DeleteRequest {
+                range: TimeRange::new(Timestamp(0), Timestamp(1000)),
+                key_range: KeyRange::all(),
+            })
+            .await
+            .unwrap();
+        assert_eq!(
+            bounded(Some(2.0), Some(6.0)),
+            aggregate(AggregateFunction::Count, 0, 10000).await.unwrap()
+        );
+        assert_eq!(
+            bounded(None, None),
+            aggregate(AggregateFunction::Sum, 0, 10000).await.unwrap()
+        );
+        assert_eq!(
+            bounded(Some(1.0), Some(3.0)),
+            aggregate(AggregateFunction::Max, 0, 10000).await.unwrap()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_aggregate_overlapping_ssts() {
+        let build_table = |merge_mode| {
+            crate::testing::TableBuilder::new()
+                .tag("host")
+                .field("value", DataType::Float64)
+                .write_options(WriteOptions {
+                    merge_mode,
+                    ..Default::default()
+                })
+                .build()
+        };
+        let aggregate = |storage: &CloudObjectStorage, function| {
+            storage.aggregate(AggregateRequest {
+                column: "value".to_string(),
+                function,
+                range: TimeRange::new(Timestamp(0), Timestamp(10000)),
+            })
+        };
+        let bounded = |lower, upper| AggregateResult::Bounded { lower, upper };
+
+        // Values 0..4 are written twice into two overlapping ssts.
+        let table = build_table(MergeMode::MergeOnWrite).await.unwrap();
+        table.write_series(2, 2).await.unwrap();
+        table.write_series(2, 2).await.unwrap();
+        assert_eq!(2, table.storage.manifest.all_ssts().await.len());
+        let storage = &table.storage;
+        assert_eq!(
+            bounded(Some(4.0), Some(8.0)),
+            aggregate(storage, AggregateFunction::Count).await.unwrap()
+        );
+        assert_eq!(
+            bounded(None, None),
+            aggregate(storage, AggregateFunction::Sum).await.unwrap()
+        );
+        assert_eq!(
+            bounded(Some(0.0), None),
+            aggregate(storage, AggregateFunction::Min).await.unwrap()
+        );
+        assert_eq!(
+            bounded(None, Some(3.0)),
+            aggregate(storage, AggregateFunction::Max).await.unwrap()
+        );
+
+        // Overlapping ssts are merged by the scan.
+        assert_eq!(
+            4,
+            table
+                .scan_all()
+                .await
+                .unwrap()
+                .iter()
+                .map(|b| b.num_rows())
+                .sum::<usize>()
+        );
+        assert_eq!(
+            AggregateResult::Exact(Some(4.0)),
+            aggregate(storage, AggregateFunction::Count).await.unwrap()
+        );
+        assert_eq!(
+            AggregateResult::Exact(Some(6.0)),
+            aggregate(storage, AggregateFunction::Sum).await.unwrap()
+        );
+
+        // Rows are never merged in append mode.
+        let table = build_table(MergeMode::Append).await.unwrap();
+        table.write_series(2, 2).await.unwrap();
+        table.write_series(2, 2).await.unwrap();
+        assert_eq!(
+            AggregateResult::Exact(Some(8.0)),
+            aggregate(&table.storage, AggregateFunction::Count)
+                .await
+                .unwrap()
+        );
+        assert_eq!(
+            AggregateResult::Exact(Some(12.0)),
+            aggregate(&table.storage, AggregateFunction::Sum)
+                .await
+                .unwrap()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_reject_empty_range() {
+        let table = crate::testing::TableBuilder::new()
+            .tag("host")
+            .field("value", DataType::Float64)
+            .build()
+            .await
+            .unwrap();
+        table.write_series(2, 2).await.unwrap();
+        let range = || TimeRange::new(Timestamp(2000), Timestamp(1000));
+
+        let err = table
+            .storage
+            .scan(ScanRequest {
+                range: range(),
+                predicate: Vec::new(),
+                projections: None,
+                limit_per_series: None,
+                output_order: OutputOrder::None,
+                output_exprs: None,
+                memory_limit: None,
+                snapshot_version: None,
+                batch_size: None,
+            })
+            .await
+            .err()
+            .unwrap();
+        assert!(matches!(err, Error::TimestampInvalid { .. }));
+
+        let err = table
+            .storage
+            .delete(DeleteRequest {
+                range: range(),
+                key_range: KeyRange::all(),
+            })
+            .await
+            .unwrap_err();
+        assert!(matches!(err, Error::TimestampInvalid { .. }));
+
+        let err = table
+            .storage
+            .aggregate(AggregateRequest {
+                column: "value".to_string(),
+                function: AggregateFunction::Count,
+                range: range(),
+            })
+            .await
+            .unwrap_err();
+        assert!(matches!(err, Error::TimestampInvalid { .. }));
+    }
 }
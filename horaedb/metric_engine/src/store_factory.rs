@@ -0,0 +1,246 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+//! Build the object store from url.
+//!
+//! The backend is chosen by the scheme of the url, and the path of the url is
+//! the root path of the data under the store:
+//! - `s3://{bucket}/{path}`: Amazon S3 and compatible services
+//! - `oss://{bucket}/{path}`: Alibaba Cloud OSS
+//! - `gs://{bucket}/{path}`: Google Cloud Storage
+//! - `az://{container}/{path}`: Azure Blob Storage
+//! - `file:///{path}`: local file system
+//! - `memory:///{path}`: in-memory store, mainly for tests
+//!
+//! Options are the config keys of the builders of `object_store`, e.g.
+//! `region`, `endpoint`, `access_key_id`, and settings absent from options are
+//! read from the environment variables.
+
+use std::{collections::HashMap, str::FromStr, sync::Arc};
+
+use anyhow::Context;
+use macros::ensure;
+use object_store::{
+    aws::{AmazonS3Builder, AmazonS3ConfigKey, AwsCredentialProvider},
+    azure::{AzureConfigKey, AzureCredentialProvider, MicrosoftAzureBuilder},
+    gcp::{GcpCredentialProvider, GoogleCloudStorageBuilder, GoogleConfigKey},
+    local::LocalFileSystem,
+    memory::InMemory,
+};
+
+use crate::{types::ObjectStoreRef, AnyhowError, Result};
+
+/// Credential provider of the backend, overriding the static credentials in
+/// options, e.g. to refresh temporary credentials.
+#[derive(Clone)]
+pub enum StoreCredentials {
+    Aws(AwsCredentialProvider),
+    Gcp(GcpCredentialProvider),
+    Azure(AzureCredentialProvider),
+}
+
+#[derive(Clone, Default)]
+pub struct StoreConfig {
+    pub url: String,
+    pub options: HashMap<String, String>,
+    pub credentials: Option<StoreCredentials>,
+}
+
+impl StoreConfig {
+    pub fn new(url: impl Into<String>) -> Self {
+        Self {
+            url: url.into(),
+            ..Default::default()
+        }
+    }
+
+    pub fn with_option(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        self.options.insert(key.into(), value.into());
+        self
+    }
+
+    pub fn with_credentials(mut self, credentials: StoreCredentials) -> Self {
+        self.credentials = Some(credentials);
+        self
+    }
+}
+
+/// Build the object store of `config`, returns the store and the root path of
+/// the data under it.
+pub fn build_store(config: &StoreConfig) -> Result<(ObjectStoreRef, String)> {
+    let url = &config.url;
+    let (scheme, rest) = url
+        .split_once("://")
+        .with_context(|| format!("invalid store url:{url}"))?;
+    match scheme {
+        "file" => {
+            let dir = rest.trim_end_matches('/');
+            ensure!(dir.starts_with('/'), "path must be absolute, url:{url}");
+            std::fs::create_dir_all(dir).with_context(|| format!("create dir, path:{dir}"))?;
+            let store = LocalFileSystem::new_with_prefix(dir).context("build local store")?;
+            // Paths of the local store are relative to the dir.
+            return Ok((Arc::new(store), String::new()));
+        }
+        "memory" => {
+            let root_path = rest.trim_matches('/').to_string();
+            return Ok((Arc::new(InMemory::new()), root_path));
+        }
+        _ => {}
+    }
+
+    let (bucket, path) = rest.split_once('/').unwrap_or((rest, ""));
+    ensure!(!bucket.is_empty(), "bucket is missing, url:{url}");
+    let root_path = path.trim_matches('/').to_string();
+    let store: ObjectStoreRef = match scheme {
+        "s3" | "s3a" => Arc::new(build_s3(bucket, config)?),
+        "oss" => Arc::new(build_oss(bucket, config)?),
+        "gs" => {
+            let mut builder = GoogleCloudStorageBuilder::from_env().with_bucket_name(bucket);
+            for (key, value) in parse_options::<GoogleConfigKey>(&config.options)? {
+                builder = builder.with_config(key, value);
+            }
+            match &config.credentials {
+                Some(StoreCredentials::Gcp(provider)) => {
+                    builder = builder.with_credentials(provider.clone());
+                }
+                Some(_) => return Err(mismatched_credentials(scheme)),
+                None => {}
+            }
+            Arc::new(builder.build().context("build gcs store")?)
+        }
+        "az" | "azure" => {
+            let mut builder = MicrosoftAzureBuilder::from_env().with_container_name(bucket);
+            for (key, value) in parse_options::<AzureConfigKey>(&config.options)? {
+                builder = builder.with_config(key, value);
+            }
+            match &config.credentials {
+                Some(StoreCredentials::Azure(provider)) => {
+                    builder = builder.with_credentials(provider.clone());
+                }
+                Some(_) => return Err(mismatched_credentials(scheme)),
+                None => {}
+            }
+            Arc::new(builder.build().context("build azure store")?)
+        }
+        _ => return Err(AnyhowError::msg(format!("unsupported store url:{url}")).into()),
+    };
+
+    Ok((store, root_path))
+}
+
+fn s3_builder(bucket: &str, config: &StoreConfig) -> Result<AmazonS3Builder> {
+    let mut builder = AmazonS3Builder::from_env().with_bucket_name(bucket);
+    for (key, value) in parse_options::<AmazonS3ConfigKey>(&config.options)? {
+        builder = builder.with_config(key, value);
+    }
+    match &config.credentials {
+        Some(StoreCredentials::Aws(provider)) => {
+            builder = builder.with_credentials(provider.clone());
+        }
+        Some(_) => return Err(mismatched_credentials("s3")),
+        None => {}
+    }
+
+    Ok(builder)
+}
+
+fn build_s3(bucket: &str, config: &StoreConfig) -> Result<object_store::aws::AmazonS3> {
+    let store = s3_builder(bucket, config)?
+        .build()
+        .context("build s3 store")?;
+    Ok(store)
+}
+
+/// OSS is compatible with S3, but only accepts virtual hosted style requests.
+fn build_oss(bucket: &str, config: &StoreConfig) -> Result<object_store::aws::AmazonS3> {
+    let mut builder = s3_builder(bucket, config)?.with_virtual_hosted_style_request(true);
+    if builder
+        .get_config_value(&AmazonS3ConfigKey::Endpoint)
+        .is_none()
+    {
+        let Some(region) = builder.get_config_value(&AmazonS3ConfigKey::Region) else {
+            return Err(AnyhowError::msg("region or endpoint of oss is required").into());
+        };
+        builder = builder.with_endpoint(format!("https://{bucket}.oss-{region}.aliyuncs.com"));
+    }
+
+    let store = builder.build().context("build oss store")?;
+    Ok(store)
+}
+
+fn parse_options<K>(options: &HashMap<String, String>) -> Result<Vec<(K, &str)>>
+where
+    K: FromStr<Err = object_store::Error>,
+{
+    options
+        .iter()
+        .map(|(key, value)| {
+            let key = key
+                .parse::<K>()
+                .with_context(|| format!("invalid store option:{key}"))?;
+            Ok((key, value.as_str()))
+        })
+        .collect()
+}
+
+fn mismatched_credentials(scheme: &str) -> crate::Error {
+    AnyhowError::msg(format!("credentials mismatch the store, scheme:{scheme}")).into()
+}
+
+#[cfg(test)]
+mod tests {
+    use object_store::{path::Path, ObjectStore, PutPayload};
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_build_store() {
+        let (store, root_path) = build_store(&StoreConfig::new("memory:///data/")).unwrap();
+        assert_eq!("data", root_path);
+        store
+            .put(&Path::from("/data/a"), PutPayload::from_static(b"a"))
+            .await
+            .unwrap();
+
+        let dir = std::env::temp_dir().join(format!("store-factory-{}", std::process::id()));
+        let url = format!("file://{}", dir.display());
+        let (store, root_path) = build_store(&StoreConfig::new(url)).unwrap();
+        assert_eq!("", root_path);
+        store
+            .put(&Path::from("/a"), PutPayload::from_static(b"a"))
+            .await
+            .unwrap();
+        assert!(dir.join("a").exists());
+        std::fs::remove_dir_all(dir).unwrap();
+
+        let config = StoreConfig::new("s3://bucket/data")
+            .with_option("region", "us-east-1")
+            .with_option("access_key_id", "ak")
+            .with_option("secret_access_key", "sk");
+        let (_, root_path) = build_store(&config).unwrap();
+        assert_eq!("data", root_path);
+
+        // Region or endpoint is required by oss.
+        let config = StoreConfig::new("oss://bucket/data")
+            .with_option("access_key_id", "ak")
+            .with_option("secret_access_key", "sk");
+        assert!(build_store(&config).is_err());
+        assert!(build_store(&config.with_option("region", "cn-hangzhou")).is_ok());
+
+        assert!(build_store(&StoreConfig::new("s3://bucket").with_option("unknown", "v")).is_err());
+        assert!(build_store(&StoreConfig::new("hdfs://bucket")).is_err());
+    }
+}
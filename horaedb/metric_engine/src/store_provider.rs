@@ -0,0 +1,124 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+//! Resolve the object store of each tenant.
+//!
+//! Every tenant may keep its data in its own bucket/prefix with its own
+//! credentials, the storage of a tenant is opened with the store and root path
+//! resolved here.
+
+use std::{
+    collections::HashMap,
+    sync::{Arc, RwLock},
+};
+
+use async_trait::async_trait;
+
+use crate::{types::ObjectStoreRef, Result};
+
+/// Object store and root path holding the data of a tenant.
+#[derive(Clone)]
+pub struct TenantStore {
+    pub store: ObjectStoreRef,
+    pub root_path: String,
+}
+
+impl std::fmt::Debug for TenantStore {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("TenantStore")
+            .field("store", &self.store.to_string())
+            .field("root_path", &self.root_path)
+            .finish()
+    }
+}
+
+#[async_trait]
+pub trait StoreProvider: Send + Sync {
+    async fn resolve(&self, tenant: &str) -> Result<TenantStore>;
+}
+
+pub type StoreProviderRef = Arc<dyn StoreProvider>;
+
+/// Provider with dedicated stores registered for some tenants, other tenants
+/// are isolated by path under the root of the default store.
+pub struct StaticStoreProvider {
+    default_store: ObjectStoreRef,
+    default_root_path: String,
+    tenants: RwLock<HashMap<String, TenantStore>>,
+}
+
+impl StaticStoreProvider {
+    pub fn new(default_store: ObjectStoreRef, default_root_path: String) -> Self {
+        Self {
+            default_store,
+            default_root_path,
+            tenants: RwLock::new(HashMap::new()),
+        }
+    }
+
+    /// Register a dedicated store for the tenant, replacing the old one if
+    /// any.
+    pub fn register(&self, tenant: &str, store: TenantStore) {
+        self.tenants
+            .write()
+            .unwrap()
+            .insert(tenant.to_string(), store);
+    }
+}
+
+#[async_trait]
+impl StoreProvider for StaticStoreProvider {
+    async fn resolve(&self, tenant: &str) -> Result<TenantStore> {
+        if let Some(store) = self.tenants.read().unwrap().get(tenant) {
+            return Ok(store.clone());
+        }
+
+        Ok(TenantStore {
+            store: self.default_store.clone(),
+            root_path: format!("{}/{tenant}", self.default_root_path),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use object_store::memory::InMemory;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_static_store_provider() {
+        let default_store: ObjectStoreRef = Arc::new(InMemory::new());
+        let tenant_store: ObjectStoreRef = Arc::new(InMemory::new());
+        let provider = StaticStoreProvider::new(default_store.clone(), "/data".to_string());
+        provider.register(
+            "t1",
+            TenantStore {
+                store: tenant_store.clone(),
+                root_path: "/t1-bucket".to_string(),
+            },
+        );
+
+        let resolved = provider.resolve("t1").await.unwrap();
+        assert!(Arc::ptr_eq(&resolved.store, &tenant_store));
+        assert_eq!(resolved.root_path, "/t1-bucket");
+
+        let resolved = provider.resolve("t2").await.unwrap();
+        assert!(Arc::ptr_eq(&resolved.store, &default_store));
+        assert_eq!(resolved.root_path, "/data/t2");
+    }
+}
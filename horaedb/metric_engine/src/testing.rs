@@ -0,0 +1,333 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+//! Helpers to build tables and synthetic data in tests, enabled by the
+//! `testing` feature.
+//!
+//! ```ignore
+//! let table = TableBuilder::new()
+//!     .tag("host")
+//!     .field("value", DataType::Float64)
+//!     .build()
+//!     .await?;
+//! table.write_series(10, 100).await?;
+//! let batches = table.scan_all().await?;
+//! ```
+
+use std::sync::Arc;
+
+use anyhow::{anyhow, Context};
+use arrow::{
+    array::{
+        ArrayRef, BooleanArray, Decimal128Array, Float64Array, Int64Array, RecordBatch,
+        StringArray, UInt64Array,
+    },
+    compute::cast,
+    datatypes::{DataType, Field, Schema, SchemaRef},
+};
+use datafusion::physical_plan::common::collect;
+use object_store::memory::InMemory;
+
+use crate::{
+    storage::{CloudObjectStorage, OutputOrder, ScanRequest, TimeMergeStorage, WriteRequest},
+    types::{ObjectStoreRef, TimeRange, Timestamp, WriteOptions},
+    Result,
+};
+
+/// Builder of a table with fluent schema definitions.
+///
+/// Columns are laid out as tags, timestamp and fields, and tags together with
+/// the timestamp are the primary keys.
+pub struct TableBuilder {
+    root_path: String,
+    store: Option<ObjectStoreRef>,
+    tags: Vec<(String, DataType)>,
+    timestamp: String,
+    fields: Vec<(String, DataType)>,
+    write_options: WriteOptions,
+}
+
+impl Default for TableBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl TableBuilder {
+    pub fn new() -> Self {
+        Self {
+            root_path: "/test".to_string(),
+            store: None,
+            tags: Vec::new(),
+            timestamp: "ts".to_string(),
+            fields: Vec::new(),
+            write_options: WriteOptions::default(),
+        }
+    }
+
+    pub fn root_path(mut self, root_path: impl Into<String>) -> Self {
+        self.root_path = root_path.into();
+        self
+    }
+
+    /// Object store of the table, an in-memory store is used if not set.
+    pub fn store(mut self, store: ObjectStoreRef) -> Self {
+        self.store = Some(store);
+        self
+    }
+
+    pub fn tag(mut self, name: impl Into<String>) -> Self {
+        self.tags.push((name.into(), DataType::Utf8));
+        self
+    }
+
+    /// Tag of string values encoded as a dictionary with `i32` keys.
+    pub fn dictionary_tag(mut self, name: impl Into<String>) -> Self {
+        let data_type = DataType::Dictionary(Box::new(DataType::Int32), Box::new(DataType::Utf8));
+        self.tags.push((name.into(), data_type));
+        self
+    }
+
+    pub fn timestamp(mut self, name: impl Into<String>) -> Self {
+        self.timestamp = name.into();
+        self
+    }
+
+    pub fn field(mut self, name: impl Into<String>, data_type: DataType) -> Self {
+        self.fields.push((name.into(), data_type));
+        self
+    }
+
+    pub fn write_options(mut self, write_options: WriteOptions) -> Self {
+        self.write_options = write_options;
+        self
+    }
+
+    pub fn build_schema(&self) -> SchemaRef {
+        let tags = self
+            .tags
+            .iter()
+            .map(|(name, data_type)| Field::new(name, data_type.clone(), false));
+        let timestamp = Field::new(&self.timestamp, DataType::Int64, false);
+        let fields = self
+            .fields
+            .iter()
+            .map(|(name, data_type)| Field::new(name, data_type.clone(), true));
+
+        Arc::new(Schema::new(
+            tags.chain(std::iter::once(timestamp))
+                .chain(fields)
+                .collect::<Vec<_>>(),
+        ))
+    }
+
+    pub async fn build(self) -> Result<TestTable> {
+        let schema = self.build_schema();
+        let store = self.store.unwrap_or_else(|| Arc::new(InMemory::new()));
+        let num_tags = self.tags.len();
+        let storage = CloudObjectStorage::try_new(
+            self.root_path,
+            store.clone(),
+            schema,
+            num_tags + 1,
+            num_tags,
+            self.write_options,
+        )
+        .await?;
+
+        Ok(TestTable {
+            storage,
+            store,
+            num_tags,
+        })
+    }
+}
+
+pub struct TestTable {
+    pub storage: CloudObjectStorage,
+    pub store: ObjectStoreRef,
+    num_tags: usize,
+}
+
+impl TestTable {
+    pub fn generator(&self) -> SeriesGenerator {
+        SeriesGenerator::new(self.storage.schema().clone(), self.num_tags)
+    }
+
+    /// Generate and write `num_series` series with `points_per_series` points
+    /// each, the written batch is returned.
+    pub async fn write_series(
+        &self,
+        num_series: usize,
+        points_per_series: usize,
+    ) -> Result<RecordBatch> {
+        let batch = self
+            .generator()
+            .num_series(num_series)
+            .points_per_series(points_per_series)
+            .generate()?;
+        self.storage
+            .write(WriteRequest {
+                batch: batch.clone(),
+            })
+            .await?;
+
+        Ok(batch)
+    }
+
+    pub async fn scan_all(&self) -> Result<Vec<RecordBatch>> {
+        let stream = self
+            .storage
+            .scan(ScanRequest {
+                range: TimeRange::new(Timestamp::MIN, Timestamp::MAX),
+                predicate: Vec::new(),
+                projections: None,
+                limit_per_series: None,
+                output_order: OutputOrder::ByKey,
+                output_exprs: None,
+                memory_limit: None,
+                snapshot_version: None,
+                batch_size: None,
+            })
+            .await?;
+        let batches = collect(stream).await.context("collect scan result")?;
+
+        Ok(batches)
+    }
+}
+
+/// Generator of synthetic series data.
+///
+/// Tag values of the i-th series are `{tag}-{i}`, timestamps start from
+/// `start` with the interval `step`, and field values increase with rows.
+pub struct SeriesGenerator {
+    schema: SchemaRef,
+    num_tags: usize,
+    num_series: usize,
+    points_per_series: usize,
+    start: i64,
+    step: i64,
+}
+
+impl SeriesGenerator {
+    pub fn new(schema: SchemaRef, num_tags: usize) -> Self {
+        Self {
+            schema,
+            num_tags,
+            num_series: 1,
+            points_per_series: 1,
+            start: 0,
+            step: 1000,
+        }
+    }
+
+    pub fn num_series(mut self, num_series: usize) -> Self {
+        self.num_series = num_series;
+        self
+    }
+
+    pub fn points_per_series(mut self, points_per_series: usize) -> Self {
+        self.points_per_series = points_per_series;
+        self
+    }
+
+    pub fn start(mut self, start: i64) -> Self {
+        self.start = start;
+        self
+    }
+
+    pub fn step(mut self, step: i64) -> Self {
+        self.step = step;
+        self
+    }
+
+    pub fn generate(&self) -> Result<RecordBatch> {
+        let num_rows = self.num_series * self.points_per_series;
+        let series =
+            || (0..self.num_series).flat_map(|s| std::iter::repeat(s).take(self.points_per_series));
+
+        let mut columns: Vec<ArrayRef> = Vec::with_capacity(self.schema.fields().len());
+        for (idx, field) in self.schema.fields().iter().enumerate() {
+            let column: ArrayRef = if idx < self.num_tags {
+                let values: ArrayRef = Arc::new(StringArray::from_iter_values(
+                    series().map(|s| format!("{}-{s}", field.name())),
+                ));
+                cast(&values, field.data_type()).context("cast tag column")?
+            } else if idx == self.num_tags {
+                Arc::new(Int64Array::from_iter_values((0..num_rows).map(|i| {
+                    self.start + (i % self.points_per_series) as i64 * self.step
+                })))
+            } else {
+                match field.data_type() {
+                    DataType::Int64 => Arc::new(Int64Array::from_iter_values(
+                        (0..num_rows).map(|i| i as i64),
+                    )),
+                    DataType::UInt64 => Arc::new(UInt64Array::from_iter_values(
+                        (0..num_rows).map(|i| i as u64),
+                    )),
+                    DataType::Float64 => Arc::new(Float64Array::from_iter_values(
+                        (0..num_rows).map(|i| i as f64),
+                    )),
+                    DataType::Utf8 => Arc::new(StringArray::from_iter_values(
+                        (0..num_rows).map(|i| format!("value-{i}")),
+                    )),
+                    DataType::Boolean => Arc::new(BooleanArray::from_iter(
+                        (0..num_rows).map(|i| Some(i % 2 == 0)),
+                    )),
+                    DataType::Decimal128(precision, scale) => Arc::new(
+                        Decimal128Array::from_iter_values((0..num_rows).map(|i| i as i128))
+                            .with_precision_and_scale(*precision, *scale)
+                            .context("build decimal column")?,
+                    ),
+                    data_type => {
+                        return Err(anyhow!("unsupported field type, type:{data_type}").into())
+                    }
+                }
+            };
+            columns.push(column);
+        }
+
+        let batch =
+            RecordBatch::try_new(self.schema.clone(), columns).context("build record batch")?;
+        Ok(batch)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_write_and_scan() {
+        let table = TableBuilder::new()
+            .tag("host")
+            .tag("region")
+            .field("value", DataType::Float64)
+            .field("ok", DataType::Boolean)
+            .build()
+            .await
+            .unwrap();
+
+        let batch = table.write_series(3, 4).await.unwrap();
+        assert_eq!(12, batch.num_rows());
+        assert_eq!(4, batch.num_columns());
+
+        let batches = table.scan_all().await.unwrap();
+        let num_rows: usize = batches.iter().map(|b| b.num_rows()).sum();
+        assert_eq!(12, num_rows);
+    }
+}
@@ -0,0 +1,114 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+//! Tiered storage of ssts.
+//!
+//! With tiering enabled, fresh ssts are written to a fast local store, e.g.
+//! local SSDs, and are migrated to the object store once they are old enough.
+//! Both tiers share the same layout, and the manifest records the tier of
+//! every sst, which decides where it's read from. Inverted indexes are small,
+//! and are always kept in the object store.
+//!
+//! A migrated sst is switched to the cloud tier in the manifest before its
+//! local copy is deleted, so it's always readable from the tier recorded.
+
+use std::{
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use tokio::task::JoinHandle;
+
+use crate::{storage::CloudObjectStorage, types::ObjectStoreRef};
+
+#[derive(Debug, Clone)]
+pub struct TieringOptions {
+    /// Store of fresh ssts.
+    pub local_store: ObjectStoreRef,
+    /// Ssts older than this are migrated to the object store.
+    pub migrate_after: Duration,
+}
+
+impl TieringOptions {
+    pub fn new(local_store: ObjectStoreRef) -> Self {
+        Self {
+            local_store,
+            migrate_after: Duration::from_secs(3600),
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct MigrateResult {
+    pub files_migrated: usize,
+    pub bytes_migrated: u64,
+}
+
+#[derive(Debug, Default)]
+pub struct TierMigrateStats {
+    pub files_migrated: AtomicU64,
+    pub bytes_migrated: AtomicU64,
+    pub failed_rounds: AtomicU64,
+}
+
+/// Background task migrating ssts of a storage to the object store every
+/// `interval`. The task is stopped when dropped.
+pub struct TierMigrator {
+    stats: Arc<TierMigrateStats>,
+    handle: JoinHandle<()>,
+}
+
+impl TierMigrator {
+    pub fn start(storage: Arc<CloudObjectStorage>, interval: Duration) -> Self {
+        let stats = Arc::new(TierMigrateStats::default());
+        let task_stats = stats.clone();
+        let handle = tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(interval);
+            loop {
+                ticker.tick().await;
+                match storage.migrate_tiers().await {
+                    Ok(result) => {
+                        task_stats
+                            .files_migrated
+                            .fetch_add(result.files_migrated as u64, Ordering::Relaxed);
+                        task_stats
+                            .bytes_migrated
+                            .fetch_add(result.bytes_migrated, Ordering::Relaxed);
+                    }
+                    Err(_) => {
+                        task_stats.failed_rounds.fetch_add(1, Ordering::Relaxed);
+                    }
+                }
+            }
+        });
+
+        Self { stats, handle }
+    }
+
+    pub fn stats(&self) -> &TierMigrateStats {
+        &self.stats
+    }
+}
+
+impl Drop for TierMigrator {
+    fn drop(&mut self) {
+        self.handle.abort();
+    }
+}
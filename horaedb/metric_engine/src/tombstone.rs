@@ -0,0 +1,156 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+//! Range tombstones recorded by range deletes.
+//!
+//! Deleted rows are not rewritten immediately, a tombstone is recorded in the
+//! manifest instead. It's applied to the ssts written before it, rows matching
+//! it are filtered out during scan and dropped during compaction, and the
+//! tombstone is removed once all these ssts are compacted.
+
+use datafusion::{
+    logical_expr::{not, utils::disjunction, Expr},
+    prelude::{ident, lit},
+};
+use macros::ensure;
+
+use crate::{
+    sst::{FileId, SstFile},
+    types::{TimeRange, Timestamp},
+    Error,
+};
+
+/// Range of the leading series key, `start` is inclusive and `end` is
+/// exclusive, and `None` means unbounded.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct KeyRange {
+    pub start: Option<String>,
+    pub end: Option<String>,
+}
+
+impl KeyRange {
+    /// Range covering all keys.
+    pub fn all() -> Self {
+        Self::default()
+    }
+
+    pub fn is_all(&self) -> bool {
+        self.start.is_none() && self.end.is_none()
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Tombstone {
+    /// Allocated from the file ids of the manifest.
+    pub id: FileId,
+    /// Rows of ssts whose max sequence is smaller than it are deleted.
+    pub sequence: u64,
+    pub key_range: KeyRange,
+    pub time_range: TimeRange,
+}
+
+impl Tombstone {
+    /// Whether rows of `sst` may be deleted by the tombstone.
+    pub fn applies_to(&self, sst: &SstFile) -> bool {
+        sst.meta.max_sequence < self.sequence && sst.meta.time_range.overlaps(&self.time_range)
+    }
+
+    /// Build the expr matching rows deleted by the tombstone.
+    ///
+    /// `sequence_column` is the column of max sequences of the ssts rows come
+    /// from, and rows are assumed to come from ssts the tombstone applies to
+    /// if it's `None`.
+    pub fn to_expr(
+        &self,
+        key_column: Option<&str>,
+        timestamp_column: &str,
+        sequence_column: Option<&str>,
+    ) -> Expr {
+        let mut expr = ident(timestamp_column)
+            .gt_eq(lit(self.time_range.start.0))
+            .and(ident(timestamp_column).lt(lit(self.time_range.end.0)));
+        if let Some(key_column) = key_column {
+            if let Some(start) = &self.key_range.start {
+                expr = expr.and(ident(key_column).gt_eq(lit(start.clone())));
+            }
+            if let Some(end) = &self.key_range.end {
+                expr = expr.and(ident(key_column).lt(lit(end.clone())));
+            }
+        }
+        if let Some(sequence_column) = sequence_column {
+            expr = expr.and(ident(sequence_column).lt(lit(self.sequence)));
+        }
+
+        expr
+    }
+}
+
+/// Build the expr keeping rows not deleted by any of `tombstones`, `None` is
+/// returned if `tombstones` is empty.
+///
+/// See [Tombstone::to_expr] for the arguments.
+pub fn build_filter_expr(
+    tombstones: &[Tombstone],
+    key_column: Option<&str>,
+    timestamp_column: &str,
+    sequence_column: Option<&str>,
+) -> Option<Expr> {
+    let deleted = disjunction(
+        tombstones
+            .iter()
+            .map(|t| t.to_expr(key_column, timestamp_column, sequence_column)),
+    )?;
+
+    Some(not(deleted))
+}
+
+impl TryFrom<pb_types::Tombstone> for Tombstone {
+    type Error = Error;
+
+    fn try_from(value: pb_types::Tombstone) -> Result<Self, Self::Error> {
+        ensure!(
+            value.time_range.is_some(),
+            Error::manifest_corrupt("time range of tombstone is missing")
+        );
+        let time_range = value.time_range.unwrap();
+
+        Ok(Self {
+            id: value.id,
+            sequence: value.sequence,
+            key_range: KeyRange {
+                start: value.key_start,
+                end: value.key_end,
+            },
+            time_range: TimeRange::try_new(Timestamp(time_range.start), Timestamp(time_range.end))?,
+        })
+    }
+}
+
+impl From<Tombstone> for pb_types::Tombstone {
+    fn from(value: Tombstone) -> Self {
+        pb_types::Tombstone {
+            id: value.id,
+            sequence: value.sequence,
+            time_range: Some(pb_types::TimeRange {
+                start: *value.time_range.start,
+                end: *value.time_range.end,
+            }),
+            key_start: value.key_range.start,
+            key_end: value.key_range.end,
+        }
+    }
+}
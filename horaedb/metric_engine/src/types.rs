@@ -19,12 +19,123 @@ use std::{
     collections::HashMap,
     ops::{Add, Deref, Range},
     sync::Arc,
+    time::Duration,
 };
 
+use arrow::datatypes::TimeUnit as ArrowTimeUnit;
+use macros::ensure;
 use object_store::ObjectStore;
 use parquet::basic::{Compression, Encoding, ZstdLevel};
 
-use crate::sst::FileId;
+use crate::{
+    codec::ValueCodec,
+    sst::{ColumnAggregate, FileId, Tier},
+    Error, Result,
+};
+
+/// Order of timestamps of rows with the same series keys.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum TimeOrder {
+    #[default]
+    Asc,
+    /// Newest rows come first, so reading the latest rows of a series can
+    /// stop at its first rows.
+    Desc,
+}
+
+/// How rows of the same primary key and timestamp are merged.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum MergeMode {
+    /// Rows are kept as written.
+    #[default]
+    Append,
+    /// Rows are deduplicated when written and compacted, the latest written
+    /// one is kept, see [TimeMergeStorage::upsert]. Scans merge ssts
+    /// overlapping in time before reading them, so they never dedup rows
+    /// themselves.
+    ///
+    /// [TimeMergeStorage::upsert]: crate::storage::TimeMergeStorage::upsert
+    MergeOnWrite,
+}
+
+/// How nulls of primary key columns are handled.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum NullKeyPolicy {
+    /// Nulls are sorted before other values.
+    #[default]
+    NullsFirst,
+    /// Nulls are sorted after other values.
+    NullsLast,
+    /// Writes and imports with nulls in any primary key column fail with
+    /// [Error::NullPrimaryKey](crate::error::Error::NullPrimaryKey), since
+    /// null keys make rows of different series indistinguishable to dedup.
+    Reject,
+}
+
+impl NullKeyPolicy {
+    pub fn nulls_first(&self) -> bool {
+        *self != Self::NullsLast
+    }
+}
+
+/// Precision of timestamps.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum TimeUnit {
+    Second,
+    #[default]
+    Millisecond,
+    Microsecond,
+    Nanosecond,
+}
+
+impl TimeUnit {
+    /// Number of units in one second.
+    pub const fn units_per_second(&self) -> i64 {
+        match self {
+            TimeUnit::Second => 1,
+            TimeUnit::Millisecond => 1_000,
+            TimeUnit::Microsecond => 1_000_000,
+            TimeUnit::Nanosecond => 1_000_000_000,
+        }
+    }
+
+    pub const fn units_per_day(&self) -> i64 {
+        self.units_per_second() * 24 * 3600
+    }
+
+    /// Convert `value` of this unit to `to`, values are rounded down when
+    /// converted to a coarser unit, and saturated on overflow.
+    pub fn convert(&self, value: i64, to: TimeUnit) -> i64 {
+        let (from, to) = (self.units_per_second(), to.units_per_second());
+        if from <= to {
+            value.saturating_mul(to / from)
+        } else {
+            value.div_euclid(from / to)
+        }
+    }
+
+    /// Same as [TimeUnit::convert], but rounds up.
+    pub fn convert_ceil(&self, value: i64, to: TimeUnit) -> i64 {
+        let (from, to) = (self.units_per_second(), to.units_per_second());
+        if from <= to {
+            value.saturating_mul(to / from)
+        } else {
+            let factor = from / to;
+            value.div_euclid(factor) + i64::from(value.rem_euclid(factor) != 0)
+        }
+    }
+}
+
+impl From<ArrowTimeUnit> for TimeUnit {
+    fn from(value: ArrowTimeUnit) -> Self {
+        match value {
+            ArrowTimeUnit::Second => TimeUnit::Second,
+            ArrowTimeUnit::Millisecond => TimeUnit::Millisecond,
+            ArrowTimeUnit::Microsecond => TimeUnit::Microsecond,
+            ArrowTimeUnit::Nanosecond => TimeUnit::Nanosecond,
+        }
+    }
+}
 
 #[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
 pub struct Timestamp(pub i64);
@@ -62,14 +173,22 @@ impl Deref for Timestamp {
 impl Timestamp {
     pub const MAX: Timestamp = Timestamp(i64::MAX);
     pub const MIN: Timestamp = Timestamp(i64::MIN);
+
+    pub fn convert(&self, from: TimeUnit, to: TimeUnit) -> Timestamp {
+        Timestamp(from.convert(self.0, to))
+    }
 }
 
-#[derive(Clone, Debug)]
+/// Range of timestamps, `start` is inclusive and `end` is exclusive, and it's
+/// never empty.
+#[derive(Clone, Debug, PartialEq, Eq)]
 pub struct TimeRange(Range<Timestamp>);
 
-impl From<Range<Timestamp>> for TimeRange {
-    fn from(value: Range<Timestamp>) -> Self {
-        Self(value)
+impl TryFrom<Range<Timestamp>> for TimeRange {
+    type Error = Error;
+
+    fn try_from(value: Range<Timestamp>) -> Result<Self> {
+        Self::try_new(value.start, value.end)
     }
 }
 
@@ -82,13 +201,77 @@ impl Deref for TimeRange {
 }
 
 impl TimeRange {
-    pub fn new(start: Timestamp, end: Timestamp) -> Self {
+    /// Caller should ensure `start < end`, and ranges of requests are checked
+    /// again by the storage, see [TimeRange::check].
+    pub(crate) fn new(start: Timestamp, end: Timestamp) -> Self {
         Self(start..end)
     }
 
+    pub fn try_new(start: Timestamp, end: Timestamp) -> Result<Self> {
+        ensure!(
+            start < end,
+            Error::TimestampInvalid {
+                msg: format!("invalid time range, start:{}, end:{}", start.0, end.0),
+            }
+        );
+        Ok(Self(start..end))
+    }
+
+    /// Check the range built by [TimeRange::new] is not empty.
+    pub(crate) fn check(&self) -> Result<()> {
+        Self::try_new(self.0.start.clone(), self.0.end.clone()).map(|_| ())
+    }
+
+    /// Build range from the min and max timestamp, both are inclusive.
+    pub fn try_from_inclusive(min: Timestamp, max: Timestamp) -> Result<Self> {
+        let end = max
+            .0
+            .checked_add(1)
+            .ok_or_else(|| Error::TimestampInvalid {
+                msg: format!("time range end overflows, max:{}", max.0),
+            })?;
+        Self::try_new(min, Timestamp(end))
+    }
+
+    pub fn contains(&self, ts: &Timestamp) -> bool {
+        self.0.contains(ts)
+    }
+
     pub fn overlaps(&self, other: &TimeRange) -> bool {
         self.0.start < other.0.end && other.0.start < self.0.end
     }
+
+    /// Returns `None` when two ranges don't overlap.
+    pub fn intersection(&self, other: &TimeRange) -> Option<TimeRange> {
+        self.overlaps(other).then(|| {
+            Self(
+                self.0.start.clone().max(other.0.start.clone())
+                    ..self.0.end.clone().min(other.0.end.clone()),
+            )
+        })
+    }
+
+    /// The smallest range covering both ranges.
+    pub fn union(&self, other: &TimeRange) -> TimeRange {
+        Self(
+            self.0.start.clone().min(other.0.start.clone())
+                ..self.0.end.clone().max(other.0.end.clone()),
+        )
+    }
+
+    /// Convert the range from unit `from` to `to`, the converted range always
+    /// covers the original one.
+    pub fn convert(&self, from: TimeUnit, to: TimeUnit) -> Result<TimeRange> {
+        Self::try_new(
+            self.0.start.convert(from, to),
+            Timestamp(from.convert_ceil(self.0.end.0, to)),
+        )
+    }
+
+    /// Length of the range, saturated at `i64::MAX`.
+    pub fn duration(&self) -> i64 {
+        self.0.end.0.saturating_sub(self.0.start.0)
+    }
 }
 
 pub type ObjectStoreRef = Arc<dyn ObjectStore>;
@@ -96,39 +279,240 @@ pub type ObjectStoreRef = Arc<dyn ObjectStore>;
 pub struct WriteResult {
     pub id: FileId,
     pub size: usize,
+    /// Size of the inverted index of the sst, 0 if it's not written.
+    pub inverted_index_size: usize,
+    /// Aggregates of the numeric value columns of the sst.
+    pub aggregates: Vec<ColumnAggregate>,
+    pub tier: Tier,
+    /// Min and max keys of the series key columns, empty if they are unknown.
+    pub min_key: Vec<u8>,
+    pub max_key: Vec<u8>,
+    /// Crc32 of the sst, `None` if it's unknown.
+    pub checksum: Option<u32>,
+    /// Value columns all null in the sst, see
+    /// [FileMeta::null_columns](crate::sst::FileMeta::null_columns).
+    pub null_columns: Vec<String>,
 }
 
 pub struct ColumnOptions {
     pub enable_dict: Option<bool>,
     pub enable_bloom_filter: Option<bool>,
+    pub enable_inverted_index: Option<bool>,
     pub encoding: Option<Encoding>,
     pub compression: Option<Compression>,
+    /// Encode values of the column by the codec instead of parquet encodings,
+    /// only for value columns.
+    pub codec: Option<ValueCodec>,
+}
+
+#[derive(Debug, Clone)]
+pub struct ManifestOptions {
+    /// Updates arriving within this interval are committed in one delta.
+    pub commit_interval: Duration,
+    /// Deltas are merged into the snapshot when there are this many of them.
+    pub max_deltas: usize,
+    /// Number of file ids reserved in the manifest at a time.
+    pub file_id_batch: u64,
+    /// Deltas merged into the snapshot are deleted only after they are this
+    /// old, so readers which loaded an older snapshot can still read them.
+    pub delta_retention: Duration,
+    /// Number of merged deltas deleted in one batch.
+    pub delete_batch_size: usize,
+    /// Interval between two batches of deletes, which limits the rate of
+    /// deleting.
+    pub delete_interval: Duration,
+    /// Interval of reloading the manifest opened read only by replicas, which
+    /// must be shorter than the retention of deltas.
+    pub refresh_interval: Duration,
+    /// Merged deltas, history snapshots and ssts removed by compactions are
+    /// kept at least this long, so scans at versions within it always succeed,
+    /// see [crate::storage::ScanRequest::snapshot_version]. Zero means ssts
+    /// are deleted once compacted.
+    pub history_retention: Duration,
+}
+
+impl Default for ManifestOptions {
+    fn default() -> Self {
+        Self {
+            commit_interval: Duration::from_millis(10),
+            max_deltas: 32,
+            file_id_batch: 1024,
+            delta_retention: Duration::from_secs(600),
+            delete_batch_size: 1000,
+            delete_interval: Duration::from_millis(100),
+            refresh_interval: Duration::from_secs(10),
+            history_retention: Duration::ZERO,
+        }
+    }
 }
 
 pub struct WriteOptions {
+    // precision of the timestamp column, timestamps of incoming batches are
+    // converted to it
+    pub time_unit: TimeUnit,
     pub max_row_group_size: usize,
+    // target bytes of a row group, rows of the first row group are estimated
+    // from the average row width of incoming batches, and the following ones
+    // from the encoded row width of the flushed row groups, capped by
+    // max_row_group_size
+    pub target_row_group_bytes: Option<usize>,
     pub write_bacth_size: usize,
     pub enable_sorting_columns: bool,
     // use to set column props with default value
     pub enable_dict: bool,
     pub enable_bloom_filter: bool,
+    // write an inverted index of tag values to row groups along with every
+    // sst, see crate::inverted_index
+    pub enable_inverted_index: bool,
+    // write page level statistics, so the column index can be used for page
+    // pruning, the offset index is always written
+    pub enable_page_index: bool,
+    // order of timestamps within the same series keys, in ssts and scan output
+    pub time_order: TimeOrder,
     pub encoding: Encoding,
     pub compression: Compression,
     // use to set column props with column name
     pub column_options: Option<HashMap<String, ColumnOptions>>,
+    pub manifest: ManifestOptions,
+    pub merge_mode: MergeMode,
+    // split every flush at the boundaries of fixed time segments aligned to
+    // the epoch, e.g. 2h, so an sst never spans segments, `None` flushes every
+    // batch into one sst
+    pub flush_segment: Option<Duration>,
+    // split every flush by the hash of series keys into this many sub-shards,
+    // which are encoded and uploaded concurrently to raise the ingest
+    // throughput of a hot root, scans read them as parallel partitions and
+    // merge the results
+    pub write_shards: usize,
+    // value columns all null in an sst are not written into its data file,
+    // and recorded in the sst meta instead, which keeps ssts of wide tables
+    // with thousands of sparse fields small
+    pub sparse_columns: bool,
+    // nulls of primary keys are rejected or sorted by it, it should not be
+    // changed once ssts are written, same as time_order
+    pub null_keys: NullKeyPolicy,
 }
 
 impl Default for WriteOptions {
     fn default() -> Self {
         Self {
+            time_unit: TimeUnit::default(),
             max_row_group_size: 8192,
+            target_row_group_bytes: None,
             write_bacth_size: 1024,
             enable_sorting_columns: true,
             enable_dict: false,
             enable_bloom_filter: false,
+            enable_inverted_index: false,
+            enable_page_index: true,
+            time_order: TimeOrder::default(),
             encoding: Encoding::PLAIN,
             compression: Compression::ZSTD(ZstdLevel::default()),
             column_options: None,
+            manifest: ManifestOptions::default(),
+            merge_mode: MergeMode::default(),
+            flush_segment: None,
+            write_shards: 1,
+            sparse_columns: false,
+            null_keys: NullKeyPolicy::default(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use proptest::prelude::*;
+
+    use super::*;
+
+    const BOUND: i64 = 1 << 48;
+
+    fn time_range() -> impl Strategy<Value = TimeRange> {
+        (-BOUND..BOUND, 1..BOUND).prop_map(|(start, len)| {
+            TimeRange::try_new(Timestamp(start), Timestamp(start + len)).unwrap()
+        })
+    }
+
+    #[test]
+    fn test_invalid_time_range() {
+        assert!(TimeRange::try_new(Timestamp(1), Timestamp(1)).is_err());
+        assert!(TimeRange::try_new(Timestamp(2), Timestamp(1)).is_err());
+        assert!(TimeRange::try_from_inclusive(Timestamp(0), Timestamp::MAX).is_err());
+        assert!(TimeRange::try_from(Timestamp(2)..Timestamp(1)).is_err());
+        assert!(TimeRange::new(Timestamp(2), Timestamp(1)).check().is_err());
+        assert_eq!(
+            TimeRange::new(Timestamp(1), Timestamp(2)),
+            TimeRange::try_from_inclusive(Timestamp(1), Timestamp(1)).unwrap()
+        );
+        assert_eq!(
+            i64::MAX,
+            TimeRange::new(Timestamp::MIN, Timestamp::MAX).duration()
+        );
+    }
+
+    #[test]
+    fn test_time_unit_convert() {
+        assert_eq!(
+            1_500_000,
+            TimeUnit::Millisecond.convert(1500, TimeUnit::Microsecond)
+        );
+        assert_eq!(1, TimeUnit::Millisecond.convert(1500, TimeUnit::Second));
+        assert_eq!(
+            2,
+            TimeUnit::Millisecond.convert_ceil(1500, TimeUnit::Second)
+        );
+        assert_eq!(-2, TimeUnit::Millisecond.convert(-1500, TimeUnit::Second));
+        assert_eq!(
+            -1,
+            TimeUnit::Millisecond.convert_ceil(-1500, TimeUnit::Second)
+        );
+        assert_eq!(
+            i64::MAX,
+            TimeUnit::Second.convert(i64::MAX, TimeUnit::Nanosecond)
+        );
+
+        let range = TimeRange::new(Timestamp(1500), Timestamp(2500));
+        assert_eq!(
+            TimeRange::new(Timestamp(1), Timestamp(3)),
+            range
+                .convert(TimeUnit::Millisecond, TimeUnit::Second)
+                .unwrap()
+        );
+    }
+
+    proptest! {
+        #[test]
+        fn prop_try_new(start in any::<i64>(), end in any::<i64>()) {
+            prop_assert_eq!(start < end, TimeRange::try_new(Timestamp(start), Timestamp(end)).is_ok());
+        }
+
+        #[test]
+        fn prop_contains(range in time_range(), ts in -BOUND..BOUND * 2) {
+            prop_assert_eq!(
+                range.start.0 <= ts && ts < range.end.0,
+                range.contains(&Timestamp(ts))
+            );
+        }
+
+        #[test]
+        fn prop_intersection(a in time_range(), b in time_range()) {
+            let intersection = a.intersection(&b);
+            prop_assert_eq!(intersection.clone(), b.intersection(&a));
+            prop_assert_eq!(a.overlaps(&b), intersection.is_some());
+            if let Some(i) = intersection {
+                prop_assert!(i.start >= a.start && i.end <= a.end);
+                prop_assert!(i.start >= b.start && i.end <= b.end);
+                prop_assert!(i.duration() <= a.duration().min(b.duration()));
+            }
+        }
+
+        #[test]
+        fn prop_union(a in time_range(), b in time_range()) {
+            let union = a.union(&b);
+            prop_assert_eq!(union.clone(), b.union(&a));
+            prop_assert!(union.start <= a.start && union.end >= a.end);
+            prop_assert!(union.start <= b.start && union.end >= b.end);
+            prop_assert!(union.duration() >= a.duration().max(b.duration()));
         }
     }
 }
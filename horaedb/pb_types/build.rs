@@ -19,5 +19,9 @@ use std::io::Result;
 
 fn main() -> Result<()> {
     prost_build::compile_protos(&["protos/sst.proto"], &["protos/"])?;
+    tonic_build::configure()
+        .build_client(true)
+        .build_server(true)
+        .compile_protos(&["protos/admin.proto"], &["protos/"])?;
     Ok(())
 }
@@ -19,4 +19,8 @@ mod pb_types {
     include!(concat!(env!("OUT_DIR"), "/pb_types.sst.rs"));
 }
 
+pub mod admin {
+    include!(concat!(env!("OUT_DIR"), "/pb_types.admin.rs"));
+}
+
 pub use pb_types::*;
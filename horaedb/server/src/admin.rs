@@ -0,0 +1,361 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+//! Admin gRPC service of the storage.
+//!
+//! Backup, restore and export are run as background jobs tracked by the
+//! [JobRegistry], so operators can trigger them remotely and poll their
+//! states. Every request must carry the admin token.
+
+use std::sync::Arc;
+
+use metric_engine::{
+    backup::BackupRequest,
+    export::ExportRequest,
+    storage::CloudObjectStorage,
+    types::{ObjectStoreRef, TimeRange},
+};
+use pb_types::admin::{
+    admin_server::{Admin, AdminServer},
+    GetJobRequest, Job, JobKind, JobResponse, ListJobsRequest, ListJobsResponse,
+    StartBackupRequest, StartExportRequest, StartRestoreRequest,
+};
+use tonic::{metadata::MetadataMap, Request, Response, Status};
+
+use crate::{
+    jobs::{JobLimits, JobRegistry},
+    status::to_status,
+};
+
+const AUTHORIZATION_KEY: &str = "authorization";
+
+#[derive(Debug, Clone, Default)]
+pub struct AdminConfig {
+    /// Token expected in the `authorization` metadata as `Bearer {token}`,
+    /// all requests are rejected when it's empty.
+    pub token: String,
+    pub job_limits: JobLimits,
+}
+
+pub struct AdminService {
+    token: String,
+    storage: Arc<CloudObjectStorage>,
+    /// Store where backups are written to and restored from.
+    backup_store: ObjectStoreRef,
+    /// Store where exported datasets are written to.
+    export_store: ObjectStoreRef,
+    jobs: JobRegistry,
+}
+
+impl AdminService {
+    pub fn new(
+        config: AdminConfig,
+        storage: Arc<CloudObjectStorage>,
+        backup_store: ObjectStoreRef,
+        export_store: ObjectStoreRef,
+    ) -> Self {
+        Self {
+            token: config.token,
+            storage,
+            backup_store,
+            export_store,
+            jobs: JobRegistry::new(config.job_limits),
+        }
+    }
+
+    pub fn into_server(self) -> AdminServer<Self> {
+        AdminServer::new(self)
+    }
+
+    fn authenticate(&self, metadata: &MetadataMap) -> Result<(), Status> {
+        if self.token.is_empty() {
+            return Err(Status::unauthenticated("admin token is not configured"));
+        }
+
+        let provided = metadata
+            .get(AUTHORIZATION_KEY)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.strip_prefix("Bearer "))
+            .ok_or_else(|| Status::unauthenticated("admin token is missing"))?;
+        if !constant_time_eq(provided.as_bytes(), self.token.as_bytes()) {
+            return Err(Status::unauthenticated("admin token is invalid"));
+        }
+
+        Ok(())
+    }
+
+    fn spawn<F>(&self, kind: JobKind, task: F) -> Result<Response<JobResponse>, Status>
+    where
+        F: std::future::Future<Output = anyhow::Result<String>> + Send + 'static,
+    {
+        let job = self.jobs.spawn(kind, task)?;
+        Ok(Response::new(JobResponse { job: Some(job) }))
+    }
+}
+
+/// Compare without early return, so the token can't be guessed by timing.
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+fn check_prefix(prefix: &str) -> Result<(), Status> {
+    if prefix.trim_matches('/').is_empty() {
+        return Err(Status::invalid_argument("prefix is empty"));
+    }
+
+    Ok(())
+}
+
+#[tonic::async_trait]
+impl Admin for AdminService {
+    async fn start_backup(
+        &self,
+        request: Request<StartBackupRequest>,
+    ) -> Result<Response<JobResponse>, Status> {
+        self.authenticate(request.metadata())?;
+        let req = request.into_inner();
+        check_prefix(&req.prefix)?;
+
+        let storage = self.storage.clone();
+        let backup_req = BackupRequest {
+            store: self.backup_store.clone(),
+            prefix: req.prefix,
+            incremental: req.incremental,
+        };
+        self.spawn(JobKind::Backup, async move {
+            let result = storage.backup(backup_req).await?;
+            Ok(format!("{result:?}"))
+        })
+    }
+
+    async fn start_restore(
+        &self,
+        request: Request<StartRestoreRequest>,
+    ) -> Result<Response<JobResponse>, Status> {
+        self.authenticate(request.metadata())?;
+        let req = request.into_inner();
+        check_prefix(&req.prefix)?;
+        check_prefix(&req.target_root)?;
+
+        let storage = self.storage.clone();
+        let backup_store = self.backup_store.clone();
+        self.spawn(JobKind::Restore, async move {
+            let result = storage
+                .restore_to(&backup_store, &req.prefix, req.backup_id, &req.target_root)
+                .await?;
+            Ok(format!("{result:?}"))
+        })
+    }
+
+    async fn start_export(
+        &self,
+        request: Request<StartExportRequest>,
+    ) -> Result<Response<JobResponse>, Status> {
+        self.authenticate(request.metadata())?;
+        let req = request.into_inner();
+        check_prefix(&req.prefix)?;
+        let range =
+            TimeRange::try_new(req.start.into(), req.end.into()).map_err(|e| to_status(&e))?;
+
+        let storage = self.storage.clone();
+        let export_req = ExportRequest {
+            range,
+            store: self.export_store.clone(),
+            prefix: req.prefix,
+        };
+        self.spawn(JobKind::Export, async move {
+            let result = storage.export(export_req).await?;
+            let num_rows: usize = result.files.iter().map(|f| f.num_rows).sum();
+            Ok(format!(
+                "num_files:{}, num_rows:{num_rows}",
+                result.files.len()
+            ))
+        })
+    }
+
+    async fn get_job(
+        &self,
+        request: Request<GetJobRequest>,
+    ) -> Result<Response<JobResponse>, Status> {
+        self.authenticate(request.metadata())?;
+        let id = request.into_inner().id;
+        let job = self
+            .jobs
+            .get(id)
+            .ok_or_else(|| Status::not_found(format!("job not found, id:{id}")))?;
+
+        Ok(Response::new(JobResponse { job: Some(job) }))
+    }
+
+    async fn list_jobs(
+        &self,
+        request: Request<ListJobsRequest>,
+    ) -> Result<Response<ListJobsResponse>, Status> {
+        self.authenticate(request.metadata())?;
+        let jobs: Vec<Job> = self.jobs.list();
+
+        Ok(Response::new(ListJobsResponse { jobs }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use arrow::datatypes::{DataType, Field, Schema};
+    use metric_engine::types::WriteOptions;
+    use object_store::memory::InMemory;
+    use pb_types::admin::JobState;
+
+    use super::*;
+
+    const TOKEN: &str = "secret";
+
+    async fn new_service() -> AdminService {
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("pk", DataType::UInt64, false),
+            Field::new("ts", DataType::Int64, false),
+            Field::new("value", DataType::UInt64, false),
+        ]));
+        let storage = CloudObjectStorage::try_new(
+            "/test".to_string(),
+            Arc::new(InMemory::new()),
+            schema,
+            1,
+            1,
+            WriteOptions::default(),
+        )
+        .await
+        .unwrap();
+        let config = AdminConfig {
+            token: TOKEN.to_string(),
+            job_limits: JobLimits::default(),
+        };
+
+        AdminService::new(
+            config,
+            Arc::new(storage),
+            Arc::new(InMemory::new()),
+            Arc::new(InMemory::new()),
+        )
+    }
+
+    fn new_request<T>(message: T, token: Option<&str>) -> Request<T> {
+        let mut request = Request::new(message);
+        if let Some(token) = token {
+            request.metadata_mut().insert(
+                AUTHORIZATION_KEY,
+                format!("Bearer {token}").parse().unwrap(),
+            );
+        }
+        request
+    }
+
+    async fn wait_finished(service: &AdminService, id: u64) -> Job {
+        loop {
+            let job = service
+                .get_job(new_request(GetJobRequest { id }, Some(TOKEN)))
+                .await
+                .unwrap()
+                .into_inner()
+                .job
+                .unwrap();
+            if job.state() != JobState::Running {
+                return job;
+            }
+            tokio::time::sleep(Duration::from_millis(5)).await;
+        }
+    }
+
+    #[tokio::test]
+    async fn test_authenticate() {
+        let service = new_service().await;
+        for token in [None, Some("wrong")] {
+            let err = service
+                .list_jobs(new_request(ListJobsRequest {}, token))
+                .await
+                .unwrap_err();
+            assert_eq!(err.code(), tonic::Code::Unauthenticated);
+        }
+
+        let jobs = service
+            .list_jobs(new_request(ListJobsRequest {}, Some(TOKEN)))
+            .await
+            .unwrap()
+            .into_inner()
+            .jobs;
+        assert!(jobs.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_backup_and_restore_jobs() {
+        let service = new_service().await;
+        let backup = StartBackupRequest {
+            prefix: "backup".to_string(),
+            incremental: false,
+        };
+        let job = service
+            .start_backup(new_request(backup, Some(TOKEN)))
+            .await
+            .unwrap()
+            .into_inner()
+            .job
+            .unwrap();
+        assert_eq!(job.kind(), JobKind::Backup);
+        let job = wait_finished(&service, job.id).await;
+        assert_eq!(job.state(), JobState::Succeeded, "{}", job.error);
+
+        // Restoring into the running root is rejected.
+        let restore = StartRestoreRequest {
+            prefix: "backup".to_string(),
+            backup_id: None,
+            target_root: "/test/".to_string(),
+        };
+        let job = service
+            .start_restore(new_request(restore, Some(TOKEN)))
+            .await
+            .unwrap()
+            .into_inner()
+            .job
+            .unwrap();
+        let job = wait_finished(&service, job.id).await;
+        assert_eq!(job.state(), JobState::Failed);
+
+        let restore = StartRestoreRequest {
+            prefix: "backup".to_string(),
+            backup_id: None,
+            target_root: "/restored".to_string(),
+        };
+        let job = service
+            .start_restore(new_request(restore, Some(TOKEN)))
+            .await
+            .unwrap()
+            .into_inner()
+            .job
+            .unwrap();
+        let job = wait_finished(&service, job.id).await;
+        assert_eq!(job.state(), JobState::Succeeded, "{}", job.error);
+
+        let jobs = service
+            .list_jobs(new_request(ListJobsRequest {}, Some(TOKEN)))
+            .await
+            .unwrap()
+            .into_inner()
+            .jobs;
+        assert_eq!(jobs.len(), 3);
+    }
+}
@@ -0,0 +1,234 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+//! Tracking of the long running admin jobs.
+//!
+//! Jobs are run in the background, and their states are kept in memory so
+//! operators can poll them. At most `max_running_jobs` jobs run at the same
+//! time, new jobs are rejected when the limit is reached, and only the latest
+//! `max_finished_jobs` finished jobs are kept.
+
+use std::{
+    collections::BTreeMap,
+    future::Future,
+    sync::{Arc, Mutex},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use pb_types::admin::{Job, JobKind, JobState};
+use tonic::Status;
+use tracing::{info, warn};
+
+#[derive(Debug, Clone, Copy)]
+pub struct JobLimits {
+    pub max_running_jobs: usize,
+    pub max_finished_jobs: usize,
+}
+
+impl Default for JobLimits {
+    fn default() -> Self {
+        Self {
+            max_running_jobs: 1,
+            max_finished_jobs: 100,
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct Jobs {
+    next_id: u64,
+    num_running: usize,
+    jobs: BTreeMap<u64, Job>,
+}
+
+impl Jobs {
+    fn finish(&mut self, id: u64, result: anyhow::Result<String>, max_finished_jobs: usize) {
+        self.num_running -= 1;
+        if let Some(job) = self.jobs.get_mut(&id) {
+            job.end_time = now_ms();
+            match result {
+                Ok(v) => {
+                    job.set_state(JobState::Succeeded);
+                    job.result = v;
+                }
+                Err(e) => {
+                    job.set_state(JobState::Failed);
+                    job.error = format!("{e:#}");
+                }
+            }
+        }
+
+        let num_finished = self.jobs.len() - self.num_running;
+        let to_evict = num_finished.saturating_sub(max_finished_jobs);
+        let evicted = self
+            .jobs
+            .values()
+            .filter(|job| job.state() != JobState::Running)
+            .map(|job| job.id)
+            .take(to_evict)
+            .collect::<Vec<_>>();
+        for id in evicted {
+            self.jobs.remove(&id);
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct JobRegistry {
+    limits: JobLimits,
+    jobs: Arc<Mutex<Jobs>>,
+}
+
+impl JobRegistry {
+    pub fn new(limits: JobLimits) -> Self {
+        Self {
+            limits,
+            jobs: Arc::new(Mutex::new(Jobs::default())),
+        }
+    }
+
+    /// Run `task` in the background as a new job, the summary returned by the
+    /// task is recorded as the result of the job.
+    ///
+    /// Returns `resource_exhausted` if too many jobs are running.
+    pub fn spawn<F>(&self, kind: JobKind, task: F) -> Result<Job, Status>
+    where
+        F: Future<Output = anyhow::Result<String>> + Send + 'static,
+    {
+        let job = {
+            let mut jobs = self.jobs.lock().unwrap();
+            if jobs.num_running >= self.limits.max_running_jobs {
+                return Err(Status::resource_exhausted(format!(
+                    "too many running jobs, limit:{}",
+                    self.limits.max_running_jobs
+                )));
+            }
+
+            jobs.next_id += 1;
+            jobs.num_running += 1;
+            let mut job = Job {
+                id: jobs.next_id,
+                start_time: now_ms(),
+                ..Default::default()
+            };
+            job.set_kind(kind);
+            job.set_state(JobState::Running);
+            jobs.jobs.insert(job.id, job.clone());
+            job
+        };
+
+        info!(id = job.id, kind = kind.as_str_name(), "Admin job started");
+        let id = job.id;
+        let max_finished_jobs = self.limits.max_finished_jobs;
+        let registry = self.jobs.clone();
+        tokio::spawn(async move {
+            // Run the task in its own task, so a panic fails the job instead
+            // of leaking the running slot.
+            let result = tokio::spawn(task)
+                .await
+                .unwrap_or_else(|e| Err(anyhow::anyhow!("job panicked, err:{e}")));
+            match &result {
+                Ok(v) => info!(id, result = v.as_str(), "Admin job succeeded"),
+                Err(e) => warn!(id, "Admin job failed, err:{e:#}"),
+            }
+            registry
+                .lock()
+                .unwrap()
+                .finish(id, result, max_finished_jobs);
+        });
+
+        Ok(job)
+    }
+
+    pub fn get(&self, id: u64) -> Option<Job> {
+        self.jobs.lock().unwrap().jobs.get(&id).cloned()
+    }
+
+    /// Returns the tracked jobs ordered by id.
+    pub fn list(&self) -> Vec<Job> {
+        self.jobs.lock().unwrap().jobs.values().cloned().collect()
+    }
+}
+
+fn now_ms() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|v| v.as_millis() as i64)
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use tokio::sync::oneshot;
+
+    use super::*;
+
+    async fn wait_finished(registry: &JobRegistry, id: u64) -> Job {
+        loop {
+            let job = registry.get(id).unwrap();
+            if job.state() != JobState::Running {
+                return job;
+            }
+            tokio::time::sleep(Duration::from_millis(5)).await;
+        }
+    }
+
+    #[tokio::test]
+    async fn test_throttle_and_evict_jobs() {
+        let registry = JobRegistry::new(JobLimits {
+            max_running_jobs: 1,
+            max_finished_jobs: 1,
+        });
+
+        let (tx, rx) = oneshot::channel::<()>();
+        let job = registry
+            .spawn(JobKind::Backup, async move {
+                rx.await?;
+                Ok("done".to_string())
+            })
+            .unwrap();
+        assert_eq!(job.state(), JobState::Running);
+        assert_eq!(job.kind(), JobKind::Backup);
+
+        // The running job blocks new jobs.
+        let err = registry
+            .spawn(JobKind::Export, async { Ok(String::new()) })
+            .unwrap_err();
+        assert_eq!(err.code(), tonic::Code::ResourceExhausted);
+
+        tx.send(()).unwrap();
+        let finished = wait_finished(&registry, job.id).await;
+        assert_eq!(finished.state(), JobState::Succeeded);
+        assert_eq!(finished.result, "done");
+        assert!(finished.end_time >= finished.start_time);
+
+        let failed = registry
+            .spawn(JobKind::Restore, async { Err(anyhow::anyhow!("broken")) })
+            .unwrap();
+        let failed = wait_finished(&registry, failed.id).await;
+        assert_eq!(failed.state(), JobState::Failed);
+        assert_eq!(failed.error, "broken");
+
+        // Only the latest finished job is kept.
+        let jobs = registry.list();
+        assert_eq!(jobs.len(), 1);
+        assert_eq!(jobs[0].id, failed.id);
+        assert!(registry.get(job.id).is_none());
+    }
+}
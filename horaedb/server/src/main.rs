@@ -15,12 +15,20 @@
 // specific language governing permissions and limitations
 // under the License.
 
+use horaedb_server::telemetry::{self, TelemetryConfig};
 use tracing::info;
 
-fn main() {
-    // install global collector configured based on RUST_LOG env var.
-    tracing_subscriber::fmt::init();
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    // Spans are exported to the OTLP collector if its endpoint is set.
+    let config = TelemetryConfig {
+        otlp_endpoint: std::env::var("HORAEDB_OTLP_ENDPOINT").ok(),
+        ..Default::default()
+    };
+    let _telemetry = telemetry::init(&config)?;
 
     let port = 5000;
     info!(port, "Start horaedb server...");
+
+    Ok(())
 }
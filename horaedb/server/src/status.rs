@@ -0,0 +1,51 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+//! Map errors of the storage to gRPC status codes.
+
+use metric_engine::{Error, ErrorKind};
+use tonic::{Code, Status};
+
+pub fn to_status(err: &Error) -> Status {
+    let code = match err.kind() {
+        ErrorKind::InvalidArgument => Code::InvalidArgument,
+        ErrorKind::ResourceExhausted => Code::ResourceExhausted,
+        ErrorKind::Unavailable => Code::Unavailable,
+        ErrorKind::DataLoss => Code::DataLoss,
+        ErrorKind::Internal => Code::Internal,
+    };
+
+    Status::new(code, err.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use metric_engine::types::{TimeRange, Timestamp};
+
+    use super::*;
+
+    #[test]
+    fn test_to_status() {
+        let err = TimeRange::try_new(Timestamp(1), Timestamp(0)).unwrap_err();
+        assert_eq!(Code::InvalidArgument, to_status(&err).code());
+
+        let err = Error::Backpressure {
+            msg: "write memory exhausted".to_string(),
+        };
+        assert_eq!(Code::ResourceExhausted, to_status(&err).code());
+    }
+}
@@ -18,7 +18,9 @@
 use std::cmp;
 
 use common_types::request_id::RequestId;
-use logger::{debug, info};
+use generic_error::BoxError;
+use logger::{debug, info, warn};
+use object_store::Path;
 use snafu::ResultExt;
 
 use crate::{
@@ -28,14 +30,20 @@ use crate::{
         },
         CompactionInputFiles, CompactionTask, ExpiredFiles,
     },
-    instance::flush_compaction::{AllocFileId, Other, Result, StoreVersionEdit},
+    instance::flush_compaction::{AccessCheckpoint, AllocFileId, Other, Result, StoreVersionEdit},
     manifest::{
         meta_edit::{MetaEdit, MetaEditRequest, MetaUpdate, VersionEditMeta},
         ManifestRef,
     },
-    sst::{factory::SstWriteOptions, file::FileMeta},
+    sst::{
+        factory::{SstWriteOptions, StoreProviderRef},
+        file::{self, FileMeta},
+        manager::FileId,
+        resumable_writer::WriteCheckpoint,
+    },
     table::{
         data::TableData,
+        sst_util,
         version_edit::{AddFile, DeleteFile},
     },
 };
@@ -46,11 +54,45 @@ pub(crate) struct Compactor {
 
     /// Manifest (or meta) stores meta data of the engine instance.
     manifest: ManifestRef,
+
+    /// Max number of ssts written in parallel for one compaction input.
+    max_parallel_sst_writers: usize,
+
+    /// Min number of rows of every sst written in parallel.
+    min_rows_per_parallel_sst: usize,
+
+    /// Provider of the store picker for persisting the write checkpoints.
+    store_provider: StoreProviderRef,
+
+    /// Number of rows of every sst written with a checkpoint, and the
+    /// resumable writing is disabled if it is zero.
+    sst_checkpoint_rows: usize,
 }
 
 impl Compactor {
-    pub fn new(runner: CompactionRunnerPtr, manifest: ManifestRef) -> Self {
-        Self { runner, manifest }
+    pub fn new(
+        runner: CompactionRunnerPtr,
+        manifest: ManifestRef,
+        max_parallel_sst_writers: usize,
+        min_rows_per_parallel_sst: usize,
+        store_provider: StoreProviderRef,
+        sst_checkpoint_rows: usize,
+    ) -> Self {
+        Self {
+            runner,
+            manifest,
+            max_parallel_sst_writers,
+            min_rows_per_parallel_sst,
+            store_provider,
+            sst_checkpoint_rows,
+        }
+    }
+
+    /// Decide the number of output ssts by the number of input rows.
+    fn num_output_ssts(&self, input_row_num: u64) -> usize {
+        let min_rows = self.min_rows_per_parallel_sst.max(1) as u64;
+        let num_ssts = (input_row_num / min_rows) as usize;
+        num_ssts.clamp(1, self.max_parallel_sst_writers.max(1))
     }
 
     pub async fn compact_table(
@@ -91,15 +133,18 @@ impl Compactor {
             self.delete_expired_files(table_data, &request_id, files, &mut edit_meta);
         }
 
+        let mut checkpoint_paths = Vec::new();
         for input in inputs {
-            self.compact_input_files(
-                request_id.clone(),
-                table_data,
-                input,
-                sst_write_options,
-                &mut edit_meta,
-            )
-            .await?;
+            let checkpoint_path = self
+                .compact_input_files(
+                    request_id.clone(),
+                    table_data,
+                    input,
+                    sst_write_options,
+                    &mut edit_meta,
+                )
+                .await?;
+            checkpoint_paths.extend(checkpoint_path);
         }
 
         if !table_data.allow_compaction() {
@@ -125,9 +170,86 @@ impl Compactor {
             .await
             .context(StoreVersionEdit)?;
 
+        let level_stats = table_data.current_version().level_stats();
+        debug!(
+            "Level stats after compaction, table:{}, table_id:{}, level_stats:{:?}",
+            table_data.name, table_data.id, level_stats
+        );
+        table_data.metrics.on_level_stats(&level_stats);
+
+        // The checkpoints are useless once the output ssts are committed, and the
+        // leaked ones are never loaded again because the input files are deleted.
+        let store = self
+            .store_provider
+            .store_picker(table_data.space_id)
+            .default_store();
+        for path in checkpoint_paths {
+            if let Err(e) = WriteCheckpoint::delete(store, &path).await {
+                warn!(
+                    "Failed to delete compaction checkpoint, table:{}, table_id:{}, path:{}, err:{}",
+                    table_data.name, table_data.id, path, e
+                );
+            }
+        }
+
         Ok(())
     }
 
+    /// Load the checkpoint of the unfinished compaction of the same input to
+    /// reuse its output ssts, or allocate the output ssts and persist the
+    /// initial checkpoint.
+    ///
+    /// Returns the ids of the output ssts and the number of rows of every sst.
+    async fn load_or_init_checkpoint(
+        &self,
+        table_data: &TableData,
+        checkpoint_path: &Path,
+        input_row_num: u64,
+    ) -> Result<(Vec<FileId>, usize)> {
+        let store = self
+            .store_provider
+            .store_picker(table_data.space_id)
+            .default_store();
+        let checkpoint = WriteCheckpoint::load(store, checkpoint_path)
+            .await
+            .box_err()
+            .with_context(|| AccessCheckpoint {
+                path: checkpoint_path.to_string(),
+            })?;
+        if let Some(checkpoint) = checkpoint {
+            info!(
+                "Resume compaction from checkpoint, table:{}, table_id:{}, path:{}, rows_written:{}",
+                table_data.name, table_data.id, checkpoint_path, checkpoint.rows_written
+            );
+            return Ok((checkpoint.file_ids, checkpoint.rows_per_sst));
+        }
+
+        let rows_per_sst = self.sst_checkpoint_rows;
+        let num_output_ssts = input_row_num.div_ceil(rows_per_sst as u64) as usize;
+        let mut file_ids = Vec::with_capacity(num_output_ssts);
+        for _ in 0..num_output_ssts {
+            let file_id = table_data
+                .alloc_file_id(&self.manifest)
+                .await
+                .context(AllocFileId)?;
+            file_ids.push(file_id);
+        }
+
+        WriteCheckpoint::new(file_ids.clone(), rows_per_sst)
+            .save(store, checkpoint_path)
+            .await
+            .box_err()
+            .with_context(|| AccessCheckpoint {
+                path: checkpoint_path.to_string(),
+            })?;
+
+        Ok((file_ids, rows_per_sst))
+    }
+
+    /// Compact the input files and add the outputs into `edit_meta`.
+    ///
+    /// Returns the path of the write checkpoint which should be deleted after
+    /// the `edit_meta` is applied.
     #[allow(clippy::too_many_arguments)]
     pub async fn compact_input_files(
         &self,
@@ -136,14 +258,14 @@ impl Compactor {
         input: &CompactionInputFiles,
         sst_write_options: &SstWriteOptions,
         edit_meta: &mut VersionEditMeta,
-    ) -> Result<()> {
+    ) -> Result<Option<Path>> {
         debug!(
             "Compact input files, table_name:{}, id:{}, input::{:?}, edit_meta:{:?}",
             table_data.name, table_data.id, input, edit_meta
         );
 
         if input.files.is_empty() {
-            return Ok(());
+            return Ok(None);
         }
 
         // Metrics
@@ -170,18 +292,53 @@ impl Compactor {
             request_id, table_data.name, table_data.id, input.files,
         );
 
-        // Alloc file id for the merged sst.
-        let file_id = table_data
-            .alloc_file_id(&self.manifest)
-            .await
-            .context(AllocFileId)?;
+        // The huge output is written with checkpoints to resume on failure.
+        let checkpoint_path = (self.sst_checkpoint_rows > 0
+            && sst_row_num > self.sst_checkpoint_rows as u64)
+            .then(|| {
+                let input_file_ids: Vec<_> = input.files.iter().map(|file| file.id()).collect();
+                sst_util::new_compaction_checkpoint_path(
+                    table_data.space_id,
+                    table_data.id,
+                    &input_file_ids,
+                )
+            });
+        let (file_ids, rows_per_sst) = if let Some(path) = &checkpoint_path {
+            self.load_or_init_checkpoint(table_data, path, sst_row_num)
+                .await?
+        } else {
+            // Alloc file ids for the merged ssts.
+            let num_output_ssts = self.num_output_ssts(sst_row_num);
+            let mut file_ids = Vec::with_capacity(num_output_ssts);
+            for _ in 0..num_output_ssts {
+                let file_id = table_data
+                    .alloc_file_id(&self.manifest)
+                    .await
+                    .context(AllocFileId)?;
+                file_ids.push(file_id);
+            }
+            let rows_per_sst = sst_row_num.div_ceil(num_output_ssts as u64) as usize;
+            (file_ids, rows_per_sst)
+        };
+        let file_id = file_ids[0];
+
+        // Pick the low-cardinality columns by the column stats of the input files.
+        let mut sst_write_options = sst_write_options.clone();
+        sst_write_options
+            .column_stats
+            .extend(file::low_cardinality_columns(
+                &table_data.schema(),
+                &input.files,
+            ));
 
         let task = CompactionRunnerTask::new(
             request_id.clone(),
             input.clone(),
             table_data,
-            file_id,
-            sst_write_options.clone(),
+            &file_ids,
+            rows_per_sst,
+            checkpoint_path.clone(),
+            sst_write_options,
         );
 
         let task_result = self.runner.run(task).await?;
@@ -189,6 +346,7 @@ impl Compactor {
             sst_info,
             sst_meta,
             output_file_path,
+            extra_sst_infos,
         } = task_result;
 
         let sst_file_size = sst_info.file_size as u64;
@@ -235,11 +393,39 @@ impl Compactor {
                 max_seq: sst_meta.max_sequence,
                 time_range: sst_meta.time_range,
                 storage_format: sst_info.storage_format,
-                associated_files: vec![sst_info.meta_path],
+                associated_files: sst_info.associated_files(),
+                column_stats: sst_info.column_stats.clone(),
             },
         });
 
-        Ok(())
+        // Add the additional files split from the output.
+        for (file_id, sst_info) in file_ids[1..].iter().zip(extra_sst_infos) {
+            let Some(sst_info) = sst_info else {
+                continue;
+            };
+
+            table_data
+                .metrics
+                .compaction_observe_output_sst_size(sst_info.file_size as u64);
+            table_data
+                .metrics
+                .compaction_observe_output_sst_row_num(sst_info.row_num as u64);
+            edit_meta.files_to_add.push(AddFile {
+                level: input.output_level,
+                file: FileMeta {
+                    id: *file_id,
+                    size: sst_info.file_size as u64,
+                    row_num: sst_info.row_num as u64,
+                    max_seq: sst_meta.max_sequence,
+                    time_range: sst_info.time_range,
+                    storage_format: sst_info.storage_format,
+                    associated_files: sst_info.associated_files(),
+                    column_stats: sst_info.column_stats.clone(),
+                },
+            });
+        }
+
+        Ok(checkpoint_path)
     }
 
     pub fn delete_expired_files(
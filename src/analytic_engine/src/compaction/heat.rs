@@ -0,0 +1,251 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+//! Query heat of the cold time ranges.
+//!
+//! The segments older than `cold_age` are rarely queried and may be left with
+//! many small ssts. Once such a segment is queried frequently (e.g. during an
+//! incident retrospective), a compaction targeting the segment is triggered so
+//! that the following queries on it get fast.
+
+use std::{collections::HashMap, sync::Mutex, time::Duration};
+
+use common_types::time::{TimeRange, Timestamp};
+use serde::{Deserialize, Serialize};
+use time_ext::ReadableDuration;
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct QueryHeatConfig {
+    /// Whether to compact the cold segments becoming hot.
+    pub enable: bool,
+    /// The segments ending earlier than `now - cold_age` are considered cold.
+    pub cold_age: ReadableDuration,
+    /// A cold segment becomes hot once it is queried so many times in
+    /// `window`.
+    pub hot_query_threshold: usize,
+    pub window: ReadableDuration,
+    /// Max number of segments tracked per table, and the queries covering more
+    /// cold segments are ignored.
+    pub max_tracked_segments: usize,
+}
+
+impl Default for QueryHeatConfig {
+    fn default() -> Self {
+        Self {
+            enable: false,
+            cold_age: ReadableDuration::days(1),
+            hot_query_threshold: 3,
+            window: ReadableDuration::minutes(10),
+            max_tracked_segments: 64,
+        }
+    }
+}
+
+#[derive(Debug)]
+struct SegmentHeat {
+    window_start: Timestamp,
+    queries: usize,
+    /// Whether the compaction has been triggered in current window.
+    triggered: bool,
+}
+
+impl SegmentHeat {
+    fn is_window_expired(&self, now: Timestamp, window: Duration) -> bool {
+        self.window_start <= now.sub_duration_or_min(window)
+    }
+}
+
+/// Query heat of the cold segments of a table.
+#[derive(Debug, Default)]
+pub struct QueryHeat {
+    /// Segment start -> heat of the segment.
+    segments: Mutex<HashMap<Timestamp, SegmentHeat>>,
+}
+
+impl QueryHeat {
+    /// Record a query on `query_range`, returns the cold segments becoming hot
+    /// by this query.
+    pub fn record(
+        &self,
+        config: &QueryHeatConfig,
+        query_range: TimeRange,
+        segment_duration: Duration,
+        now: Timestamp,
+    ) -> Vec<TimeRange> {
+        if !config.enable {
+            return Vec::new();
+        }
+
+        let cold_segments = Self::cold_segments(config, query_range, segment_duration, now);
+        if cold_segments.is_empty() {
+            return Vec::new();
+        }
+
+        let window = config.window.0;
+        let mut hot_segments = Vec::new();
+        let mut segments = self.segments.lock().unwrap();
+        for segment in cold_segments {
+            let start = segment.inclusive_start();
+            if !segments.contains_key(&start) && segments.len() >= config.max_tracked_segments {
+                segments.retain(|_, heat| !heat.is_window_expired(now, window));
+                if segments.len() >= config.max_tracked_segments {
+                    continue;
+                }
+            }
+
+            let heat = segments.entry(start).or_insert(SegmentHeat {
+                window_start: now,
+                queries: 0,
+                triggered: false,
+            });
+            if heat.is_window_expired(now, window) {
+                *heat = SegmentHeat {
+                    window_start: now,
+                    queries: 0,
+                    triggered: false,
+                };
+            }
+
+            heat.queries += 1;
+            if !heat.triggered && heat.queries >= config.hot_query_threshold {
+                heat.triggered = true;
+                hot_segments.push(segment);
+            }
+        }
+
+        hot_segments
+    }
+
+    /// Returns the cold segments covered by `query_range`, empty if there are
+    /// too many of them.
+    fn cold_segments(
+        config: &QueryHeatConfig,
+        query_range: TimeRange,
+        segment_duration: Duration,
+        now: Timestamp,
+    ) -> Vec<TimeRange> {
+        let cold_end = now
+            .sub_duration_or_min(config.cold_age.0)
+            .min(query_range.exclusive_end());
+        let Some(first) = TimeRange::bucket_of(query_range.inclusive_start(), segment_duration)
+        else {
+            return Vec::new();
+        };
+        if first.inclusive_start() >= cold_end {
+            return Vec::new();
+        }
+
+        let segment_ms = first.exclusive_end().as_i64() - first.inclusive_start().as_i64();
+        let num_segments = cold_end
+            .as_i64()
+            .checked_sub(first.inclusive_start().as_i64())
+            .map(|span| (span + segment_ms - 1) / segment_ms);
+        match num_segments {
+            Some(n) if n as usize <= config.max_tracked_segments => (),
+            _ => return Vec::new(),
+        }
+
+        let mut segments = Vec::new();
+        let mut segment = Some(first);
+        while let Some(current) = segment {
+            if current.inclusive_start() >= cold_end {
+                break;
+            }
+            segments.push(current);
+            segment = TimeRange::bucket_of(current.exclusive_end(), segment_duration);
+        }
+
+        segments
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HOUR_MS: i64 = 3600 * 1000;
+
+    fn new_config() -> QueryHeatConfig {
+        QueryHeatConfig {
+            enable: true,
+            cold_age: ReadableDuration::hours(24),
+            hot_query_threshold: 2,
+            window: ReadableDuration::minutes(10),
+            max_tracked_segments: 4,
+        }
+    }
+
+    #[test]
+    fn test_cold_segment_becomes_hot() {
+        let config = new_config();
+        let heat = QueryHeat::default();
+        let segment_duration = Duration::from_millis(HOUR_MS as u64);
+        let now = Timestamp::new(100 * HOUR_MS);
+
+        // Queries on the recent data are ignored.
+        let recent = TimeRange::new_unchecked_for_test(90 * HOUR_MS, 100 * HOUR_MS);
+        for _ in 0..3 {
+            assert!(heat
+                .record(&config, recent, segment_duration, now)
+                .is_empty());
+        }
+
+        let cold = TimeRange::new_unchecked_for_test(10 * HOUR_MS + 10, 11 * HOUR_MS + 10);
+        assert!(heat.record(&config, cold, segment_duration, now).is_empty());
+        let hot_segments = heat.record(&config, cold, segment_duration, now);
+        assert_eq!(
+            hot_segments,
+            vec![
+                TimeRange::new_unchecked_for_test(10 * HOUR_MS, 11 * HOUR_MS),
+                TimeRange::new_unchecked_for_test(11 * HOUR_MS, 12 * HOUR_MS),
+            ]
+        );
+        // Only triggered once in a window.
+        assert!(heat.record(&config, cold, segment_duration, now).is_empty());
+
+        // Triggered again in the next window.
+        let now = now.checked_add_i64(HOUR_MS).unwrap();
+        assert!(heat.record(&config, cold, segment_duration, now).is_empty());
+        assert_eq!(heat.record(&config, cold, segment_duration, now).len(), 2);
+
+        // Too broad queries are ignored.
+        let broad = TimeRange::new_unchecked_for_test(0, 10 * HOUR_MS);
+        for _ in 0..3 {
+            assert!(heat
+                .record(&config, broad, segment_duration, now)
+                .is_empty());
+        }
+        let all = TimeRange::min_to_max();
+        assert!(heat.record(&config, all, segment_duration, now).is_empty());
+    }
+
+    #[test]
+    fn test_disabled() {
+        let config = QueryHeatConfig {
+            enable: false,
+            ..new_config()
+        };
+        let heat = QueryHeat::default();
+        let cold = TimeRange::new_unchecked_for_test(10 * HOUR_MS, 11 * HOUR_MS);
+        let segment_duration = Duration::from_millis(HOUR_MS as u64);
+        let now = Timestamp::new(100 * HOUR_MS);
+        for _ in 0..3 {
+            assert!(heat.record(&config, cold, segment_duration, now).is_empty());
+        }
+    }
+}
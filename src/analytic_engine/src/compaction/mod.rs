@@ -19,7 +19,7 @@
 
 use std::{collections::HashMap, fmt, str::FromStr, sync::Arc};
 
-use common_types::COMPACTION_STRATEGY;
+use common_types::{time::TimeRange, COMPACTION_STRATEGY};
 use generic_error::{BoxError, GenericError};
 use macros::define_result;
 use serde::{Deserialize, Serialize};
@@ -35,6 +35,7 @@ use crate::{
 };
 
 pub mod compactor;
+pub mod heat;
 mod metrics;
 pub mod picker;
 pub mod runner;
@@ -96,6 +97,7 @@ pub enum CompactionStrategy {
     Default,
     TimeWindow(TimeWindowCompactionOptions),
     SizeTiered(SizeTieredCompactionOptions),
+    LazyLeveling(LazyLevelingCompactionOptions),
 }
 
 #[derive(Debug, Clone, Copy, Deserialize, PartialEq, Serialize)]
@@ -115,6 +117,19 @@ pub struct TimeWindowCompactionOptions {
     pub timestamp_resolution: TimeUnit,
 }
 
+/// Options of the lazy leveling compaction.
+///
+/// The level 0 ssts are compacted by the time window strategy, and every output
+/// is stacked onto the largest level as a new sorted run. The sorted runs in a
+/// segment of the largest level are only merged when their number exceeds
+/// `max_sorted_runs`, which trades some read amplification for less write
+/// amplification.
+#[derive(Debug, Clone, Copy, Deserialize, PartialEq, Serialize)]
+pub struct LazyLevelingCompactionOptions {
+    pub time_window: TimeWindowCompactionOptions,
+    pub max_sorted_runs: usize,
+}
+
 // TODO: MAX_INPUT_SSTABLE_SIZE is a temp solution to control sst size
 // Remove this when we can control compaction's output size
 // https://github.com/apache/incubator-horaedb/issues/408
@@ -147,15 +162,26 @@ impl Default for TimeWindowCompactionOptions {
     }
 }
 
+impl Default for LazyLevelingCompactionOptions {
+    fn default() -> Self {
+        Self {
+            time_window: TimeWindowCompactionOptions::default(),
+            max_sorted_runs: 8,
+        }
+    }
+}
+
 const BUCKET_LOW_KEY: &str = "compaction_bucket_low";
 const BUCKET_HIGH_KEY: &str = "compaction_bucket_high";
 const MIN_THRESHOLD_KEY: &str = "compaction_min_threshold";
 const MAX_THRESHOLD_KEY: &str = "compaction_max_threshold";
 const MIN_SSTABLE_SIZE_KEY: &str = "compaction_min_sstable_size";
 const TIMESTAMP_RESOLUTION_KEY: &str = "compaction_timestamp_resolution";
+const MAX_SORTED_RUNS_KEY: &str = "compaction_max_sorted_runs";
 const DEFAULT_STRATEGY: &str = "default";
 const STC_STRATEGY: &str = "size_tiered";
 const TWC_STRATEGY: &str = "time_window";
+const LLC_STRATEGY: &str = "lazy_leveling";
 
 impl CompactionStrategy {
     pub(crate) fn parse_from(
@@ -170,6 +196,9 @@ impl CompactionStrategy {
             TWC_STRATEGY => Ok(CompactionStrategy::TimeWindow(
                 TimeWindowCompactionOptions::parse_from(options)?,
             )),
+            LLC_STRATEGY => Ok(CompactionStrategy::LazyLeveling(
+                LazyLevelingCompactionOptions::parse_from(options)?,
+            )),
             _ => ParseStrategy {
                 value: value.to_string(),
             }
@@ -193,6 +222,10 @@ impl CompactionStrategy {
                 m.insert(COMPACTION_STRATEGY.to_string(), TWC_STRATEGY.to_string());
                 opts.fill_raw_map(m);
             }
+            CompactionStrategy::LazyLeveling(opts) => {
+                m.insert(COMPACTION_STRATEGY.to_string(), LLC_STRATEGY.to_string());
+                opts.fill_raw_map(m);
+            }
         }
     }
 }
@@ -332,6 +365,48 @@ impl TimeWindowCompactionOptions {
     }
 }
 
+impl LazyLevelingCompactionOptions {
+    fn fill_raw_map(&self, m: &mut HashMap<String, String>) {
+        self.time_window.fill_raw_map(m);
+
+        m.insert(
+            MAX_SORTED_RUNS_KEY.to_string(),
+            format!("{}", self.max_sorted_runs),
+        );
+    }
+
+    pub(crate) fn validate(&self) -> Result<()> {
+        ensure!(
+            self.max_sorted_runs > 0,
+            InvalidOption {
+                error: format!("{} value must be greater than 0", MAX_SORTED_RUNS_KEY),
+            }
+        );
+
+        Ok(())
+    }
+
+    pub(crate) fn parse_from(
+        options: &HashMap<String, String>,
+    ) -> Result<LazyLevelingCompactionOptions> {
+        let mut opts = LazyLevelingCompactionOptions {
+            time_window: TimeWindowCompactionOptions::parse_from(options)?,
+            ..Default::default()
+        };
+
+        if let Some(v) = options.get(MAX_SORTED_RUNS_KEY) {
+            opts.max_sorted_runs = v.parse().context(ParseInt {
+                key: MAX_SORTED_RUNS_KEY,
+                value: v,
+            })?;
+        }
+
+        opts.validate()?;
+
+        Ok(opts)
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct CompactionInputFiles {
     /// Level of the files to be compacted.
@@ -599,6 +674,8 @@ impl Drop for WaiterNotifier {
 pub struct TableCompactionRequest {
     pub table_data: TableDataRef,
     pub waiter: Option<oneshot::Sender<WaitResult<()>>>,
+    /// Compact the ssts in this time range first if it is set.
+    pub target_range: Option<TimeRange>,
 }
 
 impl TableCompactionRequest {
@@ -607,6 +684,7 @@ impl TableCompactionRequest {
         let req = Self {
             table_data,
             waiter: Some(tx),
+            target_range: None,
         };
 
         (req, rx)
@@ -616,6 +694,16 @@ impl TableCompactionRequest {
         TableCompactionRequest {
             table_data,
             waiter: None,
+            target_range: None,
+        }
+    }
+
+    /// Build a request compacting the ssts in `target_range`.
+    pub fn targeted(table_data: TableDataRef, target_range: TimeRange) -> Self {
+        TableCompactionRequest {
+            table_data,
+            waiter: None,
+            target_range: Some(target_range),
         }
     }
 }
@@ -678,5 +766,26 @@ mod tests {
             c,
             CompactionStrategy::parse_from("time_window", &m).unwrap()
         );
+
+        let llc_opts = LazyLevelingCompactionOptions {
+            time_window: twc_opts,
+            max_sorted_runs: 3,
+        };
+        let c = CompactionStrategy::LazyLeveling(llc_opts);
+        let mut m = HashMap::new();
+        c.fill_raw_map(&mut m);
+
+        assert_eq!(8, m.len());
+        assert_eq!(m[COMPACTION_STRATEGY], "lazy_leveling");
+        assert_eq!(m[TIMESTAMP_RESOLUTION_KEY], "milliseconds");
+        assert_eq!(m[MAX_SORTED_RUNS_KEY], "3");
+
+        assert_eq!(
+            c,
+            CompactionStrategy::parse_from("lazy_leveling", &m).unwrap()
+        );
+
+        m.insert(MAX_SORTED_RUNS_KEY.to_string(), "0".to_string());
+        assert!(CompactionStrategy::parse_from("lazy_leveling", &m).is_err());
     }
 }
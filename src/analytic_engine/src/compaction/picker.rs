@@ -23,7 +23,7 @@ use std::{
     time::Duration,
 };
 
-use common_types::time::Timestamp;
+use common_types::time::{TimeRange, Timestamp};
 use logger::{debug, info};
 use macros::define_result;
 use snafu::Snafu;
@@ -32,7 +32,7 @@ use time_ext::TimeUnit;
 use crate::{
     compaction::{
         CompactionInputFiles, CompactionStrategy, CompactionTask, CompactionTaskBuilder,
-        SizeTieredCompactionOptions, TimeWindowCompactionOptions,
+        LazyLevelingCompactionOptions, SizeTieredCompactionOptions, TimeWindowCompactionOptions,
     },
     sst::{
         file::{FileHandle, Level},
@@ -51,6 +51,10 @@ pub struct PickerContext {
     /// The ttl of the data in sst.
     pub ttl: Option<Duration>,
     pub strategy: CompactionStrategy,
+    /// The current time, used to decide the expired ssts.
+    pub now: Timestamp,
+    /// The ssts in this time range are picked first if it is set.
+    pub target_range: Option<TimeRange>,
 }
 
 impl PickerContext {
@@ -64,9 +68,17 @@ impl PickerContext {
     fn time_window_opts(&self) -> TimeWindowCompactionOptions {
         match self.strategy {
             CompactionStrategy::TimeWindow(opts) => opts,
+            CompactionStrategy::LazyLeveling(opts) => opts.time_window,
             _ => TimeWindowCompactionOptions::default(),
         }
     }
+
+    fn lazy_leveling_opts(&self) -> LazyLevelingCompactionOptions {
+        match self.strategy {
+            CompactionStrategy::LazyLeveling(opts) => opts,
+            _ => LazyLevelingCompactionOptions::default(),
+        }
+    }
 }
 
 pub trait CompactionPicker {
@@ -106,6 +118,7 @@ impl CommonCompactionPicker {
             CompactionStrategy::TimeWindow(_) | CompactionStrategy::Default => {
                 Arc::new(TimeWindowPicker::default())
             }
+            CompactionStrategy::LazyLeveling(_) => Arc::new(LazyLevelingPicker::default()),
         };
         Self { level_picker }
     }
@@ -133,6 +146,43 @@ impl CommonCompactionPicker {
 
         None
     }
+
+    /// Pick the ssts overlapping with the `target_range` from the first level
+    /// having more than one of them.
+    fn pick_target_candidates(
+        ctx: &PickerContext,
+        levels_controller: &LevelsController,
+        target_range: TimeRange,
+        expire_time: Option<Timestamp>,
+    ) -> Option<CompactionInputFiles> {
+        let size_tiered_opts = match ctx.strategy {
+            CompactionStrategy::SizeTiered(opts) => opts,
+            _ => ctx.time_window_opts().size_tiered,
+        };
+        for level in levels_controller.levels() {
+            let mut files: Vec<_> = find_uncompact_files(levels_controller, level, expire_time)
+                .into_iter()
+                .filter(|file| file.time_range().intersect_with(target_range))
+                .collect();
+            // Merge the newest ssts first, so that only ssts with adjacent sequences are
+            // merged after trimming.
+            files.sort_unstable_by_key(|f| std::cmp::Reverse(f.max_sequence()));
+            let files = trim_to_threshold(
+                files,
+                size_tiered_opts.max_threshold,
+                size_tiered_opts.max_input_sstable_size.as_byte(),
+            );
+            if files.len() > 1 {
+                return Some(CompactionInputFiles {
+                    level,
+                    files,
+                    output_level: level.next(),
+                });
+            }
+        }
+
+        None
+    }
 }
 
 impl CompactionPicker for CommonCompactionPicker {
@@ -141,12 +191,15 @@ impl CompactionPicker for CommonCompactionPicker {
         ctx: PickerContext,
         levels_controller: &mut LevelsController,
     ) -> Result<CompactionTask> {
-        let expire_time = ctx.ttl.map(Timestamp::expire_time);
+        let expire_time = ctx.ttl.map(|ttl| ctx.now.sub_duration_or_min(ttl));
         let mut builder =
             CompactionTaskBuilder::with_expired(levels_controller.expired_ssts(expire_time));
 
-        if let Some(input_files) =
-            self.pick_compact_candidates(&ctx, levels_controller, expire_time)
+        let target_input_files = ctx.target_range.and_then(|target_range| {
+            Self::pick_target_candidates(&ctx, levels_controller, target_range, expire_time)
+        });
+        if let Some(input_files) = target_input_files
+            .or_else(|| self.pick_compact_candidates(&ctx, levels_controller, expire_time))
         {
             info!(
                 "Compaction strategy: {:?} picker pick files to compact, input_files:{:?}",
@@ -695,6 +748,80 @@ impl LevelPicker for TimeWindowPicker {
     }
 }
 
+/// Lazy leveling compaction strategy
+///
+/// The levels below the largest one are compacted by [TimeWindowPicker], and
+/// every output is stacked onto the largest level as a new sorted run. The
+/// sorted runs in a segment of the largest level are left untouched until
+/// their number exceeds `max_sorted_runs`, and then the newest runs are merged
+/// together.
+#[derive(Default)]
+pub struct LazyLevelingPicker {
+    time_window: TimeWindowPicker,
+}
+
+impl LazyLevelingPicker {
+    /// Pick the sorted runs to merge from the newest segment whose number of
+    /// runs exceeds `max_sorted_runs`.
+    fn pick_sorted_runs(
+        segments: BTreeMap<Timestamp, Vec<FileHandle>>,
+        opts: &LazyLevelingCompactionOptions,
+    ) -> Option<Vec<FileHandle>> {
+        let size_tiered_opts = &opts.time_window.size_tiered;
+        for (segment_key, mut runs) in segments.into_iter().rev() {
+            if runs.len() <= opts.max_sorted_runs {
+                continue;
+            }
+
+            // Only runs with adjacent sequences can be merged, and the newest runs are
+            // usually the smallest ones, so merge them first.
+            runs.sort_unstable_by_key(|f| std::cmp::Reverse(f.max_sequence()));
+            let files = trim_to_threshold(
+                runs,
+                size_tiered_opts.max_threshold,
+                size_tiered_opts.max_input_sstable_size.as_byte(),
+            );
+            if files.len() >= 2 {
+                debug!("Merge sorted runs, segment_key:{segment_key:?}, files:{files:?}");
+                return Some(files);
+            }
+        }
+
+        None
+    }
+}
+
+impl LevelPicker for LazyLevelingPicker {
+    fn pick_candidates_at_level(
+        &self,
+        ctx: &PickerContext,
+        levels_controller: &LevelsController,
+        level: Level,
+        expire_time: Option<Timestamp>,
+    ) -> Option<Vec<FileHandle>> {
+        if level != Level::MAX {
+            return self.time_window.pick_candidates_at_level(
+                ctx,
+                levels_controller,
+                level,
+                expire_time,
+            );
+        }
+
+        let segments = SizeTieredPicker::files_by_segment(
+            levels_controller,
+            level,
+            ctx.segment_duration,
+            expire_time,
+        );
+        if segments.is_empty() {
+            return None;
+        }
+
+        Self::pick_sorted_runs(segments, &ctx.lazy_leveling_opts())
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use std::time::Duration;
@@ -819,12 +946,14 @@ mod tests {
     fn test_time_window_picker() {
         let picker_manager = PickerManager;
         let twp = picker_manager.get_picker(CompactionStrategy::Default);
+        let now = Timestamp::new(1_700_000_000_000);
         let mut ctx = PickerContext {
             segment_duration: Duration::from_millis(1000),
             ttl: Some(Duration::from_secs(100000)),
             strategy: CompactionStrategy::Default,
+            now,
+            target_range: None,
         };
-        let now = Timestamp::now();
         {
             let mut lc = build_old_bucket_case(now.as_i64());
             let task = twp.pick_compaction(ctx.clone(), &mut lc).unwrap();
@@ -877,6 +1006,7 @@ mod tests {
                     max_seq: 0,
                     storage_format: StorageFormat::default(),
                     associated_files: Vec::new(),
+                    column_stats: Vec::new(),
                 };
                 let queue = FilePurgeQueue::new(1, 1.into(), tx.clone());
                 FileHandle::new(file_meta, queue)
@@ -898,6 +1028,7 @@ mod tests {
                     max_seq,
                     storage_format: StorageFormat::default(),
                     associated_files: Vec::new(),
+                    column_stats: Vec::new(),
                 };
                 let queue = FilePurgeQueue::new(1, 1.into(), tx.clone());
                 FileHandle::new(file_meta, queue)
@@ -1084,4 +1215,130 @@ mod tests {
                 .collect::<Vec<_>>()
         );
     }
+
+    fn build_sorted_runs_case(runs: Vec<(u64, TimeRange)>) -> LevelsController {
+        let (tx, _rx) = mpsc::unbounded_channel();
+        let queue = FilePurgeQueue::new(1, 1.into(), tx);
+        let mut levels_controller = LevelsController::new(queue);
+        for (id, (max_seq, time_range)) in runs.into_iter().enumerate() {
+            let file_meta = FileMeta {
+                size: 100,
+                time_range,
+                id: id as u64,
+                row_num: 0,
+                max_seq,
+                storage_format: StorageFormat::default(),
+                associated_files: Vec::new(),
+                column_stats: Vec::new(),
+            };
+            levels_controller.add_sst_to_level(Level::MAX, file_meta);
+        }
+
+        levels_controller
+    }
+
+    #[test]
+    fn test_lazy_leveling_picker() {
+        let opts = LazyLevelingCompactionOptions {
+            max_sorted_runs: 3,
+            ..Default::default()
+        };
+        let strategy = CompactionStrategy::LazyLeveling(opts);
+        let picker = PickerManager.get_picker(strategy);
+        let ctx = PickerContext {
+            segment_duration: Duration::from_millis(1000),
+            ttl: None,
+            strategy,
+            now: Timestamp::new(1_700_000_000_000),
+            target_range: None,
+        };
+        let old_segment = TimeRange::new_unchecked_for_test(100, 200);
+        let new_segment = TimeRange::new_unchecked_for_test(1100, 1200);
+
+        // The number of runs in every segment doesn't exceed the bound.
+        {
+            let mut lc = build_sorted_runs_case(vec![
+                (1, old_segment),
+                (2, old_segment),
+                (3, old_segment),
+                (4, new_segment),
+            ]);
+            let task = picker.pick_compaction(ctx.clone(), &mut lc).unwrap();
+            assert!(task.inputs.is_empty());
+        }
+
+        // The runs of the newest segment exceeding the bound are merged.
+        {
+            let mut lc = build_sorted_runs_case(vec![
+                (1, old_segment),
+                (2, old_segment),
+                (3, old_segment),
+                (4, old_segment),
+                (5, new_segment),
+                (6, new_segment),
+                (7, new_segment),
+                (8, new_segment),
+            ]);
+            let task = picker.pick_compaction(ctx.clone(), &mut lc).unwrap();
+            assert_eq!(task.inputs.len(), 1);
+            assert_eq!(task.inputs[0].level, Level::MAX);
+            assert_eq!(task.inputs[0].output_level, Level::MAX);
+            assert_eq!(
+                vec![8, 7, 6, 5],
+                task.inputs[0]
+                    .files
+                    .iter()
+                    .map(|f| f.max_sequence())
+                    .collect::<Vec<_>>()
+            );
+        }
+
+        // The level 0 ssts are compacted into a new sorted run of the largest level.
+        {
+            let now = ctx.now.as_i64();
+            let mut lc = build_old_bucket_case(now);
+            let task = picker.pick_compaction(ctx.clone(), &mut lc).unwrap();
+            assert_eq!(task.inputs[0].level, Level::MIN);
+            assert_eq!(task.inputs[0].output_level, Level::MAX);
+            assert_eq!(task.inputs[0].files.len(), 2);
+        }
+    }
+
+    #[test]
+    fn test_pick_target_range() {
+        let old_segment = TimeRange::new_unchecked_for_test(100, 200);
+        let new_segment = TimeRange::new_unchecked_for_test(1100, 1200);
+        let mut ctx = PickerContext {
+            segment_duration: Duration::from_millis(1000),
+            ttl: None,
+            strategy: CompactionStrategy::Default,
+            now: Timestamp::new(1_700_000_000_000),
+            target_range: Some(TimeRange::new_unchecked_for_test(0, 1000)),
+        };
+        let picker = PickerManager.get_picker(ctx.strategy);
+
+        let mut lc = build_sorted_runs_case(vec![
+            (1, old_segment),
+            (2, old_segment),
+            (3, old_segment),
+            (4, new_segment),
+        ]);
+        let task = picker.pick_compaction(ctx.clone(), &mut lc).unwrap();
+        assert_eq!(task.inputs.len(), 1);
+        assert_eq!(task.inputs[0].level, Level::MAX);
+        assert_eq!(
+            vec![3, 2, 1],
+            task.inputs[0]
+                .files
+                .iter()
+                .map(|f| f.max_sequence())
+                .collect::<Vec<_>>()
+        );
+
+        // Nothing to compact in the target range.
+        ctx.target_range = Some(new_segment);
+        let mut lc = build_sorted_runs_case(vec![(1, new_segment)]);
+        let task = picker.pick_compaction(ctx, &mut lc).unwrap();
+        assert!(task.inputs.is_empty());
+    }
 }
@@ -35,8 +35,10 @@ use crate::{
         merge::{MergeBuilder, MergeConfig},
     },
     sst::{
-        factory::{ColumnStats, FactoryRef, ObjectStorePickerRef, ScanOptions, SstWriteOptions},
+        factory::{ColumnStats, FactoryRef, ScanOptions, SstWriteOptions, StoreProviderRef},
         meta_data::{cache::MetaCacheRef, SstMetaData, SstMetaReader},
+        parallel_writer::ParallelSstWriter,
+        resumable_writer::ResumableSstWriter,
         writer::MetaData,
     },
     Config, ScanType, SstReadOptionsBuilder,
@@ -51,8 +53,8 @@ pub struct LocalCompactionRunner {
     scan_options: ScanOptions,
     /// Sst factory
     sst_factory: FactoryRef,
-    /// Store picker for persisting sst
-    store_picker: ObjectStorePickerRef,
+    /// Provider of the store picker for persisting sst
+    store_provider: StoreProviderRef,
     // TODO: maybe not needed in compaction
     sst_meta_cache: Option<MetaCacheRef>,
 }
@@ -62,7 +64,7 @@ impl LocalCompactionRunner {
         runtime: Arc<Runtime>,
         config: &Config,
         sst_factory: FactoryRef,
-        store_picker: ObjectStorePickerRef,
+        store_provider: StoreProviderRef,
         sst_meta_cache: Option<MetaCacheRef>,
     ) -> Self {
         let scan_options = ScanOptions {
@@ -75,7 +77,7 @@ impl LocalCompactionRunner {
             runtime,
             scan_options,
             sst_factory,
-            store_picker,
+            store_provider,
             sst_meta_cache,
         }
     }
@@ -102,6 +104,7 @@ impl CompactionRunner for LocalCompactionRunner {
         let row_projector_builder =
             RowProjectorBuilder::new(fetched_schema, table_schema, Some(primary_key_indexes));
 
+        let store_picker = self.store_provider.store_picker(task.space_id);
         let request_id = task.request_id;
         let merge_iter = {
             let mut builder = MergeBuilder::new(MergeConfig {
@@ -116,7 +119,7 @@ impl CompactionRunner for LocalCompactionRunner {
                 predicate: Arc::new(Predicate::empty()),
                 sst_read_options_builder: sst_read_options_builder.clone(),
                 sst_factory: &self.sst_factory,
-                store_picker: &self.store_picker,
+                store_picker,
                 merge_iter_options: task.input_ctx.merge_iter_options.clone(),
                 need_dedup: task.input_ctx.need_dedup,
                 reverse: false,
@@ -148,14 +151,16 @@ impl CompactionRunner for LocalCompactionRunner {
                 table_id: task.table_id,
                 factory: self.sst_factory.clone(),
                 read_opts: sst_read_options,
-                store_picker: self.store_picker.clone(),
+                store_picker: store_picker.clone(),
             };
             let sst_metas = meta_reader
                 .fetch_metas(&task.input_ctx.files.files)
                 .await
                 .context(ReadSstMeta)?;
 
-            let column_stats = collect_column_stats_from_meta_datas(&sst_metas);
+            // Columns picked by the column stats of the input files are kept.
+            let mut column_stats = task.output_ctx.write_options.column_stats.clone();
+            column_stats.extend(collect_column_stats_from_meta_datas(&sst_metas));
             let merged_meta =
                 MetaData::merge(sst_metas.into_iter().map(MetaData::from), task.schema);
             (merged_meta, column_stats)
@@ -169,12 +174,57 @@ impl CompactionRunner for LocalCompactionRunner {
             column_stats,
         };
 
+        if !task.output_ctx.extra_file_paths.is_empty() {
+            let mut file_paths = Vec::with_capacity(1 + task.output_ctx.extra_file_paths.len());
+            file_paths.push(task.output_ctx.file_path.clone());
+            file_paths.extend(task.output_ctx.extra_file_paths.iter().cloned());
+
+            let sst_infos = if let Some(checkpoint_path) = &task.output_ctx.checkpoint_path {
+                let sst_writer = ResumableSstWriter::new(
+                    &self.sst_factory,
+                    &sst_write_options,
+                    store_picker,
+                    task.input_ctx.files.output_level,
+                    &file_paths,
+                    task.output_ctx.rows_per_sst,
+                    checkpoint_path,
+                );
+                sst_writer
+                    .write(request_id, &sst_meta, record_batch_stream)
+                    .await
+            } else {
+                let sst_writer = ParallelSstWriter::new(
+                    &self.sst_factory,
+                    &sst_write_options,
+                    store_picker,
+                    task.input_ctx.files.output_level,
+                    &file_paths,
+                    task.output_ctx.rows_per_sst,
+                );
+                sst_writer
+                    .write(request_id, &sst_meta, record_batch_stream)
+                    .await
+            };
+            let mut sst_infos = sst_infos.box_err().with_context(|| WriteSst {
+                path: format!("{file_paths:?}"),
+            })?;
+            // The first sst is always written.
+            let sst_info = sst_infos.remove(0).unwrap();
+
+            return Ok(CompactionRunnerResult {
+                sst_info,
+                sst_meta,
+                output_file_path: task.output_ctx.file_path.clone(),
+                extra_sst_infos: sst_infos,
+            });
+        }
+
         let mut sst_writer = self
             .sst_factory
             .create_writer(
                 &sst_write_options,
                 &task.output_ctx.file_path,
-                &self.store_picker,
+                store_picker,
                 task.input_ctx.files.output_level,
             )
             .await
@@ -194,6 +244,7 @@ impl CompactionRunner for LocalCompactionRunner {
             sst_info,
             sst_meta,
             output_file_path: task.output_ctx.file_path.clone(),
+            extra_sst_infos: Vec::new(),
         })
     }
 }
@@ -17,7 +17,7 @@
 
 pub mod local_runner;
 pub mod node_picker;
-mod remote_client;
+pub mod remote_client;
 pub mod remote_runner;
 
 use std::sync::Arc;
@@ -25,10 +25,13 @@ use std::sync::Arc;
 use async_trait::async_trait;
 use common_types::{request_id::RequestId, schema::Schema, SequenceNumber};
 use generic_error::{BoxError, GenericError};
+use horaedbproto::compaction_service::{ExecResult, ExecuteCompactionTaskResponse};
 use macros::define_result;
 use object_store::Path;
+use prost::Message;
 use snafu::{Backtrace, OptionExt, ResultExt, Snafu};
 use table_engine::table::TableId;
+use tonic::metadata::{MetadataMap, MetadataValue};
 
 use crate::{
     compaction::CompactionInputFiles,
@@ -37,7 +40,7 @@ use crate::{
     space::SpaceId,
     sst::{
         factory::SstWriteOptions,
-        writer::{MetaData, SstInfo},
+        writer::{MetaData, MetaDataExt, SstInfo, SstInfoExt},
     },
     table::data::TableData,
 };
@@ -102,6 +105,9 @@ pub enum Error {
     #[snafu(display("Failed to convert sst meta, err:{}", source))]
     ConvertSstMeta { source: GenericError },
 
+    #[snafu(display("Failed to decode exec result ext, err:{}", source))]
+    DecodeExecResultExt { source: GenericError },
+
     #[snafu(display("Failed to connect the service endpoint:{}, err:{}", addr, source,))]
     FailConnect { addr: String, source: GenericError },
 
@@ -151,11 +157,13 @@ impl CompactionRunnerTask {
         request_id: RequestId,
         input_files: CompactionInputFiles,
         table_data: &TableData,
-        file_id: u64,
+        file_ids: &[u64],
+        rows_per_sst: usize,
+        checkpoint_path: Option<Path>,
         sst_write_options: SstWriteOptions,
     ) -> Self {
         // Create task key.
-        let task_key = table_data.compaction_task_key(file_id);
+        let task_key = table_data.compaction_task_key(file_ids[0]);
 
         // Create executor task.
         let table_options = table_data.table_options();
@@ -174,10 +182,17 @@ impl CompactionRunnerTask {
         };
 
         let output_ctx = {
-            let file_path = table_data.sst_file_path(file_id);
+            let file_path = table_data.sst_file_path(file_ids[0]);
+            let extra_file_paths = file_ids[1..]
+                .iter()
+                .map(|file_id| table_data.sst_file_path(*file_id))
+                .collect();
             OutputContext {
                 file_path,
                 write_options: sst_write_options,
+                extra_file_paths,
+                rows_per_sst,
+                checkpoint_path,
             }
         };
 
@@ -262,26 +277,79 @@ pub struct CompactionRunnerResult {
     pub output_file_path: Path,
     pub sst_info: SstInfo,
     pub sst_meta: MetaData,
+    /// Infos of the ssts in [OutputContext::extra_file_paths], and the info is
+    /// `None` if no rows are written into the sst.
+    pub extra_sst_infos: Vec<Option<SstInfo>>,
 }
 
-impl TryFrom<horaedbproto::compaction_service::ExecuteCompactionTaskResponse>
-    for CompactionRunnerResult
-{
+/// Key of the binary metadata of the [ExecuteCompactionTaskResponse] carrying
+/// the encoded [ExecResultExt].
+const EXEC_RESULT_EXT_KEY: &str = "horaedb-exec-result-ext-bin";
+
+/// Pb message of the attributes of the compaction output which are absent
+/// from [ExecResult].
+///
+/// It's sent in the metadata of the response, so the nodes unaware of it just
+/// ignore it.
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct ExecResultExt {
+    #[prost(message, optional, tag = "1")]
+    pub sst_info: Option<SstInfoExt>,
+    #[prost(message, optional, tag = "2")]
+    pub sst_meta: Option<MetaDataExt>,
+}
+
+impl ExecResultExt {
+    /// Decode the ext from the metadata of the response, and the default one
+    /// is returned if it's absent.
+    pub fn decode_from(metadata: &MetadataMap) -> Result<Self> {
+        let Some(value) = metadata.get_bin(EXEC_RESULT_EXT_KEY) else {
+            return Ok(Self::default());
+        };
+        let buf = value.to_bytes().box_err().context(DecodeExecResultExt)?;
+
+        Self::decode(buf).box_err().context(DecodeExecResultExt)
+    }
+
+    /// Encode the ext into the metadata of the response.
+    pub fn encode_to(&self, metadata: &mut MetadataMap) {
+        metadata.insert_bin(
+            EXEC_RESULT_EXT_KEY,
+            MetadataValue::from_bytes(&self.encode_to_vec()),
+        );
+    }
+}
+
+impl From<CompactionRunnerResult> for (ExecResult, ExecResultExt) {
+    fn from(value: CompactionRunnerResult) -> Self {
+        let (sst_info, sst_info_ext) = value.sst_info.into();
+        let (sst_meta, sst_meta_ext) = value.sst_meta.into();
+        let res = ExecResult {
+            output_file_path: value.output_file_path.into(),
+            sst_info: Some(sst_info),
+            sst_meta: Some(sst_meta),
+        };
+        let ext = ExecResultExt {
+            sst_info: Some(sst_info_ext),
+            sst_meta: Some(sst_meta_ext),
+        };
+
+        (res, ext)
+    }
+}
+
+impl TryFrom<(ExecuteCompactionTaskResponse, ExecResultExt)> for CompactionRunnerResult {
     type Error = Error;
 
-    fn try_from(
-        resp: horaedbproto::compaction_service::ExecuteCompactionTaskResponse,
-    ) -> Result<Self> {
+    fn try_from((resp, ext): (ExecuteCompactionTaskResponse, ExecResultExt)) -> Result<Self> {
         let res = resp.result.context(EmptyExecResult)?;
-        let sst_info = res
-            .sst_info
-            .context(EmptySstInfo)?
+        let sst_info_ext = ext.sst_info.unwrap_or_default();
+        let sst_info = (res.sst_info.context(EmptySstInfo)?, sst_info_ext)
             .try_into()
             .box_err()
             .context(ConvertSstInfo)?;
-        let sst_meta = res
-            .sst_meta
-            .context(EmptySstMeta)?
+        let sst_meta_ext = ext.sst_meta.unwrap_or_default();
+        let sst_meta = (res.sst_meta.context(EmptySstMeta)?, sst_meta_ext)
             .try_into()
             .box_err()
             .context(ConvertSstMeta)?;
@@ -290,6 +358,8 @@ impl TryFrom<horaedbproto::compaction_service::ExecuteCompactionTaskResponse>
             output_file_path: res.output_file_path.into(),
             sst_info,
             sst_meta,
+            // The remote compaction always writes a single sst.
+            extra_sst_infos: Vec::new(),
         })
     }
 }
@@ -346,6 +416,15 @@ pub struct OutputContext {
     pub file_path: Path,
     /// Output sst write context
     pub write_options: SstWriteOptions,
+    /// Paths of the additional ssts to split the output into by key range, and
+    /// the output is a single sst if it is empty.
+    pub extra_file_paths: Vec<Path>,
+    /// Number of rows of every output sst except the last one, only used if
+    /// `extra_file_paths` is not empty.
+    pub rows_per_sst: usize,
+    /// Path of the write checkpoint, and the ssts are written one by one with
+    /// a checkpoint persisted after every sst if it is set.
+    pub checkpoint_path: Option<Path>,
 }
 
 impl TryFrom<horaedbproto::compaction_service::OutputContext> for OutputContext {
@@ -360,9 +439,14 @@ impl TryFrom<horaedbproto::compaction_service::OutputContext> for OutputContext
             .box_err()
             .context(ConvertSstWriteOptions)?;
 
+        // TODO: split the output of the remote compaction into multiple ssts.
+        // TODO: support resumable writing in the remote compaction.
         Ok(OutputContext {
             file_path,
             write_options,
+            extra_file_paths: Vec::new(),
+            rows_per_sst: 0,
+            checkpoint_path: None,
         })
     }
 }
@@ -375,3 +459,34 @@ impl From<OutputContext> for horaedbproto::compaction_service::OutputContext {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sst::writer::SstColumnStatsPb;
+
+    #[test]
+    fn test_exec_result_ext_in_metadata() {
+        let mut metadata = MetadataMap::new();
+        assert_eq!(
+            ExecResultExt::default(),
+            ExecResultExt::decode_from(&metadata).unwrap()
+        );
+
+        let ext = ExecResultExt {
+            sst_info: Some(SstInfoExt {
+                column_stats: vec![SstColumnStatsPb {
+                    null_count: 1,
+                    distinct_count: 2,
+                }],
+                index_paths: vec!["1.sst.row_count.index".to_string()],
+                extensions: Default::default(),
+            }),
+            sst_meta: Some(MetaDataExt {
+                extensions: [("version".to_string(), vec![1, 0xff])].into(),
+            }),
+        };
+        ext.encode_to(&mut metadata);
+        assert_eq!(ext, ExecResultExt::decode_from(&metadata).unwrap());
+    }
+}
@@ -28,7 +28,7 @@ use snafu::{OptionExt, ResultExt};
 use time_ext::ReadableDuration;
 
 use crate::compaction::runner::{
-    BadResponse, FailConnect, FailExecuteCompactionTask, MissingHeader, Result,
+    BadResponse, ExecResultExt, FailConnect, FailExecuteCompactionTask, MissingHeader, Result,
 };
 
 type CompactionServiceGrpcClient = CompactionServiceClient<tonic::transport::Channel>;
@@ -37,14 +37,21 @@ type CompactionServiceGrpcClient = CompactionServiceClient<tonic::transport::Cha
 #[serde(default)]
 pub struct CompactionClientConfig {
     pub compaction_server_addr: String,
+    pub connect_timeout: ReadableDuration,
+    /// Timeout of executing a compaction task, which should be long enough
+    /// for the remote node to finish merging the input ssts.
     pub timeout: ReadableDuration,
+    /// Max attempts to execute a compaction task on the remote nodes.
+    pub max_attempts: usize,
 }
 
 impl Default for CompactionClientConfig {
     fn default() -> Self {
         Self {
             compaction_server_addr: "127.0.0.1:7878".to_string(),
-            timeout: ReadableDuration::secs(5),
+            connect_timeout: ReadableDuration::secs(5),
+            timeout: ReadableDuration::minutes(30),
+            max_attempts: 3,
         }
     }
 }
@@ -53,10 +60,15 @@ impl Default for CompactionClientConfig {
 /// communicate with CompactionServer cluster.
 #[async_trait]
 pub trait CompactionClient: Send + Sync {
+    /// Execute the task remotely, the response is returned along with the
+    /// [ExecResultExt] sent in its metadata.
     async fn execute_compaction_task(
         &self,
         req: horaedbproto::compaction_service::ExecuteCompactionTaskRequest,
-    ) -> Result<horaedbproto::compaction_service::ExecuteCompactionTaskResponse>;
+    ) -> Result<(
+        horaedbproto::compaction_service::ExecuteCompactionTaskResponse,
+        ExecResultExt,
+    )>;
 }
 
 pub type CompactionClientRef = Arc<dyn CompactionClient>;
@@ -76,6 +88,7 @@ impl CompactionClientImpl {
                     .context(FailConnect {
                         addr: &config.compaction_server_addr,
                     })?
+                    .connect_timeout(config.connect_timeout.0)
                     .timeout(config.timeout.0);
             CompactionServiceGrpcClient::connect(endpoint)
                 .await
@@ -99,7 +112,10 @@ impl CompactionClient for CompactionClientImpl {
     async fn execute_compaction_task(
         &self,
         pb_req: horaedbproto::compaction_service::ExecuteCompactionTaskRequest,
-    ) -> Result<horaedbproto::compaction_service::ExecuteCompactionTaskResponse> {
+    ) -> Result<(
+        horaedbproto::compaction_service::ExecuteCompactionTaskResponse,
+        ExecResultExt,
+    )> {
         // TODO(leslie): Add request header for ExecuteCompactionTaskRequest.
 
         info!(
@@ -107,13 +123,14 @@ impl CompactionClient for CompactionClientImpl {
             pb_req
         );
 
-        let pb_resp = self
+        let resp = self
             .client()
             .execute_compaction_task(pb_req)
             .await
             .box_err()
-            .context(FailExecuteCompactionTask)?
-            .into_inner();
+            .context(FailExecuteCompactionTask)?;
+        let ext = ExecResultExt::decode_from(resp.metadata())?;
+        let pb_resp = resp.into_inner();
 
         info!(
             "Compaction client finish executing compaction task in remote compaction node, req:{:?}",
@@ -121,7 +138,7 @@ impl CompactionClient for CompactionClientImpl {
         );
 
         check_response_header(&pb_resp.header)?;
-        Ok(pb_resp)
+        Ok((pb_resp, ext))
     }
 }
 
@@ -15,16 +15,22 @@
 // specific language governing permissions and limitations
 // under the License.
 
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+
 use async_trait::async_trait;
 use generic_error::BoxError;
-use logger::info;
+use horaedbproto::compaction_service::ExecuteCompactionTaskResponse;
+use logger::{info, warn};
 use snafu::ResultExt;
 
 use super::{local_runner::LocalCompactionRunner, node_picker::RemoteCompactionNodePickerRef};
 use crate::{
     compaction::runner::{
         remote_client::{build_compaction_client, CompactionClientConfig, CompactionClientRef},
-        CompactionRunner, CompactionRunnerResult, CompactionRunnerTask,
+        CompactionRunner, CompactionRunnerResult, CompactionRunnerTask, ExecResultExt,
     },
     instance::flush_compaction::{
         self, BuildCompactionClientFailed, ConvertCompactionTaskResponse,
@@ -32,30 +38,108 @@ use crate::{
     },
 };
 
+/// Runner offloading the compaction task to the remote compaction nodes.
+///
+/// The task is shipped to a node picked from the compaction node fleet, and
+/// the remote node merges the input ssts and returns the infos of the output
+/// ssts, which will be installed by the compactor on this node. A failed task
+/// is retried on the (possibly different) node picked again, at most
+/// [CompactionClientConfig::max_attempts] times in total.
 pub struct RemoteCompactionRunner {
-    pub node_picker: RemoteCompactionNodePickerRef,
+    node_picker: RemoteCompactionNodePickerRef,
+    client_config: CompactionClientConfig,
+    /// Clients keyed by the endpoints of the compaction nodes, so the
+    /// connections can be reused across the tasks.
+    clients: Mutex<HashMap<String, CompactionClientRef>>,
 
-    pub fallback_local_when_failed: bool,
+    fallback_local_when_failed: bool,
     /// Responsible for executing compaction task locally if fail to remote
     /// compact when `fallback_local_when_failed` is true, used for better fault
     /// tolerance.
-    pub local_compaction_runner: LocalCompactionRunner,
+    local_compaction_runner: LocalCompactionRunner,
 }
 
 impl RemoteCompactionRunner {
-    async fn get_compaction_client(&self) -> Result<CompactionClientRef> {
-        let mut config = CompactionClientConfig::default();
+    pub fn new(
+        node_picker: RemoteCompactionNodePickerRef,
+        client_config: CompactionClientConfig,
+        fallback_local_when_failed: bool,
+        local_compaction_runner: LocalCompactionRunner,
+    ) -> Self {
+        Self {
+            node_picker,
+            client_config,
+            clients: Mutex::new(HashMap::new()),
+            fallback_local_when_failed,
+            local_compaction_runner,
+        }
+    }
+
+    async fn get_compaction_client(&self) -> Result<(String, CompactionClientRef)> {
         let endpoint = self
             .node_picker
             .get_compaction_node()
             .await
             .context(PickCompactionNodeFailed)?;
-        config.compaction_server_addr = make_formatted_endpoint(&endpoint);
+        let endpoint = make_formatted_endpoint(&endpoint);
+        if let Some(client) = self.clients.lock().unwrap().get(&endpoint) {
+            return Ok((endpoint, client.clone()));
+        }
 
+        let mut config = self.client_config.clone();
+        config.compaction_server_addr = endpoint.clone();
         let client = build_compaction_client(config)
             .await
             .context(BuildCompactionClientFailed)?;
-        Ok(client)
+        self.clients
+            .lock()
+            .unwrap()
+            .insert(endpoint.clone(), client.clone());
+
+        Ok((endpoint, client))
+    }
+
+    /// Execute the task on the remote nodes, and retry on failure.
+    async fn remote_compact(
+        &self,
+        task: &CompactionRunnerTask,
+    ) -> Result<(ExecuteCompactionTaskResponse, ExecResultExt)> {
+        let max_attempts = self.client_config.max_attempts.max(1);
+        let mut attempt = 1;
+        loop {
+            let (endpoint, client) = match self
+                .get_compaction_client()
+                .await
+                .box_err()
+                .context(GetCompactionClientFailed)
+            {
+                Ok(v) => v,
+                Err(e) if attempt < max_attempts => {
+                    warn!("Failed to get compaction client, attempt:{attempt}, err:{e}");
+                    attempt += 1;
+                    continue;
+                }
+                Err(e) => return Err(e),
+            };
+
+            match client.execute_compaction_task(task.clone().into()).await {
+                Ok(resp) => return Ok(resp),
+                Err(e) => {
+                    // The connection may be broken, so drop the client and reconnect next
+                    // time.
+                    self.clients.lock().unwrap().remove(&endpoint);
+                    if attempt >= max_attempts {
+                        return Err(flush_compaction::Error::RemoteCompactFailed { source: e });
+                    }
+
+                    warn!(
+                        "Failed to execute compaction task remotely, endpoint:{endpoint}, \
+                         attempt:{attempt}, err:{e}"
+                    );
+                    attempt += 1;
+                }
+            }
+        }
     }
 
     async fn local_compact(&self, task: CompactionRunnerTask) -> Result<CompactionRunnerResult> {
@@ -68,27 +152,8 @@ impl CompactionRunner for RemoteCompactionRunner {
     /// Run the compaction task either on a remote node or fall back to local
     /// compaction.
     async fn run(&self, task: CompactionRunnerTask) -> Result<CompactionRunnerResult> {
-        let client = self
-            .get_compaction_client()
-            .await
-            .box_err()
-            .context(GetCompactionClientFailed);
-
-        let pb_resp = match client {
-            Ok(client) => match client.execute_compaction_task(task.clone().into()).await {
-                Ok(resp) => resp,
-                Err(e) => {
-                    if !self.fallback_local_when_failed {
-                        return Err(flush_compaction::Error::RemoteCompactFailed { source: e });
-                    }
-
-                    info!(
-                        "The compaction task falls back to local because of error:{}",
-                        e
-                    );
-                    return self.local_compact(task).await;
-                }
-            },
+        let resp = match self.remote_compact(&task).await {
+            Ok(v) => v,
             Err(e) => {
                 if !self.fallback_local_when_failed {
                     return Err(e);
@@ -102,12 +167,12 @@ impl CompactionRunner for RemoteCompactionRunner {
             }
         };
 
-        let resp = pb_resp
+        let res = resp
             .try_into()
             .box_err()
             .context(ConvertCompactionTaskResponse)?;
 
-        Ok(resp)
+        Ok(res)
     }
 }
 
@@ -28,7 +28,10 @@ use std::{
 };
 
 use async_trait::async_trait;
-use common_types::request_id::RequestId;
+use common_types::{
+    request_id::RequestId,
+    time::{TimeRange, Timestamp},
+};
 use futures::{stream::FuturesUnordered, StreamExt};
 use logger::{debug, error, info, warn};
 use macros::define_result;
@@ -48,9 +51,9 @@ use tokio::{
 
 use crate::{
     compaction::{
-        compactor::Compactor, metrics::COMPACTION_PENDING_REQUEST_GAUGE, picker::PickerContext,
-        runner::CompactionRunnerPtr, CompactionTask, PickerManager, TableCompactionRequest,
-        WaitError, WaiterNotifier,
+        compactor::Compactor, heat::QueryHeatConfig, metrics::COMPACTION_PENDING_REQUEST_GAUGE,
+        picker::PickerContext, runner::CompactionRunnerPtr, CompactionTask, PickerManager,
+        TableCompactionRequest, WaitError, WaiterNotifier,
     },
     instance::{
         flush_compaction::{Flusher, TableFlushOptions},
@@ -78,6 +81,18 @@ pub struct SchedulerConfig {
     pub max_unflushed_duration: ReadableDuration,
     pub memory_limit: ReadableSize,
     pub max_pending_compaction_tasks: usize,
+    /// Max number of ssts written in parallel by one compaction task, and the
+    /// parallel writing is disabled if it is not greater than 1.
+    pub max_parallel_sst_writers: usize,
+    /// The compaction output is split into multiple ssts only if every sst
+    /// has at least so many rows.
+    pub min_rows_per_parallel_sst: usize,
+    /// The compaction output with more rows is split into ssts with so many
+    /// rows written one by one, and a checkpoint is persisted after every sst
+    /// so that the failed compaction can be resumed. Zero means disabled.
+    pub sst_checkpoint_rows: usize,
+    /// Compact the cold segments becoming hot.
+    pub query_heat: QueryHeatConfig,
 }
 
 impl Default for SchedulerConfig {
@@ -91,6 +106,10 @@ impl Default for SchedulerConfig {
             max_unflushed_duration: ReadableDuration(Duration::from_secs(60 * 60 * 5)),
             memory_limit: ReadableSize::gb(4),
             max_pending_compaction_tasks: 1024,
+            max_parallel_sst_writers: 1,
+            min_rows_per_parallel_sst: 10_000_000,
+            sst_checkpoint_rows: 0,
+            query_heat: QueryHeatConfig::default(),
         }
     }
 }
@@ -316,7 +335,14 @@ impl SchedulerImpl {
         let (tx, rx) = mpsc::channel(config.schedule_channel_len);
         let running = Arc::new(AtomicBool::new(true));
 
-        let compactor = Arc::new(Compactor::new(runner, space_store.manifest.clone()));
+        let compactor = Arc::new(Compactor::new(
+            runner,
+            space_store.manifest.clone(),
+            config.max_parallel_sst_writers,
+            config.min_rows_per_parallel_sst,
+            space_store.store_provider.clone(),
+            config.sst_checkpoint_rows,
+        ));
         let mut worker = ScheduleWorker {
             sender: tx.clone(),
             receiver: rx,
@@ -592,7 +618,11 @@ impl ScheduleWorker {
         let table_options = table_data.table_options();
         let compaction_strategy = table_options.compaction_strategy;
         let picker = self.picker_manager.get_picker(compaction_strategy);
-        let picker_ctx = match new_picker_context(&table_options) {
+        let picker_ctx = match new_picker_context(
+            &table_options,
+            table_data.now(),
+            compact_req.target_range,
+        ) {
             Some(v) => v,
             None => {
                 warn!("No valid context can be created, compaction request will be ignored, table_id:{}, table_name:{}",
@@ -675,7 +705,7 @@ impl ScheduleWorker {
         for table_data in &tables_buf {
             let last_flush_time = table_data.last_flush_time();
             let flush_deadline_ms = last_flush_time + self.max_unflushed_duration.as_millis_u64();
-            let now_ms = time_ext::current_time_millis();
+            let now_ms = table_data.clock().now_ms();
             if now_ms > flush_deadline_ms {
                 info!(
                     "Scheduled flush is triggered, table:{}, last_flush_time:{last_flush_time}ms, max_unflushed_duration:{:?}",
@@ -706,13 +736,19 @@ impl ScheduleWorker {
 
 // If segment duration is None, then no compaction should be triggered, but we
 // return a None context instead of panic here.
-fn new_picker_context(table_opts: &TableOptions) -> Option<PickerContext> {
+fn new_picker_context(
+    table_opts: &TableOptions,
+    now: Timestamp,
+    target_range: Option<TimeRange>,
+) -> Option<PickerContext> {
     table_opts
         .segment_duration()
         .map(|segment_duration| PickerContext {
             segment_duration,
             ttl: table_opts.ttl().map(|ttl| ttl.0),
             strategy: table_opts.compaction_strategy,
+            now,
+            target_range,
         })
 }
 
@@ -20,6 +20,7 @@
 use std::{fmt, sync::Arc};
 
 use table_engine::engine::EngineRuntimes;
+use time_ext::clock::ClockRef;
 
 use crate::{sst::meta_data::cache::MetaCacheRef, Config};
 
@@ -33,6 +34,9 @@ pub struct OpenContext {
 
     /// Sst meta data cache.
     pub meta_cache: Option<MetaCacheRef>,
+
+    /// Clock to get the current time.
+    pub clock: ClockRef,
 }
 
 impl fmt::Debug for OpenContext {
@@ -30,7 +30,7 @@ use table_engine::{
         Close, CloseShardRequest, CloseTableRequest, CreateTableParams, CreateTableRequest,
         DropTableRequest, OpenShard, OpenShardRequest, OpenShardResult, OpenTableNoCause,
         OpenTableRequest, OpenTableWithCause, Result, ShardStats, TableDef, TableEngine,
-        TableEngineStats, Unexpected,
+        TableEngineStats, TableNotFound, Unexpected, WriteTables, WriteTablesRequest,
     },
     table::{SchemaId, TableRef},
     ANALYTIC_ENGINE_TYPE,
@@ -243,6 +243,27 @@ impl TableEngine for TableEngineImpl {
         self.close_tables_of_shard(close_requests).await
     }
 
+    async fn write_tables(&self, request: WriteTablesRequest) -> Result<usize> {
+        let mut writes = Vec::with_capacity(request.writes.len());
+        for write in request.writes {
+            let space_id = build_space_id(write.schema_id);
+            let space_table = self
+                .instance
+                .find_table(space_id, &write.table_name)
+                .await?
+                .context(TableNotFound {
+                    table: &write.table_name,
+                })?;
+            writes.push((space_table, write.request));
+        }
+
+        self.instance
+            .write_tables_atomically(writes)
+            .await
+            .box_err()
+            .context(WriteTables)
+    }
+
     async fn report_statistics(&self) -> Result<Option<TableEngineStats>> {
         let table_engine_stats =
             collect_stats_from_metric(&FETCHED_SST_BYTES_HISTOGRAM, &TABLE_WRITE_BYTES_COUNTER)?;
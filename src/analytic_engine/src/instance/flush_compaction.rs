@@ -17,7 +17,11 @@
 
 // Flush and compaction logic of instance
 
-use std::{cmp, collections::Bound, fmt};
+use std::{
+    cmp,
+    collections::{Bound, HashMap},
+    fmt,
+};
 
 use common_types::{
     projected_schema::{ProjectedSchema, RowProjectorBuilder},
@@ -36,7 +40,7 @@ use logger::{debug, error, info};
 use macros::define_result;
 use runtime::RuntimeRef;
 use snafu::{Backtrace, ResultExt, Snafu};
-use time_ext::{self, ReadableDuration};
+use time_ext::ReadableDuration;
 use tokio::{sync::oneshot, time::Instant};
 use wal::manager::WalLocation;
 
@@ -50,9 +54,9 @@ use crate::{
     },
     memtable::{ColumnarIterPtr, MemTableRef, ScanContext, ScanRequest},
     sst::{
-        factory::{self, SstWriteOptions},
-        file::{FileMeta, Level},
-        writer::MetaData,
+        factory::{self, ColumnStats, SstWriteOptions},
+        file::{self, FileMeta, Level},
+        writer::{MetaData, SstExtensions},
     },
     table::{
         data::{self, TableDataRef},
@@ -105,6 +109,9 @@ pub enum Error {
     #[snafu(display("Failed to write sst, file_path:{}, source:{}", path, source))]
     WriteSst { path: String, source: GenericError },
 
+    #[snafu(display("Failed to access write checkpoint, path:{}, source:{}", path, source))]
+    AccessCheckpoint { path: String, source: GenericError },
+
     #[snafu(display(
         "Background flush failed, cannot write more data, retry_count:{}, err:{}.\nBacktrace:\n{}",
         retry_count,
@@ -243,9 +250,8 @@ struct FrequentFlushChecker {
 
 impl FrequentFlushChecker {
     #[inline]
-    fn is_frequent_flush(&self) -> bool {
-        let now = time_ext::current_time_millis();
-        self.last_flush_time_ms + self.min_flush_interval_ms > now
+    fn is_frequent_flush(&self, now_ms: u64) -> bool {
+        self.last_flush_time_ms + self.min_flush_interval_ms > now_ms
     }
 }
 
@@ -345,7 +351,7 @@ impl FlushTask {
             })?;
 
         self.table_data
-            .set_last_flush_time(time_ext::current_time_millis());
+            .set_last_flush_time(self.table_data.clock().now_ms());
 
         info!(
             "Instance flush memtables done, table:{}, table_id:{}, request_id:{}, cost:{}ms",
@@ -364,7 +370,7 @@ impl FlushTask {
                 min_flush_interval_ms,
                 last_flush_time_ms: self.table_data.last_flush_time(),
             };
-            checker.is_frequent_flush()
+            checker.is_frequent_flush(self.table_data.clock().now_ms())
         } else {
             false
         }
@@ -547,6 +553,9 @@ impl FlushTask {
             .apply_edit(edit_req)
             .await
             .context(StoreVersionEdit)?;
+        self.table_data
+            .metrics
+            .on_level_stats(&self.table_data.current_version().level_stats());
 
         // Mark sequence <= flushed_sequence to be deleted.
         let table_location = self.table_data.table_location();
@@ -598,7 +607,7 @@ impl FlushTask {
             num_rows_per_row_group: self.table_data.table_options().num_rows_per_row_group,
             compression: self.table_data.table_options().compression,
             max_buffer_size: self.write_sst_max_buffer_size,
-            column_stats: Default::default(),
+            column_stats: self.guess_column_stats(),
         };
 
         for time_range in &time_ranges {
@@ -618,9 +627,11 @@ impl FlushTask {
                 time_range: *time_range,
                 max_sequence,
                 schema: self.table_data.schema(),
+                extensions: SstExtensions::new(),
             };
 
             let store = self.space_store.clone();
+            let space_id = self.table_data.space_id;
             let storage_format_hint = self.table_data.table_options().storage_format_hint;
             let sst_write_options = sst_write_options.clone();
             let request_id = request_id.clone();
@@ -632,7 +643,7 @@ impl FlushTask {
                     .create_writer(
                         &sst_write_options,
                         &sst_file_path,
-                        store.store_picker(),
+                        store.store_picker(space_id),
                         Level::MIN,
                     )
                     .await
@@ -725,7 +736,8 @@ impl FlushTask {
                     time_range: sst_info.time_range,
                     max_seq: sst_meta.max_sequence,
                     storage_format: sst_info.storage_format,
-                    associated_files: vec![sst_info.meta_path],
+                    associated_files: sst_info.associated_files(),
+                    column_stats: sst_info.column_stats,
                 },
             })
         }
@@ -733,6 +745,12 @@ impl FlushTask {
         Ok(Some(max_sequence))
     }
 
+    /// Guess the column stats of the flushed rows by the latest flushed sst.
+    fn guess_column_stats(&self) -> HashMap<String, ColumnStats> {
+        let latest_sst = self.table_data.current_version().latest_sst(Level::MIN);
+        file::low_cardinality_columns(&self.table_data.schema(), latest_sst.as_slice())
+    }
+
     /// Flush rows in normal (non-sampling) memtable to at most one sst file.
     async fn dump_normal_memtable(
         &self,
@@ -755,6 +773,7 @@ impl FlushTask {
             time_range: memtable_state.aligned_time_range,
             max_sequence,
             schema: self.table_data.schema(),
+            extensions: SstExtensions::new(),
         };
 
         // Alloc file id for next sst file
@@ -771,7 +790,7 @@ impl FlushTask {
             num_rows_per_row_group: self.table_data.table_options().num_rows_per_row_group,
             compression: self.table_data.table_options().compression,
             max_buffer_size: self.write_sst_max_buffer_size,
-            column_stats: Default::default(),
+            column_stats: self.guess_column_stats(),
         };
         let mut writer = self
             .space_store
@@ -779,7 +798,7 @@ impl FlushTask {
             .create_writer(
                 &sst_write_options,
                 &sst_file_path,
-                self.space_store.store_picker(),
+                self.space_store.store_picker(self.table_data.space_id),
                 Level::MIN,
             )
             .await
@@ -825,7 +844,8 @@ impl FlushTask {
             time_range: sst_info.time_range,
             max_seq: memtable_state.last_sequence(),
             storage_format: sst_info.storage_format,
-            associated_files: vec![sst_info.meta_path],
+            associated_files: sst_info.associated_files(),
+            column_stats: sst_info.column_stats,
         }))
     }
 }
@@ -973,7 +993,7 @@ mod tests {
 
     #[test]
     fn test_frequent_flush() {
-        let now = time_ext::current_time_millis();
+        let now = 100_000;
         let cases = vec![
             (now - 1000, 100, false),
             (now - 1000, 2000, true),
@@ -987,7 +1007,7 @@ mod tests {
                 last_flush_time_ms,
             };
 
-            assert_eq!(expect, checker.is_frequent_flush());
+            assert_eq!(expect, checker.is_frequent_flush(now));
         }
     }
 }
@@ -50,14 +50,16 @@ use wal::manager::{WalLocation, WalManagerRef};
 
 use self::flush_compaction::{Flusher, TableFlushOptions};
 use crate::{
-    compaction::{scheduler::CompactionSchedulerRef, TableCompactionRequest},
+    compaction::{
+        heat::QueryHeatConfig, scheduler::CompactionSchedulerRef, TableCompactionRequest,
+    },
     manifest::ManifestRef,
     row_iter::IterOptions,
     space::{SpaceId, SpaceRef, SpacesRef},
     sst::{
         factory::{
             FactoryRef as SstFactoryRef, ObjectStorePickerRef, ReadFrequency, ScanOptions,
-            SstReadOptions,
+            SstReadOptions, StoreProviderRef,
         },
         file::FilePurgerRef,
         meta_data::cache::MetaCacheRef,
@@ -103,8 +105,8 @@ pub struct SpaceStore {
     pub(crate) manifest: ManifestRef,
     /// Wal of all tables
     wal_manager: WalManagerRef,
-    /// Object store picker for persisting data.
-    store_picker: ObjectStorePickerRef,
+    /// Provider of the object store picker of each space for persisting data.
+    pub(crate) store_provider: StoreProviderRef,
     /// Sst factory.
     sst_factory: SstFactoryRef,
 }
@@ -125,8 +127,8 @@ impl SpaceStore {
 }
 
 impl SpaceStore {
-    fn store_picker(&self) -> &ObjectStorePickerRef {
-        &self.store_picker
+    fn store_picker(&self, space_id: SpaceId) -> &ObjectStorePickerRef {
+        self.store_provider.store_picker(space_id)
     }
 
     /// List all tables of all spaces
@@ -165,6 +167,8 @@ pub struct Instance {
     // End of write group options.
     file_purger: FilePurgerRef,
     compaction_scheduler: CompactionSchedulerRef,
+    /// Config to compact the cold segments becoming hot.
+    pub(crate) query_heat: QueryHeatConfig,
 
     meta_cache: Option<MetaCacheRef>,
     /// Engine memtable memory usage collector
@@ -55,7 +55,7 @@ use crate::{
     row_iter::IterOptions,
     space::{SpaceAndTable, SpaceRef, Spaces},
     sst::{
-        factory::{FactoryRef as SstFactoryRef, ObjectStorePickerRef, ScanOptions},
+        factory::{FactoryRef as SstFactoryRef, ScanOptions, StoreProviderRef},
         file::FilePurger,
     },
     table::data::{TableCatalogInfo, TableDataRef},
@@ -74,7 +74,7 @@ impl InstanceContext {
         ctx: OpenContext,
         manifest_storages: ManifestStorages,
         wal_manager: WalManagerRef,
-        store_picker: ObjectStorePickerRef,
+        store_provider: StoreProviderRef,
         sst_factory: SstFactoryRef,
         meta_client: Option<MetaClientRef>,
     ) -> Result<Self> {
@@ -87,34 +87,36 @@ impl InstanceContext {
             ctx.runtimes.compact_runtime.clone(),
             &ctx.config,
             sst_factory.clone(),
-            store_picker.clone(),
+            store_provider.clone(),
             ctx.meta_cache.clone(),
         );
 
         let compaction_runner: CompactionRunnerPtr = match &ctx.config.compaction_mode {
             CompactionMode::Offload(NodePicker::Local(endpoint)) => {
-                Box::new(RemoteCompactionRunner {
-                    node_picker: Arc::new(LocalCompactionNodePickerImpl {
+                Box::new(RemoteCompactionRunner::new(
+                    Arc::new(LocalCompactionNodePickerImpl {
                         endpoint: endpoint.clone(),
                     }),
-                    // This field is set to false here for testing.
-                    fallback_local_when_failed: false,
-                    local_compaction_runner: local_compaction_runner.clone(),
-                })
+                    ctx.config.compaction_client.clone(),
+                    // Don't fall back to local compaction here for testing.
+                    false,
+                    local_compaction_runner.clone(),
+                ))
             }
-            CompactionMode::Offload(NodePicker::Remote) => Box::new(RemoteCompactionRunner {
-                node_picker: Arc::new(RemoteCompactionNodePickerImpl {
+            CompactionMode::Offload(NodePicker::Remote) => Box::new(RemoteCompactionRunner::new(
+                Arc::new(RemoteCompactionNodePickerImpl {
                     meta_client: meta_client.context(MetaClientNotExist)?,
                 }),
-                fallback_local_when_failed: true,
-                local_compaction_runner: local_compaction_runner.clone(),
-            }),
+                ctx.config.compaction_client.clone(),
+                true,
+                local_compaction_runner.clone(),
+            )),
 
             CompactionMode::Local => Box::new(LocalCompactionRunner::new(
                 ctx.runtimes.compact_runtime.clone(),
                 &ctx.config,
                 sst_factory.clone(),
-                store_picker.clone(),
+                store_provider.clone(),
                 ctx.meta_cache.clone(),
             )),
         };
@@ -123,7 +125,7 @@ impl InstanceContext {
             ctx,
             manifest_storages,
             wal_manager,
-            store_picker,
+            store_provider,
             sst_factory,
             compaction_runner,
         )
@@ -147,16 +149,13 @@ impl Instance {
         ctx: OpenContext,
         manifest_storages: ManifestStorages,
         wal_manager: WalManagerRef,
-        store_picker: ObjectStorePickerRef,
+        store_provider: StoreProviderRef,
         sst_factory: SstFactoryRef,
         compaction_runner: CompactionRunnerPtr,
     ) -> Result<Arc<Self>> {
         let spaces: Arc<RwLock<Spaces>> = Arc::new(RwLock::new(Spaces::default()));
         let default_runtime = ctx.runtimes.default_runtime.clone();
-        let file_purger = Arc::new(FilePurger::start(
-            &default_runtime,
-            store_picker.default_store().clone(),
-        ));
+        let file_purger = Arc::new(FilePurger::start(&default_runtime, store_provider.clone()));
 
         let table_meta_set_impl = Arc::new(TableMetaSetImpl {
             spaces: spaces.clone(),
@@ -166,6 +165,7 @@ impl Instance {
             enable_primary_key_sampling: ctx.config.enable_primary_key_sampling,
             try_compat_old_layered_memtable_opts: ctx.config.try_compat_old_layered_memtable_opts,
             metrics_opt: ctx.config.metrics.clone(),
+            clock: ctx.clock.clone(),
         });
         let manifest = ManifestImpl::open(
             ctx.config.manifest.clone(),
@@ -180,7 +180,7 @@ impl Instance {
             spaces,
             manifest: Arc::new(manifest),
             wal_manager: wal_manager.clone(),
-            store_picker: store_picker.clone(),
+            store_provider,
             sst_factory,
         });
 
@@ -211,6 +211,7 @@ impl Instance {
             table_opts: ctx.config.table_opts.clone(),
 
             compaction_scheduler,
+            query_heat: ctx.config.compaction.query_heat.clone(),
             file_purger,
             meta_cache: ctx.meta_cache.clone(),
             mem_usage_collector: Arc::new(MemUsageCollector::default()),
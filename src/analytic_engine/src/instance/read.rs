@@ -32,7 +32,7 @@ use common_types::{
 };
 use futures::stream::Stream;
 use generic_error::BoxError;
-use logger::debug;
+use logger::{debug, info};
 use macros::define_result;
 use snafu::{ResultExt, Snafu};
 use table_engine::{
@@ -41,10 +41,10 @@ use table_engine::{
     },
     table::ReadRequest,
 };
-use time_ext::current_time_millis;
 use trace_metric::Metric;
 
 use crate::{
+    compaction::TableCompactionRequest,
     instance::{Instance, ScanType, SstReadOptionsBuilder},
     row_iter::{
         chain,
@@ -54,7 +54,7 @@ use crate::{
         FetchedRecordBatchIterator, IterOptions,
     },
     table::{
-        data::TableData,
+        data::{TableData, TableDataRef},
         version::{ReadView, TableVersion},
     },
     table_options::TableOptions,
@@ -93,7 +93,7 @@ impl Instance {
     /// `read_parallelism` output streams.
     pub async fn partitioned_read_from_table(
         &self,
-        table_data: &TableData,
+        table_data: &TableDataRef,
         request: ReadRequest,
     ) -> Result<PartitionedStreams> {
         debug!(
@@ -105,7 +105,7 @@ impl Instance {
         let time_range = request.predicate.time_range();
         let start_time = time_range.inclusive_start().as_i64();
         let end_time = time_range.exclusive_end().as_i64();
-        let now = current_time_millis() as i64;
+        let now = table_data.clock().now_ms() as i64;
 
         let query_time_range = (end_time as f64 - start_time as f64) / 1000.0;
         let table_metrics = table_data.metrics.maybe_table_level_metrics();
@@ -118,6 +118,8 @@ impl Instance {
         // Collect trace metrics.
         let table_options = table_data.table_options();
         table_data.metrics.on_read_request_begin();
+        self.compact_hot_segments(table_data, time_range, &table_options)
+            .await;
         let need_merge_sort = table_options.need_dedup();
         request.metrics_collector.collect(Metric::boolean(
             MERGE_SORT_METRIC_NAME.to_string(),
@@ -162,6 +164,35 @@ impl Instance {
         }
     }
 
+    /// Compact the cold segments of the table becoming hot after this query.
+    async fn compact_hot_segments(
+        &self,
+        table_data: &TableDataRef,
+        query_range: TimeRange,
+        table_options: &TableOptions,
+    ) {
+        let Some(segment_duration) = table_options.segment_duration() else {
+            return;
+        };
+        let hot_segments = table_data.query_heat.record(
+            &self.query_heat,
+            query_range,
+            segment_duration,
+            table_data.now(),
+        );
+        for segment in hot_segments {
+            info!(
+                "Cold segment becomes hot, try to compact it, table:{}, table_id:{}, segment:{:?}",
+                table_data.name, table_data.id, segment
+            );
+
+            let request = TableCompactionRequest::targeted(table_data.clone(), segment);
+            self.compaction_scheduler
+                .schedule_table_compaction(request)
+                .await;
+        }
+    }
+
     fn build_partitioned_streams(
         &self,
         request: &ReadRequest,
@@ -219,7 +250,7 @@ impl Instance {
                 predicate: request.predicate.clone(),
                 sst_factory: &self.space_store.sst_factory,
                 sst_read_options_builder: sst_read_options_builder.clone(),
-                store_picker: self.space_store.store_picker(),
+                store_picker: self.space_store.store_picker(table_data.space_id),
                 merge_iter_options: iter_options.clone(),
                 need_dedup: table_options.need_dedup(),
                 reverse: false,
@@ -278,7 +309,7 @@ impl Instance {
                 predicate: request.predicate.clone(),
                 sst_read_options_builder: sst_read_options_builder.clone(),
                 sst_factory: &self.space_store.sst_factory,
-                store_picker: self.space_store.store_picker(),
+                store_picker: self.space_store.store_picker(table_data.space_id),
             };
             let builder = chain::Builder::new(chain_config);
             let chain_iter = builder
@@ -17,7 +17,7 @@
 
 //! Write logic of instance
 
-use std::iter;
+use std::{iter, sync::Arc};
 
 use bytes_ext::ByteVec;
 use codec::{
@@ -27,6 +27,7 @@ use codec::{
 use common_types::{
     row::RowGroup,
     schema::{IndexInWriterSchema, Schema},
+    table::ShardId,
 };
 use horaedbproto::{schema as schema_pb, table_requests};
 use itertools::Itertools;
@@ -34,21 +35,22 @@ use logger::{debug, error, info, trace, warn};
 use macros::define_result;
 use smallvec::SmallVec;
 use snafu::{ensure, Backtrace, ResultExt, Snafu};
-use table_engine::table::WriteRequest;
+use table_engine::table::{Durability, FlushRequest, WriteRequest};
 use wal::{
     kv_encoder::LogBatchEncoder,
-    log_batch::Payload,
+    log_batch::{LogWriteBatch, Payload},
     manager::{SequenceNumber, WalLocation, WriteContext},
 };
 
 use crate::{
     instance,
     instance::{
-        flush_compaction::TableFlushOptions, serial_executor::TableOpSerialExecutor, InstanceRef,
+        flush_compaction::TableFlushOptions, serial_executor::TableOpSerialExecutor, Instance,
+        InstanceRef,
     },
     memtable::{key::KeySequence, PutContext},
     payload::WritePayload,
-    space::SpaceRef,
+    space::{SpaceAndTable, SpaceRef},
     table::{data::TableDataRef, version::MemTableForWrite},
     WalEncodeConfig, WalEncodeFormat,
 };
@@ -107,6 +109,12 @@ pub enum Error {
         source: crate::instance::flush_compaction::Error,
     },
 
+    #[snafu(display("Failed to flush written table, table:{}, err:{}", table, source))]
+    FlushWrittenTable {
+        table: String,
+        source: crate::instance::Error,
+    },
+
     #[snafu(display(
         "Background flush failed, cannot write more data, err:{}.\nBacktrace:\n{}",
         msg,
@@ -124,6 +132,39 @@ pub enum Error {
 
     #[snafu(display("Failed to update sequence of memtable, err:{}", source))]
     UpdateMemTableSequence { source: crate::memtable::Error },
+
+    #[snafu(display(
+        "Tables to write atomically must be on the same shard, table:{}, shard_id:{}, expect_shard_id:{}.\nBacktrace:\n{}",
+        table,
+        shard_id,
+        expect_shard_id,
+        backtrace,
+    ))]
+    WriteTablesOfDifferentShards {
+        table: String,
+        shard_id: ShardId,
+        expect_shard_id: ShardId,
+        backtrace: Backtrace,
+    },
+
+    #[snafu(display(
+        "Table to write atomically appears more than once, table:{}.\nBacktrace:\n{}",
+        table,
+        backtrace,
+    ))]
+    DuplicateTableToWrite { table: String, backtrace: Backtrace },
+
+    #[snafu(display(
+        "Durability of the write can't be reached, table:{}, durability:{}.\nBacktrace:\n{}",
+        table,
+        durability,
+        backtrace,
+    ))]
+    UnsupportedDurability {
+        table: String,
+        durability: Durability,
+        backtrace: Backtrace,
+    },
 }
 
 define_result!(Error);
@@ -329,6 +370,123 @@ impl<'a> Writer<'a> {
     }
 }
 
+impl Instance {
+    /// Write to the tables on the same shard atomically: the log batches of
+    /// all the tables are written into the wal at once, so either all or none
+    /// of the writes are persisted.
+    ///
+    /// The tables written with [Durability::ObjectStore] are flushed before
+    /// returning, like [crate::table::TableImpl] does.
+    ///
+    /// Returns the total number of written rows.
+    pub async fn write_tables_atomically(
+        self: &Arc<Self>,
+        mut writes: Vec<(SpaceAndTable, WriteRequest)>,
+    ) -> Result<usize> {
+        if writes.is_empty() {
+            return Ok(0);
+        }
+
+        // Lock the tables in the order of the table id to avoid the deadlock between
+        // the atomic writes.
+        writes.sort_unstable_by_key(|(space_table, _)| space_table.table_data().id);
+        for pair in writes.windows(2) {
+            let table_data = pair[1].0.table_data();
+            ensure!(
+                pair[0].0.table_data().id != table_data.id,
+                DuplicateTableToWrite {
+                    table: &table_data.name,
+                }
+            );
+        }
+        let expect_shard_id = writes[0].0.table_data().shard_info.shard_id;
+        for (space_table, _) in &writes {
+            let table_data = space_table.table_data();
+            ensure!(
+                table_data.shard_info.shard_id == expect_shard_id,
+                WriteTablesOfDifferentShards {
+                    table: &table_data.name,
+                    shard_id: table_data.shard_info.shard_id,
+                    expect_shard_id,
+                }
+            );
+        }
+
+        let mut serial_execs = Vec::with_capacity(writes.len());
+        for (space_table, _) in &writes {
+            serial_execs.push(space_table.table_data().serial_exec.lock().await);
+        }
+
+        let sync_wal = writes
+            .iter()
+            .any(|(_, request)| request.durability == Durability::WalSync);
+        let tables_to_flush: Vec<_> = writes
+            .iter()
+            .filter(|(_, request)| request.durability == Durability::ObjectStore)
+            .map(|(space_table, _)| space_table.table_data().clone())
+            .collect();
+        let mut writers = Vec::with_capacity(writes.len());
+        let mut prepared_writes = Vec::with_capacity(writes.len());
+        for ((space_table, request), serial_exec) in writes.into_iter().zip(&mut serial_execs) {
+            let table_data = space_table.table_data();
+            table_data.metrics.on_write_request_begin();
+            let mut writer = Writer::new(
+                self.clone(),
+                space_table.space().clone(),
+                table_data.clone(),
+                serial_exec,
+            );
+            prepared_writes.push(writer.prepare(request).await?);
+            writers.push(writer);
+        }
+
+        let sequences: Vec<SequenceNumber> = if self.disable_wal {
+            writers
+                .iter()
+                .map(|writer| writer.table_data.next_sequence())
+                .collect()
+        } else {
+            let log_batches: Vec<_> = prepared_writes
+                .iter_mut()
+                .filter_map(|prepared| prepared.log_batch.take())
+                .collect();
+            let write_ctx = WriteContext {
+                sync: sync_wal,
+                ..Default::default()
+            };
+            self.space_store
+                .wal_manager
+                .write_atomically(&write_ctx, &log_batches)
+                .await
+                .with_context(|| WriteLogBatch {
+                    table: writers
+                        .iter()
+                        .map(|writer| writer.table_data.name.as_str())
+                        .join(","),
+                })?
+        };
+
+        let mut num_rows = 0;
+        for ((writer, prepared), sequence) in writers.iter_mut().zip(prepared_writes).zip(sequences)
+        {
+            num_rows += writer.commit(prepared, sequence).await?;
+        }
+        // The flush requires the serial executors of the tables.
+        drop(writers);
+        drop(serial_execs);
+
+        for table_data in &tables_to_flush {
+            self.manual_flush_table(table_data, FlushRequest { sync: true })
+                .await
+                .context(FlushWrittenTable {
+                    table: &table_data.name,
+                })?;
+        }
+
+        Ok(num_rows)
+    }
+}
+
 pub(crate) struct MemTableWriter<'a> {
     table_data: TableDataRef,
     _serial_exec: &'a mut TableOpSerialExecutor,
@@ -414,55 +572,89 @@ impl<'a> MemTableWriter<'a> {
     }
 }
 
+/// The write request of a table validated and encoded, which is ready to be
+/// written into the wal.
+pub(crate) struct PreparedWrite {
+    encode_ctx: EncodeContext,
+    /// The log batch to write into the wal, `None` if the wal is disabled.
+    log_batch: Option<LogWriteBatch>,
+}
+
 impl<'a> Writer<'a> {
     pub(crate) async fn write(&mut self, request: WriteRequest) -> Result<usize> {
         let _timer = self.table_data.metrics.start_table_write_execute_timer();
         self.table_data.metrics.on_write_request_begin();
 
+        let sync_wal = request.durability == Durability::WalSync;
+        let prepared = self.prepare(request).await?;
+        let seq = match &prepared.log_batch {
+            Some(log_batch) => self.write_to_wal(log_batch, sync_wal).await?,
+            // When wal is disabled, just update the last_seq one by one.
+            None => self.table_data.next_sequence(),
+        };
+
+        self.commit(prepared, seq).await
+    }
+
+    /// Validate and preprocess the request, and encode it into the log batch
+    /// to write into the wal.
+    pub(crate) async fn prepare(&mut self, request: WriteRequest) -> Result<PreparedWrite> {
         self.validate_before_write(&request)?;
         let mut encode_ctx = EncodeContext::new(request.row_group);
 
         self.preprocess_write(&mut encode_ctx).await?;
 
-        let table_data = self.table_data.clone();
-        let seq = if self.instance.disable_wal {
-            // When wal is disabled, just update the last_seq one by one.
-            table_data.next_sequence()
-        } else {
-            let encoded_payload = {
-                let _timer = self.table_data.metrics.start_table_write_encode_timer();
-                let schema = self.table_data.schema();
-                encode_ctx.encode(&self.instance.wal_encode, &schema)?
-            };
+        if self.instance.disable_wal {
+            return Ok(PreparedWrite {
+                encode_ctx,
+                log_batch: None,
+            });
+        }
 
-            match encoded_payload {
-                EncodedPayload::Rows(encoded_rows) => {
-                    self.write_to_wal_in_rows(encoded_rows).await?
-                }
-                EncodedPayload::Cols(encoded_cols) => {
-                    self.write_to_wal_in_cols(encoded_cols).await?
-                }
-            }
+        let encoded_payload = {
+            let _timer = self.table_data.metrics.start_table_write_encode_timer();
+            let schema = self.table_data.schema();
+            encode_ctx.encode(&self.instance.wal_encode, &schema)?
         };
+        let log_batch = match encoded_payload {
+            EncodedPayload::Rows(encoded_rows) => self.encode_rows_to_log_batch(encoded_rows)?,
+            EncodedPayload::Cols(encoded_cols) => self.encode_cols_to_log_batch(encoded_cols)?,
+        };
+
+        Ok(PreparedWrite {
+            encode_ctx,
+            log_batch: Some(log_batch),
+        })
+    }
 
-        // Write the row group to the memtable and update the state in the mem.
+    /// Write the row group of the prepared write whose log batch has been
+    /// written into the wal with `sequence` to the memtable and update the
+    /// state in the mem.
+    ///
+    /// Returns the number of written rows.
+    pub(crate) async fn commit(
+        &mut self,
+        prepared: PreparedWrite,
+        sequence: SequenceNumber,
+    ) -> Result<usize> {
+        let table_data = self.table_data.clone();
         let EncodeContext {
             row_group,
             index_in_writer,
-        } = encode_ctx;
-        self.write_to_mem(&table_data, &row_group, index_in_writer, seq)
+        } = prepared.encode_ctx;
+        self.write_to_mem(&table_data, &row_group, index_in_writer, sequence)
             .await?;
 
         Ok(row_group.num_rows())
     }
 
-    async fn write_to_wal_in_rows(&self, encoded_rows: Vec<ByteVec>) -> Result<SequenceNumber> {
+    fn encode_rows_to_log_batch(&self, encoded_rows: Vec<ByteVec>) -> Result<LogWriteBatch> {
         let split_res = self.maybe_split_write_request(encoded_rows);
         match split_res {
             SplitResult::Integrate { encoded_rows } => {
                 let write_req = self.make_rowwise_write_request(encoded_rows);
                 let payload = WritePayload::Write(&write_req);
-                self.write_to_wal(iter::once(payload)).await
+                self.encode_log_batch(iter::once(payload))
             }
             SplitResult::Splitted { encoded_batches } => {
                 let write_reqs = encoded_batches
@@ -471,12 +663,12 @@ impl<'a> Writer<'a> {
                     .collect_vec();
 
                 let payload = write_reqs.iter().map(WritePayload::Write);
-                self.write_to_wal(payload).await
+                self.encode_log_batch(payload)
             }
         }
     }
 
-    async fn write_to_wal_in_cols(&self, encoded_cols: Vec<ByteVec>) -> Result<SequenceNumber> {
+    fn encode_cols_to_log_batch(&self, encoded_cols: Vec<ByteVec>) -> Result<LogWriteBatch> {
         let write_req = table_requests::WriteRequest {
             version: WalEncodeVersion::Columnar.as_u32(),
             schema: None,
@@ -485,7 +677,7 @@ impl<'a> Writer<'a> {
         };
         let payload = WritePayload::Write(&write_req);
 
-        self.write_to_wal(iter::once(payload)).await
+        self.encode_log_batch(iter::once(payload))
     }
 
     fn make_rowwise_write_request(
@@ -560,6 +752,16 @@ impl<'a> Writer<'a> {
                 rows: request.row_group.num_rows(),
             }
         );
+        // The wal can't be synced if it is disabled or ignores the sync.
+        ensure!(
+            request.durability != Durability::WalSync
+                || (!self.instance.disable_wal
+                    && self.instance.space_store.wal_manager.supports_sync()),
+            UnsupportedDurability {
+                table: &self.table_data.name,
+                durability: request.durability,
+            }
+        );
 
         Ok(())
     }
@@ -631,31 +833,41 @@ impl<'a> Writer<'a> {
         Ok(())
     }
 
-    /// Write log_batch into wal, return the sequence number of log_batch.
-    async fn write_to_wal<I, P>(&self, payloads: I) -> Result<SequenceNumber>
+    /// Encode the payloads into the log batch to write into the wal of the
+    /// table.
+    fn encode_log_batch<I, P>(&self, payloads: I) -> Result<LogWriteBatch>
     where
         I: Iterator<Item = P>,
         P: Payload,
     {
-        let _timer = self.table_data.metrics.start_table_write_wal_timer();
         let table_location = self.table_data.table_location();
         let wal_location =
             instance::create_wal_location(table_location.id, table_location.shard_info);
         let log_batch_encoder = LogBatchEncoder::create(wal_location);
-        let log_batch = log_batch_encoder
+        log_batch_encoder
             .encode_batch(payloads)
             .context(EncodePayloads {
                 table: &self.table_data.name,
                 wal_location,
-            })?;
+            })
+    }
+
+    /// Write log_batch into wal, return the sequence number of log_batch.
+    ///
+    /// The wal is synced to the disk before returning if `sync` is true.
+    async fn write_to_wal(&self, log_batch: &LogWriteBatch, sync: bool) -> Result<SequenceNumber> {
+        let _timer = self.table_data.metrics.start_table_write_wal_timer();
 
         // Write to wal manager
-        let write_ctx = WriteContext::default();
+        let write_ctx = WriteContext {
+            sync,
+            ..Default::default()
+        };
         let sequence = self
             .instance
             .space_store
             .wal_manager
-            .write(&write_ctx, &log_batch)
+            .write(&write_ctx, log_batch)
             .await
             .context(WriteLogBatch {
                 table: &self.table_data.name,
@@ -40,7 +40,7 @@ pub mod table_meta_set_impl;
 #[cfg(any(test, feature = "test"))]
 pub mod tests;
 
-use compaction::runner::node_picker::NodePicker;
+use compaction::runner::{node_picker::NodePicker, remote_client::CompactionClientConfig};
 use error::ErrorKind;
 use manifest::details::Options as ManifestOptions;
 use object_store::config::StorageOptions;
@@ -94,6 +94,8 @@ pub struct Config {
 
     /// Offload the compaction task or not.
     pub compaction_mode: CompactionMode,
+    /// Config of the client to offload the compaction task.
+    pub compaction_client: CompactionClientConfig,
 
     /// sst meta cache capacity
     pub sst_meta_cache_cap: Option<usize>,
@@ -206,6 +208,7 @@ impl Default for Config {
             try_compat_old_layered_memtable_opts: false,
             compaction: SchedulerConfig::default(),
             compaction_mode: CompactionMode::Local,
+            compaction_client: CompactionClientConfig::default(),
             sst_meta_cache_cap: Some(1000),
             sst_data_cache_cap: Some(1000),
             manifest: ManifestOptions::default(),
@@ -52,7 +52,8 @@ use wal::{
 use crate::{
     manifest::{
         meta_edit::{
-            MetaEdit, MetaEditRequest, MetaUpdate, MetaUpdateDecoder, MetaUpdatePayload, Snapshot,
+            MetaEdit, MetaEditRequest, MetaExt, MetaUpdate, MetaUpdateDecoder, MetaUpdatePayload,
+            Snapshot,
         },
         meta_snapshot::{MetaSnapshot, MetaSnapshotBuilder},
         Error, LoadRequest, Manifest, Result, SnapshotRequest,
@@ -533,8 +534,9 @@ impl MetaUpdateSnapshotStore for ObjectStoreBasedSnapshotStore {
     /// Store the latest snapshot to the underlying store by overwriting the old
     /// snapshot.
     async fn store(&self, snapshot: &Snapshot) -> Result<()> {
-        let snapshot_pb = manifest_pb::Snapshot::from(snapshot.clone());
-        let payload = snapshot_pb.encode_to_vec();
+        let (snapshot_pb, ext): (manifest_pb::Snapshot, MetaExt) = snapshot.clone().into();
+        let mut payload = snapshot_pb.encode_to_vec();
+        ext.encode(&mut payload).map_err(anyhow::Error::new)?;
         // The atomic write is ensured by the [`ObjectStore`] implementation.
         self.store
             .put(&self.snapshot_path, payload.into())
@@ -573,7 +575,8 @@ impl MetaUpdateSnapshotStore for ObjectStoreBasedSnapshotStore {
             .map_err(anyhow::Error::new)?;
         let snapshot_pb =
             manifest_pb::Snapshot::decode(payload.as_bytes()).map_err(anyhow::Error::new)?;
-        let snapshot = Snapshot::try_from(snapshot_pb).map_err(anyhow::Error::new)?;
+        let ext = MetaExt::decode(payload.as_bytes()).map_err(anyhow::Error::new)?;
+        let snapshot = Snapshot::try_from((snapshot_pb, ext)).map_err(anyhow::Error::new)?;
 
         Ok(Some(snapshot))
     }
@@ -628,6 +631,7 @@ impl MetaUpdateLogStore for WalBasedLogStore {
 
         let write_ctx = WriteContext {
             timeout: self.opts.store_timeout.0,
+            ..Default::default()
         };
 
         self.wal_manager
@@ -656,10 +660,12 @@ mod tests {
     use object_store::local_file;
     use runtime::Runtime;
     use table_engine::table::{SchemaId, TableId, TableSeqGenerator};
+    use time_ext::clock::SystemClock;
     use wal::rocksdb_impl::manager::Builder as WalBuilder;
 
     use super::*;
     use crate::{
+        compaction::{CompactionStrategy, LazyLevelingCompactionOptions},
         manifest::{
             details::{MetaUpdateLogEntryIterator, MetaUpdateLogStore},
             meta_edit::{
@@ -777,6 +783,7 @@ mod tests {
                     metrics_opt: MetricsOptions::default(),
                     enable_primary_key_sampling: false,
                     try_compat_old_layered_memtable_opts: false,
+                    clock: SystemClock::new_ref(),
                 },
                 &purger,
                 mem_size_options,
@@ -1055,6 +1062,33 @@ mod tests {
             manifest_data_builder.apply_update(alter_options).unwrap();
         }
 
+        async fn alter_table_options_with_manifest(
+            &self,
+            table_id: TableId,
+            options: TableOptions,
+            manifest_data_builder: &mut MetaSnapshotBuilder,
+            manifest: &ManifestImpl,
+        ) {
+            let shard_info = TableShardInfo {
+                shard_id: DEFAULT_SHARD_ID,
+            };
+
+            let alter_options = MetaUpdate::AlterOptions(AlterOptionsMeta {
+                space_id: self.table_catalog_info.schema_id.as_u32(),
+                table_id,
+                options,
+            });
+            let edit_req = {
+                MetaEditRequest {
+                    shard_info,
+                    meta_edit: MetaEdit::Update(alter_options.clone()),
+                    table_catalog_info: self.table_catalog_info.clone(),
+                }
+            };
+            manifest.apply_edit(edit_req).await.unwrap();
+            manifest_data_builder.apply_update(alter_options).unwrap();
+        }
+
         async fn alter_table_schema(
             &self,
             table_id: TableId,
@@ -1152,6 +1186,74 @@ mod tests {
         });
     }
 
+    #[test]
+    fn test_manifest_lazy_leveling_options() {
+        let ctx = TestContext::new("lazy_leveling_options", SchemaId::from_u32(0));
+        let runtime = ctx.runtime.clone();
+
+        runtime.block_on(async move {
+            let lazy_leveling_options = |max_sorted_runs| TableOptions {
+                compaction_strategy: CompactionStrategy::LazyLeveling(
+                    LazyLevelingCompactionOptions {
+                        max_sorted_runs,
+                        ..Default::default()
+                    },
+                ),
+                ..Default::default()
+            };
+            let table_id = ctx.alloc_table_id();
+            let location = WalLocation::new(DEFAULT_SHARD_ID as u64, table_id.as_u64());
+            let load_req = LoadRequest {
+                space_id: ctx.table_catalog_info.schema_id.as_u32(),
+                table_catalog_info: ctx.table_catalog_info.clone(),
+                table_id,
+                shard_id: DEFAULT_SHARD_ID,
+            };
+            let mut manifest_data_builder = MetaSnapshotBuilder::default();
+            let manifest = ctx.open_manifest().await;
+            ctx.add_table_with_manifest(table_id, &mut manifest_data_builder, &manifest)
+                .await;
+
+            // Recover the options from the snapshot.
+            ctx.alter_table_options_with_manifest(
+                table_id,
+                lazy_leveling_options(4),
+                &mut manifest_data_builder,
+                &manifest,
+            )
+            .await;
+            manifest
+                .do_snapshot_internal(
+                    ctx.table_catalog_info.schema_id.as_u32(),
+                    table_id,
+                    location,
+                )
+                .await
+                .unwrap();
+            ctx.check_table_manifest_data_with_manifest(
+                &load_req,
+                &manifest_data_builder.build(),
+                &manifest,
+            )
+            .await;
+
+            // Recover the options from the logs.
+            ctx.alter_table_options_with_manifest(
+                table_id,
+                lazy_leveling_options(6),
+                &mut manifest_data_builder,
+                &manifest,
+            )
+            .await;
+            ctx.check_table_manifest_data_with_manifest(
+                &load_req,
+                &manifest_data_builder.build(),
+                &manifest,
+            )
+            .await;
+        });
+    }
+
     #[test]
     fn test_manifest_alter_schema() {
         let ctx = TestContext::new("version_edit", SchemaId::from_u32(0));
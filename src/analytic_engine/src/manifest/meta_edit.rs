@@ -39,9 +39,30 @@ use crate::{
         version::TableVersionMeta,
         version_edit::{AddFile, DeleteFile, VersionEdit},
     },
+    table_options::TableOptionsExt,
     TableOptions,
 };
 
+/// Pb message of the meta which [manifest_pb::MetaUpdate] and
+/// [manifest_pb::Snapshot] have no place for.
+///
+/// It's encoded right after them with the tags unused by them, so the nodes
+/// unaware of it just skip it when decoding them, and vice versa.
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct MetaExt {
+    /// The ext of the table options carried by the meta, if any.
+    #[prost(message, optional, tag = "10001")]
+    pub table_options: Option<TableOptionsExt>,
+}
+
+impl MetaExt {
+    fn with_table_options(opts: Option<&TableOptions>) -> Self {
+        Self {
+            table_options: opts.map(TableOptionsExt::from),
+        }
+    }
+}
+
 /// Modifications to meta data in meta
 #[derive(Debug, Clone)]
 pub enum MetaUpdate {
@@ -52,8 +73,13 @@ pub enum MetaUpdate {
     AlterOptions(AlterOptionsMeta),
 }
 
-impl From<MetaUpdate> for manifest_pb::MetaUpdate {
+impl From<MetaUpdate> for (manifest_pb::MetaUpdate, MetaExt) {
     fn from(update: MetaUpdate) -> Self {
+        let ext = MetaExt::with_table_options(match &update {
+            MetaUpdate::AddTable(v) => Some(&v.opts),
+            MetaUpdate::AlterOptions(v) => Some(&v.options),
+            _ => None,
+        });
         let meta = match update {
             MetaUpdate::AddTable(v) => manifest_pb::meta_update::Meta::AddTable(v.into()),
             MetaUpdate::VersionEdit(v) => manifest_pb::meta_update::Meta::VersionEdit(v.into()),
@@ -62,7 +88,7 @@ impl From<MetaUpdate> for manifest_pb::MetaUpdate {
             MetaUpdate::DropTable(v) => manifest_pb::meta_update::Meta::DropTable(v.into()),
         };
 
-        manifest_pb::MetaUpdate { meta: Some(meta) }
+        (manifest_pb::MetaUpdate { meta: Some(meta) }, ext)
     }
 }
 
@@ -88,13 +114,14 @@ impl MetaUpdate {
     }
 }
 
-impl TryFrom<manifest_pb::MetaUpdate> for MetaUpdate {
+impl TryFrom<(manifest_pb::MetaUpdate, MetaExt)> for MetaUpdate {
     type Error = Error;
 
-    fn try_from(src: manifest_pb::MetaUpdate) -> Result<Self> {
+    fn try_from((src, ext): (manifest_pb::MetaUpdate, MetaExt)) -> Result<Self> {
+        let options_ext = ext.table_options.unwrap_or_default();
         let meta_update = match src.meta.context("Empty meta update.")? {
             manifest_pb::meta_update::Meta::AddTable(v) => {
-                let add_table = AddTableMeta::try_from(v)?;
+                let add_table = AddTableMeta::try_from((v, options_ext))?;
                 MetaUpdate::AddTable(add_table)
             }
             manifest_pb::meta_update::Meta::VersionEdit(v) => {
@@ -106,7 +133,7 @@ impl TryFrom<manifest_pb::MetaUpdate> for MetaUpdate {
                 MetaUpdate::AlterSchema(alter_schema)
             }
             manifest_pb::meta_update::Meta::AlterOptions(v) => {
-                let alter_options = AlterOptionsMeta::try_from(v)?;
+                let alter_options = AlterOptionsMeta::try_from((v, options_ext))?;
                 MetaUpdate::AlterOptions(alter_options)
             }
             manifest_pb::meta_update::Meta::DropTable(v) => {
@@ -146,10 +173,10 @@ impl From<AddTableMeta> for manifest_pb::AddTableMeta {
     }
 }
 
-impl TryFrom<manifest_pb::AddTableMeta> for AddTableMeta {
+impl TryFrom<(manifest_pb::AddTableMeta, TableOptionsExt)> for AddTableMeta {
     type Error = Error;
 
-    fn try_from(src: manifest_pb::AddTableMeta) -> Result<Self> {
+    fn try_from((src, options_ext): (manifest_pb::AddTableMeta, TableOptionsExt)) -> Result<Self> {
         let table_schema = src.schema.context("Empty table schema.")?;
         let opts = src.options.context("Empty table options.")?;
 
@@ -158,7 +185,7 @@ impl TryFrom<manifest_pb::AddTableMeta> for AddTableMeta {
             table_id: TableId::from(src.table_id),
             table_name: src.table_name,
             schema: Schema::try_from(table_schema).map_err(anyhow::Error::new)?,
-            opts: TableOptions::try_from(opts).map_err(anyhow::Error::new)?,
+            opts: TableOptions::try_from((opts, options_ext)).map_err(anyhow::Error::new)?,
         })
     }
 }
@@ -320,16 +347,19 @@ impl From<AlterOptionsMeta> for manifest_pb::AlterOptionsMeta {
     }
 }
 
-impl TryFrom<manifest_pb::AlterOptionsMeta> for AlterOptionsMeta {
+impl TryFrom<(manifest_pb::AlterOptionsMeta, TableOptionsExt)> for AlterOptionsMeta {
     type Error = Error;
 
-    fn try_from(src: manifest_pb::AlterOptionsMeta) -> Result<Self> {
+    fn try_from(
+        (src, options_ext): (manifest_pb::AlterOptionsMeta, TableOptionsExt),
+    ) -> Result<Self> {
         let table_options = src.options.context("Empty table options.")?;
 
         Ok(Self {
             space_id: src.space_id,
             table_id: TableId::from(src.table_id),
-            options: TableOptions::try_from(table_options).map_err(anyhow::Error::new)?,
+            options: TableOptions::try_from((table_options, options_ext))
+                .map_err(anyhow::Error::new)?,
         })
     }
 }
@@ -337,11 +367,12 @@ impl TryFrom<manifest_pb::AlterOptionsMeta> for AlterOptionsMeta {
 /// An adapter to implement [wal::log_batch::Payload] for
 /// [proto::meta_update::MetaUpdate]
 #[derive(Debug)]
-pub struct MetaUpdatePayload(manifest_pb::MetaUpdate);
+pub struct MetaUpdatePayload(manifest_pb::MetaUpdate, MetaExt);
 
 impl From<MetaUpdate> for MetaUpdatePayload {
     fn from(src: MetaUpdate) -> Self {
-        Self(src.into())
+        let (update, ext) = src.into();
+        Self(update, ext)
     }
 }
 
@@ -355,11 +386,12 @@ impl Payload for MetaUpdatePayload {
     type Error = Error;
 
     fn encode_size(&self) -> usize {
-        self.0.encoded_len()
+        self.0.encoded_len() + self.1.encoded_len()
     }
 
     fn encode_to<B: BufMut>(&self, buf: &mut B) -> Result<()> {
         self.0.encode(buf).map_err(anyhow::Error::new)?;
+        self.1.encode(buf).map_err(anyhow::Error::new)?;
         Ok(())
     }
 }
@@ -374,7 +406,8 @@ impl PayloadDecoder for MetaUpdateDecoder {
     fn decode<B: Buf>(&self, _ctx: &PayloadDecodeContext, buf: &mut B) -> Result<Self::Target> {
         let meta_update_pb =
             manifest_pb::MetaUpdate::decode(buf.chunk()).map_err(anyhow::Error::new)?;
-        MetaUpdate::try_from(meta_update_pb)
+        let ext = MetaExt::decode(buf.chunk()).map_err(anyhow::Error::new)?;
+        MetaUpdate::try_from((meta_update_pb, ext))
     }
 }
 
@@ -390,11 +423,15 @@ pub struct Snapshot {
     pub data: Option<MetaSnapshot>,
 }
 
-impl TryFrom<manifest_pb::Snapshot> for Snapshot {
+impl TryFrom<(manifest_pb::Snapshot, MetaExt)> for Snapshot {
     type Error = Error;
 
-    fn try_from(src: manifest_pb::Snapshot) -> Result<Self> {
-        let meta = src.meta.map(AddTableMeta::try_from).transpose()?;
+    fn try_from((src, ext): (manifest_pb::Snapshot, MetaExt)) -> Result<Self> {
+        let options_ext = ext.table_options.unwrap_or_default();
+        let meta = src
+            .meta
+            .map(|v| AddTableMeta::try_from((v, options_ext)))
+            .transpose()?;
 
         let version_edit = src
             .version_edit
@@ -418,9 +455,10 @@ impl TryFrom<manifest_pb::Snapshot> for Snapshot {
     }
 }
 
-impl From<Snapshot> for manifest_pb::Snapshot {
+impl From<Snapshot> for (manifest_pb::Snapshot, MetaExt) {
     fn from(src: Snapshot) -> Self {
-        if let Some((meta, version_edit)) = src.data.map(|v| {
+        let ext = MetaExt::with_table_options(src.data.as_ref().map(|v| &v.table_meta.opts));
+        let snapshot = if let Some((meta, version_edit)) = src.data.map(|v| {
             let space_id = v.table_meta.space_id;
             let table_id = v.table_meta.table_id;
             let table_meta = manifest_pb::AddTableMeta::from(v.table_meta);
@@ -438,18 +476,20 @@ impl From<Snapshot> for manifest_pb::Snapshot {
                 version_edit.map(manifest_pb::VersionEditMeta::from),
             )
         }) {
-            Self {
+            manifest_pb::Snapshot {
                 end_seq: src.end_seq,
                 meta: Some(meta),
                 version_edit,
             }
         } else {
-            Self {
+            manifest_pb::Snapshot {
                 end_seq: src.end_seq,
                 meta: None,
                 version_edit: None,
             }
-        }
+        };
+
+        (snapshot, ext)
     }
 }
 
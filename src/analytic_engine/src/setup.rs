@@ -17,23 +17,26 @@
 
 //! Setup the analytic engine
 
-use std::{num::NonZeroUsize, path::Path, pin::Pin, sync::Arc};
+use std::{collections::HashMap, num::NonZeroUsize, path::Path, pin::Pin, sync::Arc};
 
 use futures::Future;
 use macros::define_result;
 use meta_client::MetaClientRef;
 use object_store::{
     aliyun,
-    config::{ObjectStoreOptions, StorageOptions},
+    config::{ObjectStoreOptions, StorageOptions, TenantStoreOptions},
     disk_cache::DiskCacheStore,
     local_file,
     mem_cache::{MemCache, MemCacheStore},
+    memory,
     metrics::StoreWithMetrics,
     prefix::StoreWithPrefix,
     s3, ObjectStoreRef,
 };
+use size_ext::ReadableSize;
 use snafu::{ResultExt, Snafu};
 use table_engine::engine::{EngineRuntimes, TableEngineRef};
+use time_ext::clock::SystemClock;
 use wal::manager::{OpenedWals, WalManagerRef};
 
 use crate::{
@@ -42,7 +45,10 @@ use crate::{
     engine::TableEngineImpl,
     instance::open::{InstanceContext, ManifestStorages},
     sst::{
-        factory::{FactoryImpl, ObjectStorePicker, ObjectStorePickerRef, ReadFrequency},
+        factory::{
+            FactoryImpl, ObjectStorePicker, ObjectStorePickerRef, ReadFrequency, StoreProviderRef,
+            TenantStoreProvider,
+        },
         meta_data::cache::{MetaCache, MetaCacheRef},
     },
     Config,
@@ -109,6 +115,12 @@ impl<'a> EngineBuilder<'a> {
             wal_manager: self.opened_wals.manifest_wal.clone(),
             oss_storage: opened_storages.default_store().clone(),
         };
+        let store_provider = open_store_provider(
+            &self.config.storage,
+            Arc::new(opened_storages),
+            self.engine_runtimes.clone(),
+        )
+        .await?;
 
         let InstanceContext {
             instance,
@@ -118,7 +130,7 @@ impl<'a> EngineBuilder<'a> {
             self.engine_runtimes,
             self.opened_wals.data_wal,
             manifest_storages,
-            Arc::new(opened_storages),
+            store_provider,
             self.meta_client,
         )
         .await?;
@@ -137,7 +149,7 @@ async fn build_instance_context(
     engine_runtimes: Arc<EngineRuntimes>,
     wal_manager: WalManagerRef,
     manifest_storages: ManifestStorages,
-    store_picker: ObjectStorePickerRef,
+    store_provider: StoreProviderRef,
     meta_client: Option<MetaClientRef>,
 ) -> Result<InstanceContext> {
     let meta_cache: Option<MetaCacheRef> = config
@@ -148,13 +160,14 @@ async fn build_instance_context(
         config,
         runtimes: engine_runtimes,
         meta_cache,
+        clock: SystemClock::new_ref(),
     };
 
     let instance_ctx = InstanceContext::new(
         open_ctx,
         manifest_storages,
         wal_manager,
-        store_picker,
+        store_provider,
         Arc::new(FactoryImpl),
         meta_client.clone(),
     )
@@ -183,6 +196,42 @@ impl ObjectStorePicker for OpenedStorages {
     }
 }
 
+/// Open the dedicated stores of the configured tenants, the manifest and the
+/// ssts of other tenants are kept in the default store.
+async fn open_store_provider(
+    opts: &StorageOptions,
+    default_picker: ObjectStorePickerRef,
+    engine_runtimes: Arc<EngineRuntimes>,
+) -> Result<StoreProviderRef> {
+    if opts.tenant_stores.is_empty() {
+        return Ok(Arc::new(default_picker));
+    }
+
+    let mut tenant_pickers = HashMap::with_capacity(opts.tenant_stores.len());
+    for TenantStoreOptions {
+        schema_id,
+        object_store,
+    } in &opts.tenant_stores
+    {
+        // The caches are shared by nothing but the default store, so they are
+        // disabled for the tenant stores.
+        let tenant_opts = StorageOptions {
+            mem_cache_capacity: ReadableSize(0),
+            disk_cache_capacity: ReadableSize(0),
+            object_store: object_store.clone(),
+            tenant_stores: Vec::new(),
+            ..opts.clone()
+        };
+        let opened_storages = open_storage(tenant_opts, engine_runtimes.clone()).await?;
+        tenant_pickers.insert(*schema_id, Arc::new(opened_storages) as _);
+    }
+
+    Ok(Arc::new(TenantStoreProvider::new(
+        default_picker,
+        tenant_pickers,
+    )))
+}
+
 // Build store in multiple layer, access speed decrease in turn.
 // MemCacheStore           → DiskCacheStore → real ObjectStore(OSS/S3...)
 // MemCacheStore(ReadOnly) ↑
@@ -230,6 +279,7 @@ fn open_storage(
                 let store_with_prefix = StoreWithPrefix::new(s3_option.prefix, store);
                 Arc::new(store_with_prefix.context(OpenObjectStore)?) as _
             }
+            ObjectStoreOptions::Memory(memory_opts) => memory::shared(&memory_opts.name),
         };
 
         store = Arc::new(StoreWithMetrics::new(
@@ -31,6 +31,7 @@ use trace_metric::MetricsCollector;
 
 use super::parquet::encoding::ColumnEncoding;
 use crate::{
+    space::SpaceId,
     sst::{
         file::Level,
         header,
@@ -87,6 +88,49 @@ impl ObjectStorePicker for ObjectStoreRef {
     }
 }
 
+/// Resolve the [`ObjectStorePicker`] holding the ssts of a space, so that
+/// every tenant (schema) can persist its data to its own bucket/prefix.
+pub trait StoreProvider: Send + Sync + Debug {
+    fn store_picker(&self, space_id: SpaceId) -> &ObjectStorePickerRef;
+}
+
+pub type StoreProviderRef = Arc<dyn StoreProvider>;
+
+/// All spaces share the same [`ObjectStorePicker`].
+impl StoreProvider for ObjectStorePickerRef {
+    fn store_picker(&self, _space_id: SpaceId) -> &ObjectStorePickerRef {
+        self
+    }
+}
+
+/// [`StoreProvider`] with dedicated stores for some tenants, others fall back
+/// to the default one.
+#[derive(Debug)]
+pub struct TenantStoreProvider {
+    default_picker: ObjectStorePickerRef,
+    tenant_pickers: HashMap<SpaceId, ObjectStorePickerRef>,
+}
+
+impl TenantStoreProvider {
+    pub fn new(
+        default_picker: ObjectStorePickerRef,
+        tenant_pickers: HashMap<SpaceId, ObjectStorePickerRef>,
+    ) -> Self {
+        Self {
+            default_picker,
+            tenant_pickers,
+        }
+    }
+}
+
+impl StoreProvider for TenantStoreProvider {
+    fn store_picker(&self, space_id: SpaceId) -> &ObjectStorePickerRef {
+        self.tenant_pickers
+            .get(&space_id)
+            .unwrap_or(&self.default_picker)
+    }
+}
+
 /// Sst factory reference
 pub type FactoryRef = Arc<dyn Factory>;
 
@@ -301,3 +345,31 @@ impl Factory for FactoryImpl {
         )))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use object_store::local_file;
+    use tempfile::tempdir;
+
+    use super::*;
+
+    fn new_picker() -> ObjectStorePickerRef {
+        let root = tempdir().unwrap().as_ref().to_string_lossy().to_string();
+        let store: ObjectStoreRef = Arc::new(local_file::try_new_with_default(root).unwrap());
+        Arc::new(store)
+    }
+
+    #[test]
+    fn test_tenant_store_provider() {
+        let default_picker = new_picker();
+        let tenant_picker = new_picker();
+        let provider = TenantStoreProvider::new(
+            default_picker.clone(),
+            HashMap::from([(1, tenant_picker.clone())]),
+        );
+
+        assert!(Arc::ptr_eq(provider.store_picker(1), &tenant_picker));
+        assert!(Arc::ptr_eq(provider.store_picker(2), &default_picker));
+        assert!(Arc::ptr_eq(default_picker.store_picker(1), &default_picker));
+    }
+}
@@ -19,7 +19,7 @@
 
 use std::{
     borrow::Borrow,
-    collections::{BTreeMap, HashSet},
+    collections::{BTreeMap, HashMap, HashSet},
     fmt,
     fmt::Debug,
     hash::{Hash, Hasher},
@@ -31,6 +31,8 @@ use std::{
 };
 
 use common_types::{
+    datum::DatumKind,
+    schema::Schema,
     time::{TimeRange, Timestamp},
     SequenceNumber,
 };
@@ -48,7 +50,17 @@ use tokio::sync::{
     Mutex,
 };
 
-use crate::{space::SpaceId, sst::manager::FileId, table::sst_util, table_options::StorageFormat};
+use crate::{
+    space::SpaceId,
+    sst::{
+        factory::{ColumnStats, StoreProviderRef},
+        manager::FileId,
+        parquet::writer::KEEP_COLUMN_VALUE_THRESHOLD,
+        writer::SstColumnStats,
+    },
+    table::sst_util,
+    table_options::StorageFormat,
+};
 
 /// Error of sst file.
 #[derive(Debug, Snafu)]
@@ -289,6 +301,11 @@ impl FileHandle {
         self.inner.meta.storage_format
     }
 
+    #[inline]
+    pub fn column_stats(&self) -> &[SstColumnStats] {
+        &self.inner.meta.column_stats
+    }
+
     #[inline]
     pub fn meta(&self) -> FileMeta {
         self.inner.meta.clone()
@@ -467,6 +484,43 @@ impl FileHandleSet {
     }
 }
 
+/// Pick the string columns whose distinct count is low in all the `files` by
+/// their column stats, which are worth dictionary encoding.
+///
+/// Nothing is picked if any of the `files` has no column stats.
+pub fn low_cardinality_columns(
+    schema: &Schema,
+    files: &[FileHandle],
+) -> HashMap<String, ColumnStats> {
+    if files.is_empty() || files.iter().any(|file| file.column_stats().is_empty()) {
+        return HashMap::new();
+    }
+
+    schema
+        .columns()
+        .iter()
+        .enumerate()
+        .filter(|(_, column)| column.data_type == DatumKind::String)
+        .filter(|(col_idx, _)| {
+            files.iter().all(|file| {
+                // The column added after writing the file is all nulls in it.
+                file.column_stats()
+                    .get(*col_idx)
+                    .map_or(0, |stats| stats.distinct_count)
+                    <= KEEP_COLUMN_VALUE_THRESHOLD
+            })
+        })
+        .map(|(_, column)| {
+            (
+                column.name.clone(),
+                ColumnStats {
+                    low_cardinality: true,
+                },
+            )
+        })
+        .collect()
+}
+
 /// Meta of a sst file, immutable once created
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct FileMeta {
@@ -484,6 +538,10 @@ pub struct FileMeta {
     pub storage_format: StorageFormat,
     /// Associated files, such as: meta_path
     pub associated_files: Vec<String>,
+    /// Statistics of the columns gathered during writing the file, which is
+    /// only kept in memory, so it is empty if unknown, e.g. the file is
+    /// recovered from the manifest.
+    pub column_stats: Vec<SstColumnStats>,
 }
 
 impl FileMeta {
@@ -521,6 +579,7 @@ impl TryFrom<horaedbproto::compaction_service::FileMeta> for FileMeta {
             max_seq: value.max_seq,
             storage_format,
             associated_files,
+            column_stats: Vec::new(),
         })
     }
 }
@@ -643,14 +702,14 @@ impl FilePurger {
         },
     };
 
-    pub fn start(runtime: &Runtime, store: ObjectStoreRef) -> Self {
+    pub fn start(runtime: &Runtime, store_provider: StoreProviderRef) -> Self {
         // We must use unbound channel, so the sender wont block when the handle is
         // dropped.
         let (tx, rx) = mpsc::unbounded_channel();
 
         // Spawn a background job to purge files.
         let handle = runtime.spawn(async {
-            Self::purge_file_loop(store, rx).await;
+            Self::purge_file_loop(store_provider, rx).await;
         });
 
         Self {
@@ -686,7 +745,10 @@ impl FilePurger {
         }
     }
 
-    async fn purge_file_loop(store: ObjectStoreRef, mut receiver: UnboundedReceiver<Request>) {
+    async fn purge_file_loop(
+        store_provider: StoreProviderRef,
+        mut receiver: UnboundedReceiver<Request>,
+    ) {
         info!("File purger start");
 
         while let Some(request) = receiver.recv().await {
@@ -704,12 +766,16 @@ impl FilePurger {
                         sst_file_path.to_string()
                     );
 
+                    // Files of a space live in the store of its tenant.
+                    let store = store_provider
+                        .store_picker(purge_request.space_id)
+                        .default_store();
                     for path in purge_request.associated_files {
                         let path = Path::from(path);
-                        Self::delete_file(&store, &path).await;
+                        Self::delete_file(store, &path).await;
                     }
 
-                    Self::delete_file(&store, &sst_file_path).await;
+                    Self::delete_file(store, &sst_file_path).await;
                 }
                 Request::Exit => break,
             }
@@ -723,6 +789,8 @@ pub type FilePurgerRef = Arc<FilePurger>;
 
 #[cfg(test)]
 pub mod tests {
+    use common_types::tests::build_schema_with_dictionary;
+
     use super::*;
 
     pub struct FilePurgerMocker;
@@ -737,4 +805,63 @@ pub mod tests {
             }
         }
     }
+
+    fn build_file_handle(column_stats: Vec<SstColumnStats>) -> FileHandle {
+        let (tx, _rx) = mpsc::unbounded_channel();
+        let file_meta = FileMeta {
+            id: 1,
+            size: 0,
+            row_num: 10,
+            time_range: TimeRange::empty(),
+            max_seq: 0,
+            storage_format: StorageFormat::default(),
+            associated_files: Vec::new(),
+            column_stats,
+        };
+        FileHandle::new(file_meta, FilePurgeQueue::new(1, 1.into(), tx))
+    }
+
+    fn build_column_stats(distinct_counts: &[usize]) -> Vec<SstColumnStats> {
+        distinct_counts
+            .iter()
+            .map(|distinct_count| SstColumnStats {
+                null_count: 0,
+                distinct_count: *distinct_count,
+            })
+            .collect()
+    }
+
+    fn check_low_cardinality_columns(files: &[FileHandle], expected_cols: &[&str]) {
+        let schema = build_schema_with_dictionary();
+        let columns = low_cardinality_columns(&schema, files);
+        let mut cols: Vec<_> = columns.keys().map(|v| v.as_str()).collect();
+        cols.sort();
+        assert_eq!(expected_cols, cols);
+        assert!(columns.values().all(|stats| stats.low_cardinality));
+    }
+
+    #[test]
+    fn test_low_cardinality_columns() {
+        // The string columns are field2, tag1 and tag2.
+        let files = vec![
+            build_file_handle(build_column_stats(&[100, 100, 100, 5, 100, 100, 3, 50])),
+            build_file_handle(build_column_stats(&[100, 100, 100, 20, 100, 100, 10, 3])),
+        ];
+        check_low_cardinality_columns(&files, &["field2", "tag1"]);
+
+        // The tags are added after writing the first file.
+        let files = vec![
+            build_file_handle(build_column_stats(&[1, 1, 1, 1, 1, 1])),
+            build_file_handle(build_column_stats(&[1, 1, 1, 30, 1, 1, 10, 10])),
+        ];
+        check_low_cardinality_columns(&files, &["tag1", "tag2"]);
+
+        // The column stats of the second file is unknown.
+        let files = vec![
+            build_file_handle(build_column_stats(&[1, 1, 1, 1, 1, 1, 1, 1])),
+            build_file_handle(Vec::new()),
+        ];
+        check_low_cardinality_columns(&files, &[]);
+        check_low_cardinality_columns(&[], &[]);
+    }
 }
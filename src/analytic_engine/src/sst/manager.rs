@@ -27,6 +27,20 @@ use crate::{
 /// Id for a sst file
 pub type FileId = u64;
 
+/// Statistics of the sst files at a level
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct LevelStats {
+    pub level: Level,
+    /// Number of the ssts, every sst is a sorted run
+    pub num_ssts: usize,
+    /// Number of the ssts being compacted
+    pub num_being_compacted: usize,
+    /// Total size of the ssts in bytes
+    pub total_size: u64,
+    /// Total number of rows of the ssts
+    pub num_rows: u64,
+}
+
 /// A table level manager that manages all the sst files of the table
 pub struct LevelsController {
     levels: Vec<LevelHandler>,
@@ -115,6 +129,28 @@ impl LevelsController {
             .any(|level_handler| level_handler.has_expired_sst(expire_time))
     }
 
+    /// Collect the statistics of every level.
+    pub fn level_stats(&self) -> Vec<LevelStats> {
+        self.levels
+            .iter()
+            .map(|level_handler| {
+                let mut stats = LevelStats {
+                    level: level_handler.level,
+                    ..Default::default()
+                };
+                for file in level_handler.iter_ssts() {
+                    stats.num_ssts += 1;
+                    stats.total_size += file.size();
+                    stats.num_rows += file.row_num();
+                    if file.being_compacted() {
+                        stats.num_being_compacted += 1;
+                    }
+                }
+                stats
+            })
+            .collect()
+    }
+
     pub fn expired_ssts(&self, expire_time: Option<Timestamp>) -> Vec<ExpiredFiles> {
         self.levels()
             .map(|level| {
@@ -166,6 +202,7 @@ pub mod tests {
                         max_seq: sst_meta.max_sequence(),
                         storage_format: StorageFormat::Columnar,
                         associated_files: Vec::new(),
+                        column_stats: Vec::new(),
                     },
                 );
             }
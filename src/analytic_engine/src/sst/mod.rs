@@ -23,6 +23,8 @@ pub mod header;
 pub mod manager;
 pub mod meta_data;
 pub mod metrics;
+pub mod parallel_writer;
 pub mod parquet;
 pub mod reader;
+pub mod resumable_writer;
 pub mod writer;
@@ -0,0 +1,252 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+//! Sst writer splitting the input into multiple ssts written in parallel.
+
+use std::pin::Pin;
+
+use common_types::request_id::RequestId;
+use futures::{channel::mpsc, future, SinkExt, StreamExt};
+use generic_error::BoxError;
+use object_store::Path;
+use snafu::ResultExt;
+
+use crate::sst::{
+    factory::{FactoryRef, ObjectStorePickerRef, SstWriteOptions},
+    file::Level,
+    writer::{
+        CreateWriter, MetaData, RecordBatchStream, RecordBatchStreamItem, Result, SstExtensions,
+        SstInfo,
+    },
+};
+
+/// Max number of record batches buffered for every underlying writer.
+const MAX_BATCHES_IN_FLIGHT_PER_WRITER: usize = 4;
+
+/// The writer splitting the sorted input by key range into multiple ssts.
+///
+/// The input is dispatched to the underlying writers in order, and the writer
+/// of one key range encodes and uploads its sst while the following key ranges
+/// are still being read, so the large input can be written faster than a
+/// single writer.
+pub struct ParallelSstWriter<'a> {
+    factory: &'a FactoryRef,
+    options: &'a SstWriteOptions,
+    store_picker: &'a ObjectStorePickerRef,
+    level: Level,
+    /// Paths of the output ssts, in the order of the key ranges.
+    paths: &'a [Path],
+    /// Number of rows of every sst except the last one.
+    rows_per_sst: usize,
+}
+
+impl<'a> ParallelSstWriter<'a> {
+    /// Create the writer splitting the input into at most `paths.len()` ssts.
+    ///
+    /// Panic if the `paths` is empty.
+    pub fn new(
+        factory: &'a FactoryRef,
+        options: &'a SstWriteOptions,
+        store_picker: &'a ObjectStorePickerRef,
+        level: Level,
+        paths: &'a [Path],
+        rows_per_sst: usize,
+    ) -> Self {
+        assert!(!paths.is_empty());
+
+        Self {
+            factory,
+            options,
+            store_picker,
+            level,
+            paths,
+            rows_per_sst,
+        }
+    }
+
+    /// Write the sorted input into the ssts, and every sst contains a
+    /// contiguous key range of the input.
+    ///
+    /// The returned infos are in the order of the `paths`, and the info is
+    /// `None` if no rows are left for the sst. Like the single writer, the
+    /// first sst is always written even if the input is empty.
+    ///
+    /// The written ssts will be leaked if any of the writers fails.
+    pub async fn write(
+        &self,
+        request_id: RequestId,
+        meta: &MetaData,
+        input: RecordBatchStream,
+    ) -> Result<Vec<Option<SstInfo>>> {
+        let mut senders = Vec::with_capacity(self.paths.len());
+        let mut writes = Vec::with_capacity(self.paths.len());
+        for (idx, path) in self.paths.iter().enumerate() {
+            let (tx, rx) = mpsc::channel(MAX_BATCHES_IN_FLIGHT_PER_WRITER);
+            senders.push(tx);
+
+            let request_id = request_id.clone();
+            writes.push(async move {
+                let mut rx = rx.peekable();
+                if idx > 0 && Pin::new(&mut rx).peek().await.is_none() {
+                    return Ok(None);
+                }
+
+                let mut writer = self
+                    .factory
+                    .create_writer(self.options, path, self.store_picker, self.level)
+                    .await
+                    .box_err()
+                    .context(CreateWriter)?;
+                writer.write(request_id, meta, Box::new(rx)).await.map(Some)
+            });
+        }
+
+        let (_, sst_infos) =
+            future::join(self.dispatch(input, senders), future::try_join_all(writes)).await;
+
+        sst_infos
+    }
+
+    /// Dispatch the input to the writers in order, and switch to the next
+    /// writer once the current one has received `rows_per_sst` rows.
+    ///
+    /// The error of the input is passed to the current writer to make it fail.
+    async fn dispatch(
+        &self,
+        mut input: RecordBatchStream,
+        senders: Vec<mpsc::Sender<RecordBatchStreamItem>>,
+    ) {
+        let mut senders = senders.into_iter();
+        // The `paths` is ensured not empty.
+        let mut current = senders.next().unwrap();
+        let mut num_rows = 0;
+        while let Some(item) = input.next().await {
+            let is_err = item.is_err();
+            if let Ok(batch) = &item {
+                if num_rows >= self.rows_per_sst {
+                    if let Some(next) = senders.next() {
+                        // The previous writer finishes once its sender is dropped.
+                        current = next;
+                        num_rows = 0;
+                    }
+                }
+                num_rows += batch.num_rows();
+            }
+
+            // Failed to send means the writer has failed, and its error will be returned.
+            if current.send(item).await.is_err() || is_err {
+                return;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use bytes_ext::Bytes;
+    use common_types::{
+        tests::{build_row_for_dictionary, build_schema_with_dictionary},
+        time::{TimeRange, Timestamp},
+    };
+    use futures::stream;
+    use object_store::{local_file, ObjectStoreRef};
+    use runtime::Runtime;
+    use tempfile::tempdir;
+
+    use super::*;
+    use crate::{
+        row_iter::tests::build_fetched_record_batch_with_key,
+        sst::factory::FactoryImpl,
+        table_options::{self, StorageFormatHint},
+    };
+
+    fn check_parallel_write(
+        runtime: &Runtime,
+        num_paths: usize,
+        rows_per_sst: usize,
+        expected_row_nums: Vec<Option<usize>>,
+    ) {
+        runtime.block_on(async {
+            let factory: FactoryRef = Arc::new(FactoryImpl);
+            let options = SstWriteOptions {
+                storage_format_hint: StorageFormatHint::Auto,
+                num_rows_per_row_group: 2,
+                compression: table_options::Compression::Uncompressed,
+                max_buffer_size: 0,
+                column_stats: Default::default(),
+            };
+            let root = tempdir().unwrap().as_ref().to_string_lossy().to_string();
+            let store: ObjectStoreRef = Arc::new(local_file::try_new_with_default(root).unwrap());
+            let store_picker: ObjectStorePickerRef = Arc::new(store);
+            let paths: Vec<_> = (0..num_paths)
+                .map(|i| Path::from(format!("{i}.sst")))
+                .collect();
+
+            let schema = build_schema_with_dictionary();
+            let meta = MetaData {
+                min_key: Bytes::from_static(b"a"),
+                max_key: Bytes::from_static(b"j"),
+                time_range: TimeRange::new_unchecked(Timestamp::new(100), Timestamp::new(101)),
+                max_sequence: 200,
+                schema: schema.clone(),
+                extensions: SstExtensions::new(),
+            };
+            // 5 batches with 2 rows in every batch.
+            let batches: Vec<_> = [b"a", b"c", b"e", b"g", b"i"]
+                .into_iter()
+                .map(|key| {
+                    let next_key = [key[0] + 1];
+                    let rows = vec![
+                        build_row_for_dictionary(key, 100, 1.0, "v", 1, 1, None, "tag"),
+                        build_row_for_dictionary(&next_key, 100, 1.0, "v", 1, 1, None, "tag"),
+                    ];
+                    Ok(build_fetched_record_batch_with_key(schema.clone(), rows))
+                })
+                .collect();
+
+            let writer = ParallelSstWriter::new(
+                &factory,
+                &options,
+                &store_picker,
+                Level::MAX,
+                &paths,
+                rows_per_sst,
+            );
+            let sst_infos = writer
+                .write(RequestId::next_id(), &meta, Box::new(stream::iter(batches)))
+                .await
+                .unwrap();
+            let row_nums: Vec<_> = sst_infos
+                .iter()
+                .map(|info| info.as_ref().map(|v| v.row_num))
+                .collect();
+            assert_eq!(expected_row_nums, row_nums);
+        });
+    }
+
+    #[test]
+    fn test_parallel_write() {
+        let runtime = Arc::new(runtime::Builder::default().enable_all().build().unwrap());
+
+        check_parallel_write(&runtime, 1, 4, vec![Some(10)]);
+        check_parallel_write(&runtime, 3, 4, vec![Some(4), Some(4), Some(2)]);
+        check_parallel_write(&runtime, 4, 4, vec![Some(4), Some(4), Some(2), None]);
+        check_parallel_write(&runtime, 2, 3, vec![Some(4), Some(6)]);
+    }
+}
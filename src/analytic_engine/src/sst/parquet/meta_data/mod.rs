@@ -25,7 +25,10 @@ use horaedbproto::{schema as schema_pb, sst as sst_pb};
 use macros::define_result;
 use snafu::{Backtrace, OptionExt, ResultExt, Snafu};
 
-use crate::sst::{parquet::meta_data::filter::ParquetFilter, writer::MetaData};
+use crate::sst::{
+    parquet::meta_data::filter::ParquetFilter,
+    writer::{MetaData, SstExtensions},
+};
 
 pub mod filter;
 
@@ -105,6 +108,7 @@ impl From<ParquetMetaData> for MetaData {
             time_range: meta.time_range,
             max_sequence: meta.max_sequence,
             schema: meta.schema,
+            extensions: SstExtensions::new(),
         }
     }
 }
@@ -117,6 +121,7 @@ impl From<Arc<ParquetMetaData>> for MetaData {
             time_range: meta.time_range,
             max_sequence: meta.max_sequence,
             schema: meta.schema.clone(),
+            extensions: SstExtensions::new(),
         }
     }
 }
@@ -17,9 +17,10 @@
 
 //! Sst writer implementation based on parquet.
 
-use std::collections::{HashMap, HashSet};
+use std::collections::{BTreeSet, HashMap, HashSet};
 
 use async_trait::async_trait;
+use bytes_ext::Bytes;
 use common_types::{
     datum::DatumKind, record_batch::FetchedRecordBatch, request_id::RequestId, schema::Schema,
     time::TimeRange,
@@ -47,15 +48,18 @@ use crate::{
             },
         },
         writer::{
-            BuildParquetFilter, EncodePbData, EncodeRecordBatch, ExpectTimestampColumn, MetaData,
-            PollRecordBatch, RecordBatchStream, Result, SstInfo, SstWriter, Storage,
+            BuildIndex, BuildParquetFilter, EncodePbData, EncodeRecordBatch, ExpectTimestampColumn,
+            IndexBuilder, MetaData, PollRecordBatch, RecordBatchStream, Result, SstColumnStats,
+            SstExtensions, SstInfo, SstWriter, Storage,
         },
     },
     table::sst_util,
     table_options::StorageFormat,
 };
 
-const KEEP_COLUMN_VALUE_THRESHOLD: usize = 20;
+/// Max number of the distinct values of a column to keep in the meta data,
+/// and such column is thought to be low-cardinality.
+pub(crate) const KEEP_COLUMN_VALUE_THRESHOLD: usize = 20;
 /// Only the row group which contains at least
 /// `MIN_NUM_ROWS_DICT_ENCODING_SAMPLE` rows can be sampling to decide whether
 /// to do dictionary encoding.
@@ -64,6 +68,9 @@ const MIN_NUM_ROWS_SAMPLE_DICT_ENCODING: usize = 1024;
 /// `total_num_values * MAX_UNIQUE_VALUE_RATIO_DICT_ENCODING`, there is no need
 /// to do dictionary encoding for such column.
 const MAX_UNIQUE_VALUE_RATIO_DICT_ENCODING: f64 = 0.12;
+/// Number of the minimum hashes kept to estimate the distinct count of a
+/// column.
+const DISTINCT_SKETCH_SIZE: usize = 256;
 
 /// The implementation of sst based on parquet and object storage.
 #[derive(Debug)]
@@ -73,6 +80,8 @@ pub struct ParquetSstWriter<'a> {
     /// The storage where the data is persist.
     store: &'a ObjectStoreRef,
     options: WriteOptions,
+    /// Builders of the auxiliary indexes written along with the sst.
+    index_builders: Vec<Box<dyn IndexBuilder>>,
 }
 
 impl<'a> ParquetSstWriter<'a> {
@@ -86,6 +95,7 @@ impl<'a> ParquetSstWriter<'a> {
             path,
             store,
             options,
+            index_builders: Vec::new(),
         }
     }
 }
@@ -105,6 +115,8 @@ struct RecordBatchGroupWriter<'a> {
     // `column_values` is used to collect distinct values in each columns,
     // its order is the same with schema's columns.
     column_values: Option<Vec<Option<ColumnValueSet>>>,
+    column_stats: ColumnStatsCollector,
+    index_builders: Vec<Box<dyn IndexBuilder>>,
 }
 
 #[derive(Clone, Debug)]
@@ -129,6 +141,7 @@ impl<'a> RecordBatchGroupWriter<'a> {
         input: RecordBatchStream,
         meta_data: &'a MetaData,
         options: WriteOptions,
+        index_builders: Vec<Box<dyn IndexBuilder>>,
     ) -> Self {
         // No need to build complex index for the min-level sst so there is no need to
         // collect the column values.
@@ -148,6 +161,7 @@ impl<'a> RecordBatchGroupWriter<'a> {
                 .collect()
         });
 
+        let column_stats = ColumnStatsCollector::new(meta_data.schema.num_columns());
         Self {
             request_id,
             input,
@@ -156,6 +170,8 @@ impl<'a> RecordBatchGroupWriter<'a> {
             input_exhausted: false,
             real_time_range: None,
             column_values,
+            column_stats,
+            index_builders,
         }
     }
 
@@ -326,7 +342,13 @@ impl<'a> RecordBatchGroupWriter<'a> {
         mut self,
         sink: W,
         meta_path: &Path,
-    ) -> Result<(usize, ParquetMetaData, ParquetEncoder)> {
+    ) -> Result<(
+        usize,
+        ParquetMetaData,
+        ParquetEncoder,
+        Vec<SstColumnStats>,
+        Vec<(String, Bytes)>,
+    )> {
         let mut prev_record_batch: Option<FetchedRecordBatch> = None;
         let mut arrow_row_group = Vec::new();
         let mut total_num_rows = 0;
@@ -368,6 +390,12 @@ impl<'a> RecordBatchGroupWriter<'a> {
                 if let Some(column_values) = self.column_values.as_mut() {
                     Self::update_column_values(column_values, &record_batch);
                 }
+                self.column_stats.update(&record_batch);
+                for builder in &mut self.index_builders {
+                    builder.update(&record_batch).with_context(|| BuildIndex {
+                        name: builder.name(),
+                    })?;
+                }
 
                 arrow_row_group.push(record_batch.into_record_batch().into_arrow_record_batch());
             }
@@ -403,7 +431,87 @@ impl<'a> RecordBatchGroupWriter<'a> {
             .box_err()
             .context(EncodeRecordBatch)?;
 
-        Ok((total_num_rows, parquet_meta_data, parquet_encoder))
+        let indexes = self
+            .index_builders
+            .iter_mut()
+            .map(|builder| {
+                let index = builder.finish().with_context(|| BuildIndex {
+                    name: builder.name(),
+                })?;
+                Ok((builder.name().to_string(), index))
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        Ok((
+            total_num_rows,
+            parquet_meta_data,
+            parquet_encoder,
+            self.column_stats.finish(),
+            indexes,
+        ))
+    }
+}
+
+/// Collector of the null count and approximate distinct count of columns.
+///
+/// The distinct count is estimated by the k-minimum-values sketch, which only
+/// keeps the k smallest hashes of the values.
+struct ColumnStatsCollector {
+    null_counts: Vec<usize>,
+    min_hashes: Vec<BTreeSet<u64>>,
+}
+
+impl ColumnStatsCollector {
+    fn new(num_columns: usize) -> Self {
+        Self {
+            null_counts: vec![0; num_columns],
+            min_hashes: vec![BTreeSet::new(); num_columns],
+        }
+    }
+
+    fn update(&mut self, record_batch: &FetchedRecordBatch) {
+        for (col_idx, column) in record_batch.columns().iter().enumerate() {
+            let min_hashes = &mut self.min_hashes[col_idx];
+            for row in 0..column.num_rows() {
+                let datum_view = column.datum_view(row);
+                if datum_view.is_null() {
+                    self.null_counts[col_idx] += 1;
+                    continue;
+                }
+                datum_view.do_with_bytes(|bytes| {
+                    Self::insert_hash(min_hashes, hash_ext::hash64(bytes));
+                });
+            }
+        }
+    }
+
+    fn insert_hash(min_hashes: &mut BTreeSet<u64>, hash: u64) {
+        if min_hashes.len() < DISTINCT_SKETCH_SIZE {
+            min_hashes.insert(hash);
+        } else if hash < *min_hashes.last().unwrap() && min_hashes.insert(hash) {
+            min_hashes.pop_last();
+        }
+    }
+
+    fn estimate_distinct_count(min_hashes: &BTreeSet<u64>) -> usize {
+        if min_hashes.len() < DISTINCT_SKETCH_SIZE {
+            return min_hashes.len();
+        }
+
+        // The k-th minimum hash normalized to (0, 1].
+        let kth_hash = (*min_hashes.last().unwrap() as f64 + 1.0) / (u64::MAX as f64);
+        ((DISTINCT_SKETCH_SIZE - 1) as f64 / kth_hash) as usize
+    }
+
+    fn finish(self) -> Vec<SstColumnStats> {
+        self.null_counts
+            .into_iter()
+            .zip(self.min_hashes.iter())
+            .map(|(null_count, min_hashes)| SstColumnStats {
+                null_count,
+                distinct_count: Self::estimate_distinct_count(min_hashes),
+            })
+            .collect()
     }
 }
 
@@ -430,6 +538,10 @@ async fn multi_upload_abort(aborter: MultiUploadRef) {
 
 #[async_trait]
 impl<'a> SstWriter for ParquetSstWriter<'a> {
+    fn add_index_builder(&mut self, builder: Box<dyn IndexBuilder>) {
+        self.index_builders.push(builder);
+    }
+
     async fn write(
         &mut self,
         request_id: RequestId,
@@ -448,7 +560,13 @@ impl<'a> SstWriter for ParquetSstWriter<'a> {
             sst_level: self.options.sst_level,
             column_encodings: std::mem::take(&mut self.options.column_encodings),
         };
-        let group_writer = RecordBatchGroupWriter::new(request_id, input, meta, write_options);
+        let group_writer = RecordBatchGroupWriter::new(
+            request_id,
+            input,
+            meta,
+            write_options,
+            std::mem::take(&mut self.index_builders),
+        );
 
         let sink = MultiUploadWriter::new(self.store, self.path)
             .await
@@ -457,7 +575,7 @@ impl<'a> SstWriter for ParquetSstWriter<'a> {
 
         let meta_path = Path::from(sst_util::new_metadata_path(self.path.as_ref()));
 
-        let (total_num_rows, parquet_metadata, mut data_encoder) =
+        let (total_num_rows, parquet_metadata, mut data_encoder, column_stats, indexes) =
             match group_writer.write_all(sink, &meta_path).await {
                 Ok(v) => v,
                 Err(e) => {
@@ -491,6 +609,16 @@ impl<'a> SstWriter for ParquetSstWriter<'a> {
             .box_err()
             .context(EncodeRecordBatch)?;
 
+        let mut index_paths = Vec::with_capacity(indexes.len());
+        for (name, index) in indexes {
+            let index_path = Path::from(sst_util::new_index_path(self.path.as_ref(), &name));
+            self.store
+                .put(&index_path, index.into())
+                .await
+                .context(Storage)?;
+            index_paths.push(index_path.to_string());
+        }
+
         let file_head = self.store.head(self.path).await.context(Storage)?;
         Ok(SstInfo {
             file_size: file_head.size,
@@ -498,6 +626,9 @@ impl<'a> SstWriter for ParquetSstWriter<'a> {
             storage_format: StorageFormat::Columnar,
             meta_path: meta_path.to_string(),
             time_range,
+            column_stats,
+            index_paths,
+            extensions: SstExtensions::new(),
         })
     }
 }
@@ -592,6 +723,7 @@ mod tests {
         time::{TimeRange, Timestamp},
     };
     use futures::stream;
+    use generic_error::GenericResult;
     use object_store::local_file;
     use runtime::{self, Runtime};
     use table_engine::predicate::Predicate;
@@ -612,6 +744,27 @@ mod tests {
 
     // TODO(xikai): add test for reverse reader
 
+    /// Index recording the number of rows of every record batch written.
+    #[derive(Debug, Default)]
+    struct RowCountIndexBuilder {
+        row_counts: Vec<u8>,
+    }
+
+    impl IndexBuilder for RowCountIndexBuilder {
+        fn name(&self) -> &str {
+            "row_count"
+        }
+
+        fn update(&mut self, record_batch: &FetchedRecordBatch) -> GenericResult<()> {
+            self.row_counts.push(record_batch.num_rows() as u8);
+            Ok(())
+        }
+
+        fn finish(&mut self) -> GenericResult<Bytes> {
+            Ok(Bytes::from(std::mem::take(&mut self.row_counts)))
+        }
+    }
+
     #[test]
     fn test_parquet_build_and_read() {
         test_util::init_log_for_test();
@@ -651,6 +804,7 @@ mod tests {
                 time_range: TimeRange::new_unchecked(Timestamp::new(1), Timestamp::new(2)),
                 max_sequence: 200,
                 schema: schema.clone(),
+                extensions: SstExtensions::new(),
             };
 
             let mut counter = 5;
@@ -707,6 +861,7 @@ mod tests {
                 )
                 .await
                 .unwrap();
+            writer.add_index_builder(Box::<RowCountIndexBuilder>::default());
             let sst_info = writer
                 .write(
                     RequestId::next_id(),
@@ -717,6 +872,21 @@ mod tests {
                 .unwrap();
 
             assert_eq!(20, sst_info.row_num);
+            assert_eq!(schema.num_columns(), sst_info.column_stats.len());
+            // The 7th column has one null value in every batch.
+            assert_eq!(5, sst_info.column_stats[6].null_count);
+            assert_eq!(4, sst_info.column_stats[0].distinct_count);
+            assert_eq!(2, sst_info.column_stats[7].distinct_count);
+            assert_eq!(vec!["data.par.row_count.index"], sst_info.index_paths);
+            let index = store_picker
+                .default_store()
+                .get(&Path::from("data.par.row_count.index"))
+                .await
+                .unwrap()
+                .bytes()
+                .await
+                .unwrap();
+            assert_eq!(20, index.iter().map(|v| *v as usize).sum::<usize>());
 
             let scan_options = ScanOptions::default();
             // read sst back to test
@@ -885,12 +1055,14 @@ mod tests {
             time_range: Default::default(),
             max_sequence: 1,
             schema,
+            extensions: SstExtensions::new(),
         };
         let mut group_writer = RecordBatchGroupWriter::new(
             RequestId::next_id(),
             record_batch_stream,
             &meta_data,
             write_options,
+            Vec::new(),
         );
 
         let mut prev_record_batch = None;
@@ -950,6 +1122,7 @@ mod tests {
             time_range: TimeRange::new_unchecked(Timestamp::new(1), Timestamp::new(2)),
             max_sequence: 200,
             schema,
+            extensions: SstExtensions::new(),
         };
         let record_batches_with_key = vec![record_batch_with_key0, record_batch_with_key1];
 
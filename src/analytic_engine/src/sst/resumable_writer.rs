@@ -0,0 +1,572 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+//! Sst writer persisting a checkpoint after every finished sst, so that a
+//! failed writing of a huge output can be resumed.
+//!
+//! The checkpoint is taken at the granularity of sst: a half written sst can't
+//! be resumed because the parquet file is encoded as a whole and the multipart
+//! upload state isn't exposed by the object store, so the unfinished sst will
+//! be rewritten from its first row on resuming.
+
+use std::collections::BTreeMap;
+
+use bytes_ext::Bytes;
+use codec::{memcomparable::MemComparable, Encoder};
+use common_types::{
+    record_batch::FetchedRecordBatch,
+    request_id::RequestId,
+    time::{TimeRange, Timestamp},
+};
+use futures::{channel::mpsc, future, SinkExt, StreamExt};
+use generic_error::BoxError;
+use logger::info;
+use object_store::{ObjectStoreRef, Path};
+use serde::{Deserialize, Serialize};
+use snafu::ResultExt;
+
+use crate::{
+    sst::{
+        factory::{FactoryRef, ObjectStorePickerRef, SstWriteOptions},
+        file::Level,
+        manager::FileId,
+        writer::{
+            CheckpointMismatch, CreateWriter, EncodeKey, LoadCheckpoint, MetaData, PollRecordBatch,
+            RecordBatchStream, RecordBatchStreamItem, Result, SaveCheckpoint, SstColumnStats,
+            SstInfo,
+        },
+    },
+    table_options::StorageFormat,
+};
+
+/// Max number of record batches buffered for the underlying writer.
+const MAX_BATCHES_IN_FLIGHT: usize = 4;
+
+/// Info of the sst finished before the checkpoint is taken.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FinishedSst {
+    pub file_size: usize,
+    pub row_num: usize,
+    pub storage_format: StorageFormat,
+    pub meta_path: String,
+    /// Inclusive start and exclusive end of the time range.
+    pub time_range: (i64, i64),
+    pub column_stats: Vec<SstColumnStats>,
+    pub index_paths: Vec<String>,
+    #[serde(default)]
+    pub extensions: BTreeMap<String, Vec<u8>>,
+}
+
+impl From<&SstInfo> for FinishedSst {
+    fn from(info: &SstInfo) -> Self {
+        Self {
+            file_size: info.file_size,
+            row_num: info.row_num,
+            storage_format: info.storage_format,
+            meta_path: info.meta_path.clone(),
+            time_range: (
+                info.time_range.inclusive_start().as_i64(),
+                info.time_range.exclusive_end().as_i64(),
+            ),
+            column_stats: info.column_stats.clone(),
+            index_paths: info.index_paths.clone(),
+            extensions: info
+                .extensions
+                .iter()
+                .map(|(k, v)| (k.clone(), v.to_vec()))
+                .collect(),
+        }
+    }
+}
+
+impl From<FinishedSst> for SstInfo {
+    fn from(sst: FinishedSst) -> Self {
+        Self {
+            file_size: sst.file_size,
+            row_num: sst.row_num,
+            storage_format: sst.storage_format,
+            meta_path: sst.meta_path,
+            time_range: TimeRange::new_unchecked(
+                Timestamp::new(sst.time_range.0),
+                Timestamp::new(sst.time_range.1),
+            ),
+            column_stats: sst.column_stats,
+            index_paths: sst.index_paths,
+            extensions: sst
+                .extensions
+                .into_iter()
+                .map(|(k, v)| (k, Bytes::from(v)))
+                .collect(),
+        }
+    }
+}
+
+/// Progress of the resumable writing.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct WriteCheckpoint {
+    /// Ids of all the output ssts, which must be reused on resuming.
+    pub file_ids: Vec<FileId>,
+    /// Number of rows of every sst except the last one.
+    pub rows_per_sst: usize,
+    /// Number of the input rows persisted in the finished ssts.
+    pub rows_written: usize,
+    /// Memcomparable encoded primary key of the last persisted row, used to
+    /// verify the input is the same on resuming.
+    pub last_key: Vec<u8>,
+    /// Infos of the finished ssts, in the order of the `file_ids`.
+    pub finished_ssts: Vec<FinishedSst>,
+}
+
+impl WriteCheckpoint {
+    pub fn new(file_ids: Vec<FileId>, rows_per_sst: usize) -> Self {
+        Self {
+            file_ids,
+            rows_per_sst,
+            ..Default::default()
+        }
+    }
+
+    /// Load the checkpoint, and `None` is returned if it doesn't exist.
+    pub async fn load(store: &ObjectStoreRef, path: &Path) -> Result<Option<Self>> {
+        let payload = match store.get(path).await {
+            Ok(res) => res
+                .bytes()
+                .await
+                .box_err()
+                .with_context(|| LoadCheckpoint {
+                    path: path.to_string(),
+                })?,
+            Err(object_store::ObjectStoreError::NotFound { .. }) => return Ok(None),
+            Err(e) => {
+                return Err(e).box_err().with_context(|| LoadCheckpoint {
+                    path: path.to_string(),
+                })
+            }
+        };
+
+        serde_json::from_slice(&payload)
+            .box_err()
+            .with_context(|| LoadCheckpoint {
+                path: path.to_string(),
+            })
+            .map(Some)
+    }
+
+    /// Persist the checkpoint by overwriting the old one.
+    pub async fn save(&self, store: &ObjectStoreRef, path: &Path) -> Result<()> {
+        let payload = serde_json::to_vec(self)
+            .box_err()
+            .with_context(|| SaveCheckpoint {
+                path: path.to_string(),
+            })?;
+        // The atomic write is ensured by the [`ObjectStore`] implementation.
+        store
+            .put(path, payload.into())
+            .await
+            .box_err()
+            .with_context(|| SaveCheckpoint {
+                path: path.to_string(),
+            })?;
+
+        Ok(())
+    }
+
+    /// Delete the checkpoint, and it is ok if it doesn't exist.
+    pub async fn delete(store: &ObjectStoreRef, path: &Path) -> Result<()> {
+        match store.delete(path).await {
+            Ok(()) | Err(object_store::ObjectStoreError::NotFound { .. }) => Ok(()),
+            Err(e) => Err(e).box_err().with_context(|| SaveCheckpoint {
+                path: path.to_string(),
+            }),
+        }
+    }
+}
+
+/// The writer splitting the sorted input by key range into multiple ssts like
+/// the [ParallelSstWriter](crate::sst::parallel_writer::ParallelSstWriter),
+/// but the ssts are written one by one and a checkpoint is persisted after
+/// every sst is finished.
+///
+/// On resuming, the rows persisted by the checkpoint are skipped from the
+/// input, which must be the same sorted input as the failed writing.
+pub struct ResumableSstWriter<'a> {
+    factory: &'a FactoryRef,
+    options: &'a SstWriteOptions,
+    store_picker: &'a ObjectStorePickerRef,
+    level: Level,
+    /// Paths of the output ssts, in the order of the key ranges.
+    paths: &'a [Path],
+    /// Number of rows of every sst except the last one.
+    rows_per_sst: usize,
+    checkpoint_path: &'a Path,
+}
+
+impl<'a> ResumableSstWriter<'a> {
+    /// Create the writer splitting the input into at most `paths.len()` ssts.
+    ///
+    /// Panic if the `paths` is empty.
+    pub fn new(
+        factory: &'a FactoryRef,
+        options: &'a SstWriteOptions,
+        store_picker: &'a ObjectStorePickerRef,
+        level: Level,
+        paths: &'a [Path],
+        rows_per_sst: usize,
+        checkpoint_path: &'a Path,
+    ) -> Self {
+        assert!(!paths.is_empty());
+
+        Self {
+            factory,
+            options,
+            store_picker,
+            level,
+            paths,
+            rows_per_sst,
+            checkpoint_path,
+        }
+    }
+
+    /// Write the sorted input into the ssts, resuming from the checkpoint if
+    /// it exists.
+    ///
+    /// The returned infos are in the order of the `paths`, including the ssts
+    /// finished by the previous writing, and the info is `None` if no rows are
+    /// left for the sst. The first sst is always written even if the input is
+    /// empty.
+    ///
+    /// The checkpoint is kept after the writing finishes, and it is up to the
+    /// caller to delete it once the ssts are committed.
+    pub async fn write(
+        &self,
+        request_id: RequestId,
+        meta: &MetaData,
+        mut input: RecordBatchStream,
+    ) -> Result<Vec<Option<SstInfo>>> {
+        let store = self.store_picker.default_store();
+        let mut checkpoint = WriteCheckpoint::load(store, self.checkpoint_path)
+            .await?
+            .unwrap_or_else(|| WriteCheckpoint::new(Vec::new(), self.rows_per_sst));
+        if checkpoint.finished_ssts.len() > self.paths.len() {
+            return CheckpointMismatch {
+                msg: format!(
+                    "too many finished ssts, finished:{}, paths:{}",
+                    checkpoint.finished_ssts.len(),
+                    self.paths.len()
+                ),
+            }
+            .fail();
+        }
+
+        let mut pending = if checkpoint.rows_written > 0 {
+            info!(
+                "Resume sst writing from checkpoint, request_id:{request_id}, path:{}, rows_written:{}, finished_ssts:{}",
+                self.checkpoint_path,
+                checkpoint.rows_written,
+                checkpoint.finished_ssts.len()
+            );
+            skip_written_rows(&mut input, checkpoint.rows_written, &checkpoint.last_key).await?
+        } else {
+            None
+        };
+
+        let mut sst_infos: Vec<_> = checkpoint
+            .finished_ssts
+            .iter()
+            .cloned()
+            .map(|sst| Some(SstInfo::from(sst)))
+            .collect();
+        for idx in sst_infos.len()..self.paths.len() {
+            if pending.is_none() {
+                pending = next_batch(&mut input).await?;
+            }
+            if idx > 0 && pending.is_none() {
+                sst_infos.push(None);
+                continue;
+            }
+
+            let is_last = idx + 1 == self.paths.len();
+            let (tx, rx) = mpsc::channel(MAX_BATCHES_IN_FLIGHT);
+            let mut writer = self
+                .factory
+                .create_writer(
+                    self.options,
+                    &self.paths[idx],
+                    self.store_picker,
+                    self.level,
+                )
+                .await
+                .box_err()
+                .context(CreateWriter)?;
+            let (dispatched, sst_info) = future::join(
+                self.dispatch(&mut input, pending.take(), tx, is_last),
+                writer.write(request_id.clone(), meta, Box::new(rx)),
+            )
+            .await;
+            let sst_info = sst_info?;
+            let (num_rows, last_row) = dispatched;
+
+            if let Some(row) = last_row {
+                checkpoint.last_key = encode_last_key(&row)?;
+            }
+            checkpoint.rows_written += num_rows;
+            checkpoint.finished_ssts.push(FinishedSst::from(&sst_info));
+            checkpoint.save(store, self.checkpoint_path).await?;
+            sst_infos.push(Some(sst_info));
+        }
+
+        Ok(sst_infos)
+    }
+
+    /// Dispatch the input to the writer until it has received `rows_per_sst`
+    /// rows, or all the input if it is the last writer.
+    ///
+    /// Returns the number of the dispatched rows and the last dispatched row.
+    /// The error of the input is passed to the writer to make it fail.
+    async fn dispatch(
+        &self,
+        input: &mut RecordBatchStream,
+        first: Option<FetchedRecordBatch>,
+        mut sender: mpsc::Sender<RecordBatchStreamItem>,
+        is_last: bool,
+    ) -> (usize, Option<FetchedRecordBatch>) {
+        let mut num_rows = 0;
+        let mut last_row = None;
+        let mut next = first.map(Ok);
+        loop {
+            if next.is_none() {
+                if !is_last && num_rows >= self.rows_per_sst {
+                    break;
+                }
+                next = input.next().await;
+            }
+            let Some(item) = next.take() else {
+                break;
+            };
+
+            let is_err = item.is_err();
+            if let Ok(batch) = &item {
+                if !batch.is_empty() {
+                    num_rows += batch.num_rows();
+                    last_row = Some(batch.slice(batch.num_rows() - 1, 1));
+                }
+            }
+            // Failed to send means the writer has failed, and its error will be returned.
+            if sender.send(item).await.is_err() || is_err {
+                break;
+            }
+        }
+
+        // The writer finishes once the sender is dropped.
+        (num_rows, last_row)
+    }
+}
+
+/// Poll the next batch from the input, and the empty batches are skipped.
+async fn next_batch(input: &mut RecordBatchStream) -> Result<Option<FetchedRecordBatch>> {
+    while let Some(batch) = input.next().await {
+        let batch = batch.context(PollRecordBatch)?;
+        if !batch.is_empty() {
+            return Ok(Some(batch));
+        }
+    }
+
+    Ok(None)
+}
+
+/// Skip the `rows_written` rows of the input, and verify the key of the last
+/// skipped row matches the `last_key` in the checkpoint.
+///
+/// Returns the rest rows of the batch containing the last skipped row.
+async fn skip_written_rows(
+    input: &mut RecordBatchStream,
+    rows_written: usize,
+    last_key: &[u8],
+) -> Result<Option<FetchedRecordBatch>> {
+    let mut skipped = 0;
+    while let Some(batch) = next_batch(input).await? {
+        let num_rows = batch.num_rows();
+        if skipped + num_rows < rows_written {
+            skipped += num_rows;
+            continue;
+        }
+
+        let offset = rows_written - skipped;
+        let key = encode_last_key(&batch.slice(0, offset))?;
+        if key != last_key {
+            return CheckpointMismatch {
+                msg: format!("last key is changed, rows_written:{rows_written}"),
+            }
+            .fail();
+        }
+
+        return Ok((offset < num_rows).then(|| batch.slice(offset, num_rows - offset)));
+    }
+
+    CheckpointMismatch {
+        msg: format!("input has fewer rows than written, rows_written:{rows_written}"),
+    }
+    .fail()
+}
+
+/// Encode the primary key of the last row of the batch.
+///
+/// Panic if the batch is empty.
+fn encode_last_key(batch: &FetchedRecordBatch) -> Result<Vec<u8>> {
+    let row_idx = batch.num_rows() - 1;
+    let mut key = Vec::new();
+    let encoder = MemComparable;
+    for idx in batch.primary_key_indexes().unwrap_or_default() {
+        let datum = batch.column(*idx).datum(row_idx);
+        encoder
+            .encode(&mut key, &datum)
+            .box_err()
+            .context(EncodeKey)?;
+    }
+
+    Ok(key)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use common_types::tests::{build_row_for_dictionary, build_schema_with_dictionary};
+    use futures::stream;
+    use object_store::local_file;
+    use tempfile::tempdir;
+
+    use super::*;
+    use crate::{
+        row_iter::tests::build_fetched_record_batch_with_key,
+        sst::{factory::FactoryImpl, writer::SstExtensions},
+        table_options::{self, StorageFormatHint},
+    };
+
+    /// Build 5 batches with 2 rows in every batch, and an error is appended
+    /// after the first `fail_after` batches if it is set.
+    fn build_input(meta: &MetaData, fail_after: Option<usize>) -> RecordBatchStream {
+        let mut batches: Vec<_> = [b"a", b"c", b"e", b"g", b"i"]
+            .into_iter()
+            .map(|key| {
+                let next_key = [key[0] + 1];
+                let rows = vec![
+                    build_row_for_dictionary(key, 100, 1.0, "v", 1, 1, None, "tag"),
+                    build_row_for_dictionary(&next_key, 100, 1.0, "v", 1, 1, None, "tag"),
+                ];
+                Ok(build_fetched_record_batch_with_key(
+                    meta.schema.clone(),
+                    rows,
+                ))
+            })
+            .collect();
+        if let Some(n) = fail_after {
+            batches.truncate(n);
+            batches.push(Err("input failed".into()));
+        }
+
+        Box::new(stream::iter(batches))
+    }
+
+    #[test]
+    fn test_resumable_write() {
+        let runtime = Arc::new(runtime::Builder::default().enable_all().build().unwrap());
+        runtime.block_on(async {
+            let factory: FactoryRef = Arc::new(FactoryImpl);
+            let options = SstWriteOptions {
+                storage_format_hint: StorageFormatHint::Auto,
+                num_rows_per_row_group: 2,
+                compression: table_options::Compression::Uncompressed,
+                max_buffer_size: 0,
+                column_stats: Default::default(),
+            };
+            let dir = tempdir().unwrap();
+            let root = dir.as_ref().to_string_lossy().to_string();
+            let store: ObjectStoreRef = Arc::new(local_file::try_new_with_default(root).unwrap());
+            let store_picker: ObjectStorePickerRef = Arc::new(store.clone());
+            let paths: Vec<_> = (0..3).map(|i| Path::from(format!("{i}.sst"))).collect();
+            let checkpoint_path = Path::from("compaction.checkpoint");
+
+            let meta = MetaData {
+                min_key: Bytes::from_static(b"a"),
+                max_key: Bytes::from_static(b"j"),
+                time_range: TimeRange::new_unchecked(Timestamp::new(100), Timestamp::new(101)),
+                max_sequence: 200,
+                schema: build_schema_with_dictionary(),
+                extensions: SstExtensions::new(),
+            };
+            let writer = ResumableSstWriter::new(
+                &factory,
+                &options,
+                &store_picker,
+                Level::MAX,
+                &paths,
+                4,
+                &checkpoint_path,
+            );
+
+            // The first sst is finished before the input fails.
+            let res = writer
+                .write(RequestId::next_id(), &meta, build_input(&meta, Some(3)))
+                .await;
+            assert!(res.is_err());
+            let checkpoint = WriteCheckpoint::load(&store, &checkpoint_path)
+                .await
+                .unwrap()
+                .unwrap();
+            assert_eq!(4, checkpoint.rows_written);
+            assert_eq!(1, checkpoint.finished_ssts.len());
+
+            // Resume from the checkpoint.
+            let sst_infos = writer
+                .write(RequestId::next_id(), &meta, build_input(&meta, None))
+                .await
+                .unwrap();
+            let row_nums: Vec<_> = sst_infos
+                .iter()
+                .map(|info| info.as_ref().map(|v| v.row_num))
+                .collect();
+            assert_eq!(vec![Some(4), Some(4), Some(2)], row_nums);
+            let checkpoint = WriteCheckpoint::load(&store, &checkpoint_path)
+                .await
+                .unwrap()
+                .unwrap();
+            assert_eq!(10, checkpoint.rows_written);
+
+            // The input mismatching the checkpoint can't be resumed.
+            let mut checkpoint = WriteCheckpoint::new(Vec::new(), 4);
+            checkpoint.rows_written = 4;
+            checkpoint.last_key = b"mismatched".to_vec();
+            checkpoint
+                .finished_ssts
+                .push(FinishedSst::from(&sst_infos[0].clone().unwrap()));
+            checkpoint.save(&store, &checkpoint_path).await.unwrap();
+            let res = writer
+                .write(RequestId::next_id(), &meta, build_input(&meta, None))
+                .await;
+            assert!(res.is_err());
+
+            WriteCheckpoint::delete(&store, &checkpoint_path)
+                .await
+                .unwrap();
+            assert!(WriteCheckpoint::load(&store, &checkpoint_path)
+                .await
+                .unwrap()
+                .is_none());
+        });
+    }
+}
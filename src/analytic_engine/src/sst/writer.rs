@@ -17,7 +17,7 @@
 
 //! Sst writer trait definition
 
-use std::cmp;
+use std::{cmp, collections::BTreeMap};
 
 use async_trait::async_trait;
 use bytes_ext::Bytes;
@@ -26,7 +26,9 @@ use common_types::{
     SequenceNumber,
 };
 use futures::Stream;
-use generic_error::{BoxError, GenericError};
+use generic_error::{BoxError, GenericError, GenericResult};
+use horaedbproto::compaction_service;
+use serde::{Deserialize, Serialize};
 use snafu::{OptionExt, ResultExt};
 
 use crate::table_options::StorageFormat;
@@ -112,6 +114,26 @@ pub mod error {
 
         #[snafu(display("Failed to convert schema, err:{}", source))]
         ConvertSchema { source: GenericError },
+
+        #[snafu(display("Failed to create sst writer, err:{}", source))]
+        CreateWriter { source: GenericError },
+
+        #[snafu(display("Failed to build index, name:{name}, err:{source}"))]
+        BuildIndex { name: String, source: GenericError },
+
+        #[snafu(display("Failed to load write checkpoint, path:{path}, err:{source}"))]
+        LoadCheckpoint { path: String, source: GenericError },
+
+        #[snafu(display("Failed to save write checkpoint, path:{path}, err:{source}"))]
+        SaveCheckpoint { path: String, source: GenericError },
+
+        #[snafu(display(
+            "Write checkpoint mismatches the input, msg:{msg}.\nBacktrace:\n{backtrace}"
+        ))]
+        CheckpointMismatch { msg: String, backtrace: Backtrace },
+
+        #[snafu(display("Failed to encode primary key, err:{source}"))]
+        EncodeKey { source: GenericError },
     }
 
     define_result!(Error);
@@ -123,6 +145,84 @@ pub type RecordBatchStreamItem = std::result::Result<FetchedRecordBatch, Generic
 // TODO(yingwen): SstReader also has a RecordBatchStream, can we use same type?
 pub type RecordBatchStream = Box<dyn Stream<Item = RecordBatchStreamItem> + Send + Unpin>;
 
+/// Statistics of one column gathered during writing the sst.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SstColumnStats {
+    pub null_count: usize,
+    /// Approximate number of the distinct non-null values.
+    pub distinct_count: usize,
+}
+
+/// Pb message of [SstColumnStats].
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct SstColumnStatsPb {
+    #[prost(uint64, tag = "1")]
+    pub null_count: u64,
+    #[prost(uint64, tag = "2")]
+    pub distinct_count: u64,
+}
+
+impl From<&SstColumnStats> for SstColumnStatsPb {
+    fn from(value: &SstColumnStats) -> Self {
+        Self {
+            null_count: value.null_count as u64,
+            distinct_count: value.distinct_count as u64,
+        }
+    }
+}
+
+impl From<SstColumnStatsPb> for SstColumnStats {
+    fn from(value: SstColumnStatsPb) -> Self {
+        Self {
+            null_count: value.null_count as usize,
+            distinct_count: value.distinct_count as usize,
+        }
+    }
+}
+
+/// Pb message of the attributes of [SstInfo] which are absent from
+/// [compaction_service::SstInfo], it's sent along with the latter by the
+/// compaction service.
+///
+/// New fields must take new tags, so that the nodes of different versions are
+/// able to decode the messages of each other.
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct SstInfoExt {
+    #[prost(message, repeated, tag = "1")]
+    pub column_stats: Vec<SstColumnStatsPb>,
+    #[prost(string, repeated, tag = "2")]
+    pub index_paths: Vec<String>,
+    #[prost(btree_map = "string, bytes", tag = "3")]
+    pub extensions: BTreeMap<String, Vec<u8>>,
+}
+
+/// Pb message of the attributes of [MetaData] which are absent from
+/// [compaction_service::MetaData], see [SstInfoExt].
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct MetaDataExt {
+    #[prost(btree_map = "string, bytes", tag = "1")]
+    pub extensions: BTreeMap<String, Vec<u8>>,
+}
+
+/// Extended attributes of an sst keyed by their names, so new per-sst
+/// attributes can be attached without changing the definitions of the sst
+/// infos.
+pub type SstExtensions = BTreeMap<String, Bytes>;
+
+fn extensions_into_pb(extensions: SstExtensions) -> BTreeMap<String, Vec<u8>> {
+    extensions
+        .into_iter()
+        .map(|(name, value)| (name, Vec::from(value)))
+        .collect()
+}
+
+fn extensions_from_pb(extensions: BTreeMap<String, Vec<u8>>) -> SstExtensions {
+    extensions
+        .into_iter()
+        .map(|(name, value)| (name, Bytes::from(value)))
+        .collect()
+}
+
 #[derive(Debug, Clone)]
 pub struct SstInfo {
     pub file_size: usize,
@@ -131,12 +231,40 @@ pub struct SstInfo {
     pub meta_path: String,
     /// Real time range, not aligned to segment.
     pub time_range: TimeRange,
+    /// Stats of every column, in the same order as the columns of schema.
+    ///
+    /// Empty if the stats are unknown.
+    pub column_stats: Vec<SstColumnStats>,
+    /// Paths of the auxiliary index files written along with the sst.
+    pub index_paths: Vec<String>,
+    /// Extended attributes of the sst.
+    pub extensions: SstExtensions,
+}
+
+impl SstInfo {
+    /// All the files written along with the sst, which should be purged
+    /// together with the sst.
+    pub fn associated_files(&self) -> Vec<String> {
+        let mut files = Vec::with_capacity(1 + self.index_paths.len());
+        files.push(self.meta_path.clone());
+        files.extend(self.index_paths.iter().cloned());
+        files
+    }
+}
+
+/// The sst info sent by the nodes unaware of [SstInfoExt].
+impl TryFrom<compaction_service::SstInfo> for SstInfo {
+    type Error = Error;
+
+    fn try_from(value: compaction_service::SstInfo) -> Result<Self> {
+        Self::try_from((value, SstInfoExt::default()))
+    }
 }
 
-impl TryFrom<horaedbproto::compaction_service::SstInfo> for SstInfo {
+impl TryFrom<(compaction_service::SstInfo, SstInfoExt)> for SstInfo {
     type Error = Error;
 
-    fn try_from(value: horaedbproto::compaction_service::SstInfo) -> Result<Self> {
+    fn try_from((value, ext): (compaction_service::SstInfo, SstInfoExt)) -> Result<Self> {
         let storage_format = value
             .storage_format
             .try_into()
@@ -155,19 +283,29 @@ impl TryFrom<horaedbproto::compaction_service::SstInfo> for SstInfo {
             storage_format,
             meta_path: value.meta_path,
             time_range,
+            column_stats: ext.column_stats.into_iter().map(Into::into).collect(),
+            index_paths: ext.index_paths,
+            extensions: extensions_from_pb(ext.extensions),
         })
     }
 }
 
-impl From<SstInfo> for horaedbproto::compaction_service::SstInfo {
+impl From<SstInfo> for (compaction_service::SstInfo, SstInfoExt) {
     fn from(value: SstInfo) -> Self {
-        Self {
+        let ext = SstInfoExt {
+            column_stats: value.column_stats.iter().map(Into::into).collect(),
+            index_paths: value.index_paths,
+            extensions: extensions_into_pb(value.extensions),
+        };
+        let info = compaction_service::SstInfo {
             file_size: value.file_size as u64,
             row_num: value.row_num as u64,
             storage_format: value.storage_format.into(),
             meta_path: value.meta_path,
             time_range: Some(value.time_range.into()),
-        }
+        };
+
+        (info, ext)
     }
 }
 
@@ -183,12 +321,23 @@ pub struct MetaData {
     pub max_sequence: SequenceNumber,
     /// The schema of the sst.
     pub schema: Schema,
+    /// Extended attributes of the sst.
+    pub extensions: SstExtensions,
 }
 
-impl TryFrom<horaedbproto::compaction_service::MetaData> for MetaData {
+/// The sst meta data sent by the nodes unaware of [MetaDataExt].
+impl TryFrom<compaction_service::MetaData> for MetaData {
     type Error = Error;
 
-    fn try_from(meta: horaedbproto::compaction_service::MetaData) -> Result<Self> {
+    fn try_from(meta: compaction_service::MetaData) -> Result<Self> {
+        Self::try_from((meta, MetaDataExt::default()))
+    }
+}
+
+impl TryFrom<(compaction_service::MetaData, MetaDataExt)> for MetaData {
+    type Error = Error;
+
+    fn try_from((meta, ext): (compaction_service::MetaData, MetaDataExt)) -> Result<Self> {
         let time_range = meta
             .time_range
             .context(EmptyTimeRange)?
@@ -208,28 +357,53 @@ impl TryFrom<horaedbproto::compaction_service::MetaData> for MetaData {
             time_range,
             max_sequence: meta.max_sequence,
             schema,
+            extensions: extensions_from_pb(ext.extensions),
         })
     }
 }
 
-impl From<MetaData> for horaedbproto::compaction_service::MetaData {
+impl From<MetaData> for (compaction_service::MetaData, MetaDataExt) {
     fn from(meta: MetaData) -> Self {
-        Self {
-            min_key: meta.min_key.to_vec(),
-            max_key: meta.max_key.to_vec(),
+        let ext = MetaDataExt {
+            extensions: extensions_into_pb(meta.extensions),
+        };
+        let meta = compaction_service::MetaData {
+            // No copy if the keys are not shared.
+            min_key: Vec::from(meta.min_key),
+            max_key: Vec::from(meta.max_key),
             max_sequence: meta.max_sequence,
             time_range: Some(meta.time_range.into()),
             schema: Some((&meta.schema).into()),
-        }
+        };
+
+        (meta, ext)
     }
 }
 
+/// Builder of an auxiliary index of the sst, e.g. an inverted index on the
+/// tag columns, which is persisted as a separate file along with the sst.
+pub trait IndexBuilder: Send + std::fmt::Debug {
+    /// Name of the index, which is a part of the index file name, so it should
+    /// be unique among the indexes of one sst.
+    fn name(&self) -> &str;
+
+    /// Update the index with a record batch written into the sst.
+    fn update(&mut self, record_batch: &FetchedRecordBatch) -> GenericResult<()>;
+
+    /// Finish building and return the encoded index.
+    fn finish(&mut self) -> GenericResult<Bytes>;
+}
+
 /// The writer for sst.
 ///
 /// The caller provides a stream of [RecordBatch] and the writer takes
 /// responsibilities for persisting the records.
 #[async_trait]
 pub trait SstWriter {
+    /// Register a builder to emit an auxiliary index file along with the sst,
+    /// and the path of the index file is returned in [SstInfo::index_paths].
+    fn add_index_builder(&mut self, builder: Box<dyn IndexBuilder>);
+
     async fn write(
         &mut self,
         request_id: RequestId,
@@ -241,6 +415,9 @@ pub trait SstWriter {
 impl MetaData {
     /// Merge multiple meta datas into the one.
     ///
+    /// The extensions are attributes of every single sst, so they are not
+    /// merged.
+    ///
     /// Panic if the metas is empty.
     pub fn merge<I>(mut metas: I, schema: Schema) -> Self
     where
@@ -267,6 +444,157 @@ impl MetaData {
             time_range: TimeRange::new(time_range_start, time_range_end).unwrap(),
             max_sequence,
             schema,
+            extensions: SstExtensions::new(),
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use common_types::{tests::build_schema, time::Timestamp};
+    use prost::Message;
+
+    use super::*;
+
+    /// Append a length-delimited field with an unknown tag to the encoded
+    /// message, which simulates a message sent by a newer node.
+    fn append_unknown_field(buf: &mut Vec<u8>) {
+        // Field number 100 with wire type 2 (length-delimited).
+        buf.extend_from_slice(&[0xa2, 0x06]);
+        buf.push(3);
+        buf.extend_from_slice(b"new");
+    }
+
+    fn build_extensions() -> SstExtensions {
+        SstExtensions::from([
+            ("empty".to_string(), Bytes::new()),
+            ("version".to_string(), Bytes::from_static(&[0, 1, 0xff])),
+        ])
+    }
+
+    fn build_sst_info() -> SstInfo {
+        SstInfo {
+            file_size: 1024,
+            row_num: 100,
+            storage_format: StorageFormat::Columnar,
+            meta_path: "1/1/1.sst".to_string(),
+            time_range: TimeRange::new_unchecked(Timestamp::new(100), Timestamp::new(200)),
+            column_stats: vec![
+                SstColumnStats {
+                    null_count: 0,
+                    distinct_count: 100,
+                },
+                SstColumnStats {
+                    null_count: 20,
+                    distinct_count: 3,
+                },
+            ],
+            index_paths: vec!["1/1/1.sst.row_count.index".to_string()],
+            extensions: build_extensions(),
+        }
+    }
+
+    fn build_meta_data() -> MetaData {
+        MetaData {
+            min_key: Bytes::from_static(b"100"),
+            max_key: Bytes::from_static(b"200"),
+            time_range: TimeRange::new_unchecked(Timestamp::new(100), Timestamp::new(200)),
+            max_sequence: 200,
+            schema: build_schema(),
+            extensions: build_extensions(),
+        }
+    }
+
+    #[test]
+    fn test_sst_info_pb_round_trip() {
+        let sst_info = build_sst_info();
+        let (info, ext): (compaction_service::SstInfo, SstInfoExt) = sst_info.clone().into();
+        let info = compaction_service::SstInfo::decode(info.encode_to_vec().as_slice()).unwrap();
+        let ext = SstInfoExt::decode(ext.encode_to_vec().as_slice()).unwrap();
+        let converted = SstInfo::try_from((info, ext)).unwrap();
+
+        assert_eq!(sst_info.file_size, converted.file_size);
+        assert_eq!(sst_info.row_num, converted.row_num);
+        assert_eq!(sst_info.storage_format, converted.storage_format);
+        assert_eq!(sst_info.meta_path, converted.meta_path);
+        assert_eq!(sst_info.time_range, converted.time_range);
+        assert_eq!(sst_info.column_stats, converted.column_stats);
+        assert_eq!(sst_info.index_paths, converted.index_paths);
+        assert_eq!(sst_info.associated_files(), converted.associated_files());
+        assert!(!converted.extensions.is_empty());
+        assert_eq!(sst_info.extensions, converted.extensions);
+    }
+
+    #[test]
+    fn test_sst_info_without_ext() {
+        // The sst info sent by the nodes unaware of the ext.
+        let sst_info = build_sst_info();
+        let (info, _): (compaction_service::SstInfo, SstInfoExt) = sst_info.clone().into();
+        let converted = SstInfo::try_from(info).unwrap();
+
+        assert_eq!(sst_info.meta_path, converted.meta_path);
+        assert!(converted.column_stats.is_empty());
+        assert!(converted.index_paths.is_empty());
+        assert!(converted.extensions.is_empty());
+    }
+
+    #[test]
+    fn test_meta_data_pb_round_trip() {
+        let meta = build_meta_data();
+        let (pb_meta, ext): (compaction_service::MetaData, MetaDataExt) = meta.clone().into();
+        let pb_meta =
+            compaction_service::MetaData::decode(pb_meta.encode_to_vec().as_slice()).unwrap();
+        let ext = MetaDataExt::decode(ext.encode_to_vec().as_slice()).unwrap();
+        let converted = MetaData::try_from((pb_meta, ext)).unwrap();
+
+        assert_eq!(meta.min_key, converted.min_key);
+        assert_eq!(meta.max_key, converted.max_key);
+        assert_eq!(meta.time_range, converted.time_range);
+        assert_eq!(meta.max_sequence, converted.max_sequence);
+        assert_eq!(meta.schema, converted.schema);
+        assert!(!converted.extensions.is_empty());
+        assert_eq!(meta.extensions, converted.extensions);
+    }
+
+    #[test]
+    fn test_tolerate_unknown_fields() {
+        let sst_info = build_sst_info();
+        let (info, _): (compaction_service::SstInfo, SstInfoExt) = sst_info.clone().into();
+        let mut encoded = info.encode_to_vec();
+        append_unknown_field(&mut encoded);
+        let decoded = compaction_service::SstInfo::decode(encoded.as_slice()).unwrap();
+        let converted = SstInfo::try_from(decoded).unwrap();
+        assert_eq!(sst_info.meta_path, converted.meta_path);
+        assert_eq!(sst_info.time_range, converted.time_range);
+
+        let meta = build_meta_data();
+        let (pb_meta, _): (compaction_service::MetaData, MetaDataExt) = meta.clone().into();
+        let mut encoded = pb_meta.encode_to_vec();
+        append_unknown_field(&mut encoded);
+        let decoded = compaction_service::MetaData::decode(encoded.as_slice()).unwrap();
+        let converted = MetaData::try_from(decoded).unwrap();
+        assert_eq!(meta.min_key, converted.min_key);
+        assert_eq!(meta.max_sequence, converted.max_sequence);
+    }
+
+    #[test]
+    fn test_ext_tolerate_unknown_fields() {
+        let sst_info = build_sst_info();
+        let (info, ext): (compaction_service::SstInfo, SstInfoExt) = sst_info.clone().into();
+        let mut encoded = ext.encode_to_vec();
+        append_unknown_field(&mut encoded);
+        let ext = SstInfoExt::decode(encoded.as_slice()).unwrap();
+        let converted = SstInfo::try_from((info, ext)).unwrap();
+        assert_eq!(sst_info.column_stats, converted.column_stats);
+        assert_eq!(sst_info.index_paths, converted.index_paths);
+        assert_eq!(sst_info.extensions, converted.extensions);
+
+        let meta = build_meta_data();
+        let (pb_meta, ext): (compaction_service::MetaData, MetaDataExt) = meta.clone().into();
+        let mut encoded = ext.encode_to_vec();
+        append_unknown_field(&mut encoded);
+        let ext = MetaDataExt::decode(encoded.as_slice()).unwrap();
+        let converted = MetaData::try_from((pb_meta, ext)).unwrap();
+        assert_eq!(meta.extensions, converted.extensions);
+    }
+}
@@ -46,9 +46,10 @@ use macros::define_result;
 use object_store::Path;
 use snafu::{ensure, Backtrace, OptionExt, ResultExt, Snafu};
 use table_engine::table::{SchemaId, TableId};
-use time_ext::ReadableDuration;
+use time_ext::{clock::ClockRef, ReadableDuration};
 
 use crate::{
+    compaction::heat::QueryHeat,
     instance::serial_executor::TableOpSerialExecutor,
     manifest::{
         meta_edit::{AddTableMeta, MetaEdit, MetaEditRequest, MetaUpdate, VersionEditMeta},
@@ -157,6 +158,7 @@ pub struct TableConfig {
     pub metrics_opt: MetricsOptions,
     pub enable_primary_key_sampling: bool,
     pub try_compat_old_layered_memtable_opts: bool,
+    pub clock: ClockRef,
 }
 
 #[derive(Debug, Clone)]
@@ -227,6 +229,9 @@ pub struct TableData {
     /// Not persist, used to determine if this table should flush.
     last_flush_time_ms: AtomicU64,
 
+    /// Clock to get the current time, e.g. for ttl and flush interval
+    clock: ClockRef,
+
     /// Table Status
     status: AtomicTableStatus,
 
@@ -250,6 +255,9 @@ pub struct TableData {
 
     /// The table operation serial_exec
     pub serial_exec: tokio::sync::Mutex<TableOpSerialExecutor>,
+
+    /// Query heat of the cold segments
+    pub query_heat: QueryHeat,
 }
 
 impl fmt::Debug for TableData {
@@ -322,6 +330,7 @@ impl TableData {
             manifest_snapshot_every_n_updates,
             metrics_opt,
             enable_primary_key_sampling,
+            clock,
             ..
         } = config;
 
@@ -382,10 +391,12 @@ impl TableData {
             last_memtable_id: AtomicU64::new(0),
             allocator: IdAllocator::new(0, 0, DEFAULT_ALLOC_STEP),
             last_flush_time_ms: AtomicU64::new(0),
+            clock,
             status: TableStatus::Ok.into(),
             metrics,
             shard_info: TableShardInfo::new(shard_id),
             serial_exec: tokio::sync::Mutex::new(TableOpSerialExecutor::new(id)),
+            query_heat: QueryHeat::default(),
             manifest_updates: AtomicUsize::new(0),
             manifest_snapshot_every_n_updates,
             enable_primary_key_sampling,
@@ -411,6 +422,7 @@ impl TableData {
             metrics_opt,
             enable_primary_key_sampling,
             try_compat_old_layered_memtable_opts,
+            clock,
         } = config;
 
         let memtable_factory: MemTableFactoryRef = match add_meta.opts.memtable_type {
@@ -481,10 +493,12 @@ impl TableData {
             last_memtable_id: AtomicU64::new(0),
             allocator,
             last_flush_time_ms: AtomicU64::new(0),
+            clock,
             status: TableStatus::Ok.into(),
             metrics,
             shard_info: TableShardInfo::new(shard_id),
             serial_exec: tokio::sync::Mutex::new(TableOpSerialExecutor::new(add_meta.table_id)),
+            query_heat: QueryHeat::default(),
             manifest_updates: AtomicUsize::new(0),
             manifest_snapshot_every_n_updates,
             enable_primary_key_sampling,
@@ -779,7 +793,18 @@ impl TableData {
     }
 
     pub fn is_expired(&self, timestamp: Timestamp) -> bool {
-        self.table_options().is_expired(timestamp)
+        self.table_options().is_expired(timestamp, self.now())
+    }
+
+    #[inline]
+    pub fn clock(&self) -> &ClockRef {
+        &self.clock
+    }
+
+    /// Current time of the table's clock.
+    #[inline]
+    pub fn now(&self) -> Timestamp {
+        Timestamp::new(self.clock.now_ms() as i64)
     }
 
     pub fn table_location(&self) -> TableLocation {
@@ -917,7 +942,10 @@ pub mod tests {
         engine::{CreateTableParams, CreateTableRequest, TableState},
         table::SchemaId,
     };
-    use time_ext::ReadableDuration;
+    use time_ext::{
+        clock::{LogicalClock, SystemClock},
+        ReadableDuration,
+    };
 
     use super::*;
     use crate::{
@@ -974,6 +1002,7 @@ pub mod tests {
         table_name: String,
         shard_id: ShardId,
         manifest_snapshot_every_n_updates: NonZeroUsize,
+        clock: ClockRef,
     }
 
     impl TableDataMocker {
@@ -1000,6 +1029,11 @@ pub mod tests {
             self
         }
 
+        pub fn clock(mut self, clock: ClockRef) -> Self {
+            self.clock = clock;
+            self
+        }
+
         pub fn build(self) -> TableData {
             let space_id = DEFAULT_SPACE_ID;
             let schema_id = DEFAULT_SCHEMA_ID;
@@ -1048,6 +1082,7 @@ pub mod tests {
                     metrics_opt: MetricsOptions::default(),
                     enable_primary_key_sampling: false,
                     try_compat_old_layered_memtable_opts: false,
+                    clock: self.clock,
                 },
                 &purger,
                 mem_size_options,
@@ -1063,6 +1098,7 @@ pub mod tests {
                 table_name: "mocked_table".to_string(),
                 shard_id: DEFAULT_SHARD_ID,
                 manifest_snapshot_every_n_updates: NonZeroUsize::new(usize::MAX).unwrap(),
+                clock: SystemClock::new_ref(),
             }
         }
     }
@@ -1127,6 +1163,24 @@ pub mod tests {
         assert_eq!(time_range, mem_state.aligned_time_range);
     }
 
+    #[test]
+    fn test_is_expired() {
+        let clock = Arc::new(LogicalClock::new(10_000));
+        let table_data = TableDataMocker::default().clock(clock.clone()).build();
+        let mut table_opts = (*table_data.table_options()).clone();
+        table_opts.enable_ttl = true;
+        table_opts.ttl = ReadableDuration::secs(5);
+        table_data.set_table_options(table_opts);
+
+        assert_eq!(Timestamp::new(10_000), table_data.now());
+        assert!(!table_data.is_expired(Timestamp::new(5_000)));
+        assert!(table_data.is_expired(Timestamp::new(4_999)));
+
+        clock.advance(Duration::from_secs(1));
+        assert!(table_data.is_expired(Timestamp::new(5_000)));
+        assert!(!table_data.is_expired(Timestamp::new(6_000)));
+    }
+
     #[test]
     fn test_compute_mutable_limit() {
         // Build the cases for compute_mutable_limit.
@@ -31,11 +31,15 @@ use prometheus::{
     exponential_buckets,
     local::{LocalHistogram, LocalHistogramTimer},
     register_histogram, register_histogram_vec, register_int_counter, register_int_counter_vec,
-    Histogram, HistogramTimer, HistogramVec, IntCounter, IntCounterVec,
+    register_int_gauge_vec, Histogram, HistogramTimer, HistogramVec, IntCounter, IntCounterVec,
+    IntGaugeVec,
 };
 use table_engine::{partition::maybe_extract_partitioned_table_name, table::TableStats};
 
-use crate::{sst::metrics::MaybeTableLevelMetrics as SstMaybeTableLevelMetrics, MetricsOptions};
+use crate::{
+    sst::{manager::LevelStats, metrics::MaybeTableLevelMetrics as SstMaybeTableLevelMetrics},
+    MetricsOptions,
+};
 
 const KB: f64 = 1024.0;
 const DEFAULT_METRICS_KEY: &str = "total";
@@ -68,6 +72,22 @@ lazy_static! {
     .unwrap();
     // End of counters.
 
+    // Gauges:
+    static ref TABLE_LEVEL_SST_NUM_GAUGE: IntGaugeVec = register_int_gauge_vec!(
+        "table_level_sst_num",
+        "Number of ssts at every level of the table",
+        &["shard_id", "table", "level"]
+    )
+    .unwrap();
+
+    static ref TABLE_LEVEL_SST_SIZE_GAUGE: IntGaugeVec = register_int_gauge_vec!(
+        "table_level_sst_size",
+        "Total size of ssts at every level of the table in bytes",
+        &["shard_id", "table", "level"]
+    )
+    .unwrap();
+    // End of gauges.
+
     // Histograms:
     static ref TABLE_WRITE_BATCH_HISTOGRAM: Histogram = register_histogram!(
         "table_write_batch_size",
@@ -409,6 +429,27 @@ impl Metrics {
         TABLE_COMPACTION_DURATION_HISTOGRAM.start_timer()
     }
 
+    /// Set the per-level sst gauges of the table.
+    ///
+    /// Only works with table level metrics enabled, since the stats of
+    /// different tables can't be summed into the `total` gauges.
+    pub fn on_level_stats(&self, level_stats: &[LevelStats]) {
+        if self.maybe_table_name == DEFAULT_METRICS_KEY {
+            return;
+        }
+
+        for stats in level_stats {
+            let level = stats.level.to_string();
+            let labels = [self.shard_id_label.as_str(), &self.maybe_table_name, &level];
+            TABLE_LEVEL_SST_NUM_GAUGE
+                .with_label_values(&labels)
+                .set(stats.num_ssts as i64);
+            TABLE_LEVEL_SST_SIZE_GAUGE
+                .with_label_values(&labels)
+                .set(stats.total_size as i64);
+        }
+    }
+
     #[inline]
     pub fn compaction_observe_duration(&self, duration: Duration) {
         TABLE_COMPACTION_DURATION_HISTOGRAM.observe(duration.as_secs_f64());
@@ -40,10 +40,10 @@ use table_engine::{
     predicate::PredicateBuilder,
     stream::{PartitionedStreams, SendableRecordBatchStream},
     table::{
-        AlterOptions, AlterSchema, AlterSchemaRequest, Compact, Flush, FlushRequest, Get,
-        GetInvalidPrimaryKey, GetNullPrimaryKey, GetRequest, MergeWrite, ReadOptions, ReadRequest,
-        Result, Scan, Table, TableId, TableStats, TooManyPendingWrites, WaitForPendingWrites,
-        Write, WriteRequest,
+        AlterOptions, AlterSchema, AlterSchemaRequest, Compact, Durability, Flush, FlushRequest,
+        Get, GetInvalidPrimaryKey, GetNullPrimaryKey, GetRequest, MergeWrite, ReadOptions,
+        ReadRequest, Result, Scan, Table, TableId, TableStats, TimeRangeStats,
+        TooManyPendingWrites, WaitForPendingWrites, Write, WriteRequest,
     },
     ANALYTIC_ENGINE_TYPE,
 };
@@ -266,9 +266,13 @@ fn merge_pending_write_requests(
         rows
     };
 
+    let durability = last_req.durability;
     let schema = last_req.row_group.into_schema();
     let row_group = RowGroup::new_unchecked(schema, total_rows);
-    WriteRequest { row_group }
+    WriteRequest {
+        row_group,
+        durability,
+    }
 }
 
 impl TableImpl {
@@ -421,7 +425,10 @@ impl TableImpl {
 
     #[inline]
     fn should_queue_write_request(&self, request: &WriteRequest) -> bool {
-        request.row_group.num_rows() < self.instance.max_rows_in_write_queue
+        // Only the writes with the default durability are merged, so that the merged
+        // write shares the same durability.
+        request.durability == Durability::WalBuffered
+            && request.row_group.num_rows() < self.instance.max_rows_in_write_queue
     }
 }
 
@@ -467,6 +474,23 @@ impl Table for TableImpl {
         self.table_data.metrics.table_stats()
     }
 
+    fn time_range_stats(&self, time_range: TimeRange) -> Option<TimeRangeStats> {
+        // The duplicated rows are merged during reading, so the row number in the
+        // metadata can't be used.
+        if self.table_data.table_options().need_dedup() {
+            return None;
+        }
+
+        // Holding the `serial_exec` blocks the writes to the memtables, so the row
+        // numbers and time ranges of the memtables are consistent with each other.
+        // Fall back to the scan instead of waiting if a write is in progress.
+        let _serial_exec = self.table_data.serial_exec.try_lock().ok()?;
+        self.table_data
+            .current_version()
+            .pick_read_view(TimeRange::min_to_max())
+            .time_range_stats(time_range, self.table_data.schema().num_columns())
+    }
+
     fn support_pushdown(&self, read_schema: &Schema, col_names: &[String]) -> bool {
         let need_dedup = self.table_data.table_options().need_dedup();
 
@@ -481,17 +505,33 @@ impl Table for TableImpl {
         }
 
         let mut serial_exec = self.table_data.serial_exec.lock().await;
-        let mut writer = Writer::new(
-            self.instance.clone(),
-            self.space.clone(),
-            self.table_data.clone(),
-            &mut serial_exec,
-        );
-        writer
-            .write(request)
-            .await
-            .box_err()
-            .context(Write { table: self.name() })
+        let durability = request.durability;
+        let num_rows = {
+            let mut writer = Writer::new(
+                self.instance.clone(),
+                self.space.clone(),
+                self.table_data.clone(),
+                &mut serial_exec,
+            );
+            writer
+                .write(request)
+                .await
+                .box_err()
+                .context(Write { table: self.name() })?
+        };
+        drop(serial_exec);
+
+        if durability == Durability::ObjectStore {
+            // The write is acknowledged after the memtables are flushed into the object
+            // store.
+            self.instance
+                .manual_flush_table(&self.table_data, FlushRequest { sync: true })
+                .await
+                .box_err()
+                .context(Write { table: self.name() })?;
+        }
+
+        Ok(num_rows)
     }
 
     async fn read(&self, mut request: ReadRequest) -> Result<SendableRecordBatchStream> {
@@ -681,7 +721,10 @@ mod tests {
         }
         let rows = row_util::new_rows_6(&schema_rows);
         let row_group = RowGroup::try_new(schema, rows).unwrap();
-        WriteRequest { row_group }
+        WriteRequest {
+            row_group,
+            durability: Durability::default(),
+        }
     }
 
     #[test]
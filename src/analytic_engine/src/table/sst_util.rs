@@ -26,6 +26,8 @@ use crate::{space::SpaceId, sst::manager::FileId};
 
 const SST_FILE_SUFFIX: &str = "sst";
 const SST_CUSTOM_METADATA_FILE_SUFFIX: &str = "metadata";
+const SST_INDEX_FILE_SUFFIX: &str = "index";
+const COMPACTION_CHECKPOINT_FILE_SUFFIX: &str = "checkpoint";
 
 #[inline]
 /// Generate the sst file name.
@@ -45,3 +47,27 @@ pub fn new_sst_file_path(space_id: SpaceId, table_id: TableId, file_id: FileId)
 pub fn new_metadata_path(sst_file_path: &str) -> String {
     format!("{sst_file_path}.{SST_CUSTOM_METADATA_FILE_SUFFIX}")
 }
+
+/// Convert sst_file_path into the path of the auxiliary index named `name`
+pub fn new_index_path(sst_file_path: &str, name: &str) -> String {
+    format!("{sst_file_path}.{name}.{SST_INDEX_FILE_SUFFIX}")
+}
+
+/// Generate the path of the write checkpoint of the compaction, which is
+/// identified by its input files.
+pub fn new_compaction_checkpoint_path(
+    space_id: SpaceId,
+    table_id: TableId,
+    input_file_ids: &[FileId],
+) -> Path {
+    let mut file_ids = input_file_ids.to_vec();
+    file_ids.sort_unstable();
+    let bytes: Vec<_> = file_ids.iter().flat_map(|id| id.to_le_bytes()).collect();
+    let hash = hash_ext::hash64(bytes.as_slice());
+
+    Path::from_iter([
+        space_id.to_string(),
+        table_id.to_string(),
+        format!("compaction-{hash}.{COMPACTION_CHECKPOINT_FILE_SUFFIX}"),
+    ])
+}
@@ -35,6 +35,7 @@ use common_types::{
 use macros::define_result;
 use sampling_cache::SamplingCachedUsize;
 use snafu::{ensure, Backtrace, ResultExt, Snafu};
+use table_engine::table::{ColumnStats, TimeRangeStats};
 use time_ext::ReadableDuration;
 
 use crate::{
@@ -45,8 +46,9 @@ use crate::{
     memtable::{self, key::KeySequence, MemTableRef, PutContext},
     sampler::{DefaultSampler, PrimaryKeySampler, SamplerRef, MAX_SUGGEST_PRIMARY_KEY_NUM},
     sst::{
-        file::{FileHandle, FilePurgeQueue, SST_LEVEL_NUM},
-        manager::{FileId, LevelsController},
+        file::{FileHandle, FilePurgeQueue, Level, SST_LEVEL_NUM},
+        manager::{FileId, LevelStats, LevelsController},
+        writer::SstColumnStats,
     },
     table::{
         data::{MemTableId, DEFAULT_ALLOC_STEP},
@@ -575,6 +577,91 @@ impl ReadView {
     pub fn contains_sampling(&self) -> bool {
         self.sampling_mem.is_some()
     }
+
+    /// Collect the statistics of the rows in the `time_range` from the
+    /// metadata of the memtables and ssts in this view.
+    ///
+    /// Returns `None` if any memtable or sst partially overlaps with the
+    /// `time_range`, or the row number of a memtable is unknown. The stats of
+    /// the `num_columns` columns are only known if all the rows are in the
+    /// ssts with column stats.
+    pub fn time_range_stats(
+        &self,
+        time_range: TimeRange,
+        num_columns: usize,
+    ) -> Option<TimeRangeStats> {
+        let memtables = self
+            .sampling_mem
+            .iter()
+            .map(|v| &v.mem)
+            .chain(self.memtables.iter().map(|v| &v.mem))
+            .map(|mem| (mem.time_range(), mem.metrics().row_count, None));
+        let ssts = self.leveled_ssts.iter().flatten().map(|file| {
+            (
+                Some(file.time_range()),
+                file.row_num() as usize,
+                Some(file.column_stats()),
+            )
+        });
+
+        let mut stats = TimeRangeStats {
+            column_stats: Some(vec![ColumnStats::default(); num_columns]),
+            ..Default::default()
+        };
+        for (source_time_range, num_rows, source_column_stats) in memtables.chain(ssts) {
+            // No rows in this memtable.
+            let Some(source_time_range) = source_time_range else {
+                continue;
+            };
+            if !source_time_range.intersect_with(time_range) {
+                continue;
+            }
+            if time_range.intersected_range(source_time_range) != Some(source_time_range) {
+                return None;
+            }
+            // Some kinds of memtables don't count their rows.
+            if num_rows == 0 {
+                return None;
+            }
+
+            stats.num_rows += num_rows;
+            // The column stats of the rows in the memtables are unknown.
+            stats.column_stats = match (stats.column_stats.take(), source_column_stats) {
+                (Some(mut column_stats), Some(source_column_stats))
+                    if !source_column_stats.is_empty() =>
+                {
+                    merge_column_stats(&mut column_stats, source_column_stats, num_rows);
+                    Some(column_stats)
+                }
+                _ => None,
+            };
+            stats.time_range = Some(match stats.time_range {
+                Some(v) => v.merge_range(source_time_range),
+                None => source_time_range,
+            });
+        }
+
+        Some(stats)
+    }
+}
+
+/// Merge the column stats of a sst with `num_rows` rows into `column_stats`.
+fn merge_column_stats(
+    column_stats: &mut [ColumnStats],
+    sst_column_stats: &[SstColumnStats],
+    num_rows: usize,
+) {
+    for (col_idx, stats) in column_stats.iter_mut().enumerate() {
+        match sst_column_stats.get(col_idx) {
+            Some(sst_stats) => {
+                stats.null_count += sst_stats.null_count;
+                // The distinct values may be shared between ssts.
+                stats.distinct_count = stats.distinct_count.max(sst_stats.distinct_count);
+            }
+            // The column added after writing the sst is all nulls in it.
+            None => stats.null_count += num_rows,
+        }
+    }
 }
 
 /// Data of TableVersion
@@ -841,6 +928,12 @@ impl TableVersion {
         picker.pick_compaction(picker_ctx, &mut inner.levels_controller)
     }
 
+    pub fn latest_sst(&self, level: Level) -> Option<FileHandle> {
+        let inner = self.inner.read().unwrap();
+
+        inner.levels_controller.latest_sst(level)
+    }
+
     pub fn has_expired_sst(&self, expire_time: Option<Timestamp>) -> bool {
         let inner = self.inner.read().unwrap();
 
@@ -853,6 +946,12 @@ impl TableVersion {
         inner.levels_controller.expired_ssts(expire_time)
     }
 
+    pub fn level_stats(&self) -> Vec<LevelStats> {
+        let inner = self.inner.read().unwrap();
+
+        inner.levels_controller.level_stats()
+    }
+
     pub fn flushed_sequence(&self) -> SequenceNumber {
         let inner = self.inner.read().unwrap();
 
@@ -1234,4 +1333,94 @@ mod tests {
         assert_eq!(1, read_view.leveled_ssts[0].len());
         assert_eq!(file_id, read_view.leveled_ssts[0][0].id());
     }
+
+    #[test]
+    fn test_read_view_time_range_stats() {
+        let version = new_table_version();
+        let sst_column_stats = |stats: &[(usize, usize)]| {
+            stats
+                .iter()
+                .map(|(null_count, distinct_count)| SstColumnStats {
+                    null_count: *null_count,
+                    distinct_count: *distinct_count,
+                })
+                .collect::<Vec<_>>()
+        };
+        let files_to_add = vec![
+            AddFileMocker::new(1)
+                .time_range(TimeRange::new_unchecked_for_test(100, 200))
+                .row_num(10)
+                .column_stats(sst_column_stats(&[(1, 5), (2, 3)]))
+                .build(),
+            // The second column is added after writing this sst.
+            AddFileMocker::new(2)
+                .time_range(TimeRange::new_unchecked_for_test(300, 400))
+                .row_num(5)
+                .column_stats(sst_column_stats(&[(0, 7)]))
+                .build(),
+            // The column stats of this sst is unknown.
+            AddFileMocker::new(3)
+                .time_range(TimeRange::new_unchecked_for_test(500, 600))
+                .row_num(3)
+                .build(),
+        ];
+        let edit = VersionEdit {
+            flushed_sequence: 0,
+            mems_to_remove: vec![],
+            files_to_add,
+            files_to_delete: vec![],
+            max_file_id: 0,
+        };
+        version.apply_edit(edit);
+
+        let column_stats = |stats: &[(usize, usize)]| {
+            stats
+                .iter()
+                .map(|(null_count, distinct_count)| ColumnStats {
+                    null_count: *null_count,
+                    distinct_count: *distinct_count,
+                })
+                .collect::<Vec<_>>()
+        };
+        let read_view = version.pick_read_view(TimeRange::min_to_max());
+        let cases = [
+            (
+                TimeRange::new_unchecked_for_test(0, 250),
+                Some(TimeRangeStats {
+                    num_rows: 10,
+                    time_range: Some(TimeRange::new_unchecked_for_test(100, 200)),
+                    column_stats: Some(column_stats(&[(1, 5), (2, 3)])),
+                }),
+            ),
+            (
+                TimeRange::new_unchecked_for_test(0, 450),
+                Some(TimeRangeStats {
+                    num_rows: 15,
+                    time_range: Some(TimeRange::new_unchecked_for_test(100, 400)),
+                    column_stats: Some(column_stats(&[(1, 7), (7, 3)])),
+                }),
+            ),
+            (
+                TimeRange::new_unchecked_for_test(0, 1000),
+                Some(TimeRangeStats {
+                    num_rows: 18,
+                    time_range: Some(TimeRange::new_unchecked_for_test(100, 600)),
+                    column_stats: None,
+                }),
+            ),
+            (
+                TimeRange::new_unchecked_for_test(700, 800),
+                Some(TimeRangeStats {
+                    num_rows: 0,
+                    time_range: None,
+                    column_stats: Some(column_stats(&[(0, 0), (0, 0)])),
+                }),
+            ),
+            // The first sst partially overlaps with the time range.
+            (TimeRange::new_unchecked_for_test(150, 1000), None),
+        ];
+        for (time_range, expected) in cases {
+            assert_eq!(expected, read_view.time_range_stats(time_range, 2));
+        }
+    }
 }
@@ -106,6 +106,8 @@ impl TryFrom<manifest_pb::AddFileMeta> for AddFile {
                 storage_format: StorageFormat::try_from(storage_format)
                     .context(ConvertStorageFormat)?,
                 associated_files: src.associated_files,
+                // Column stats are not persisted in the manifest.
+                column_stats: Vec::new(),
             },
         };
 
@@ -163,12 +165,15 @@ pub struct VersionEdit {
 #[cfg(test)]
 pub mod tests {
     use super::*;
+    use crate::sst::writer::SstColumnStats;
 
     #[must_use]
     pub struct AddFileMocker {
         file_id: FileId,
         time_range: TimeRange,
         max_seq: SequenceNumber,
+        row_num: u64,
+        column_stats: Vec<SstColumnStats>,
     }
 
     impl AddFileMocker {
@@ -177,6 +182,8 @@ pub mod tests {
                 file_id,
                 time_range: TimeRange::empty(),
                 max_seq: 0,
+                row_num: 0,
+                column_stats: Vec::new(),
             }
         }
 
@@ -190,17 +197,28 @@ pub mod tests {
             self
         }
 
+        pub fn row_num(mut self, row_num: u64) -> Self {
+            self.row_num = row_num;
+            self
+        }
+
+        pub fn column_stats(mut self, column_stats: Vec<SstColumnStats>) -> Self {
+            self.column_stats = column_stats;
+            self
+        }
+
         pub fn build(&self) -> AddFile {
             AddFile {
                 level: Level::MIN,
                 file: FileMeta {
                     id: self.file_id,
                     size: 0,
-                    row_num: 0,
+                    row_num: self.row_num,
                     time_range: self.time_range,
                     max_seq: self.max_seq,
                     storage_format: StorageFormat::default(),
                     associated_files: Vec::new(),
+                    column_stats: self.column_stats.clone(),
                 },
             }
         }
@@ -23,6 +23,7 @@ use anyhow::Context;
 use id_allocator::IdAllocator;
 use logger::debug;
 use table_engine::table::TableId;
+use time_ext::clock::ClockRef;
 
 use crate::{
     manifest::{
@@ -56,6 +57,7 @@ pub(crate) struct TableMetaSetImpl {
     pub(crate) enable_primary_key_sampling: bool,
     pub(crate) try_compat_old_layered_memtable_opts: bool,
     pub(crate) metrics_opt: MetricsOptions,
+    pub(crate) clock: ClockRef,
 }
 
 impl fmt::Debug for TableMetaSetImpl {
@@ -143,6 +145,7 @@ impl TableMetaSetImpl {
                             enable_primary_key_sampling: self.enable_primary_key_sampling,
                             try_compat_old_layered_memtable_opts: self
                                 .try_compat_old_layered_memtable_opts,
+                            clock: self.clock.clone(),
                         },
                         &self.file_purger,
                         mem_size_options,
@@ -275,6 +278,7 @@ impl TableMetaSetImpl {
                     metrics_opt: self.metrics_opt.clone(),
                     enable_primary_key_sampling: self.enable_primary_key_sampling,
                     try_compat_old_layered_memtable_opts: self.try_compat_old_layered_memtable_opts,
+                    clock: self.clock.clone(),
                 },
                 mem_size_options,
                 allocator,
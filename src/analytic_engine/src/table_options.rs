@@ -34,7 +34,8 @@ use time_ext::{parse_duration, DurationExt, ReadableDuration, TimeUnit};
 
 use crate::{
     compaction::{
-        self, CompactionStrategy, SizeTieredCompactionOptions, TimeWindowCompactionOptions,
+        self, CompactionStrategy, LazyLevelingCompactionOptions, SizeTieredCompactionOptions,
+        TimeWindowCompactionOptions,
     },
     memtable::{LayeredMemtableOptions, MemtableType},
 };
@@ -615,8 +616,9 @@ impl TableOptions {
         }
     }
 
-    pub fn is_expired(&self, timestamp: Timestamp) -> bool {
-        self.enable_ttl && timestamp.is_expired(Timestamp::expire_time(self.ttl.0))
+    /// Whether the `timestamp` is expired at the time `now`.
+    pub fn is_expired(&self, timestamp: Timestamp, now: Timestamp) -> bool {
+        self.enable_ttl && timestamp.is_expired(now.sub_duration_or_min(self.ttl.0))
     }
 }
 
@@ -671,6 +673,43 @@ impl From<manifest_pb::CompactionOptions> for TimeWindowCompactionOptions {
     }
 }
 
+/// The table options which [manifest_pb::TableOptions] has no place for.
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct TableOptionsExt {
+    /// Set if the compaction strategy is lazy leveling, whose time window
+    /// options are kept in [manifest_pb::TableOptions::compaction_options].
+    #[prost(message, optional, tag = "1")]
+    pub lazy_leveling: Option<LazyLevelingCompactionOptionsExt>,
+}
+
+/// Pb message of the [LazyLevelingCompactionOptions] besides its time window
+/// options.
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct LazyLevelingCompactionOptionsExt {
+    #[prost(uint64, tag = "1")]
+    pub max_sorted_runs: u64,
+}
+
+impl From<&TableOptions> for TableOptionsExt {
+    fn from(opts: &TableOptions) -> Self {
+        let lazy_leveling = match &opts.compaction_strategy {
+            CompactionStrategy::LazyLeveling(v) => Some(LazyLevelingCompactionOptionsExt {
+                max_sorted_runs: v.max_sorted_runs as u64,
+            }),
+            _ => None,
+        };
+
+        Self { lazy_leveling }
+    }
+}
+
+impl From<TableOptions> for (manifest_pb::TableOptions, TableOptionsExt) {
+    fn from(opts: TableOptions) -> Self {
+        let ext = TableOptionsExt::from(&opts);
+        (opts.into(), ext)
+    }
+}
+
 impl From<TableOptions> for manifest_pb::TableOptions {
     fn from(opts: TableOptions) -> Self {
         let segment_duration = opts
@@ -689,6 +728,12 @@ impl From<TableOptions> for manifest_pb::TableOptions {
                 manifest_pb::CompactionStrategy::TimeWindow,
                 Some(manifest_pb::CompactionOptions::from(v)),
             ),
+            // The rest of the lazy leveling options are kept in [TableOptionsExt], and the
+            // nodes unaware of it fall back to the time window strategy.
+            CompactionStrategy::LazyLeveling(v) => (
+                manifest_pb::CompactionStrategy::TimeWindow,
+                Some(manifest_pb::CompactionOptions::from(v.time_window)),
+            ),
         };
 
         let layered_memtable_opts = opts.layered_memtable_opts.into();
@@ -732,10 +777,19 @@ impl From<manifest_pb::UpdateMode> for UpdateMode {
     }
 }
 
+/// The table options persisted by the nodes unaware of [TableOptionsExt].
 impl TryFrom<manifest_pb::TableOptions> for TableOptions {
     type Error = Error;
 
     fn try_from(opts: manifest_pb::TableOptions) -> Result<Self> {
+        Self::try_from((opts, TableOptionsExt::default()))
+    }
+}
+
+impl TryFrom<(manifest_pb::TableOptions, TableOptionsExt)> for TableOptions {
+    type Error = Error;
+
+    fn try_from((opts, ext): (manifest_pb::TableOptions, TableOptionsExt)) -> Result<Self> {
         let compression = opts.compression();
         let update_mode = opts.update_mode();
 
@@ -753,7 +807,13 @@ impl TryFrom<manifest_pb::TableOptions> for TableOptions {
                     .compaction_options
                     .map(TimeWindowCompactionOptions::from)
                     .unwrap_or_default();
-                CompactionStrategy::TimeWindow(opts)
+                match ext.lazy_leveling {
+                    Some(v) => CompactionStrategy::LazyLeveling(LazyLevelingCompactionOptions {
+                        time_window: opts,
+                        max_sorted_runs: v.max_sorted_runs as usize,
+                    }),
+                    None => CompactionStrategy::TimeWindow(opts),
+                }
             }
         };
 
@@ -919,3 +979,36 @@ fn parse_size(v: &str) -> Result<ReadableSize> {
         backtrace: Backtrace::generate(),
     })
 }
+
+#[cfg(test)]
+mod tests {
+    use prost::Message;
+
+    use super::*;
+
+    #[test]
+    fn test_lazy_leveling_options_pb_round_trip() {
+        let opts = TableOptions {
+            compaction_strategy: CompactionStrategy::LazyLeveling(LazyLevelingCompactionOptions {
+                max_sorted_runs: 4,
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+
+        let (opts_pb, ext): (manifest_pb::TableOptions, TableOptionsExt) = opts.clone().into();
+        let ext = TableOptionsExt::decode(ext.encode_to_vec().as_slice()).unwrap();
+        let decoded = TableOptions::try_from((opts_pb.clone(), ext)).unwrap();
+        assert_eq!(decoded, opts);
+
+        // The nodes unaware of the ext fall back to the time window strategy.
+        let decoded = TableOptions::try_from(opts_pb).unwrap();
+        let CompactionStrategy::LazyLeveling(expect) = opts.compaction_strategy else {
+            unreachable!()
+        };
+        assert_eq!(
+            decoded.compaction_strategy,
+            CompactionStrategy::TimeWindow(expect.time_window)
+        );
+    }
+}
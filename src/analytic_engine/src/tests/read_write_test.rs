@@ -21,6 +21,7 @@ use std::{thread, time};
 
 use common_types::time::Timestamp;
 use logger::info;
+use table_engine::table::Durability;
 use wal::manager::WalsOpener;
 
 use crate::{
@@ -184,6 +185,187 @@ fn test_multi_table_read_write<T: EngineBuildContext>(engine_context: T) {
     });
 }
 
+#[test]
+fn test_write_tables_atomically_rocks() {
+    let rocksdb_ctxs = rocksdb_ctxs();
+    for ctx in rocksdb_ctxs {
+        test_write_tables_atomically(ctx);
+    }
+}
+
+#[test]
+fn test_write_tables_atomically_mem_wal() {
+    let env = TestEnv::builder().build();
+    let mut test_ctx = env.new_context(memory_ctxs().remove(0));
+
+    env.block_on(async {
+        test_ctx.open().await;
+
+        let test_table1 = "test_write_tables_atomically1";
+        let test_table2 = "test_write_tables_atomically2";
+        let fixed_schema_table = test_ctx.create_fixed_schema_table(test_table1).await;
+        let _ = test_ctx.create_fixed_schema_table(test_table2).await;
+
+        let rows = [(
+            "key1",
+            Timestamp::new(test_ctx.start_ms()),
+            "tag1-1",
+            11.0,
+            110.0,
+            "tag2-1",
+        )];
+        // The wal based on the table kv doesn't support the atomic write.
+        let res = test_ctx
+            .write_to_tables_atomically(vec![
+                (test_table1, fixed_schema_table.rows_to_row_group(&rows)),
+                (test_table2, fixed_schema_table.rows_to_row_group(&rows)),
+            ])
+            .await;
+        assert!(res.is_err());
+    });
+}
+
+fn test_write_tables_atomically<T: EngineBuildContext>(engine_context: T) {
+    let env = TestEnv::builder().build();
+    let mut test_ctx = env.new_context(engine_context);
+
+    env.block_on(async {
+        test_ctx.open().await;
+
+        let test_table1 = "test_write_tables_atomically1";
+        let test_table2 = "test_write_tables_atomically2";
+        let fixed_schema_table = test_ctx.create_fixed_schema_table(test_table1).await;
+        let _ = test_ctx.create_fixed_schema_table(test_table2).await;
+
+        let start_ms = test_ctx.start_ms();
+        let rows1 = [
+            (
+                "key1",
+                Timestamp::new(start_ms),
+                "tag1-1",
+                11.0,
+                110.0,
+                "tag2-1",
+            ),
+            (
+                "key2",
+                Timestamp::new(start_ms + 1),
+                "tag1-2",
+                12.0,
+                110.0,
+                "tag2-2",
+            ),
+        ];
+        let rows2 = [(
+            "key3",
+            Timestamp::new(start_ms + 2),
+            "tag1-3",
+            13.0,
+            110.0,
+            "tag2-3",
+        )];
+
+        let num_rows = test_ctx
+            .write_to_tables_atomically(vec![
+                (test_table2, fixed_schema_table.rows_to_row_group(&rows2)),
+                (test_table1, fixed_schema_table.rows_to_row_group(&rows1)),
+            ])
+            .await
+            .unwrap();
+        assert_eq!(num_rows, 3);
+
+        // A table can't be written twice in one atomic write.
+        let res = test_ctx
+            .write_to_tables_atomically(vec![
+                (test_table1, fixed_schema_table.rows_to_row_group(&rows1)),
+                (test_table1, fixed_schema_table.rows_to_row_group(&rows2)),
+            ])
+            .await;
+        assert!(res.is_err());
+
+        util::check_read(
+            &test_ctx,
+            &fixed_schema_table,
+            "Test write tables atomically, table1",
+            test_table1,
+            &rows1,
+        )
+        .await;
+        util::check_read(
+            &test_ctx,
+            &fixed_schema_table,
+            "Test write tables atomically, table2",
+            test_table2,
+            &rows2,
+        )
+        .await;
+
+        // The writes are replayed from the wal after reopening.
+        test_ctx
+            .reopen_with_tables(&[test_table1, test_table2])
+            .await;
+
+        util::check_read(
+            &test_ctx,
+            &fixed_schema_table,
+            "Test write tables atomically, table1 after reopen",
+            test_table1,
+            &rows1,
+        )
+        .await;
+        util::check_read(
+            &test_ctx,
+            &fixed_schema_table,
+            "Test write tables atomically, table2 after reopen",
+            test_table2,
+            &rows2,
+        )
+        .await;
+
+        // The tables written with the object store durability are flushed before the
+        // write returns.
+        let rows3 = [(
+            "key4",
+            Timestamp::new(start_ms + 3),
+            "tag1-4",
+            14.0,
+            110.0,
+            "tag2-4",
+        )];
+        let (table1, table2) = (test_ctx.table(test_table1), test_ctx.table(test_table2));
+        let (old_stats1, old_stats2) = (table1.stats(), table2.stats());
+        let num_rows = test_ctx
+            .write_to_tables_atomically_with_durability(vec![
+                (
+                    test_table1,
+                    fixed_schema_table.rows_to_row_group(&rows3),
+                    Durability::ObjectStore,
+                ),
+                (
+                    test_table2,
+                    fixed_schema_table.rows_to_row_group(&rows3),
+                    Durability::default(),
+                ),
+            ])
+            .await
+            .unwrap();
+        assert_eq!(num_rows, 2);
+        assert_eq!(old_stats1.num_flush + 1, table1.stats().num_flush);
+        assert_eq!(old_stats2.num_flush, table2.stats().num_flush);
+
+        let mut expect_rows = rows1.to_vec();
+        expect_rows.extend_from_slice(&rows3);
+        util::check_read(
+            &test_ctx,
+            &fixed_schema_table,
+            "Test write tables atomically, table1 flushed",
+            test_table1,
+            &expect_rows,
+        )
+        .await;
+    });
+}
+
 #[test]
 fn test_table_write_read_rocks() {
     let rocksdb_ctxs = rocksdb_ctxs();
@@ -267,6 +449,64 @@ fn test_table_write_read<T: EngineBuildContext>(engine_context: T) {
     });
 }
 
+#[test]
+fn test_table_write_with_durability_rocks() {
+    let rocksdb_ctxs = rocksdb_ctxs();
+    for ctx in rocksdb_ctxs {
+        test_table_write_with_durability(ctx, true);
+    }
+}
+
+#[test]
+fn test_table_write_with_durability_mem_wal() {
+    let memory_ctxs = memory_ctxs();
+    for ctx in memory_ctxs {
+        test_table_write_with_durability(ctx, false);
+    }
+}
+
+fn test_table_write_with_durability<T: EngineBuildContext>(
+    engine_context: T,
+    wal_supports_sync: bool,
+) {
+    let env = TestEnv::builder().build();
+    let mut test_ctx = env.new_context(engine_context);
+
+    env.block_on(async {
+        test_ctx.open().await;
+
+        let test_table1 = "test_table1";
+        let fixed_schema_table = test_ctx.create_fixed_schema_table(test_table1).await;
+
+        let start_ms = test_ctx.start_ms();
+        let rows = [(
+            "key1",
+            Timestamp::new(start_ms),
+            "tag1-1",
+            11.0,
+            110.0,
+            "tag2-1",
+        )];
+        let row_group = fixed_schema_table.rows_to_row_group(&rows);
+
+        // The write is rejected if the wal can't be synced, rather than
+        // acknowledged with the durability not reached.
+        let result = test_ctx
+            .write_to_table_with_durability(test_table1, row_group, Durability::WalSync)
+            .await;
+        assert_eq!(wal_supports_sync, result.is_ok());
+        let expect_rows: &[_] = if wal_supports_sync { &rows } else { &[] };
+        util::check_read(
+            &test_ctx,
+            &fixed_schema_table,
+            "Test write with durability",
+            test_table1,
+            expect_rows,
+        )
+        .await;
+    });
+}
+
 #[test]
 fn test_table_write_get_rocks() {
     let rocksdb_ctxs = rocksdb_ctxs();
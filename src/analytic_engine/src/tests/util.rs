@@ -34,12 +34,13 @@ use size_ext::ReadableSize;
 use table_engine::{
     engine::{
         CreateTableRequest, DropTableRequest, EngineRuntimes, OpenShardRequest, OpenTableRequest,
-        Result as EngineResult, TableDef, TableEngineRef,
+        Result as EngineResult, TableDef, TableEngineRef, TableWriteRequest, WriteTablesRequest,
     },
     table::{
-        AlterSchemaRequest, FlushRequest, GetRequest, ReadRequest, Result, SchemaId, TableId,
-        TableRef, WriteRequest,
+        AlterSchemaRequest, Durability, FlushRequest, GetRequest, ReadRequest, Result, SchemaId,
+        TableId, TableRef, WriteRequest,
     },
+    ANALYTIC_ENGINE_TYPE,
 };
 use tempfile::TempDir;
 use time_ext::ReadableDuration;
@@ -338,9 +339,61 @@ impl<T: WalsOpener> TestContext<T> {
     }
 
     pub async fn write_to_table(&self, table_name: &str, row_group: RowGroup) {
+        self.write_to_table_with_durability(table_name, row_group, Durability::default())
+            .await
+            .unwrap();
+    }
+
+    pub async fn write_to_table_with_durability(
+        &self,
+        table_name: &str,
+        row_group: RowGroup,
+        durability: Durability,
+    ) -> Result<usize> {
         let table = self.table(table_name);
 
-        table.write(WriteRequest { row_group }).await.unwrap();
+        table
+            .write(WriteRequest {
+                row_group,
+                durability,
+            })
+            .await
+    }
+
+    pub async fn write_to_tables_atomically(
+        &self,
+        writes: Vec<(&str, RowGroup)>,
+    ) -> EngineResult<usize> {
+        let writes = writes
+            .into_iter()
+            .map(|(table_name, row_group)| (table_name, row_group, Durability::default()))
+            .collect();
+        self.write_to_tables_atomically_with_durability(writes)
+            .await
+    }
+
+    pub async fn write_to_tables_atomically_with_durability(
+        &self,
+        writes: Vec<(&str, RowGroup, Durability)>,
+    ) -> EngineResult<usize> {
+        let writes = writes
+            .into_iter()
+            .map(|(table_name, row_group, durability)| TableWriteRequest {
+                schema_id: self.schema_id,
+                table_name: table_name.to_string(),
+                request: WriteRequest {
+                    row_group,
+                    durability,
+                },
+            })
+            .collect();
+
+        self.engine()
+            .write_tables(WriteTablesRequest {
+                writes,
+                engine: ANALYTIC_ENGINE_TYPE.to_string(),
+            })
+            .await
     }
 
     pub async fn read_table(
@@ -514,6 +567,7 @@ impl Builder {
                     max_retries: 3,
                     timeout: Default::default(),
                 }),
+                tenant_stores: Vec::new(),
             },
             wal: WalConfig {
                 storage: StorageConfig::RocksDB(Box::new(RocksDBStorageConfig {
@@ -594,6 +648,7 @@ impl Default for RocksDBEngineBuildContext {
                     max_retries: 3,
                     timeout: Default::default(),
                 }),
+                tenant_stores: Vec::new(),
             },
             wal: WalConfig {
                 storage: StorageConfig::RocksDB(Box::new(RocksDBStorageConfig {
@@ -629,6 +684,7 @@ impl Clone for RocksDBEngineBuildContext {
                 max_retries: 3,
                 timeout: Default::default(),
             }),
+            tenant_stores: Vec::new(),
         };
 
         config.storage = storage;
@@ -695,6 +751,7 @@ impl Default for MemoryEngineBuildContext {
                     max_retries: 3,
                     timeout: Default::default(),
                 }),
+                tenant_stores: Vec::new(),
             },
             wal: WalConfig {
                 storage: StorageConfig::Obkv(Box::default()),
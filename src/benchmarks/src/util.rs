@@ -59,7 +59,7 @@ use snafu::{ResultExt, Snafu};
 use table_engine::{
     engine::{CreateTableRequest, EngineRuntimes, OpenShardRequest, TableDef, TableEngineRef},
     predicate::Predicate,
-    table::{ReadRequest, SchemaId, TableId, TableRef, WriteRequest},
+    table::{Durability, ReadRequest, SchemaId, TableId, TableRef, WriteRequest},
 };
 use tempfile::TempDir;
 use time_ext::ReadableDuration;
@@ -221,6 +221,7 @@ pub async fn file_handles_from_ssts(
             max_seq: sst_meta.max_sequence,
             storage_format: StorageFormat::Columnar,
             associated_files: Vec::new(),
+            column_stats: Vec::new(),
         };
 
         let handle = FileHandle::new(file_meta, purge_queue.clone());
@@ -311,6 +312,7 @@ impl Builder {
                     max_retries: 3,
                     timeout: Default::default(),
                 }),
+                tenant_stores: Vec::new(),
             },
             wal: WalConfig {
                 storage: StorageConfig::RocksDB(Box::new(RocksDBStorageConfig {
@@ -391,6 +393,7 @@ impl Default for RocksDBEngineBuildContext {
                     max_retries: 3,
                     timeout: Default::default(),
                 }),
+                tenant_stores: Vec::new(),
             },
             wal: WalConfig {
                 storage: StorageConfig::RocksDB(Box::new(RocksDBStorageConfig {
@@ -426,6 +429,7 @@ impl Clone for RocksDBEngineBuildContext {
                 max_retries: 3,
                 timeout: Default::default(),
             }),
+            tenant_stores: Vec::new(),
         };
 
         config.storage = storage;
@@ -564,7 +568,13 @@ impl<T: WalsOpener> TestContext<T> {
     pub async fn write_to_table(&self, table_name: &str, row_group: RowGroup) {
         let table = self.table(table_name);
 
-        table.write(WriteRequest { row_group }).await.unwrap();
+        table
+            .write(WriteRequest {
+                row_group,
+                durability: Durability::default(),
+            })
+            .await
+            .unwrap();
     }
 
     pub fn table(&self, table_name: &str) -> TableRef {
@@ -75,6 +75,16 @@ pub enum Error {
     // segmentation fault.
     #[snafu(display("Failed to operate table, msg:{:?}.\n", msg))]
     TableOperatorNoCause { msg: Option<String> },
+
+    #[snafu(display(
+        "Failed to operate some tables, msg:{}, failed_tables:{:?}.\n",
+        msg,
+        failed_tables
+    ))]
+    TableOperatorPartialFailure {
+        msg: String,
+        failed_tables: Vec<String>,
+    },
 }
 
 define_result!(Error);
@@ -376,6 +376,8 @@ impl TableDef {
 
 pub type CloseShardRequest = OpenShardRequest;
 
+pub type FlushShardRequest = OpenShardRequest;
+
 /// Schema manage tables.
 #[async_trait]
 pub trait Schema {
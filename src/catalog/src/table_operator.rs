@@ -15,21 +15,26 @@
 // specific language governing permissions and limitations
 // under the License.
 
-use std::time::Instant;
+use std::{collections::BTreeSet, sync::Arc, time::Instant};
 
+use common_types::schema::Schema;
 use generic_error::BoxError;
 use logger::{error, info, warn};
 use snafu::{OptionExt, ResultExt};
-use table_engine::{engine, table::TableRef};
+use table_engine::{
+    engine,
+    table::{FlushRequest, TableId, TableRef},
+};
 use time_ext::InstantExt;
 
 use crate::{
     manager::ManagerRef,
     schema::{
         CloseOptions, CloseShardRequest, CloseTableRequest, CreateOptions, CreateTableRequest,
-        DropOptions, DropTableRequest, OpenOptions, OpenShardRequest, OpenTableRequest, SchemaRef,
+        DropOptions, DropTableRequest, FlushShardRequest, OpenOptions, OpenShardRequest,
+        OpenTableRequest, SchemaRef,
     },
-    Result, TableOperatorNoCause, TableOperatorWithCause,
+    Result, TableOperatorNoCause, TableOperatorPartialFailure, TableOperatorWithCause,
 };
 
 /// Table operator
@@ -57,8 +62,9 @@ impl TableOperator {
         for open_ctx in request.table_defs {
             let schema = self.schema_by_name(&open_ctx.catalog_name, &open_ctx.schema_name)?;
             let table_id = open_ctx.id;
+            let table_name = open_ctx.name.clone();
             engine_table_defs.push(open_ctx.into_engine_table_def(schema.id()));
-            related_schemas.push((table_id, schema));
+            related_schemas.push((table_id, table_name, schema));
         }
 
         // Open tables by table engine.
@@ -77,8 +83,10 @@ impl TableOperator {
         let mut success_count = 0_u32;
         let mut missing_table_count = 0_u32;
         let mut open_table_errs = Vec::new();
+        let mut incompatible_tables = Vec::new();
+        let mut failed_tables = Vec::new();
 
-        for (table_id, schema) in related_schemas {
+        for (table_id, table_name, schema) in related_schemas {
             let table_result = shard_result
                 .remove(&table_id)
                 .context(TableOperatorNoCause {
@@ -89,36 +97,50 @@ impl TableOperator {
 
             match table_result {
                 Ok(Some(table)) => {
+                    // Fail fast rather than serving a table different from other copies of it.
+                    let registered = schema.table_by_name(&table_name).ok().flatten();
+                    let diffs =
+                        diff_opened_table(table_id, &table_name, &table, registered.as_ref());
+                    if !diffs.is_empty() {
+                        error!("TableOperator opened an incompatible table, table_id:{table_id}, schema_id:{:?}, shard_id:{shard_id}, diffs:{diffs:?}", schema.id());
+                        incompatible_tables.push(format!("{table_name}:{diffs:?}"));
+                        failed_tables.push(table_name);
+                        continue;
+                    }
+
                     schema.register_table(table);
                     success_count += 1;
                 }
                 Ok(None) => {
                     error!("TableOperator failed to open a missing table, table_id:{table_id}, schema_id:{:?}, shard_id:{shard_id}", schema.id());
                     missing_table_count += 1;
+                    failed_tables.push(table_name);
                 }
                 Err(e) => {
                     error!("TableOperator failed to open table, table_id:{table_id}, schema_id:{:?}, shard_id:{shard_id}, err:{}", schema.id(), e);
                     open_table_errs.push(e);
+                    failed_tables.push(table_name);
                 }
             }
         }
 
         info!(
-            "Open shard finish, shard id:{shard_id}, cost:{}ms, success_count:{success_count}, missing_table_count:{missing_table_count}, open_table_errs:{open_table_errs:?}",
+            "Open shard finish, shard id:{shard_id}, cost:{}ms, success_count:{success_count}, missing_table_count:{missing_table_count}, open_table_errs:{open_table_errs:?}, incompatible_tables:{incompatible_tables:?}",
             instant.saturating_elapsed().as_millis(),
         );
 
-        if missing_table_count == 0 && open_table_errs.is_empty() {
+        if missing_table_count == 0 && open_table_errs.is_empty() && incompatible_tables.is_empty()
+        {
             Ok(())
         } else {
             let msg = format!(
                 "Failed to open shard, some tables open failed, shard id:{shard_id}, \
                 missing_table_count:{missing_table_count}, \
-                open_err_count:{}",
+                open_err_count:{}, incompatible_tables:{incompatible_tables:?}",
                 open_table_errs.len()
             );
 
-            TableOperatorNoCause { msg }.fail()
+            TableOperatorPartialFailure { msg, failed_tables }.fail()
         }
     }
 
@@ -132,8 +154,9 @@ impl TableOperator {
         let mut engine_table_defs = Vec::with_capacity(request.table_defs.len());
         for table_def in request.table_defs {
             let schema = self.schema_by_name(&table_def.catalog_name, &table_def.schema_name)?;
+            let table_name = table_def.name.clone();
             engine_table_defs.push(table_def.into_engine_table_def(schema.id()));
-            schemas.push(schema);
+            schemas.push((table_name, schema));
         }
 
         //  Close tables by table engine.
@@ -148,14 +171,20 @@ impl TableOperator {
         // Check and unregister successful closed table from schema.
         let mut success_count = 0_u32;
         let mut close_table_errs = Vec::new();
+        let mut failed_tables = Vec::new();
 
-        for (schema, close_result) in schemas.into_iter().zip(close_results.into_iter()) {
+        for ((table_name, schema), close_result) in
+            schemas.into_iter().zip(close_results.into_iter())
+        {
             match close_result {
                 Ok(table_name) => {
                     schema.unregister_table(&table_name);
                     success_count += 1;
                 }
-                Err(e) => close_table_errs.push(e),
+                Err(e) => {
+                    close_table_errs.push(e);
+                    failed_tables.push(table_name);
+                }
             }
         }
 
@@ -167,10 +196,66 @@ impl TableOperator {
         if close_table_errs.is_empty() {
             Ok(())
         } else {
-            TableOperatorNoCause {
+            TableOperatorPartialFailure {
                 msg: format!(
                     "Failed to close shard, shard id:{shard_id}, success_count:{success_count}, close_err_count:{}", close_table_errs.len(),
                 ),
+                failed_tables,
+            }
+            .fail()
+        }
+    }
+
+    /// Flush all the tables of the shard, and wait for the flushes to finish.
+    pub async fn flush_shard(&self, request: FlushShardRequest) -> Result<()> {
+        let instant = Instant::now();
+        let shard_id = request.shard_id;
+
+        let mut success_count = 0_u32;
+        let mut flush_table_errs = Vec::new();
+        let mut failed_tables = Vec::new();
+        for table_def in request.table_defs {
+            let schema = self.schema_by_name(&table_def.catalog_name, &table_def.schema_name)?;
+            let table = schema.table_by_name(&table_def.name).box_err().context(
+                TableOperatorWithCause {
+                    msg: format!("failed to find table, table:{}", table_def.name),
+                },
+            )?;
+            let Some(table) = table else {
+                error!(
+                    "TableOperator failed to flush a missing table, table:{}, shard_id:{shard_id}",
+                    table_def.name
+                );
+                failed_tables.push(table_def.name);
+                continue;
+            };
+
+            match table.flush(FlushRequest { sync: true }).await {
+                Ok(()) => success_count += 1,
+                Err(e) => {
+                    error!(
+                        "TableOperator failed to flush table, table:{}, shard_id:{shard_id}, err:{e}",
+                        table_def.name
+                    );
+                    flush_table_errs.push(e);
+                    failed_tables.push(table_def.name);
+                }
+            }
+        }
+
+        info!(
+            "Flush shard finished, shard id:{shard_id}, cost:{}ms, success_count:{success_count}, flush_table_errs:{flush_table_errs:?}",
+            instant.saturating_elapsed().as_millis(),
+        );
+
+        if failed_tables.is_empty() {
+            Ok(())
+        } else {
+            TableOperatorPartialFailure {
+                msg: format!(
+                    "Failed to flush shard, shard id:{shard_id}, success_count:{success_count}, failed_count:{}", failed_tables.len(),
+                ),
+                failed_tables,
             }
             .fail()
         }
@@ -289,3 +374,94 @@ impl TableOperator {
             })
     }
 }
+
+/// Differences of the table opened by the engine from its definition in meta,
+/// and from the copy of it already registered in the catalog, e.g. opened by
+/// another shard on this node before.
+///
+/// Empty if the opened table is compatible.
+fn diff_opened_table(
+    table_id: TableId,
+    table_name: &str,
+    opened: &TableRef,
+    registered: Option<&TableRef>,
+) -> Vec<String> {
+    let mut diffs = Vec::new();
+    if opened.id() != table_id {
+        diffs.push(format!("table id, meta:{table_id}, local:{}", opened.id()));
+    }
+    if opened.name() != table_name {
+        diffs.push(format!(
+            "table name, meta:{table_name}, local:{}",
+            opened.name()
+        ));
+    }
+
+    let Some(registered) = registered.filter(|t| !Arc::ptr_eq(t, opened)) else {
+        return diffs;
+    };
+    let (expected, actual) = (registered.schema(), opened.schema());
+    for column in expected.columns() {
+        match actual.column_with_name(&column.name) {
+            None => diffs.push(format!("column {} is missing", column.name)),
+            Some(c)
+                if (c.data_type, c.is_nullable, c.is_tag)
+                    != (column.data_type, column.is_nullable, column.is_tag) =>
+            {
+                diffs.push(format!(
+                    "column {}, registered:{}(nullable:{}, tag:{}), local:{}(nullable:{}, tag:{})",
+                    column.name,
+                    column.data_type,
+                    column.is_nullable,
+                    column.is_tag,
+                    c.data_type,
+                    c.is_nullable,
+                    c.is_tag
+                ));
+            }
+            Some(_) => (),
+        }
+    }
+    for column in actual.columns() {
+        if expected.column_with_name(&column.name).is_none() {
+            diffs.push(format!("column {} is unknown", column.name));
+        }
+    }
+    let key_names = |schema: &Schema| {
+        schema
+            .primary_key_indexes()
+            .iter()
+            .map(|i| schema.column(*i).name.clone())
+            .collect::<Vec<_>>()
+    };
+    if key_names(&expected) != key_names(&actual) {
+        diffs.push(format!(
+            "primary key, registered:{:?}, local:{:?}",
+            key_names(&expected),
+            key_names(&actual)
+        ));
+    }
+    if expected.timestamp_name() != actual.timestamp_name() {
+        diffs.push(format!(
+            "timestamp column, registered:{}, local:{}",
+            expected.timestamp_name(),
+            actual.timestamp_name()
+        ));
+    }
+
+    let (expected, actual) = (registered.options(), opened.options());
+    let keys = expected
+        .keys()
+        .chain(actual.keys())
+        .collect::<BTreeSet<_>>();
+    for key in keys {
+        let (registered_value, local_value) = (expected.get(key), actual.get(key));
+        if registered_value != local_value {
+            diffs.push(format!(
+                "option {key}, registered:{registered_value:?}, local:{local_value:?}"
+            ));
+        }
+    }
+
+    diffs
+}
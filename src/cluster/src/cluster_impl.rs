@@ -34,6 +34,7 @@ use meta_client::{
 };
 use runtime::{JoinHandle, Runtime};
 use snafu::{ensure, OptionExt, ResultExt};
+use time_ext::clock::{ClockRef, SystemClock};
 use tokio::{
     fs, io,
     sync::mpsc::{self, Sender},
@@ -41,9 +42,11 @@ use tokio::{
 };
 
 use crate::{
-    config::{ClusterConfig, EtcdClientConfig},
+    config::{ClusterConfig, EtcdClientConfig, ShardRetryConfig},
+    shard_limiter::{ShardLimitConfig, ShardLimiter},
     shard_lock_manager::{self, ShardLockManager, ShardLockManagerRef},
-    shard_set::{Shard, ShardRef, ShardSet},
+    shard_registry::ShardRegistry,
+    shard_set::{Shard, ShardRef, ShardSet, ShardSetSnapshot},
     topology::ClusterTopology,
     Cluster, ClusterNodesNotFound, ClusterNodesResp, EtcdClientFailureWithCause,
     InitEtcdClientConfig, InvalidArguments, MetaClientFailure, NodeType, OpenShard,
@@ -62,7 +65,12 @@ pub struct ClusterImpl {
     config: ClusterConfig,
     heartbeat_handle: Mutex<Option<JoinHandle<()>>>,
     stop_heartbeat_tx: Mutex<Option<Sender<()>>>,
-    shard_lock_manager: ShardLockManagerRef,
+    /// `None` if the shard lock is disabled.
+    shard_lock_manager: Option<ShardLockManagerRef>,
+    shard_registry: Option<Arc<ShardRegistry>>,
+    registry_handle: Mutex<Option<JoinHandle<()>>>,
+    stop_registry_tx: Mutex<Option<Sender<()>>>,
+    recovered_shards: Mutex<Vec<ShardRef>>,
 }
 
 impl ClusterImpl {
@@ -73,6 +81,54 @@ impl ClusterImpl {
         config: ClusterConfig,
         runtime: Arc<Runtime>,
     ) -> Result<Self> {
+        let shard_lock_manager = if config.disable_shard_lock {
+            None
+        } else {
+            let manager = Self::build_shard_lock_manager(node_name, &config, &runtime).await?;
+            Some(Arc::new(manager))
+        };
+
+        shard_set.set_max_shards(config.shard_limit.max_shards);
+        let inner = Arc::new(Inner::new(
+            shard_set,
+            meta_client,
+            config.shard_limit.clone(),
+            config.shard_retry.clone(),
+            config.queue_updates_when_frozen,
+            SystemClock::new_ref(),
+        )?);
+
+        let (shard_registry, recovered_shards) = match &config.shard_registry {
+            Some(registry_config) => {
+                let registry = Arc::new(ShardRegistry::new(&registry_config.path));
+                let recovered_shards = inner.recover_shards(&registry);
+                inner
+                    .shard_set
+                    .register_listener(Arc::new(registry.listener()));
+                (Some(registry), recovered_shards)
+            }
+            None => (None, Vec::new()),
+        };
+
+        Ok(Self {
+            inner,
+            runtime,
+            config,
+            heartbeat_handle: Mutex::new(None),
+            stop_heartbeat_tx: Mutex::new(None),
+            shard_lock_manager,
+            shard_registry,
+            registry_handle: Mutex::new(None),
+            stop_registry_tx: Mutex::new(None),
+            recovered_shards: Mutex::new(recovered_shards),
+        })
+    }
+
+    async fn build_shard_lock_manager(
+        node_name: String,
+        config: &ClusterConfig,
+        runtime: &Arc<Runtime>,
+    ) -> Result<ShardLockManager> {
         if let Err(e) = config.etcd_client.validate() {
             return InvalidArguments { msg: e }.fail();
         }
@@ -99,18 +155,27 @@ impl ClusterImpl {
             enable_fast_reacquire_lock: config.etcd_client.enable_shard_lock_fast_reacquire,
             rpc_timeout: config.etcd_client.rpc_timeout(),
             runtime: runtime.clone(),
+            clock: SystemClock::new_ref(),
         };
-        let shard_lock_manager = ShardLockManager::new(shard_lock_mgr_config, etcd_client);
 
-        let inner = Arc::new(Inner::new(shard_set, meta_client)?);
-        Ok(Self {
-            inner,
-            runtime,
-            config,
-            heartbeat_handle: Mutex::new(None),
-            stop_heartbeat_tx: Mutex::new(None),
-            shard_lock_manager: Arc::new(shard_lock_manager),
-        })
+        Ok(ShardLockManager::new(shard_lock_mgr_config, etcd_client))
+    }
+
+    fn start_registry_persist_loop(&self) {
+        let (Some(registry), Some(registry_config)) =
+            (&self.shard_registry, &self.config.shard_registry)
+        else {
+            return;
+        };
+
+        let (handle, tx) = registry.clone().start_persist_loop(
+            self.inner.shard_set.clone(),
+            registry_config.persist_interval.0,
+            &self.runtime,
+        );
+
+        *self.stop_registry_tx.lock().unwrap() = Some(tx);
+        *self.registry_handle.lock().unwrap() = Some(handle);
     }
 
     fn start_heartbeat_loop(&self) {
@@ -120,6 +185,7 @@ impl ClusterImpl {
         let (tx, mut rx) = mpsc::channel(1);
 
         let handle = self.runtime.spawn(async move {
+            let mut last_snapshot = ShardSetSnapshot::default();
             loop {
                 let shard_infos = inner
                     .shard_set
@@ -127,7 +193,13 @@ impl ClusterImpl {
                     .iter()
                     .map(|shard| shard.shard_info())
                     .collect();
-                info!("Node heartbeat to meta, shard infos:{:?}", shard_infos);
+                // Only log the changes since the last heartbeat.
+                let snapshot = inner.shard_set.snapshot();
+                let diff = snapshot.diff(&last_snapshot);
+                if !diff.is_empty() {
+                    info!("Node heartbeat to meta, shard changes:{diff:?}");
+                }
+                last_snapshot = snapshot;
 
                 let resp = inner.meta_client.send_heartbeat(shard_infos).await;
                 let wait = match resp {
@@ -182,14 +254,29 @@ struct Inner {
     shard_set: ShardSet,
     meta_client: MetaClientRef,
     topology: RwLock<ClusterTopology>,
+    shard_limit: ShardLimitConfig,
+    shard_retry: ShardRetryConfig,
+    queue_updates_when_frozen: bool,
+    clock: ClockRef,
 }
 
 impl Inner {
-    fn new(shard_set: ShardSet, meta_client: MetaClientRef) -> Result<Self> {
+    fn new(
+        shard_set: ShardSet,
+        meta_client: MetaClientRef,
+        shard_limit: ShardLimitConfig,
+        shard_retry: ShardRetryConfig,
+        queue_updates_when_frozen: bool,
+        clock: ClockRef,
+    ) -> Result<Self> {
         Ok(Self {
             shard_set,
             meta_client,
             topology: Default::default(),
+            shard_limit,
+            shard_retry,
+            queue_updates_when_frozen,
+            clock,
         })
     }
 
@@ -251,6 +338,40 @@ impl Inner {
         Ok(resp)
     }
 
+    /// Insert the shards persisted in the `registry` into the shard set
+    /// without opening them.
+    ///
+    /// A broken registry is ignored, and the shards will be opened once
+    /// HoraeMeta assigns them to this node again.
+    fn recover_shards(&self, registry: &ShardRegistry) -> Vec<ShardRef> {
+        let tables_of_shards = match registry.load() {
+            Ok(v) => v,
+            Err(e) => {
+                error!("Failed to load shard registry, skip recovering shards, err:{e}");
+                return Vec::new();
+            }
+        };
+
+        tables_of_shards
+            .into_iter()
+            .filter_map(|tables_of_shard| {
+                let shard_id = tables_of_shard.shard_info.id;
+                let limiter = ShardLimiter::new(&self.shard_limit, self.clock.clone());
+                let shard = Shard::new(tables_of_shard, limiter)
+                    .with_retry(self.shard_retry.clone())
+                    .queue_updates_when_frozen(self.queue_updates_when_frozen);
+                let shard = Arc::new(shard);
+
+                info!("Recover shard from registry, id:{shard_id}, shard:{shard:?}");
+                if let Err(e) = self.shard_set.insert(shard_id, shard.clone()) {
+                    error!("Failed to recover shard, id:{shard_id}, err:{e}");
+                    return None;
+                }
+                Some(shard)
+            })
+            .collect()
+    }
+
     async fn open_shard(&self, shard_info: &ShardInfo) -> Result<ShardRef> {
         if let Some(shard) = self.shard_set.get(shard_info.id) {
             let cur_shard_info = shard.shard_info();
@@ -300,10 +421,14 @@ impl Inner {
             })?;
 
         let shard_id = tables_of_shard.shard_info.id;
-        let shard = Arc::new(Shard::new(tables_of_shard));
+        let limiter = ShardLimiter::new(&self.shard_limit, self.clock.clone());
+        let shard = Shard::new(tables_of_shard, limiter)
+            .with_retry(self.shard_retry.clone())
+            .queue_updates_when_frozen(self.queue_updates_when_frozen);
+        let shard = Arc::new(shard);
 
         info!("Insert shard to shard_set, id:{shard_id}, shard:{shard:?}");
-        if let Some(old_shard) = self.shard_set.insert(shard_id, shard.clone()) {
+        if let Some(old_shard) = self.shard_set.insert(shard_id, shard.clone())? {
             info!("Remove old shard, id:{shard_id}, old:{old_shard:?}");
         }
 
@@ -351,6 +476,9 @@ impl Cluster for ClusterImpl {
         // start the background loop for sending heartbeat.
         self.start_heartbeat_loop();
 
+        // start the background loop for persisting the shards.
+        self.start_registry_persist_loop();
+
         info!("Cluster has started");
         Ok(())
     }
@@ -372,6 +500,20 @@ impl Cluster for ClusterImpl {
             }
         }
 
+        {
+            let tx = self.stop_registry_tx.lock().unwrap().take();
+            if let Some(tx) = tx {
+                let _ = tx.send(()).await;
+            }
+        }
+
+        {
+            let handle = self.registry_handle.lock().unwrap().take();
+            if let Some(handle) = handle {
+                let _ = handle.await;
+            }
+        }
+
         info!("Cluster has stopped");
         Ok(())
     }
@@ -410,7 +552,11 @@ impl Cluster for ClusterImpl {
         self.inner.fetch_nodes().await
     }
 
-    fn shard_lock_manager(&self) -> ShardLockManagerRef {
+    fn take_recovered_shards(&self) -> Vec<ShardRef> {
+        std::mem::take(&mut *self.recovered_shards.lock().unwrap())
+    }
+
+    fn shard_lock_manager(&self) -> Option<ShardLockManagerRef> {
         self.shard_lock_manager.clone()
     }
 }
@@ -18,12 +18,13 @@
 use std::time::Duration;
 
 use common_types::schema::TIMESTAMP_COLUMN;
+use future_ext::{BackoffConfig, RetryConfig};
 use meta_client::meta_impl::MetaClientConfig;
 use serde::{Deserialize, Serialize};
 use table_engine::ANALYTIC_ENGINE_TYPE;
 use time_ext::ReadableDuration;
 
-use crate::NodeType;
+use crate::{shard_limiter::ShardLimitConfig, NodeType};
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
 #[serde(default)]
@@ -138,4 +139,105 @@ pub struct ClusterConfig {
     pub node_type: NodeType,
     pub meta_client: MetaClientConfig,
     pub etcd_client: EtcdClientConfig,
+    /// Rate limits applied to every shard opened on this node.
+    pub shard_limit: ShardLimitConfig,
+    /// Retries of the operations on every shard opened on this node.
+    pub shard_retry: ShardRetryConfig,
+    /// Queue the table updates of the frozen shards and replay them once the
+    /// shards are unfrozen, rather than reject them.
+    pub queue_updates_when_frozen: bool,
+    /// Persist the shards of this node locally to reopen them faster after
+    /// restarting, disabled if not set.
+    pub shard_registry: Option<ShardRegistryConfig>,
+    /// Shards aren't protected by the locks in etcd if set, which is only safe
+    /// when every shard is assigned to at most one node, e.g. in tests.
+    pub disable_shard_lock: bool,
+}
+
+#[derive(Clone, Deserialize, Debug, Serialize)]
+#[serde(default)]
+pub struct ShardRegistryConfig {
+    /// Path of the file persisting the shards.
+    pub path: String,
+    /// Interval to check the changes of the shards not notified, e.g. the
+    /// tables opened or closed on the shards.
+    pub persist_interval: ReadableDuration,
+}
+
+impl Default for ShardRegistryConfig {
+    fn default() -> Self {
+        Self {
+            path: "/tmp/horaedb/shard_registry.json".to_string(),
+            persist_interval: ReadableDuration::secs(10),
+        }
+    }
+}
+
+/// Config of the cluster whose topology is declared in a file rather than
+/// managed by HoraeMeta.
+#[derive(Clone, Deserialize, Debug, Serialize)]
+#[serde(default)]
+pub struct StaticClusterConfig {
+    /// Path of the toml file declaring the topology.
+    pub topology_path: String,
+    /// Interval to reload the topology file.
+    pub reload_interval: ReadableDuration,
+    /// Rate limits applied to every shard opened on this node.
+    pub shard_limit: ShardLimitConfig,
+    /// Retries of the operations on every shard opened on this node.
+    pub shard_retry: ShardRetryConfig,
+    /// Queue the table updates of the frozen shards and replay them once the
+    /// shards are unfrozen, rather than reject them.
+    pub queue_updates_when_frozen: bool,
+}
+
+impl Default for StaticClusterConfig {
+    fn default() -> Self {
+        Self {
+            topology_path: "".to_string(),
+            reload_interval: ReadableDuration::secs(10),
+            shard_limit: ShardLimitConfig::default(),
+            shard_retry: ShardRetryConfig::default(),
+            queue_updates_when_frozen: false,
+        }
+    }
+}
+
+/// Retries of the engine operations on a shard, e.g. opening the shard or
+/// creating a table on it.
+///
+/// Only the transient errors of the engine are retried, and the shard is
+/// marked as failed with the reason if it still fails to be opened after
+/// `max_retries` retries, so it can be rescheduled rather than stuck in
+/// opening.
+#[derive(Clone, Deserialize, Debug, Serialize)]
+#[serde(default)]
+pub struct ShardRetryConfig {
+    pub max_retries: usize,
+    /// The backoff before the first retry, which is doubled for every retry.
+    pub init_backoff: ReadableDuration,
+    pub max_backoff: ReadableDuration,
+}
+
+impl ShardRetryConfig {
+    pub fn retry_config(&self) -> RetryConfig {
+        RetryConfig {
+            max_retries: self.max_retries,
+            backoff: BackoffConfig {
+                init_backoff: self.init_backoff.0,
+                max_backoff: self.max_backoff.0,
+                base: 2.0,
+            },
+        }
+    }
+}
+
+impl Default for ShardRetryConfig {
+    fn default() -> Self {
+        Self {
+            max_retries: 3,
+            init_backoff: ReadableDuration::millis(100),
+            max_backoff: ReadableDuration::secs(5),
+        }
+    }
 }
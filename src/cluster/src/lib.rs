@@ -22,6 +22,8 @@
 //!   etc.
 //!
 //! The core types are [Cluster] trait and its implementation [ClusterImpl].
+//! For the small clusters with fixed nodes, [StaticClusterImpl] serves the
+//! topology declared in a file without HoraeMeta.
 
 #![feature(trait_alias)]
 
@@ -42,10 +44,13 @@ use crate::shard_set::ShardRef;
 
 pub mod cluster_impl;
 pub mod config;
+pub mod shard_limiter;
 pub mod shard_lock_manager;
 pub mod shard_operation;
 pub mod shard_operator;
+pub mod shard_registry;
 pub mod shard_set;
+pub mod static_cluster;
 #[allow(dead_code)]
 pub mod topology;
 
@@ -95,32 +100,64 @@ pub enum Error {
     #[snafu(display("Fail to open shard, msg:{msg}.\nBacktrace:\n{backtrace}"))]
     OpenShardNoCause { msg: String, backtrace: Backtrace },
 
+    #[snafu(display(
+        "Fail to operate some tables of shard, shard_id:{shard_id}, failed_tables:{failed_tables:?}, msg:{msg}.\nBacktrace:\n{backtrace}",
+    ))]
+    ShardPartialFailure {
+        shard_id: ShardId,
+        failed_tables: Vec<String>,
+        msg: String,
+        backtrace: Backtrace,
+    },
+
     #[snafu(display("Fail to close shard, msg:{msg}, source:{source}."))]
     CloseShardWithCause { msg: String, source: GenericError },
 
     #[snafu(display("Fail to close shard, msg:{msg}.\nBacktrace:\n{backtrace}"))]
     CloseShardNoCause { msg: String, backtrace: Backtrace },
 
-    #[snafu(display("Fail to create table on shard, msg:{msg}, source:{source}."))]
-    CreateTableWithCause { msg: String, source: GenericError },
+    #[snafu(display("Fail to drain shard, msg:{msg}, source:{source}."))]
+    DrainShardWithCause { msg: String, source: GenericError },
+
+    #[snafu(display("Fail to drain shard, msg:{msg}.\nBacktrace:\n{backtrace}"))]
+    DrainShardNoCause { msg: String, backtrace: Backtrace },
+
+    #[snafu(display("Fail to create table on shard, table:{table}, msg:{msg}, source:{source}."))]
+    CreateTableWithCause {
+        table: String,
+        msg: String,
+        source: GenericError,
+    },
 
     #[snafu(display("Fail to create table on shard, msg:{msg}.\nBacktrace:\n{backtrace}"))]
     CreateTableNoCause { msg: String, backtrace: Backtrace },
 
-    #[snafu(display("Fail to drop table on shard, msg:{msg}, source:{source}."))]
-    DropTableWithCause { msg: String, source: GenericError },
+    #[snafu(display("Fail to drop table on shard, table:{table}, msg:{msg}, source:{source}."))]
+    DropTableWithCause {
+        table: String,
+        msg: String,
+        source: GenericError,
+    },
 
     #[snafu(display("Fail to drop table on shard, msg:{msg}.\nBacktrace:\n{backtrace}"))]
     DropTableNoCause { msg: String, backtrace: Backtrace },
 
-    #[snafu(display("Fail to open table on shard, msg:{msg}, source:{source}."))]
-    OpenTableWithCause { msg: String, source: GenericError },
+    #[snafu(display("Fail to open table on shard, table:{table}, msg:{msg}, source:{source}."))]
+    OpenTableWithCause {
+        table: String,
+        msg: String,
+        source: GenericError,
+    },
 
     #[snafu(display("Fail to open table on shard, msg:{msg}.\nBacktrace:\n{backtrace}"))]
     OpenTableNoCause { msg: String, backtrace: Backtrace },
 
-    #[snafu(display("Fail to close table on shard, msg:{msg}, source:{source}."))]
-    CloseTableWithCause { msg: String, source: GenericError },
+    #[snafu(display("Fail to close table on shard, table:{table}, msg:{msg}, source:{source}."))]
+    CloseTableWithCause {
+        table: String,
+        msg: String,
+        source: GenericError,
+    },
 
     #[snafu(display("Fail to close table on shard, msg:{msg}.\nBacktrace:\n{backtrace}"))]
     CloseTableNoCause { msg: String, backtrace: Backtrace },
@@ -157,19 +194,122 @@ pub enum Error {
         backtrace: Backtrace,
     },
 
+    #[snafu(display(
+        "Shard is throttled, shard_id:{shard_id}, msg:{msg}.\nBacktrace:\n{backtrace}",
+    ))]
+    ShardThrottled {
+        shard_id: ShardId,
+        msg: String,
+        backtrace: Backtrace,
+    },
+
+    #[snafu(display(
+        "Too many tables on shard, shard_id:{shard_id}, max_tables:{max_tables}.\nBacktrace:\n{backtrace}",
+    ))]
+    TooManyTables {
+        shard_id: ShardId,
+        max_tables: usize,
+        backtrace: Backtrace,
+    },
+
+    #[snafu(display(
+        "Too many shards on node, shard_id:{shard_id}, max_shards:{max_shards}.\nBacktrace:\n{backtrace}",
+    ))]
+    TooManyShards {
+        shard_id: ShardId,
+        max_shards: usize,
+        backtrace: Backtrace,
+    },
+
     #[snafu(display(
         "Cluster nodes are not found in the topology, version:{version}.\nBacktrace:\n{backtrace}",
     ))]
     ClusterNodesNotFound { version: u64, backtrace: Backtrace },
+
+    #[snafu(display("Failed to load static topology, path:{path}, err:{source}."))]
+    LoadStaticTopology { path: String, source: GenericError },
+
+    #[snafu(display("Failed to access shard registry, path:{path}, err:{source}."))]
+    AccessShardRegistry { path: String, source: GenericError },
 }
 
 define_result!(Error);
 
+impl Error {
+    /// Whether the failed operation may succeed by being retried as is, e.g.
+    /// the failure is caused by the storage or the network.
+    ///
+    /// The permanent failures, e.g. the shard version mismatches or the table
+    /// is missing, won't be fixed by retrying until the request is changed.
+    pub fn is_retriable(&self) -> bool {
+        match self {
+            Error::MetaClientFailure { .. }
+            | Error::EtcdClientFailureWithCause { .. }
+            | Error::ShardPartialFailure { .. }
+            | Error::UpdateFrozenShard { .. }
+            | Error::ShardThrottled { .. }
+            | Error::ClusterNodesNotFound { .. } => true,
+            Error::Internal { source, .. }
+            | Error::OpenShardWithCause { source, .. }
+            | Error::CloseShardWithCause { source, .. }
+            | Error::DrainShardWithCause { source, .. }
+            | Error::CreateTableWithCause { source, .. }
+            | Error::DropTableWithCause { source, .. }
+            | Error::OpenTableWithCause { source, .. }
+            | Error::CloseTableWithCause { source, .. } => {
+                // The errors from the shard itself decide whether to retry, and the other
+                // errors (from the table engine mostly) are considered transient.
+                source
+                    .downcast_ref::<Error>()
+                    .map(Error::is_retriable)
+                    .unwrap_or(true)
+            }
+            Error::InvalidArguments { .. }
+            | Error::BuildMetaClient { .. }
+            | Error::StartMetaClient { .. }
+            | Error::InitEtcdClientConfig { .. }
+            | Error::OpenShard { .. }
+            | Error::OpenShardNoCause { .. }
+            | Error::CloseShardNoCause { .. }
+            | Error::DrainShardNoCause { .. }
+            | Error::CreateTableNoCause { .. }
+            | Error::DropTableNoCause { .. }
+            | Error::OpenTableNoCause { .. }
+            | Error::CloseTableNoCause { .. }
+            | Error::ShardNotFound { .. }
+            | Error::TableNotFound { .. }
+            | Error::TableAlreadyExists { .. }
+            | Error::SchemaNotFound { .. }
+            | Error::ShardVersionMismatch { .. }
+            | Error::TooManyTables { .. }
+            | Error::TooManyShards { .. }
+            | Error::LoadStaticTopology { .. }
+            | Error::AccessShardRegistry { .. } => false,
+        }
+    }
+
+    /// The tables failed in the operation, empty if no specific table is
+    /// responsible for the failure.
+    pub fn failed_tables(&self) -> Vec<&str> {
+        match self {
+            Error::ShardPartialFailure { failed_tables, .. } => {
+                failed_tables.iter().map(String::as_str).collect()
+            }
+            Error::CreateTableWithCause { table, .. }
+            | Error::DropTableWithCause { table, .. }
+            | Error::OpenTableWithCause { table, .. }
+            | Error::CloseTableWithCause { table, .. } => vec![table.as_str()],
+            _ => Vec::new(),
+        }
+    }
+}
+
 #[derive(Debug)]
 pub enum TableStatus {
     Ready,
     Recovering,
     Frozen,
+    Failed,
 }
 
 impl From<ShardStatus> for TableStatus {
@@ -178,6 +318,7 @@ impl From<ShardStatus> for TableStatus {
             ShardStatus::Init | ShardStatus::Opening => TableStatus::Recovering,
             ShardStatus::Ready => TableStatus::Ready,
             ShardStatus::Frozen => TableStatus::Frozen,
+            ShardStatus::Failed { .. } => TableStatus::Failed,
         }
     }
 }
@@ -219,5 +360,61 @@ pub trait Cluster {
 
     async fn route_tables(&self, req: &RouteTablesRequest) -> Result<RouteTablesResponse>;
     async fn fetch_nodes(&self) -> Result<ClusterNodesResp>;
-    fn shard_lock_manager(&self) -> ShardLockManagerRef;
+
+    /// Get the manager of the shard locks, None if the shards aren't protected
+    /// by the locks.
+    fn shard_lock_manager(&self) -> Option<ShardLockManagerRef>;
+
+    /// Take the shards recovered from the local shard registry, which have
+    /// been inserted into the cluster but not opened yet.
+    ///
+    /// The shards are only returned by the first call.
+    fn take_recovered_shards(&self) -> Vec<ShardRef>;
+}
+
+#[cfg(test)]
+mod tests {
+    use generic_error::BoxError;
+    use snafu::ResultExt;
+
+    use super::*;
+
+    #[test]
+    fn test_error_retriable() {
+        let err = ShardPartialFailure {
+            shard_id: 1u32,
+            failed_tables: vec!["a".to_string(), "b".to_string()],
+            msg: "open shard",
+        }
+        .fail::<()>()
+        .unwrap_err();
+        assert!(err.is_retriable());
+        assert_eq!(err.failed_tables(), vec!["a", "b"]);
+
+        let err = TableNotFound { msg: "a" }.fail::<()>().unwrap_err();
+        assert!(!err.is_retriable());
+        assert!(err.failed_tables().is_empty());
+
+        // The classification of the shard error is kept after being wrapped.
+        let err = TableAlreadyExists { msg: "a" }
+            .fail::<()>()
+            .box_err()
+            .context(CreateTableWithCause {
+                table: "a",
+                msg: "create table",
+            })
+            .unwrap_err();
+        assert!(!err.is_retriable());
+        assert_eq!(err.failed_tables(), vec!["a"]);
+
+        // The errors from the table engine are considered transient.
+        let err = Err::<(), _>(std::io::Error::new(std::io::ErrorKind::Other, "io"))
+            .box_err()
+            .context(OpenTableWithCause {
+                table: "a",
+                msg: "open table",
+            })
+            .unwrap_err();
+        assert!(err.is_retriable());
+    }
 }
@@ -0,0 +1,214 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+//! Limiters of the shard, which prevent a hot shard from starving the other
+//! shards on the same node, and a misbehaving control plane from overloading
+//! the node.
+
+use std::{sync::Mutex, time::Instant};
+
+use serde::{Deserialize, Serialize};
+use time_ext::clock::ClockRef;
+
+/// Limits applied to every shard on the node.
+///
+/// Zero means unlimited.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct ShardLimitConfig {
+    /// Max number of table operations (create or drop table) per second.
+    pub table_ops_per_sec: u64,
+    /// Max number of rows written per second.
+    pub write_rows_per_sec: u64,
+    /// Max number of tables on a shard, tables beyond it are rejected to be
+    /// created or opened on the shard.
+    pub max_tables_per_shard: usize,
+    /// Max number of shards on the node, shards beyond it are rejected to be
+    /// opened.
+    pub max_shards: usize,
+}
+
+/// Token bucket whose capacity is the tokens generated in one second.
+#[derive(Debug)]
+struct TokenBucket {
+    rate: u64,
+    clock: ClockRef,
+    state: Mutex<BucketState>,
+}
+
+#[derive(Debug)]
+struct BucketState {
+    /// It can be negative after a request larger than the capacity is allowed.
+    tokens: f64,
+    last_refill_at: Instant,
+}
+
+impl TokenBucket {
+    fn new(rate: u64, clock: ClockRef) -> Self {
+        let state = BucketState {
+            tokens: rate as f64,
+            last_refill_at: clock.now_instant(),
+        };
+
+        Self {
+            rate,
+            clock,
+            state: Mutex::new(state),
+        }
+    }
+
+    /// Try to take `n` tokens, and return false if there are not enough
+    /// tokens.
+    ///
+    /// A request larger than the capacity is allowed once the bucket is full,
+    /// and the following requests have to wait for the debt to be paid off.
+    fn try_acquire(&self, n: u64) -> bool {
+        let capacity = self.rate as f64;
+        let now = self.clock.now_instant();
+
+        let mut state = self.state.lock().unwrap();
+        let elapsed = now.saturating_duration_since(state.last_refill_at);
+        state.tokens = (state.tokens + elapsed.as_secs_f64() * capacity).min(capacity);
+        state.last_refill_at = now;
+
+        let required = n as f64;
+        if state.tokens < required.min(capacity) {
+            return false;
+        }
+        state.tokens -= required;
+
+        true
+    }
+}
+
+/// Limiter of the table operations and writes on a shard.
+#[derive(Debug)]
+pub struct ShardLimiter {
+    table_ops: Option<TokenBucket>,
+    write_rows: Option<TokenBucket>,
+    max_tables: Option<usize>,
+}
+
+impl ShardLimiter {
+    pub fn new(config: &ShardLimitConfig, clock: ClockRef) -> Self {
+        let build_bucket = |rate: u64| (rate > 0).then(|| TokenBucket::new(rate, clock.clone()));
+
+        Self {
+            table_ops: build_bucket(config.table_ops_per_sec),
+            write_rows: build_bucket(config.write_rows_per_sec),
+            max_tables: (config.max_tables_per_shard > 0).then_some(config.max_tables_per_shard),
+        }
+    }
+
+    /// Limiter which allows all the requests.
+    pub fn unlimited() -> Self {
+        Self {
+            table_ops: None,
+            write_rows: None,
+            max_tables: None,
+        }
+    }
+
+    /// Return false if the table operation should be rejected.
+    pub fn try_acquire_table_op(&self) -> bool {
+        self.table_ops
+            .as_ref()
+            .map(|bucket| bucket.try_acquire(1))
+            .unwrap_or(true)
+    }
+
+    /// Max number of tables on the shard, `None` if unlimited.
+    #[inline]
+    pub fn max_tables(&self) -> Option<usize> {
+        self.max_tables
+    }
+
+    /// Return false if the write of `num_rows` rows should be rejected.
+    pub fn try_acquire_write(&self, num_rows: u64) -> bool {
+        self.write_rows
+            .as_ref()
+            .map(|bucket| bucket.try_acquire(num_rows))
+            .unwrap_or(true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{sync::Arc, time::Duration};
+
+    use time_ext::clock::LogicalClock;
+
+    use super::*;
+
+    #[test]
+    fn test_unlimited() {
+        let limiter =
+            ShardLimiter::new(&ShardLimitConfig::default(), Arc::new(LogicalClock::new(0)));
+        for _ in 0..1000 {
+            assert!(limiter.try_acquire_table_op());
+            assert!(limiter.try_acquire_write(u64::MAX));
+        }
+    }
+
+    #[test]
+    fn test_table_ops_limit() {
+        let clock = Arc::new(LogicalClock::new(0));
+        let config = ShardLimitConfig {
+            table_ops_per_sec: 2,
+            ..Default::default()
+        };
+        let limiter = ShardLimiter::new(&config, clock.clone());
+
+        assert!(limiter.try_acquire_table_op());
+        assert!(limiter.try_acquire_table_op());
+        assert!(!limiter.try_acquire_table_op());
+
+        clock.advance(Duration::from_millis(500));
+        assert!(limiter.try_acquire_table_op());
+        assert!(!limiter.try_acquire_table_op());
+
+        // The tokens never exceed the capacity.
+        clock.advance(Duration::from_secs(10));
+        assert!(limiter.try_acquire_table_op());
+        assert!(limiter.try_acquire_table_op());
+        assert!(!limiter.try_acquire_table_op());
+    }
+
+    #[test]
+    fn test_write_limit() {
+        let clock = Arc::new(LogicalClock::new(0));
+        let config = ShardLimitConfig {
+            write_rows_per_sec: 100,
+            ..Default::default()
+        };
+        let limiter = ShardLimiter::new(&config, clock.clone());
+
+        assert!(limiter.try_acquire_write(60));
+        assert!(!limiter.try_acquire_write(60));
+        assert!(limiter.try_acquire_write(40));
+        assert!(!limiter.try_acquire_write(1));
+
+        // A write larger than the capacity passes once the bucket is full, and the
+        // following writes are rejected until the debt is paid off.
+        clock.advance(Duration::from_secs(1));
+        assert!(limiter.try_acquire_write(250));
+        clock.advance(Duration::from_secs(1));
+        assert!(!limiter.try_acquire_write(1));
+        clock.advance(Duration::from_millis(600));
+        assert!(limiter.try_acquire_write(10));
+    }
+}
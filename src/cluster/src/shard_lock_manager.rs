@@ -33,6 +33,7 @@ use macros::define_result;
 use prost::Message;
 use runtime::{JoinHandle, RuntimeRef};
 use snafu::{ensure, Backtrace, ResultExt, Snafu};
+use time_ext::clock::ClockRef;
 use tokio::sync::{oneshot, RwLock as AsyncRwLock};
 
 #[derive(Debug, Snafu)]
@@ -148,6 +149,7 @@ pub struct ShardLockManager {
 
     etcd_client: Client,
     runtime: RuntimeRef,
+    clock: ClockRef,
 
     // ShardID -> ShardLock
     shard_locks: Arc<AsyncRwLock<HashMap<u32, ShardLock>>>,
@@ -156,11 +158,12 @@ pub struct ShardLockManager {
 #[derive(Debug)]
 struct LeaseState {
     expired_at: Instant,
+    clock: ClockRef,
 }
 
 impl LeaseState {
-    fn new(expired_at: Instant) -> Self {
-        Self { expired_at }
+    fn new(expired_at: Instant, clock: ClockRef) -> Self {
+        Self { expired_at, clock }
     }
 
     /// Get the duration until the lease is expired.
@@ -169,19 +172,22 @@ impl LeaseState {
     /// alive. And None will be returned if the lease is expired.
     fn duration_until_expired(&self) -> Option<Duration> {
         let expired_at = self.expired_at;
-        let now = Instant::now();
+        let now = self.clock.now_instant();
         expired_at.checked_duration_since(now)
     }
 
     /// Check whether lease is expired.
     fn is_expired(&self) -> bool {
-        self.expired_at < Instant::now()
+        self.expired_at < self.clock.now_instant()
     }
 }
 
 impl LeaseState {
-    fn update_expired_at(&mut self, expired_at: Instant) {
-        self.expired_at = expired_at;
+    /// Renew the lease with the `ttl` from now, and return the new expired
+    /// time.
+    fn renew(&mut self, ttl: Duration) -> Instant {
+        self.expired_at = self.clock.now_instant() + ttl;
+        self.expired_at
     }
 }
 
@@ -262,8 +268,10 @@ impl Lease {
                     .fail();
                 }
 
-                let expired_at = Instant::now() + Duration::from_secs(ttl_sec as u64);
-                state.write().unwrap().update_expired_at(expired_at);
+                let expired_at = state
+                    .write()
+                    .unwrap()
+                    .renew(Duration::from_secs(ttl_sec as u64));
 
                 debug!(
                     "Succeed to keep lease alive, id:{}, ttl:{ttl_sec}s, expired_at:{expired_at:?}",
@@ -370,6 +378,8 @@ pub struct ShardLock {
     enable_fast_reacquire: bool,
     /// The timeout for etcd rpc
     rpc_timeout: Duration,
+    /// The clock to decide whether the lease is expired
+    clock: ClockRef,
 
     lease: Option<Arc<Lease>>,
     lease_check_handle: Option<JoinHandle<()>>,
@@ -392,6 +402,7 @@ impl ShardLock {
         lease_check_interval: Duration,
         enable_fast_reacquire: bool,
         rpc_timeout: Duration,
+        clock: ClockRef,
     ) -> Self {
         Self {
             shard_id,
@@ -401,6 +412,7 @@ impl ShardLock {
             lease_check_interval,
             enable_fast_reacquire,
             rpc_timeout,
+            clock,
 
             lease: None,
             lease_check_handle: None,
@@ -472,7 +484,7 @@ impl ShardLock {
             return Ok(None);
         }
 
-        let lease_expired_at = Instant::now() + Duration::from_secs(ttl_sec as u64);
+        let lease_expired_at = self.clock.now_instant() + Duration::from_secs(ttl_sec as u64);
         Ok(Some(LeaseInfo {
             id: lease_id,
             expired_at: lease_expired_at,
@@ -495,7 +507,7 @@ impl ShardLock {
             }
         );
 
-        let lease_expired_at = Instant::now() + Duration::from_secs(resp.ttl() as u64);
+        let lease_expired_at = self.clock.now_instant() + Duration::from_secs(resp.ttl() as u64);
         let lease_id = resp.id();
         self.create_lock_with_lease(lease_id, etcd_client).await?;
 
@@ -660,7 +672,7 @@ impl ShardLock {
     {
         // Try to acquire the lock to ensure there is only one running keepalive
         // procedure.
-        let initial_state = LeaseState::new(expired_at);
+        let initial_state = LeaseState::new(expired_at, self.clock.clone());
         let lease = Arc::new(Lease::new(
             lease_id,
             Duration::from_secs(self.ttl_sec),
@@ -781,6 +793,7 @@ pub struct Config {
     pub enable_fast_reacquire_lock: bool,
     pub rpc_timeout: Duration,
     pub runtime: RuntimeRef,
+    pub clock: ClockRef,
 }
 
 impl ShardLockManager {
@@ -793,6 +806,7 @@ impl ShardLockManager {
             enable_fast_reacquire_lock,
             rpc_timeout,
             runtime,
+            clock,
         } = config;
 
         let value = Bytes::from(ShardLockValue { node_name }.encode_to_vec());
@@ -806,6 +820,7 @@ impl ShardLockManager {
             enable_fast_reacquire_lock,
             etcd_client,
             runtime,
+            clock,
             shard_locks: Arc::new(AsyncRwLock::new(HashMap::new())),
         }
     }
@@ -844,6 +859,7 @@ impl ShardLockManager {
                 self.lock_lease_check_interval,
                 self.enable_fast_reacquire_lock,
                 self.rpc_timeout,
+                self.clock.clone(),
             );
 
             let mut etcd_client = self.etcd_client.clone();
@@ -888,6 +904,8 @@ impl ShardLockManager {
 
 #[cfg(test)]
 mod tests {
+    use time_ext::clock::{Clock, LogicalClock};
+
     use super::*;
 
     #[test]
@@ -905,4 +923,30 @@ mod tests {
             assert_eq!(key, expected);
         }
     }
+
+    #[test]
+    fn test_lease_state_expiration() {
+        let clock = Arc::new(LogicalClock::new(0));
+        let expired_at = clock.now_instant() + Duration::from_secs(10);
+        let mut state = LeaseState::new(expired_at, clock.clone());
+        assert!(!state.is_expired());
+        assert_eq!(
+            Some(Duration::from_secs(10)),
+            state.duration_until_expired()
+        );
+
+        clock.advance(Duration::from_secs(4));
+        assert!(!state.is_expired());
+        assert_eq!(Some(Duration::from_secs(6)), state.duration_until_expired());
+
+        // Renew the lease before it is expired.
+        state.renew(Duration::from_secs(10));
+        clock.advance(Duration::from_secs(8));
+        assert!(!state.is_expired());
+        assert_eq!(Some(Duration::from_secs(2)), state.duration_until_expired());
+
+        clock.advance(Duration::from_secs(3));
+        assert!(state.is_expired());
+        assert_eq!(None, state.duration_until_expired());
+    }
 }
@@ -15,18 +15,19 @@
 // specific language governing permissions and limitations
 // under the License.
 
-use std::collections::HashMap;
+use std::{collections::HashMap, future::Future};
 
 use catalog::{
     schema::{
         CloseOptions, CloseTableRequest, CreateOptions, CreateTableRequest, DropOptions,
-        DropTableRequest, OpenOptions, OpenTableRequest, TableDef,
+        DropTableRequest, FlushShardRequest, OpenOptions, OpenTableRequest, TableDef,
     },
     table_operator::TableOperator,
 };
 use common_types::table::ShardVersion;
+use future_ext::retry_async_if;
 use generic_error::BoxError;
-use logger::info;
+use logger::{info, warn};
 use snafu::ResultExt;
 use table_engine::{
     engine::{CreateTableParams, TableEngineRef, TableState},
@@ -34,10 +35,11 @@ use table_engine::{
 };
 
 use crate::{
+    config::ShardRetryConfig,
     shard_operation::WalRegionCloserRef,
-    shard_set::{ShardDataRef, UpdatedTableInfo},
-    CloseShardWithCause, CloseTableWithCause, CreateTableWithCause, DropTableWithCause,
-    OpenShardWithCause, OpenTableWithCause, Result,
+    shard_set::{ShardDataRef, ShardHandoverToken, UpdatedTableInfo},
+    CloseShardWithCause, CloseTableWithCause, CreateTableWithCause, DrainShardWithCause,
+    DropTableWithCause, OpenShardWithCause, OpenTableWithCause, Result, ShardPartialFailure,
 };
 
 pub struct OpenContext {
@@ -73,6 +75,21 @@ impl std::fmt::Debug for CloseContext {
     }
 }
 
+pub struct DrainContext {
+    pub catalog: String,
+    pub table_operator: TableOperator,
+    pub engine: String,
+}
+
+impl std::fmt::Debug for DrainContext {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("DrainContext")
+            .field("catalog", &self.catalog)
+            .field("engine", &self.engine)
+            .finish()
+    }
+}
+
 pub struct CreateTableContext {
     pub catalog: String,
     pub table_engine: TableEngineRef,
@@ -122,14 +139,44 @@ pub type CloseTableContext = AlterContext;
 
 pub struct ShardOperator {
     pub data: ShardDataRef,
+    pub retry: ShardRetryConfig,
+}
+
+/// Errors of the engine may be recovered by retrying, e.g. failing to access
+/// the storage, while the others, e.g. the schema is not found, never.
+fn is_retriable(e: &catalog::Error) -> bool {
+    matches!(
+        e,
+        catalog::Error::TableOperatorWithCause { .. }
+            | catalog::Error::TableOperatorPartialFailure { .. }
+    )
 }
 
 impl ShardOperator {
+    /// Run the engine operation, and retry it on transient errors.
+    async fn retry<F, Fut, T>(&self, op: &str, f: F) -> catalog::Result<T>
+    where
+        F: Fn() -> Fut,
+        Fut: Future<Output = catalog::Result<T>>,
+    {
+        let shard_id = self.data.read().unwrap().shard_info.id;
+        let f = &f;
+        let attempt = move || async move {
+            let ret = f().await;
+            if let Err(e) = &ret {
+                warn!("ShardOperator {op} failed, shard_id:{shard_id}, err:{e}");
+            }
+            ret
+        };
+
+        retry_async_if(attempt, &self.retry.retry_config(), is_retriable).await
+    }
+
     pub async fn open(&self, ctx: OpenContext) -> Result<()> {
         let (shard_info, tables) = {
             let data = self.data.read().unwrap();
             let shard_info = data.shard_info.clone();
-            let tables = data.tables.clone();
+            let tables = data.tables.to_vec();
 
             (shard_info, tables)
         };
@@ -155,13 +202,26 @@ impl ShardOperator {
             table_engine: ctx.table_engine.clone(),
         };
 
-        ctx.table_operator
-            .open_shard(open_shard_request, opts)
-            .await
-            .box_err()
-            .with_context(|| OpenShardWithCause {
-                msg: format!("shard_info:{shard_info:?}"),
-            })?;
+        let open_shard = || {
+            ctx.table_operator
+                .open_shard(open_shard_request.clone(), opts.clone())
+        };
+        match self.retry("open shard", open_shard).await {
+            Ok(()) => (),
+            Err(catalog::Error::TableOperatorPartialFailure { failed_tables, .. }) => {
+                return ShardPartialFailure {
+                    shard_id: shard_info.id,
+                    failed_tables,
+                    msg: format!("open shard, shard_info:{shard_info:?}"),
+                }
+                .fail();
+            }
+            Err(e) => {
+                return Err(e).box_err().with_context(|| OpenShardWithCause {
+                    msg: format!("shard_info:{shard_info:?}"),
+                });
+            }
+        }
 
         info!("ShardOperator open sequentially finish, shard_id:{shard_info:?}");
 
@@ -172,7 +232,7 @@ impl ShardOperator {
         let (shard_info, tables) = {
             let data = self.data.read().unwrap();
             let shard_info = data.shard_info.clone();
-            let tables = data.tables.clone();
+            let tables = data.tables.to_vec();
 
             (shard_info, tables)
         };
@@ -202,13 +262,26 @@ impl ShardOperator {
             table_engine: ctx.table_engine,
         };
 
-        ctx.table_operator
-            .close_shard(close_shard_request, opts)
-            .await
-            .box_err()
-            .with_context(|| CloseShardWithCause {
-                msg: format!("shard_info:{shard_info:?}"),
-            })?;
+        let close_shard = || {
+            ctx.table_operator
+                .close_shard(close_shard_request.clone(), opts.clone())
+        };
+        match self.retry("close shard", close_shard).await {
+            Ok(()) => (),
+            Err(catalog::Error::TableOperatorPartialFailure { failed_tables, .. }) => {
+                return ShardPartialFailure {
+                    shard_id: shard_info.id,
+                    failed_tables,
+                    msg: format!("close shard, shard_info:{shard_info:?}"),
+                }
+                .fail();
+            }
+            Err(e) => {
+                return Err(e).box_err().with_context(|| CloseShardWithCause {
+                    msg: format!("shard_info:{shard_info:?}"),
+                });
+            }
+        }
 
         // Try to close wal region
         ctx.wal_region_closer
@@ -223,6 +296,61 @@ impl ShardOperator {
         Ok(())
     }
 
+    /// Flush all tables of the frozen shard, and build the handover token from
+    /// the flushed shard.
+    pub async fn drain(&self, ctx: DrainContext) -> Result<ShardHandoverToken> {
+        let (shard_info, tables) = {
+            let data = self.data.read().unwrap();
+            let shard_info = data.shard_info.clone();
+            let tables = data.tables.to_vec();
+
+            (shard_info, tables)
+        };
+        info!("ShardOperator drain sequentially begin, shard_info:{shard_info:?}");
+
+        let table_ids = tables.iter().map(|info| info.id).collect();
+        let table_defs = tables
+            .into_iter()
+            .map(|info| TableDef {
+                catalog_name: ctx.catalog.clone(),
+                schema_name: info.schema_name,
+                id: TableId::from(info.id),
+                name: info.name,
+            })
+            .collect();
+        let flush_shard_request = FlushShardRequest {
+            shard_id: shard_info.id,
+            table_defs,
+            engine: ctx.engine,
+        };
+
+        let flush_shard = || ctx.table_operator.flush_shard(flush_shard_request.clone());
+        match self.retry("drain shard", flush_shard).await {
+            Ok(()) => (),
+            Err(catalog::Error::TableOperatorPartialFailure { failed_tables, .. }) => {
+                return ShardPartialFailure {
+                    shard_id: shard_info.id,
+                    failed_tables,
+                    msg: format!("drain shard, shard_info:{shard_info:?}"),
+                }
+                .fail();
+            }
+            Err(e) => {
+                return Err(e).box_err().with_context(|| DrainShardWithCause {
+                    msg: format!("shard_info:{shard_info:?}"),
+                });
+            }
+        }
+
+        info!("ShardOperator drain sequentially finish, shard_info:{shard_info:?}");
+
+        Ok(ShardHandoverToken::new(
+            shard_info.id,
+            shard_info.version,
+            table_ids,
+        ))
+    }
+
     pub async fn create_table(&self, ctx: CreateTableContext) -> Result<ShardVersion> {
         let shard_info = &ctx.updated_table_info.shard_info;
         let table_info = &ctx.updated_table_info.table_info;
@@ -261,12 +389,16 @@ impl ShardOperator {
             create_if_not_exists: ctx.create_if_not_exist,
         };
 
-        let _ = ctx
-            .table_operator
-            .create_table_on_shard(create_table_request.clone(), create_opts)
+        let create_table = || {
+            ctx.table_operator
+                .create_table_on_shard(create_table_request.clone(), create_opts.clone())
+        };
+        let _ = self
+            .retry("create table", create_table)
             .await
             .box_err()
             .with_context(|| CreateTableWithCause {
+                table: &table_info.name,
                 msg: format!("shard_info:{shard_info:?}, table_info:{table_info:?}"),
             })?;
 
@@ -280,6 +412,7 @@ impl ShardOperator {
             data.try_create_table(ctx.updated_table_info.clone())
                 .box_err()
                 .with_context(|| CreateTableWithCause {
+                    table: &table_info.name,
                     msg: format!("shard_info:{shard_info:?}, table_info:{table_info:?}"),
                 })?
         };
@@ -313,11 +446,15 @@ impl ShardOperator {
             table_engine: ctx.table_engine,
         };
 
-        ctx.table_operator
-            .drop_table_on_shard(drop_table_request.clone(), drop_opts)
+        let drop_table = || {
+            ctx.table_operator
+                .drop_table_on_shard(drop_table_request.clone(), drop_opts.clone())
+        };
+        self.retry("drop table", drop_table)
             .await
             .box_err()
             .with_context(|| DropTableWithCause {
+                table: &table_info.name,
                 msg: format!("shard_info:{shard_info:?}, table_info:{table_info:?}"),
             })?;
 
@@ -332,6 +469,7 @@ impl ShardOperator {
             data.try_drop_table(ctx.updated_table_info.clone())
                 .box_err()
                 .with_context(|| DropTableWithCause {
+                    table: &table_info.name,
                     msg: format!("shard_info:{shard_info:?}, table_info:{table_info:?}"),
                 })?
         };
@@ -370,11 +508,15 @@ impl ShardOperator {
             table_engine: ctx.table_engine,
         };
 
-        ctx.table_operator
-            .open_table_on_shard(open_table_request.clone(), open_opts)
+        let open_table = || {
+            ctx.table_operator
+                .open_table_on_shard(open_table_request.clone(), open_opts.clone())
+        };
+        self.retry("open table", open_table)
             .await
             .box_err()
             .with_context(|| OpenTableWithCause {
+                table: &table_info.name,
                 msg: format!("shard_info:{shard_info:?}, table_info:{table_info:?}"),
             })?;
 
@@ -389,6 +531,7 @@ impl ShardOperator {
             data.try_open_table(ctx.updated_table_info.clone())
                 .box_err()
                 .with_context(|| OpenTableWithCause {
+                    table: &table_info.name,
                     msg: format!("shard_info:{shard_info:?}, table_info:{table_info:?}"),
                 })?;
         }
@@ -420,11 +563,15 @@ impl ShardOperator {
             table_engine: ctx.table_engine,
         };
 
-        ctx.table_operator
-            .close_table_on_shard(close_table_request.clone(), close_opts)
+        let close_table = || {
+            ctx.table_operator
+                .close_table_on_shard(close_table_request.clone(), close_opts.clone())
+        };
+        self.retry("close table", close_table)
             .await
             .box_err()
             .with_context(|| CloseTableWithCause {
+                table: &table_info.name,
                 msg: format!("shard_info:{shard_info:?}, table_info:{table_info:?}"),
             })?;
 
@@ -439,6 +586,7 @@ impl ShardOperator {
             data.try_close_table(ctx.updated_table_info.clone())
                 .box_err()
                 .with_context(|| CloseTableWithCause {
+                    table: &table_info.name,
                     msg: format!("shard_info:{shard_info:?}, table_info:{table_info:?}"),
                 })?;
         }
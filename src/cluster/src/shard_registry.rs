@@ -0,0 +1,363 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+//! Local persistence of the shards opened on the node.
+//!
+//! The shards (ids, versions and tables) are persisted to a local file, so
+//! that the node can begin to reopen them right after restarting instead of
+//! waiting for HoraeMeta to assign them again. The registry is only a hint:
+//! the shards reopened from it are still reconciled with HoraeMeta through the
+//! heartbeat, and HoraeMeta may update or close them later.
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use common_types::{
+    schema::SchemaId,
+    table::{ShardId, ShardVersion},
+};
+use generic_error::BoxError;
+use horaedbproto::cluster as cluster_pb;
+use logger::{error, info};
+use meta_client::types::{ShardInfo, ShardRole, ShardStatus, TableInfo, TablesOfShard};
+use prost::Message;
+use runtime::{JoinHandle, Runtime};
+use serde::{Deserialize, Serialize};
+use snafu::ResultExt;
+use table_engine::partition::PartitionInfo;
+use tokio::{
+    sync::{mpsc, Notify},
+    time,
+};
+
+use crate::{
+    shard_set::{ShardEventListener, ShardSet},
+    AccessShardRegistry, Result,
+};
+
+#[derive(Debug, Deserialize, Serialize, PartialEq)]
+struct PersistedTable {
+    id: u64,
+    name: String,
+    schema_id: SchemaId,
+    schema_name: String,
+    /// The partition info encoded in protobuf.
+    partition_info: Option<Vec<u8>>,
+}
+
+#[derive(Debug, Deserialize, Serialize, PartialEq)]
+struct PersistedShard {
+    id: ShardId,
+    version: ShardVersion,
+    is_leader: bool,
+    tables: Vec<PersistedTable>,
+}
+
+#[derive(Debug, Default, Deserialize, Serialize, PartialEq)]
+struct PersistedShards {
+    shards: Vec<PersistedShard>,
+}
+
+impl From<TablesOfShard> for PersistedShard {
+    fn from(tables_of_shard: TablesOfShard) -> Self {
+        let TablesOfShard { shard_info, tables } = tables_of_shard;
+        let tables = tables
+            .into_iter()
+            .map(|table| PersistedTable {
+                id: table.id,
+                name: table.name,
+                schema_id: table.schema_id,
+                schema_name: table.schema_name,
+                partition_info: table
+                    .partition_info
+                    .map(|v| cluster_pb::PartitionInfo::from(v).encode_to_vec()),
+            })
+            .collect();
+
+        Self {
+            id: shard_info.id,
+            version: shard_info.version,
+            is_leader: shard_info.is_leader(),
+            tables,
+        }
+    }
+}
+
+impl PersistedShard {
+    fn into_tables_of_shard(self, path: &str) -> Result<TablesOfShard> {
+        let role = if self.is_leader {
+            ShardRole::Leader
+        } else {
+            ShardRole::Follower
+        };
+        let shard_info = ShardInfo {
+            id: self.id,
+            role,
+            version: self.version,
+            // The shard is not opened after being loaded.
+            status: ShardStatus::Init,
+        };
+
+        let mut tables = Vec::with_capacity(self.tables.len());
+        for table in self.tables {
+            let partition_info = table
+                .partition_info
+                .map(|buf| {
+                    let pb = cluster_pb::PartitionInfo::decode(buf.as_slice()).box_err()?;
+                    PartitionInfo::try_from(pb).box_err()
+                })
+                .transpose()
+                .context(AccessShardRegistry { path })?;
+
+            tables.push(TableInfo {
+                id: table.id,
+                name: table.name,
+                schema_id: table.schema_id,
+                schema_name: table.schema_name,
+                partition_info,
+            });
+        }
+
+        Ok(TablesOfShard { shard_info, tables })
+    }
+}
+
+/// The registry persisting the shards of the node into a local file.
+pub struct ShardRegistry {
+    path: PathBuf,
+    /// The content written last time, used to skip the unchanged snapshots.
+    last_persisted: Mutex<Option<Vec<u8>>>,
+    /// Notified when the shards are changed.
+    changed: Arc<Notify>,
+}
+
+impl ShardRegistry {
+    pub fn new(path: impl AsRef<Path>) -> Self {
+        Self {
+            path: path.as_ref().to_path_buf(),
+            last_persisted: Mutex::new(None),
+            changed: Arc::new(Notify::new()),
+        }
+    }
+
+    fn path_str(&self) -> String {
+        self.path.to_string_lossy().to_string()
+    }
+
+    /// Load the shards persisted last time, empty if the registry file doesn't
+    /// exist.
+    pub fn load(&self) -> Result<Vec<TablesOfShard>> {
+        let path = self.path_str();
+        if !self.path.exists() {
+            return Ok(Vec::new());
+        }
+
+        let buf = fs::read(&self.path)
+            .box_err()
+            .context(AccessShardRegistry { path: &path })?;
+        let persisted: PersistedShards = serde_json::from_slice(&buf)
+            .box_err()
+            .context(AccessShardRegistry { path: &path })?;
+        let shards = persisted
+            .shards
+            .into_iter()
+            .map(|shard| shard.into_tables_of_shard(&path))
+            .collect::<Result<Vec<_>>>()?;
+
+        *self.last_persisted.lock().unwrap() = Some(buf);
+        Ok(shards)
+    }
+
+    /// Persist the shards if they are changed since the last persistence.
+    ///
+    /// The file is replaced atomically, so a crash won't leave a broken file.
+    /// Returns whether the file is written.
+    pub fn persist(&self, mut shards: Vec<TablesOfShard>) -> Result<bool> {
+        let path = self.path_str();
+        shards.sort_unstable_by_key(|shard| shard.shard_info.id);
+        let persisted = PersistedShards {
+            shards: shards.into_iter().map(PersistedShard::from).collect(),
+        };
+        let buf = serde_json::to_vec(&persisted)
+            .box_err()
+            .context(AccessShardRegistry { path: &path })?;
+
+        let mut last_persisted = self.last_persisted.lock().unwrap();
+        if last_persisted.as_ref() == Some(&buf) {
+            return Ok(false);
+        }
+
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir)
+                .box_err()
+                .context(AccessShardRegistry { path: &path })?;
+        }
+        let tmp_path = self.path.with_extension("tmp");
+        fs::write(&tmp_path, &buf)
+            .and_then(|_| fs::rename(&tmp_path, &self.path))
+            .box_err()
+            .context(AccessShardRegistry { path: &path })?;
+
+        *last_persisted = Some(buf);
+        Ok(true)
+    }
+
+    /// Build the listener notifying the registry of the changes of the shards.
+    pub fn listener(&self) -> ShardRegistryListener {
+        ShardRegistryListener {
+            changed: self.changed.clone(),
+        }
+    }
+
+    fn persist_shard_set(&self, shard_set: &ShardSet) {
+        let shards = shard_set
+            .all_shards()
+            .iter()
+            .map(|shard| shard.tables_of_shard())
+            .collect();
+        match self.persist(shards) {
+            Ok(true) => info!("Shard registry is persisted, path:{}", self.path_str()),
+            Ok(false) => (),
+            Err(e) => error!("Failed to persist shard registry, err:{e}"),
+        }
+    }
+
+    /// Persist the shards in the `shard_set` whenever they are changed, and
+    /// check the changes not notified by the listener every `interval`.
+    ///
+    /// The loop exits after persisting the shards for the last time once the
+    /// returned sender is dropped or sent.
+    pub fn start_persist_loop(
+        self: Arc<Self>,
+        shard_set: ShardSet,
+        interval: Duration,
+        runtime: &Runtime,
+    ) -> (JoinHandle<()>, mpsc::Sender<()>) {
+        let (tx, mut rx) = mpsc::channel(1);
+        let handle = runtime.spawn(async move {
+            loop {
+                self.persist_shard_set(&shard_set);
+
+                tokio::select! {
+                    _ = self.changed.notified() => (),
+                    _ = time::sleep(interval) => (),
+                    _ = rx.recv() => {
+                        self.persist_shard_set(&shard_set);
+                        info!("Shard registry persist loop exits");
+                        break;
+                    }
+                }
+            }
+        });
+
+        (handle, tx)
+    }
+}
+
+/// The [ShardEventListener] triggering the persistence of the shard registry.
+///
+/// The tables opened or closed on a shard don't change its version and aren't
+/// notified, which are persisted by the periodical check instead.
+pub struct ShardRegistryListener {
+    changed: Arc<Notify>,
+}
+
+impl ShardEventListener for ShardRegistryListener {
+    fn on_insert(&self, _shard_info: &ShardInfo) {
+        self.changed.notify_one();
+    }
+
+    fn on_remove(&self, _shard_info: &ShardInfo) {
+        self.changed.notify_one();
+    }
+
+    fn on_version_change(
+        &self,
+        _shard_id: ShardId,
+        _old_version: ShardVersion,
+        _new_version: ShardVersion,
+    ) {
+        self.changed.notify_one();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn build_tables_of_shard(shard_id: ShardId, version: ShardVersion) -> TablesOfShard {
+        TablesOfShard {
+            shard_info: ShardInfo {
+                id: shard_id,
+                role: ShardRole::Leader,
+                version,
+                status: ShardStatus::Ready,
+            },
+            tables: vec![TableInfo {
+                id: shard_id as u64 * 100,
+                name: format!("table_{shard_id}"),
+                schema_id: 0,
+                schema_name: "public".to_string(),
+                partition_info: None,
+            }],
+        }
+    }
+
+    #[test]
+    fn test_persist_and_load() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("registry").join("shards.json");
+
+        let registry = ShardRegistry::new(&path);
+        assert!(registry.load().unwrap().is_empty());
+
+        let shards = vec![build_tables_of_shard(2, 5), build_tables_of_shard(1, 3)];
+        assert!(registry.persist(shards.clone()).unwrap());
+        // The unchanged shards are not written again.
+        assert!(!registry.persist(shards).unwrap());
+
+        let registry = ShardRegistry::new(&path);
+        let loaded = registry.load().unwrap();
+        assert_eq!(loaded.len(), 2);
+        assert_eq!(loaded[0].shard_info.id, 1);
+        assert_eq!(loaded[0].shard_info.version, 3);
+        assert_eq!(loaded[0].shard_info.status, ShardStatus::Init);
+        assert_eq!(loaded[1].shard_info.id, 2);
+        assert_eq!(loaded[1].tables[0].id, 200);
+        assert_eq!(loaded[1].tables[0].name, "table_2");
+
+        // The loaded content is considered persisted.
+        let shards = vec![build_tables_of_shard(1, 3), build_tables_of_shard(2, 5)];
+        assert!(!registry.persist(shards).unwrap());
+        assert!(registry.persist(vec![build_tables_of_shard(1, 4)]).unwrap());
+        assert_eq!(ShardRegistry::new(&path).load().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_load_broken_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("shards.json");
+        fs::write(&path, b"not json").unwrap();
+
+        let registry = ShardRegistry::new(&path);
+        assert!(registry.load().is_err());
+    }
+}
@@ -15,28 +15,103 @@
 // specific language governing permissions and limitations
 // under the License.
 
-use std::{collections::HashMap, sync::Arc};
+use std::{
+    collections::{BTreeMap, HashMap},
+    sync::{
+        atomic::{AtomicU64, AtomicUsize, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
 
-use common_types::table::ShardVersion;
+use common_types::{
+    table::{ShardVersion, TableId},
+    time::Timestamp,
+};
 use generic_error::BoxError;
+use logger::{info, warn};
 use meta_client::types::{ShardId, ShardInfo, ShardStatus, TableInfo, TablesOfShard};
 use snafu::{ensure, OptionExt, ResultExt};
+use tokio::sync::{Notify, Semaphore};
 
 use crate::{
+    config::ShardRetryConfig,
+    shard_limiter::ShardLimiter,
     shard_operator::{
-        CloseContext, CloseTableContext, CreateTableContext, DropTableContext, OpenContext,
-        OpenTableContext, ShardOperator,
+        CloseContext, CloseTableContext, CreateTableContext, DrainContext, DropTableContext,
+        OpenContext, OpenTableContext, ShardOperator,
     },
-    OpenShardNoCause, OpenShardWithCause, Result, ShardVersionMismatch, TableAlreadyExists,
-    TableNotFound, UpdateFrozenShard,
+    DrainShardNoCause, InvalidArguments, OpenShardNoCause, OpenShardWithCause, Result,
+    ShardThrottled, ShardVersionMismatch, TableAlreadyExists, TableNotFound, TooManyShards,
+    TooManyTables, UpdateFrozenShard,
 };
 
+/// Listener of the changes of the shards in the [ShardSet].
+///
+/// The callbacks are invoked synchronously, and some of them are invoked with
+/// the lock of the shard held, so they should be cheap and must not access
+/// the shard.
+pub trait ShardEventListener: Send + Sync {
+    /// The shard is inserted into the shard set.
+    fn on_insert(&self, _shard_info: &ShardInfo) {}
+
+    /// The shard is removed from the shard set.
+    fn on_remove(&self, _shard_info: &ShardInfo) {}
+
+    /// The version of the shard is changed by creating or dropping a table.
+    fn on_version_change(
+        &self,
+        _shard_id: ShardId,
+        _old_version: ShardVersion,
+        _new_version: ShardVersion,
+    ) {
+    }
+
+    /// The shard is frozen before being closed.
+    fn on_freeze(&self, _shard_info: &ShardInfo) {}
+}
+
+pub type ShardEventListenerRef = Arc<dyn ShardEventListener>;
+
+/// The listeners shared by the shard set and all the shards in it.
+#[derive(Clone, Default)]
+pub struct ShardEventListeners {
+    inner: Arc<std::sync::RwLock<Vec<ShardEventListenerRef>>>,
+}
+
+impl std::fmt::Debug for ShardEventListeners {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let num_listeners = self.inner.read().unwrap().len();
+        f.debug_struct("ShardEventListeners")
+            .field("num_listeners", &num_listeners)
+            .finish()
+    }
+}
+
+impl ShardEventListeners {
+    fn register(&self, listener: ShardEventListenerRef) {
+        self.inner.write().unwrap().push(listener);
+    }
+
+    fn notify(&self, f: impl Fn(&dyn ShardEventListener)) {
+        let listeners = self.inner.read().unwrap();
+        for listener in listeners.iter() {
+            f(listener.as_ref());
+        }
+    }
+}
+
 /// Shard set
 ///
 /// Manage all shards opened on current node
 #[derive(Debug, Default, Clone)]
 pub struct ShardSet {
     inner: Arc<std::sync::RwLock<HashMap<ShardId, ShardRef>>>,
+    listeners: ShardEventListeners,
+    /// Max number of shards in the set, zero means unlimited.
+    max_shards: Arc<AtomicUsize>,
+    /// Version of the last snapshot of the set.
+    snapshot_version: Arc<AtomicU64>,
 }
 
 impl ShardSet {
@@ -52,25 +127,217 @@ impl ShardSet {
         inner.get(&shard_id).cloned()
     }
 
+    /// Take a snapshot of the shards in the set, whose version is larger than
+    /// the previous ones.
+    pub fn snapshot(&self) -> ShardSetSnapshot {
+        let shards = self
+            .all_shards()
+            .iter()
+            .map(|shard| {
+                let data = shard.data.read().unwrap();
+                let summary = ShardSummary {
+                    id: data.shard_info.id,
+                    version: data.shard_info.version,
+                    status: data.shard_info.status.clone(),
+                    num_tables: data.tables.len(),
+                };
+                (summary.id, summary)
+            })
+            .collect();
+
+        ShardSetSnapshot {
+            version: self.snapshot_version.fetch_add(1, Ordering::Relaxed) + 1,
+            shards,
+        }
+    }
+
+    /// Set the max number of shards in the set, zero means unlimited.
+    ///
+    /// The shards inserted already are kept even if they exceed the limit.
+    pub fn set_max_shards(&self, max_shards: usize) {
+        self.max_shards.store(max_shards, Ordering::Relaxed);
+    }
+
+    /// Register the listener, which is notified of the changes of all the
+    /// shards in the set, including the ones inserted before.
+    pub fn register_listener(&self, listener: ShardEventListenerRef) {
+        self.listeners.register(listener);
+    }
+
     /// Remove the shard.
     pub fn remove(&self, shard_id: ShardId) -> Option<ShardRef> {
-        let mut inner = self.inner.write().unwrap();
-        inner.remove(&shard_id)
+        let removed = {
+            let mut inner = self.inner.write().unwrap();
+            inner.remove(&shard_id)
+        };
+
+        if let Some(shard) = &removed {
+            let shard_info = shard.shard_info();
+            self.listeners
+                .notify(|listener| listener.on_remove(&shard_info));
+        }
+        removed
     }
 
     /// Insert the tables of one shard.
-    pub fn insert(&self, shard_id: ShardId, shard: ShardRef) -> Option<ShardRef> {
-        let mut inner = self.inner.write().unwrap();
-        inner.insert(shard_id, shard)
+    ///
+    /// The replaced shard is notified as removed before the new one is
+    /// notified as inserted. Return error if the shard is new to the set and
+    /// the set is full.
+    pub fn insert(&self, shard_id: ShardId, shard: ShardRef) -> Result<Option<ShardRef>> {
+        let replaced = {
+            let mut inner = self.inner.write().unwrap();
+            let max_shards = self.max_shards.load(Ordering::Relaxed);
+            ensure!(
+                max_shards == 0 || inner.len() < max_shards || inner.contains_key(&shard_id),
+                TooManyShards {
+                    shard_id,
+                    max_shards,
+                }
+            );
+            shard.set_listeners(self.listeners.clone());
+            inner.insert(shard_id, shard.clone())
+        };
+
+        if let Some(old_shard) = &replaced {
+            let old_shard_info = old_shard.shard_info();
+            self.listeners
+                .notify(|listener| listener.on_remove(&old_shard_info));
+        }
+        let shard_info = shard.shard_info();
+        self.listeners
+            .notify(|listener| listener.on_insert(&shard_info));
+        Ok(replaced)
+    }
+
+    /// Open the shards concurrently, and at most `parallelism` shards are
+    /// opened at the same time.
+    ///
+    /// A failed shard won't stop opening the others, and the results are
+    /// returned in the same order as `shards`.
+    pub async fn open_all<F>(
+        shards: Vec<(ShardRef, OpenContext)>,
+        parallelism: usize,
+        on_event: F,
+    ) -> Vec<Result<()>>
+    where
+        F: Fn(OpenShardEvent),
+    {
+        let semaphore = Semaphore::new(parallelism.max(1));
+        let open_shards = shards.into_iter().map(|(shard, ctx)| {
+            let semaphore = &semaphore;
+            let on_event = &on_event;
+            async move {
+                let _permit = semaphore
+                    .acquire()
+                    .await
+                    .expect("semaphore is never closed");
+
+                let shard_id = shard.shard_info().id;
+                on_event(OpenShardEvent::Begin { shard_id });
+                let begin = Instant::now();
+                let ret = shard.open(ctx).await;
+                let cost = begin.elapsed();
+                match &ret {
+                    Ok(()) => on_event(OpenShardEvent::Finish { shard_id, cost }),
+                    Err(e) => on_event(OpenShardEvent::Fail {
+                        shard_id,
+                        cost,
+                        msg: e.to_string(),
+                    }),
+                }
+
+                ret
+            }
+        });
+
+        futures::future::join_all(open_shards).await
     }
 }
 
+/// Summary of a shard in the [ShardSetSnapshot].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ShardSummary {
+    pub id: ShardId,
+    pub version: ShardVersion,
+    pub status: ShardStatus,
+    pub num_tables: usize,
+}
+
+/// Compact summary of the shards in the set taken by [ShardSet::snapshot],
+/// e.g. to report only the changes by the heartbeat.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ShardSetSnapshot {
+    /// Version of the snapshot, zero if it's not taken from the set.
+    pub version: u64,
+    pub shards: BTreeMap<ShardId, ShardSummary>,
+}
+
+impl ShardSetSnapshot {
+    /// Get the changes of the shards since the `base` snapshot.
+    pub fn diff(&self, base: &ShardSetSnapshot) -> ShardSetDiff {
+        let upserted = self
+            .shards
+            .values()
+            .filter(|summary| base.shards.get(&summary.id) != Some(*summary))
+            .cloned()
+            .collect();
+        let removed = base
+            .shards
+            .keys()
+            .filter(|shard_id| !self.shards.contains_key(shard_id))
+            .cloned()
+            .collect();
+
+        ShardSetDiff {
+            base_version: base.version,
+            version: self.version,
+            upserted,
+            removed,
+        }
+    }
+}
+
+/// Changes of the shards between two snapshots, see [ShardSetSnapshot::diff].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ShardSetDiff {
+    pub base_version: u64,
+    pub version: u64,
+    /// The shards inserted or changed since the base snapshot.
+    pub upserted: Vec<ShardSummary>,
+    /// The shards removed since the base snapshot.
+    pub removed: Vec<ShardId>,
+}
+
+impl ShardSetDiff {
+    pub fn is_empty(&self) -> bool {
+        self.upserted.is_empty() && self.removed.is_empty()
+    }
+}
+
+/// Progress of opening a shard by [ShardSet::open_all].
+#[derive(Debug, Clone)]
+pub enum OpenShardEvent {
+    /// The shard begins to be opened.
+    Begin { shard_id: ShardId },
+    /// The shard is opened successfully.
+    Finish { shard_id: ShardId, cost: Duration },
+    /// The shard fails to be opened.
+    Fail {
+        shard_id: ShardId,
+        cost: Duration,
+        msg: String,
+    },
+}
+
 /// Shard
 ///
 /// NOTICE: all write operations on a shard will be performed sequentially.
 pub struct Shard {
     data: ShardDataRef,
     operator: tokio::sync::Mutex<ShardOperator>,
+    limiter: ShardLimiter,
+    inflight_writes: InflightWrites,
 }
 
 impl std::fmt::Debug for Shard {
@@ -80,15 +347,41 @@ impl std::fmt::Debug for Shard {
 }
 
 impl Shard {
-    pub fn new(tables_of_shard: TablesOfShard) -> Self {
+    pub fn new(tables_of_shard: TablesOfShard, limiter: ShardLimiter) -> Self {
         let data = Arc::new(std::sync::RwLock::new(ShardData {
             shard_info: tables_of_shard.shard_info,
-            tables: tables_of_shard.tables,
+            tables: ShardTables::new(tables_of_shard.tables),
+            listeners: ShardEventListeners::default(),
+            max_tables: limiter.max_tables(),
+            queued_updates: None,
         }));
 
-        let operator = tokio::sync::Mutex::new(ShardOperator { data: data.clone() });
+        let operator = tokio::sync::Mutex::new(ShardOperator {
+            data: data.clone(),
+            retry: ShardRetryConfig::default(),
+        });
 
-        Self { data, operator }
+        Self {
+            data,
+            operator,
+            limiter,
+            inflight_writes: InflightWrites::default(),
+        }
+    }
+
+    /// Set the retries of the engine operations on the shard.
+    pub fn with_retry(mut self, retry: ShardRetryConfig) -> Self {
+        self.operator.get_mut().retry = retry;
+        self
+    }
+
+    /// Queue the table updates while the shard is frozen rather than reject
+    /// them, which are replayed by [Shard::unfreeze].
+    pub fn queue_updates_when_frozen(self, enable: bool) -> Self {
+        if enable {
+            self.data.write().unwrap().enable_update_queue();
+        }
+        self
     }
 
     pub fn shard_info(&self) -> ShardInfo {
@@ -97,6 +390,21 @@ impl Shard {
         data.shard_info.clone()
     }
 
+    /// Get the shard info and the tables of the shard.
+    pub fn tables_of_shard(&self) -> TablesOfShard {
+        let data = self.data.read().unwrap();
+
+        TablesOfShard {
+            shard_info: data.shard_info.clone(),
+            tables: data.tables.to_vec(),
+        }
+    }
+
+    fn set_listeners(&self, listeners: ShardEventListeners) {
+        let mut data = self.data.write().unwrap();
+        data.listeners = listeners;
+    }
+
     pub fn find_table(&self, schema_name: &str, table_name: &str) -> Option<TableInfo> {
         let data = self.data.read().unwrap();
         data.find_table(schema_name, table_name)
@@ -125,12 +433,14 @@ impl Shard {
 
         let ret = operator.open(ctx).await;
 
-        if ret.is_ok() {
+        {
             let mut data = self.data.write().unwrap();
-            data.finish_open();
+            match &ret {
+                Ok(()) => data.finish_open(),
+                // The failed shard can be rescheduled to open again.
+                Err(e) => data.fail(e.to_string()),
+            }
         }
-        // If open failed, shard status is unchanged(`Opening`), so it can be
-        // rescheduled to open again.
 
         ret
     }
@@ -155,12 +465,81 @@ impl Shard {
         operator.close(ctx).await
     }
 
+    /// Drain the shard to hand it over to another node without data loss.
+    ///
+    /// The shard is frozen first so no more writes are accepted, then the
+    /// writes in flight are waited for, and all the tables are flushed at last.
+    /// The returned token should be reported to the meta service, and the
+    /// shard can be closed afterwards.
+    pub async fn drain(&self, ctx: DrainContext) -> Result<ShardHandoverToken> {
+        let operator = self.operator.lock().await;
+        let shard_id = {
+            let mut data = self.data.write().unwrap();
+            ensure!(
+                data.is_opened(),
+                DrainShardNoCause {
+                    msg: format!("shard is not opened, shard_info:{:?}", data.shard_info),
+                }
+            );
+            data.freeze();
+            data.shard_info.id
+        };
+
+        info!("Shard is frozen to drain, wait for the writes in flight, shard_id:{shard_id}");
+        self.inflight_writes.wait_drained().await;
+
+        operator.drain(ctx).await
+    }
+
+    /// Unfreeze the shard, e.g. the migration of it is aborted, and replay the
+    /// table updates queued while it's frozen.
+    ///
+    /// The updates failed to replay are logged and dropped, since the meta
+    /// service will correct the tables of the shard by its version.
+    pub async fn unfreeze(&self) {
+        // Hold the operator to avoid racing with the concurrent drain.
+        let _operator = self.operator.lock().await;
+        let (shard_id, failed) = {
+            let mut data = self.data.write().unwrap();
+            (data.shard_info.id, data.unfreeze())
+        };
+
+        info!("Shard is unfrozen, shard_id:{shard_id}");
+        for (update, e) in failed {
+            warn!(
+                "Failed to replay queued update, shard_id:{shard_id}, update:{update:?}, err:{e}"
+            );
+        }
+    }
+
+    /// Begin to write the shard, the returned guard should be held until the
+    /// write finishes, so [Shard::drain] can wait for it.
+    ///
+    /// Return error if the shard is frozen.
+    pub fn begin_write(&self) -> Result<ShardWriteGuard<'_>> {
+        // Register the write before checking the status, so the write is
+        // either rejected or waited by the concurrent drain.
+        let guard = self.inflight_writes.begin();
+        ensure!(
+            !self.is_frozen(),
+            UpdateFrozenShard {
+                shard_id: self.shard_id(),
+            }
+        );
+
+        Ok(guard)
+    }
+
     pub async fn create_table(&self, ctx: CreateTableContext) -> Result<ShardVersion> {
+        self.check_table_op_limit("create table")?;
+
         let operator = self.operator.lock().await;
         operator.create_table(ctx).await
     }
 
     pub async fn drop_table(&self, ctx: DropTableContext) -> Result<ShardVersion> {
+        self.check_table_op_limit("drop table")?;
+
         let operator = self.operator.lock().await;
         operator.drop_table(ctx).await
     }
@@ -174,16 +553,224 @@ impl Shard {
         let operator = self.operator.lock().await;
         operator.close_table(ctx).await
     }
+
+    /// Check whether the write of `num_rows` rows exceeds the write limit of
+    /// the shard.
+    pub fn check_write_limit(&self, num_rows: u64) -> Result<()> {
+        ensure!(
+            self.limiter.try_acquire_write(num_rows),
+            ShardThrottled {
+                shard_id: self.shard_id(),
+                msg: format!("too many rows are written, num_rows:{num_rows}"),
+            }
+        );
+
+        Ok(())
+    }
+
+    // The open and close operations are not limited because they are necessary
+    // for the shard to be served.
+    fn check_table_op_limit(&self, op: &str) -> Result<()> {
+        ensure!(
+            self.limiter.try_acquire_table_op(),
+            ShardThrottled {
+                shard_id: self.shard_id(),
+                msg: format!("too many table operations, op:{op}"),
+            }
+        );
+
+        Ok(())
+    }
+
+    #[inline]
+    fn shard_id(&self) -> ShardId {
+        self.data.read().unwrap().shard_info.id
+    }
 }
 
 pub type ShardRef = Arc<Shard>;
 
+/// The writes in flight on a shard.
+#[derive(Debug, Default)]
+struct InflightWrites {
+    num: AtomicUsize,
+    drained: Notify,
+}
+
+impl InflightWrites {
+    fn begin(&self) -> ShardWriteGuard<'_> {
+        self.num.fetch_add(1, Ordering::SeqCst);
+        ShardWriteGuard { writes: self }
+    }
+
+    async fn wait_drained(&self) {
+        // A stale notification only leads to another check.
+        while self.num.load(Ordering::SeqCst) > 0 {
+            self.drained.notified().await;
+        }
+    }
+}
+
+/// The guard of a write in flight on a shard.
+pub struct ShardWriteGuard<'a> {
+    writes: &'a InflightWrites,
+}
+
+impl<'a> Drop for ShardWriteGuard<'a> {
+    fn drop(&mut self) {
+        if self.writes.num.fetch_sub(1, Ordering::SeqCst) == 1 {
+            self.writes.drained.notify_one();
+        }
+    }
+}
+
+/// The token produced by draining a shard, which tells the meta service that
+/// all the data of the shard at `shard_version` is persisted.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ShardHandoverToken {
+    pub shard_id: ShardId,
+    pub shard_version: ShardVersion,
+    pub table_ids: Vec<TableId>,
+    pub drained_at: Timestamp,
+}
+
+impl ShardHandoverToken {
+    pub fn new(shard_id: ShardId, shard_version: ShardVersion, table_ids: Vec<TableId>) -> Self {
+        Self {
+            shard_id,
+            shard_version,
+            table_ids,
+            drained_at: Timestamp::now(),
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct UpdatedTableInfo {
     pub shard_info: ShardInfo,
     pub table_info: TableInfo,
 }
 
+/// Tables of the shard, indexed by the name and the id.
+#[derive(Debug, Default)]
+pub struct ShardTables {
+    /// Schema name -> table name -> table
+    tables_by_name: HashMap<String, HashMap<String, TableInfo>>,
+    /// Table id -> (schema name, table name)
+    names_by_id: HashMap<TableId, (String, String)>,
+}
+
+impl ShardTables {
+    pub fn new(tables: Vec<TableInfo>) -> Self {
+        let mut shard_tables = Self::default();
+        for table in tables {
+            shard_tables.insert(table);
+        }
+
+        shard_tables
+    }
+
+    pub fn get(&self, schema_name: &str, table_name: &str) -> Option<&TableInfo> {
+        self.tables_by_name.get(schema_name)?.get(table_name)
+    }
+
+    #[inline]
+    pub fn contains_id(&self, table_id: TableId) -> bool {
+        self.names_by_id.contains_key(&table_id)
+    }
+
+    /// Insert the table, the table with the same name or id is replaced.
+    pub fn insert(&mut self, table: TableInfo) {
+        self.remove_by_id(table.id);
+        if let Some(old_table) = self.get(&table.schema_name, &table.name) {
+            let old_table_id = old_table.id;
+            self.remove_by_id(old_table_id);
+        }
+
+        self.names_by_id
+            .insert(table.id, (table.schema_name.clone(), table.name.clone()));
+        self.tables_by_name
+            .entry(table.schema_name.clone())
+            .or_default()
+            .insert(table.name.clone(), table);
+    }
+
+    pub fn remove_by_id(&mut self, table_id: TableId) -> Option<TableInfo> {
+        let (schema_name, table_name) = self.names_by_id.remove(&table_id)?;
+        let tables = self.tables_by_name.get_mut(&schema_name)?;
+        let table = tables.remove(&table_name);
+        if tables.is_empty() {
+            self.tables_by_name.remove(&schema_name);
+        }
+
+        table
+    }
+
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.names_by_id.len()
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.names_by_id.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &TableInfo> {
+        self.tables_by_name
+            .values()
+            .flat_map(|tables| tables.values())
+    }
+
+    pub fn to_vec(&self) -> Vec<TableInfo> {
+        self.iter().cloned().collect()
+    }
+}
+
+/// Tables changed by [ShardData::apply_diff], a table recreated with another
+/// id is both removed and added.
+#[derive(Debug, Clone, Default)]
+pub struct ShardTablesDiff {
+    /// Tables in the target but not in the shard, which should be opened.
+    pub added: Vec<TableInfo>,
+    /// Tables in the shard but not in the target, which should be closed.
+    pub removed: Vec<TableInfo>,
+}
+
+impl ShardTablesDiff {
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty()
+    }
+}
+
+/// Max number of table updates queued on a frozen shard, the following ones
+/// are rejected.
+const MAX_QUEUED_UPDATES: usize = 1024;
+
+/// A table update on the frozen shard, which is replayed once the shard is
+/// unfrozen, see [Shard::unfreeze].
+#[derive(Debug, Clone)]
+pub enum QueuedTableUpdate {
+    Create(UpdatedTableInfo),
+    Drop(UpdatedTableInfo),
+    Open(UpdatedTableInfo),
+    Close(UpdatedTableInfo),
+}
+
+impl QueuedTableUpdate {
+    pub fn updated_info(&self) -> &UpdatedTableInfo {
+        match self {
+            Self::Create(v) | Self::Drop(v) | Self::Open(v) | Self::Close(v) => v,
+        }
+    }
+
+    #[inline]
+    fn inc_version(&self) -> bool {
+        matches!(self, Self::Create(_) | Self::Drop(_))
+    }
+}
+
 /// Shard data
 #[derive(Debug)]
 pub struct ShardData {
@@ -191,20 +778,35 @@ pub struct ShardData {
     pub shard_info: ShardInfo,
 
     /// Tables in shard
-    pub tables: Vec<TableInfo>,
+    pub tables: ShardTables,
+
+    /// Listeners of the shard set the shard belongs to
+    listeners: ShardEventListeners,
+
+    /// Max number of tables, `None` if unlimited
+    max_tables: Option<usize>,
+
+    /// Table updates queued while frozen, `None` if they are rejected instead
+    queued_updates: Option<Vec<QueuedTableUpdate>>,
 }
 
 impl ShardData {
     pub fn find_table(&self, schema_name: &str, table_name: &str) -> Option<TableInfo> {
-        self.tables
-            .iter()
-            .find(|table| table.schema_name == schema_name && table.name == table_name)
-            .cloned()
+        self.tables.get(schema_name, table_name).cloned()
     }
 
     #[inline]
     pub fn freeze(&mut self) {
         self.shard_info.status = ShardStatus::Frozen;
+        self.listeners
+            .notify(|listener| listener.on_freeze(&self.shard_info));
+    }
+
+    /// Mark the shard as failed to be opened, the tables opened already are
+    /// still served like the opening shard.
+    #[inline]
+    pub fn fail(&mut self, reason: String) {
+        self.shard_info.status = ShardStatus::Failed { reason };
     }
 
     #[inline]
@@ -234,21 +836,123 @@ impl ShardData {
         matches!(self.shard_info.status, ShardStatus::Frozen)
     }
 
+    fn ensure_table_capacity(&self, num_tables: usize) -> Result<()> {
+        if let Some(max_tables) = self.max_tables {
+            ensure!(
+                num_tables <= max_tables,
+                TooManyTables {
+                    shard_id: self.shard_info.id,
+                    max_tables,
+                }
+            );
+        }
+
+        Ok(())
+    }
+
     #[inline]
     fn inc_shard_version(&mut self) {
+        let old_version = self.shard_info.version;
         self.shard_info.version += 1;
+        self.listeners.notify(|listener| {
+            listener.on_version_change(self.shard_info.id, old_version, self.shard_info.version)
+        });
+    }
+
+    /// Queue the table updates while the shard is frozen rather than reject
+    /// them.
+    pub fn enable_update_queue(&mut self) {
+        self.queued_updates.get_or_insert_with(Vec::new);
+    }
+
+    /// Queue the update on the frozen shard, and return the version of the
+    /// shard after the update is replayed.
+    ///
+    /// The version of the update must follow the queued ones, so the queued
+    /// updates can be replayed in order.
+    fn queue_update(&mut self, update: QueuedTableUpdate) -> Result<ShardVersion> {
+        let shard_id = self.shard_info.id;
+        let Some(queued) = &self.queued_updates else {
+            return UpdateFrozenShard { shard_id }.fail();
+        };
+        ensure!(
+            queued.len() < MAX_QUEUED_UPDATES,
+            UpdateFrozenShard { shard_id }
+        );
+
+        let num_version_changes = queued.iter().filter(|v| v.inc_version()).count();
+        let expect_version = self.shard_info.version + num_version_changes as ShardVersion;
+        let update_version = update.updated_info().shard_info.version;
+        ensure!(
+            update_version == expect_version,
+            ShardVersionMismatch {
+                shard_info: self.shard_info.clone(),
+                expect_version: update_version,
+            }
+        );
+
+        let version = expect_version + ShardVersion::from(update.inc_version());
+        info!("Queue table update on frozen shard, shard_id:{shard_id}, update:{update:?}");
+        self.queued_updates
+            .get_or_insert_with(Vec::new)
+            .push(update);
+
+        Ok(version)
+    }
+
+    /// Unfreeze the shard, and replay the table updates queued while it's
+    /// frozen in order.
+    ///
+    /// The failed updates are returned along with the errors, e.g. the table to
+    /// create exists already.
+    pub fn unfreeze(&mut self) -> Vec<(QueuedTableUpdate, Error)> {
+        if !self.is_frozen() {
+            return Vec::new();
+        }
+
+        self.shard_info.status = ShardStatus::Ready;
+        let queued = self
+            .queued_updates
+            .as_mut()
+            .map(std::mem::take)
+            .unwrap_or_default();
+        let mut failed = Vec::new();
+        for update in queued {
+            let ret = match update.clone() {
+                QueuedTableUpdate::Create(v) => self.try_insert_table(v, true),
+                QueuedTableUpdate::Drop(v) => self.try_remove_table(v, true),
+                QueuedTableUpdate::Open(v) => self.try_insert_table(v, false),
+                QueuedTableUpdate::Close(v) => self.try_remove_table(v, false),
+            };
+            if let Err(e) = ret {
+                failed.push((update, e));
+            }
+        }
+
+        failed
     }
 
     /// Create the table on the shard, whose version will be incremented.
+    ///
+    /// The update is queued if the shard is frozen and the queue is enabled.
     #[inline]
     pub fn try_create_table(&mut self, updated_info: UpdatedTableInfo) -> Result<ShardVersion> {
+        if self.is_frozen() {
+            return self.queue_update(QueuedTableUpdate::Create(updated_info));
+        }
         self.try_insert_table(updated_info, true)
     }
 
     /// Open the table on the shard, whose version won't change.
+    ///
+    /// The update is queued if the shard is frozen and the queue is enabled.
     #[inline]
     pub fn try_open_table(&mut self, updated_info: UpdatedTableInfo) -> Result<()> {
-        self.try_insert_table(updated_info, false)?;
+        if self.is_frozen() {
+            self.queue_update(QueuedTableUpdate::Open(updated_info))?;
+        } else {
+            self.try_insert_table(updated_info, false)?;
+        }
 
         Ok(())
     }
@@ -282,16 +986,21 @@ impl ShardData {
             }
         );
 
-        let table = self.tables.iter().find(|v| v.id == new_table.id);
         ensure!(
-            table.is_none(),
+            !self.tables.contains_id(new_table.id)
+                && self
+                    .tables
+                    .get(&new_table.schema_name, &new_table.name)
+                    .is_none(),
             TableAlreadyExists {
                 msg: "the table to insert has already existed",
             }
         );
 
+        self.ensure_table_capacity(self.tables.len() + 1)?;
+
         // Insert the new table into the shard.
-        self.tables.push(new_table);
+        self.tables.insert(new_table);
 
         // Update the shard version if necessary.
         if inc_version {
@@ -301,16 +1010,101 @@ impl ShardData {
         Ok(self.shard_info.version)
     }
 
+    /// Converge the tables and the version of the shard to `target`, which is
+    /// the view of the meta service, e.g. carried by the heartbeat.
+    ///
+    /// The target is validated before anything is changed, so either all the
+    /// changes are applied or none is. The status and the role of the shard
+    /// are kept.
+    pub fn apply_diff(&mut self, target: TablesOfShard) -> Result<ShardTablesDiff> {
+        let TablesOfShard {
+            shard_info: target_info,
+            tables: target_tables,
+        } = target;
+
+        ensure!(
+            self.shard_info.id == target_info.id,
+            InvalidArguments {
+                msg: format!(
+                    "shard id mismatch, shard_id:{}, target_shard_id:{}",
+                    self.shard_info.id, target_info.id
+                ),
+            }
+        );
+
+        ensure!(
+            !self.is_frozen(),
+            UpdateFrozenShard {
+                shard_id: self.shard_info.id,
+            }
+        );
+
+        // The shard never goes back to an older version.
+        ensure!(
+            self.shard_info.version <= target_info.version,
+            ShardVersionMismatch {
+                shard_info: self.shard_info.clone(),
+                expect_version: target_info.version,
+            }
+        );
+
+        let num_target_tables = target_tables.len();
+        let tables = ShardTables::new(target_tables);
+        ensure!(
+            tables.len() == num_target_tables,
+            TableAlreadyExists {
+                msg: "the target has tables with the same name or id",
+            }
+        );
+        self.ensure_table_capacity(tables.len())?;
+
+        let diff = |from: &ShardTables, to: &ShardTables| {
+            from.iter()
+                .filter(|table| {
+                    to.get(&table.schema_name, &table.name)
+                        .map_or(true, |v| v.id != table.id)
+                })
+                .cloned()
+                .collect()
+        };
+        let changes = ShardTablesDiff {
+            added: diff(&tables, &self.tables),
+            removed: diff(&self.tables, &tables),
+        };
+
+        self.tables = tables;
+        let old_version = self.shard_info.version;
+        self.shard_info.version = target_info.version;
+        if old_version != target_info.version {
+            self.listeners.notify(|listener| {
+                listener.on_version_change(self.shard_info.id, old_version, target_info.version)
+            });
+        }
+
+        Ok(changes)
+    }
+
     /// Drop the table from the shard, whose version will be incremented.
+    ///
+    /// The update is queued if the shard is frozen and the queue is enabled.
     #[inline]
     pub fn try_drop_table(&mut self, updated_info: UpdatedTableInfo) -> Result<ShardVersion> {
+        if self.is_frozen() {
+            return self.queue_update(QueuedTableUpdate::Drop(updated_info));
+        }
         self.try_remove_table(updated_info, true)
     }
 
     /// Close the table from the shard, whose version won't change.
+    ///
+    /// The update is queued if the shard is frozen and the queue is enabled.
     #[inline]
     pub fn try_close_table(&mut self, updated_info: UpdatedTableInfo) -> Result<()> {
-        self.try_remove_table(updated_info, false)?;
+        if self.is_frozen() {
+            self.queue_update(QueuedTableUpdate::Close(updated_info))?;
+        } else {
+            self.try_remove_table(updated_info, false)?;
+        }
 
         Ok(())
     }
@@ -344,17 +1138,13 @@ impl ShardData {
             }
         );
 
-        let table_idx = self
-            .tables
-            .iter()
-            .position(|v| v.id == new_table.id)
+        // Remove the table from the shard.
+        self.tables
+            .remove_by_id(new_table.id)
             .with_context(|| TableNotFound {
                 msg: format!("the table to remove is not found, table:{new_table:?}"),
             })?;
 
-        // Remove the table from the shard.
-        self.tables.swap_remove(table_idx);
-
         // Update the shard version if necessary.
         if inc_version {
             self.inc_shard_version();
@@ -365,3 +1155,543 @@ impl ShardData {
 }
 
 pub type ShardDataRef = Arc<std::sync::RwLock<ShardData>>;
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use catalog::{table_operator::TableOperator, test_util::MockCatalogManagerBuilder};
+    use table_engine::memory::MemoryTableEngine;
+    use time_ext::clock::LogicalClock;
+
+    use super::*;
+    use crate::{shard_limiter::ShardLimitConfig, Error};
+
+    fn new_shard(shard_id: ShardId, status: ShardStatus) -> ShardRef {
+        let tables_of_shard = TablesOfShard {
+            shard_info: ShardInfo {
+                id: shard_id,
+                status,
+                ..Default::default()
+            },
+            tables: Vec::new(),
+        };
+
+        Arc::new(Shard::new(tables_of_shard, ShardLimiter::unlimited()))
+    }
+
+    fn new_open_context() -> OpenContext {
+        let catalog_manager =
+            MockCatalogManagerBuilder::new("horaedb".to_string(), "public".to_string(), vec![])
+                .build();
+
+        OpenContext {
+            catalog: "horaedb".to_string(),
+            table_engine: Arc::new(MemoryTableEngine),
+            table_operator: TableOperator::new(catalog_manager),
+            engine: "Memory".to_string(),
+        }
+    }
+
+    fn new_table(id: TableId, schema_name: &str, name: &str) -> TableInfo {
+        TableInfo {
+            id,
+            name: name.to_string(),
+            schema_id: 0,
+            schema_name: schema_name.to_string(),
+            partition_info: None,
+        }
+    }
+
+    #[test]
+    fn test_shard_tables() {
+        let mut tables = ShardTables::new(vec![
+            new_table(1, "public", "a"),
+            new_table(2, "public", "b"),
+            new_table(3, "test", "a"),
+        ]);
+        assert_eq!(tables.len(), 3);
+        assert_eq!(tables.get("public", "a").unwrap().id, 1);
+        assert_eq!(tables.get("test", "a").unwrap().id, 3);
+        assert!(tables.get("test", "b").is_none());
+        assert!(tables.contains_id(2));
+
+        // The table with the same name is replaced.
+        tables.insert(new_table(4, "public", "a"));
+        assert_eq!(tables.len(), 3);
+        assert_eq!(tables.get("public", "a").unwrap().id, 4);
+        assert!(!tables.contains_id(1));
+
+        assert_eq!(tables.remove_by_id(3).unwrap().name, "a");
+        assert!(tables.remove_by_id(3).is_none());
+        assert!(tables.get("test", "a").is_none());
+
+        let mut ids: Vec<_> = tables.iter().map(|table| table.id).collect();
+        ids.sort();
+        assert_eq!(ids, vec![2, 4]);
+    }
+
+    #[test]
+    fn test_update_tables_of_shard_data() {
+        let shard_info = ShardInfo {
+            id: 0,
+            status: ShardStatus::Ready,
+            ..Default::default()
+        };
+        let mut data = ShardData {
+            shard_info: shard_info.clone(),
+            tables: ShardTables::default(),
+            listeners: ShardEventListeners::default(),
+            max_tables: None,
+            queued_updates: None,
+        };
+
+        let updated_info = |version, table| UpdatedTableInfo {
+            shard_info: ShardInfo {
+                version,
+                ..shard_info.clone()
+            },
+            table_info: table,
+        };
+        assert_eq!(
+            data.try_create_table(updated_info(0, new_table(1, "public", "a")))
+                .unwrap(),
+            1
+        );
+        assert_eq!(data.find_table("public", "a").unwrap().id, 1);
+
+        // The table with the same id or name exists already.
+        assert!(data
+            .try_create_table(updated_info(1, new_table(1, "public", "b")))
+            .is_err());
+        assert!(data
+            .try_create_table(updated_info(1, new_table(2, "public", "a")))
+            .is_err());
+
+        assert_eq!(
+            data.try_drop_table(updated_info(1, new_table(1, "public", "a")))
+                .unwrap(),
+            2
+        );
+        assert!(data.find_table("public", "a").is_none());
+        assert!(data
+            .try_drop_table(updated_info(2, new_table(1, "public", "a")))
+            .is_err());
+    }
+
+    #[test]
+    fn test_apply_diff_of_shard_data() {
+        let shard_info = ShardInfo {
+            id: 0,
+            version: 1,
+            status: ShardStatus::Ready,
+            ..Default::default()
+        };
+        let mut data = ShardData {
+            shard_info: shard_info.clone(),
+            tables: ShardTables::new(vec![
+                new_table(1, "public", "a"),
+                new_table(2, "public", "b"),
+                new_table(3, "public", "c"),
+            ]),
+            listeners: ShardEventListeners::default(),
+            max_tables: None,
+            queued_updates: None,
+        };
+        let target = |version, tables| TablesOfShard {
+            shard_info: ShardInfo {
+                version,
+                ..shard_info.clone()
+            },
+            tables,
+        };
+
+        // The target is older, or has duplicate tables.
+        assert!(data
+            .apply_diff(target(0, vec![new_table(1, "public", "a")]))
+            .is_err());
+        assert!(data
+            .apply_diff(target(
+                2,
+                vec![new_table(1, "public", "a"), new_table(4, "public", "a")]
+            ))
+            .is_err());
+        assert_eq!(data.tables.len(), 3);
+        assert_eq!(data.shard_info.version, 1);
+
+        // Table `b` is dropped, `c` is recreated and `d` is created.
+        let diff = data
+            .apply_diff(target(
+                3,
+                vec![
+                    new_table(1, "public", "a"),
+                    new_table(4, "public", "c"),
+                    new_table(5, "public", "d"),
+                ],
+            ))
+            .unwrap();
+        let ids = |tables: &[TableInfo]| {
+            let mut ids: Vec<_> = tables.iter().map(|table| table.id).collect();
+            ids.sort();
+            ids
+        };
+        assert_eq!(ids(&diff.added), vec![4, 5]);
+        assert_eq!(ids(&diff.removed), vec![2, 3]);
+        assert_eq!(data.shard_info.version, 3);
+        assert_eq!(data.shard_info.status, ShardStatus::Ready);
+        assert_eq!(data.find_table("public", "c").unwrap().id, 4);
+
+        // Applying the same target again changes nothing.
+        let diff = data.apply_diff(target(3, data.tables.to_vec())).unwrap();
+        assert!(diff.is_empty());
+
+        data.freeze();
+        assert!(data.apply_diff(target(4, Vec::new())).is_err());
+    }
+
+    #[test]
+    fn test_snapshot_diff_of_shard_set() {
+        let shard_set = ShardSet::default();
+        for shard_id in 0..3 {
+            shard_set
+                .insert(shard_id, new_shard(shard_id, ShardStatus::Ready))
+                .unwrap();
+        }
+        let base = shard_set.snapshot();
+        assert_eq!(base.shards.len(), 3);
+        let diff = base.diff(&ShardSetSnapshot::default());
+        assert_eq!(diff.upserted.len(), 3);
+        assert!(diff.removed.is_empty());
+
+        let snapshot = shard_set.snapshot();
+        assert!(snapshot.version > base.version);
+        assert!(snapshot.diff(&base).is_empty());
+
+        // Shard 0 is removed, 1 gets a new table and 3 is inserted.
+        shard_set.remove(0);
+        {
+            let shard = shard_set.get(1).unwrap();
+            let mut data = shard.data.write().unwrap();
+            let updated_info = UpdatedTableInfo {
+                shard_info: data.shard_info.clone(),
+                table_info: new_table(1, "public", "a"),
+            };
+            data.try_create_table(updated_info).unwrap();
+        }
+        shard_set
+            .insert(3, new_shard(3, ShardStatus::Ready))
+            .unwrap();
+
+        let snapshot = shard_set.snapshot();
+        let diff = snapshot.diff(&base);
+        assert_eq!(diff.base_version, base.version);
+        assert_eq!(diff.version, snapshot.version);
+        assert_eq!(diff.removed, vec![0]);
+        let upserted: Vec<_> = diff
+            .upserted
+            .iter()
+            .map(|summary| (summary.id, summary.version, summary.num_tables))
+            .collect();
+        assert_eq!(upserted, vec![(1, 1, 1), (3, 0, 0)]);
+    }
+
+    #[test]
+    fn test_queue_updates_of_frozen_shard() {
+        let shard_info = ShardInfo {
+            id: 0,
+            status: ShardStatus::Ready,
+            ..Default::default()
+        };
+        let mut data = ShardData {
+            shard_info: shard_info.clone(),
+            tables: ShardTables::new(vec![new_table(1, "public", "a")]),
+            listeners: ShardEventListeners::default(),
+            max_tables: None,
+            queued_updates: None,
+        };
+        let updated_info = |version, table| UpdatedTableInfo {
+            shard_info: ShardInfo {
+                version,
+                ..shard_info.clone()
+            },
+            table_info: table,
+        };
+
+        // The updates are rejected by default.
+        data.freeze();
+        assert!(matches!(
+            data.try_create_table(updated_info(0, new_table(2, "public", "b"))),
+            Err(Error::UpdateFrozenShard { .. })
+        ));
+
+        data.enable_update_queue();
+        assert_eq!(
+            data.try_create_table(updated_info(0, new_table(2, "public", "b")))
+                .unwrap(),
+            1
+        );
+        data.try_close_table(updated_info(1, new_table(1, "public", "a")))
+            .unwrap();
+        // The version must follow the queued updates.
+        assert!(matches!(
+            data.try_drop_table(updated_info(0, new_table(2, "public", "b"))),
+            Err(Error::ShardVersionMismatch { .. })
+        ));
+        // The table to create exists already, which fails on replaying.
+        assert_eq!(
+            data.try_create_table(updated_info(1, new_table(3, "public", "b")))
+                .unwrap(),
+            2
+        );
+        assert_eq!(data.tables.len(), 1);
+        assert_eq!(data.shard_info.version, 0);
+
+        let failed = data.unfreeze();
+        assert_eq!(failed.len(), 1);
+        assert_eq!(failed[0].0.updated_info().table_info.id, 3);
+        assert!(!data.is_frozen());
+        assert_eq!(data.shard_info.version, 1);
+        assert!(data.find_table("public", "a").is_none());
+        assert_eq!(data.find_table("public", "b").unwrap().id, 2);
+        assert!(data.queued_updates.as_ref().unwrap().is_empty());
+
+        // Nothing to replay on the shard not frozen.
+        assert!(data.unfreeze().is_empty());
+    }
+
+    #[test]
+    fn test_shard_capacity_limits() {
+        let shard_set = ShardSet::default();
+        shard_set.set_max_shards(2);
+        for shard_id in 0..2 {
+            shard_set
+                .insert(shard_id, new_shard(shard_id, ShardStatus::Ready))
+                .unwrap();
+        }
+        assert!(matches!(
+            shard_set.insert(2, new_shard(2, ShardStatus::Ready)),
+            Err(Error::TooManyShards { max_shards: 2, .. })
+        ));
+        assert!(shard_set.get(2).is_none());
+        // The shard in the set can still be replaced.
+        assert!(shard_set
+            .insert(1, new_shard(1, ShardStatus::Ready))
+            .unwrap()
+            .is_some());
+
+        let config = ShardLimitConfig {
+            max_tables_per_shard: 1,
+            ..Default::default()
+        };
+        let limiter = ShardLimiter::new(&config, Arc::new(LogicalClock::new(0)));
+        let tables_of_shard = TablesOfShard {
+            shard_info: ShardInfo {
+                id: 0,
+                status: ShardStatus::Ready,
+                ..Default::default()
+            },
+            tables: Vec::new(),
+        };
+        let shard = Shard::new(tables_of_shard, limiter);
+        let mut data = shard.data.write().unwrap();
+        for (table, ok) in [
+            (new_table(1, "public", "a"), true),
+            (new_table(2, "public", "b"), false),
+        ] {
+            let updated_info = UpdatedTableInfo {
+                shard_info: data.shard_info.clone(),
+                table_info: table,
+            };
+            let ret = data.try_create_table(updated_info);
+            assert_eq!(ok, ret.is_ok());
+            if !ok {
+                assert!(matches!(
+                    ret,
+                    Err(Error::TooManyTables { max_tables: 1, .. })
+                ));
+            }
+        }
+        assert_eq!(data.tables.len(), 1);
+    }
+
+    #[derive(Default)]
+    struct RecordingListener {
+        events: Mutex<Vec<String>>,
+    }
+
+    impl ShardEventListener for RecordingListener {
+        fn on_insert(&self, shard_info: &ShardInfo) {
+            let event = format!("insert:{}:{}", shard_info.id, shard_info.version);
+            self.events.lock().unwrap().push(event);
+        }
+
+        fn on_remove(&self, shard_info: &ShardInfo) {
+            let event = format!("remove:{}:{}", shard_info.id, shard_info.version);
+            self.events.lock().unwrap().push(event);
+        }
+
+        fn on_version_change(
+            &self,
+            shard_id: ShardId,
+            old_version: ShardVersion,
+            new_version: ShardVersion,
+        ) {
+            let event = format!("version:{shard_id}:{old_version}->{new_version}");
+            self.events.lock().unwrap().push(event);
+        }
+
+        fn on_freeze(&self, shard_info: &ShardInfo) {
+            let event = format!("freeze:{}", shard_info.id);
+            self.events.lock().unwrap().push(event);
+        }
+    }
+
+    #[test]
+    fn test_shard_event_listener() {
+        let shard_set = ShardSet::default();
+        // The listener is notified of the shards inserted before registration too.
+        let shard = new_shard(0, ShardStatus::Ready);
+        shard_set.insert(0, shard.clone()).unwrap();
+        let listener = Arc::new(RecordingListener::default());
+        shard_set.register_listener(listener.clone());
+
+        {
+            let mut data = shard.data.write().unwrap();
+            let updated_info = UpdatedTableInfo {
+                shard_info: data.shard_info.clone(),
+                table_info: new_table(1, "public", "a"),
+            };
+            data.try_create_table(updated_info).unwrap();
+            data.freeze();
+        }
+        shard_set
+            .insert(0, new_shard(0, ShardStatus::Ready))
+            .unwrap();
+        shard_set.remove(0);
+        assert!(shard_set.remove(0).is_none());
+
+        let events = listener.events.lock().unwrap().clone();
+        assert_eq!(
+            events,
+            vec![
+                "version:0:0->1",
+                "freeze:0",
+                "remove:0:1",
+                "insert:0:0",
+                "remove:0:0",
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_open_all_shards() {
+        // The last shard is opened already, so it fails to be opened again.
+        let shards: Vec<_> = (0..8)
+            .map(|shard_id| {
+                let status = if shard_id == 7 {
+                    ShardStatus::Ready
+                } else {
+                    ShardStatus::Init
+                };
+                (new_shard(shard_id, status), new_open_context())
+            })
+            .collect();
+        let shard_refs: Vec<_> = shards.iter().map(|(shard, _)| shard.clone()).collect();
+
+        let events = Mutex::new(Vec::new());
+        let results =
+            ShardSet::open_all(shards, 3, |event| events.lock().unwrap().push(event)).await;
+
+        assert_eq!(8, results.len());
+        for (shard, result) in shard_refs.iter().zip(results.iter()).take(7) {
+            assert!(result.is_ok());
+            assert!(shard.is_opened());
+        }
+        assert!(results[7].is_err());
+
+        let events = events.into_inner().unwrap();
+        let begin_num = events
+            .iter()
+            .filter(|e| matches!(e, OpenShardEvent::Begin { .. }))
+            .count();
+        let finish_num = events
+            .iter()
+            .filter(|e| matches!(e, OpenShardEvent::Finish { .. }))
+            .count();
+        let failed_shards: Vec<_> = events
+            .iter()
+            .filter_map(|e| match e {
+                OpenShardEvent::Fail { shard_id, .. } => Some(*shard_id),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(8, begin_num);
+        assert_eq!(7, finish_num);
+        assert_eq!(vec![7], failed_shards);
+    }
+
+    #[tokio::test]
+    async fn test_open_shard_failed() {
+        let tables_of_shard = TablesOfShard {
+            shard_info: ShardInfo {
+                id: 1,
+                ..Default::default()
+            },
+            tables: vec![new_table(1, "not_exist", "a")],
+        };
+        let shard = Shard::new(tables_of_shard, ShardLimiter::unlimited());
+
+        // The schema is never found, so the shard fails without retries.
+        assert!(shard.open(new_open_context()).await.is_err());
+        let ShardStatus::Failed { reason } = shard.get_status() else {
+            panic!("shard should be failed, status:{:?}", shard.get_status());
+        };
+        assert!(reason.contains("not_exist"), "{reason}");
+        assert!(!shard.is_opened());
+        assert!(!shard.is_frozen());
+
+        // The failed shard can be opened again.
+        assert!(shard.open(new_open_context()).await.is_err());
+        assert!(matches!(shard.get_status(), ShardStatus::Failed { .. }));
+    }
+
+    fn new_drain_context() -> DrainContext {
+        let OpenContext {
+            catalog,
+            table_operator,
+            engine,
+            ..
+        } = new_open_context();
+
+        DrainContext {
+            catalog,
+            table_operator,
+            engine,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_drain_shard() {
+        let shard = new_shard(1, ShardStatus::Init);
+        assert!(shard.drain(new_drain_context()).await.is_err());
+
+        let shard = new_shard(1, ShardStatus::Ready);
+        let guard = shard.begin_write().unwrap();
+        let drain = {
+            let shard = shard.clone();
+            tokio::spawn(async move { shard.drain(new_drain_context()).await })
+        };
+
+        // The drain waits for the write in flight, and new writes are rejected.
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert!(!drain.is_finished());
+        assert!(shard.is_frozen());
+        assert!(shard.begin_write().is_err());
+
+        drop(guard);
+        let token = drain.await.unwrap().unwrap();
+        assert_eq!(token.shard_id, 1);
+        assert_eq!(token.shard_version, shard.shard_info().version);
+        assert!(token.table_ids.is_empty());
+    }
+}
@@ -0,0 +1,666 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+//! A [Cluster] whose topology is declared in a file instead of being managed
+//! by HoraeMeta, which suits the small clusters with fixed nodes.
+//!
+//! The topology file assigns the shards to the nodes:
+//!
+//! ```toml
+//! [[nodes]]
+//! endpoint = "127.0.0.1:8831"
+//! shards = [0, 1]
+//!
+//! [[nodes]]
+//! endpoint = "127.0.0.1:18831"
+//! shards = [2, 3]
+//! ```
+//!
+//! A table belongs to the shard chosen by the hash of its name, so the shard
+//! ids must be continuous from 0. The file is reloaded periodically, and the
+//! shards can be moved between the nodes without restarting them, but the
+//! number of shards can't be changed because the tables would be rehashed.
+
+use std::{
+    collections::{HashMap, HashSet},
+    sync::{Arc, Mutex, RwLock},
+};
+
+use async_trait::async_trait;
+use common_types::table::ShardId;
+use generic_error::BoxError;
+use hash_ext::hash64;
+use logger::{error, info, warn};
+use meta_client::types::{
+    NodeShard, RouteEntry, RouteTablesRequest, RouteTablesResponse, ShardInfo, ShardRole,
+    ShardStatus, TableInfo, TablesOfShard,
+};
+use runtime::{JoinHandle, Runtime};
+use serde::{Deserialize, Serialize};
+use snafu::{ensure, OptionExt, ResultExt};
+use time_ext::clock::{ClockRef, SystemClock};
+use tokio::{
+    sync::mpsc::{self, Sender},
+    time,
+};
+
+use crate::{
+    config::{ShardRetryConfig, StaticClusterConfig},
+    shard_limiter::{ShardLimitConfig, ShardLimiter},
+    shard_lock_manager::ShardLockManagerRef,
+    shard_set::{Shard, ShardRef, ShardSet},
+    Cluster, ClusterNodesResp, InvalidArguments, LoadStaticTopology, NodeType, Result,
+    ShardNotFound, TableStatus,
+};
+
+/// The shards assigned to a node.
+#[derive(Clone, Debug, Default, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(default)]
+pub struct NodeShards {
+    /// Endpoint of the node, in the form of `addr:grpc_port`.
+    pub endpoint: String,
+    pub shards: Vec<ShardId>,
+}
+
+/// The topology declared in the file.
+#[derive(Clone, Debug, Default, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(default)]
+pub struct StaticTopology {
+    pub nodes: Vec<NodeShards>,
+}
+
+impl StaticTopology {
+    /// Load the topology from the toml file, and it is validated.
+    pub fn load(path: &str) -> Result<Self> {
+        let mut toml_buf = String::new();
+        let topology: Self = toml_ext::parse_toml_from_path(path, &mut toml_buf)
+            .box_err()
+            .context(LoadStaticTopology { path })?;
+        topology.validate()?;
+
+        Ok(topology)
+    }
+
+    pub fn validate(&self) -> Result<()> {
+        ensure!(
+            !self.nodes.is_empty(),
+            InvalidArguments {
+                msg: "no node is declared in the topology",
+            }
+        );
+
+        let mut endpoints = HashSet::with_capacity(self.nodes.len());
+        let mut shard_ids = HashSet::new();
+        for node in &self.nodes {
+            let valid_endpoint = node
+                .endpoint
+                .rsplit_once(':')
+                .map(|(addr, port)| !addr.is_empty() && port.parse::<u16>().is_ok())
+                .unwrap_or(false);
+            ensure!(
+                valid_endpoint,
+                InvalidArguments {
+                    msg: format!("invalid endpoint of node, endpoint:{}", node.endpoint),
+                }
+            );
+            ensure!(
+                endpoints.insert(node.endpoint.as_str()),
+                InvalidArguments {
+                    msg: format!("node is declared repeatedly, endpoint:{}", node.endpoint),
+                }
+            );
+
+            for shard_id in &node.shards {
+                ensure!(
+                    shard_ids.insert(*shard_id),
+                    InvalidArguments {
+                        msg: format!("shard is assigned to multiple nodes, shard_id:{shard_id}"),
+                    }
+                );
+            }
+        }
+
+        let num_shards = shard_ids.len();
+        ensure!(
+            num_shards > 0,
+            InvalidArguments {
+                msg: "no shard is declared in the topology",
+            }
+        );
+        ensure!(
+            (0..num_shards as ShardId).all(|shard_id| shard_ids.contains(&shard_id)),
+            InvalidArguments {
+                msg: format!("shard ids must be continuous from 0, num_shards:{num_shards}"),
+            }
+        );
+
+        Ok(())
+    }
+
+    #[inline]
+    pub fn num_shards(&self) -> usize {
+        self.nodes.iter().map(|node| node.shards.len()).sum()
+    }
+
+    fn shards_of_node(&self, endpoint: &str) -> Vec<ShardId> {
+        self.nodes
+            .iter()
+            .find(|node| node.endpoint == endpoint)
+            .map(|node| node.shards.clone())
+            .unwrap_or_default()
+    }
+
+    /// Find the shard of the table and the endpoint of the node holding it.
+    fn locate_table(&self, schema_name: &str, table_name: &str) -> Option<(ShardId, &str)> {
+        let num_shards = self.num_shards() as u64;
+        if num_shards == 0 {
+            return None;
+        }
+
+        let key = format!("{schema_name}.{table_name}");
+        let shard_id = (hash64(key.as_bytes()) % num_shards) as ShardId;
+        self.nodes
+            .iter()
+            .find(|node| node.shards.contains(&shard_id))
+            .map(|node| (shard_id, node.endpoint.as_str()))
+    }
+}
+
+fn static_shard_info(shard_id: ShardId) -> ShardInfo {
+    ShardInfo {
+        id: shard_id,
+        role: ShardRole::Leader,
+        version: 0,
+        status: ShardStatus::Ready,
+    }
+}
+
+/// StaticClusterImpl is an implementation of [`Cluster`] based on the topology
+/// file.
+///
+/// The shards assigned to this node are opened without any table, because
+/// the tables are managed by the local catalog in this mode.
+pub struct StaticClusterImpl {
+    inner: Arc<Inner>,
+    runtime: Arc<Runtime>,
+    config: StaticClusterConfig,
+    reload_handle: Mutex<Option<JoinHandle<()>>>,
+    stop_reload_tx: Mutex<Option<Sender<()>>>,
+}
+
+impl StaticClusterImpl {
+    pub fn try_new(
+        endpoint: String,
+        shard_set: ShardSet,
+        config: StaticClusterConfig,
+        runtime: Arc<Runtime>,
+    ) -> Result<Self> {
+        let topology = StaticTopology::load(&config.topology_path)?;
+        shard_set.set_max_shards(config.shard_limit.max_shards);
+        let inner = Arc::new(Inner {
+            endpoint,
+            topology_path: config.topology_path.clone(),
+            shard_set,
+            topology: RwLock::new(VersionedTopology {
+                version: 0,
+                topology: Arc::new(topology),
+            }),
+            shard_limit: config.shard_limit.clone(),
+            shard_retry: config.shard_retry.clone(),
+            queue_updates_when_frozen: config.queue_updates_when_frozen,
+            clock: SystemClock::new_ref(),
+        });
+        inner.sync_shards();
+
+        Ok(Self {
+            inner,
+            runtime,
+            config,
+            reload_handle: Mutex::new(None),
+            stop_reload_tx: Mutex::new(None),
+        })
+    }
+
+    fn start_reload_loop(&self) {
+        let interval = self.config.reload_interval.0;
+        let inner = self.inner.clone();
+        let (tx, mut rx) = mpsc::channel(1);
+
+        let handle = self.runtime.spawn(async move {
+            loop {
+                if time::timeout(interval, rx.recv()).await.is_ok() {
+                    warn!("Receive exit command and exit topology reload loop");
+                    break;
+                }
+
+                match inner.reload() {
+                    Ok(true) => info!("Static topology is reloaded"),
+                    Ok(false) => {}
+                    Err(e) => error!("Failed to reload static topology, keep the current, err:{e}"),
+                }
+            }
+        });
+
+        *self.stop_reload_tx.lock().unwrap() = Some(tx);
+        *self.reload_handle.lock().unwrap() = Some(handle);
+    }
+}
+
+struct VersionedTopology {
+    /// Incremented every time a changed topology is reloaded.
+    version: u64,
+    topology: Arc<StaticTopology>,
+}
+
+struct Inner {
+    endpoint: String,
+    topology_path: String,
+    shard_set: ShardSet,
+    topology: RwLock<VersionedTopology>,
+    shard_limit: ShardLimitConfig,
+    shard_retry: ShardRetryConfig,
+    queue_updates_when_frozen: bool,
+    clock: ClockRef,
+}
+
+impl Inner {
+    fn topology(&self) -> (u64, Arc<StaticTopology>) {
+        let topology = self.topology.read().unwrap();
+        (topology.version, topology.topology.clone())
+    }
+
+    /// Reload the topology file, return true if the topology is changed.
+    fn reload(&self) -> Result<bool> {
+        let new_topology = StaticTopology::load(&self.topology_path)?;
+        {
+            let mut topology = self.topology.write().unwrap();
+            if *topology.topology == new_topology {
+                return Ok(false);
+            }
+
+            let num_shards = topology.topology.num_shards();
+            ensure!(
+                new_topology.num_shards() == num_shards,
+                InvalidArguments {
+                    msg: format!(
+                        "number of shards can't be changed, current:{num_shards}, new:{}",
+                        new_topology.num_shards()
+                    ),
+                }
+            );
+
+            topology.version += 1;
+            topology.topology = Arc::new(new_topology);
+        }
+
+        self.sync_shards();
+        Ok(true)
+    }
+
+    /// Open the shards assigned to this node and close the ones moved away.
+    fn sync_shards(&self) {
+        let (_, topology) = self.topology();
+        let shard_ids: HashSet<_> = topology
+            .shards_of_node(&self.endpoint)
+            .into_iter()
+            .collect();
+
+        for shard in self.shard_set.all_shards() {
+            let shard_id = shard.shard_info().id;
+            if !shard_ids.contains(&shard_id) {
+                info!("Remove shard moved away from this node, shard_id:{shard_id}");
+                self.shard_set.remove(shard_id);
+            }
+        }
+
+        for shard_id in shard_ids {
+            if self.shard_set.get(shard_id).is_none() {
+                info!("Insert shard assigned to this node, shard_id:{shard_id}");
+                if let Err(e) = self.insert_shard(static_shard_info(shard_id)) {
+                    error!("Failed to insert shard, shard_id:{shard_id}, err:{e}");
+                }
+            }
+        }
+    }
+
+    fn insert_shard(&self, shard_info: ShardInfo) -> Result<ShardRef> {
+        let shard_id = shard_info.id;
+        let tables_of_shard = TablesOfShard {
+            shard_info,
+            tables: Vec::new(),
+        };
+        let limiter = ShardLimiter::new(&self.shard_limit, self.clock.clone());
+        let shard = Shard::new(tables_of_shard, limiter)
+            .with_retry(self.shard_retry.clone())
+            .queue_updates_when_frozen(self.queue_updates_when_frozen);
+        let shard = Arc::new(shard);
+        self.shard_set.insert(shard_id, shard.clone())?;
+
+        Ok(shard)
+    }
+
+    fn route_tables(&self, req: &RouteTablesRequest) -> RouteTablesResponse {
+        let (version, topology) = self.topology();
+        let mut entries = HashMap::with_capacity(req.table_names.len());
+        for table_name in &req.table_names {
+            let Some((shard_id, endpoint)) = topology.locate_table(&req.schema_name, table_name)
+            else {
+                continue;
+            };
+
+            // The table meta isn't managed by the static topology, so only the names are
+            // filled.
+            let table_info = TableInfo {
+                id: 0,
+                name: table_name.clone(),
+                schema_id: 0,
+                schema_name: req.schema_name.clone(),
+                partition_info: None,
+            };
+            let node_shard = NodeShard {
+                endpoint: endpoint.to_string(),
+                shard_info: static_shard_info(shard_id),
+            };
+            entries.insert(
+                table_name.clone(),
+                RouteEntry {
+                    table_info,
+                    node_shards: vec![node_shard],
+                },
+            );
+        }
+
+        RouteTablesResponse {
+            cluster_topology_version: version,
+            entries,
+        }
+    }
+
+    fn fetch_nodes(&self) -> ClusterNodesResp {
+        let (version, topology) = self.topology();
+        let nodes = topology
+            .nodes
+            .iter()
+            .flat_map(|node| {
+                node.shards.iter().map(|shard_id| NodeShard {
+                    endpoint: node.endpoint.clone(),
+                    shard_info: static_shard_info(*shard_id),
+                })
+            })
+            .collect();
+
+        ClusterNodesResp {
+            cluster_topology_version: version,
+            cluster_nodes: Arc::new(nodes),
+        }
+    }
+
+    fn get_table_status(&self, schema_name: &str, table_name: &str) -> Option<TableStatus> {
+        let (_, topology) = self.topology();
+        let (shard_id, _) = topology.locate_table(schema_name, table_name)?;
+        self.shard_set
+            .get(shard_id)
+            .map(|shard| TableStatus::from(shard.get_status()))
+    }
+}
+
+#[async_trait]
+impl Cluster for StaticClusterImpl {
+    async fn start(&self) -> Result<()> {
+        info!("Static cluster is starting with config:{:?}", self.config);
+
+        self.start_reload_loop();
+
+        info!("Static cluster has started");
+        Ok(())
+    }
+
+    async fn stop(&self) -> Result<()> {
+        info!("Static cluster is stopping");
+
+        {
+            let tx = self.stop_reload_tx.lock().unwrap().take();
+            if let Some(tx) = tx {
+                let _ = tx.send(()).await;
+            }
+        }
+
+        {
+            let handle = self.reload_handle.lock().unwrap().take();
+            if let Some(handle) = handle {
+                let _ = handle.await;
+            }
+        }
+
+        info!("Static cluster has stopped");
+        Ok(())
+    }
+
+    fn node_type(&self) -> NodeType {
+        NodeType::HoraeDB
+    }
+
+    async fn open_shard(&self, shard_info: &ShardInfo) -> Result<ShardRef> {
+        if let Some(shard) = self.inner.shard_set.get(shard_info.id) {
+            return Ok(shard);
+        }
+
+        self.inner.insert_shard(static_shard_info(shard_info.id))
+    }
+
+    fn shard(&self, shard_id: ShardId) -> Option<ShardRef> {
+        self.inner.shard_set.get(shard_id)
+    }
+
+    fn get_table_status(&self, schema_name: &str, table_name: &str) -> Option<TableStatus> {
+        self.inner.get_table_status(schema_name, table_name)
+    }
+
+    async fn close_shard(&self, shard_id: ShardId) -> Result<ShardRef> {
+        info!("Remove shard from shard_set, id:{shard_id}");
+        self.inner
+            .shard_set
+            .remove(shard_id)
+            .with_context(|| ShardNotFound {
+                msg: format!("close non-existent shard, shard_id:{shard_id}"),
+            })
+    }
+
+    fn list_shards(&self) -> Vec<ShardInfo> {
+        self.inner
+            .shard_set
+            .all_shards()
+            .iter()
+            .map(|shard| shard.shard_info())
+            .collect()
+    }
+
+    async fn route_tables(&self, req: &RouteTablesRequest) -> Result<RouteTablesResponse> {
+        Ok(self.inner.route_tables(req))
+    }
+
+    async fn fetch_nodes(&self) -> Result<ClusterNodesResp> {
+        Ok(self.inner.fetch_nodes())
+    }
+
+    fn take_recovered_shards(&self) -> Vec<ShardRef> {
+        Vec::new()
+    }
+
+    fn shard_lock_manager(&self) -> Option<ShardLockManagerRef> {
+        // Every shard is assigned to exactly one node by the topology file, so no lock
+        // is needed.
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use super::*;
+
+    fn node(endpoint: &str, shards: Vec<ShardId>) -> NodeShards {
+        NodeShards {
+            endpoint: endpoint.to_string(),
+            shards,
+        }
+    }
+
+    #[test]
+    fn test_validate_topology() {
+        let valid = StaticTopology {
+            nodes: vec![
+                node("127.0.0.1:8831", vec![0, 2]),
+                node("127.0.0.2:8831", vec![1]),
+            ],
+        };
+        assert!(valid.validate().is_ok());
+        assert_eq!(valid.num_shards(), 3);
+
+        let invalid_topologies = vec![
+            StaticTopology { nodes: vec![] },
+            StaticTopology {
+                nodes: vec![node("127.0.0.1:8831", vec![])],
+            },
+            StaticTopology {
+                nodes: vec![node("127.0.0.1", vec![0])],
+            },
+            StaticTopology {
+                nodes: vec![
+                    node("127.0.0.1:8831", vec![0]),
+                    node("127.0.0.1:8831", vec![1]),
+                ],
+            },
+            StaticTopology {
+                nodes: vec![
+                    node("127.0.0.1:8831", vec![0, 1]),
+                    node("127.0.0.2:8831", vec![1]),
+                ],
+            },
+            StaticTopology {
+                nodes: vec![node("127.0.0.1:8831", vec![0, 2])],
+            },
+        ];
+        for topology in invalid_topologies {
+            assert!(topology.validate().is_err(), "topology:{topology:?}");
+        }
+    }
+
+    #[test]
+    fn test_locate_table() {
+        let topology = StaticTopology {
+            nodes: vec![
+                node("127.0.0.1:8831", vec![0, 1]),
+                node("127.0.0.2:8831", vec![2, 3]),
+            ],
+        };
+
+        for i in 0..100 {
+            let table_name = format!("table_{i}");
+            let (shard_id, endpoint) = topology.locate_table("public", &table_name).unwrap();
+            assert!(shard_id < 4);
+            let expect_endpoint = if shard_id < 2 {
+                "127.0.0.1:8831"
+            } else {
+                "127.0.0.2:8831"
+            };
+            assert_eq!(endpoint, expect_endpoint);
+
+            // The location is stable.
+            assert_eq!(
+                topology.locate_table("public", &table_name),
+                Some((shard_id, endpoint))
+            );
+        }
+    }
+
+    fn write_topology(path: &std::path::Path, topology: &StaticTopology) {
+        let mut f = std::fs::File::create(path).unwrap();
+        f.write_all(toml::to_string(topology).unwrap().as_bytes())
+            .unwrap();
+        f.sync_all().unwrap();
+    }
+
+    #[test]
+    fn test_reload_topology() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("topology.toml");
+        let endpoint = "127.0.0.1:8831";
+        write_topology(
+            &path,
+            &StaticTopology {
+                nodes: vec![node(endpoint, vec![0, 1]), node("127.0.0.2:8831", vec![2])],
+            },
+        );
+
+        let config = StaticClusterConfig {
+            topology_path: path.to_str().unwrap().to_string(),
+            ..Default::default()
+        };
+        let runtime = Arc::new(
+            runtime::Builder::default()
+                .worker_threads(1)
+                .enable_all()
+                .build()
+                .unwrap(),
+        );
+        let cluster =
+            StaticClusterImpl::try_new(endpoint.to_string(), ShardSet::default(), config, runtime)
+                .unwrap();
+        let mut shard_ids: Vec<_> = cluster.list_shards().iter().map(|v| v.id).collect();
+        shard_ids.sort();
+        assert_eq!(shard_ids, vec![0, 1]);
+        assert!(cluster.shard(0).unwrap().is_opened());
+
+        // Unchanged topology is ignored.
+        assert!(!cluster.inner.reload().unwrap());
+
+        // Move shard 1 away and take shard 2.
+        write_topology(
+            &path,
+            &StaticTopology {
+                nodes: vec![node(endpoint, vec![0, 2]), node("127.0.0.2:8831", vec![1])],
+            },
+        );
+        assert!(cluster.inner.reload().unwrap());
+        let mut shard_ids: Vec<_> = cluster.list_shards().iter().map(|v| v.id).collect();
+        shard_ids.sort();
+        assert_eq!(shard_ids, vec![0, 2]);
+        assert_eq!(cluster.inner.fetch_nodes().cluster_topology_version, 1);
+
+        // Invalid topology and changed number of shards are rejected, and the current
+        // topology is kept.
+        write_topology(
+            &path,
+            &StaticTopology {
+                nodes: vec![node(endpoint, vec![0, 1]), node("127.0.0.2:8831", vec![1])],
+            },
+        );
+        assert!(cluster.inner.reload().is_err());
+        write_topology(
+            &path,
+            &StaticTopology {
+                nodes: vec![node(endpoint, vec![0, 1, 2, 3])],
+            },
+        );
+        assert!(cluster.inner.reload().is_err());
+        let mut shard_ids: Vec<_> = cluster.list_shards().iter().map(|v| v.id).collect();
+        shard_ids.sort();
+        assert_eq!(shard_ids, vec![0, 2]);
+    }
+}
@@ -106,15 +106,29 @@ pub async fn retry_async<F, Fut, T, E>(f: F, config: &RetryConfig) -> Fut::Outpu
 where
     F: Fn() -> Fut,
     Fut: Future<Output = Result<T, E>>,
+{
+    retry_async_if(f, config, |_| true).await
+}
+
+/// Like [retry_async], but only the errors accepted by `should_retry` are
+/// retried, and the others are returned at once.
+pub async fn retry_async_if<F, Fut, T, E, P>(
+    f: F,
+    config: &RetryConfig,
+    should_retry: P,
+) -> Fut::Output
+where
+    F: Fn() -> Fut,
+    Fut: Future<Output = Result<T, E>>,
+    P: Fn(&E) -> bool,
 {
     let mut backoff = Backoff::new(&config.backoff);
     for _ in 0..config.max_retries {
-        let result = f().await;
-
-        if result.is_ok() {
-            return result;
+        match f().await {
+            Ok(v) => return Ok(v),
+            Err(e) if !should_retry(&e) => return Err(e),
+            Err(_) => tokio::time::sleep(backoff.next()).await,
         }
-        tokio::time::sleep(backoff.next()).await;
     }
 
     f().await
@@ -176,6 +190,23 @@ mod tests {
             assert_eq!(2, ret.unwrap());
             assert_eq!(3, runs.load(Ordering::Relaxed));
         }
+
+        // errors not accepted aren't retried
+        {
+            let runs = AtomicU8::new(0);
+            let f = || {
+                let err = if runs.fetch_add(1, Ordering::Relaxed) < 1 {
+                    1
+                } else {
+                    2
+                };
+                futures::future::err::<i32, i32>(err)
+            };
+
+            let ret = retry_async_if(f, &config, |e| *e == 1).await;
+            assert_eq!(Err(2), ret);
+            assert_eq!(2, runs.load(Ordering::Relaxed));
+        }
     }
 
     #[test]
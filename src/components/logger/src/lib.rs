@@ -270,7 +270,7 @@ pub struct RuntimeLevel {
 }
 
 impl RuntimeLevel {
-    fn new(default_level: Level) -> Self {
+    pub fn new(default_level: Level) -> Self {
         Self {
             level: Arc::new(AtomicUsize::new(default_level.as_usize())),
             default_level,
@@ -36,6 +36,9 @@ pub struct StorageOptions {
     pub disk_cache_partition_bits: usize,
     pub disk_cache_dir: String,
     pub object_store: ObjectStoreOptions,
+    /// Per-tenant object stores, keyed by schema id. Ssts of schemas not
+    /// listed here are persisted to `object_store`.
+    pub tenant_stores: Vec<TenantStoreOptions>,
 }
 
 impl Default for StorageOptions {
@@ -50,10 +53,19 @@ impl Default for StorageOptions {
             disk_cache_page_size: ReadableSize::mb(2),
             disk_cache_partition_bits: 4,
             object_store: ObjectStoreOptions::Local(LocalOptions::new_with_default(root_path)),
+            tenant_stores: Vec::new(),
         }
     }
 }
 
+/// Object store of a single tenant (schema), which may live in its own
+/// bucket/prefix with its own credentials.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct TenantStoreOptions {
+    pub schema_id: u32,
+    pub object_store: ObjectStoreOptions,
+}
+
 #[derive(Debug, Clone, Deserialize, Serialize)]
 #[serde(tag = "type")]
 #[allow(clippy::large_enum_variant)]
@@ -61,6 +73,14 @@ pub enum ObjectStoreOptions {
     Local(LocalOptions),
     Aliyun(AliyunOptions),
     S3(S3Options),
+    Memory(MemoryOptions),
+}
+
+/// In-memory store shared by the servers in the same process, see
+/// [crate::memory].
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct MemoryOptions {
+    pub name: String,
 }
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
@@ -30,6 +30,7 @@ pub mod config;
 pub mod disk_cache;
 pub mod local_file;
 pub mod mem_cache;
+pub mod memory;
 pub mod metrics;
 pub mod multi_part;
 pub mod prefix;
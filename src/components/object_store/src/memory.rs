@@ -0,0 +1,49 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+//! In-memory object stores shared by name in the process.
+//!
+//! Mainly used by tests running several servers in one process, which should
+//! see the same data like on a real object store.
+
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+
+use lazy_static::lazy_static;
+use upstream::memory::InMemory;
+
+use crate::ObjectStoreRef;
+
+lazy_static! {
+    static ref STORES: Mutex<HashMap<String, ObjectStoreRef>> = Mutex::new(HashMap::new());
+}
+
+/// Get the in-memory store of `name`, it's created on the first call.
+pub fn shared(name: &str) -> ObjectStoreRef {
+    STORES
+        .lock()
+        .unwrap()
+        .entry(name.to_string())
+        .or_insert_with(|| Arc::new(InMemory::new()))
+        .clone()
+}
+
+/// Remove the store of `name`, the data is freed once no one holds the store.
+pub fn remove(name: &str) {
+    STORES.lock().unwrap().remove(name);
+}
@@ -0,0 +1,134 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+//! Clock abstraction for the time dependent logic, e.g. ttl, flush interval
+//! and lease expiration.
+//!
+//! The [SystemClock] should be used in production and the [LogicalClock] can
+//! be used in tests to make the time dependent behaviors reproducible.
+
+use std::{
+    fmt,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+
+use crate::{current_time_millis, DurationExt};
+
+pub trait Clock: fmt::Debug + Send + Sync {
+    /// Current wall time in milliseconds since the unix epoch.
+    fn now_ms(&self) -> u64;
+
+    /// Current monotonic time, which should be used to compute deadlines.
+    fn now_instant(&self) -> Instant;
+}
+
+pub type ClockRef = Arc<dyn Clock>;
+
+/// Clock backed by the system time.
+#[derive(Debug, Default)]
+pub struct SystemClock;
+
+impl SystemClock {
+    pub fn new_ref() -> ClockRef {
+        Arc::new(SystemClock)
+    }
+}
+
+impl Clock for SystemClock {
+    #[inline]
+    fn now_ms(&self) -> u64 {
+        current_time_millis()
+    }
+
+    #[inline]
+    fn now_instant(&self) -> Instant {
+        Instant::now()
+    }
+}
+
+/// Clock which only moves forward when it is advanced manually.
+#[derive(Debug)]
+pub struct LogicalClock {
+    start_ms: u64,
+    start_instant: Instant,
+    elapsed_ms: AtomicU64,
+}
+
+impl LogicalClock {
+    /// Create a clock whose wall time starts from `start_ms`.
+    pub fn new(start_ms: u64) -> Self {
+        Self {
+            start_ms,
+            start_instant: Instant::now(),
+            elapsed_ms: AtomicU64::new(0),
+        }
+    }
+
+    /// Move the clock forward by `duration`, and the precision is in
+    /// milliseconds.
+    pub fn advance(&self, duration: Duration) {
+        self.elapsed_ms
+            .fetch_add(duration.as_millis_u64(), Ordering::Relaxed);
+    }
+
+    #[inline]
+    fn elapsed(&self) -> Duration {
+        Duration::from_millis(self.elapsed_ms.load(Ordering::Relaxed))
+    }
+}
+
+impl Clock for LogicalClock {
+    fn now_ms(&self) -> u64 {
+        self.start_ms + self.elapsed().as_millis_u64()
+    }
+
+    fn now_instant(&self) -> Instant {
+        self.start_instant + self.elapsed()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_logical_clock() {
+        let clock = LogicalClock::new(1000);
+        let start_instant = clock.now_instant();
+        assert_eq!(1000, clock.now_ms());
+        assert_eq!(1000, clock.now_ms());
+        assert_eq!(start_instant, clock.now_instant());
+
+        clock.advance(Duration::from_millis(500));
+        assert_eq!(1500, clock.now_ms());
+        assert_eq!(
+            Duration::from_millis(500),
+            clock.now_instant() - start_instant
+        );
+
+        clock.advance(Duration::from_secs(1));
+        assert_eq!(2500, clock.now_ms());
+        assert_eq!(
+            Duration::from_millis(1500),
+            clock.now_instant() - start_instant
+        );
+    }
+}
@@ -19,6 +19,8 @@
 
 // TODO(yingwen): Move to common_types ?
 
+pub mod clock;
+
 use std::{
     convert::TryInto,
     fmt::{self, Write},
@@ -17,7 +17,7 @@
 
 // Config for horaedb server.
 
-use cluster::config::ClusterConfig;
+use cluster::config::{ClusterConfig, StaticClusterConfig};
 use proxy::limiter::LimiterConfig;
 use serde::{Deserialize, Serialize};
 use server::config::{ServerConfig, StaticRouteConfig};
@@ -99,12 +99,16 @@ impl Config {
 ///
 /// [ClusterDeployment::WithMeta] means to start one or multiple HoraeDB
 /// instance(s) under the control of HoraeMeta.
+///
+/// [ClusterDeployment::StaticTopology] means to start multiple HoraeDB
+/// instances without HoraeMeta, whose shards are assigned by a topology file.
 #[derive(Clone, Debug, Deserialize, Serialize)]
 #[serde(tag = "mode")]
 #[allow(clippy::large_enum_variant)]
 pub enum ClusterDeployment {
     NoMeta(StaticRouteConfig),
     WithMeta(ClusterConfig),
+    StaticTopology(StaticClusterConfig),
 }
 
 #[derive(Clone, Debug, Deserialize, Serialize)]
@@ -25,12 +25,18 @@ use analytic_engine::{
 };
 use catalog::{manager::ManagerRef, schema::OpenOptions, table_operator::TableOperator};
 use catalog_impls::{table_based::TableBasedManager, volatile, CatalogManagerImpl};
-use cluster::{cluster_impl::ClusterImpl, config::ClusterConfig, shard_set::ShardSet};
+use cluster::{
+    cluster_impl::ClusterImpl,
+    config::{ClusterConfig, StaticClusterConfig},
+    shard_set::ShardSet,
+    static_cluster::StaticClusterImpl,
+    Cluster,
+};
 use datafusion::execution::runtime_env::RuntimeConfig as DfRuntimeConfig;
 use df_operator::registry::{FunctionRegistry, FunctionRegistryImpl};
 use interpreters::table_manipulator::{catalog_based, meta_based};
 use logger::{info, warn, RuntimeLevel};
-use meta_client::{meta_impl, types::NodeMetaInfo};
+use meta_client::{meta_impl, types::NodeMetaInfo, MetaClientRef};
 use proxy::{
     limiter::Limiter,
     schema_config_provider::{
@@ -42,7 +48,7 @@ use runtime::PriorityRuntime;
 use server::{
     config::{StaticRouteConfig, StaticTopologyConfig},
     local_tables::LocalTablesRecoverer,
-    server::{Builder, DatafusionContext},
+    server::{Builder, DatafusionContext, Server},
 };
 use table_engine::{
     engine::{EngineRuntimes, TableEngineRef},
@@ -96,7 +102,7 @@ fn build_runtime(name: &str, threads_num: usize) -> runtime::Runtime {
     build_runtime_with_stack_size(name, threads_num, None)
 }
 
-fn build_engine_runtimes(config: &RuntimeConfig) -> EngineRuntimes {
+pub fn build_engine_runtimes(config: &RuntimeConfig) -> EngineRuntimes {
     let read_stack_size = config.read_thread_stack_size.as_byte() as usize;
     EngineRuntimes {
         read_runtime: PriorityRuntime::new(
@@ -214,6 +220,29 @@ async fn run_server_with_runtimes<T>(
     log_runtime: Arc<RuntimeLevel>,
 ) where
     T: WalsOpener,
+{
+    let server = start_server::<T>(config, engine_runtimes, log_runtime, None).await;
+
+    // Wait for signal
+    signal_handler::wait_for_signal();
+
+    // Stop server
+    server.stop().await;
+}
+
+/// Build and start a server, returns the started server.
+///
+/// The meta client is built by the cluster config if `meta_client` is not
+/// provided, a stub can be provided to run servers without HoraeMeta, e.g. in
+/// tests.
+pub async fn start_server<T>(
+    config: Config,
+    engine_runtimes: Arc<EngineRuntimes>,
+    log_runtime: Arc<RuntimeLevel>,
+    meta_client: Option<MetaClientRef>,
+) -> Server
+where
+    T: WalsOpener,
 {
     // Init function registry.
     let mut function_registry = FunctionRegistryImpl::new();
@@ -262,6 +291,17 @@ async fn run_server_with_runtimes<T>(
                 builder,
                 engine_runtimes.clone(),
                 wal_builder,
+                meta_client,
+            )
+            .await
+        }
+        Some(ClusterDeployment::StaticTopology(static_cluster_config)) => {
+            build_with_static_topology(
+                &config,
+                static_cluster_config,
+                builder,
+                engine_runtimes.clone(),
+                wal_builder,
             )
             .await
         }
@@ -271,11 +311,7 @@ async fn run_server_with_runtimes<T>(
     let mut server = builder.build().expect("Failed to create server");
     server.start().await.expect("Failed to start server");
 
-    // Wait for signal
-    signal_handler::wait_for_signal();
-
-    // Stop server
-    server.stop().await;
+    server
 }
 
 // Build proxy for all table engines.
@@ -305,6 +341,7 @@ async fn build_with_meta<T: WalsOpener>(
     builder: Builder,
     runtimes: Arc<EngineRuntimes>,
     wal_opener: T,
+    meta_client: Option<MetaClientRef>,
 ) -> Builder {
     // Build meta related modules.
     let node_meta_info = NodeMetaInfo {
@@ -319,10 +356,12 @@ async fn build_with_meta<T: WalsOpener>(
     info!("Build horaedb with node meta info:{node_meta_info:?}");
 
     let endpoint = node_meta_info.endpoint();
-    let meta_client =
-        meta_impl::build_meta_client(cluster_config.meta_client.clone(), node_meta_info)
+    let meta_client = match meta_client {
+        Some(v) => v,
+        None => meta_impl::build_meta_client(cluster_config.meta_client.clone(), node_meta_info)
             .await
-            .expect("fail to build meta client");
+            .expect("fail to build meta client"),
+    };
 
     let shard_set = ShardSet::default();
     let cluster = {
@@ -341,6 +380,18 @@ async fn build_with_meta<T: WalsOpener>(
         cluster.clone(),
         config.server.route_cache.clone(),
     ));
+    // Serve the requests with the routes persisted by the last run until they are
+    // refreshed from meta.
+    match router.restore_routes().await {
+        Ok(num_routes) => info!("Restore persisted routes, num_routes:{num_routes}"),
+        Err(e) => warn!("Failed to restore persisted routes, err:{e}"),
+    }
+    {
+        let router = router.clone();
+        runtimes
+            .default_runtime
+            .spawn(async move { router.run_persist_loop().await });
+    }
 
     let opened_wals = wal_opener
         .open_wals(&config.analytic.wal, make_wal_runtime(runtimes.clone()))
@@ -466,6 +517,47 @@ async fn build_without_meta<T: WalsOpener>(
         .local_tables_recoverer(local_tables_recoverer)
 }
 
+async fn build_with_static_topology<T: WalsOpener>(
+    config: &Config,
+    static_cluster_config: &StaticClusterConfig,
+    builder: Builder,
+    runtimes: Arc<EngineRuntimes>,
+    wal_opener: T,
+) -> Builder {
+    // The tables are managed by the local catalog like the deployment without meta,
+    // and only the router is replaced to route the tables by the topology.
+    let builder = build_without_meta(
+        config,
+        &StaticRouteConfig::default(),
+        builder,
+        runtimes.clone(),
+        wal_opener,
+    )
+    .await;
+
+    let endpoint = format!("{}:{}", config.node.addr, config.server.grpc_port);
+    info!("Build horaedb with static topology, endpoint:{endpoint}");
+    let cluster = StaticClusterImpl::try_new(
+        endpoint,
+        ShardSet::default(),
+        static_cluster_config.clone(),
+        runtimes.meta_runtime.clone(),
+    )
+    .expect("Failed to build static cluster");
+    // The cluster isn't handed to the server, which would serve the meta events
+    // for it, so it is started here to reload the topology file.
+    cluster
+        .start()
+        .await
+        .expect("Failed to start static cluster");
+
+    let router = Arc::new(ClusterBasedRouter::new(
+        Arc::new(cluster),
+        config.server.route_cache.clone(),
+    ));
+    builder.router(router)
+}
+
 async fn create_static_topology_schema(
     catalog_mgr: ManagerRef,
     static_topology_config: StaticTopologyConfig,
@@ -0,0 +1,64 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+//! Tests of the multi-node behaviors on an in-process cluster.
+
+mod test_cluster;
+
+use test_cluster::TestCluster;
+
+#[test]
+fn test_query_after_shard_moved() {
+    let cluster = TestCluster::start(2, 2);
+
+    let resp = cluster
+        .sql(
+            0,
+            "CREATE TABLE `demo` (`name` string TAG, `value` double NOT NULL, \
+             `t` timestamp NOT NULL, TIMESTAMP KEY(t)) ENGINE=Analytic",
+        )
+        .unwrap();
+    assert_eq!("affected_rows: 0", resp);
+    let shard_id = cluster.meta().table_shard("public", "demo").unwrap();
+    let owner = cluster.meta().shard_node(shard_id).unwrap();
+    let owner = (0..2).find(|i| cluster.endpoint(*i) == owner).unwrap();
+
+    // Written and queried by the node not owning the table, which are forwarded
+    // to the owner.
+    let other = 1 - owner;
+    let resp = cluster
+        .sql(
+            other,
+            "INSERT INTO demo (t, name, value) VALUES (1651737067000, 'horaedb', 100)",
+        )
+        .unwrap();
+    assert_eq!("affected_rows: 1", resp);
+    for node in [owner, other] {
+        let resp = cluster.sql(node, "SELECT name, value FROM demo").unwrap();
+        assert!(resp.contains("horaedb"), "node:{node}, resp:{resp}");
+    }
+
+    // The data is still there after the shard is moved to the other node.
+    cluster.move_shard(shard_id, other);
+    assert_eq!(
+        Some(cluster.endpoint(other)),
+        cluster.meta().shard_node(shard_id).as_deref()
+    );
+    for node in [owner, other] {
+        let resp = cluster.sql(node, "SELECT name, value FROM demo").unwrap();
+        assert!(resp.contains("horaedb"), "node:{node}, resp:{resp}");
+    }
+}
@@ -0,0 +1,438 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+//! In-process stub of HoraeMeta.
+//!
+//! Tables and shards are kept in memory, and shards are assigned to the nodes
+//! by the test explicitly, which are notified by the meta event service like
+//! by HoraeMeta.
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    sync::Mutex,
+};
+
+use async_trait::async_trait;
+use common_types::{
+    schema::SchemaId,
+    table::{ShardId, ShardVersion, TableId},
+};
+use generic_error::BoxError;
+use horaedbproto::{
+    common::ResponseHeader,
+    meta_event::{
+        meta_event_service_client::MetaEventServiceClient, CloseShardRequest,
+        CreateTableOnShardRequest, DropTableOnShardRequest, OpenShardRequest, UpdateShardInfo,
+    },
+    meta_service as meta_service_pb,
+};
+use meta_client::{
+    types::{
+        AllocSchemaIdRequest, AllocSchemaIdResponse, CreateTableRequest, CreateTableResponse,
+        DropTableRequest, DropTableResponse, FetchCompactionNodeRequest,
+        FetchCompactionNodeResponse, GetNodesRequest, GetNodesResponse, GetTablesOfShardsRequest,
+        GetTablesOfShardsResponse, NodeShard, RouteEntry, RouteTablesRequest, RouteTablesResponse,
+        ShardInfo, ShardRole, ShardStatus, TableInfo, TablesOfShard,
+    },
+    BadResponse, Convert, FailConnect, FailCreateTable, FailDropTable, MetaClient, Result,
+};
+use snafu::ResultExt;
+
+#[derive(Debug, Default)]
+struct ShardEntry {
+    /// Endpoint of the node the shard is assigned to.
+    endpoint: Option<String>,
+    version: ShardVersion,
+}
+
+impl ShardEntry {
+    fn shard_info(&self, id: ShardId) -> ShardInfo {
+        ShardInfo {
+            id,
+            role: ShardRole::Leader,
+            version: self.version,
+            status: ShardStatus::Ready,
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct State {
+    schemas: HashMap<String, SchemaId>,
+    /// (schema, table) -> (table, shard of the table)
+    tables: HashMap<(String, String), (TableInfo, ShardId)>,
+    shards: BTreeMap<ShardId, ShardEntry>,
+    next_table_id: TableId,
+    topology_version: u64,
+}
+
+impl State {
+    fn schema_id(&mut self, name: &str) -> SchemaId {
+        let next_id = self.schemas.len() as SchemaId;
+        *self.schemas.entry(name.to_string()).or_insert(next_id)
+    }
+
+    fn node_shard(&self, shard_id: ShardId) -> Option<NodeShard> {
+        let shard = self.shards.get(&shard_id)?;
+        Some(NodeShard {
+            endpoint: shard.endpoint.clone()?,
+            shard_info: shard.shard_info(shard_id),
+        })
+    }
+}
+
+/// Stub of HoraeMeta shared by all the nodes of a test cluster.
+#[derive(Debug, Default)]
+pub struct MetaStub {
+    state: Mutex<State>,
+}
+
+impl MetaStub {
+    /// Create a stub managing shards `0..num_shards`, which are not assigned
+    /// to any node yet.
+    pub fn new(num_shards: u32) -> Self {
+        let state = State {
+            shards: (0..num_shards)
+                .map(|id| (id, ShardEntry::default()))
+                .collect(),
+            ..Default::default()
+        };
+        Self {
+            state: Mutex::new(state),
+        }
+    }
+
+    /// Endpoint of the node the shard is assigned to.
+    pub fn shard_node(&self, shard_id: ShardId) -> Option<String> {
+        let state = self.state.lock().unwrap();
+        state.shards.get(&shard_id)?.endpoint.clone()
+    }
+
+    pub fn table_shard(&self, schema: &str, table: &str) -> Option<ShardId> {
+        let state = self.state.lock().unwrap();
+        let key = (schema.to_string(), table.to_string());
+        state.tables.get(&key).map(|(_, shard_id)| *shard_id)
+    }
+
+    /// Open the shard on the node at `endpoint`, the shard is closed on the
+    /// node it's assigned to before if any.
+    pub async fn assign_shard(&self, shard_id: ShardId, endpoint: &str) -> Result<()> {
+        let (shard_info, old_endpoint) = {
+            let state = self.state.lock().unwrap();
+            let Some(shard) = state.shards.get(&shard_id) else {
+                return BadResponse {
+                    code: 404_u32,
+                    msg: format!("shard not found, shard_id:{shard_id}"),
+                }
+                .fail();
+            };
+            (shard.shard_info(shard_id), shard.endpoint.clone())
+        };
+
+        if let Some(old_endpoint) = old_endpoint {
+            let req = CloseShardRequest {
+                shard_id,
+                ..Default::default()
+            };
+            let resp = connect(&old_endpoint)
+                .await?
+                .close_shard(req)
+                .await
+                .box_err()
+                .context(Convert {
+                    msg: format!("failed to close shard, shard_id:{shard_id}"),
+                })?;
+            check_header(resp.into_inner().header)?;
+            self.set_shard_node(shard_id, None);
+        }
+
+        let req = OpenShardRequest {
+            shard: Some(shard_info.into()),
+            ..Default::default()
+        };
+        let resp = connect(endpoint)
+            .await?
+            .open_shard(req)
+            .await
+            .box_err()
+            .context(Convert {
+                msg: format!("failed to open shard, shard_id:{shard_id}"),
+            })?;
+        check_header(resp.into_inner().header)?;
+        self.set_shard_node(shard_id, Some(endpoint.to_string()));
+
+        Ok(())
+    }
+
+    fn set_shard_node(&self, shard_id: ShardId, endpoint: Option<String>) {
+        let mut state = self.state.lock().unwrap();
+        if let Some(shard) = state.shards.get_mut(&shard_id) {
+            shard.endpoint = endpoint;
+        }
+        state.topology_version += 1;
+    }
+
+    /// Returns the update of the shard, and the endpoint of its node.
+    fn update_shard_info(state: &State, shard_id: ShardId) -> Result<(UpdateShardInfo, String)> {
+        let Some(node_shard) = state.node_shard(shard_id) else {
+            return BadResponse {
+                code: 500_u32,
+                msg: format!("shard is not assigned, shard_id:{shard_id}"),
+            }
+            .fail();
+        };
+        let update = UpdateShardInfo {
+            curr_shard_info: Some(node_shard.shard_info.into()),
+            ..Default::default()
+        };
+
+        Ok((update, node_shard.endpoint))
+    }
+
+    fn inc_shard_version(&self, shard_id: ShardId) -> ShardInfo {
+        let mut state = self.state.lock().unwrap();
+        let shard = state.shards.get_mut(&shard_id).unwrap();
+        shard.version += 1;
+        shard.shard_info(shard_id)
+    }
+}
+
+#[async_trait]
+impl MetaClient for MetaStub {
+    async fn alloc_schema_id(&self, req: AllocSchemaIdRequest) -> Result<AllocSchemaIdResponse> {
+        let id = self.state.lock().unwrap().schema_id(&req.name);
+        Ok(AllocSchemaIdResponse { name: req.name, id })
+    }
+
+    async fn create_table(&self, req: CreateTableRequest) -> Result<CreateTableResponse> {
+        let (table_info, shard_id, update, endpoint) = {
+            let mut state = self.state.lock().unwrap();
+            let key = (req.schema_name.clone(), req.name.clone());
+            if let Some((table_info, shard_id)) = state.tables.get(&key) {
+                if !req.create_if_not_exist {
+                    return BadResponse {
+                        code: 409_u32,
+                        msg: format!("table already exists, table:{}", req.name),
+                    }
+                    .fail();
+                }
+                return Ok(CreateTableResponse {
+                    created_table: table_info.clone(),
+                    shard_info: state.shards[shard_id].shard_info(*shard_id),
+                });
+            }
+            if req.partition_table_info.is_some() {
+                return BadResponse {
+                    code: 501_u32,
+                    msg: "partition table is not supported by the stub".to_string(),
+                }
+                .fail();
+            }
+
+            // Tables are placed on the assigned shard with the fewest tables.
+            let shard_id = state
+                .shards
+                .iter()
+                .filter(|(_, shard)| shard.endpoint.is_some())
+                .map(|(id, _)| *id)
+                .min_by_key(|id| state.tables.values().filter(|(_, s)| s == id).count());
+            let Some(shard_id) = shard_id else {
+                return BadResponse {
+                    code: 500_u32,
+                    msg: "no shard is assigned".to_string(),
+                }
+                .fail();
+            };
+            let table_info = TableInfo {
+                id: state.next_table_id,
+                name: req.name.clone(),
+                schema_id: state.schema_id(&req.schema_name),
+                schema_name: req.schema_name.clone(),
+                partition_info: None,
+            };
+            state.next_table_id += 1;
+            let (update, endpoint) = Self::update_shard_info(&state, shard_id)?;
+            (table_info, shard_id, update, endpoint)
+        };
+
+        let pb_req = CreateTableOnShardRequest {
+            update_shard_info: Some(update),
+            table_info: Some(to_pb_table_info(&table_info)),
+            encoded_schema: req.encoded_schema,
+            engine: req.engine,
+            create_if_not_exist: req.create_if_not_exist,
+            options: req.options,
+            ..Default::default()
+        };
+        let resp = connect(&endpoint)
+            .await?
+            .create_table_on_shard(pb_req)
+            .await
+            .box_err()
+            .context(FailCreateTable)?;
+        check_header(resp.into_inner().header)?;
+
+        let shard_info = self.inc_shard_version(shard_id);
+        self.state
+            .lock()
+            .unwrap()
+            .tables
+            .insert((req.schema_name, req.name), (table_info.clone(), shard_id));
+        Ok(CreateTableResponse {
+            created_table: table_info,
+            shard_info,
+        })
+    }
+
+    async fn drop_table(&self, req: DropTableRequest) -> Result<DropTableResponse> {
+        let key = (req.schema_name, req.name);
+        let (table_info, shard_id, update, endpoint) = {
+            let state = self.state.lock().unwrap();
+            let Some((table_info, shard_id)) = state.tables.get(&key) else {
+                return Ok(DropTableResponse {
+                    dropped_table: None,
+                });
+            };
+            let (update, endpoint) = Self::update_shard_info(&state, *shard_id)?;
+            (table_info.clone(), *shard_id, update, endpoint)
+        };
+
+        let pb_req = DropTableOnShardRequest {
+            update_shard_info: Some(update),
+            table_info: Some(to_pb_table_info(&table_info)),
+            ..Default::default()
+        };
+        let resp = connect(&endpoint)
+            .await?
+            .drop_table_on_shard(pb_req)
+            .await
+            .box_err()
+            .context(FailDropTable)?;
+        check_header(resp.into_inner().header)?;
+
+        self.inc_shard_version(shard_id);
+        self.state.lock().unwrap().tables.remove(&key);
+        Ok(DropTableResponse {
+            dropped_table: Some(table_info),
+        })
+    }
+
+    async fn get_tables_of_shards(
+        &self,
+        req: GetTablesOfShardsRequest,
+    ) -> Result<GetTablesOfShardsResponse> {
+        let state = self.state.lock().unwrap();
+        let tables_by_shard = req
+            .shard_ids
+            .into_iter()
+            .filter_map(|shard_id| {
+                let shard = state.shards.get(&shard_id)?;
+                let tables = state
+                    .tables
+                    .values()
+                    .filter(|(_, id)| *id == shard_id)
+                    .map(|(table, _)| table.clone())
+                    .collect();
+                let tables_of_shard = TablesOfShard {
+                    shard_info: shard.shard_info(shard_id),
+                    tables,
+                };
+                Some((shard_id, tables_of_shard))
+            })
+            .collect();
+
+        Ok(GetTablesOfShardsResponse { tables_by_shard })
+    }
+
+    async fn route_tables(&self, req: RouteTablesRequest) -> Result<RouteTablesResponse> {
+        let state = self.state.lock().unwrap();
+        let entries = req
+            .table_names
+            .into_iter()
+            .filter_map(|name| {
+                let key = (req.schema_name.clone(), name);
+                let (table_info, shard_id) = state.tables.get(&key)?;
+                let entry = RouteEntry {
+                    table_info: table_info.clone(),
+                    node_shards: state.node_shard(*shard_id).into_iter().collect(),
+                };
+                Some((key.1, entry))
+            })
+            .collect();
+
+        Ok(RouteTablesResponse {
+            cluster_topology_version: state.topology_version,
+            entries,
+        })
+    }
+
+    async fn get_nodes(&self, _req: GetNodesRequest) -> Result<GetNodesResponse> {
+        let state = self.state.lock().unwrap();
+        let node_shards = state
+            .shards
+            .keys()
+            .filter_map(|shard_id| state.node_shard(*shard_id))
+            .collect();
+
+        Ok(GetNodesResponse {
+            cluster_topology_version: state.topology_version,
+            node_shards,
+        })
+    }
+
+    async fn fetch_compaction_node(
+        &self,
+        _req: FetchCompactionNodeRequest,
+    ) -> Result<FetchCompactionNodeResponse> {
+        BadResponse {
+            code: 501_u32,
+            msg: "compaction node is not supported by the stub".to_string(),
+        }
+        .fail()
+    }
+
+    async fn send_heartbeat(&self, _req: Vec<ShardInfo>) -> Result<()> {
+        Ok(())
+    }
+}
+
+async fn connect(endpoint: &str) -> Result<MetaEventServiceClient<tonic::transport::Channel>> {
+    MetaEventServiceClient::connect(format!("http://{endpoint}"))
+        .await
+        .box_err()
+        .context(FailConnect { addr: endpoint })
+}
+
+fn check_header(header: Option<ResponseHeader>) -> Result<()> {
+    match header {
+        Some(header) if header.code != 0 => BadResponse {
+            code: header.code,
+            msg: header.error,
+        }
+        .fail(),
+        _ => Ok(()),
+    }
+}
+
+fn to_pb_table_info(table_info: &TableInfo) -> meta_service_pb::TableInfo {
+    meta_service_pb::TableInfo {
+        id: table_info.id,
+        name: table_info.name.clone(),
+        schema_id: table_info.schema_id,
+        schema_name: table_info.schema_name.clone(),
+        partition_info: None,
+    }
+}
@@ -0,0 +1,242 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+//! In-process cluster for the tests of multi-node behaviors.
+//!
+//! All the nodes run in the current process, they share one in-memory object
+//! store and are managed by a [MetaStub], while every node has its own wal in
+//! a temporary directory.
+
+mod meta_stub;
+
+use std::{
+    net::{TcpListener, TcpStream},
+    sync::Arc,
+    thread,
+    time::Duration,
+};
+
+use analytic_engine::Config as AnalyticConfig;
+use cluster::config::ClusterConfig;
+use common_types::table::ShardId;
+use horaedb::{
+    config::{ClusterDeployment, Config, RuntimeConfig},
+    setup,
+};
+use horaedb_client::{
+    db_client::{Builder, DbClient, Mode},
+    model::sql_query::{display::CsvFormatter, Request},
+    RpcContext,
+};
+pub use meta_stub::MetaStub;
+use object_store::{
+    config::{MemoryOptions, ObjectStoreOptions, StorageOptions},
+    memory,
+};
+use runtime::Runtime;
+use server::{config::ServerConfig, server::Server};
+use size_ext::ReadableSize;
+use table_engine::engine::EngineRuntimes;
+use tempfile::TempDir;
+use wal::{
+    config::{Config as WalConfig, StorageConfig},
+    local_storage_impl::{config::LocalStorageConfig, wal_manager::LocalStorageWalsOpener},
+};
+
+struct TestNode {
+    /// Grpc endpoint of the node.
+    endpoint: String,
+    runtimes: Arc<EngineRuntimes>,
+    server: Option<Server>,
+    _wal_dir: TempDir,
+}
+
+/// A cluster of nodes running in the current process.
+pub struct TestCluster {
+    store_name: String,
+    meta: Arc<MetaStub>,
+    nodes: Vec<TestNode>,
+    runtime: Runtime,
+}
+
+impl TestCluster {
+    /// Start `num_nodes` nodes, and assign `num_shards` shards to them in the
+    /// round-robin way.
+    pub fn start(num_nodes: usize, num_shards: u32) -> Self {
+        let runtime = runtime::Builder::default()
+            .worker_threads(1)
+            .thread_name("test-cluster")
+            .enable_all()
+            .build()
+            .unwrap();
+        let meta = Arc::new(MetaStub::new(num_shards));
+        let store_name = format!("test_cluster_{}", free_port());
+
+        let nodes = (0..num_nodes)
+            .map(|_| start_node(&store_name, meta.clone()))
+            .collect::<Vec<_>>();
+        for (shard_id, node) in (0..num_shards).zip(nodes.iter().cycle()) {
+            runtime
+                .block_on(meta.assign_shard(shard_id, &node.endpoint))
+                .unwrap();
+        }
+
+        Self {
+            store_name,
+            meta,
+            nodes,
+            runtime,
+        }
+    }
+
+    pub fn meta(&self) -> &MetaStub {
+        &self.meta
+    }
+
+    pub fn endpoint(&self, node: usize) -> &str {
+        &self.nodes[node].endpoint
+    }
+
+    /// Execute the sql by the client connected to the `node`, returns the
+    /// affected rows or the rows in csv like the integration tests.
+    pub fn sql(&self, node: usize, sql: &str) -> Result<String, String> {
+        let client = Builder::new(self.nodes[node].endpoint.clone(), Mode::Proxy).build();
+        let ctx = RpcContext {
+            database: Some("public".to_string()),
+            timeout: None,
+        };
+        let req = Request {
+            tables: vec![],
+            sql: sql.to_string(),
+        };
+
+        let resp = self
+            .runtime
+            .block_on(client.sql_query(&ctx, &req))
+            .map_err(|e| format!("{e:?}"))?;
+        if resp.rows.is_empty() {
+            Ok(format!("affected_rows: {}", resp.affected_rows))
+        } else {
+            Ok(format!("{}", CsvFormatter { resp }))
+        }
+    }
+
+    /// Move the shard to the `node`, its tables are closed on the node it's
+    /// assigned to and then opened on the `node`.
+    pub fn move_shard(&self, shard_id: ShardId, node: usize) {
+        self.runtime
+            .block_on(self.meta.assign_shard(shard_id, &self.nodes[node].endpoint))
+            .unwrap();
+    }
+}
+
+impl Drop for TestCluster {
+    fn drop(&mut self) {
+        for node in &mut self.nodes {
+            if let Some(server) = node.server.take() {
+                node.runtimes.default_runtime.block_on(server.stop());
+            }
+        }
+        memory::remove(&self.store_name);
+    }
+}
+
+fn start_node(store_name: &str, meta: Arc<MetaStub>) -> TestNode {
+    let grpc_port = free_port();
+    let wal_dir = TempDir::new().unwrap();
+    let runtime_config = RuntimeConfig {
+        read_thread_num: 2,
+        read_thread_stack_size: ReadableSize::mb(16),
+        low_read_thread_num: 1,
+        write_thread_num: 2,
+        meta_thread_num: 1,
+        compact_thread_num: 1,
+        default_thread_num: 2,
+        io_thread_num: 1,
+    };
+    let config = Config {
+        server: ServerConfig {
+            bind_addr: "127.0.0.1".to_string(),
+            grpc_port,
+            http_port: free_port(),
+            mysql_port: free_port(),
+            postgresql_port: free_port(),
+            ..Default::default()
+        },
+        runtime: runtime_config.clone(),
+        analytic: AnalyticConfig {
+            storage: StorageOptions {
+                mem_cache_capacity: ReadableSize(0),
+                object_store: ObjectStoreOptions::Memory(MemoryOptions {
+                    name: store_name.to_string(),
+                }),
+                ..Default::default()
+            },
+            wal: WalConfig {
+                storage: StorageConfig::Local(Box::new(LocalStorageConfig {
+                    data_dir: wal_dir.path().to_str().unwrap().to_string(),
+                    ..Default::default()
+                })),
+                disable_data: false,
+            },
+            ..Default::default()
+        },
+        cluster_deployment: Some(ClusterDeployment::WithMeta(ClusterConfig {
+            disable_shard_lock: true,
+            ..Default::default()
+        })),
+        ..Default::default()
+    };
+
+    let runtimes = Arc::new(setup::build_engine_runtimes(&runtime_config));
+    let log_runtime = Arc::new(logger::RuntimeLevel::new(logger::Level::Info));
+    let server = runtimes
+        .default_runtime
+        .block_on(setup::start_server::<LocalStorageWalsOpener>(
+            config,
+            runtimes.clone(),
+            log_runtime,
+            Some(meta),
+        ));
+
+    let endpoint = format!("127.0.0.1:{grpc_port}");
+    wait_for_listening(&endpoint);
+    TestNode {
+        endpoint,
+        runtimes,
+        server: Some(server),
+        _wal_dir: wal_dir,
+    }
+}
+
+/// Pick a free port of the localhost.
+///
+/// The port may be taken by others before it's bound by the node, which is
+/// unlikely in the tests.
+fn free_port() -> u16 {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    listener.local_addr().unwrap().port()
+}
+
+fn wait_for_listening(endpoint: &str) {
+    for _ in 0..100 {
+        if TcpStream::connect(endpoint).is_ok() {
+            return;
+        }
+        thread::sleep(Duration::from_millis(100));
+    }
+    panic!("node is not listening, endpoint:{endpoint}");
+}
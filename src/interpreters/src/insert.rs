@@ -57,7 +57,7 @@ use runtime::Priority;
 use snafu::{ensure, OptionExt, ResultExt, Snafu};
 use table_engine::{
     stream::SendableRecordBatchStream,
-    table::{TableRef, WriteRequest},
+    table::{Durability, TableRef, WriteRequest},
 };
 use tokio::sync::mpsc;
 
@@ -167,12 +167,14 @@ impl Interpreter for InsertInterpreter {
             table,
             source,
             default_value_map,
+            durability,
         } = self.plan;
 
         match source {
             InsertSource::Values { row_group: rows } => {
                 let num_rows =
-                    prepare_and_write_table(table.clone(), rows, &default_value_map).await?;
+                    prepare_and_write_table(table.clone(), rows, &default_value_map, durability)
+                        .await?;
 
                 Ok(Output::AffectedRows(num_rows))
             }
@@ -225,6 +227,7 @@ impl Interpreter for InsertInterpreter {
                                 column_index_in_insert.as_slice(),
                                 table.clone(),
                                 &default_value_map,
+                                durability,
                             )
                             .await?;
                             result_rows += num_rows;
@@ -237,6 +240,7 @@ impl Interpreter for InsertInterpreter {
                             column_index_in_insert.as_slice(),
                             table,
                             &default_value_map,
+                            durability,
                         )
                         .await?;
                         result_rows += num_rows;
@@ -264,6 +268,7 @@ async fn write_record_batches(
     column_index_in_insert: &[InsertMode],
     table: TableRef,
     default_value_map: &BTreeMap<usize, DfLogicalExpr>,
+    durability: Durability,
 ) -> InterpreterResult<usize> {
     let row_group = convert_records_to_row_group(
         record_batches.as_slice(),
@@ -273,20 +278,21 @@ async fn write_record_batches(
     .context(Insert)?;
     record_batches.clear();
 
-    prepare_and_write_table(table, row_group, default_value_map).await
+    prepare_and_write_table(table, row_group, default_value_map, durability).await
 }
 
 async fn prepare_and_write_table(
     table: TableRef,
     mut row_group: RowGroup,
     default_value_map: &BTreeMap<usize, DfLogicalExpr>,
+    durability: Durability,
 ) -> InterpreterResult<usize> {
-    maybe_generate_tsid(&mut row_group).context(Insert)?;
-
-    // Fill default values
-    fill_default_values(table.clone(), &mut row_group, default_value_map).context(Insert)?;
+    prepare_row_group(table.clone(), &mut row_group, default_value_map).context(Insert)?;
 
-    let request = WriteRequest { row_group };
+    let request = WriteRequest {
+        row_group,
+        durability,
+    };
 
     let num_rows = table
         .write(request)
@@ -376,6 +382,19 @@ fn convert_records_to_row_group(
     RowGroup::try_new(schema, data_rows).context(BuildRow)
 }
 
+/// Prepare the rows before writing them to the table: generate the tsid and
+/// fill the missing columns with their default values.
+pub fn prepare_row_group(
+    table: TableRef,
+    row_group: &mut RowGroup,
+    default_value_map: &BTreeMap<usize, DfLogicalExpr>,
+) -> Result<()> {
+    maybe_generate_tsid(row_group)?;
+
+    // Fill default values
+    fill_default_values(table, row_group, default_value_map)
+}
+
 fn maybe_generate_tsid(rows: &mut RowGroup) -> Result<()> {
     let schema = rows.schema();
     let tsid_idx = schema.index_of_tsid();
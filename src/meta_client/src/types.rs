@@ -190,12 +190,15 @@ pub struct NodeInfo {
 /// ╱ Opening ╲____│Ready│
 /// ╲         ╱yes └──┬──┘
 ///  ╲_______╱    ┌───▽──┐
-///               │Frozen│
-///               └──────┘
+///      │no      │Frozen│
+///  ┌───▽──┐     └──────┘
+///  │Failed│
+///  └──────┘
 /// ```
 /// When an open request comes in, shard can only be opened when it's in
 /// - `Init`, which means it has not been opened before.
-/// - `Opening`, which means it has been opened before, but failed.
+/// - `Opening`, which means it's being opened.
+/// - `Failed`, which means it failed to be opened after retries.
 #[derive(Debug, Default, Clone, PartialEq, Eq, Serialize)]
 pub enum ShardStatus {
     /// Created, but not opened
@@ -207,6 +210,8 @@ pub enum ShardStatus {
     Ready,
     /// Further updates are prohibited
     Frozen,
+    /// Failed to be opened after retries
+    Failed { reason: String },
 }
 
 #[derive(Debug, Default, Clone, PartialEq, Eq, Serialize)]
@@ -47,9 +47,9 @@ use table_engine::{
     },
     stream::{PartitionedStreams, SendableRecordBatchStream},
     table::{
-        AlterOptions, AlterSchema, AlterSchemaRequest, CreatePartitionRule, FlushRequest,
-        GetRequest, LocatePartitions, ReadRequest, Result, Scan, Table, TableId, TableStats,
-        UnexpectedWithMsg, UnsupportedMethod, WriteBatch, WriteRequest,
+        AlterOptions, AlterSchema, AlterSchemaRequest, CreatePartitionRule, Durability,
+        FlushRequest, GetRequest, LocatePartitions, ReadRequest, Result, Scan, Table, TableId,
+        TableStats, UnexpectedWithMsg, UnsupportedMethod, WriteBatch, WriteRequest,
     },
 };
 
@@ -108,12 +108,16 @@ impl PartitionTableImpl {
         &self,
         partition_id: usize,
         row_group: RowGroup,
+        durability: Durability,
     ) -> Result<usize> {
         let sub_table_ident = self.get_sub_table_ident(partition_id);
 
         let request = RemoteWriteRequest {
             table: sub_table_ident,
-            write_request: WriteRequest { row_group },
+            write_request: WriteRequest {
+                row_group,
+                durability,
+            },
         };
 
         self.remote_engine
@@ -129,6 +133,7 @@ impl PartitionTableImpl {
         &self,
         schema: Schema,
         partitioned_rows: PartitionedRowsIter,
+        durability: Durability,
     ) -> Result<usize> {
         let mut split_rows = HashMap::new();
         for PartitionedRow { partition_id, row } in partitioned_rows {
@@ -148,7 +153,10 @@ impl PartitionTableImpl {
 
             let request = RemoteWriteRequest {
                 table: sub_table_ident,
-                write_request: WriteRequest { row_group },
+                write_request: WriteRequest {
+                    row_group,
+                    durability,
+                },
             };
             request_batch.push(request);
         }
@@ -233,7 +241,8 @@ impl Table for PartitionTableImpl {
             .with_label_values(&["total"])
             .start_timer();
 
-        // Split write request.
+        // Split write request, the sub-tables are written with the same durability.
+        let durability = request.durability;
         let schema = request.row_group.schema().clone();
         let partition_rows = {
             let _locate_timer = PARTITION_TABLE_WRITE_DURATION_HISTOGRAM
@@ -249,9 +258,13 @@ impl Table for PartitionTableImpl {
             PartitionedRows::Single {
                 partition_id,
                 row_group,
-            } => self.write_single_row_group(partition_id, row_group).await,
+            } => {
+                self.write_single_row_group(partition_id, row_group, durability)
+                    .await
+            }
             PartitionedRows::Multiple(iter) => {
-                self.write_partitioned_row_groups(schema, iter).await
+                self.write_partitioned_row_groups(schema, iter, durability)
+                    .await
             }
         }
     }
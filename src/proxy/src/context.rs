@@ -21,7 +21,8 @@ use std::time::Duration;
 
 use common_types::request_id::RequestId;
 use macros::define_result;
-use snafu::{ensure, Backtrace, Snafu};
+use snafu::{ensure, Backtrace, OptionExt, Snafu};
+use table_engine::table::Durability;
 
 #[allow(clippy::enum_variant_names)]
 #[derive(Debug, Snafu)]
@@ -37,6 +38,18 @@ pub enum Error {
 
     #[snafu(display("Missing router.\nBacktrace:\n{}", backtrace))]
     MissingRouter { backtrace: Backtrace },
+
+    #[snafu(display("Invalid durability:{}.\nBacktrace:\n{}", durability, backtrace))]
+    InvalidDurability {
+        durability: String,
+        backtrace: Backtrace,
+    },
+
+    #[snafu(display("Invalid max staleness:{}.\nBacktrace:\n{}", max_staleness, backtrace))]
+    InvalidMaxStaleness {
+        max_staleness: String,
+        backtrace: Backtrace,
+    },
 }
 
 define_result!(Error);
@@ -58,6 +71,10 @@ pub struct RequestContext {
     pub request_id: RequestId,
     /// authorization
     pub authorization: Option<String>,
+    /// Durability level of the writes
+    pub durability: Durability,
+    /// Staleness tolerated by the queries
+    pub max_staleness: Option<Duration>,
 }
 
 impl RequestContext {
@@ -72,6 +89,8 @@ pub struct Builder {
     schema: String,
     timeout: Option<Duration>,
     authorization: Option<String>,
+    durability: Option<String>,
+    max_staleness: Option<String>,
 }
 
 impl Builder {
@@ -95,9 +114,32 @@ impl Builder {
         self
     }
 
+    pub fn durability(mut self, durability: Option<String>) -> Self {
+        self.durability = durability;
+        self
+    }
+
+    pub fn max_staleness(mut self, max_staleness: Option<String>) -> Self {
+        self.max_staleness = max_staleness;
+        self
+    }
+
     pub fn build(self) -> Result<RequestContext> {
         ensure!(!self.catalog.is_empty(), MissingCatalog);
         ensure!(!self.schema.is_empty(), MissingSchema);
+        let durability = match self.durability {
+            Some(durability) => {
+                Durability::parse(&durability).context(InvalidDurability { durability })?
+            }
+            None => Durability::default(),
+        };
+        let max_staleness = self
+            .max_staleness
+            .map(|max_staleness| {
+                crate::parse_max_staleness(&max_staleness)
+                    .context(InvalidMaxStaleness { max_staleness })
+            })
+            .transpose()?;
 
         Ok(RequestContext {
             catalog: self.catalog,
@@ -105,6 +147,8 @@ impl Builder {
             timeout: self.timeout,
             request_id: RequestId::next_id(),
             authorization: self.authorization,
+            durability,
+            max_staleness,
         })
     }
 }
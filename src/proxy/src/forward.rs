@@ -20,6 +20,7 @@ use std::{
     collections::HashMap,
     net::Ipv4Addr,
     sync::{Arc, RwLock},
+    time::Duration,
 };
 
 use async_trait::async_trait;
@@ -37,7 +38,7 @@ use tonic::{
     transport::{self, Channel},
 };
 
-use crate::{auth::AUTHORIZATION, FORWARDED_FROM};
+use crate::{auth::AUTHORIZATION, FORWARDED_FROM, MAX_STALENESS};
 
 #[derive(Debug, Snafu)]
 pub enum Error {
@@ -207,6 +208,9 @@ pub struct ForwardRequest<Req> {
     pub req: tonic::Request<Req>,
     pub forwarded_from: Option<String>,
     pub authorization: Option<String>,
+    /// The staleness tolerated by the request, which may be forwarded to the
+    /// followers if set.
+    pub max_staleness: Option<Duration>,
 }
 
 impl Forwarder<DefaultClientBuilder> {
@@ -282,9 +286,10 @@ impl<B: ClientBuilder> Forwarder<B> {
         let ForwardRequest {
             schema,
             table,
-            req,
+            mut req,
             forwarded_from,
             authorization,
+            max_staleness,
         } = forward_req;
 
         let req_pb = RouteRequestPb {
@@ -292,7 +297,7 @@ impl<B: ClientBuilder> Forwarder<B> {
             tables: vec![table],
         };
 
-        let request = RouteRequest::new(req_pb, true);
+        let request = RouteRequest::new(req_pb, true).with_max_staleness(max_staleness);
         let endpoint = match self.router.route(request).await {
             Ok(mut routes) => {
                 if routes.len() != 1 || routes[0].endpoint.is_none() {
@@ -311,6 +316,15 @@ impl<B: ClientBuilder> Forwarder<B> {
             }
         };
 
+        // Carry the bound so that the follower receiving the forwarded request won't
+        // route it to the leader again.
+        if let Some(max_staleness) = max_staleness {
+            req.metadata_mut().insert(
+                MAX_STALENESS,
+                max_staleness.as_secs().to_string().parse().unwrap(),
+            );
+        }
+
         self.forward_with_endpoint(endpoint, req, forwarded_from, authorization, do_rpc)
             .await
     }
@@ -512,6 +526,7 @@ mod tests {
                 req: query_request.into_request(),
                 forwarded_from: None,
                 authorization: None,
+                max_staleness: None,
             }
         };
 
@@ -228,6 +228,7 @@ impl Proxy {
             req: req.clone().into_request(),
             forwarded_from: ctx.forwarded_from.clone(),
             authorization: ctx.authorization.clone(),
+            max_staleness: ctx.max_staleness,
         };
         let do_query = |mut client: StorageServiceClient<Channel>,
                         request: tonic::Request<SqlQueryRequest>,
@@ -79,7 +79,8 @@ impl Proxy {
             }),
             table_requests: write_table_requests,
         };
-        let ctx = ProxyContext::new(ctx.timeout, None, ctx.authorization);
+        let ctx =
+            ProxyContext::new(ctx.timeout, None, ctx.authorization).with_durability(ctx.durability);
 
         match self.handle_write_internal(ctx, table_request).await {
             Ok(result) => {
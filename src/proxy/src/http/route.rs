@@ -49,10 +49,7 @@ impl Proxy {
             tables: vec![table.to_string()],
         };
 
-        let request = RouteRequest {
-            route_with_cache: false,
-            inner: req_pb,
-        };
+        let request = RouteRequest::new(req_pb, false);
 
         let routes = self.route(request).await?;
 
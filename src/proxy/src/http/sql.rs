@@ -50,7 +50,8 @@ impl Proxy {
         req: Request,
     ) -> Result<Output> {
         let schema = &ctx.schema;
-        let ctx = Context::new(ctx.timeout, None, ctx.authorization.clone());
+        let ctx = Context::new(ctx.timeout, None, ctx.authorization.clone())
+            .with_max_staleness(ctx.max_staleness);
 
         let query_res = self
             .handle_sql(
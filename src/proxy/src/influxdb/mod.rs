@@ -81,7 +81,8 @@ impl Proxy {
             }),
             table_requests: write_table_requests,
         };
-        let proxy_context = Context::new(ctx.timeout, None, ctx.authorization);
+        let proxy_context =
+            Context::new(ctx.timeout, None, ctx.authorization).with_durability(ctx.durability);
 
         match self
             .handle_write_internal(proxy_context, table_request)
@@ -105,7 +106,9 @@ impl Proxy {
                     ctx.catalog, ctx.schema
                 );
 
-                Ok(())
+                Ok(WriteResponse {
+                    durability: result.durability.as_str(),
+                })
             }
             Err(e) => {
                 HTTP_HANDLER_COUNTER_VEC.write_failed.inc();
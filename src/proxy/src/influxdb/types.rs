@@ -66,7 +66,13 @@ impl WriteRequest {
     }
 }
 
-pub type WriteResponse = ();
+/// Influxql write response
+///
+/// The durability level reached by the written rows is echoed to the client.
+#[derive(Debug, Serialize)]
+pub struct WriteResponse {
+    pub durability: &'static str,
+}
 
 /// Query string parameters for write api
 ///
@@ -53,6 +53,8 @@ pub struct DynamicConfig {
     pub fronted: Arc<FrontendDynamicConfig>,
     /// Slow threshold(seconds)
     pub slow_threshold: Arc<AtomicU64>,
+    /// Number of traced queries per thousand queries, 0 disables the tracing
+    pub query_trace_sample_permille: Arc<AtomicU64>,
 }
 
 impl Default for DynamicConfig {
@@ -60,6 +62,7 @@ impl Default for DynamicConfig {
         Self {
             fronted: Default::default(),
             slow_threshold: Arc::new(AtomicU64::new(5)),
+            query_trace_sample_permille: Arc::new(AtomicU64::new(0)),
         }
     }
 }
@@ -35,12 +35,21 @@ pub mod instance;
 pub mod limiter;
 mod metrics;
 pub mod opentsdb;
+mod query_trace;
 mod read;
 pub mod schema_config_provider;
+pub mod schema_registry;
 mod util;
 mod write;
 
 pub const FORWARDED_FROM: &str = "forwarded-from";
+/// Metadata key carrying the durability level of the write request
+pub const DURABILITY: &str = "x-horaedb-durability";
+/// Metadata key marking the tables of the write request to be written
+/// atomically
+pub const ATOMIC_WRITE: &str = "x-horaedb-atomic-write";
+/// Metadata key carrying the seconds of staleness tolerated by the query
+pub const MAX_STALENESS: &str = "x-horaedb-max-staleness";
 
 use std::{
     sync::Arc,
@@ -75,7 +84,7 @@ use table_engine::{
     engine::{CreateTableParams, EngineRuntimes, TableState},
     partition::PartitionInfo,
     remote::model::{GetTableInfoRequest, TableIdentifier, TableInfo},
-    table::{TableId, TableRef},
+    table::{Durability, TableId, TableRef},
     PARTITION_TABLE_ENGINE_TYPE,
 };
 use tonic::{transport::Channel, IntoRequest};
@@ -185,6 +194,7 @@ impl Proxy {
             req: req.into_request(),
             forwarded_from: None,
             authorization: ctx.authorization.clone(),
+            max_staleness: ctx.max_staleness,
         };
         let do_query = |mut client: StorageServiceClient<Channel>,
                         request: tonic::Request<PrometheusRemoteQueryRequest>,
@@ -549,6 +559,9 @@ pub struct Context {
     timeout: Option<Duration>,
     forwarded_from: Option<String>,
     authorization: Option<String>,
+    durability: Durability,
+    atomic_write: bool,
+    max_staleness: Option<Duration>,
 }
 
 impl Context {
@@ -562,6 +575,31 @@ impl Context {
             timeout,
             forwarded_from,
             authorization,
+            durability: Durability::default(),
+            atomic_write: false,
+            max_staleness: None,
         }
     }
+
+    pub fn with_durability(mut self, durability: Durability) -> Self {
+        self.durability = durability;
+        self
+    }
+
+    /// Write all the tables of the write request atomically, which requires
+    /// them to be on the same shard.
+    pub fn with_atomic_write(mut self, atomic_write: bool) -> Self {
+        self.atomic_write = atomic_write;
+        self
+    }
+
+    pub fn with_max_staleness(mut self, max_staleness: Option<Duration>) -> Self {
+        self.max_staleness = max_staleness;
+        self
+    }
+}
+
+/// Parse the seconds of the staleness tolerated by the query.
+pub fn parse_max_staleness(value: &str) -> Option<Duration> {
+    value.trim().parse().ok().map(Duration::from_secs)
 }
@@ -17,8 +17,13 @@
 
 // Grpc proxy metrics
 
+use std::sync::Mutex;
+
 use lazy_static::lazy_static;
-use prometheus::{register_int_counter_vec, IntCounterVec};
+use prometheus::{
+    exponential_buckets, register_gauge_vec, register_histogram, register_int_counter_vec,
+    GaugeVec, Histogram, IntCounterVec,
+};
 use prometheus_static_metric::{auto_flush_from, make_auto_flush_static_metric};
 
 make_auto_flush_static_metric! {
@@ -78,3 +83,71 @@ lazy_static! {
     pub static ref HTTP_HANDLER_COUNTER_VEC: HttpHandlerCounterVec =
         auto_flush_from!(HTTP_HANDLER_COUNTER_VEC_GLOBAL, HttpHandlerCounterVec);
 }
+
+lazy_static! {
+    pub static ref SQL_QUERY_DURATION_HISTOGRAM: ExemplarHistogram = {
+        // 0.005s, 0.01s, ... 163.84s
+        let buckets = exponential_buckets(0.005, 2.0, 16).unwrap();
+        let histogram = register_histogram!(
+            "sql_query_duration",
+            "Bucketed histogram of sql query duration",
+            buckets.clone()
+        )
+        .unwrap();
+        let exemplars = register_gauge_vec!(
+            "sql_query_duration_exemplar",
+            "Duration of the latest sampled sql query in every bucket",
+            &["le", "trace_id"]
+        )
+        .unwrap();
+        ExemplarHistogram::new(histogram, exemplars, buckets)
+    };
+}
+
+/// A histogram keeping the latest sampled trace of every bucket as an
+/// exemplar.
+///
+/// The prometheus client doesn't support exemplars, so they are exposed by a
+/// gauge labeled with the `le` of the bucket and the `trace_id`, whose value
+/// is the observed value.
+pub struct ExemplarHistogram {
+    histogram: Histogram,
+    exemplars: GaugeVec,
+    /// Upper bounds of the buckets, excluding the `+Inf` one.
+    buckets: Vec<f64>,
+    /// Trace id of the exemplar of every bucket, including the `+Inf` one.
+    trace_ids: Mutex<Vec<Option<String>>>,
+}
+
+impl ExemplarHistogram {
+    pub fn new(histogram: Histogram, exemplars: GaugeVec, buckets: Vec<f64>) -> Self {
+        let trace_ids = Mutex::new(vec![None; buckets.len() + 1]);
+        Self {
+            histogram,
+            exemplars,
+            buckets,
+            trace_ids,
+        }
+    }
+
+    pub fn observe(&self, v: f64) {
+        self.histogram.observe(v);
+    }
+
+    /// Observe `v` and replace the exemplar of its bucket with `trace_id`.
+    pub fn observe_with_exemplar(&self, v: f64, trace_id: &str) {
+        self.histogram.observe(v);
+
+        let idx = self.buckets.partition_point(|upper| *upper < v);
+        let le = match self.buckets.get(idx) {
+            Some(upper) => upper.to_string(),
+            None => "+Inf".to_string(),
+        };
+
+        let mut trace_ids = self.trace_ids.lock().unwrap();
+        if let Some(old_trace_id) = trace_ids[idx].replace(trace_id.to_string()) {
+            let _ = self.exemplars.remove_label_values(&[&le, &old_trace_id]);
+        }
+        self.exemplars.with_label_values(&[&le, trace_id]).set(v);
+    }
+}
@@ -69,7 +69,8 @@ impl Proxy {
             }),
             table_requests: write_table_requests,
         };
-        let proxy_context = Context::new(ctx.timeout, None, ctx.authorization);
+        let proxy_context =
+            Context::new(ctx.timeout, None, ctx.authorization).with_durability(ctx.durability);
 
         match self
             .handle_write_internal(proxy_context, table_request)
@@ -0,0 +1,184 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+//! Head-based sampling of the query tracing.
+//!
+//! Whether to trace a query is decided once the query arrives, and a sampled
+//! query records the elapsed time of every phase. The request id is used as
+//! the trace id, so that the exemplar attached to the query duration histogram
+//! leads to the trace in the log.
+
+use std::{
+    collections::hash_map::DefaultHasher,
+    hash::{Hash, Hasher},
+    time::{Duration, Instant},
+};
+
+use common_types::request_id::RequestId;
+use logger::info;
+
+use crate::metrics::SQL_QUERY_DURATION_HISTOGRAM;
+
+/// The sample ratio is expressed as the number of traced queries per
+/// `SAMPLE_RATIO_BASE` queries.
+pub const SAMPLE_RATIO_BASE: u64 = 1000;
+
+/// Decide whether to trace the query with the `request_id`.
+///
+/// The decision only depends on the request id, so it is stable for the same
+/// request.
+pub fn should_sample(request_id: &RequestId, sample_permille: u64) -> bool {
+    if sample_permille == 0 {
+        return false;
+    }
+    if sample_permille >= SAMPLE_RATIO_BASE {
+        return true;
+    }
+
+    let mut hasher = DefaultHasher::new();
+    request_id.as_str().hash(&mut hasher);
+    hasher.finish() % SAMPLE_RATIO_BASE < sample_permille
+}
+
+/// Trace of a query, which is a no-op if the query isn't sampled.
+pub struct QueryTrace {
+    trace_id: String,
+    sampled: bool,
+    start_time: Instant,
+    last_span_end: Instant,
+    spans: Vec<(&'static str, Duration)>,
+}
+
+impl QueryTrace {
+    pub fn new(request_id: &RequestId, sample_permille: u64, start_time: Instant) -> Self {
+        Self {
+            trace_id: request_id.to_string(),
+            sampled: should_sample(request_id, sample_permille),
+            start_time,
+            last_span_end: start_time,
+            spans: Vec::new(),
+        }
+    }
+
+    #[inline]
+    pub fn is_sampled(&self) -> bool {
+        self.sampled
+    }
+
+    /// Record the span `name` which lasts from the end of the last span to now.
+    pub fn finish_span(&mut self, name: &'static str) {
+        if !self.sampled {
+            return;
+        }
+
+        let now = Instant::now();
+        self.spans
+            .push((name, now.saturating_duration_since(self.last_span_end)));
+        self.last_span_end = now;
+    }
+
+    /// Finish the trace and observe the duration of the query, the trace id is
+    /// attached to the histogram as an exemplar if the query is sampled.
+    pub fn finish(self) {
+        let elapsed = self.start_time.elapsed();
+        if !self.sampled {
+            SQL_QUERY_DURATION_HISTOGRAM.observe(elapsed.as_secs_f64());
+            return;
+        }
+
+        SQL_QUERY_DURATION_HISTOGRAM.observe_with_exemplar(elapsed.as_secs_f64(), &self.trace_id);
+        let spans = self
+            .spans
+            .iter()
+            .map(|(name, cost)| format!("{name}:{cost:?}"))
+            .collect::<Vec<_>>()
+            .join(", ");
+        info!(
+            "Query trace, trace_id:{}, elapsed:{elapsed:?}, spans:[{spans}]",
+            self.trace_id
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use prometheus::{GaugeVec, Histogram, HistogramOpts, Opts};
+
+    use super::*;
+    use crate::metrics::ExemplarHistogram;
+
+    #[test]
+    fn test_should_sample() {
+        let request_ids = (0..1000).map(|_| RequestId::next_id()).collect::<Vec<_>>();
+        assert!(request_ids.iter().all(|id| !should_sample(id, 0)));
+        assert!(request_ids
+            .iter()
+            .all(|id| should_sample(id, SAMPLE_RATIO_BASE)));
+
+        let sampled = request_ids
+            .iter()
+            .filter(|id| should_sample(id, 100))
+            .count();
+        assert!(sampled > 0 && sampled < request_ids.len());
+
+        // The decision is stable for the same request.
+        for id in &request_ids {
+            assert_eq!(should_sample(id, 100), should_sample(id, 100));
+        }
+    }
+
+    #[test]
+    fn test_record_spans() {
+        let request_id = RequestId::next_id();
+        let mut trace = QueryTrace::new(&request_id, SAMPLE_RATIO_BASE, Instant::now());
+        trace.finish_span("parse");
+        trace.finish_span("plan");
+        let names = trace
+            .spans
+            .iter()
+            .map(|(name, _)| *name)
+            .collect::<Vec<_>>();
+        assert_eq!(names, vec!["parse", "plan"]);
+
+        let mut trace = QueryTrace::new(&request_id, 0, Instant::now());
+        trace.finish_span("parse");
+        assert!(!trace.is_sampled());
+        assert!(trace.spans.is_empty());
+    }
+
+    #[test]
+    fn test_exemplar_histogram() {
+        let buckets = vec![0.1, 1.0];
+        let histogram =
+            Histogram::with_opts(HistogramOpts::new("test", "test").buckets(buckets.clone()))
+                .unwrap();
+        let exemplars = GaugeVec::new(Opts::new("test", "test"), &["le", "trace_id"]).unwrap();
+        let histogram = ExemplarHistogram::new(histogram, exemplars.clone(), buckets);
+
+        histogram.observe_with_exemplar(0.05, "a");
+        histogram.observe_with_exemplar(0.5, "b");
+        histogram.observe_with_exemplar(5.0, "c");
+        assert_eq!(exemplars.with_label_values(&["0.1", "a"]).get(), 0.05);
+        assert_eq!(exemplars.with_label_values(&["1", "b"]).get(), 0.5);
+        assert_eq!(exemplars.with_label_values(&["+Inf", "c"]).get(), 5.0);
+
+        // Only the latest exemplar of a bucket is kept.
+        histogram.observe_with_exemplar(0.06, "d");
+        assert!(exemplars.remove_label_values(&["0.1", "a"]).is_err());
+        assert_eq!(exemplars.with_label_values(&["0.1", "d"]).get(), 0.06);
+    }
+}
@@ -43,6 +43,7 @@ use crate::{
     error::{ErrNoCause, ErrWithCause, Error, Internal, InternalNoCause, Result},
     forward::{ForwardRequest, ForwardResult},
     metrics::GRPC_HANDLER_COUNTER_VEC,
+    query_trace::QueryTrace,
     Context, Proxy,
 };
 
@@ -182,6 +183,12 @@ impl Proxy {
         let slow_threshold = Duration::from_secs(slow_threshold_secs);
         let mut slow_timer = SlowTimer::new(request_id.as_str(), sql, slow_threshold);
         let deadline = ctx.timeout.map(|t| slow_timer.start_time() + t);
+        let sample_permille = self
+            .instance()
+            .dyn_config
+            .query_trace_sample_permille
+            .load(std::sync::atomic::Ordering::Relaxed);
+        let mut trace = QueryTrace::new(request_id, sample_permille, slow_timer.start_time());
         let catalog = self.instance.catalog_manager.default_catalog_name();
 
         info!("Handle sql query begin, request_id:{request_id}, catalog:{catalog}, schema:{schema}, ctx:{ctx:?}, sql:{sql}");
@@ -207,6 +214,7 @@ impl Proxy {
                 code: StatusCode::BAD_REQUEST,
                 msg: "Failed to parse sql",
             })?;
+        trace.finish_span("parse");
 
         // TODO: For simplicity, we only support executing one statement
         let stmts_len = stmts.len();
@@ -260,6 +268,7 @@ impl Proxy {
                 slow_timer.priority(priority);
             }
         }
+        trace.finish_span("plan");
 
         let output = if enable_partition_table_access {
             self.execute_plan_involving_partition_table(
@@ -278,11 +287,14 @@ impl Proxy {
             code: StatusCode::INTERNAL_SERVER_ERROR,
             msg: "Failed to execute plan",
         })?;
+        trace.finish_span("execute");
 
         let cost = slow_timer.elapsed();
+        let traced = trace.is_sampled();
         info!(
-            "Handle sql query finished, sql:{sql}, elapsed:{cost:?}, catalog:{catalog}, schema:{schema}, ctx:{ctx:?}",
+            "Handle sql query finished, sql:{sql}, elapsed:{cost:?}, catalog:{catalog}, schema:{schema}, ctx:{ctx:?}, traced:{traced}",
         );
+        trace.finish();
 
         Ok(output)
     }
@@ -317,6 +329,7 @@ impl Proxy {
             req: sql_request.into_request(),
             forwarded_from: ctx.forwarded_from,
             authorization: ctx.authorization,
+            max_staleness: ctx.max_staleness,
         };
         let do_query = |mut client: StorageServiceClient<Channel>,
                         request: tonic::Request<SqlQueryRequest>,
@@ -0,0 +1,173 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+//! Client of the Confluent schema registry, only avro schemas are supported.
+
+use async_trait::async_trait;
+use common_types::datum::DatumKind;
+use generic_error::BoxError;
+use serde::Deserialize;
+use serde_json::Value;
+use snafu::{OptionExt, ResultExt};
+
+use super::{FetchSchema, InvalidSchema, RegistryColumn, RegistrySchema, Result, SchemaRegistry};
+
+#[derive(Debug, Deserialize)]
+struct SchemaResponse {
+    schema: String,
+}
+
+/// Schema registry backed by the rest api of Confluent schema registry.
+///
+/// Fields of the avro record are mapped to columns, and string fields are
+/// treated as tags.
+pub struct ConfluentSchemaRegistry {
+    endpoint: String,
+    client: reqwest::Client,
+}
+
+impl ConfluentSchemaRegistry {
+    pub fn new(endpoint: String) -> Self {
+        Self {
+            endpoint: endpoint.trim_end_matches('/').to_string(),
+            client: reqwest::Client::new(),
+        }
+    }
+}
+
+#[async_trait]
+impl SchemaRegistry for ConfluentSchemaRegistry {
+    async fn fetch_schema(&self, id: u32) -> Result<RegistrySchema> {
+        let url = format!("{}/schemas/ids/{id}", self.endpoint);
+        let resp: SchemaResponse = self
+            .client
+            .get(&url)
+            .send()
+            .await
+            .and_then(|resp| resp.error_for_status())
+            .box_err()
+            .context(FetchSchema { id })?
+            .json()
+            .await
+            .box_err()
+            .context(FetchSchema { id })?;
+
+        parse_avro_schema(id, &resp.schema)
+    }
+}
+
+fn parse_avro_schema(id: u32, schema: &str) -> Result<RegistrySchema> {
+    let schema: Value = serde_json::from_str(schema)
+        .box_err()
+        .context(FetchSchema { id })?;
+    let fields = schema
+        .get("fields")
+        .and_then(Value::as_array)
+        .with_context(|| InvalidSchema {
+            id,
+            msg: "avro record fields are missing",
+        })?;
+
+    let mut columns = Vec::with_capacity(fields.len());
+    for field in fields {
+        let name = field
+            .get("name")
+            .and_then(Value::as_str)
+            .with_context(|| InvalidSchema {
+                id,
+                msg: "avro field name is missing",
+            })?;
+        let kind = field
+            .get("type")
+            .and_then(avro_type_to_datum_kind)
+            .with_context(|| InvalidSchema {
+                id,
+                msg: format!("unsupported avro type of field:{name}"),
+            })?;
+        columns.push(RegistryColumn {
+            name: name.to_string(),
+            kind,
+            is_tag: kind == DatumKind::String,
+        });
+    }
+
+    Ok(RegistrySchema { id, columns })
+}
+
+fn avro_type_to_datum_kind(avro_type: &Value) -> Option<DatumKind> {
+    match avro_type {
+        Value::String(name) => match name.as_str() {
+            "string" => Some(DatumKind::String),
+            "long" => Some(DatumKind::Int64),
+            "int" => Some(DatumKind::Int32),
+            "double" => Some(DatumKind::Double),
+            "float" => Some(DatumKind::Float),
+            "boolean" => Some(DatumKind::Boolean),
+            "bytes" => Some(DatumKind::Varbinary),
+            _ => None,
+        },
+        // Nullable type is represented as union of null and the actual type.
+        Value::Array(types) => {
+            let mut non_null_types = types.iter().filter(|t| t.as_str() != Some("null"));
+            match (non_null_types.next(), non_null_types.next()) {
+                (Some(t), None) => avro_type_to_datum_kind(t),
+                _ => None,
+            }
+        }
+        Value::Object(obj) => match obj.get("logicalType").and_then(Value::as_str) {
+            Some("timestamp-millis") => Some(DatumKind::Timestamp),
+            _ => obj.get("type").and_then(avro_type_to_datum_kind),
+        },
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_avro_schema() {
+        let schema = r#"{
+            "type": "record",
+            "name": "cpu",
+            "fields": [
+                {"name": "host", "type": "string"},
+                {"name": "value", "type": ["null", "double"]},
+                {"name": "ts", "type": {"type": "long", "logicalType": "timestamp-millis"}}
+            ]
+        }"#;
+
+        let schema = parse_avro_schema(1, schema).unwrap();
+        let columns = schema
+            .columns
+            .iter()
+            .map(|c| (c.name.as_str(), c.kind, c.is_tag))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            vec![
+                ("host", DatumKind::String, true),
+                ("value", DatumKind::Double, false),
+                ("ts", DatumKind::Timestamp, false),
+            ],
+            columns
+        );
+
+        let schema = r#"{"type": "record", "fields": [{"name": "m", "type": {"type": "map"}}]}"#;
+        assert!(parse_avro_schema(1, schema).is_err());
+    }
+}
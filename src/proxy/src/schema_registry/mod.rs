@@ -0,0 +1,225 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+//! Integration with a schema registry (Confluent-style) for ingestion formats.
+//!
+//! Payloads of the ingestion paths carry a schema id, the schema is resolved
+//! from the registry once and then cached, and columns missing in the table
+//! are derived from it so the table can evolve with the payload schema.
+
+use std::{
+    collections::HashMap,
+    sync::{Arc, RwLock},
+};
+
+use async_trait::async_trait;
+use common_types::{column_schema::ColumnSchema, datum::DatumKind, schema::Schema};
+use generic_error::{BoxError, GenericError};
+use macros::define_result;
+use query_frontend::planner::build_column_schema;
+use snafu::{ensure, Backtrace, ResultExt, Snafu};
+
+pub mod confluent;
+
+/// Magic byte of the Confluent wire format.
+const WIRE_FORMAT_MAGIC: u8 = 0;
+const WIRE_FORMAT_HEADER_LEN: usize = 5;
+
+#[derive(Debug, Snafu)]
+#[snafu(visibility(pub))]
+pub enum Error {
+    #[snafu(display("Invalid payload, msg:{msg}.\nBacktrace:\n{backtrace}"))]
+    InvalidPayload { msg: String, backtrace: Backtrace },
+
+    #[snafu(display("Failed to fetch schema, id:{id}, err:{source}"))]
+    FetchSchema { id: u32, source: GenericError },
+
+    #[snafu(display("Invalid schema, id:{id}, msg:{msg}.\nBacktrace:\n{backtrace}"))]
+    InvalidSchema {
+        id: u32,
+        msg: String,
+        backtrace: Backtrace,
+    },
+
+    #[snafu(display(
+        "Incompatible column, column:{column}, table:{table_kind}, registry:{registry_kind}.\nBacktrace:\n{backtrace}"
+    ))]
+    IncompatibleColumn {
+        column: String,
+        table_kind: DatumKind,
+        registry_kind: DatumKind,
+        backtrace: Backtrace,
+    },
+
+    #[snafu(display("Failed to build column schema, column:{column}, err:{source}"))]
+    BuildColumnSchema {
+        column: String,
+        source: GenericError,
+    },
+}
+
+define_result!(Error);
+
+/// Column resolved from the registry schema.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RegistryColumn {
+    pub name: String,
+    pub kind: DatumKind,
+    pub is_tag: bool,
+}
+
+/// Table schema resolved from the registry schema.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RegistrySchema {
+    pub id: u32,
+    pub columns: Vec<RegistryColumn>,
+}
+
+pub type SchemaRegistryRef = Arc<dyn SchemaRegistry + Send + Sync>;
+
+#[async_trait]
+pub trait SchemaRegistry {
+    async fn fetch_schema(&self, id: u32) -> Result<RegistrySchema>;
+}
+
+/// Split the payload encoded in the Confluent wire format into the schema id
+/// and the body.
+pub fn decode_wire_format(payload: &[u8]) -> Result<(u32, &[u8])> {
+    ensure!(
+        payload.len() >= WIRE_FORMAT_HEADER_LEN,
+        InvalidPayload {
+            msg: format!("payload is too short, len:{}", payload.len()),
+        }
+    );
+    ensure!(
+        payload[0] == WIRE_FORMAT_MAGIC,
+        InvalidPayload {
+            msg: format!("invalid magic byte:{}", payload[0]),
+        }
+    );
+
+    let id = u32::from_be_bytes(payload[1..WIRE_FORMAT_HEADER_LEN].try_into().unwrap());
+    Ok((id, &payload[WIRE_FORMAT_HEADER_LEN..]))
+}
+
+/// Registry caching the resolved schemas, schemas in registry are immutable
+/// once registered, so the cache never expires.
+pub struct CachedSchemaRegistry {
+    inner: SchemaRegistryRef,
+    cache: RwLock<HashMap<u32, Arc<RegistrySchema>>>,
+}
+
+impl CachedSchemaRegistry {
+    pub fn new(inner: SchemaRegistryRef) -> Self {
+        Self {
+            inner,
+            cache: RwLock::new(HashMap::new()),
+        }
+    }
+
+    pub async fn get_schema(&self, id: u32) -> Result<Arc<RegistrySchema>> {
+        if let Some(schema) = self.cache.read().unwrap().get(&id) {
+            return Ok(schema.clone());
+        }
+
+        let schema = Arc::new(self.inner.fetch_schema(id).await?);
+        self.cache.write().unwrap().insert(id, schema.clone());
+        Ok(schema)
+    }
+}
+
+/// Find the columns in the registry schema but missing in the table schema.
+///
+/// Only adding nullable columns is considered compatible, an error is returned
+/// if the kind of an existing column differs.
+pub fn find_new_columns(
+    table_schema: &Schema,
+    registry_schema: &RegistrySchema,
+) -> Result<Vec<ColumnSchema>> {
+    let mut new_columns = Vec::new();
+    for column in &registry_schema.columns {
+        match table_schema.index_of(&column.name) {
+            Some(idx) => {
+                let table_kind = table_schema.column(idx).data_type;
+                ensure!(
+                    table_kind == column.kind,
+                    IncompatibleColumn {
+                        column: &column.name,
+                        table_kind,
+                        registry_kind: column.kind,
+                    }
+                );
+            }
+            None => {
+                let column_schema = build_column_schema(&column.name, column.kind, column.is_tag)
+                    .box_err()
+                    .context(BuildColumnSchema {
+                        column: &column.name,
+                    })?;
+                new_columns.push(column_schema);
+            }
+        }
+    }
+
+    Ok(new_columns)
+}
+
+#[cfg(test)]
+mod tests {
+    use common_types::tests::build_schema;
+
+    use super::*;
+
+    #[test]
+    fn test_decode_wire_format() {
+        let payload = [0, 0, 0, 1, 2, 42];
+        let (id, body) = decode_wire_format(&payload).unwrap();
+        assert_eq!(258, id);
+        assert_eq!(&[42], body);
+
+        assert!(decode_wire_format(&[0, 0, 1]).is_err());
+        assert!(decode_wire_format(&[1, 0, 0, 0, 1]).is_err());
+    }
+
+    #[test]
+    fn test_find_new_columns() {
+        let table_schema = build_schema();
+        let mut registry_schema = RegistrySchema {
+            id: 1,
+            columns: vec![
+                RegistryColumn {
+                    name: "field1".to_string(),
+                    kind: DatumKind::Double,
+                    is_tag: false,
+                },
+                RegistryColumn {
+                    name: "host".to_string(),
+                    kind: DatumKind::String,
+                    is_tag: true,
+                },
+            ],
+        };
+
+        let new_columns = find_new_columns(&table_schema, &registry_schema).unwrap();
+        assert_eq!(1, new_columns.len());
+        assert_eq!("host", new_columns[0].name);
+        assert!(new_columns[0].is_tag);
+
+        registry_schema.columns[0].kind = DatumKind::Int64;
+        assert!(find_new_columns(&table_schema, &registry_schema).is_err());
+    }
+}
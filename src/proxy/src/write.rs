@@ -24,6 +24,7 @@ use std::{
 };
 
 use bytes::Bytes;
+use catalog::schema::SchemaRef;
 use cluster::config::SchemaConfig;
 use common_types::{
     column_schema::ColumnSchema,
@@ -40,7 +41,7 @@ use horaedbproto::storage::{
     WriteRequest, WriteResponse as WriteResponsePB, WriteSeriesEntry, WriteTableRequest,
 };
 use http::StatusCode;
-use interpreters::interpreter::Output;
+use interpreters::{insert, interpreter::Output};
 use logger::{debug, error, info, warn};
 use query_frontend::{
     frontend::{Context as FrontendContext, Frontend},
@@ -50,13 +51,16 @@ use query_frontend::{
 };
 use router::{endpoint::Endpoint, RouteRequest};
 use snafu::{ensure, OptionExt, ResultExt};
-use table_engine::table::TableRef;
+use table_engine::{
+    engine::{TableWriteRequest, WriteTablesRequest},
+    table::{self, Durability, TableRef},
+};
 use tonic::transport::Channel;
 
 use crate::{
     error::{ErrNoCause, ErrWithCause, Internal, InternalNoCause, Result},
     forward::{ForwardResult, ForwarderRef},
-    Context, Proxy,
+    Context, Proxy, ATOMIC_WRITE, DURABILITY,
 };
 
 type WriteResponseFutures<'a> = Vec<BoxFuture<'a, runtime::Result<Result<WriteResponse>>>>;
@@ -73,12 +77,15 @@ pub struct WriteContext {
     pub catalog: String,
     pub schema: String,
     pub auto_create_table: bool,
+    pub durability: Durability,
 }
 
 #[derive(Debug, Default)]
 pub(crate) struct WriteResponse {
     pub success: u32,
     pub failed: u32,
+    /// The durability level reached by the written rows
+    pub durability: Durability,
 }
 
 impl Proxy {
@@ -121,6 +128,9 @@ impl Proxy {
 
         let (write_request_to_local, write_requests_to_forward) =
             self.split_write_request(req).await?;
+        if ctx.atomic_write {
+            check_atomic_write_routes(&write_request_to_local, &write_requests_to_forward)?;
+        }
 
         let mut futures = Vec::with_capacity(write_requests_to_forward.len() + 1);
 
@@ -151,6 +161,9 @@ impl Proxy {
         })?;
         let (write_request_to_local, write_requests_to_forward) =
             self.split_write_request(req).await?;
+        if ctx.atomic_write {
+            check_atomic_write_routes(&write_request_to_local, &write_requests_to_forward)?;
+        }
 
         let mut futures = Vec::with_capacity(write_requests_to_forward.len() + 1);
 
@@ -409,16 +422,19 @@ impl Proxy {
     ) -> Result<WriteResponse> {
         let mut futures: FuturesUnordered<_> = futures.into_iter().collect();
         let mut success = 0;
+        let mut durability = Durability::default();
         while let Some(resp) = futures.next().await {
             let resp = resp.box_err().context(ErrWithCause {
                 code: StatusCode::INTERNAL_SERVER_ERROR,
                 msg: "Failed to join task",
-            })?;
-            success += resp?.success;
+            })??;
+            success += resp.success;
+            durability = resp.durability;
         }
 
         Ok(WriteResponse {
             success,
+            durability,
             ..Default::default()
         })
     }
@@ -448,10 +464,21 @@ impl Proxy {
             Box::new(write) as _
         };
 
+        let durability = ctx.durability;
+        let mut request = tonic::Request::new(table_write_request);
+        // The durability is carried by the metadata and honored by the remote node.
+        request
+            .metadata_mut()
+            .insert(DURABILITY, durability.as_str().parse().unwrap());
+        if ctx.atomic_write {
+            request
+                .metadata_mut()
+                .insert(ATOMIC_WRITE, "true".parse().unwrap());
+        }
         let forward_result = forwarder
             .forward_with_endpoint(
                 endpoint,
-                tonic::Request::new(table_write_request),
+                request,
                 ctx.forwarded_from,
                 ctx.authorization,
                 do_write,
@@ -472,6 +499,7 @@ impl Proxy {
             ForwardResult::Forwarded(resp) => resp.map(|v: WriteResponsePB| WriteResponse {
                 success: v.success,
                 failed: v.failed,
+                durability,
             }),
             ForwardResult::Local => InternalNoCause {
                 msg: "Local response is not expected".to_string(),
@@ -505,12 +533,24 @@ impl Proxy {
             catalog: catalog_name.to_string(),
             schema: schema_name.clone(),
             auto_create_table: self.auto_create_table,
+            durability: ctx.durability,
         };
 
         let plans = self
             .write_request_to_insert_plan(req.table_requests, write_context)
             .await?;
 
+        if ctx.atomic_write {
+            let success = self
+                .write_plans_atomically(catalog_name, &schema_name, plans)
+                .await?;
+            return Ok(WriteResponse {
+                success: success as u32,
+                durability: ctx.durability,
+                ..Default::default()
+            });
+        }
+
         let mut success = 0;
 
         // TODO: concurrently run the insert plan here
@@ -557,10 +597,86 @@ impl Proxy {
 
         Ok(WriteResponse {
             success: success as u32,
+            durability: ctx.durability,
             ..Default::default()
         })
     }
 
+    /// Write the tables of the insert plans atomically through
+    /// [table_engine::engine::TableEngine::write_tables].
+    async fn write_plans_atomically(
+        &self,
+        catalog: &str,
+        schema: &str,
+        plans: Vec<PlanWithTable>,
+    ) -> Result<usize> {
+        let schema_id = self.try_get_schema(catalog, schema)?.id();
+        let mut engine = None;
+        let mut writes = Vec::with_capacity(plans.len());
+        for PlanWithTable { plan, table } in plans {
+            self.instance
+                .limiter
+                .try_limit(&plan)
+                .box_err()
+                .context(ErrWithCause {
+                    code: StatusCode::INTERNAL_SERVER_ERROR,
+                    msg: "table is blocked",
+                })?;
+
+            let Plan::Insert(InsertPlan {
+                source: InsertSource::Values { mut row_group },
+                default_value_map,
+                durability,
+                ..
+            }) = plan
+            else {
+                return InternalNoCause {
+                    msg: "Invalid plan type, expect insert values".to_string(),
+                }
+                .fail();
+            };
+            let expect_engine = engine.get_or_insert_with(|| table.engine_type().to_string());
+            ensure!(
+                expect_engine.as_str() == table.engine_type(),
+                ErrNoCause {
+                    code: StatusCode::BAD_REQUEST,
+                    msg: format!(
+                        "Tables of different engines can't be written atomically, table:{}",
+                        table.name()
+                    ),
+                }
+            );
+
+            insert::prepare_row_group(table.clone(), &mut row_group, &default_value_map)
+                .box_err()
+                .context(ErrWithCause {
+                    code: StatusCode::BAD_REQUEST,
+                    msg: format!("Failed to prepare rows, table:{}", table.name()),
+                })?;
+            writes.push(TableWriteRequest {
+                schema_id,
+                table_name: table.name().to_string(),
+                request: table::WriteRequest {
+                    row_group,
+                    durability,
+                },
+            });
+        }
+
+        let Some(engine) = engine else {
+            return Ok(0);
+        };
+        self.instance
+            .table_engine
+            .write_tables(WriteTablesRequest { writes, engine })
+            .await
+            .box_err()
+            .context(ErrWithCause {
+                code: StatusCode::INTERNAL_SERVER_ERROR,
+                msg: "Failed to write tables atomically",
+            })
+    }
+
     async fn write_request_to_insert_plan(
         &self,
         table_requests: Vec<WriteTableRequest>,
@@ -574,6 +690,7 @@ impl Proxy {
             schema,
             deadline,
             auto_create_table,
+            durability,
         } = write_context;
         for write_table_req in table_requests {
             let table_name = &write_table_req.table;
@@ -610,7 +727,8 @@ impl Proxy {
             }
 
             let table_clone = table.clone();
-            let plan = match write_table_request_to_insert_plan(table, write_table_req) {
+            let plan = match write_table_request_to_insert_plan(table, write_table_req, durability)
+            {
                 Err(e) => {
                     // TODO: remove this logic.
                     // Refer to https://github.com/apache/incubator-horaedb/issues/1248.
@@ -662,6 +780,16 @@ impl Proxy {
         schema: &str,
         table_name: &str,
     ) -> Result<Option<TableRef>> {
+        self.try_get_schema(catalog, schema)?
+            .table_by_name(table_name)
+            .box_err()
+            .with_context(|| ErrWithCause {
+                code: StatusCode::INTERNAL_SERVER_ERROR,
+                msg: format!("Failed to find table, table:{table_name}"),
+            })
+    }
+
+    fn try_get_schema(&self, catalog: &str, schema: &str) -> Result<SchemaRef> {
         self.instance
             .catalog_manager
             .catalog_by_name(catalog)
@@ -683,12 +811,6 @@ impl Proxy {
             .with_context(|| ErrNoCause {
                 code: StatusCode::BAD_REQUEST,
                 msg: format!("Schema not found, schema_name:{schema}"),
-            })?
-            .table_by_name(table_name)
-            .box_err()
-            .with_context(|| ErrWithCause {
-                code: StatusCode::INTERNAL_SERVER_ERROR,
-                msg: format!("Failed to find table, table:{table_name}"),
             })
     }
 
@@ -834,9 +956,29 @@ fn build_column<'a>(
     Ok(())
 }
 
+/// The tables of an atomic write must be written by one node, since the
+/// atomicity is ensured by the wal of the shard holding them.
+fn check_atomic_write_routes(
+    write_request_to_local: &WriteRequest,
+    write_requests_to_forward: &HashMap<Endpoint, WriteRequest>,
+) -> Result<()> {
+    let num_nodes = write_requests_to_forward.len()
+        + usize::from(!write_request_to_local.table_requests.is_empty());
+    ensure!(
+        num_nodes <= 1,
+        ErrNoCause {
+            code: StatusCode::BAD_REQUEST,
+            msg: "Tables of an atomic write must be on the same shard",
+        }
+    );
+
+    Ok(())
+}
+
 fn write_table_request_to_insert_plan(
     table: TableRef,
     write_table_req: WriteTableRequest,
+    durability: Durability,
 ) -> Result<InsertPlan> {
     let schema = table.schema();
 
@@ -863,6 +1005,7 @@ fn write_table_request_to_insert_plan(
         table,
         source: InsertSource::Values { row_group },
         default_value_map: BTreeMap::new(),
+        durability,
     })
 }
 
@@ -1205,6 +1348,28 @@ mod test {
         (schema, tag_names, field_names, write_entry)
     }
 
+    #[test]
+    fn test_check_atomic_write_routes() {
+        let build_request = |num_tables| WriteRequest {
+            table_requests: vec![generate_write_table_request(); num_tables],
+            context: None,
+        };
+        let endpoint1 = Endpoint::new("127.0.0.1".to_string(), 8831);
+        let endpoint2 = Endpoint::new("127.0.0.2".to_string(), 8831);
+
+        // All the tables are written by the local node.
+        assert!(check_atomic_write_routes(&build_request(2), &HashMap::new()).is_ok());
+        // All the tables are forwarded to one node.
+        let to_forward = HashMap::from([(endpoint1.clone(), build_request(2))]);
+        assert!(check_atomic_write_routes(&build_request(0), &to_forward).is_ok());
+
+        // The tables are written by multiple nodes.
+        assert!(check_atomic_write_routes(&build_request(1), &to_forward).is_err());
+        let to_forward =
+            HashMap::from([(endpoint1, build_request(1)), (endpoint2, build_request(1))]);
+        assert!(check_atomic_write_routes(&build_request(0), &to_forward).is_err());
+    }
+
     fn generate_write_table_request() -> WriteTableRequest {
         let tag1 = make_tag(0, NAME_NEW_COL1);
         let tag2 = make_tag(1, NAME_COL1);
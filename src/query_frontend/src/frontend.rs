@@ -27,7 +27,7 @@ use influxql_parser::statement::Statement as InfluxqlStatement;
 use macros::define_result;
 use prom_remote_api::types::Query as PromRemoteQuery;
 use snafu::{Backtrace, OptionExt, ResultExt, Snafu};
-use sqlparser::ast::{SetExpr, Statement as SqlStatement, TableFactor};
+use sqlparser::ast::{Cte, Query, SetExpr, Statement as SqlStatement, TableFactor};
 use table_engine::table;
 
 use crate::{
@@ -237,35 +237,49 @@ pub fn parse_table_name_with_standard(sql_statement: &SqlStatement) -> Option<St
         }
         SqlStatement::Explain { statement, .. } => {
             if let SqlStatement::Query(q) = *statement {
-                match *q.body {
-                    SetExpr::Select(select) => {
-                        if select.from.len() != 1 {
-                            None
-                        } else if let TableFactor::Table { name, .. } = &select.from[0].relation {
-                            Some(TableName::from(name.clone()).to_string())
-                        } else {
-                            None
-                        }
-                    }
-                    // TODO: return unsupported error rather than none.
-                    _ => None,
-                }
+                table_name_of_query(&q, &[])
             } else {
                 None
             }
         }
-        SqlStatement::Query(q) => match *q.body {
-            SetExpr::Select(select) => {
-                if select.from.len() != 1 {
-                    None
-                } else if let TableFactor::Table { name, .. } = &select.from[0].relation {
-                    Some(TableName::from(name.clone()).to_string())
-                } else {
-                    None
-                }
+        SqlStatement::Query(q) => table_name_of_query(&q, &[]),
+        _ => None,
+    }
+}
+
+/// Find the table the `query` reads from.
+///
+/// The table referenced by a CTE or a subquery in the `FROM` clause is
+/// resolved to the underlying table, and `None` is returned if the query
+/// doesn't read exactly one table.
+fn table_name_of_query<'a>(query: &'a Query, outer_ctes: &[&'a Cte]) -> Option<String> {
+    let mut ctes = outer_ctes.to_vec();
+    if let Some(with) = &query.with {
+        ctes.extend(with.cte_tables.iter());
+    }
+
+    let SetExpr::Select(select) = query.body.as_ref() else {
+        // TODO: return unsupported error rather than none.
+        return None;
+    };
+    if select.from.len() != 1 {
+        return None;
+    }
+
+    match &select.from[0].relation {
+        TableFactor::Table { name, .. } => {
+            let table_name = TableName::from(name.clone()).to_string();
+            // The later defined cte shadows the earlier ones, and a cte can only refer
+            // to the ctes defined before it.
+            match ctes
+                .iter()
+                .rposition(|cte| cte.alias.name.value == table_name)
+            {
+                Some(idx) => table_name_of_query(&ctes[idx].query, &ctes[..idx]),
+                None => Some(table_name),
             }
-            _ => None,
-        },
+        }
+        TableFactor::Derived { subquery, .. } => table_name_of_query(subquery, &ctes),
         _ => None,
     }
 }
@@ -309,6 +323,10 @@ mod tests {
                             format!("select * from `{table}`"),
                             format!("explain select * from {table}"),
                             format!("explain select * from `{table}`"),
+                            format!("select * from (select * from {table} where t > 10) as sub"),
+                            format!("with cte as (select * from `{table}`) select * from cte"),
+                            format!("with a as (select * from {table}), b as (select * from a) select * from b"),
+                            format!("explain with cte as (select * from {table}) select * from (select * from cte) t"),
                             format!("CREATE TABLE {table} (`name`string TAG,`value` double NOT NULL, `t` timestamp NOT NULL, TIMESTAMP KEY(t))"),
                             format!("CREATE TABLE `{table}` (`name`string TAG,`value` double NOT NULL, `t` timestamp NOT NULL, TIMESTAMP KEY(t))"),
                             format!("drop table {table}"),
@@ -330,6 +348,12 @@ mod tests {
                 Some(table.to_string())
             );
         }
+        // A cte with the same name as the table it reads resolves to the table.
+        assert_eq!(
+            frontend::parse_table_name_with_sql("with t as (select * from t) select * from t")
+                .unwrap(),
+            Some("t".to_string())
+        );
         assert!(frontend::parse_table_name_with_sql("-- just comment")
             .unwrap()
             .is_none());
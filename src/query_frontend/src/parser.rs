@@ -19,13 +19,15 @@
 //!
 //! Some codes are copied from datafusion: <https://github.com/apache/arrow/blob/9d86440946b8b07e03abb94fad2da278affae08f/rust/datafusion/src/sql/parser.rs#L74>
 
+use std::ops::ControlFlow;
+
 use logger::debug;
 use macros::define_result;
 use paste::paste;
 use sqlparser::{
     ast::{
-        ColumnDef, ColumnOption, ColumnOptionDef, DataType, Expr, Ident, ObjectName, SetExpr,
-        Statement as SqlStatement, TableConstraint, TableFactor, TableWithJoins,
+        ColumnDef, ColumnOption, ColumnOptionDef, DataType, Expr, Ident, ObjectName, Query,
+        Statement as SqlStatement, TableConstraint, VisitMut, VisitorMut,
     },
     dialect::{keywords::Keyword, Dialect, MySqlDialect},
     parser::{IsOptional::Mandatory, Parser as SqlParser, ParserError},
@@ -863,36 +865,45 @@ fn build_timestamp_key_constraint(col_defs: &[ColumnDef], constraints: &mut Vec<
 /// It is used to process table name in `SELECT`, for preventing `datafusion`
 /// converting the table name to lowercase, because `HoraeDB` only support
 /// case-sensitive in sql.
+///
+/// The table names in the CTEs, subqueries and joins are normalized, and so
+/// are the names of the CTEs, which may be referenced as tables.
 // TODO: maybe other items(such as: alias, column name) need to be normalized,
 // too.
 pub fn maybe_normalize_table_name(statement: &mut SqlStatement) {
-    if let SqlStatement::Query(query) = statement {
-        if let SetExpr::Select(select) = query.body.as_mut() {
-            select.from.iter_mut().for_each(maybe_convert_one_from);
-        }
+    if let SqlStatement::Query(_) = statement {
+        let _ = statement.visit(&mut TableNameNormalizer);
     }
 }
 
-fn maybe_convert_one_from(one_from: &mut TableWithJoins) {
-    let TableWithJoins { relation, joins } = one_from;
-    maybe_convert_relation(relation);
-    joins.iter_mut().for_each(|join| {
-        maybe_convert_relation(&mut join.relation);
-    });
-}
+struct TableNameNormalizer;
+
+impl VisitorMut for TableNameNormalizer {
+    type Break = ();
+
+    fn pre_visit_query(&mut self, query: &mut Query) -> ControlFlow<Self::Break> {
+        if let Some(with) = &mut query.with {
+            for cte in &mut with.cte_tables {
+                maybe_quote_ident(&mut cte.alias.name);
+            }
+        }
+        ControlFlow::Continue(())
+    }
 
-fn maybe_convert_relation(relation: &mut TableFactor) {
-    if let TableFactor::Table { name, .. } = relation {
-        maybe_convert_table_name(name);
+    fn pre_visit_relation(&mut self, relation: &mut ObjectName) -> ControlFlow<Self::Break> {
+        maybe_convert_table_name(relation);
+        ControlFlow::Continue(())
     }
 }
 
 fn maybe_convert_table_name(object_name: &mut ObjectName) {
-    object_name.0.iter_mut().for_each(|id| {
-        if id.quote_style.is_none() {
-            let _ = std::mem::replace(id, Ident::with_quote('`', id.value.clone()));
-        }
-    })
+    object_name.0.iter_mut().for_each(maybe_quote_ident)
+}
+
+fn maybe_quote_ident(id: &mut Ident) {
+    if id.quote_style.is_none() {
+        let _ = std::mem::replace(id, Ident::with_quote('`', id.value.clone()));
+    }
 }
 
 #[cfg(test)]
@@ -1434,6 +1445,25 @@ mod tests {
                 }
             )
         }
+
+        {
+            let sql = "with Cte as (select * from TEstA where t > 1) \
+                       select * from Cte join (select * from TEstB) as sub on Cte.t = sub.t";
+            let statements = Parser::parse_sql(sql).unwrap();
+            assert!(
+                if let Statement::Standard(standard_statement) = &statements[0] {
+                    let standard_statement_str = format!("{standard_statement}");
+                    assert!(standard_statement_str.contains("WITH `Cte` AS"));
+                    assert!(standard_statement_str.contains("FROM `TEstA`"));
+                    assert!(standard_statement_str.contains("FROM `Cte`"));
+                    assert!(standard_statement_str.contains("FROM `TEstB`"));
+
+                    true
+                } else {
+                    false
+                }
+            )
+        }
     }
 
     #[test]
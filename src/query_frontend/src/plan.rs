@@ -37,7 +37,10 @@ use logger::{debug, warn};
 use macros::define_result;
 use runtime::Priority;
 use snafu::{OptionExt, Snafu};
-use table_engine::{partition::PartitionInfo, table::TableRef};
+use table_engine::{
+    partition::PartitionInfo,
+    table::{Durability, TableRef},
+};
 
 use crate::{
     ast::ShowCreateObject,
@@ -330,6 +333,9 @@ pub struct InsertPlan {
     /// Column indexes in schema to its default-value-expr which is used to fill
     /// values
     pub default_value_map: BTreeMap<usize, DfLogicalExpr>,
+    /// The durability level the written rows must reach before the insert is
+    /// acknowledged
+    pub durability: Durability,
 }
 
 #[derive(Debug)]
@@ -58,10 +58,10 @@ use macros::define_result;
 use prom_remote_api::types::Query as PromRemoteQuery;
 use snafu::{ensure, Backtrace, OptionExt, ResultExt, Snafu};
 use sqlparser::ast::{
-    visit_statements_mut, ColumnDef, ColumnOption, Expr, Expr as SqlExpr, Ident, Query, SelectItem,
-    SetExpr, SqlOption, Statement as SqlStatement, TableConstraint, UnaryOperator, Value, Values,
+    visit_expressions_mut, ColumnDef, ColumnOption, Expr, Expr as SqlExpr, Ident, Query, SetExpr,
+    SqlOption, Statement as SqlStatement, TableConstraint, UnaryOperator, Value, Values,
 };
-use table_engine::table::TableRef;
+use table_engine::table::{Durability, TableRef};
 
 use crate::{
     ast::{
@@ -1008,6 +1008,7 @@ impl<'a, P: MetaProvider> PlannerDelegate<'a, P> {
                     table,
                     source,
                     default_value_map,
+                    durability: Durability::default(),
                 }))
             }
             // We already known this stmt is a INSERT stmt
@@ -1085,27 +1086,14 @@ impl<'a, P: MetaProvider> PlannerDelegate<'a, P> {
 // Datafusion only support lower-case function name when
 // `enable_ident_normalization` is `true`, but we want to
 // function case-insensitive, so add this normalization.
+// The functions in ctes, subqueries and filters are normalized too.
 fn normalize_func_name(sql_stmt: &mut SqlStatement) {
-    let normalize_expr = |expr: &mut SqlExpr| {
+    visit_expressions_mut(sql_stmt, |expr| {
         if let SqlExpr::Function(ref mut func) = expr {
             for ident in &mut func.name.0 {
                 ident.value = ident.value.to_lowercase();
             }
         }
-    };
-
-    visit_statements_mut(sql_stmt, |stmt| {
-        if let SqlStatement::Query(q) = stmt {
-            if let SetExpr::Select(select) = q.body.as_mut() {
-                for projection in &mut select.projection {
-                    match projection {
-                        SelectItem::UnnamedExpr(ref mut expr) => normalize_expr(expr),
-                        SelectItem::ExprWithAlias { ref mut expr, .. } => normalize_expr(expr),
-                        SelectItem::QualifiedWildcard(_, _) | SelectItem::Wildcard(_) => {}
-                    }
-                }
-            }
-        }
         ControlFlow::<()>::Continue(())
     });
 }
@@ -1704,6 +1692,28 @@ pub mod tests {
         .unwrap();
     }
 
+    #[test]
+    fn test_cte_and_subquery_statement_to_plan() {
+        // Pick the last value of each series with a cte joined to a subquery.
+        let sql = "WITH latest AS (SELECT key1, MAX(key2) AS ts FROM test_table GROUP BY key1) \
+                   SELECT t.key1, t.key2, t.field1 \
+                   FROM (SELECT * FROM test_table WHERE key2 > 1000) AS t \
+                   JOIN latest ON t.key1 = latest.key1 AND t.key2 = latest.ts;";
+        let plan = sql_to_logical_plan(sql).unwrap();
+        let Plan::Query(query_plan) = plan else {
+            panic!("It should be query plan");
+        };
+        assert_eq!(query_plan.table_name.as_deref(), Some("test_table"));
+
+        let sql = "WITH cte AS (SELECT key1, COUNT(field1) AS cnt FROM test_table GROUP BY key1) \
+                   SELECT * FROM cte WHERE cnt > 1;";
+        let plan = sql_to_logical_plan(sql).unwrap();
+        let Plan::Query(query_plan) = plan else {
+            panic!("It should be query plan");
+        };
+        assert_eq!(query_plan.table_name.as_deref(), Some("test_table"));
+    }
+
     #[test]
     fn test_partitioned_table_query_statement_to_plan() {
         let sql = "select * from test_partitioned_table;";
@@ -1953,6 +1963,7 @@ pub mod tests {
             },
         },
         default_value_map: {},
+        durability: WalBuffered,
     },
 )"#,
         )
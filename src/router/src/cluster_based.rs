@@ -17,29 +17,47 @@
 
 //! A router based on the [`cluster::Cluster`].
 
+use std::{collections::HashMap, sync::Arc, time::Duration};
+
 use async_trait::async_trait;
 use cluster::ClusterRef;
+use common_types::table::ShardId;
 use generic_error::BoxError;
 use horaedbproto::storage::Route;
-use logger::trace;
+use logger::{info, trace, warn};
 use meta_client::types::RouteTablesRequest;
 use moka::future::Cache;
 use snafu::ResultExt;
 
 use crate::{
-    endpoint::Endpoint, OtherWithCause, ParseEndpoint, Result, RouteCacheConfig, RouteRequest,
+    endpoint::Endpoint,
+    replica::{ReplicaLagTracker, ReplicaLagTrackerRef},
+    route_snapshot::{PersistedRoute, RouteSnapshot},
+    OtherWithCause, ParseEndpoint, ReadReplicaConfig, Result, RouteCacheConfig, RouteRequest,
     Router, TableInfo,
 };
 
+/// Max number of tables routed in one meta request when refreshing the cached
+/// routes.
+const REFRESH_ROUTES_BATCH_SIZE: usize = 512;
+
 #[derive(Clone, Debug)]
 struct RouteData {
     table_info: TableInfo,
+    /// Endpoint of the leader shard.
     endpoint: Option<Endpoint>,
+    /// The shard of the table, unknown for the restored routes.
+    shard_id: Option<ShardId>,
+    /// Endpoints of the follower shards.
+    followers: Vec<Endpoint>,
 }
 
 pub struct ClusterBasedRouter {
     cluster: ClusterRef,
     cache: Option<Cache<String, RouteData>>,
+    cache_config: RouteCacheConfig,
+    replica_config: ReadReplicaConfig,
+    replica_lag_tracker: ReplicaLagTrackerRef,
 }
 
 impl ClusterBasedRouter {
@@ -56,7 +74,139 @@ impl ClusterBasedRouter {
             None
         };
 
-        Self { cluster, cache }
+        Self {
+            cluster,
+            cache,
+            cache_config,
+            replica_config: ReadReplicaConfig::default(),
+            replica_lag_tracker: Arc::new(ReplicaLagTracker::default()),
+        }
+    }
+
+    /// Nothing reports the progress of the follower shards to the
+    /// [ReplicaLagTracker] yet, so it is not exposed by the server config and
+    /// all queries keep being routed to the leaders.
+    pub fn with_read_replica(mut self, replica_config: ReadReplicaConfig) -> Self {
+        self.replica_config = replica_config;
+        self
+    }
+
+    /// The tracker which the progress of the follower shards should be
+    /// reported to.
+    pub fn replica_lag_tracker(&self) -> &ReplicaLagTrackerRef {
+        &self.replica_lag_tracker
+    }
+
+    fn persist_path(&self) -> Option<&str> {
+        self.cache
+            .as_ref()
+            .and(self.cache_config.persist_path.as_deref())
+    }
+
+    /// Restore the routes persisted by the last run into the cache, so that
+    /// the requests can be served before the routes are fetched from meta.
+    ///
+    /// Return the number of the restored routes.
+    pub async fn restore_routes(&self) -> Result<usize> {
+        let (Some(cache), Some(path)) = (&self.cache, self.persist_path()) else {
+            return Ok(0);
+        };
+        let Some(snapshot) = RouteSnapshot::load(path).await? else {
+            return Ok(0);
+        };
+
+        let now = time_ext::current_time_millis();
+        if snapshot.is_expired(self.cache_config.persist_ttl.0, now) {
+            info!(
+                "Skip restoring expired routes, path:{path}, created_at_ms:{}",
+                snapshot.created_at_ms
+            );
+            return Ok(0);
+        }
+
+        let num_routes = snapshot.routes.len();
+        for route in snapshot.routes {
+            let route_data = RouteData {
+                table_info: route.table_info(),
+                endpoint: route.endpoint,
+                shard_id: None,
+                followers: Vec::new(),
+            };
+            cache
+                .insert(route_data.table_info.name.clone(), route_data)
+                .await;
+        }
+
+        Ok(num_routes)
+    }
+
+    /// Fetch the routes of all the cached tables from meta again, which is
+    /// used to replace the restored routes with the latest ones.
+    pub async fn refresh_cached_routes(&self) -> Result<()> {
+        let Some(cache) = &self.cache else {
+            return Ok(());
+        };
+
+        let mut tables_by_schema: HashMap<String, Vec<String>> = HashMap::new();
+        for (table, route) in cache.iter() {
+            tables_by_schema
+                .entry(route.table_info.schema_name)
+                .or_default()
+                .push(table.as_ref().clone());
+        }
+
+        for (schema, tables) in tables_by_schema {
+            for tables in tables.chunks(REFRESH_ROUTES_BATCH_SIZE) {
+                let mut routes = Vec::with_capacity(tables.len());
+                self.route_from_meta(tables, schema.clone(), &mut routes)
+                    .await?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Persist the cached routes to the local disk.
+    pub async fn persist_routes(&self) -> Result<()> {
+        let (Some(cache), Some(path)) = (&self.cache, self.persist_path()) else {
+            return Ok(());
+        };
+
+        let routes = cache
+            .iter()
+            .filter_map(|(_, route)| {
+                PersistedRoute::try_new(&route.table_info, route.endpoint.as_ref())
+            })
+            .collect();
+        let snapshot = RouteSnapshot {
+            created_at_ms: time_ext::current_time_millis(),
+            routes,
+        };
+
+        snapshot.persist(path).await
+    }
+
+    /// Refresh the restored routes, and then persist the cached routes
+    /// periodically.
+    ///
+    /// It returns immediately if the routes are not persisted, otherwise it
+    /// never returns.
+    pub async fn run_persist_loop(&self) {
+        if self.persist_path().is_none() {
+            return;
+        }
+
+        if let Err(e) = self.refresh_cached_routes().await {
+            warn!("Failed to refresh cached routes, err:{e}");
+        }
+
+        let interval = self.cache_config.persist_interval.0;
+        loop {
+            tokio::time::sleep(interval).await;
+            if let Err(e) = self.persist_routes().await {
+                warn!("Failed to persist cached routes, err:{e}");
+            }
+        }
     }
 
     /// route table from local cache, return cache routes and tables which are
@@ -100,17 +250,34 @@ impl ClusterBasedRouter {
             })?;
         trace!("Route tables by cluster, req:{route_tables_req:?}, resp:{route_resp:?}");
 
-        // Now we pick up the nodes who own the leader shard for the route response.
+        // Now we pick up the nodes who own the leader shard for the route response, and
+        // the followers are kept for the requests tolerating staleness.
         for (table_name, route_entry) in route_resp.entries {
             let route = if route_entry.node_shards.is_empty() {
                 Some(make_route(route_entry.table_info, None)?)
             } else {
+                let followers = route_entry
+                    .node_shards
+                    .iter()
+                    .filter(|node_shard| !node_shard.shard_info.is_leader())
+                    .map(|node_shard| {
+                        node_shard.endpoint.parse().context(ParseEndpoint {
+                            endpoint: &node_shard.endpoint,
+                        })
+                    })
+                    .collect::<Result<Vec<_>>>()?;
                 route_entry
                     .node_shards
-                    .into_iter()
+                    .iter()
                     .find(|node_shard| node_shard.shard_info.is_leader())
                     .map(|node_shard| {
-                        make_route(route_entry.table_info, Some(&node_shard.endpoint))
+                        make_route(route_entry.table_info, Some(&node_shard.endpoint)).map(
+                            |route| RouteData {
+                                shard_id: Some(node_shard.shard_info.id),
+                                followers,
+                                ..route
+                            },
+                        )
                     })
                     .transpose()?
             };
@@ -152,6 +319,30 @@ impl ClusterBasedRouter {
 
         Ok(routes)
     }
+
+    /// Pick the freshest follower within the staleness bound if the request
+    /// tolerates staleness, and fall back to the leader otherwise.
+    fn pick_endpoint(
+        &self,
+        route: &RouteData,
+        max_staleness: Option<Duration>,
+        now_ms: u64,
+    ) -> Option<Endpoint> {
+        if let (true, Some(max_staleness), Some(shard_id)) =
+            (self.replica_config.enable, max_staleness, route.shard_id)
+        {
+            if let Some(follower) = self.replica_lag_tracker.pick_follower(
+                shard_id,
+                &route.followers,
+                max_staleness,
+                now_ms,
+            ) {
+                return Some(follower.clone());
+            }
+        }
+
+        route.endpoint.clone()
+    }
 }
 
 /// Make a route according to the table_info and the raw endpoint.
@@ -163,6 +354,8 @@ fn make_route(table_info: TableInfo, endpoint: Option<&str>) -> Result<RouteData
     Ok(RouteData {
         table_info,
         endpoint,
+        shard_id: None,
+        followers: Vec::new(),
     })
 }
 
@@ -174,11 +367,14 @@ impl Router for ClusterBasedRouter {
             .route_internal(&req.inner.tables, req_ctx.database, req.route_with_cache)
             .await?;
 
+        let now = time_ext::current_time_millis();
         Ok(route_datas
             .into_iter()
             .map(|v| Route {
+                endpoint: self
+                    .pick_endpoint(&v, req.max_staleness, now)
+                    .map(Into::into),
                 table: v.table_info.name,
-                endpoint: v.endpoint.map(Into::into),
             })
             .collect())
     }
@@ -208,7 +404,9 @@ mod tests {
     use common_types::{cluster::NodeType, table::ShardId};
     use horaedbproto::storage::{RequestContext, RouteRequest as RouteRequestPb};
     use meta_client::types::{
-        NodeShard, RouteEntry, RouteTablesResponse, ShardInfo, ShardRole::Leader, TableInfo,
+        NodeShard, RouteEntry, RouteTablesResponse, ShardInfo,
+        ShardRole::{Follower, Leader},
+        TableInfo,
     };
     use time_ext::ReadableDuration;
 
@@ -250,6 +448,10 @@ mod tests {
             unimplemented!();
         }
 
+        fn take_recovered_shards(&self) -> Vec<ShardRef> {
+            unimplemented!();
+        }
+
         async fn route_tables(
             &self,
             req: &RouteTablesRequest,
@@ -266,15 +468,26 @@ mod tests {
                             schema_id: 0,
                             partition_info: None,
                         },
-                        node_shards: vec![NodeShard {
-                            endpoint: String::from("127.0.0.1:8831"),
-                            shard_info: ShardInfo {
-                                id: 0,
-                                role: Leader,
-                                version: 100,
-                                status: Default::default(),
+                        node_shards: vec![
+                            NodeShard {
+                                endpoint: String::from("127.0.0.1:8831"),
+                                shard_info: ShardInfo {
+                                    id: 0,
+                                    role: Leader,
+                                    version: 100,
+                                    status: Default::default(),
+                                },
+                            },
+                            NodeShard {
+                                endpoint: String::from("127.0.0.1:8832"),
+                                shard_info: ShardInfo {
+                                    id: 0,
+                                    role: Follower,
+                                    version: 100,
+                                    status: Default::default(),
+                                },
                             },
-                        }],
+                        ],
                     },
                 );
             }
@@ -289,7 +502,7 @@ mod tests {
             unimplemented!();
         }
 
-        fn shard_lock_manager(&self) -> ShardLockManagerRef {
+        fn shard_lock_manager(&self) -> Option<ShardLockManagerRef> {
             unimplemented!();
         }
     }
@@ -303,6 +516,7 @@ mod tests {
             ttl: ReadableDuration::from(Duration::from_secs(4)),
             tti: ReadableDuration::from(Duration::from_secs(2)),
             capacity: 2,
+            ..Default::default()
         };
         let router = ClusterBasedRouter::new(Arc::new(mock_cluster), config);
 
@@ -346,4 +560,76 @@ mod tests {
         assert_eq!(miss.len(), 1);
         assert_eq!(miss[0], table2.to_string());
     }
+
+    #[tokio::test]
+    async fn test_persist_and_restore_routes() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("routes.json");
+        let config = RouteCacheConfig {
+            enable: true,
+            persist_path: Some(path.to_str().unwrap().to_string()),
+            ..Default::default()
+        };
+
+        let router = ClusterBasedRouter::new(Arc::new(MockClusterImpl {}), config.clone());
+        let tables = vec!["table1".to_string(), "table2".to_string()];
+        let mut routes = Vec::with_capacity(tables.len());
+        router
+            .route_from_meta(&tables, String::from("public"), &mut routes)
+            .await
+            .unwrap();
+        router.persist_routes().await.unwrap();
+
+        // The restored routes are served from the cache without routing by meta.
+        let router = ClusterBasedRouter::new(Arc::new(MockClusterImpl {}), config);
+        assert_eq!(router.restore_routes().await.unwrap(), 2);
+        let mut routes = Vec::with_capacity(tables.len());
+        let miss = router.route_from_cache(&tables, &mut routes);
+        assert!(miss.is_empty());
+        assert_eq!(routes.len(), 2);
+        for (table, route) in tables.iter().zip(routes) {
+            assert_eq!(&route.table_info.name, table);
+            assert_eq!(route.endpoint, Some("127.0.0.1:8831".parse().unwrap()));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_route_to_follower() {
+        let router = ClusterBasedRouter::new(Arc::new(MockClusterImpl {}), Default::default())
+            .with_read_replica(ReadReplicaConfig { enable: true });
+        let leader: Endpoint = "127.0.0.1:8831".parse().unwrap();
+        let follower: Endpoint = "127.0.0.1:8832".parse().unwrap();
+        let router = &router;
+        let route = |max_staleness| {
+            let request_pb = RouteRequestPb {
+                context: Some(RequestContext {
+                    database: String::from("public"),
+                }),
+                tables: vec!["table1".to_string()],
+            };
+            let request = RouteRequest::new(request_pb, true).with_max_staleness(max_staleness);
+            async move {
+                let mut routes = router.route(request).await.unwrap();
+                Endpoint::from(routes.remove(0).endpoint.unwrap())
+            }
+        };
+
+        // The lag of the follower is unknown.
+        let max_staleness = Some(Duration::from_secs(10));
+        assert_eq!(route(max_staleness).await, leader);
+
+        let now = time_ext::current_time_millis();
+        router
+            .replica_lag_tracker()
+            .report(0, follower.clone(), 100, 100, now);
+        assert_eq!(route(max_staleness).await, follower);
+        assert_eq!(route(None).await, leader);
+
+        // The follower lags behind too much.
+        router.replica_lag_tracker().remove_shard(0);
+        router
+            .replica_lag_tracker()
+            .report(0, follower.clone(), 100, 100, now - 60_000);
+        assert_eq!(route(max_staleness).await, leader);
+    }
 }
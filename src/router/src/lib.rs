@@ -18,6 +18,8 @@
 pub mod cluster_based;
 pub mod endpoint;
 mod hash;
+pub mod replica;
+mod route_snapshot;
 pub mod rule_based;
 use std::{sync::Arc, time::Duration};
 
@@ -85,6 +87,9 @@ pub trait Router {
 pub struct RouteRequest {
     pub route_with_cache: bool,
     pub inner: RouteRequestPb,
+    /// The staleness tolerated by the request, which may be routed to the
+    /// followers if set.
+    pub max_staleness: Option<Duration>,
 }
 
 impl RouteRequest {
@@ -92,11 +97,18 @@ impl RouteRequest {
         Self {
             route_with_cache,
             inner: request,
+            max_staleness: None,
         }
     }
+
+    pub fn with_max_staleness(mut self, max_staleness: Option<Duration>) -> Self {
+        self.max_staleness = max_staleness;
+        self
+    }
 }
 
 #[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(default)]
 pub struct RouteCacheConfig {
     /// Enable route cache, default false.
     enable: bool,
@@ -106,6 +118,13 @@ pub struct RouteCacheConfig {
     tti: ReadableDuration,
     /// how many route records can store in cache.
     capacity: u64,
+    /// The file to persist the cached routes, which will be restored after
+    /// restart. The routes won't be persisted if not set.
+    persist_path: Option<String>,
+    /// The interval to persist the cached routes.
+    persist_interval: ReadableDuration,
+    /// The persisted routes older than it won't be restored.
+    persist_ttl: ReadableDuration,
 }
 
 impl Default for RouteCacheConfig {
@@ -115,6 +134,17 @@ impl Default for RouteCacheConfig {
             ttl: ReadableDuration::from(Duration::from_secs(5)),
             tti: ReadableDuration::from(Duration::from_secs(5)),
             capacity: 10_000,
+            persist_path: None,
+            persist_interval: ReadableDuration::from(Duration::from_secs(60)),
+            persist_ttl: ReadableDuration::from(Duration::from_secs(600)),
         }
     }
 }
+
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct ReadReplicaConfig {
+    /// Route the queries tolerating staleness to the followers whose lag is
+    /// within the bound, default false.
+    pub enable: bool,
+}
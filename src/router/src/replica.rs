@@ -0,0 +1,170 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+//! Tracking the lag of the follower shards, which decides whether queries
+//! tolerating some staleness can be served by the followers.
+
+use std::{
+    collections::HashMap,
+    sync::{Arc, RwLock},
+    time::Duration,
+};
+
+use common_types::{table::ShardId, SequenceNumber};
+
+use crate::endpoint::Endpoint;
+
+#[derive(Clone, Copy, Debug)]
+struct FollowerProgress {
+    applied_sequence: SequenceNumber,
+    /// The last time when the follower was observed to have applied all the
+    /// sequences of the leader, `None` if never.
+    caught_up_at_ms: Option<u64>,
+}
+
+/// Tracker of the applied sequences of the follower shards.
+///
+/// The follower is considered to lag behind its leader by the time elapsed
+/// since it was observed to catch up with the leader at the last time, which
+/// is an upper bound of the staleness of the data read from it.
+#[derive(Debug, Default)]
+pub struct ReplicaLagTracker {
+    followers: RwLock<HashMap<(ShardId, Endpoint), FollowerProgress>>,
+}
+
+pub type ReplicaLagTrackerRef = Arc<ReplicaLagTracker>;
+
+impl ReplicaLagTracker {
+    /// Report the sequence applied by the follower of the shard at `endpoint`
+    /// and the latest sequence of the leader, observed at `now_ms`.
+    pub fn report(
+        &self,
+        shard_id: ShardId,
+        endpoint: Endpoint,
+        leader_sequence: SequenceNumber,
+        applied_sequence: SequenceNumber,
+        now_ms: u64,
+    ) {
+        let mut followers = self.followers.write().unwrap();
+        let progress = followers
+            .entry((shard_id, endpoint))
+            .or_insert(FollowerProgress {
+                applied_sequence,
+                caught_up_at_ms: None,
+            });
+        progress.applied_sequence = progress.applied_sequence.max(applied_sequence);
+        if progress.applied_sequence >= leader_sequence {
+            progress.caught_up_at_ms = Some(now_ms);
+        }
+    }
+
+    /// Forget the followers of the shard, e.g. after its leader changes.
+    pub fn remove_shard(&self, shard_id: ShardId) {
+        self.followers
+            .write()
+            .unwrap()
+            .retain(|(id, _), _| *id != shard_id);
+    }
+
+    /// The staleness of the follower of the shard at `endpoint`, `None` if it
+    /// is unknown.
+    pub fn staleness(
+        &self,
+        shard_id: ShardId,
+        endpoint: &Endpoint,
+        now_ms: u64,
+    ) -> Option<Duration> {
+        let followers = self.followers.read().unwrap();
+        let caught_up_at_ms = followers
+            .get(&(shard_id, endpoint.clone()))?
+            .caught_up_at_ms?;
+        Some(Duration::from_millis(
+            now_ms.saturating_sub(caught_up_at_ms),
+        ))
+    }
+
+    /// Pick the freshest one of the `followers` of the shard whose staleness
+    /// is within `max_staleness`.
+    pub fn pick_follower<'a>(
+        &self,
+        shard_id: ShardId,
+        followers: &'a [Endpoint],
+        max_staleness: Duration,
+        now_ms: u64,
+    ) -> Option<&'a Endpoint> {
+        followers
+            .iter()
+            .filter_map(|endpoint| {
+                let staleness = self.staleness(shard_id, endpoint, now_ms)?;
+                (staleness <= max_staleness).then_some((staleness, endpoint))
+            })
+            .min_by_key(|(staleness, _)| *staleness)
+            .map(|(_, endpoint)| endpoint)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pick_follower() {
+        let tracker = ReplicaLagTracker::default();
+        let follower1: Endpoint = "127.0.0.1:8831".parse().unwrap();
+        let follower2: Endpoint = "127.0.0.1:8832".parse().unwrap();
+        let followers = vec![follower1.clone(), follower2.clone()];
+        let max_staleness = Duration::from_secs(10);
+
+        // The followers never catching up are not picked.
+        tracker.report(0, follower1.clone(), 100, 90, 1_000);
+        assert_eq!(
+            tracker.pick_follower(0, &followers, max_staleness, 1_000),
+            None
+        );
+
+        tracker.report(0, follower1.clone(), 100, 100, 2_000);
+        tracker.report(0, follower2.clone(), 100, 100, 5_000);
+        assert_eq!(
+            tracker.pick_follower(0, &followers, max_staleness, 6_000),
+            Some(&follower2)
+        );
+
+        // The follower falls behind the leader, and its staleness grows until it
+        // catches up again.
+        tracker.report(0, follower2.clone(), 200, 150, 7_000);
+        assert_eq!(
+            tracker.staleness(0, &follower2, 14_000),
+            Some(Duration::from_secs(9))
+        );
+        assert_eq!(
+            tracker.pick_follower(0, &followers, max_staleness, 14_000),
+            Some(&follower2)
+        );
+        assert_eq!(
+            tracker.pick_follower(0, &followers, max_staleness, 16_000),
+            None
+        );
+
+        // The followers of other shards are unknown.
+        assert_eq!(
+            tracker.pick_follower(1, &followers, max_staleness, 6_000),
+            None
+        );
+        tracker.remove_shard(0);
+        assert_eq!(tracker.staleness(0, &follower1, 6_000), None);
+    }
+}
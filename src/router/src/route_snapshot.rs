@@ -0,0 +1,168 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+//! Snapshot of the cached routes persisted on the local disk, which makes the
+//! routes available right after a restart before they are fetched from meta.
+
+use std::time::Duration;
+
+use common_types::{schema::SchemaId, table::TableId};
+use generic_error::BoxError;
+use meta_client::types::TableInfo;
+use serde::{Deserialize, Serialize};
+use snafu::ResultExt;
+use tokio::{fs, io::AsyncWriteExt};
+
+use crate::{endpoint::Endpoint, OtherWithCause, Result};
+
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub(crate) struct PersistedRoute {
+    pub table_id: TableId,
+    pub table_name: String,
+    pub schema_id: SchemaId,
+    pub schema_name: String,
+    pub endpoint: Option<Endpoint>,
+}
+
+impl PersistedRoute {
+    /// Partition tables are not persisted because their partition info is
+    /// required by the route, so `None` is returned for them.
+    pub fn try_new(table_info: &TableInfo, endpoint: Option<&Endpoint>) -> Option<Self> {
+        if table_info.is_partition_table() {
+            return None;
+        }
+
+        Some(Self {
+            table_id: table_info.id,
+            table_name: table_info.name.clone(),
+            schema_id: table_info.schema_id,
+            schema_name: table_info.schema_name.clone(),
+            endpoint: endpoint.cloned(),
+        })
+    }
+
+    pub fn table_info(&self) -> TableInfo {
+        TableInfo {
+            id: self.table_id,
+            name: self.table_name.clone(),
+            schema_id: self.schema_id,
+            schema_name: self.schema_name.clone(),
+            partition_info: None,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, PartialEq, Serialize)]
+pub(crate) struct RouteSnapshot {
+    /// Wall time in milliseconds when the snapshot is taken.
+    pub created_at_ms: u64,
+    pub routes: Vec<PersistedRoute>,
+}
+
+impl RouteSnapshot {
+    /// Whether the snapshot is too old to be trusted.
+    pub fn is_expired(&self, ttl: Duration, now_ms: u64) -> bool {
+        self.created_at_ms + ttl.as_millis() as u64 <= now_ms
+    }
+
+    /// Load the snapshot from `path`, and `None` is returned if it doesn't
+    /// exist.
+    pub async fn load(path: &str) -> Result<Option<Self>> {
+        let bytes = match fs::read(path).await {
+            Ok(v) => v,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => {
+                return Err(e).box_err().context(OtherWithCause {
+                    msg: format!("failed to read route snapshot, path:{path}"),
+                })
+            }
+        };
+
+        serde_json::from_slice(&bytes)
+            .map(Some)
+            .box_err()
+            .context(OtherWithCause {
+                msg: format!("failed to decode route snapshot, path:{path}"),
+            })
+    }
+
+    /// Persist the snapshot to `path`.
+    ///
+    /// The snapshot is written into a temporary file first and then renamed,
+    /// so a crash in the middle won't leave a broken snapshot.
+    pub async fn persist(&self, path: &str) -> Result<()> {
+        let bytes = serde_json::to_vec(self).box_err().context(OtherWithCause {
+            msg: "failed to encode route snapshot",
+        })?;
+
+        let tmp_path = format!("{path}.tmp");
+        let write_tmp = async {
+            let mut file = fs::File::create(&tmp_path).await?;
+            file.write_all(&bytes).await?;
+            file.sync_all().await
+        };
+        write_tmp.await.box_err().with_context(|| OtherWithCause {
+            msg: format!("failed to write route snapshot, path:{tmp_path}"),
+        })?;
+
+        fs::rename(&tmp_path, path)
+            .await
+            .box_err()
+            .with_context(|| OtherWithCause {
+                msg: format!("failed to rename route snapshot, from:{tmp_path}, to:{path}"),
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_persist_and_load() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("routes.json");
+        let path = path.to_str().unwrap();
+        assert!(RouteSnapshot::load(path).await.unwrap().is_none());
+
+        let snapshot = RouteSnapshot {
+            created_at_ms: 1000,
+            routes: vec![
+                PersistedRoute {
+                    table_id: 1,
+                    table_name: "table1".to_string(),
+                    schema_id: 0,
+                    schema_name: "public".to_string(),
+                    endpoint: Some(Endpoint::new("127.0.0.1".to_string(), 8831)),
+                },
+                PersistedRoute {
+                    table_id: 2,
+                    table_name: "table2".to_string(),
+                    schema_id: 0,
+                    schema_name: "public".to_string(),
+                    endpoint: None,
+                },
+            ],
+        };
+        snapshot.persist(path).await.unwrap();
+
+        let loaded = RouteSnapshot::load(path).await.unwrap().unwrap();
+        assert_eq!(snapshot, loaded);
+        assert!(!loaded.is_expired(Duration::from_secs(1), 1999));
+        assert!(loaded.is_expired(Duration::from_secs(1), 2000));
+    }
+}
@@ -167,6 +167,16 @@ pub struct ServerConfig {
 
     /// Whether enable to access partition table
     pub sub_table_access_perm: SubTableAccessPerm,
+
+    /// Max number of compaction tasks executed concurrently by the compaction
+    /// service, excess requests are rejected so that the caller can try
+    /// another node.
+    pub max_concurrent_compactions: usize,
+
+    /// Number of sql queries traced per thousand queries, the trace id of the
+    /// sampled queries are attached to the query duration histogram as
+    /// exemplars. 0 disables the tracing.
+    pub query_trace_sample_permille: u64,
 }
 
 impl Default for ServerConfig {
@@ -190,6 +200,8 @@ impl Default for ServerConfig {
             remote_client: remote_engine_client::Config::default(),
             query_dedup: QueryDedupConfig::default(),
             sub_table_access_perm: SubTableAccessPerm::default(),
+            max_concurrent_compactions: 4,
+            query_trace_sample_permille: 0,
         }
     }
 }
@@ -23,6 +23,10 @@ pub const CATALOG_HEADER: &str = "x-horaedb-catalog";
 pub const SCHEMA_HEADER: &str = "x-horaedb-schema";
 /// Header of tenant name
 pub const TENANT_HEADER: &str = "x-horaedb-access-tenant";
+/// Header of the durability level of writes
+pub const DURABILITY_HEADER: &str = "x-horaedb-durability";
+/// Header of the seconds of staleness tolerated by the queries
+pub const MAX_STALENESS_HEADER: &str = "x-horaedb-max-staleness";
 /// Header of content encoding type
 pub const CONTENT_ENCODING_HEADER: &str = "content-encoding";
 
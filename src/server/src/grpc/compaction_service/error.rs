@@ -71,6 +71,7 @@ pub enum StatusCode {
     #[default]
     Ok = 0,
     BadRequest = 401,
+    TooManyRequests = 429,
     Internal = 500,
 }
 
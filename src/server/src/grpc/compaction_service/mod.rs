@@ -17,26 +17,37 @@
 
 // Compaction rpc service implementation.
 
-use std::sync::Arc;
+use std::{sync::Arc, time::Instant};
 
-use analytic_engine::compaction::runner::{CompactionRunnerRef, CompactionRunnerTask};
+use analytic_engine::compaction::runner::{
+    CompactionRunnerRef, CompactionRunnerTask, ExecResultExt,
+};
 use async_trait::async_trait;
-use error::{build_err_header, build_ok_header, ErrWithCause, StatusCode};
+use error::{build_err_header, build_ok_header, ErrNoCause, ErrWithCause, StatusCode};
 use generic_error::BoxError;
 use horaedbproto::compaction_service::{
     compaction_service_server::CompactionService, ExecResult, ExecuteCompactionTaskRequest,
     ExecuteCompactionTaskResponse,
 };
+use logger::info;
 use runtime::Runtime;
 use snafu::ResultExt;
+use tokio::sync::Semaphore;
 use tonic::{Request, Response, Status};
 
+use crate::grpc::metrics::{
+    COMPACTION_SERVICE_RUNNING_TASKS_GAUGE, COMPACTION_SERVICE_TASK_COUNTER_VEC,
+    COMPACTION_SERVICE_TASK_DURATION_HISTOGRAM,
+};
+
 mod error;
 
 /// Builder for [CompactionServiceImpl]
 pub struct Builder {
     pub runtime: Arc<Runtime>,
     pub compaction_runner: CompactionRunnerRef,
+    /// Max number of compaction tasks executed concurrently.
+    pub max_concurrent_tasks: usize,
 }
 
 impl Builder {
@@ -44,11 +55,13 @@ impl Builder {
         let Self {
             runtime,
             compaction_runner,
+            max_concurrent_tasks,
         } = self;
 
         CompactionServiceImpl {
             runtime,
             compaction_runner,
+            limiter: Arc::new(Semaphore::new(max_concurrent_tasks.max(1))),
         }
     }
 }
@@ -57,6 +70,75 @@ impl Builder {
 pub struct CompactionServiceImpl {
     pub runtime: Arc<Runtime>,
     pub compaction_runner: CompactionRunnerRef,
+    /// Limit the number of compaction tasks running at the same time.
+    limiter: Arc<Semaphore>,
+}
+
+impl CompactionServiceImpl {
+    async fn run_task(
+        &self,
+        task: CompactionRunnerTask,
+    ) -> error::Result<(ExecResult, ExecResultExt)> {
+        // Reject the task rather than queueing it, so that the caller is able to
+        // pick another node instead of waiting here.
+        let _permit = match self.limiter.clone().try_acquire_owned() {
+            Ok(permit) => permit,
+            Err(_) => {
+                COMPACTION_SERVICE_TASK_COUNTER_VEC
+                    .with_label_values(&["rejected"])
+                    .inc();
+                return ErrNoCause {
+                    code: StatusCode::TooManyRequests,
+                    msg: format!(
+                        "too many running compaction tasks, request:{}",
+                        task.request_id
+                    ),
+                }
+                .fail();
+            }
+        };
+
+        let request_id = task.request_id.clone();
+        let table_id = task.table_id;
+        info!(
+            "Compaction service begins to run task, request:{request_id}, table_id:{table_id}, \
+             input_files:{}",
+            task.input_ctx.files.files.len()
+        );
+
+        COMPACTION_SERVICE_RUNNING_TASKS_GAUGE.inc();
+        let begin = Instant::now();
+        let res = self.compaction_runner.run(task).await;
+        let cost = begin.elapsed();
+        COMPACTION_SERVICE_RUNNING_TASKS_GAUGE.dec();
+        COMPACTION_SERVICE_TASK_DURATION_HISTOGRAM.observe(cost.as_secs_f64());
+
+        let res = match res {
+            Ok(res) => {
+                COMPACTION_SERVICE_TASK_COUNTER_VEC
+                    .with_label_values(&["succeeded"])
+                    .inc();
+                res
+            }
+            Err(e) => {
+                COMPACTION_SERVICE_TASK_COUNTER_VEC
+                    .with_label_values(&["failed"])
+                    .inc();
+                return Err(e).box_err().context(ErrWithCause {
+                    code: StatusCode::Internal,
+                    msg: format!("fail to compact task, request:{request_id}"),
+                });
+            }
+        };
+
+        info!(
+            "Compaction service finishes task, request:{request_id}, table_id:{table_id}, \
+             cost:{cost:?}, output_rows:{}, output_size:{}",
+            res.sst_info.row_num, res.sst_info.file_size
+        );
+
+        Ok(res.into())
+    }
 }
 
 #[async_trait]
@@ -75,39 +157,28 @@ impl CompactionService for CompactionServiceImpl {
             });
 
         let mut resp: ExecuteCompactionTaskResponse = ExecuteCompactionTaskResponse::default();
+        let mut ext = None;
         match request {
-            Ok(task) => {
-                let request_id = task.request_id.clone();
-                let res = self
-                    .compaction_runner
-                    .run(task)
-                    .await
-                    .box_err()
-                    .with_context(|| ErrWithCause {
-                        code: StatusCode::Internal,
-                        msg: format!("fail to compact task, request:{request_id}"),
-                    });
-
-                match res {
-                    Ok(res) => {
-                        resp.header = Some(build_ok_header());
-                        resp.result = Some(ExecResult {
-                            output_file_path: res.output_file_path.into(),
-                            sst_info: Some(res.sst_info.into()),
-                            sst_meta: Some(res.sst_meta.into()),
-                        });
-                        // TODO(leslie): Add status.
-                    }
-                    Err(e) => {
-                        resp.header = Some(build_err_header(e));
-                    }
+            Ok(task) => match self.run_task(task).await {
+                Ok((result, result_ext)) => {
+                    resp.header = Some(build_ok_header());
+                    resp.result = Some(result);
+                    ext = Some(result_ext);
+                    // TODO(leslie): Add status.
                 }
-            }
+                Err(e) => {
+                    resp.header = Some(build_err_header(e));
+                }
+            },
             Err(e) => {
                 resp.header = Some(build_err_header(e));
             }
         }
 
-        Ok(Response::new(resp))
+        let mut resp = Response::new(resp);
+        if let Some(ext) = ext {
+            ext.encode_to(resp.metadata_mut());
+        }
+        Ok(resp)
     }
 }
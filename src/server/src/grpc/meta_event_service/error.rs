@@ -17,7 +17,7 @@
 
 //! Error definitions for meta event service.
 
-use generic_error::GenericError;
+use generic_error::{BoxError, GenericError};
 use horaedbproto::common::ResponseHeader;
 use macros::define_result;
 use snafu::Snafu;
@@ -74,6 +74,8 @@ pub enum StatusCode {
     #[allow(dead_code)]
     NotFound = 404,
     Internal = 500,
+    /// The operation failed transiently and can be retried by the meta as is.
+    Unavailable = 503,
 }
 
 impl StatusCode {
@@ -83,6 +85,31 @@ impl StatusCode {
     }
 }
 
+/// Build the error from the failed shard operation, and the retriable one is
+/// marked as [StatusCode::Unavailable] so that the meta can retry it
+/// automatically.
+///
+/// The failed tables are included in the message for the meta to know which
+/// tables to retry.
+pub fn build_shard_operation_err(err: cluster::Error, msg: impl Into<String>) -> Error {
+    let code = if err.is_retriable() {
+        StatusCode::Unavailable
+    } else {
+        StatusCode::Internal
+    };
+    let failed_tables = err.failed_tables();
+    let mut msg = msg.into();
+    if !failed_tables.is_empty() {
+        msg = format!("{msg}, failed_tables:{failed_tables:?}");
+    }
+
+    Error::ErrWithCause {
+        code,
+        msg,
+        source: err.box_err(),
+    }
+}
+
 pub fn build_err_header(err: Error) -> ResponseHeader {
     ResponseHeader {
         code: err.code().as_u32(),
@@ -30,7 +30,7 @@ use cluster::{
         CloseContext, CloseTableContext, CreateTableContext, DropTableContext, OpenContext,
         OpenTableContext,
     },
-    shard_set::UpdatedTableInfo,
+    shard_set::{ShardRef, ShardSet, UpdatedTableInfo},
     ClusterRef,
 };
 use common_types::{
@@ -59,7 +59,9 @@ use tonic::Response;
 use wal::manager::OpenedWals;
 
 use crate::grpc::{
-    meta_event_service::error::{ErrNoCause, ErrWithCause, Result, StatusCode},
+    meta_event_service::error::{
+        build_shard_operation_err, ErrNoCause, ErrWithCause, Result, StatusCode,
+    },
     metrics::META_EVENT_GRPC_HANDLER_DURATION_HISTOGRAM_VEC,
 };
 
@@ -98,6 +100,10 @@ macro_rules! extract_updated_table_info {
     }};
 }
 
+/// The number of the shards recovered from the local shard registry and
+/// reopened concurrently.
+const REOPEN_RECOVERED_SHARD_PARALLELISM: usize = 4;
+
 // TODO: configure retry
 const RETRY: RetryConfig = RetryConfig {
     max_retries: 10,
@@ -273,6 +279,19 @@ impl MetaServiceImpl {
         CloseTableOnShardResponse
     );
 
+    /// Reopen the shards recovered from the local shard registry in the
+    /// background, rather than waiting for HoraeMeta to assign them again.
+    pub fn spawn_reopen_recovered_shards(&self) {
+        let shards = self.cluster.take_recovered_shards();
+        if shards.is_empty() {
+            return;
+        }
+
+        let ctx = self.handler_ctx();
+        self.runtime
+            .spawn(async move { reopen_recovered_shards(ctx, shards).await });
+    }
+
     fn handler_ctx(&self) -> HandlerContext {
         HandlerContext {
             cluster: self.cluster.clone(),
@@ -302,7 +321,10 @@ struct HandlerContext {
 
 impl HandlerContext {
     async fn acquire_shard_lock(&self, shard_id: ShardId) -> Result<()> {
-        let lock_mgr = self.cluster.shard_lock_manager();
+        // Shards aren't protected by locks if the cluster has no lock manager.
+        let Some(lock_mgr) = self.cluster.shard_lock_manager() else {
+            return Ok(());
+        };
         let new_ctx = self.clone();
         let on_lock_expired = |shard_id| async move {
             warn!("Shard lock is released, try to close the tables and shard, shard_id:{shard_id}");
@@ -334,8 +356,20 @@ impl HandlerContext {
         Ok(())
     }
 
+    fn open_ctx(&self) -> OpenContext {
+        OpenContext {
+            catalog: self.default_catalog.clone(),
+            table_engine: self.table_engine.clone(),
+            table_operator: self.table_operator.clone(),
+            // FIXME: the engine type should not use the default one.
+            engine: ANALYTIC_ENGINE_TYPE.to_string(),
+        }
+    }
+
     async fn release_shard_lock(&self, shard_id: ShardId) -> Result<()> {
-        let lock_mgr = self.cluster.shard_lock_manager();
+        let Some(lock_mgr) = self.cluster.shard_lock_manager() else {
+            return Ok(());
+        };
         let revoked_by_this_call =
             lock_mgr
                 .revoke_lock(shard_id)
@@ -377,22 +411,69 @@ async fn do_open_shard(ctx: HandlerContext, shard_info: ShardInfo) -> Result<()>
         ),
     };
 
-    let open_ctx = OpenContext {
-        catalog: ctx.default_catalog.clone(),
-        table_engine: ctx.table_engine.clone(),
-        table_operator: ctx.table_operator.clone(),
-        // FIXME: the engine type should not use the default one.
-        engine: ANALYTIC_ENGINE_TYPE.to_string(),
-    };
+    let open_ctx = ctx.open_ctx();
 
     // This `open` may only open part of tables in this shard, and this is
     // allowed via shard status(PartialOpen) mechanism.
-    shard.open(open_ctx).await.box_err().context(ErrWithCause {
-        code: StatusCode::Internal,
-        msg: format!("fail to open shard, id:{}", shard_info.id),
+    shard.open(open_ctx).await.map_err(|e| {
+        build_shard_operation_err(e, format!("fail to open shard, id:{}", shard_info.id))
     })
 }
 
+/// Lock and open the shards recovered from the local shard registry.
+///
+/// The shards which can't be locked may have been moved to other nodes during
+/// the restart, and they are removed from the cluster. The opened ones are
+/// reconciled with HoraeMeta by the heartbeat later.
+async fn reopen_recovered_shards(ctx: HandlerContext, shards: Vec<ShardRef>) {
+    info!(
+        "Reopen shards recovered from registry, num_shards:{}",
+        shards.len()
+    );
+
+    let lock_shards = shards.into_iter().map(|shard| {
+        let ctx = &ctx;
+        async move {
+            let shard_id = shard.shard_info().id;
+            match ctx.acquire_shard_lock(shard_id).await {
+                Ok(()) => Some(shard),
+                Err(e) => {
+                    warn!("Failed to lock recovered shard, shard_id:{shard_id}, err:{e}");
+                    // The shard may have been replaced by the one opened by HoraeMeta.
+                    let unchanged = ctx
+                        .cluster
+                        .shard(shard_id)
+                        .map(|v| Arc::ptr_eq(&v, &shard))
+                        .unwrap_or(false);
+                    if unchanged {
+                        let _ = ctx.cluster.close_shard(shard_id).await;
+                    }
+                    None
+                }
+            }
+        }
+    });
+    let locked_shards = futures::future::join_all(lock_shards).await;
+
+    let shards_to_open = locked_shards
+        .into_iter()
+        .flatten()
+        .map(|shard| (shard, ctx.open_ctx()))
+        .collect();
+    let results = ShardSet::open_all(
+        shards_to_open,
+        REOPEN_RECOVERED_SHARD_PARALLELISM,
+        |event| info!("Reopen recovered shard, event:{event:?}"),
+    )
+    .await;
+
+    let num_failed = results.iter().filter(|v| v.is_err()).count();
+    info!(
+        "Finish reopening shards recovered from registry, num_opened:{}, num_failed:{num_failed}",
+        results.len() - num_failed
+    );
+}
+
 // TODO: maybe we should encapsulate the logic of handling meta event into a
 // trait, so that we don't need to expose the logic to the meta event service
 // implementation.
@@ -435,11 +516,7 @@ async fn do_close_shard(ctx: &HandlerContext, shard_id: ShardId) -> Result<()> {
     shard
         .close(close_ctx)
         .await
-        .box_err()
-        .context(ErrWithCause {
-            code: StatusCode::Internal,
-            msg: "fail to close shard",
-        })?;
+        .map_err(|e| build_shard_operation_err(e, "fail to close shard"))?;
 
     // Remove the shard from the cluster topology after the shard is closed indeed.
     let _ = ctx
@@ -523,11 +600,7 @@ async fn handle_create_table_on_shard(
     shard
         .create_table(create_table_ctx)
         .await
-        .box_err()
-        .context(ErrWithCause {
-            code: StatusCode::Internal,
-            msg: "fail to create table on shard",
-        })
+        .map_err(|e| build_shard_operation_err(e, "fail to create table on shard"))
 }
 
 async fn handle_drop_table_on_shard(
@@ -559,11 +632,7 @@ async fn handle_drop_table_on_shard(
     shard
         .drop_table(drop_table_ctx)
         .await
-        .box_err()
-        .context(ErrWithCause {
-            code: StatusCode::Internal,
-            msg: "fail to drop table on shard",
-        })
+        .map_err(|e| build_shard_operation_err(e, "fail to drop table on shard"))
 }
 
 async fn handle_open_table_on_shard(
@@ -592,11 +661,7 @@ async fn handle_open_table_on_shard(
     shard
         .open_table(open_table_ctx)
         .await
-        .box_err()
-        .context(ErrWithCause {
-            code: StatusCode::Internal,
-            msg: "fail to open table on shard",
-        })
+        .map_err(|e| build_shard_operation_err(e, "fail to open table on shard"))
 }
 
 async fn handle_close_table_on_shard(
@@ -626,11 +691,7 @@ async fn handle_close_table_on_shard(
     shard
         .close_table(close_table_ctx)
         .await
-        .box_err()
-        .context(ErrWithCause {
-            code: StatusCode::Internal,
-            msg: "fail to close table on shard",
-        })
+        .map_err(|e| build_shard_operation_err(e, "fail to close table on shard"))
 }
 
 #[async_trait]
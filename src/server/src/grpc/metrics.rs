@@ -20,7 +20,7 @@
 use lazy_static::lazy_static;
 use prometheus::{
     exponential_buckets, register_histogram, register_histogram_vec, register_int_counter_vec,
-    Histogram, HistogramVec, IntCounterVec,
+    register_int_gauge, Histogram, HistogramVec, IntCounterVec, IntGauge,
 };
 use prometheus_static_metric::{auto_flush_from, make_auto_flush_static_metric};
 
@@ -122,6 +122,23 @@ lazy_static! {
             exponential_buckets(0.0005, 2.0, 20).unwrap()
         )
         .unwrap();
+    pub static ref COMPACTION_SERVICE_RUNNING_TASKS_GAUGE: IntGauge = register_int_gauge!(
+        "compaction_service_running_tasks",
+        "Compaction tasks running in the compaction service"
+    )
+    .unwrap();
+    pub static ref COMPACTION_SERVICE_TASK_DURATION_HISTOGRAM: Histogram = register_histogram!(
+        "compaction_service_task_duration",
+        "Bucketed histogram of compaction tasks executed by the compaction service",
+        exponential_buckets(0.01, 2.0, 20).unwrap()
+    )
+    .unwrap();
+    pub static ref COMPACTION_SERVICE_TASK_COUNTER_VEC: IntCounterVec = register_int_counter_vec!(
+        "compaction_service_task_counter",
+        "Compaction service task counter",
+        &["result"]
+    )
+    .unwrap();
 }
 
 // Register thread local metrics with default flush interval (1s).
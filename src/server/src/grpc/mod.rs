@@ -241,6 +241,7 @@ pub struct Builder {
     query_dedup_config: Option<QueryDedupConfig>,
     hotspot_recorder: Option<Arc<HotspotRecorder>>,
     compaction_runner: Option<CompactionRunnerRef>,
+    max_concurrent_compactions: usize,
 }
 
 impl Builder {
@@ -257,6 +258,7 @@ impl Builder {
             query_dedup_config: None,
             hotspot_recorder: None,
             compaction_runner: None,
+            max_concurrent_compactions: 4,
         }
     }
 
@@ -316,6 +318,11 @@ impl Builder {
         self.compaction_runner = runner;
         self
     }
+
+    pub fn max_concurrent_compactions(mut self, max_concurrent_compactions: usize) -> Self {
+        self.max_concurrent_compactions = max_concurrent_compactions;
+        self
+    }
 }
 
 impl Builder {
@@ -340,7 +347,9 @@ impl Builder {
                         runtime: runtimes.meta_runtime.clone(),
                         opened_wals,
                     };
-                    meta_rpc_server = Some(MetaEventServiceServer::new(builder.build()));
+                    let meta_service = builder.build();
+                    meta_service.spawn_reopen_recovered_shards();
+                    meta_rpc_server = Some(MetaEventServiceServer::new(meta_service));
 
                     // Support remote compaction rpc service.
                     let compaction_runner =
@@ -348,6 +357,7 @@ impl Builder {
                     let builder = compaction_service::Builder {
                         runtime: runtimes.compact_runtime.clone(),
                         compaction_runner,
+                        max_concurrent_tasks: self.max_concurrent_compactions,
                     };
                     compaction_rpc_server = Some(CompactionServiceServer::new(builder.build()));
 
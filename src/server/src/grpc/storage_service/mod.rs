@@ -35,8 +35,11 @@ use horaedbproto::{
     },
 };
 use http::StatusCode;
-use proxy::{auth::with_file::get_authorization, Context, Proxy, FORWARDED_FROM};
-use table_engine::engine::EngineRuntimes;
+use proxy::{
+    auth::with_file::get_authorization, parse_max_staleness, Context, Proxy, ATOMIC_WRITE,
+    DURABILITY, FORWARDED_FROM, MAX_STALENESS,
+};
+use table_engine::{engine::EngineRuntimes, table::Durability};
 use time_ext::InstantExt;
 
 use crate::grpc::metrics::GRPC_HANDLER_DURATION_HISTOGRAM_VEC;
@@ -152,7 +155,8 @@ impl StorageService for StorageServiceImpl {
             self.timeout,
             get_forwarded_from(&req),
             get_authorization(&req),
-        );
+        )
+        .with_max_staleness(get_max_staleness(&req)?);
 
         let stream = self.stream_sql_query_internal(ctx, proxy, req).await;
 
@@ -170,6 +174,58 @@ fn get_forwarded_from<T>(req: &tonic::Request<T>) -> Option<String> {
         .map(|value| value.to_str().unwrap().to_string())
 }
 
+/// Build the write response with the durability level reached in the response
+/// metadata, since the response message has no place for it.
+///
+/// Writes are rejected if the requested level can't be reached, so the reached
+/// level is the requested one.
+fn build_write_response(
+    resp: WriteResponse,
+    durability: Durability,
+) -> tonic::Response<WriteResponse> {
+    let mut resp = tonic::Response::new(resp);
+    resp.metadata_mut()
+        .insert(DURABILITY, durability.as_str().parse().unwrap());
+    resp
+}
+
+fn get_durability<T>(req: &tonic::Request<T>) -> Result<Durability, tonic::Status> {
+    let Some(value) = req.metadata().get(DURABILITY) else {
+        return Ok(Durability::default());
+    };
+
+    value
+        .to_str()
+        .ok()
+        .and_then(Durability::parse)
+        .ok_or_else(|| tonic::Status::invalid_argument(format!("invalid durability:{value:?}")))
+}
+
+fn get_atomic_write<T>(req: &tonic::Request<T>) -> Result<bool, tonic::Status> {
+    let Some(value) = req.metadata().get(ATOMIC_WRITE) else {
+        return Ok(false);
+    };
+
+    value
+        .to_str()
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .ok_or_else(|| tonic::Status::invalid_argument(format!("invalid atomic write:{value:?}")))
+}
+
+fn get_max_staleness<T>(req: &tonic::Request<T>) -> Result<Option<Duration>, tonic::Status> {
+    let Some(value) = req.metadata().get(MAX_STALENESS) else {
+        return Ok(None);
+    };
+
+    value
+        .to_str()
+        .ok()
+        .and_then(parse_max_staleness)
+        .map(Some)
+        .ok_or_else(|| tonic::Status::invalid_argument(format!("invalid max staleness:{value:?}")))
+}
+
 // TODO: Use macros to simplify duplicate code
 impl StorageServiceImpl {
     async fn route_internal(
@@ -207,11 +263,14 @@ impl StorageServiceImpl {
         &self,
         req: tonic::Request<WriteRequest>,
     ) -> Result<tonic::Response<WriteResponse>, tonic::Status> {
+        let durability = get_durability(&req)?;
         let ctx = Context::new(
             self.timeout,
             get_forwarded_from(&req),
             get_authorization(&req),
-        );
+        )
+        .with_durability(durability)
+        .with_atomic_write(get_atomic_write(&req)?);
 
         let req = req.into_inner();
         let proxy = self.proxy.clone();
@@ -241,7 +300,7 @@ impl StorageServiceImpl {
             },
         };
 
-        Ok(tonic::Response::new(resp))
+        Ok(build_write_response(resp, durability))
     }
 
     async fn sql_query_internal(
@@ -252,7 +311,8 @@ impl StorageServiceImpl {
             self.timeout,
             get_forwarded_from(&req),
             get_authorization(&req),
-        );
+        )
+        .with_max_staleness(get_max_staleness(&req)?);
         let proxy = self.proxy.clone();
 
         let join_handle = self
@@ -357,11 +417,14 @@ impl StorageServiceImpl {
         &self,
         req: tonic::Request<tonic::Streaming<WriteRequest>>,
     ) -> Result<tonic::Response<WriteResponse>, tonic::Status> {
+        let durability = get_durability(&req)?;
         let ctx = Context::new(
             self.timeout,
             get_forwarded_from(&req),
             get_authorization(&req),
-        );
+        )
+        .with_durability(durability)
+        .with_atomic_write(get_atomic_write(&req)?);
         let mut stream = req.into_inner();
         let proxy = self.proxy.clone();
 
@@ -418,7 +481,7 @@ impl StorageServiceImpl {
             },
         };
 
-        Ok(tonic::Response::new(resp))
+        Ok(build_write_response(resp, durability))
     }
 
     async fn stream_sql_query_internal(
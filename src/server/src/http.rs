@@ -256,6 +256,7 @@ impl Service {
             .or(self.wal_stats())
             .or(self.query_push_down())
             .or(self.slow_threshold())
+            .or(self.query_trace_sample())
             .with(warp::log::custom(|info| {
                 let path = info.path();
                 // Don't record /debug API
@@ -765,6 +766,26 @@ impl Service {
             })
     }
 
+    // PUT /debug/query_trace_sample/{permille}
+    fn query_trace_sample(
+        &self,
+    ) -> impl Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone {
+        warp::path!("debug" / "query_trace_sample" / ..)
+            .and(warp::path::param::<u64>())
+            .and(warp::put())
+            .and(self.with_proxy())
+            .and_then(|sample_permille: u64, proxy: Arc<Proxy>| async move {
+                proxy
+                    .instance()
+                    .dyn_config
+                    .query_trace_sample_permille
+                    .store(sample_permille, Ordering::Relaxed);
+                std::result::Result::<_, Rejection>::Ok(
+                    format!("current_query_trace_sample:{sample_permille}/1000").into_response(),
+                )
+            })
+    }
+
     fn with_context(
         &self,
     ) -> impl Filter<Extract = (RequestContext,), Error = warp::Rejection> + Clone {
@@ -787,11 +808,15 @@ impl Service {
             .and(header::optional::<String>(consts::SCHEMA_HEADER))
             .and(header::optional::<String>(consts::TENANT_HEADER))
             .and(header::optional::<String>(AUTHORIZATION))
+            .and(header::optional::<String>(consts::DURABILITY_HEADER))
+            .and(header::optional::<String>(consts::MAX_STALENESS_HEADER))
             .and_then(
                 move |catalog: Option<_>,
                       schema: Option<_>,
                       _tenant: Option<_>,
-                      authorization: Option<_>| {
+                      authorization: Option<_>,
+                      durability: Option<_>,
+                      max_staleness: Option<_>| {
                     // Clone the captured variables
                     let default_catalog = default_catalog.clone();
                     let schema = schema.unwrap_or_else(|| default_schema.clone());
@@ -807,6 +832,8 @@ impl Service {
                             .schema(schema)
                             .timeout(timeout)
                             .authorization(authorization)
+                            .durability(durability)
+                            .max_staleness(max_staleness)
                             .build()
                             .context(CreateContext)
                             .map_err(reject::custom)
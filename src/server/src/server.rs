@@ -17,7 +17,7 @@
 
 //! Server
 
-use std::sync::Arc;
+use std::sync::{atomic::AtomicU64, Arc};
 
 use analytic_engine::compaction::runner::CompactionRunnerRef;
 use catalog::manager::ManagerRef;
@@ -422,8 +422,13 @@ impl Builder {
             .build(QueryEngineType::Datafusion)
             .context(BuildQueryEngine)?;
 
-        // TODO: build dynamic config from server config.
-        let proxy_dyn_config = DynamicConfig::default();
+        // TODO: build all the dynamic config from server config.
+        let proxy_dyn_config = DynamicConfig {
+            query_trace_sample_permille: Arc::new(AtomicU64::new(
+                self.server_config.query_trace_sample_permille,
+            )),
+            ..Default::default()
+        };
         let instance = {
             let instance = Instance {
                 catalog_manager,
@@ -536,6 +541,7 @@ impl Builder {
             .hotspot_recorder(hotspot_recorder)
             .query_dedup(self.server_config.query_dedup)
             .compaction_runner(self.compaction_runner.clone())
+            .max_concurrent_compactions(self.server_config.max_concurrent_compactions)
             .build()
             .context(BuildGrpcService)?;
 
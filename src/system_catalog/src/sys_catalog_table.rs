@@ -48,7 +48,8 @@ use table_engine::{
     },
     predicate::PredicateBuilder,
     table::{
-        GetRequest, ReadOptions, ReadRequest, SchemaId, TableId, TableInfo, TableRef, WriteRequest,
+        Durability, GetRequest, ReadOptions, ReadRequest, SchemaId, TableId, TableInfo, TableRef,
+        WriteRequest,
     },
 };
 use tokio::sync::Mutex;
@@ -359,7 +360,10 @@ impl SysCatalogTable {
 
         let row_group = request.into_row_group(self.table.schema())?;
 
-        let write_req = WriteRequest { row_group };
+        let write_req = WriteRequest {
+            row_group,
+            durability: Durability::default(),
+        };
         self.table.write(write_req).await.context(PersistCatalog)?;
 
         Ok(())
@@ -371,7 +375,10 @@ impl SysCatalogTable {
 
         let row_group = request.into_row_group(self.table.schema())?;
 
-        let write_req = WriteRequest { row_group };
+        let write_req = WriteRequest {
+            row_group,
+            durability: Durability::default(),
+        };
         self.table.write(write_req).await.context(PersistSchema)?;
 
         Ok(())
@@ -1006,7 +1013,10 @@ pub struct TableWriter {
 impl TableWriter {
     async fn write(&self) -> Result<()> {
         let row_group = self.convert_table_info_to_row_group()?;
-        let write_req = WriteRequest { row_group };
+        let write_req = WriteRequest {
+            row_group,
+            durability: Durability::default(),
+        };
         self.catalog_table
             .write(write_req)
             .await
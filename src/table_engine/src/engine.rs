@@ -34,7 +34,7 @@ use snafu::{ensure, Backtrace, Snafu};
 
 use crate::{
     partition::PartitionInfo,
-    table::{SchemaId, TableId, TableInfo, TableRef},
+    table::{SchemaId, TableId, TableInfo, TableRef, WriteRequest},
 };
 
 #[derive(Debug, Snafu)]
@@ -97,6 +97,22 @@ pub enum Error {
         msg: Option<String>,
         source: GenericError,
     },
+
+    #[snafu(display(
+        "Atomic write of multiple tables is not supported, engine:{}.\nBacktrace:\n{}",
+        engine_type,
+        backtrace
+    ))]
+    WriteTablesNotSupported {
+        engine_type: String,
+        backtrace: Backtrace,
+    },
+
+    #[snafu(display("Table is not found, table:{}.\nBacktrace:\n{}", table, backtrace))]
+    TableNotFound { table: String, backtrace: Backtrace },
+
+    #[snafu(display("Failed to write tables atomically, err:{}", source))]
+    WriteTables { source: GenericError },
 }
 
 define_result!(Error);
@@ -321,6 +337,26 @@ pub struct OpenShardRequest {
     pub engine: String,
 }
 
+/// The write of a table in the [WriteTablesRequest].
+#[derive(Debug)]
+pub struct TableWriteRequest {
+    /// Schema id
+    pub schema_id: SchemaId,
+    /// Table name
+    pub table_name: String,
+    /// The rows to write
+    pub request: WriteRequest,
+}
+
+/// Request to write multiple tables on the same shard atomically.
+#[derive(Debug)]
+pub struct WriteTablesRequest {
+    /// The writes of the tables, a table can appear at most once.
+    pub writes: Vec<TableWriteRequest>,
+    /// Table engine type
+    pub engine: String,
+}
+
 #[derive(Clone, Debug)]
 pub struct TableDef {
     pub catalog_name: String,
@@ -377,6 +413,17 @@ pub trait TableEngine: Send + Sync {
     /// Close tables on same shard.
     async fn close_shard(&self, request: CloseShardRequest) -> Vec<Result<String>>;
 
+    /// Write to multiple tables on the same shard atomically, that is to say,
+    /// either all or none of the writes are persisted.
+    ///
+    /// Returns the total number of written rows.
+    async fn write_tables(&self, _request: WriteTablesRequest) -> Result<usize> {
+        WriteTablesNotSupported {
+            engine_type: self.engine_type(),
+        }
+        .fail()
+    }
+
     /// Report the statistics of the table engine.
     async fn report_statistics(&self) -> Result<Option<TableEngineStats>> {
         Ok(None)
@@ -107,6 +107,25 @@ impl Predicate {
     pub fn time_range(&self) -> TimeRange {
         self.time_range
     }
+
+    /// Returns the time range the pushdown exprs are exactly equivalent to.
+    ///
+    /// Returns `None` if any of the exprs is not a plain comparison between the
+    /// timestamp column and a timestamp literal, that is to say, the rows in
+    /// the returned time range may be still filtered out by the exprs.
+    pub fn exact_time_range(&self, schema: &Schema) -> Option<TimeRange> {
+        let time_range_extractor = TimeRangeExtractor {
+            timestamp_column_name: schema.timestamp_name(),
+            filters: &self.exprs,
+        };
+
+        let is_exact = self
+            .exprs
+            .iter()
+            .all(|expr| time_range_extractor.is_exact_time_range_expr(expr));
+
+        is_exact.then(|| time_range_extractor.extract())
+    }
 }
 
 impl TryFrom<&Predicate> for horaedbproto::remote_engine::Predicate {
@@ -374,6 +393,45 @@ impl<'a> TimeRangeExtractor<'a> {
         TimeRange::new(inclusive_start, inclusive_end).unwrap_or_else(TimeRange::empty)
     }
 
+    /// Whether the time range extracted from the `expr` is exactly equivalent
+    /// to the `expr`.
+    fn is_exact_time_range_expr(&self, expr: &Expr) -> bool {
+        match expr {
+            Expr::BinaryExpr(datafusion::logical_expr::BinaryExpr { left, op, right }) => {
+                match op {
+                    Operator::And => {
+                        self.is_exact_time_range_expr(left) && self.is_exact_time_range_expr(right)
+                    }
+                    Operator::Eq
+                    | Operator::Lt
+                    | Operator::LtEq
+                    | Operator::Gt
+                    | Operator::GtEq => self
+                        .timestamp_from_column_and_value_expr(left, right)
+                        .is_some(),
+                    _ => false,
+                }
+            }
+            Expr::Between(datafusion::logical_expr::Between {
+                expr,
+                negated,
+                low,
+                high,
+            }) => {
+                let is_timestamp_column = matches!(
+                    expr.as_ref(),
+                    Expr::Column(column) if column.name == self.timestamp_column_name
+                );
+
+                is_timestamp_column
+                    && !negated
+                    && Self::timestamp_from_scalar_expr(low).is_some()
+                    && Self::timestamp_from_scalar_expr(high).is_some()
+            }
+            _ => false,
+        }
+    }
+
     /// Extract the time range recursively from the `expr`.
     ///
     /// Now the strategy is conservative: for the sub-expr which we are not sure
@@ -582,4 +640,48 @@ mod tests {
             assert_eq!(predict.time_range(), expcted);
         }
     }
+
+    #[test]
+    fn test_exact_time_range() {
+        let schema = build_schema_with_dictionary();
+        let cases = vec![
+            (vec![], Some(TimeRange::min_to_max())),
+            (
+                // key2 >= 500 and key2 < 600
+                vec![col("key2")
+                    .gt_eq(set_timestamp(500))
+                    .and(col("key2").lt(set_timestamp(600)))],
+                Some(TimeRange::new(Timestamp::new(500), Timestamp::new(600)).unwrap()),
+            ),
+            (
+                // key2 between 500 and 600, key2 > 550
+                vec![
+                    col("key2").between(set_timestamp(500), set_timestamp(600)),
+                    col("key2").gt(set_timestamp(550)),
+                ],
+                Some(TimeRange::new(Timestamp::new(551), Timestamp::new(601)).unwrap()),
+            ),
+            (
+                // key2 < 10 or key2 > 11
+                vec![col("key2")
+                    .lt(set_timestamp(10))
+                    .or(col("key2").gt(set_timestamp(11)))],
+                None,
+            ),
+            (
+                // key2 > 500 and tag1 = 'a'
+                vec![col("key2")
+                    .gt(set_timestamp(500))
+                    .and(col("tag1").eq(Expr::Literal(ScalarValue::from("a"))))],
+                None,
+            ),
+        ];
+        for (exprs, expected) in cases {
+            let predicate = PredicateBuilder::default()
+                .add_pushdown_exprs(&exprs)
+                .extract_time_range(&schema, &exprs)
+                .build();
+            assert_eq!(predicate.exact_time_range(&schema), expected);
+        }
+    }
 }
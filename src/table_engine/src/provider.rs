@@ -29,6 +29,7 @@ use arrow::datatypes::SchemaRef;
 use async_trait::async_trait;
 use common_types::{projected_schema::ProjectedSchema, request_id::RequestId, schema::Schema};
 use datafusion::{
+    common::stats::Precision,
     config::{ConfigEntry, ConfigExtension, ExtensionOptions},
     datasource::TableProvider,
     error::{DataFusionError, Result},
@@ -42,6 +43,7 @@ use datafusion::{
         DisplayAs, DisplayFormatType, ExecutionPlan, Metric, Partitioning, PhysicalExpr,
         SendableRecordBatchStream as DfSendableRecordBatchStream, Statistics,
     },
+    scalar::ScalarValue,
 };
 use df_operator::visitor;
 use logger::debug;
@@ -51,7 +53,7 @@ use trace_metric::{collector::FormatCollectorVisitor, MetricsCollector};
 use crate::{
     predicate::{PredicateBuilder, PredicateRef},
     stream::{ScanStreamState, ToDfStream},
-    table::{ReadOptions, ReadRequest, TableRef},
+    table::{ReadOptions, ReadRequest, TableRef, TimeRangeStats},
 };
 
 pub const SCAN_TABLE_METRICS_COLLECTOR_NAME: &str = "scan_table";
@@ -415,6 +417,9 @@ pub struct ScanTable {
     table: TableRef,
     request: ReadRequest,
     stream_state: Mutex<ScanStreamState>,
+    /// Exact statistics of the rows to scan, which is collected from the table
+    /// metadata if the predicate only constrains the time range.
+    time_range_stats: Option<TimeRangeStats>,
 
     // FIXME: in origin partitioned table scan need to modify the parallelism when initializing
     // stream...
@@ -428,6 +433,7 @@ impl ScanTable {
             table,
             request,
             stream_state: Mutex::new(ScanStreamState::default()),
+            time_range_stats: None,
             parallelism,
         }
     }
@@ -442,6 +448,15 @@ impl ScanTable {
         stream_state.init(read_res);
         self.parallelism = stream_state.streams.len();
 
+        // The statistics allow the aggregations like `count(*)`, `min(time)` and
+        // `max(time)` to be answered without consuming the streams.
+        let table_schema = self.request.projected_schema.table_schema();
+        self.time_range_stats = self
+            .request
+            .predicate
+            .exact_time_range(table_schema)
+            .and_then(|time_range| self.table.time_range_stats(time_range));
+
         Ok(())
     }
 }
@@ -521,11 +536,63 @@ impl ExecutionPlan for ScanTable {
         &self,
     ) -> std::result::Result<datafusion::common::Statistics, datafusion::error::DataFusionError>
     {
-        // TODO(yingwen): Implement this
-        Ok(Statistics::new_unknown(&self.schema()))
+        let schema = self.schema();
+        let Some(stats) = &self.time_range_stats else {
+            return Ok(Statistics::new_unknown(&schema));
+        };
+
+        let table_schema = self.request.projected_schema.table_schema();
+        Ok(build_statistics(&schema, table_schema, stats))
     }
 }
 
+/// Build the statistics of the scan output in `schema` from the `stats` of the
+/// table with `table_schema`.
+fn build_statistics(
+    schema: &SchemaRef,
+    table_schema: &Schema,
+    stats: &TimeRangeStats,
+) -> Statistics {
+    let mut statistics = Statistics::new_unknown(schema);
+    statistics.num_rows = Precision::Exact(stats.num_rows);
+
+    if let Some(column_stats) = &stats.column_stats {
+        for (field, column_statistics) in schema
+            .fields()
+            .iter()
+            .zip(statistics.column_statistics.iter_mut())
+        {
+            let Some(col_stats) = table_schema
+                .index_of(field.name())
+                .and_then(|idx| column_stats.get(idx))
+            else {
+                continue;
+            };
+            column_statistics.null_count = Precision::Exact(col_stats.null_count);
+            // The distinct count is estimated by a sketch during writing the ssts.
+            column_statistics.distinct_count = Precision::Inexact(col_stats.distinct_count);
+        }
+    }
+
+    if let Ok(idx) = schema.index_of(table_schema.timestamp_name()) {
+        let (min, max) = match stats.time_range {
+            Some(time_range) => (
+                Some(time_range.inclusive_start().as_i64()),
+                Some(time_range.exclusive_end().as_i64() - 1),
+            ),
+            None => (None, None),
+        };
+        let column_statistics = &mut statistics.column_statistics[idx];
+        column_statistics.null_count = Precision::Exact(0);
+        column_statistics.min_value =
+            Precision::Exact(ScalarValue::TimestampMillisecond(min, None));
+        column_statistics.max_value =
+            Precision::Exact(ScalarValue::TimestampMillisecond(max, None));
+    }
+
+    statistics
+}
+
 impl DisplayAs for ScanTable {
     fn fmt_as(&self, _t: DisplayFormatType, f: &mut fmt::Formatter) -> fmt::Result {
         write!(
@@ -562,3 +629,58 @@ fn collect_projection_from_expr(exprs: &[Expr], schema: &Schema) -> HashSet<usiz
 
     projections
 }
+
+#[cfg(test)]
+mod tests {
+    use common_types::{tests::build_schema, time::TimeRange};
+
+    use super::*;
+    use crate::table::ColumnStats;
+
+    #[test]
+    fn test_build_statistics() {
+        let table_schema = build_schema();
+        // Project field2 and key2.
+        let projected_schema =
+            ProjectedSchema::new(table_schema.clone(), Some(vec![3, 1])).unwrap();
+        let schema = projected_schema.to_projected_arrow_schema();
+        let column_stats = (0..table_schema.num_columns())
+            .map(|idx| ColumnStats {
+                null_count: idx,
+                distinct_count: idx * 10,
+            })
+            .collect();
+        let stats = TimeRangeStats {
+            num_rows: 100,
+            time_range: Some(TimeRange::new_unchecked_for_test(100, 200)),
+            column_stats: Some(column_stats),
+        };
+
+        let statistics = build_statistics(&schema, &table_schema, &stats);
+        assert_eq!(Precision::Exact(100), statistics.num_rows);
+        let field2 = &statistics.column_statistics[0];
+        assert_eq!(Precision::Exact(3), field2.null_count);
+        assert_eq!(Precision::Inexact(30), field2.distinct_count);
+        let key2 = &statistics.column_statistics[1];
+        assert_eq!(Precision::Exact(0), key2.null_count);
+        assert_eq!(
+            Precision::Exact(ScalarValue::TimestampMillisecond(Some(199), None)),
+            key2.max_value
+        );
+
+        // Only the row number and time range are known.
+        let stats = TimeRangeStats {
+            column_stats: None,
+            ..stats
+        };
+        let statistics = build_statistics(&schema, &table_schema, &stats);
+        assert_eq!(
+            Precision::Absent,
+            statistics.column_statistics[0].null_count
+        );
+        assert_eq!(
+            Precision::Absent,
+            statistics.column_statistics[0].distinct_count
+        );
+    }
+}
@@ -23,7 +23,7 @@ use crate::{
     engine::{
         CloseShardRequest, CloseTableRequest, CreateTableParams, CreateTableRequest,
         DropTableRequest, OpenShardRequest, OpenShardResult, OpenTableRequest, TableEngine,
-        TableEngineRef, UnknownEngineType,
+        TableEngineRef, UnknownEngineType, WriteTablesRequest,
     },
     memory::MemoryTableEngine,
     table::TableRef,
@@ -115,4 +115,12 @@ impl TableEngine for TableEngineProxy {
             engine_type => vec![UnknownEngineType { engine_type }.fail()],
         }
     }
+
+    async fn write_tables(&self, request: WriteTablesRequest) -> crate::engine::Result<usize> {
+        match request.engine.as_str() {
+            MEMORY_ENGINE_TYPE => self.memory.write_tables(request).await,
+            ANALYTIC_ENGINE_TYPE => self.analytic.write_tables(request).await,
+            engine_type => UnknownEngineType { engine_type }.fail(),
+        }
+    }
 }
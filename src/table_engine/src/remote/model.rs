@@ -44,8 +44,8 @@ use snafu::{ensure, Backtrace, OptionExt, ResultExt, Snafu};
 use crate::{
     partition::PartitionInfo,
     table::{
-        ReadRequest as TableReadRequest, SchemaId, TableId, WriteRequest as TableWriteRequest,
-        NO_TIMEOUT,
+        Durability, ReadRequest as TableReadRequest, SchemaId, TableId,
+        WriteRequest as TableWriteRequest, NO_TIMEOUT,
     },
 };
 
@@ -98,6 +98,14 @@ pub enum Error {
         backtrace,
     ))]
     ConvertRemoteExecuteRequest { msg: String, backtrace: Backtrace },
+
+    #[snafu(display(
+        "Durability {durability} is not supported by remote writes.\nBacktrace:\n{backtrace}",
+    ))]
+    UnsupportedDurability {
+        durability: Durability,
+        backtrace: Backtrace,
+    },
 }
 
 define_result!(Error);
@@ -190,7 +198,10 @@ impl WriteRequest {
     pub fn new(table_ident: TableIdentifier, row_group: RowGroup) -> Self {
         Self {
             table: table_ident,
-            write_request: TableWriteRequest { row_group },
+            write_request: TableWriteRequest {
+                row_group,
+                durability: Durability::default(),
+            },
         }
     }
 
@@ -222,6 +233,15 @@ impl WriteRequest {
     }
 
     pub fn convert_into_pb(self) -> Result<horaedbproto::remote_engine::WriteRequest> {
+        // The remote engine protocol can't carry the durability, so the remote
+        // write always takes the default one, and others are rejected rather than
+        // acknowledged without being reached.
+        ensure!(
+            self.write_request.durability == Durability::default(),
+            UnsupportedDurability {
+                durability: self.write_request.durability,
+            }
+        );
         let row_group = self.write_request.row_group;
         let table_schema = row_group.schema();
 
@@ -35,6 +35,7 @@ use common_types::{
     request_id::RequestId,
     row::{Row, RowGroup},
     schema::{RecordSchemaWithKey, Schema, Version},
+    time::TimeRange,
 };
 use generic_error::{BoxError, GenericError};
 use horaedbproto::sys_catalog as sys_catalog_pb;
@@ -319,10 +320,51 @@ impl fmt::Display for TableId {
     }
 }
 
+/// Durability level of a write, which trades the latency for the durability.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Durability {
+    /// The write is acknowledged after being written into the wal, which may
+    /// be still buffered and get lost if the node crashes.
+    #[default]
+    WalBuffered,
+    /// The write is acknowledged after the wal is synced to the disk.
+    WalSync,
+    /// The write is acknowledged after being flushed into the object store.
+    ObjectStore,
+}
+
+impl Durability {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Durability::WalBuffered => "wal_buffered",
+            Durability::WalSync => "wal_sync",
+            Durability::ObjectStore => "object_store",
+        }
+    }
+
+    /// Parse the durability from its name, return `None` if it is unknown.
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "wal_buffered" => Some(Durability::WalBuffered),
+            "wal_sync" => Some(Durability::WalSync),
+            "object_store" => Some(Durability::ObjectStore),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Durability {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
 #[derive(Clone, Debug)]
 pub struct WriteRequest {
     /// rows to write
     pub row_group: RowGroup,
+    /// Durability required by the write
+    pub durability: Durability,
 }
 
 #[derive(Clone, Debug)]
@@ -538,6 +580,15 @@ pub trait Table: std::fmt::Debug {
     /// Get table's statistics.
     fn stats(&self) -> TableStats;
 
+    /// Get the statistics of the rows in the `time_range` from a consistent
+    /// snapshot of the table metadata, without scanning any data.
+    ///
+    /// Returns `None` if the statistics can't be answered exactly by the
+    /// metadata.
+    fn time_range_stats(&self, _time_range: TimeRange) -> Option<TimeRangeStats> {
+        None
+    }
+
     /// Whether the columns used in filter expr can be pushdown.
     ///
     /// `read_schema` is used here to avoid upper layer see different schema
@@ -585,6 +636,28 @@ pub struct TableStats {
     pub num_flush: u64,
 }
 
+/// Exact statistics of the rows in a time range.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TimeRangeStats {
+    /// Number of the rows
+    pub num_rows: usize,
+    /// The minimal time range covering the timestamps of all the rows, `None`
+    /// if there are no rows
+    pub time_range: Option<TimeRange>,
+    /// Statistics of the columns in the order of the table schema, `None` if
+    /// unknown for some rows
+    pub column_stats: Option<Vec<ColumnStats>>,
+}
+
+/// Statistics of the values of a column.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ColumnStats {
+    /// Number of the null values
+    pub null_count: usize,
+    /// Approximate number of the distinct non-null values
+    pub distinct_count: usize,
+}
+
 /// A reference-counted pointer to Table
 pub type TableRef = Arc<dyn Table + Send + Sync>;
 
@@ -715,4 +788,17 @@ mod tests {
         assert_eq!(0, TableSeq::MIN.as_u64());
         assert_eq!(0xffffffffff, TableSeq::MAX.as_u64());
     }
+
+    #[test]
+    fn test_durability() {
+        for durability in [
+            Durability::WalBuffered,
+            Durability::WalSync,
+            Durability::ObjectStore,
+        ] {
+            assert_eq!(Some(durability), Durability::parse(durability.as_str()));
+        }
+        assert_eq!(None, Durability::parse("unknown"));
+        assert_eq!(Durability::WalBuffered, Durability::default());
+    }
 }
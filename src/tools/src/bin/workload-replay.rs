@@ -0,0 +1,185 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+//! A cli to replay the captured slow query log against a cluster
+
+use std::{sync::Arc, time::Duration};
+
+use anyhow::{Context, Result};
+use clap::Parser;
+use horaedb_client::{
+    db_client::{Builder, DbClient, Mode},
+    model::sql_query::Request as SqlQueryRequest,
+    RpcContext,
+};
+use tokio::{
+    task::JoinSet,
+    time::{self, Instant},
+};
+use tools::replay::{parse_slow_query_log, QueryRecord};
+
+#[derive(Parser, Debug)]
+#[clap(author, version, about, long_about = None)]
+struct Args {
+    /// Slow query log file
+    #[clap(short, long, required(true))]
+    log: String,
+
+    /// Endpoint of the target cluster
+    #[clap(short, long, default_value = "127.0.0.1:8831")]
+    endpoint: String,
+
+    /// Database to query
+    #[clap(short, long, default_value = "public")]
+    database: String,
+
+    /// Replay speed, 2.0 means queries are issued twice as fast as captured
+    #[clap(short, long, default_value_t = 1.0)]
+    speed: f64,
+
+    /// Issue every query this many times concurrently to scale the workload
+    #[clap(short, long, default_value_t = 1)]
+    repeat: usize,
+
+    /// Timeout of each query in seconds, 0 means no timeout
+    #[clap(short, long, default_value_t = 0)]
+    timeout: u64,
+}
+
+#[derive(Debug)]
+struct ReplayResult {
+    record: Arc<QueryRecord>,
+    elapsed: Duration,
+    error: Option<String>,
+}
+
+#[tokio::main]
+async fn main() {
+    let args = Args::parse();
+    if let Err(e) = run(args).await {
+        eprintln!("Replay failed, err:{e}");
+    }
+}
+
+async fn run(args: Args) -> Result<()> {
+    anyhow::ensure!(args.speed > 0.0, "speed must be positive");
+
+    let content = std::fs::read_to_string(&args.log)
+        .with_context(|| format!("read slow query log, path:{}", args.log))?;
+    let records = parse_slow_query_log(&content);
+    println!(
+        "Replay {} queries, endpoint:{}, speed:{}, repeat:{}",
+        records.len(),
+        args.endpoint,
+        args.speed,
+        args.repeat
+    );
+
+    let client = Builder::new(args.endpoint.clone(), Mode::Direct).build();
+    let rpc_ctx = RpcContext {
+        database: Some(args.database.clone()),
+        timeout: (args.timeout > 0).then(|| Duration::from_secs(args.timeout)),
+    };
+
+    // Every query is issued at its captured offset without waiting for the
+    // previous ones, so the original concurrency is kept.
+    let start = Instant::now();
+    let mut tasks = JoinSet::new();
+    for record in records {
+        let record = Arc::new(record);
+        let deadline = start + record.offset.div_f64(args.speed);
+        for _ in 0..args.repeat {
+            let client = client.clone();
+            let rpc_ctx = rpc_ctx.clone();
+            let record = record.clone();
+            tasks.spawn(async move {
+                time::sleep_until(deadline).await;
+                replay_one(client, rpc_ctx, record).await
+            });
+        }
+    }
+
+    let mut results = Vec::with_capacity(tasks.len());
+    while let Some(result) = tasks.join_next().await {
+        results.push(result.context("join replay task")?);
+    }
+    print_summary(results, start.elapsed());
+
+    Ok(())
+}
+
+async fn replay_one(
+    client: Arc<dyn DbClient>,
+    rpc_ctx: RpcContext,
+    record: Arc<QueryRecord>,
+) -> ReplayResult {
+    let req = SqlQueryRequest {
+        tables: vec![],
+        sql: record.query.clone(),
+    };
+    let begin = Instant::now();
+    let error = client
+        .sql_query(&rpc_ctx, &req)
+        .await
+        .err()
+        .map(|e| e.to_string());
+
+    ReplayResult {
+        record,
+        elapsed: begin.elapsed(),
+        error,
+    }
+}
+
+fn print_summary(mut results: Vec<ReplayResult>, total: Duration) {
+    let failed = results.iter().filter(|r| r.error.is_some()).count();
+    for r in results.iter().filter(|r| r.error.is_some()) {
+        println!(
+            "Query failed, id:{}, err:{}",
+            r.record.request_id,
+            r.error.as_ref().unwrap()
+        );
+    }
+
+    results.sort_by_key(|r| r.elapsed);
+    let percentile = |p: f64| {
+        if results.is_empty() {
+            return Duration::ZERO;
+        }
+        let idx = ((results.len() - 1) as f64 * p).round() as usize;
+        results[idx].elapsed
+    };
+    let regressed = results
+        .iter()
+        .filter(|r| r.error.is_none() && r.elapsed > r.record.elapsed)
+        .count();
+
+    println!(
+        "Replay finished, total:{:?}, queries:{}, failed:{}, slower than captured:{}",
+        total,
+        results.len(),
+        failed,
+        regressed
+    );
+    println!(
+        "Latency p50:{:?}, p90:{:?}, p99:{:?}, max:{:?}",
+        percentile(0.5),
+        percentile(0.9),
+        percentile(0.99),
+        percentile(1.0)
+    );
+}
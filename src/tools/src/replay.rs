@@ -0,0 +1,169 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+//! Utilities to parse the slow query log for workload replay.
+//!
+//! A slow query log line looks like:
+//! ```text
+//! 2024-10-16 13:00:14.998 INFO [src/proxy/src/read.rs:183] Normal query elapsed:5.1s, id:42, priority:None, query:SELECT * FROM t
+//! ```
+
+use std::time::Duration;
+
+use anyhow::{anyhow, Context, Result};
+use chrono::NaiveDateTime;
+
+const TIMESTAMP_FORMAT: &str = "%Y-%m-%d %H:%M:%S%.3f";
+const TIMESTAMP_LEN: usize = 23;
+const ELAPSED_PREFIX: &str = "elapsed:";
+const ID_PREFIX: &str = ", id:";
+const QUERY_PREFIX: &str = ", query:";
+
+/// A query captured in the slow query log.
+#[derive(Debug, Clone, PartialEq)]
+pub struct QueryRecord {
+    /// Offset of the query from the first captured query.
+    pub offset: Duration,
+    /// Elapsed time of the query when it was captured.
+    pub elapsed: Duration,
+    pub request_id: String,
+    pub query: String,
+}
+
+/// Parse the whole slow query log, lines failed to parse are skipped.
+///
+/// The returned records are ordered by their start time.
+pub fn parse_slow_query_log(content: &str) -> Vec<QueryRecord> {
+    let mut entries = content
+        .lines()
+        .filter_map(|line| parse_slow_query_line(line).ok())
+        .collect::<Vec<_>>();
+    entries.sort_by_key(|(start_time, ..)| *start_time);
+
+    let Some((first_start_time, ..)) = entries.first().cloned() else {
+        return Vec::new();
+    };
+    entries
+        .into_iter()
+        .map(|(start_time, elapsed, request_id, query)| QueryRecord {
+            offset: (start_time - first_start_time).to_std().unwrap_or_default(),
+            elapsed,
+            request_id,
+            query,
+        })
+        .collect()
+}
+
+/// Parse one line of the slow query log into (start time, elapsed, request
+/// id, query).
+///
+/// The log is written when the query finishes, so start time is computed by
+/// the log time minus the elapsed time.
+fn parse_slow_query_line(line: &str) -> Result<(NaiveDateTime, Duration, String, String)> {
+    let log_time = line.get(..TIMESTAMP_LEN).context("line is too short")?;
+    let log_time = NaiveDateTime::parse_from_str(log_time, TIMESTAMP_FORMAT)
+        .with_context(|| format!("invalid log time:{log_time}"))?;
+
+    let elapsed_start =
+        line.find(ELAPSED_PREFIX).context("elapsed is missing")? + ELAPSED_PREFIX.len();
+    let id_start = line[elapsed_start..]
+        .find(ID_PREFIX)
+        .context("id is missing")?
+        + elapsed_start;
+    let elapsed = parse_debug_duration(&line[elapsed_start..id_start])?;
+
+    let id_start = id_start + ID_PREFIX.len();
+    let query_start = line[id_start..]
+        .find(QUERY_PREFIX)
+        .context("query is missing")?
+        + id_start;
+    let request_id = line[id_start..]
+        .split(',')
+        .next()
+        .unwrap_or_default()
+        .to_string();
+    let query = line[query_start + QUERY_PREFIX.len()..].trim().to_string();
+
+    let start_time = log_time - chrono::Duration::from_std(elapsed)?;
+    Ok((start_time, elapsed, request_id, query))
+}
+
+/// Parse the duration formatted by `{:?}`, such as `1.5s`, `20ms`.
+fn parse_debug_duration(s: &str) -> Result<Duration> {
+    let s = s.trim();
+    let (value, nanos_per_unit) = if let Some(v) = s.strip_suffix("ns") {
+        (v, 1.0)
+    } else if let Some(v) = s.strip_suffix("µs") {
+        (v, 1e3)
+    } else if let Some(v) = s.strip_suffix("ms") {
+        (v, 1e6)
+    } else if let Some(v) = s.strip_suffix('s') {
+        (v, 1e9)
+    } else {
+        return Err(anyhow!("invalid duration:{s}"));
+    };
+    let value: f64 = value
+        .parse()
+        .with_context(|| format!("invalid duration:{s}"))?;
+
+    Ok(Duration::from_nanos((value * nanos_per_unit) as u64))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_debug_duration() {
+        let cases = [
+            ("1.5s", Duration::from_millis(1500)),
+            ("20ms", Duration::from_millis(20)),
+            ("3µs", Duration::from_micros(3)),
+            ("7ns", Duration::from_nanos(7)),
+        ];
+        for (input, expected) in cases {
+            assert_eq!(expected, parse_debug_duration(input).unwrap());
+        }
+        assert!(parse_debug_duration("1h").is_err());
+    }
+
+    #[test]
+    fn test_parse_slow_query_log() {
+        let log = r#"2024-10-16 13:00:15.000 INFO [src/proxy/src/read.rs:183] Normal query elapsed:5s, id:2, priority:None, query:SELECT 2
+invalid line
+2024-10-16 13:00:05.500 INFO [src/proxy/src/read.rs:183] Normal query elapsed:500ms, id:1, priority:Some(Low), query:SELECT * FROM t WHERE a = 'x, y'"#;
+
+        let records = parse_slow_query_log(log);
+        assert_eq!(
+            vec![
+                QueryRecord {
+                    offset: Duration::ZERO,
+                    elapsed: Duration::from_millis(500),
+                    request_id: "1".to_string(),
+                    query: "SELECT * FROM t WHERE a = 'x, y'".to_string(),
+                },
+                QueryRecord {
+                    offset: Duration::from_secs(5),
+                    elapsed: Duration::from_secs(5),
+                    request_id: "2".to_string(),
+                    query: "SELECT 2".to_string(),
+                },
+            ],
+            records
+        );
+    }
+}
@@ -67,6 +67,14 @@ impl WalManager for DoNothing {
         Ok(MIN_SEQUENCE_NUMBER)
     }
 
+    async fn write_atomically(
+        &self,
+        _ctx: &WriteContext,
+        batches: &[LogWriteBatch],
+    ) -> Result<Vec<SequenceNumber>> {
+        Ok(vec![MIN_SEQUENCE_NUMBER; batches.len()])
+    }
+
     async fn scan(
         &self,
         _ctx: &ScanContext,
@@ -332,8 +332,15 @@ impl Segment {
     }
 
     pub fn close(&mut self) -> Result<()> {
+        // Flush before closing
+        self.flush()?;
+        self.mmap.take();
+        Ok(())
+    }
+
+    /// Flush all the appended data to the disk.
+    pub fn flush(&mut self) -> Result<()> {
         if let Some(ref mut mmap) = self.mmap {
-            // Flush before closing
             mmap.flush_range(
                 self.last_flushed_position,
                 self.current_size - self.last_flushed_position,
@@ -344,7 +351,6 @@ impl Segment {
             // Update the last flushed position
             self.last_flushed_position = self.current_size;
         }
-        self.mmap.take();
         Ok(())
     }
 
@@ -741,7 +747,7 @@ impl Region {
         Ok(Arc::new(Mutex::new(new_segment)))
     }
 
-    pub fn write(&self, _ctx: &WriteContext, batch: &LogWriteBatch) -> Result<SequenceNumber> {
+    pub fn write(&self, ctx: &WriteContext, batch: &LogWriteBatch) -> Result<SequenceNumber> {
         // In the WAL based on local storage, we need to ensure the sequence number in
         // segment is monotonically increasing. So we need to acquire a lock here.
         // Perhaps we could avoid acquiring the lock here and instead allocate the
@@ -808,6 +814,9 @@ impl Region {
             prev_sequence_num,
             next_sequence_num,
         )?;
+        if ctx.sync {
+            guard.flush()?;
+        }
         Ok(next_sequence_num - 1)
     }
 
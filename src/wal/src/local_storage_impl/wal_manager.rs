@@ -149,6 +149,10 @@ impl WalManager for LocalStorageImpl {
             .context(Write)
     }
 
+    fn supports_sync(&self) -> bool {
+        true
+    }
+
     async fn scan(&self, ctx: &ScanContext, req: &ScanRequest) -> Result<BatchLogIteratorAdapter> {
         debug!(
             "Scan from LocalStorage based WAL, ctx:{:?}, req:{:?}",
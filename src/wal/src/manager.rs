@@ -171,6 +171,16 @@ pub mod error {
 
         #[snafu(display("Encountered unknown error, msg:{}.\nBacktrace:\n{}", msg, backtrace))]
         Unknown { msg: String, backtrace: Backtrace },
+
+        #[snafu(display(
+            "Atomic write of multiple locations is not supported, num_locations:{}.\nBacktrace:\n{}",
+            num_locations,
+            backtrace
+        ))]
+        AtomicWriteNotSupported {
+            num_locations: usize,
+            backtrace: Backtrace,
+        },
     }
 
     define_result!(Error);
@@ -199,12 +209,17 @@ pub struct WriteContext {
     /// Timeout to write wal and it only takes effect when writing to a Wal on a
     /// remote machine (writing to the local disk does not have timeout).
     pub timeout: Duration,
+    /// Whether to sync the written logs to the durable storage before
+    /// returning, and it only takes effect on the Wal whose
+    /// [WalManager::supports_sync] is true.
+    pub sync: bool,
 }
 
 impl Default for WriteContext {
     fn default() -> Self {
         Self {
             timeout: Duration::from_secs(1),
+            sync: false,
         }
     }
 }
@@ -353,6 +368,30 @@ pub trait WalManager: Send + Sync + fmt::Debug + 'static {
     /// Returns the max sequence number for the batch of log entries.
     async fn write(&self, ctx: &WriteContext, batch: &LogWriteBatch) -> Result<SequenceNumber>;
 
+    /// Write the batches of log entries to multiple locations atomically, that
+    /// is to say, either all or none of the batches are written.
+    ///
+    /// Returns the max sequence number for every batch.
+    async fn write_atomically(
+        &self,
+        ctx: &WriteContext,
+        batches: &[LogWriteBatch],
+    ) -> Result<Vec<SequenceNumber>> {
+        match batches {
+            [batch] => Ok(vec![self.write(ctx, batch).await?]),
+            _ => AtomicWriteNotSupported {
+                num_locations: batches.len(),
+            }
+            .fail(),
+        }
+    }
+
+    /// Whether [WriteContext::sync] is honoured by the Wal, the writes
+    /// requiring the sync should be rejected if not.
+    fn supports_sync(&self) -> bool {
+        false
+    }
+
     /// Scan all logs from a `Region`.
     async fn scan(&self, ctx: &ScanContext, req: &ScanRequest) -> Result<BatchLogIteratorAdapter>;
 
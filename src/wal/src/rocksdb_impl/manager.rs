@@ -35,7 +35,8 @@ use generic_error::BoxError;
 use logger::{debug, info, warn};
 use rocksdb::{
     rocksdb_options::ColumnFamilyDescriptor, ColumnFamilyOptions, DBCompactionStyle, DBIterator,
-    DBOptions, FifoCompactionOptions, ReadOptions, SeekKey, Statistics, Writable, WriteBatch, DB,
+    DBOptions, FifoCompactionOptions, ReadOptions, SeekKey, Statistics, Writable, WriteBatch,
+    WriteOptions, DB,
 };
 use runtime::Runtime;
 use snafu::ResultExt;
@@ -210,47 +211,64 @@ impl TableUnit {
             batch.entries.len()
         );
 
+        let wb = WriteBatch::default();
+        let max_sequence_num = self.put_to_write_batch(&wb, batch)?;
+
+        write_to_db(&self.db, &self.runtime, ctx, wb).await?;
+        Ok(max_sequence_num)
+    }
+
+    /// Put the log entries of the `batch` into the `wb`, returns the max
+    /// sequence number of the entries.
+    fn put_to_write_batch(&self, wb: &WriteBatch, batch: &LogWriteBatch) -> Result<u64> {
         manager::collect_write_log_metrics(batch);
 
         let entries_num = batch.len() as u64;
-        let (wb, max_sequence_num) = {
-            let wb = WriteBatch::default();
-            let mut next_sequence_num = self.alloc_sequence_num(entries_num);
-            let mut key_buf = BytesMut::new();
-
-            for entry in &batch.entries {
-                let region_id = batch.location.region_id;
-                self.log_encoding
-                    .encode_key(
-                        &mut key_buf,
-                        &CommonLogKey::new(region_id, batch.location.table_id, next_sequence_num),
-                    )
-                    .box_err()
-                    .context(Encoding)?;
-                wb.put(&key_buf, &entry.payload)
-                    .map_err(|e| e.into())
-                    .context(Write)?;
-
-                next_sequence_num += 1;
-            }
+        let mut next_sequence_num = self.alloc_sequence_num(entries_num);
+        let mut key_buf = BytesMut::new();
 
-            (wb, next_sequence_num - 1)
-        };
+        for entry in &batch.entries {
+            let region_id = batch.location.region_id;
+            self.log_encoding
+                .encode_key(
+                    &mut key_buf,
+                    &CommonLogKey::new(region_id, batch.location.table_id, next_sequence_num),
+                )
+                .box_err()
+                .context(Encoding)?;
+            wb.put(&key_buf, &entry.payload)
+                .map_err(|e| e.into())
+                .context(Write)?;
 
-        let db = self.db.clone();
-        self.runtime
-            .spawn_blocking(move || {
-                db.write(&wb)
-                    .map(|_| max_sequence_num)
-                    .map_err(|e| e.into())
-                    .context(Write)
-            })
-            .await
-            .box_err()
-            .context(Write)?
+            next_sequence_num += 1;
+        }
+
+        Ok(next_sequence_num - 1)
     }
 }
 
+/// Write the `wb` into the `db` in the blocking threads of the `runtime`.
+async fn write_to_db(
+    db: &Arc<DB>,
+    runtime: &Arc<Runtime>,
+    ctx: &WriteContext,
+    wb: WriteBatch,
+) -> Result<()> {
+    let db = db.clone();
+    let sync = ctx.sync;
+    runtime
+        .spawn_blocking(move || {
+            let mut write_opts = WriteOptions::new();
+            write_opts.set_sync(sync);
+            db.write_opt(&wb, &write_opts)
+                .map_err(|e| e.into())
+                .context(Write)
+        })
+        .await
+        .box_err()
+        .context(Write)?
+}
+
 /// [WalManager] implementation based on RocksDB.
 /// A [RocksImpl] consists of multiple [TableUnit]s and any read/write/delete
 /// request is delegated to specific [TableUnit].
@@ -917,6 +935,34 @@ impl WalManager for RocksImpl {
         table_unit.write(ctx, batch).await
     }
 
+    async fn write_atomically(
+        &self,
+        ctx: &WriteContext,
+        batches: &[LogWriteBatch],
+    ) -> Result<Vec<SequenceNumber>> {
+        debug!(
+            "Wal begin writing atomically, ctx:{:?}, batch_num:{}",
+            ctx,
+            batches.len()
+        );
+
+        // All the batches are put into one write batch of the RocksDB, which is
+        // written atomically.
+        let wb = WriteBatch::default();
+        let mut max_sequence_nums = Vec::with_capacity(batches.len());
+        for batch in batches {
+            let table_unit = self.get_or_create_table_unit(batch.location);
+            max_sequence_nums.push(table_unit.put_to_write_batch(&wb, batch)?);
+        }
+
+        write_to_db(&self.db, &self.runtime, ctx, wb).await?;
+        Ok(max_sequence_nums)
+    }
+
+    fn supports_sync(&self) -> bool {
+        true
+    }
+
     async fn scan(&self, ctx: &ScanContext, req: &ScanRequest) -> Result<BatchLogIteratorAdapter> {
         debug!("Wal region begin scanning, ctx:{:?}, req:{:?}", ctx, req);
 
@@ -52,6 +52,12 @@ fn test_rocksdb_wal() {
     test_all(builder, false);
 }
 
+#[test]
+fn test_rocksdb_wal_write_atomically() {
+    let env = TestEnv::new(2, RocksWalBuilder);
+    env.runtime.block_on(write_atomically(&env));
+}
+
 #[test]
 fn test_memory_table_wal_default() {
     let builder = MemoryTableWalBuilder::default();
@@ -402,6 +408,44 @@ async fn read_with_boundary<B: WalBuilder>(env: &TestEnv<B>) {
 }
 
 /// Test read and write across multiple regions parallely.
+async fn write_atomically<B: WalBuilder>(env: &TestEnv<B>) {
+    let wal = env.build_wal().await;
+    let locations = [WalLocation::new(1, 0), WalLocation::new(1, 1)];
+
+    // Write something before to make the sequences of the locations different.
+    let (_, log_batch) = env.build_log_batch(locations[0], 0, 3).await;
+    wal.write(&env.write_ctx, &log_batch).await.unwrap();
+
+    let (payload_batch_0, log_batch_0) = env.build_log_batch(locations[0], 3, 5).await;
+    let (payload_batch_1, log_batch_1) = env.build_log_batch(locations[1], 5, 10).await;
+    let max_seqs = wal
+        .write_atomically(&env.write_ctx, &[log_batch_0, log_batch_1])
+        .await
+        .expect("should succeed to write atomically");
+    assert_eq!(max_seqs.len(), 2);
+    assert_eq!(max_seqs[0], wal.sequence_num(locations[0]).await.unwrap());
+    assert_eq!(max_seqs[1], wal.sequence_num(locations[1]).await.unwrap());
+
+    check_write_batch(
+        env,
+        wal.clone(),
+        locations[0],
+        max_seqs[0],
+        &payload_batch_0,
+    )
+    .await;
+    check_write_batch(
+        env,
+        wal.clone(),
+        locations[1],
+        max_seqs[1],
+        &payload_batch_1,
+    )
+    .await;
+
+    wal.close_gracefully().await.unwrap();
+}
+
 async fn write_multiple_regions_parallelly<B: WalBuilder + 'static>(env: Arc<TestEnv<B>>) {
     let wal = env.build_wal().await;
     let mut handles = Vec::with_capacity(10);